@@ -0,0 +1,857 @@
+use core::ops;
+
+use managed::{ManagedMap, ManagedSlice};
+
+use crate::time::{Duration, Instant};
+use crate::wire::*;
+
+/// The direction of the packets a rule applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// Packets received by the interface for itself.
+    Ingress,
+    /// Packets sent by the interface, forwarded ones included.
+    Egress,
+}
+
+/// What to do with a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// Let the packet through.
+    Accept,
+    /// Silently discard the packet.
+    Drop,
+    /// Discard the packet, and tell its sender. Received packets are answered with an
+    /// ICMP administratively prohibited error, packets being sent are dropped.
+    Reject,
+}
+
+/// An inclusive range of TCP or UDP ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PortRange {
+    pub first: u16,
+    pub last: u16,
+}
+
+impl PortRange {
+    /// Create a range of the ports from `first` to `last`, both included.
+    pub const fn new(first: u16, last: u16) -> PortRange {
+        PortRange { first, last }
+    }
+
+    /// Return whether `port` is in the range.
+    pub fn contains(&self, port: u16) -> bool {
+        self.first <= port && port <= self.last
+    }
+}
+
+impl From<u16> for PortRange {
+    fn from(port: u16) -> PortRange {
+        PortRange::new(port, port)
+    }
+}
+
+/// A set of TCP control flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub const FIN: TcpFlags = TcpFlags(0x01);
+    pub const SYN: TcpFlags = TcpFlags(0x02);
+    pub const RST: TcpFlags = TcpFlags(0x04);
+    pub const PSH: TcpFlags = TcpFlags(0x08);
+    pub const ACK: TcpFlags = TcpFlags(0x10);
+    pub const URG: TcpFlags = TcpFlags(0x20);
+
+    /// Return whether all the flags of `other` are set.
+    pub fn contains(&self, other: TcpFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub(crate) fn of_packet<T: AsRef<[u8]>>(packet: &TcpPacket<T>) -> TcpFlags {
+        let flags = [
+            (packet.fin(), TcpFlags::FIN),
+            (packet.syn(), TcpFlags::SYN),
+            (packet.rst(), TcpFlags::RST),
+            (packet.psh(), TcpFlags::PSH),
+            (packet.ack(), TcpFlags::ACK),
+            (packet.urg(), TcpFlags::URG),
+        ];
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(TcpFlags::default(), |acc, (_, flag)| acc | *flag)
+    }
+
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn of_repr(repr: &TcpRepr) -> TcpFlags {
+        let mut flags = match repr.control {
+            TcpControl::None => TcpFlags::default(),
+            TcpControl::Psh => TcpFlags::PSH,
+            TcpControl::Syn => TcpFlags::SYN,
+            TcpControl::Fin => TcpFlags::FIN,
+            TcpControl::Rst => TcpFlags::RST,
+        };
+        if repr.ack_number.is_some() {
+            flags = flags | TcpFlags::ACK;
+        }
+        flags
+    }
+}
+
+impl ops::BitOr for TcpFlags {
+    type Output = TcpFlags;
+
+    fn bitor(self, rhs: TcpFlags) -> TcpFlags {
+        TcpFlags(self.0 | rhs.0)
+    }
+}
+
+/// A filter rule.
+///
+/// A rule matches the packets of its direction for which every criterion that is set
+/// holds. Port and TCP flag criteria only match TCP and UDP, respectively TCP, packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rule {
+    pub direction: Direction,
+    pub action: Action,
+    pub src_addr: Option<IpCidr>,
+    pub dst_addr: Option<IpCidr>,
+    pub protocol: Option<IpProtocol>,
+    pub src_port: Option<PortRange>,
+    pub dst_port: Option<PortRange>,
+    /// The flags of the mask, the first item, that must be set, the second item.
+    pub tcp_flags: Option<(TcpFlags, TcpFlags)>,
+}
+
+impl Rule {
+    /// Create a rule applying `action` to every packet in `direction`.
+    pub const fn new(direction: Direction, action: Action) -> Rule {
+        Rule {
+            direction,
+            action,
+            src_addr: None,
+            dst_addr: None,
+            protocol: None,
+            src_port: None,
+            dst_port: None,
+            tcp_flags: None,
+        }
+    }
+
+    /// Only match packets from `cidr`.
+    pub fn src_addr(mut self, cidr: IpCidr) -> Rule {
+        self.src_addr = Some(cidr);
+        self
+    }
+
+    /// Only match packets to `cidr`.
+    pub fn dst_addr(mut self, cidr: IpCidr) -> Rule {
+        self.dst_addr = Some(cidr);
+        self
+    }
+
+    /// Only match packets of `protocol`.
+    pub fn protocol(mut self, protocol: IpProtocol) -> Rule {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Only match TCP and UDP packets from ports in `ports`.
+    pub fn src_port<T: Into<PortRange>>(mut self, ports: T) -> Rule {
+        self.src_port = Some(ports.into());
+        self
+    }
+
+    /// Only match TCP and UDP packets to ports in `ports`.
+    pub fn dst_port<T: Into<PortRange>>(mut self, ports: T) -> Rule {
+        self.dst_port = Some(ports.into());
+        self
+    }
+
+    /// Only match TCP packets whose flags in `mask` are those of `flags`, e.g. with
+    /// `SYN | ACK` and `SYN` for connection requests.
+    pub fn tcp_flags(mut self, mask: TcpFlags, flags: TcpFlags) -> Rule {
+        self.tcp_flags = Some((mask, flags));
+        self
+    }
+
+    fn matches(&self, direction: Direction, flow: &Flow) -> bool {
+        let is_transport = matches!(flow.protocol, IpProtocol::Tcp | IpProtocol::Udp);
+        self.direction == direction
+            && self
+                .src_addr
+                .map_or(true, |cidr| cidr.contains_addr(&flow.src_addr))
+            && self
+                .dst_addr
+                .map_or(true, |cidr| cidr.contains_addr(&flow.dst_addr))
+            && self.protocol.map_or(true, |p| p == flow.protocol)
+            && self
+                .src_port
+                .map_or(true, |ports| is_transport && ports.contains(flow.src_port))
+            && self
+                .dst_port
+                .map_or(true, |ports| is_transport && ports.contains(flow.dst_port))
+            && self.tcp_flags.map_or(true, |(mask, flags)| {
+                flow.protocol == IpProtocol::Tcp && flow.tcp_flags.0 & mask.0 == flags.0
+            })
+    }
+}
+
+/// A tracked connection, seen from the interface.
+///
+/// For ICMP echo, both ports are the echo identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Connection {
+    pub protocol: IpProtocol,
+    pub local_addr: IpAddress,
+    pub local_port: u16,
+    pub remote_addr: IpAddress,
+    pub remote_port: u16,
+}
+
+/// What a filter looks at in a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Flow {
+    pub src_addr: IpAddress,
+    pub dst_addr: IpAddress,
+    pub protocol: IpProtocol,
+    /// The TCP or UDP ports, or the ICMP echo identifier.
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub tcp_flags: TcpFlags,
+    /// Whether the packet belongs to a connection that can be tracked.
+    pub trackable: bool,
+    /// For ICMP errors, the connection of the packet they quote, seen from its sender.
+    pub quoted: Option<Connection>,
+}
+
+impl Flow {
+    /// Describe an IP packet without transport information.
+    pub(crate) fn new(src_addr: IpAddress, dst_addr: IpAddress, protocol: IpProtocol) -> Flow {
+        Flow {
+            src_addr,
+            dst_addr,
+            protocol,
+            src_port: 0,
+            dst_port: 0,
+            tcp_flags: TcpFlags::default(),
+            trackable: false,
+            quoted: None,
+        }
+    }
+
+    /// Describe an IP packet from its payload. Payloads that can't be parsed are left
+    /// to rules without transport criteria.
+    pub(crate) fn parse(
+        src_addr: IpAddress,
+        dst_addr: IpAddress,
+        protocol: IpProtocol,
+        payload: &[u8],
+    ) -> Flow {
+        let flow = Flow::new(src_addr, dst_addr, protocol);
+        match protocol {
+            IpProtocol::Tcp => match TcpPacket::new_checked(payload) {
+                Ok(packet) => flow
+                    .with_ports(packet.src_port(), packet.dst_port(), true)
+                    .with_tcp_flags(TcpFlags::of_packet(&packet)),
+                Err(_) => flow,
+            },
+            IpProtocol::Udp => match UdpPacket::new_checked(payload) {
+                Ok(packet) => flow.with_ports(packet.src_port(), packet.dst_port(), true),
+                Err(_) => flow,
+            },
+            #[cfg(feature = "proto-ipv4")]
+            IpProtocol::Icmp => match Icmpv4Packet::new_checked(payload) {
+                Ok(packet)
+                    if matches!(
+                        packet.msg_type(),
+                        Icmpv4Message::EchoRequest | Icmpv4Message::EchoReply
+                    ) =>
+                {
+                    let ident = packet.echo_ident();
+                    flow.with_ports(ident, ident, true)
+                }
+                Ok(packet)
+                    if matches!(
+                        packet.msg_type(),
+                        Icmpv4Message::DstUnreachable | Icmpv4Message::TimeExceeded
+                    ) =>
+                {
+                    flow.with_quoted_ipv4(packet.data())
+                }
+                _ => flow,
+            },
+            #[cfg(feature = "proto-ipv6")]
+            IpProtocol::Icmpv6 => match Icmpv6Packet::new_checked(payload) {
+                Ok(packet)
+                    if matches!(
+                        packet.msg_type(),
+                        Icmpv6Message::EchoRequest | Icmpv6Message::EchoReply
+                    ) =>
+                {
+                    let ident = packet.echo_ident();
+                    flow.with_ports(ident, ident, true)
+                }
+                Ok(packet)
+                    if matches!(
+                        packet.msg_type(),
+                        Icmpv6Message::DstUnreachable
+                            | Icmpv6Message::PktTooBig
+                            | Icmpv6Message::TimeExceeded
+                    ) =>
+                {
+                    flow.with_quoted_ipv6(packet.payload())
+                }
+                _ => flow,
+            },
+            _ => flow,
+        }
+    }
+
+    pub(crate) fn with_ports(mut self, src_port: u16, dst_port: u16, trackable: bool) -> Flow {
+        self.src_port = src_port;
+        self.dst_port = dst_port;
+        self.trackable = trackable;
+        self
+    }
+
+    pub(crate) fn with_tcp_flags(mut self, tcp_flags: TcpFlags) -> Flow {
+        self.tcp_flags = tcp_flags;
+        self
+    }
+
+    /// Describe an ICMP error from the addresses, protocol and start of the payload of
+    /// the packet it quotes. Quoted packets without ports or echo identifier are left
+    /// to the rules.
+    pub(crate) fn with_quoted(
+        mut self,
+        src_addr: IpAddress,
+        dst_addr: IpAddress,
+        protocol: IpProtocol,
+        payload: &[u8],
+    ) -> Flow {
+        let ports = match protocol {
+            IpProtocol::Tcp | IpProtocol::Udp if payload.len() >= 4 => {
+                let packet = UdpPacket::new_unchecked(payload);
+                Some((packet.src_port(), packet.dst_port()))
+            }
+            #[cfg(feature = "proto-ipv4")]
+            IpProtocol::Icmp => match Icmpv4Packet::new_checked(payload) {
+                Ok(packet)
+                    if matches!(
+                        packet.msg_type(),
+                        Icmpv4Message::EchoRequest | Icmpv4Message::EchoReply
+                    ) =>
+                {
+                    Some((packet.echo_ident(), packet.echo_ident()))
+                }
+                _ => None,
+            },
+            #[cfg(feature = "proto-ipv6")]
+            IpProtocol::Icmpv6 => match Icmpv6Packet::new_checked(payload) {
+                Ok(packet)
+                    if matches!(
+                        packet.msg_type(),
+                        Icmpv6Message::EchoRequest | Icmpv6Message::EchoReply
+                    ) =>
+                {
+                    Some((packet.echo_ident(), packet.echo_ident()))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        self.quoted = ports.map(|(src_port, dst_port)| Connection {
+            protocol,
+            local_addr: src_addr,
+            local_port: src_port,
+            remote_addr: dst_addr,
+            remote_port: dst_port,
+        });
+        self
+    }
+
+    #[cfg(feature = "proto-ipv4")]
+    fn with_quoted_ipv4(self, data: &[u8]) -> Flow {
+        let packet = Ipv4Packet::new_unchecked(data);
+        if data.len() < IPV4_HEADER_LEN
+            || packet.version() != 4
+            || data.len() < packet.header_len() as usize
+        {
+            return self;
+        }
+        self.with_quoted(
+            packet.src_addr().into(),
+            packet.dst_addr().into(),
+            packet.next_header(),
+            &data[packet.header_len() as usize..],
+        )
+    }
+
+    #[cfg(feature = "proto-ipv6")]
+    fn with_quoted_ipv6(self, data: &[u8]) -> Flow {
+        let packet = Ipv6Packet::new_unchecked(data);
+        if data.len() < IPV6_HEADER_LEN || packet.version() != 6 {
+            return self;
+        }
+        self.with_quoted(
+            packet.src_addr().into(),
+            packet.dst_addr().into(),
+            packet.next_header(),
+            &data[IPV6_HEADER_LEN..],
+        )
+    }
+
+    fn connection(&self, direction: Direction) -> Connection {
+        match direction {
+            Direction::Ingress => Connection {
+                protocol: self.protocol,
+                local_addr: self.dst_addr,
+                local_port: self.dst_port,
+                remote_addr: self.src_addr,
+                remote_port: self.src_port,
+            },
+            Direction::Egress => Connection {
+                protocol: self.protocol,
+                local_addr: self.src_addr,
+                local_port: self.src_port,
+                remote_addr: self.dst_addr,
+                remote_port: self.dst_port,
+            },
+        }
+    }
+
+    /// Return the connection of the packet quoted by an ICMP error, seen from the
+    /// interface. An error received quotes a packet that was sent, and conversely.
+    fn quoted_connection(&self, direction: Direction) -> Option<Connection> {
+        self.quoted.map(|quoted| match direction {
+            Direction::Ingress => quoted,
+            Direction::Egress => Connection {
+                protocol: quoted.protocol,
+                local_addr: quoted.remote_addr,
+                local_port: quoted.remote_port,
+                remote_addr: quoted.local_addr,
+                remote_port: quoted.local_port,
+            },
+        })
+    }
+}
+
+/// A stateful packet filter.
+///
+/// Packets are matched against the rules in order, and the action of the first matching
+/// rule is taken, or the policy of their direction if none matches. The connections of
+/// the TCP, UDP and ICMP echo packets let through are tracked, and the packets of
+/// tracked connections are let through in both directions without looking at the rules,
+/// as are the ICMP errors about them.
+///
+/// # Examples
+///
+/// On systems with heap, a filter only accepting incoming packets of outgoing
+/// connections, and SSH connections, can be created with:
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use smoltcp::iface::{Filter, FilterAction, FilterDirection, FilterRule};
+/// use smoltcp::wire::IpProtocol;
+///
+/// let ssh = FilterRule::new(FilterDirection::Ingress, FilterAction::Accept)
+///     .protocol(IpProtocol::Tcp)
+///     .dst_port(22);
+/// let mut filter = Filter::new(vec![ssh], BTreeMap::new());
+/// filter.set_policy(FilterDirection::Ingress, FilterAction::Drop);
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::Filter;
+/// let mut rules = [];
+/// let mut connections = [None; 16];
+/// let mut filter = Filter::new(&mut rules[..], &mut connections[..]);
+/// ```
+#[derive(Debug)]
+pub struct Filter<'a> {
+    rules: ManagedSlice<'a, Rule>,
+    connections: ManagedMap<'a, Connection, Instant>,
+    ingress_policy: Action,
+    egress_policy: Action,
+}
+
+impl<'a> Filter<'a> {
+    /// Idle lifetime of a tracked TCP connection.
+    pub(crate) const TCP_TIMEOUT: Duration = Duration::from_secs(7440);
+
+    /// Idle lifetime of a tracked UDP connection.
+    pub(crate) const UDP_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// Idle lifetime of a tracked ICMP echo exchange.
+    pub(crate) const ICMP_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Create a filter with the given rules, accepting the packets no rule matches.
+    /// The connection tracking storage is cleared upon creation.
+    pub fn new<R, C>(rules: R, connections: C) -> Filter<'a>
+    where
+        R: Into<ManagedSlice<'a, Rule>>,
+        C: Into<ManagedMap<'a, Connection, Instant>>,
+    {
+        let mut connections = connections.into();
+        connections.clear();
+
+        Filter {
+            rules: rules.into(),
+            connections,
+            ingress_policy: Action::Accept,
+            egress_policy: Action::Accept,
+        }
+    }
+
+    /// Get the rules of the filter.
+    pub fn rules(&self) -> &[Rule] {
+        self.rules.as_ref()
+    }
+
+    /// Update the rules of the filter.
+    pub fn update_rules<F: FnOnce(&mut ManagedSlice<'a, Rule>)>(&mut self, f: F) {
+        f(&mut self.rules);
+    }
+
+    /// Get the action taken for packets in `direction` that no rule matches.
+    pub fn policy(&self, direction: Direction) -> Action {
+        match direction {
+            Direction::Ingress => self.ingress_policy,
+            Direction::Egress => self.egress_policy,
+        }
+    }
+
+    /// Set the action taken for packets in `direction` that no rule matches.
+    pub fn set_policy(&mut self, direction: Direction, action: Action) {
+        match direction {
+            Direction::Ingress => self.ingress_policy = action,
+            Direction::Egress => self.egress_policy = action,
+        }
+    }
+
+    /// Forget the tracked connections.
+    pub fn flush_connections(&mut self) {
+        self.connections.clear();
+    }
+
+    /// Return whether `connection` is tracked, and not expired.
+    pub fn is_tracked(&self, connection: &Connection, timestamp: Instant) -> bool {
+        match self.connections.get(connection) {
+            Some(expires_at) => timestamp < *expires_at,
+            None => false,
+        }
+    }
+
+    /// Decide what to do with a packet, tracking its connection if it's let through.
+    pub(crate) fn check(
+        &mut self,
+        direction: Direction,
+        flow: &Flow,
+        timestamp: Instant,
+    ) -> Action {
+        let connection = flow.connection(direction);
+        let related = flow
+            .quoted_connection(direction)
+            .map_or(false, |quoted| self.is_tracked(&quoted, timestamp));
+        let action = if (flow.trackable && self.is_tracked(&connection, timestamp)) || related {
+            Action::Accept
+        } else {
+            self.rules
+                .iter()
+                .find(|rule| rule.matches(direction, flow))
+                .map_or(self.policy(direction), |rule| rule.action)
+        };
+
+        if action == Action::Accept && flow.trackable {
+            if flow.tcp_flags.contains(TcpFlags::RST) {
+                self.connections.remove(&connection);
+            } else {
+                self.track(connection, timestamp);
+            }
+        }
+        action
+    }
+
+    fn track(&mut self, connection: Connection, timestamp: Instant) {
+        let timeout = match connection.protocol {
+            IpProtocol::Tcp => Self::TCP_TIMEOUT,
+            IpProtocol::Udp => Self::UDP_TIMEOUT,
+            _ => Self::ICMP_TIMEOUT,
+        };
+        let expires_at = timestamp + timeout;
+
+        if let Err((connection, expires_at)) = self.connections.insert(connection, expires_at) {
+            // The storage is full, forget the connection closest to expiring. Without
+            // storage, nothing is tracked.
+            let oldest = match self
+                .connections
+                .iter()
+                .min_by_key(|(_, expires_at)| **expires_at)
+            {
+                Some((oldest, _)) => *oldest,
+                None => return,
+            };
+            self.connections.remove(&oldest);
+            let _ = self.connections.insert(connection, expires_at);
+            net_trace!("filter: stopped tracking {:?}", oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wire::ip::test::{MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, MOCK_IP_ADDR_3};
+
+    fn host_cidr(addr: IpAddress) -> IpCidr {
+        let prefix_len = match addr {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddress::Ipv4(_) => 32,
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => 128,
+        };
+        IpCidr::new(addr, prefix_len)
+    }
+
+    fn tcp_flow(src_addr: IpAddress, dst_addr: IpAddress, dst_port: u16, flags: TcpFlags) -> Flow {
+        Flow::new(src_addr, dst_addr, IpProtocol::Tcp)
+            .with_ports(40000, dst_port, true)
+            .with_tcp_flags(flags)
+    }
+
+    #[test]
+    fn test_rules_in_order() {
+        let mut rules = [
+            Rule::new(Direction::Ingress, Action::Accept)
+                .protocol(IpProtocol::Tcp)
+                .dst_port(PortRange::new(20, 22)),
+            Rule::new(Direction::Ingress, Action::Reject).protocol(IpProtocol::Tcp),
+        ];
+        let mut storage = [None; 4];
+        let mut filter = Filter::new(&mut rules[..], &mut storage[..]);
+        let now = Instant::from_secs(0);
+
+        let ssh = tcp_flow(MOCK_IP_ADDR_2, MOCK_IP_ADDR_1, 22, TcpFlags::SYN);
+        assert_eq!(filter.check(Direction::Ingress, &ssh, now), Action::Accept);
+        let http = tcp_flow(MOCK_IP_ADDR_2, MOCK_IP_ADDR_1, 80, TcpFlags::SYN);
+        assert_eq!(filter.check(Direction::Ingress, &http, now), Action::Reject);
+        // Rules only apply to their direction.
+        assert_eq!(filter.check(Direction::Egress, &http, now), Action::Accept);
+
+        let udp =
+            Flow::new(MOCK_IP_ADDR_2, MOCK_IP_ADDR_1, IpProtocol::Udp).with_ports(1, 22, true);
+        assert_eq!(filter.check(Direction::Ingress, &udp, now), Action::Accept);
+    }
+
+    #[test]
+    fn test_addresses_and_flags() {
+        let mut rules = [Rule::new(Direction::Ingress, Action::Drop)
+            .src_addr(host_cidr(MOCK_IP_ADDR_2))
+            .tcp_flags(TcpFlags::SYN | TcpFlags::ACK, TcpFlags::SYN)];
+        let mut storage = [None; 4];
+        let mut filter = Filter::new(&mut rules[..], &mut storage[..]);
+        let now = Instant::from_secs(0);
+
+        let syn = tcp_flow(MOCK_IP_ADDR_2, MOCK_IP_ADDR_1, 80, TcpFlags::SYN);
+        assert_eq!(filter.check(Direction::Ingress, &syn, now), Action::Drop);
+        let syn_ack = tcp_flow(
+            MOCK_IP_ADDR_2,
+            MOCK_IP_ADDR_1,
+            80,
+            TcpFlags::SYN | TcpFlags::ACK,
+        );
+        assert_eq!(
+            filter.check(Direction::Ingress, &syn_ack, now),
+            Action::Accept
+        );
+        let other = tcp_flow(MOCK_IP_ADDR_3, MOCK_IP_ADDR_1, 80, TcpFlags::SYN);
+        assert_eq!(
+            filter.check(Direction::Ingress, &other, now),
+            Action::Accept
+        );
+    }
+
+    #[test]
+    fn test_connection_tracking() {
+        let mut storage = [None; 1];
+        let mut filter = Filter::new(&mut [][..], &mut storage[..]);
+        filter.set_policy(Direction::Ingress, Action::Drop);
+        let now = Instant::from_secs(0);
+
+        let request =
+            Flow::new(MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, IpProtocol::Udp).with_ports(1000, 53, true);
+        let reply =
+            Flow::new(MOCK_IP_ADDR_2, MOCK_IP_ADDR_1, IpProtocol::Udp).with_ports(53, 1000, true);
+        let other =
+            Flow::new(MOCK_IP_ADDR_2, MOCK_IP_ADDR_1, IpProtocol::Udp).with_ports(53, 1001, true);
+
+        assert_eq!(filter.check(Direction::Ingress, &reply, now), Action::Drop);
+        assert_eq!(
+            filter.check(Direction::Egress, &request, now),
+            Action::Accept
+        );
+        assert_eq!(
+            filter.check(Direction::Ingress, &reply, now),
+            Action::Accept
+        );
+        assert_eq!(filter.check(Direction::Ingress, &other, now), Action::Drop);
+
+        // Idle connections expire.
+        let later = now + Filter::UDP_TIMEOUT;
+        assert_eq!(
+            filter.check(Direction::Ingress, &reply, later),
+            Action::Drop
+        );
+
+        // A new connection replaces the oldest one when the storage is full.
+        let request_2 =
+            Flow::new(MOCK_IP_ADDR_1, MOCK_IP_ADDR_3, IpProtocol::Udp).with_ports(1000, 53, true);
+        assert_eq!(
+            filter.check(Direction::Egress, &request, later),
+            Action::Accept
+        );
+        assert_eq!(
+            filter.check(Direction::Egress, &request_2, later),
+            Action::Accept
+        );
+        assert_eq!(
+            filter.check(Direction::Ingress, &reply, later),
+            Action::Drop
+        );
+    }
+
+    #[test]
+    fn test_tcp_reset_forgets_connection() {
+        let mut storage = [None; 4];
+        let mut filter = Filter::new(&mut [][..], &mut storage[..]);
+        filter.set_policy(Direction::Ingress, Action::Drop);
+        let now = Instant::from_secs(0);
+
+        let syn = tcp_flow(MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, 80, TcpFlags::SYN);
+        assert_eq!(filter.check(Direction::Egress, &syn, now), Action::Accept);
+        let connection = syn.connection(Direction::Egress);
+        assert!(filter.is_tracked(&connection, now));
+
+        let rst = tcp_flow(MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, 80, TcpFlags::RST);
+        assert_eq!(filter.check(Direction::Egress, &rst, now), Action::Accept);
+        assert!(!filter.is_tracked(&connection, now));
+    }
+
+    #[test]
+    fn test_no_connection_tracking_storage() {
+        let mut filter = Filter::new(&mut [][..], &mut [][..]);
+        filter.set_policy(Direction::Ingress, Action::Drop);
+        let now = Instant::from_secs(0);
+
+        let request =
+            Flow::new(MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, IpProtocol::Udp).with_ports(1000, 53, true);
+        let reply =
+            Flow::new(MOCK_IP_ADDR_2, MOCK_IP_ADDR_1, IpProtocol::Udp).with_ports(53, 1000, true);
+        assert_eq!(
+            filter.check(Direction::Egress, &request, now),
+            Action::Accept
+        );
+        assert_eq!(filter.check(Direction::Ingress, &reply, now), Action::Drop);
+    }
+
+    #[test]
+    fn test_related_icmp_errors() {
+        let mut storage = [None; 4];
+        let mut filter = Filter::new(&mut [][..], &mut storage[..]);
+        filter.set_policy(Direction::Ingress, Action::Drop);
+        let now = Instant::from_secs(0);
+
+        let request =
+            Flow::new(MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, IpProtocol::Udp).with_ports(1000, 53, true);
+        assert_eq!(
+            filter.check(Direction::Egress, &request, now),
+            Action::Accept
+        );
+
+        // Errors received about the packets of a tracked connection are let through...
+        let error = |ports: &[u8]| {
+            Flow::new(MOCK_IP_ADDR_3, MOCK_IP_ADDR_1, IpProtocol::Icmp).with_quoted(
+                MOCK_IP_ADDR_1,
+                MOCK_IP_ADDR_2,
+                IpProtocol::Udp,
+                ports,
+            )
+        };
+        let received_error = error(&[0x03, 0xe8, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00]);
+        assert_eq!(
+            filter.check(Direction::Ingress, &received_error, now),
+            Action::Accept
+        );
+        // ... but not errors about other connections, or quoting too little.
+        let other_error = error(&[0x03, 0xe9, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00]);
+        assert_eq!(
+            filter.check(Direction::Ingress, &other_error, now),
+            Action::Drop
+        );
+        let truncated_error = error(&[0x03]);
+        assert_eq!(
+            filter.check(Direction::Ingress, &truncated_error, now),
+            Action::Drop
+        );
+
+        // Errors sent about the packets of a tracked connection are let through too.
+        filter.set_policy(Direction::Egress, Action::Drop);
+        let sent_error = Flow::new(MOCK_IP_ADDR_1, MOCK_IP_ADDR_2, IpProtocol::Icmp).with_quoted(
+            MOCK_IP_ADDR_2,
+            MOCK_IP_ADDR_1,
+            IpProtocol::Udp,
+            &[0x00, 0x35, 0x03, 0xe8, 0x00, 0x08, 0x00, 0x00],
+        );
+        assert_eq!(
+            filter.check(Direction::Egress, &sent_error, now),
+            Action::Accept
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_parse_icmpv4_error() {
+        let src_addr = Ipv4Address::new(192, 168, 1, 1);
+        let dst_addr = Ipv4Address::new(192, 168, 1, 2);
+        let udp = [0x03, 0xe8, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00];
+        let repr = Icmpv4Repr::DstUnreachable {
+            reason: Icmpv4DstUnreachable::FragRequired,
+            header: Ipv4Repr {
+                src_addr: dst_addr,
+                dst_addr: src_addr,
+                next_header: IpProtocol::Udp,
+                payload_len: udp.len(),
+                hop_limit: 64,
+            },
+            data: &udp,
+        };
+        let mut bytes = vec![0; repr.buffer_len()];
+        repr.emit(
+            &mut Icmpv4Packet::new_unchecked(&mut bytes[..]),
+            &crate::phy::ChecksumCapabilities::default(),
+        );
+
+        let flow = Flow::parse(src_addr.into(), dst_addr.into(), IpProtocol::Icmp, &bytes);
+        assert!(!flow.trackable);
+        assert_eq!(
+            flow.quoted_connection(Direction::Ingress),
+            Some(Connection {
+                protocol: IpProtocol::Udp,
+                local_addr: dst_addr.into(),
+                local_port: 1000,
+                remote_addr: src_addr.into(),
+                remote_port: 53,
+            })
+        );
+    }
+}
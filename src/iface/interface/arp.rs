@@ -0,0 +1,265 @@
+// ARP announcements and address conflict detection, as described in RFC 5227 § 2.3
+// and § 2.4. The IPv4 addresses added to the interface, and all of them when its
+// hardware address changes, are announced with gratuitous ARP requests. Other hosts
+// claiming one of them in ARP packets are reported, and the address is defended once.
+
+use heapless::Vec;
+use managed::ManagedSlice;
+
+use super::{Interface, InterfaceInner};
+use crate::phy::Device;
+use crate::time::{Duration, Instant};
+use crate::wire::*;
+use crate::{Error, Result};
+
+/// Maximum number of IPv4 addresses announced and watched for conflicts. Addresses
+/// beyond that are used without either.
+pub const ARP_MAX_ADDRESS_COUNT: usize = 4;
+
+/// Maximum number of conflicts waiting to be polled. Further ones are dropped.
+pub const ARP_MAX_CONFLICT_COUNT: usize = 4;
+
+// Constants from RFC 5227 § 1.1.
+const ANNOUNCE_NUM: u8 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+const DEFEND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Another host claiming one of the IPv4 addresses of the interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Conflict {
+    /// The address of the interface.
+    pub addr: Ipv4Address,
+    /// The hardware address of the other host.
+    pub hardware_addr: EthernetAddress,
+    /// When the conflicting ARP packet was received.
+    pub timestamp: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Address {
+    addr: Ipv4Address,
+    /// When the next announcement is due, if any is left.
+    announce_at: Option<Instant>,
+    announces_left: u8,
+    defended_at: Option<Instant>,
+}
+
+impl Address {
+    fn new(addr: Ipv4Address) -> Self {
+        Address {
+            addr,
+            announce_at: None,
+            announces_left: 0,
+            defended_at: None,
+        }
+    }
+
+    fn announce(&mut self, count: u8, now: Instant) {
+        self.announce_at = Some(now);
+        self.announces_left = count;
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) struct State {
+    addresses: Vec<Address, ARP_MAX_ADDRESS_COUNT>,
+    conflicts: Vec<Conflict, ARP_MAX_CONFLICT_COUNT>,
+}
+
+impl State {
+    /// Create the state for an interface, watching `ip_addrs` without announcing them.
+    pub(super) fn new(ip_addrs: &[IpCidr]) -> Self {
+        let mut state = State {
+            addresses: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        for addr in ip_addrs.iter().filter_map(ipv4_addr) {
+            if state.get(addr).is_none() && state.addresses.push(Address::new(addr)).is_err() {
+                net_debug!("arp: too many addresses, not watching {}", addr);
+            }
+        }
+        state
+    }
+
+    fn get(&self, addr: Ipv4Address) -> Option<&Address> {
+        self.addresses.iter().find(|address| address.addr == addr)
+    }
+
+    /// Forget the addresses that were removed from `ip_addrs`, and announce the ones
+    /// that were added, except those in `skip`.
+    fn sync(
+        &mut self,
+        ip_addrs: &ManagedSlice<IpCidr>,
+        skip: impl Fn(Ipv4Address) -> bool,
+        now: Instant,
+    ) {
+        let mut i = 0;
+        while i < self.addresses.len() {
+            let addr = self.addresses[i].addr;
+            if ip_addrs.iter().filter_map(ipv4_addr).any(|a| a == addr) {
+                i += 1;
+            } else {
+                self.addresses.remove(i);
+            }
+        }
+
+        for addr in ip_addrs.iter().filter_map(ipv4_addr) {
+            if self.get(addr).is_some() || skip(addr) {
+                continue;
+            }
+            let mut address = Address::new(addr);
+            address.announce(ANNOUNCE_NUM, now);
+            if self.addresses.push(address).is_err() {
+                net_debug!("arp: too many addresses, not announcing {}", addr);
+            }
+        }
+    }
+
+    /// Return the next time an announcement needs to be sent.
+    pub(super) fn poll_at(&self) -> Option<Instant> {
+        self.addresses
+            .iter()
+            .filter_map(|address| address.announce_at)
+            .min()
+    }
+}
+
+/// Return the address of `cidr` if it's announced and watched for conflicts.
+fn ipv4_addr(cidr: &IpCidr) -> Option<Ipv4Address> {
+    match cidr {
+        IpCidr::Ipv4(cidr) if cidr.address().is_unicast() && !cidr.address().is_loopback() => {
+            Some(cidr.address())
+        }
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Return whether `addr` is left to zeroconf to announce and defend.
+    fn is_zeroconf_addr(&self, addr: Ipv4Address) -> bool {
+        self.zeroconf.is_some() && addr.is_link_local()
+    }
+
+    /// Announce the IPv4 addresses that were added to the interface.
+    pub(super) fn arp_sync(&mut self) {
+        if !matches!(self.hardware_addr, Some(HardwareAddress::Ethernet(_))) {
+            return;
+        }
+        let zeroconf = self.zeroconf.is_some();
+        self.arp.sync(
+            &self.ip_addrs,
+            |addr| zeroconf && addr.is_link_local(),
+            self.now,
+        );
+    }
+
+    /// Announce every IPv4 address of the interface again, e.g. after a change of
+    /// hardware address.
+    pub(super) fn arp_announce_all(&mut self) {
+        let now = self.now;
+        for address in self.arp.addresses.iter_mut() {
+            address.announce(ANNOUNCE_NUM, now);
+        }
+    }
+
+    /// Look for another host claiming one of our addresses in a received ARP packet.
+    pub(super) fn arp_check_conflict(
+        &mut self,
+        source_hardware_addr: EthernetAddress,
+        source_protocol_addr: Ipv4Address,
+    ) {
+        if self.is_zeroconf_addr(source_protocol_addr) {
+            return;
+        }
+        let now = self.now;
+        let address = match self
+            .arp
+            .addresses
+            .iter_mut()
+            .find(|address| address.addr == source_protocol_addr)
+        {
+            Some(address) => address,
+            None => return,
+        };
+
+        net_debug!(
+            "arp: {} is claimed by {}",
+            source_protocol_addr,
+            source_hardware_addr
+        );
+        // RFC 5227 § 2.4 (b): defend the address with a single announcement, unless
+        // it was defended recently.
+        if address
+            .defended_at
+            .map_or(true, |defended_at| now >= defended_at + DEFEND_INTERVAL)
+        {
+            address.defended_at = Some(now);
+            address.announce(1, now);
+        }
+
+        let conflict = Conflict {
+            addr: source_protocol_addr,
+            hardware_addr: source_hardware_addr,
+            timestamp: now,
+        };
+        if self.arp.conflicts.push(conflict).is_err() {
+            net_debug!("arp: too many conflicts, dropping one");
+        }
+    }
+}
+
+impl<'a> Interface<'a> {
+    /// Get the next conflict detected on one of the IPv4 addresses of the interface.
+    ///
+    /// The interface keeps using the address; it's up to the application to pick
+    /// another one, for example by restarting its DHCP client.
+    pub fn poll_arp_conflict(&mut self) -> Option<Conflict> {
+        if self.inner.arp.conflicts.is_empty() {
+            None
+        } else {
+            Some(self.inner.arp.conflicts.remove(0))
+        }
+    }
+
+    /// Send the next gratuitous ARP announcement, if one is due.
+    pub(super) fn arp_egress<D>(&mut self, device: &mut D) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        let hardware_addr = match self.inner.hardware_addr {
+            Some(HardwareAddress::Ethernet(addr)) => addr,
+            _ => return Ok(false),
+        };
+        let now = self.inner.now;
+        let addr = match self
+            .inner
+            .arp
+            .addresses
+            .iter()
+            .find(|address| address.announce_at.map_or(false, |at| now >= at))
+        {
+            Some(address) => address.addr,
+            None => return Ok(false),
+        };
+
+        let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+        self.inner
+            .broadcast_arp_request(tx_token, hardware_addr, addr, addr)?;
+
+        for address in self.inner.arp.addresses.iter_mut() {
+            if address.addr == addr {
+                address.announces_left -= 1;
+                address.announce_at = if address.announces_left > 0 {
+                    Some(now + ANNOUNCE_INTERVAL)
+                } else {
+                    None
+                };
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,278 @@
+// IPv6 Duplicate Address Detection, as described in RFC 4862 § 5.4. Addresses assigned
+// when the interface is built are assumed to be unique; any address added afterwards
+// starts out tentative and is only used once no other node has claimed it.
+
+use heapless::Vec;
+use managed::ManagedSlice;
+
+use super::{Interface, InterfaceInner, IpPacket};
+use crate::phy::Device;
+use crate::rand::Rand;
+use crate::time::{Duration, Instant};
+use crate::wire::*;
+use crate::{Error, Result};
+
+/// Maximum number of IPv6 addresses whose state is tracked. Addresses beyond that are
+/// used right away, without Duplicate Address Detection.
+pub const DAD_MAX_ADDRESS_COUNT: usize = 8;
+
+/// Maximum random delay before the first probe, see RFC 4862 § 5.4.2.
+const MAX_PROBE_DELAY: Duration = Duration::from_secs(1);
+/// Time between probes, and after the last one (`RetransTimer` in RFC 4861 § 10).
+const RETRANS_TIMER: Duration = Duration::from_secs(1);
+/// Number of probes sent for each address (`DupAddrDetectTransmits` in RFC 4862 § 5.1).
+const DUP_ADDR_DETECT_TRANSMITS: u8 = 1;
+
+/// The state of an IPv6 address assigned to the interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressState {
+    /// Duplicate Address Detection is in progress; the address isn't used yet.
+    Tentative,
+    /// The address is unique on the link, and can be used freely.
+    Preferred,
+    /// The address was configured by SLAAC and its preferred lifetime has run out.
+    /// It's only used as a source address when there's no better one.
+    Deprecated,
+    /// Another node on the link uses the address, so it isn't used.
+    Duplicated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum DadState {
+    /// Probing for other nodes using the address.
+    Tentative { probe_at: Instant, probes_sent: u8 },
+    /// No other node answered the probes.
+    Preferred,
+    /// Another node answered the probes, or is probing for the same address.
+    Duplicated,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) struct State {
+    addresses: Vec<(Ipv6Address, DadState), DAD_MAX_ADDRESS_COUNT>,
+}
+
+impl State {
+    /// Create the state for an interface, considering `ip_addrs` as preferred.
+    pub(super) fn new(ip_addrs: &[IpCidr]) -> Self {
+        let mut state = State {
+            addresses: Vec::new(),
+        };
+        for addr in ip_addrs.iter().filter_map(ipv6_addr) {
+            if state.get(addr).is_none()
+                && state.addresses.push((addr, DadState::Preferred)).is_err()
+            {
+                net_debug!("dad: too many addresses, not tracking {}", addr);
+            }
+        }
+        state
+    }
+
+    fn get(&self, addr: Ipv6Address) -> Option<DadState> {
+        self.addresses
+            .iter()
+            .find(|(probe, _)| *probe == addr)
+            .map(|&(_, state)| state)
+    }
+
+    fn set(&mut self, addr: Ipv6Address, new_state: DadState) {
+        for (probe, state) in self.addresses.iter_mut() {
+            if *probe == addr {
+                *state = new_state;
+            }
+        }
+    }
+
+    /// Forget the addresses that were removed from `ip_addrs`, and start probing for
+    /// the ones that were added.
+    fn sync(&mut self, ip_addrs: &ManagedSlice<IpCidr>, rand: &mut Rand, now: Instant) {
+        let mut i = 0;
+        while i < self.addresses.len() {
+            let addr = self.addresses[i].0;
+            if ip_addrs
+                .iter()
+                .filter_map(ipv6_addr)
+                .any(|probe| probe == addr)
+            {
+                i += 1;
+            } else {
+                self.addresses.remove(i);
+            }
+        }
+
+        for addr in ip_addrs.iter().filter_map(ipv6_addr) {
+            if self.get(addr).is_some() {
+                continue;
+            }
+            let delay = rand.rand_u32() % (MAX_PROBE_DELAY.total_millis() as u32);
+            let state = DadState::Tentative {
+                probe_at: now + Duration::from_millis(delay as u64),
+                probes_sent: 0,
+            };
+            if self.addresses.push((addr, state)).is_err() {
+                net_debug!("dad: too many addresses, not probing for {}", addr);
+            } else {
+                net_debug!("dad: {} is tentative", addr);
+            }
+        }
+    }
+
+    /// Return the next time a probe needs to be sent, or an address becomes preferred.
+    pub(super) fn poll_at(&self) -> Option<Instant> {
+        self.addresses
+            .iter()
+            .filter_map(|(_, state)| match state {
+                DadState::Tentative { probe_at, .. } => Some(*probe_at),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+/// Return the address of `cidr` if Duplicate Address Detection applies to it.
+fn ipv6_addr(cidr: &IpCidr) -> Option<Ipv6Address> {
+    match cidr {
+        IpCidr::Ipv6(cidr) if cidr.address().is_unicast() && !cidr.address().is_loopback() => {
+            Some(cidr.address())
+        }
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Start Duplicate Address Detection for the IPv6 addresses that were just assigned.
+    pub(super) fn dad_sync(&mut self) {
+        // There's no Neighbor Discovery on links without hardware addresses.
+        if self.hardware_addr.is_none() {
+            return;
+        }
+        self.dad.sync(&self.ip_addrs, &mut self.rand, self.now);
+    }
+
+    /// Return whether `addr` can be used, i.e. it's neither tentative nor a duplicate.
+    pub(super) fn is_usable_ipv6_addr(&self, addr: Ipv6Address) -> bool {
+        matches!(self.dad.get(addr), None | Some(DadState::Preferred))
+    }
+
+    /// Process a Neighbor Solicitation for `target_addr`, and return whether it's one of our
+    /// addresses that must not be advertised yet.
+    pub(super) fn dad_process_solicit(
+        &mut self,
+        src_addr: Ipv6Address,
+        target_addr: Ipv6Address,
+    ) -> bool {
+        match self.dad.get(target_addr) {
+            Some(DadState::Tentative { .. }) => {
+                // RFC 4862 § 5.4.3: a solicitation from the unspecified address means another
+                // node is probing for the same address. Otherwise, it's address resolution,
+                // which is ignored until the address is known to be unique.
+                if src_addr.is_unspecified() {
+                    net_debug!(
+                        "dad: {} is also being probed for by another node",
+                        target_addr
+                    );
+                    self.dad.set(target_addr, DadState::Duplicated);
+                }
+                true
+            }
+            Some(DadState::Duplicated) => true,
+            _ => false,
+        }
+    }
+
+    /// Process a Neighbor Advertisement for `target_addr`.
+    pub(super) fn dad_process_advert(&mut self, target_addr: Ipv6Address) {
+        // RFC 4862 § 5.4.4: another node already uses the address.
+        if let Some(DadState::Tentative { .. }) = self.dad.get(target_addr) {
+            net_debug!("dad: {} is used by another node", target_addr);
+            self.dad.set(target_addr, DadState::Duplicated);
+        }
+    }
+
+    fn dad_solicit_packet(&self, target_addr: Ipv6Address) -> IpPacket<'static> {
+        // RFC 4862 § 5.4.2: probes come from the unspecified address, so they can't include
+        // the source link-layer address option.
+        let solicit = Icmpv6Repr::Ndisc(NdiscRepr::NeighborSolicit {
+            target_addr,
+            lladdr: None,
+        });
+        IpPacket::Icmpv6((
+            Ipv6Repr {
+                src_addr: Ipv6Address::UNSPECIFIED,
+                dst_addr: target_addr.solicited_node(),
+                next_header: IpProtocol::Icmpv6,
+                payload_len: solicit.buffer_len(),
+                hop_limit: 0xff,
+            },
+            solicit,
+        ))
+    }
+}
+
+impl<'a> Interface<'a> {
+    /// Get the state of an IPv6 address assigned to the interface, or `None` if it
+    /// isn't assigned.
+    pub fn ipv6_addr_state(&self, addr: Ipv6Address) -> Option<AddressState> {
+        // Unspecified addresses are free slots, not assigned addresses.
+        if addr.is_unspecified() || !self.inner.has_ip_addr(addr) {
+            return None;
+        }
+
+        match self.inner.dad.get(addr) {
+            Some(DadState::Tentative { .. }) => Some(AddressState::Tentative),
+            Some(DadState::Duplicated) => Some(AddressState::Duplicated),
+            _ if self.inner.is_deprecated_ipv6_addr(addr) => Some(AddressState::Deprecated),
+            _ => Some(AddressState::Preferred),
+        }
+    }
+
+    /// Send the next Duplicate Address Detection probe, if one is due, and mark the
+    /// addresses that went unchallenged as preferred.
+    pub(super) fn dad_egress<D>(&mut self, device: &mut D) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        self.inner.dad_sync();
+
+        let now = self.inner.now;
+        let mut probe = None;
+        for (addr, state) in self.inner.dad.addresses.iter_mut() {
+            match *state {
+                DadState::Tentative {
+                    probe_at,
+                    probes_sent,
+                } if now >= probe_at => {
+                    if probes_sent == DUP_ADDR_DETECT_TRANSMITS {
+                        net_debug!("dad: {} is preferred", addr);
+                        *state = DadState::Preferred;
+                    } else if probe.is_none() {
+                        probe = Some((*addr, probes_sent));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let (target_addr, probes_sent) = match probe {
+            Some(probe) => probe,
+            None => return Ok(false),
+        };
+
+        let pkt = self.inner.dad_solicit_packet(target_addr);
+        let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+        self.inner.dispatch_ip(tx_token, pkt, None)?;
+
+        self.inner.dad.set(
+            target_addr,
+            DadState::Tentative {
+                probe_at: now + RETRANS_TIMER,
+                probes_sent: probes_sent + 1,
+            },
+        );
+        Ok(true)
+    }
+}
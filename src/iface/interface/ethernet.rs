@@ -27,12 +27,29 @@ impl<'i> InterfaceInner<'i> {
             return None;
         }
 
-        match eth_frame.ethertype() {
+        // Strip the VLAN tag, if any. Priority tagged frames count as untagged.
+        let (ethertype, payload) = match eth_frame.ethertype() {
+            EthernetProtocol::Vlan => {
+                let vlan_packet = check!(VlanPacket::new_checked(eth_frame.payload()));
+                let vlan_id = match vlan_packet.vlan_id() {
+                    0 => None,
+                    vlan_id => Some(vlan_id),
+                };
+                if vlan_id != self.vlan_id {
+                    return None;
+                }
+                (vlan_packet.ethertype(), vlan_packet.payload())
+            }
+            _ if self.vlan_id.is_some() => return None,
+            ethertype => (ethertype, eth_frame.payload()),
+        };
+
+        match ethertype {
             #[cfg(feature = "proto-ipv4")]
-            EthernetProtocol::Arp => self.process_arp(self.now, &eth_frame),
+            EthernetProtocol::Arp => self.process_arp(self.now, payload),
             #[cfg(feature = "proto-ipv4")]
             EthernetProtocol::Ipv4 => {
-                let ipv4_packet = check!(Ipv4Packet::new_checked(eth_frame.payload()));
+                let ipv4_packet = check!(Ipv4Packet::new_checked(payload));
 
                 #[cfg(feature = "proto-ipv4-fragmentation")]
                 {
@@ -48,7 +65,7 @@ impl<'i> InterfaceInner<'i> {
             }
             #[cfg(feature = "proto-ipv6")]
             EthernetProtocol::Ipv6 => {
-                let ipv6_packet = check!(Ipv6Packet::new_checked(eth_frame.payload()));
+                let ipv6_packet = check!(Ipv6Packet::new_checked(payload));
                 self.process_ipv6(sockets, &ipv6_packet)
                     .map(EthernetPacket::Ip)
             }
@@ -68,10 +85,12 @@ impl<'i> InterfaceInner<'i> {
         Tx: TxToken,
         F: FnOnce(EthernetFrame<&mut [u8]>),
     {
-        let tx_len = EthernetFrame::<&[u8]>::buffer_len(buffer_len);
+        // Leave room in front of the frame for the VLAN tag, if any.
+        let tag_len = self.vlan_tag_len();
+        let tx_len = EthernetFrame::<&[u8]>::buffer_len(buffer_len) + tag_len;
         tx_token.consume(self.now, tx_len, |tx_buffer| {
             debug_assert!(tx_buffer.as_ref().len() == tx_len);
-            let mut frame = EthernetFrame::new_unchecked(tx_buffer);
+            let mut frame = EthernetFrame::new_unchecked(&mut tx_buffer[tag_len..]);
 
             let src_addr = if let Some(HardwareAddress::Ethernet(addr)) = self.hardware_addr {
                 addr
@@ -82,8 +101,43 @@ impl<'i> InterfaceInner<'i> {
             frame.set_src_addr(src_addr);
 
             f(frame);
+            self.insert_vlan_tag(tx_buffer);
 
             Ok(())
         })
     }
+
+    /// Return the length of the room left in front of the frames sent for the VLAN
+    /// tag, if any.
+    #[cfg(feature = "medium-ethernet")]
+    pub(super) fn vlan_tag_len(&self) -> usize {
+        if self.vlan_id.is_some() {
+            VLAN_HEADER_LEN
+        } else {
+            0
+        }
+    }
+
+    /// Move the addresses of the Ethernet frame sent after the room left for the
+    /// VLAN tag in `tx_buffer` back in front, and fill the tag in, if any.
+    #[cfg(feature = "medium-ethernet")]
+    pub(super) fn insert_vlan_tag(&self, tx_buffer: &mut [u8]) {
+        let vlan_id = match self.vlan_id {
+            Some(vlan_id) => vlan_id,
+            None => return,
+        };
+        let ethertype = EthernetFrame::new_unchecked(&tx_buffer[VLAN_HEADER_LEN..]).ethertype();
+        let addrs_len = ETHERNET_HEADER_LEN - 2;
+        tx_buffer.copy_within(VLAN_HEADER_LEN..VLAN_HEADER_LEN + addrs_len, 0);
+
+        let mut frame = EthernetFrame::new_unchecked(tx_buffer);
+        frame.set_ethertype(EthernetProtocol::Vlan);
+        let vlan_repr = VlanRepr {
+            priority: self.vlan_priority,
+            drop_eligible: false,
+            vlan_id,
+            ethertype,
+        };
+        vlan_repr.emit(&mut VlanPacket::new_unchecked(frame.payload_mut()));
+    }
 }
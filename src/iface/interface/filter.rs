@@ -0,0 +1,169 @@
+use super::icmp_reply_payload_len;
+use super::InterfaceInner;
+use super::IpPacket;
+
+#[cfg(feature = "socket-tcp")]
+use crate::iface::filter::TcpFlags;
+use crate::iface::filter::{Action, Direction, Flow};
+use crate::wire::*;
+
+impl<'a> InterfaceInner<'a> {
+    /// Run the filter on a received packet addressed to us.
+    ///
+    /// Returns `None` if the packet is let through, or the reply to send instead of
+    /// processing it.
+    pub(super) fn filter_ingress<'frame>(
+        &mut self,
+        ip_repr: &IpRepr,
+        ip_payload: &'frame [u8],
+    ) -> Option<Option<IpPacket<'frame>>> {
+        let filter = self.filter.as_mut()?;
+        let flow = Flow::parse(
+            ip_repr.src_addr(),
+            ip_repr.dst_addr(),
+            ip_repr.next_header(),
+            ip_payload,
+        );
+        match filter.check(Direction::Ingress, &flow, self.now) {
+            Action::Accept => None,
+            Action::Drop => {
+                net_debug!(
+                    "filter: dropped {} packet from {}",
+                    flow.protocol,
+                    flow.src_addr
+                );
+                Some(None)
+            }
+            Action::Reject => {
+                net_debug!(
+                    "filter: rejected {} packet from {}",
+                    flow.protocol,
+                    flow.src_addr
+                );
+                Some(self.filter_reject(ip_repr, &flow, ip_payload))
+            }
+        }
+    }
+
+    /// Run the filter on a packet about to be sent.
+    pub(super) fn filter_egress(&mut self, packet: &IpPacket) -> Action {
+        match self.filter.as_mut() {
+            Some(filter) => filter.check(Direction::Egress, &flow_of(packet), self.now),
+            None => Action::Accept,
+        }
+    }
+
+    /// Reply to a rejected packet with an ICMP administratively prohibited error.
+    fn filter_reject<'frame>(
+        &self,
+        ip_repr: &IpRepr,
+        flow: &Flow,
+        ip_payload: &'frame [u8],
+    ) -> Option<IpPacket<'frame>> {
+        // Only ICMP echo is answered with errors, not other ICMP messages.
+        #[cfg(feature = "proto-ipv4")]
+        if flow.protocol == IpProtocol::Icmp && !flow.trackable {
+            return None;
+        }
+        #[cfg(feature = "proto-ipv6")]
+        if flow.protocol == IpProtocol::Icmpv6 && !flow.trackable {
+            return None;
+        }
+
+        match *ip_repr {
+            #[cfg(feature = "proto-ipv4")]
+            IpRepr::Ipv4(ipv4_repr) => {
+                let payload_len =
+                    icmp_reply_payload_len(ip_payload.len(), IPV4_MIN_MTU, ipv4_repr.buffer_len());
+                let icmp_reply_repr = Icmpv4Repr::DstUnreachable {
+                    reason: Icmpv4DstUnreachable::CommProhibited,
+                    header: ipv4_repr,
+                    data: &ip_payload[..payload_len],
+                };
+                self.icmpv4_reply(ipv4_repr, icmp_reply_repr)
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpRepr::Ipv6(ipv6_repr) => {
+                let payload_len =
+                    icmp_reply_payload_len(ip_payload.len(), IPV6_MIN_MTU, ipv6_repr.buffer_len());
+                let icmp_reply_repr = Icmpv6Repr::DstUnreachable {
+                    reason: Icmpv6DstUnreachable::AdminProhibit,
+                    header: ipv6_repr,
+                    data: &ip_payload[..payload_len],
+                };
+                self.icmpv6_reply(ipv6_repr, icmp_reply_repr)
+            }
+        }
+    }
+}
+
+/// Describe a packet about to be sent to the filter.
+fn flow_of(packet: &IpPacket) -> Flow {
+    let ip_repr = packet.ip_repr();
+    let flow = Flow::new(
+        ip_repr.src_addr(),
+        ip_repr.dst_addr(),
+        ip_repr.next_header(),
+    );
+    match packet {
+        #[cfg(feature = "proto-ipv4")]
+        IpPacket::Icmpv4((_, Icmpv4Repr::EchoRequest { ident, .. }))
+        | IpPacket::Icmpv4((_, Icmpv4Repr::EchoReply { ident, .. })) => {
+            flow.with_ports(*ident, *ident, true)
+        }
+        #[cfg(feature = "proto-ipv4")]
+        IpPacket::Icmpv4((_, Icmpv4Repr::DstUnreachable { header, data, .. }))
+        | IpPacket::Icmpv4((_, Icmpv4Repr::TimeExceeded { header, data, .. })) => flow.with_quoted(
+            header.src_addr.into(),
+            header.dst_addr.into(),
+            header.next_header,
+            data,
+        ),
+        #[cfg(feature = "proto-ipv6")]
+        IpPacket::Icmpv6((_, Icmpv6Repr::EchoRequest { ident, .. }))
+        | IpPacket::Icmpv6((_, Icmpv6Repr::EchoReply { ident, .. })) => {
+            flow.with_ports(*ident, *ident, true)
+        }
+        #[cfg(feature = "proto-ipv6")]
+        IpPacket::Icmpv6((_, Icmpv6Repr::DstUnreachable { header, data, .. }))
+        | IpPacket::Icmpv6((_, Icmpv6Repr::PktTooBig { header, data, .. }))
+        | IpPacket::Icmpv6((_, Icmpv6Repr::TimeExceeded { header, data, .. })) => flow.with_quoted(
+            header.src_addr.into(),
+            header.dst_addr.into(),
+            header.next_header,
+            data,
+        ),
+        #[cfg(feature = "socket-raw")]
+        IpPacket::Raw((ip_repr, payload)) => Flow::parse(
+            ip_repr.src_addr(),
+            ip_repr.dst_addr(),
+            ip_repr.next_header(),
+            payload,
+        ),
+        #[cfg(any(feature = "socket-udp", feature = "socket-dns"))]
+        IpPacket::Udp((_, udp_repr, _)) => {
+            flow.with_ports(udp_repr.src_port, udp_repr.dst_port, true)
+        }
+        #[cfg(feature = "socket-tcp")]
+        IpPacket::Tcp((_, tcp_repr)) => flow
+            .with_ports(tcp_repr.src_port, tcp_repr.dst_port, true)
+            .with_tcp_flags(TcpFlags::of_repr(tcp_repr)),
+        #[cfg(feature = "socket-dhcpv4")]
+        IpPacket::Dhcpv4((_, udp_repr, _)) => {
+            flow.with_ports(udp_repr.src_port, udp_repr.dst_port, true)
+        }
+        #[cfg(feature = "socket-dhcpv6")]
+        IpPacket::Dhcpv6((_, udp_repr, _)) => {
+            flow.with_ports(udp_repr.src_port, udp_repr.dst_port, true)
+        }
+        #[cfg(feature = "iface-forwarding")]
+        IpPacket::Forward((ip_repr, packet)) => Flow::parse(
+            ip_repr.src_addr(),
+            ip_repr.dst_addr(),
+            ip_repr.next_header(),
+            &packet[ip_repr.header_len()..],
+        ),
+        #[allow(unreachable_patterns)]
+        _ => flow,
+    }
+}
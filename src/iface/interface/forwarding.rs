@@ -0,0 +1,454 @@
+#[cfg(feature = "proto-sixlowpan")]
+use super::check;
+#[cfg(any(feature = "proto-ipv4", feature = "proto-ipv6"))]
+use super::icmp_reply_payload_len;
+use super::Interface;
+use super::InterfaceId;
+use super::InterfaceInner;
+use super::IpPacket;
+use super::OutPackets;
+
+use crate::phy::{ChecksumCapabilities, Device, TxToken};
+use crate::storage::{PacketBuffer, PacketMetadata};
+use crate::time::Instant;
+use crate::wire::*;
+use crate::Result;
+
+/// Metadata of a packet waiting to be forwarded, i.e. the interface to send it through.
+pub type ForwardingMetadata = PacketMetadata<InterfaceId>;
+
+/// A ring buffer of packets waiting to be forwarded by other interfaces.
+pub type ForwardingBuffer<'a> = PacketBuffer<'a, InterfaceId>;
+
+/// Why a packet couldn't be forwarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Undeliverable {
+    HopLimitExceeded,
+    NoRoute,
+}
+
+impl<'a> Interface<'a> {
+    /// Send the packets that `ingress` received and routed through this interface.
+    ///
+    /// The packets are routed with the routing table of this interface, and sent in the
+    /// order `ingress` received them. This stops at the first packet waiting for another
+    /// interface, so call it for every interface `ingress` forwards to whenever `ingress`
+    /// has been polled.
+    ///
+    /// Returns whether any packet was sent. Packets that can't be sent, e.g. because
+    /// their destination doesn't answer neighbor discovery, are dropped.
+    pub fn forward<D>(
+        &mut self,
+        timestamp: Instant,
+        device: &mut D,
+        ingress: &mut Interface<'_>,
+    ) -> bool
+    where
+        D: Device + ?Sized,
+    {
+        self.inner.now = timestamp;
+
+        let queue = match ingress.inner.forwarding.as_mut() {
+            Some(queue) => queue,
+            None => return false,
+        };
+        let Self {
+            inner, out_packets, ..
+        } = self;
+
+        let mut forwarded_any = false;
+        loop {
+            // Fragments of a previous packet are still being sent.
+            #[cfg(any(
+                feature = "proto-ipv4-fragmentation",
+                feature = "proto-sixlowpan-fragmentation"
+            ))]
+            if !out_packets.all_transmitted() {
+                break;
+            }
+
+            let result = queue.dequeue_with(|egress, packet| {
+                if *egress != inner.id {
+                    return Err(());
+                }
+                let tx_token = device.transmit().ok_or(())?;
+                if let Err(err) = inner.dispatch_forwarded(tx_token, packet, out_packets) {
+                    net_debug!("failed to forward packet: {}", err);
+                }
+                Ok(())
+            });
+
+            match result {
+                Ok(Ok(())) => forwarded_any = true,
+                Ok(Err(())) | Err(_) => break,
+            }
+        }
+        forwarded_any
+    }
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Return whether IP packets not addressed to this interface are forwarded.
+    pub(super) fn is_forwarding(&self) -> bool {
+        self.forwarding.is_some()
+    }
+
+    /// Forward a received IP `packet` that isn't addressed to us, which includes its header.
+    ///
+    /// Packets routed back through this interface are returned to be sent right away, and
+    /// those routed through other interfaces are queued for them. An ICMP error is returned
+    /// instead when the packet can't be delivered.
+    pub(super) fn forward_ip<'frame>(
+        &mut self,
+        mut ip_repr: IpRepr,
+        packet: &'frame [u8],
+    ) -> Option<IpPacket<'frame>> {
+        let ip_payload = &packet[packet.len() - ip_repr.payload_len()..];
+
+        if !is_forwardable(&ip_repr) {
+            net_debug!(
+                "not forwarding link-local packet from {} to {}",
+                ip_repr.src_addr(),
+                ip_repr.dst_addr()
+            );
+            return None;
+        }
+
+        let egress = match self.forwarding_egress(&ip_repr) {
+            Ok(egress) => egress,
+            // RFC 1812 § 4.3.2.7: only the first fragment of a packet is reported.
+            Err(reason) if is_first_fragment(&ip_repr, packet) => {
+                return self.forwarding_error(ip_repr, ip_payload, reason)
+            }
+            Err(_) => return None,
+        };
+
+        match ip_repr {
+            #[cfg(feature = "proto-ipv4")]
+            IpRepr::Ipv4(ref mut repr) => repr.hop_limit -= 1,
+            #[cfg(feature = "proto-ipv6")]
+            IpRepr::Ipv6(ref mut repr) => repr.hop_limit -= 1,
+        }
+
+        if egress == self.id {
+            // The repr describes the whole packet, whichever options its header has.
+            ip_repr.set_payload_len(packet.len() - ip_repr.header_len());
+            return Some(IpPacket::Forward((ip_repr, packet)));
+        }
+
+        if let Some(buffer) = self.enqueue_forwarded(egress, packet.len()) {
+            buffer.copy_from_slice(packet);
+            set_hop_limit(&ip_repr, buffer);
+        }
+        None
+    }
+
+    /// Forward a received 6LoWPAN packet that isn't addressed to us, decompressing it so that
+    /// other interfaces can send it as an IPv6 packet.
+    #[cfg(feature = "proto-sixlowpan")]
+    pub(super) fn forward_sixlowpan<'frame>(
+        &mut self,
+        mut ipv6_repr: Ipv6Repr,
+        next_header: SixlowpanNextHeader,
+        payload: &'frame [u8],
+    ) -> Option<IpPacket<'frame>> {
+        let ip_repr = IpRepr::Ipv6(ipv6_repr);
+        if !is_forwardable(&ip_repr) {
+            net_debug!(
+                "not forwarding link-local packet from {} to {}",
+                ipv6_repr.src_addr,
+                ipv6_repr.dst_addr
+            );
+            return None;
+        }
+
+        let egress = match self.forwarding_egress(&ip_repr) {
+            Ok(egress) => egress,
+            Err(reason) => {
+                // Compressed headers can't be quoted as they are.
+                let ip_payload = match next_header {
+                    SixlowpanNextHeader::Uncompressed(_) => payload,
+                    SixlowpanNextHeader::Compressed => &[],
+                };
+                return self.forwarding_error(ip_repr, ip_payload, reason);
+            }
+        };
+
+        if egress == self.id {
+            net_debug!("6LoWPAN: forwarding back to the same link is not supported");
+            return None;
+        }
+
+        ipv6_repr.hop_limit -= 1;
+
+        match next_header {
+            SixlowpanNextHeader::Uncompressed(next_header) => {
+                ipv6_repr.next_header = next_header;
+                ipv6_repr.payload_len = payload.len();
+
+                if let Some(buffer) =
+                    self.enqueue_forwarded(egress, IPV6_HEADER_LEN + ipv6_repr.payload_len)
+                {
+                    let (header, ip_payload) = buffer.split_at_mut(IPV6_HEADER_LEN);
+                    ipv6_repr.emit(&mut Ipv6Packet::new_unchecked(header));
+                    ip_payload.copy_from_slice(payload);
+                }
+            }
+            SixlowpanNextHeader::Compressed => {
+                match check!(SixlowpanNhcPacket::dispatch(payload)) {
+                    SixlowpanNhcPacket::ExtHeader => {
+                        net_debug!("6LoWPAN: extension headers are currently not supported");
+                    }
+                    SixlowpanNhcPacket::UdpHeader => {
+                        let udp_packet = check!(SixlowpanUdpNhcPacket::new_checked(payload));
+                        let udp_repr = check!(SixlowpanUdpNhcRepr::parse(
+                            &udp_packet,
+                            &ipv6_repr.src_addr,
+                            &ipv6_repr.dst_addr
+                        ))
+                        .0;
+                        let udp_payload = udp_packet.payload();
+
+                        ipv6_repr.next_header = IpProtocol::Udp;
+                        ipv6_repr.payload_len = udp_repr.header_len() + udp_payload.len();
+
+                        if let Some(buffer) =
+                            self.enqueue_forwarded(egress, IPV6_HEADER_LEN + ipv6_repr.payload_len)
+                        {
+                            let (header, ip_payload) = buffer.split_at_mut(IPV6_HEADER_LEN);
+                            ipv6_repr.emit(&mut Ipv6Packet::new_unchecked(header));
+                            udp_repr.emit(
+                                &mut UdpPacket::new_unchecked(ip_payload),
+                                &ipv6_repr.src_addr.into(),
+                                &ipv6_repr.dst_addr.into(),
+                                udp_payload.len(),
+                                |buf| buf.copy_from_slice(udp_payload),
+                                &ChecksumCapabilities::default(),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Send a packet that another interface queued for forwarding.
+    fn dispatch_forwarded<Tx: TxToken>(
+        &mut self,
+        tx_token: Tx,
+        packet: &mut [u8],
+        out_packets: &mut OutPackets<'_>,
+    ) -> Result<()> {
+        #[cfg(feature = "iface-nat")]
+        if self.is_translating() && !self.nat_outbound(packet) {
+            return Ok(());
+        }
+        let packet = &*packet;
+
+        let mut ip_repr = match IpVersion::of_packet(packet)? {
+            #[cfg(feature = "proto-ipv4")]
+            IpVersion::Ipv4 => IpRepr::Ipv4(Ipv4Repr::parse(
+                &Ipv4Packet::new_checked(packet)?,
+                &ChecksumCapabilities::ignored(),
+            )?),
+            #[cfg(feature = "proto-ipv6")]
+            IpVersion::Ipv6 => IpRepr::Ipv6(Ipv6Repr::parse(&Ipv6Packet::new_checked(packet)?)?),
+        };
+        ip_repr.set_payload_len(packet.len() - ip_repr.header_len());
+
+        self.dispatch_ip(
+            tx_token,
+            IpPacket::Forward((ip_repr, packet)),
+            Some(out_packets),
+        )
+    }
+
+    /// Return the interface to forward a packet through.
+    pub(super) fn forwarding_egress(
+        &self,
+        ip_repr: &IpRepr,
+    ) -> core::result::Result<InterfaceId, Undeliverable> {
+        if ip_repr.hop_limit() <= 1 {
+            net_debug!("hop limit of packet to {} exceeded", ip_repr.dst_addr());
+            return Err(Undeliverable::HopLimitExceeded);
+        }
+
+        match self.egress_interface(&ip_repr.dst_addr()) {
+            Some(egress) => Ok(egress),
+            None => {
+                net_debug!("no route to forward packet to {}", ip_repr.dst_addr());
+                Err(Undeliverable::NoRoute)
+            }
+        }
+    }
+
+    /// Return room for a forwarded packet of `size` octets in the forwarding buffer.
+    pub(super) fn enqueue_forwarded(
+        &mut self,
+        egress: InterfaceId,
+        size: usize,
+    ) -> Option<&mut [u8]> {
+        match self.forwarding.as_mut()?.enqueue(size, egress) {
+            Ok(buffer) => Some(buffer),
+            Err(_) => {
+                net_debug!("forwarding buffer full, dropping packet for {}", egress);
+                None
+            }
+        }
+    }
+
+    /// Reply with an ICMP error to a packet that couldn't be forwarded.
+    pub(super) fn forwarding_error<'frame>(
+        &mut self,
+        ip_repr: IpRepr,
+        ip_payload: &'frame [u8],
+        reason: Undeliverable,
+    ) -> Option<IpPacket<'frame>> {
+        // RFC 1122 § 3.2.2: ICMP errors aren't answered with ICMP errors.
+        if is_icmp_error(&ip_repr, ip_payload) {
+            net_debug!("not reporting undeliverable ICMP error");
+            return None;
+        }
+
+        match ip_repr {
+            #[cfg(feature = "proto-ipv4")]
+            IpRepr::Ipv4(ipv4_repr) => {
+                let payload_len =
+                    icmp_reply_payload_len(ip_payload.len(), IPV4_MIN_MTU, ipv4_repr.buffer_len());
+                let data = &ip_payload[..payload_len];
+                let icmp_repr = match reason {
+                    Undeliverable::HopLimitExceeded => Icmpv4Repr::TimeExceeded {
+                        reason: Icmpv4TimeExceeded::TtlExpired,
+                        header: ipv4_repr,
+                        data,
+                    },
+                    Undeliverable::NoRoute => Icmpv4Repr::DstUnreachable {
+                        reason: Icmpv4DstUnreachable::NetUnreachable,
+                        header: ipv4_repr,
+                        data,
+                    },
+                };
+                // The packet wasn't addressed to us, reply from our own address.
+                let dst_addr = self.get_source_address_ipv4(ipv4_repr.src_addr)?;
+                self.icmpv4_reply(
+                    Ipv4Repr {
+                        dst_addr,
+                        ..ipv4_repr
+                    },
+                    icmp_repr,
+                )
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpRepr::Ipv6(ipv6_repr) => {
+                let payload_len =
+                    icmp_reply_payload_len(ip_payload.len(), IPV6_MIN_MTU, ipv6_repr.buffer_len());
+                let data = &ip_payload[..payload_len];
+                let icmp_repr = match reason {
+                    Undeliverable::HopLimitExceeded => Icmpv6Repr::TimeExceeded {
+                        reason: Icmpv6TimeExceeded::HopLimitExceeded,
+                        header: ipv6_repr,
+                        data,
+                    },
+                    Undeliverable::NoRoute => Icmpv6Repr::DstUnreachable {
+                        reason: Icmpv6DstUnreachable::NoRoute,
+                        header: ipv6_repr,
+                        data,
+                    },
+                };
+                // The packet wasn't addressed to us, reply from our own address.
+                let dst_addr = self.get_source_address_ipv6(ipv6_repr.src_addr)?;
+                self.icmpv6_reply(
+                    Ipv6Repr {
+                        dst_addr,
+                        ..ipv6_repr
+                    },
+                    icmp_repr,
+                )
+            }
+        }
+    }
+}
+
+/// Return whether a packet may leave its link, i.e. neither of its addresses is link-local.
+fn is_forwardable(ip_repr: &IpRepr) -> bool {
+    match ip_repr {
+        #[cfg(feature = "proto-ipv4")]
+        IpRepr::Ipv4(repr) => !repr.src_addr.is_link_local() && !repr.dst_addr.is_link_local(),
+        #[cfg(feature = "proto-ipv6")]
+        IpRepr::Ipv6(repr) => !repr.src_addr.is_link_local() && !repr.dst_addr.is_link_local(),
+    }
+}
+
+/// Return whether a packet is an ICMP error message.
+fn is_icmp_error(ip_repr: &IpRepr, ip_payload: &[u8]) -> bool {
+    match ip_repr {
+        #[cfg(feature = "proto-ipv4")]
+        IpRepr::Ipv4(repr) => {
+            repr.next_header == IpProtocol::Icmp
+                && ip_payload.first().map_or(false, |&msg_type| {
+                    matches!(
+                        Icmpv4Message::from(msg_type),
+                        Icmpv4Message::DstUnreachable
+                            | Icmpv4Message::Redirect
+                            | Icmpv4Message::TimeExceeded
+                            | Icmpv4Message::ParamProblem
+                    )
+                })
+        }
+        #[cfg(feature = "proto-ipv6")]
+        IpRepr::Ipv6(repr) => {
+            repr.next_header == IpProtocol::Icmpv6
+                && ip_payload
+                    .first()
+                    .map_or(false, |&msg_type| Icmpv6Message::from(msg_type).is_error())
+        }
+    }
+}
+
+/// Return whether the IP `packet`, which includes its header, isn't a fragment other
+/// than the first one.
+fn is_first_fragment(ip_repr: &IpRepr, packet: &[u8]) -> bool {
+    match ip_repr {
+        #[cfg(feature = "proto-ipv4")]
+        IpRepr::Ipv4(_) => Ipv4Packet::new_unchecked(packet).frag_offset() == 0,
+        #[cfg(feature = "proto-ipv6")]
+        IpRepr::Ipv6(repr) => {
+            repr.next_header != IpProtocol::Ipv6Frag
+                || Ipv6FragmentHeader::new_checked(&packet[IPV6_HEADER_LEN..])
+                    .map_or(true, |header| header.frag_offset() == 0)
+        }
+    }
+}
+
+/// Write the hop limit of `ip_repr` into the header of the IP packet in `buffer`.
+pub(super) fn set_hop_limit(ip_repr: &IpRepr, buffer: &mut [u8]) {
+    match ip_repr {
+        #[cfg(feature = "proto-ipv4")]
+        IpRepr::Ipv4(repr) => Ipv4Packet::new_unchecked(buffer).set_hop_limit(repr.hop_limit),
+        #[cfg(feature = "proto-ipv6")]
+        IpRepr::Ipv6(repr) => Ipv6Packet::new_unchecked(buffer).set_hop_limit(repr.hop_limit),
+    }
+}
+
+/// Emit a forwarded IP `packet` into `buffer`, with the hop limit of `ip_repr`.
+pub(super) fn emit(
+    ip_repr: &IpRepr,
+    packet: &[u8],
+    buffer: &mut [u8],
+    checksum_caps: &ChecksumCapabilities,
+) {
+    let buffer = &mut buffer[..packet.len()];
+    buffer.copy_from_slice(packet);
+    set_hop_limit(ip_repr, buffer);
+
+    #[cfg(feature = "proto-ipv4")]
+    #[allow(irrefutable_let_patterns)] // if only ipv4 is enabled
+    if let IpRepr::Ipv4(_) = ip_repr {
+        if checksum_caps.ipv4.tx() {
+            Ipv4Packet::new_unchecked(buffer).fill_checksum();
+        }
+    }
+    #[cfg(not(feature = "proto-ipv4"))]
+    let _ = checksum_caps;
+}
@@ -0,0 +1,263 @@
+// Packets waiting for the hardware address of their next hop, as RFC 1122 § 2.3.2.2
+// recommends. Instead of dropping the packet that triggers address resolution, and
+// the ones that follow it, `dispatch_ip` holds them in a queue assigned to the
+// neighbor. They're sent once the neighbor cache learns its address, or dropped and
+// reported to the UDP or ICMP socket that sent them if the neighbor is unreachable.
+
+use core::mem;
+use managed::ManagedSlice;
+
+use super::{Interface, InterfaceInner, IpPacket};
+use crate::iface::neighbor::State as NeighborState;
+use crate::iface::{NeighborAnswer, SocketSet};
+use crate::phy::Device;
+#[cfg(feature = "socket-icmp")]
+use crate::socket::icmp;
+#[cfg(feature = "socket-udp")]
+use crate::socket::udp;
+#[cfg(any(feature = "socket-udp", feature = "socket-icmp"))]
+use crate::socket::AnySocket;
+use crate::storage::PacketBuffer;
+use crate::wire::*;
+use crate::{Error, Result};
+
+/// A queue of IP packets waiting for the hardware address of a neighbor.
+///
+/// Each neighbor being resolved takes a free queue, which is released once its
+/// packets are sent or dropped.
+#[derive(Debug)]
+pub struct HoldQueue<'a> {
+    neighbor: Option<IpAddress>,
+    packets: PacketBuffer<'a, ()>,
+}
+
+impl<'a> HoldQueue<'a> {
+    /// Create a queue holding packets in `packets`.
+    pub fn new(packets: PacketBuffer<'a, ()>) -> Self {
+        HoldQueue {
+            neighbor: None,
+            packets,
+        }
+    }
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Hold `packet` until the hardware address of its next hop is known, if that
+    /// address is being resolved and a queue has room for the packet.
+    pub(super) fn hold_packet(&mut self, ip_repr: &IpRepr, packet: &IpPacket) -> bool {
+        if self.hold_queues.is_empty() || ip_repr.buffer_len() > self.caps.ip_mtu() {
+            return false;
+        }
+        let neighbor = match self.route(&ip_repr.dst_addr(), self.now) {
+            Ok(neighbor) => neighbor,
+            Err(_) => return false,
+        };
+        match self.neighbor_cache.as_ref() {
+            Some(cache) if cache.state(&neighbor, self.now) == Some(NeighborState::Incomplete) => {}
+            _ => return false,
+        }
+
+        let index = match self
+            .hold_queues
+            .iter()
+            .position(|queue| queue.neighbor == Some(neighbor))
+            .or_else(|| {
+                self.hold_queues
+                    .iter()
+                    .position(|queue| queue.neighbor.is_none())
+            }) {
+            Some(index) => index,
+            None => {
+                net_debug!("no hold queue left for {}", neighbor);
+                return false;
+            }
+        };
+
+        let caps = self.caps.clone();
+        let queue = &mut self.hold_queues[index];
+        let mut buffer = match queue.packets.enqueue(ip_repr.buffer_len(), ()) {
+            Ok(buffer) => buffer,
+            Err(_) => {
+                net_debug!("hold queue of {} is full", neighbor);
+                return false;
+            }
+        };
+
+        match packet {
+            #[cfg(feature = "iface-forwarding")]
+            IpPacket::Forward((_, raw_packet)) => {
+                super::forwarding::emit(ip_repr, raw_packet, buffer, &caps.checksum)
+            }
+            _ => {
+                ip_repr.emit(&mut buffer, &caps.checksum);
+                let payload = &mut buffer[ip_repr.header_len()..];
+                packet.emit_payload(ip_repr, payload, &caps);
+            }
+        }
+
+        net_trace!("holding a packet for {}", neighbor);
+        queue.neighbor = Some(neighbor);
+        true
+    }
+
+    fn hold_dispatch<D>(
+        &mut self,
+        device: &mut D,
+        sockets: &mut SocketSet<'_>,
+        queues: &mut [HoldQueue],
+    ) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        let now = self.now;
+        for queue in queues.iter_mut() {
+            let neighbor = match queue.neighbor {
+                Some(neighbor) => neighbor,
+                None => continue,
+            };
+            let cache = match self.neighbor_cache.as_mut() {
+                Some(cache) => cache,
+                None => return Ok(false),
+            };
+
+            match cache.lookup(&neighbor, now) {
+                NeighborAnswer::Found(HardwareAddress::Ethernet(hardware_addr)) => {
+                    cache.used(&neighbor, now);
+
+                    let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+                    let packet = match queue.packets.dequeue() {
+                        Ok((_, packet)) => packet,
+                        Err(_) => {
+                            queue.neighbor = None;
+                            continue;
+                        }
+                    };
+                    let ethertype = match IpVersion::of_packet(packet) {
+                        #[cfg(feature = "proto-ipv4")]
+                        Ok(IpVersion::Ipv4) => EthernetProtocol::Ipv4,
+                        #[cfg(feature = "proto-ipv6")]
+                        Ok(IpVersion::Ipv6) => EthernetProtocol::Ipv6,
+                        Err(_) => return Err(Error::Malformed),
+                    };
+
+                    net_trace!("sending a packet held for {}", neighbor);
+                    self.dispatch_ethernet(tx_token, packet.len(), |mut frame| {
+                        frame.set_dst_addr(hardware_addr);
+                        frame.set_ethertype(ethertype);
+                        frame.payload_mut().copy_from_slice(packet);
+                    })?;
+
+                    if queue.packets.is_empty() {
+                        queue.neighbor = None;
+                    }
+                    return Ok(true);
+                }
+                _ if cache.state(&neighbor, now) == Some(NeighborState::Incomplete) => {}
+                _ => {
+                    net_debug!("{} is unreachable, dropping its held packets", neighbor);
+                    while let Ok((_, packet)) = queue.packets.dequeue() {
+                        #[cfg(any(feature = "socket-udp", feature = "socket-icmp"))]
+                        report_unreachable(sockets, packet);
+                        #[cfg(not(any(feature = "socket-udp", feature = "socket-icmp")))]
+                        let _ = (&sockets, packet);
+                    }
+                    queue.neighbor = None;
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Tell the socket that sent a held packet that it couldn't be delivered.
+#[cfg(any(feature = "socket-udp", feature = "socket-icmp"))]
+fn report_unreachable(sockets: &mut SocketSet<'_>, packet: &[u8]) {
+    let (src_addr, dst_addr, protocol, payload) = match IpVersion::of_packet(packet) {
+        #[cfg(feature = "proto-ipv4")]
+        Ok(IpVersion::Ipv4) => {
+            let packet = Ipv4Packet::new_unchecked(packet);
+            (
+                IpAddress::Ipv4(packet.src_addr()),
+                IpAddress::Ipv4(packet.dst_addr()),
+                packet.next_header(),
+                packet.payload(),
+            )
+        }
+        #[cfg(feature = "proto-ipv6")]
+        Ok(IpVersion::Ipv6) => {
+            let packet = Ipv6Packet::new_unchecked(packet);
+            (
+                IpAddress::Ipv6(packet.src_addr()),
+                IpAddress::Ipv6(packet.dst_addr()),
+                packet.next_header(),
+                packet.payload(),
+            )
+        }
+        Err(_) => return,
+    };
+    #[cfg(not(feature = "socket-udp"))]
+    let _ = src_addr;
+
+    match protocol {
+        #[cfg(feature = "socket-udp")]
+        IpProtocol::Udp => {
+            let packet = match UdpPacket::new_checked(payload) {
+                Ok(packet) => packet,
+                Err(_) => return,
+            };
+            let local_endpoint = IpEndpoint::new(src_addr, packet.src_port());
+            let error = udp::RecvError::Unreachable(IpEndpoint::new(dst_addr, packet.dst_port()));
+            sockets
+                .items_mut()
+                .filter_map(|item| udp::Socket::downcast_mut(&mut item.socket))
+                .any(|socket| socket.process_error(local_endpoint, error));
+        }
+        #[cfg(all(feature = "socket-icmp", feature = "proto-ipv4"))]
+        IpProtocol::Icmp => {
+            let ident = match Icmpv4Packet::new_checked(payload) {
+                Ok(packet) if packet.msg_type() == Icmpv4Message::EchoRequest => {
+                    packet.echo_ident()
+                }
+                _ => return,
+            };
+            sockets
+                .items_mut()
+                .filter_map(|item| icmp::Socket::downcast_mut(&mut item.socket))
+                .any(|socket| socket.process_unreachable(ident, dst_addr));
+        }
+        #[cfg(all(feature = "socket-icmp", feature = "proto-ipv6"))]
+        IpProtocol::Icmpv6 => {
+            let ident = match Icmpv6Packet::new_checked(payload) {
+                Ok(packet) if packet.msg_type() == Icmpv6Message::EchoRequest => {
+                    packet.echo_ident()
+                }
+                _ => return,
+            };
+            sockets
+                .items_mut()
+                .filter_map(|item| icmp::Socket::downcast_mut(&mut item.socket))
+                .any(|socket| socket.process_unreachable(ident, dst_addr));
+        }
+        _ => (),
+    }
+}
+
+impl<'a> Interface<'a> {
+    /// Send the next packet held for a neighbor whose hardware address was found, or
+    /// drop the packets held for a neighbor that turned out to be unreachable.
+    pub(super) fn hold_egress<D>(
+        &mut self,
+        device: &mut D,
+        sockets: &mut SocketSet<'_>,
+    ) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        // Take the queues out of the interface, which dispatches their packets.
+        let mut queues = mem::replace(&mut self.inner.hold_queues, ManagedSlice::Borrowed(&mut []));
+        let result = self.inner.hold_dispatch(device, sockets, &mut queues);
+        self.inner.hold_queues = queues;
+        result
+    }
+}
@@ -0,0 +1,330 @@
+// IGMPv3 source filters, as described in RFC 3376 § 3. Groups joined with
+// `join_multicast_group` have no source filter, i.e. they're in EXCLUDE mode with an empty
+// source list, and keep being reported in the version of the querier. Changes to a source
+// filter are always reported with IGMPv3, since older versions can't express them.
+
+use heapless::Vec;
+
+use super::{Interface, InterfaceInner, IpPacket};
+use crate::phy::Device;
+use crate::time::Instant;
+use crate::wire::*;
+use crate::{Error, Result};
+
+/// Maximum number of multicast groups joined with a source filter.
+pub const IGMP_MAX_SOURCE_FILTER_COUNT: usize = 4;
+/// Maximum number of sources in the filter of a multicast group.
+pub const IGMP_MAX_SOURCE_COUNT: usize = 8;
+
+/// The filter mode of a multicast group, see RFC 3376 § 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FilterMode {
+    /// Only datagrams from the listed sources are received.
+    Include,
+    /// Datagrams from all sources but the listed ones are received.
+    Exclude,
+}
+
+type Sources = Vec<Ipv4Address, IGMP_MAX_SOURCE_COUNT>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct SourceFilter {
+    mode: FilterMode,
+    sources: Sources,
+}
+
+impl SourceFilter {
+    /// The filter of a group that isn't joined.
+    const fn none() -> Self {
+        SourceFilter {
+            mode: FilterMode::Include,
+            sources: Vec::new(),
+        }
+    }
+
+    /// The filter of a group joined without sources.
+    const fn any() -> Self {
+        SourceFilter {
+            mode: FilterMode::Exclude,
+            sources: Vec::new(),
+        }
+    }
+
+    fn accepts(&self, src_addr: Ipv4Address) -> bool {
+        (self.mode == FilterMode::Include) == self.sources.contains(&src_addr)
+    }
+
+    /// Return the group record describing this filter, see RFC 3376 § 4.2.12.
+    fn current_state_record_type(&self) -> IgmpGroupRecordType {
+        match self.mode {
+            FilterMode::Include => IgmpGroupRecordType::ModeIsInclude,
+            FilterMode::Exclude => IgmpGroupRecordType::ModeIsExclude,
+        }
+    }
+
+    /// Return the group records reporting a change from `self` to `new`, see the table
+    /// of RFC 3376 § 5.1.
+    fn state_change_records(&self, new: &SourceFilter) -> Vec<(IgmpGroupRecordType, Sources), 2> {
+        let difference = |a: &Sources, b: &Sources| -> Sources {
+            a.iter().filter(|addr| !b.contains(addr)).cloned().collect()
+        };
+
+        let mut records = Vec::new();
+        match (self.mode, new.mode) {
+            (FilterMode::Include, FilterMode::Include) => {
+                let allow = difference(&new.sources, &self.sources);
+                let block = difference(&self.sources, &new.sources);
+                let _ = records.push((IgmpGroupRecordType::AllowNewSources, allow));
+                let _ = records.push((IgmpGroupRecordType::BlockOldSources, block));
+            }
+            (FilterMode::Exclude, FilterMode::Exclude) => {
+                let allow = difference(&self.sources, &new.sources);
+                let block = difference(&new.sources, &self.sources);
+                let _ = records.push((IgmpGroupRecordType::AllowNewSources, allow));
+                let _ = records.push((IgmpGroupRecordType::BlockOldSources, block));
+            }
+            (FilterMode::Include, FilterMode::Exclude) => {
+                let _ = records.push((IgmpGroupRecordType::ChangeToExclude, new.sources.clone()));
+            }
+            (FilterMode::Exclude, FilterMode::Include) => {
+                let _ = records.push((IgmpGroupRecordType::ChangeToInclude, new.sources.clone()));
+            }
+        }
+
+        // Allow and block records without sources don't change anything.
+        let mut i = 0;
+        while i < records.len() {
+            let (record_type, ref sources) = records[i];
+            let is_change_to_mode = record_type == IgmpGroupRecordType::ChangeToInclude
+                || record_type == IgmpGroupRecordType::ChangeToExclude;
+            if is_change_to_mode || !sources.is_empty() {
+                i += 1;
+            } else {
+                records.remove(i);
+            }
+        }
+        records
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) struct SourceFilters {
+    filters: Vec<(Ipv4Address, SourceFilter), IGMP_MAX_SOURCE_FILTER_COUNT>,
+}
+
+impl SourceFilters {
+    pub(super) const fn new() -> Self {
+        SourceFilters {
+            filters: Vec::new(),
+        }
+    }
+
+    fn get(&self, group: Ipv4Address) -> Option<&SourceFilter> {
+        self.filters
+            .iter()
+            .find(|(probe, _)| *probe == group)
+            .map(|(_, filter)| filter)
+    }
+
+    fn set(&mut self, group: Ipv4Address, filter: SourceFilter) -> Result<()> {
+        match self.filters.iter_mut().find(|(probe, _)| *probe == group) {
+            Some((_, old)) => *old = filter,
+            None => self
+                .filters
+                .push((group, filter))
+                .map_err(|_| Error::Exhausted)?,
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, group: Ipv4Address) -> Option<SourceFilter> {
+        let index = self.filters.iter().position(|(probe, _)| *probe == group)?;
+        Some(self.filters.remove(index).1)
+    }
+}
+
+impl<'a> InterfaceInner<'a> {
+    fn igmp_source_filter(&self, group: Ipv4Address) -> SourceFilter {
+        match self.igmp_source_filters.get(group) {
+            Some(filter) => filter.clone(),
+            None if self.ipv4_multicast_groups.get(&group).is_some() => SourceFilter::any(),
+            None => SourceFilter::none(),
+        }
+    }
+
+    /// Return whether the source filter of `group` lets datagrams from `src_addr` through.
+    pub(super) fn igmp_accepts_source(&self, group: Ipv4Address, src_addr: Ipv4Address) -> bool {
+        match self.igmp_source_filters.get(group) {
+            Some(filter) => filter.accepts(src_addr),
+            None => true,
+        }
+    }
+
+    fn igmp_report_v3_packet<'any>(
+        &self,
+        records: &'any [IgmpGroupRecordRepr<'any>],
+    ) -> Option<IpPacket<'any>> {
+        let iface_addr = self.ipv4_address()?;
+        let igmp_repr = IgmpRepr::MembershipReportV3Records(records);
+        Some(IpPacket::Igmp((
+            Ipv4Repr {
+                src_addr: iface_addr,
+                dst_addr: Ipv4Address::MULTICAST_ALL_IGMPV3_ROUTERS,
+                next_header: IpProtocol::Igmp,
+                payload_len: igmp_repr.buffer_len(),
+                hop_limit: 1,
+            },
+            igmp_repr,
+        )))
+    }
+}
+
+impl<'a> Interface<'a> {
+    /// Add an address to the subscribed multicast IP addresses, only receiving datagrams
+    /// from the given `sources` (with [`FilterMode::Include`]), or from any source but
+    /// them (with [`FilterMode::Exclude`]). This requires an IGMPv3 capable network.
+    ///
+    /// The source filter of a group that was already joined is replaced, and joining with
+    /// `FilterMode::Exclude` and no sources removes it. Joining with `FilterMode::Include`
+    /// and no sources leaves the group.
+    ///
+    /// Returns `Ok(announce_sent)` if the source filter was updated successfully, where
+    /// `announce_sent` indicates whether an IGMPv3 report of the change has been sent.
+    ///
+    /// [`FilterMode::Include`]: enum.IgmpFilterMode.html#variant.Include
+    /// [`FilterMode::Exclude`]: enum.IgmpFilterMode.html#variant.Exclude
+    pub fn join_multicast_group_with_sources<D>(
+        &mut self,
+        device: &mut D,
+        group: Ipv4Address,
+        mode: FilterMode,
+        sources: &[Ipv4Address],
+        timestamp: Instant,
+    ) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        if !group.is_multicast() {
+            return Err(Error::Unaddressable);
+        }
+
+        let mut new = SourceFilter {
+            mode,
+            sources: Vec::new(),
+        };
+        for addr in sources {
+            if !new.sources.contains(addr) {
+                new.sources.push(*addr).map_err(|_| Error::Exhausted)?;
+            }
+        }
+        if new == SourceFilter::none() {
+            return self.leave_multicast_group(device, group, timestamp);
+        }
+
+        self.inner.now = timestamp;
+        let old = self.inner.igmp_source_filter(group);
+        if new == old {
+            return Ok(false);
+        }
+
+        let was_new = self
+            .inner
+            .ipv4_multicast_groups
+            .insert(group, ())
+            .map_err(|_| Error::Exhausted)?
+            .is_none();
+        if new == SourceFilter::any() {
+            self.inner.igmp_source_filters.remove(group);
+        } else if let Err(err) = self.inner.igmp_source_filters.set(group, new.clone()) {
+            if was_new {
+                self.inner.ipv4_multicast_groups.remove(&group);
+            }
+            return Err(err);
+        }
+
+        self.igmp_send_state_change(device, group, &old, &new)
+    }
+
+    /// Leave a multicast group joined with a source filter. Returns `None` if the group
+    /// has no source filter.
+    pub(super) fn igmp_leave_with_sources<D>(
+        &mut self,
+        device: &mut D,
+        group: Ipv4Address,
+    ) -> Option<Result<bool>>
+    where
+        D: Device + ?Sized,
+    {
+        let old = self.inner.igmp_source_filters.remove(group)?;
+        Some(self.igmp_send_state_change(device, group, &old, &SourceFilter::none()))
+    }
+
+    fn igmp_send_state_change<D>(
+        &mut self,
+        device: &mut D,
+        group: Ipv4Address,
+        old: &SourceFilter,
+        new: &SourceFilter,
+    ) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        let changes = old.state_change_records(new);
+        let records: Vec<IgmpGroupRecordRepr, 2> = changes
+            .iter()
+            .map(|(record_type, sources)| IgmpGroupRecordRepr {
+                record_type: *record_type,
+                group_addr: group,
+                sources,
+            })
+            .collect();
+
+        match self.inner.igmp_report_v3_packet(&records) {
+            Some(pkt) if !records.is_empty() => {
+                let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+                self.inner.dispatch_ip(tx_token, pkt, None)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Send a report of the membership of `group` in response to a query of `version`.
+    pub(super) fn igmp_send_report<D>(
+        &mut self,
+        device: &mut D,
+        version: IgmpVersion,
+        group: Ipv4Address,
+    ) -> Result<()>
+    where
+        D: Device + ?Sized,
+    {
+        // Older versions can't express source filters, so the group is reported as joined
+        // from any source.
+        let filter = match version {
+            IgmpVersion::Version3 => self.inner.igmp_source_filters.get(group).cloned(),
+            _ => None,
+        };
+        let record;
+        let pkt = match filter {
+            Some(ref filter) => {
+                record = [IgmpGroupRecordRepr {
+                    record_type: filter.current_state_record_type(),
+                    group_addr: group,
+                    sources: &filter.sources,
+                }];
+                self.inner.igmp_report_v3_packet(&record)
+            }
+            None => self.inner.igmp_report_packet(version, group),
+        };
+
+        if let Some(pkt) = pkt {
+            let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+            self.inner.dispatch_ip(tx_token, pkt, None)?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
 use super::check;
 use super::icmp_reply_payload_len;
+use super::pmtu;
+#[cfg(any(feature = "socket-tcp", feature = "socket-udp"))]
+use super::unreachable::Unreachable;
 use super::InterfaceInner;
 use super::IpPacket;
 use super::PacketAssemblerSet;
@@ -31,12 +34,50 @@ impl<'a> InterfaceInner<'a> {
         _fragments: Option<&'output mut PacketAssemblerSet<'a, Ipv4FragKey>>,
     ) -> Option<IpPacket<'output>> {
         let ipv4_repr = check!(Ipv4Repr::parse(ipv4_packet, &self.caps.checksum));
-        if !self.is_unicast_v4(ipv4_repr.src_addr) {
+        // DHCP clients send from the unspecified address until they're given one, anything
+        // else coming from it is discarded once DHCP servers had a look at it.
+        #[cfg(feature = "socket-dhcpv4")]
+        let from_dhcp_client =
+            ipv4_repr.src_addr.is_unspecified() && ipv4_repr.next_header == IpProtocol::Udp;
+        #[cfg(not(feature = "socket-dhcpv4"))]
+        let from_dhcp_client = false;
+        if !self.is_unicast_v4(ipv4_repr.src_addr) && !from_dhcp_client {
             // Discard packets with non-unicast source addresses.
             net_debug!("non-unicast source address");
             return None;
         }
 
+        // Forward packets addressed to others as they are, fragments included.
+        #[cfg(feature = "iface-forwarding")]
+        if self.is_forwarding()
+            && !from_dhcp_client
+            && self.is_unicast_v4(ipv4_repr.dst_addr)
+            && !self.has_ip_addr(ipv4_repr.dst_addr)
+            && !self.any_ip_accepts(ipv4_repr.dst_addr)
+        {
+            let packet = ipv4_packet.clone().into_inner().as_ref();
+            return self.forward_ip(
+                IpRepr::Ipv4(ipv4_repr),
+                &packet[..ipv4_packet.total_len() as usize],
+            );
+        }
+
+        // Translate packets for mapped ports back to the private endpoints they're for.
+        #[cfg(feature = "iface-nat")]
+        if self.is_forwarding()
+            && self.is_translating()
+            && self.has_ip_addr(ipv4_repr.dst_addr)
+            && !ipv4_packet.more_frags()
+            && ipv4_packet.frag_offset() == 0
+        {
+            let packet = ipv4_packet.clone().into_inner().as_ref();
+            if let Some(response) =
+                self.nat_inbound(ipv4_repr, &packet[..ipv4_packet.total_len() as usize])
+            {
+                return response;
+            }
+        }
+
         #[cfg(feature = "proto-ipv4-fragmentation")]
         let ip_payload = {
             const REASSEMBLY_TIMEOUT: u64 = 90;
@@ -104,6 +145,11 @@ impl<'a> InterfaceInner<'a> {
 
         let ip_repr = IpRepr::Ipv4(ipv4_repr);
 
+        #[cfg(feature = "iface-filter")]
+        if let Some(response) = self.filter_ingress(&ip_repr, ip_payload) {
+            return response;
+        }
+
         #[cfg(feature = "socket-raw")]
         let handled_by_raw_socket = self.raw_socket_filter(sockets, &ip_repr, ip_payload);
         #[cfg(not(feature = "socket-raw"))]
@@ -135,6 +181,32 @@ impl<'a> InterfaceInner<'a> {
                         return None;
                     }
                 }
+                if udp_packet.src_port() == DHCP_CLIENT_PORT
+                    && udp_packet.dst_port() == DHCP_SERVER_PORT
+                {
+                    if let Some(dhcp_server) = sockets
+                        .items_mut()
+                        .find_map(|i| dhcpv4::Server::downcast_mut(&mut i.socket))
+                    {
+                        let (src_addr, dst_addr) = (ip_repr.src_addr(), ip_repr.dst_addr());
+                        let udp_repr = check!(UdpRepr::parse(
+                            &udp_packet,
+                            &src_addr,
+                            &dst_addr,
+                            &self.caps.checksum
+                        ));
+                        let udp_payload = udp_packet.payload();
+
+                        return dhcp_server
+                            .process(self, &ipv4_repr, &udp_repr, udp_payload)
+                            .map(IpPacket::Dhcpv4);
+                    }
+                }
+            }
+
+            if from_dhcp_client {
+                net_debug!("non-unicast source address");
+                return None;
             }
         }
 
@@ -144,17 +216,24 @@ impl<'a> InterfaceInner<'a> {
         {
             // Ignore IP packets not directed at us, or broadcast, or any of the multicast groups.
             // If AnyIP is enabled, also check if the packet is routed locally.
-            if !self.any_ip
-                || !ipv4_repr.dst_addr.is_unicast()
-                || self
-                    .routes
-                    .lookup(&IpAddress::Ipv4(ipv4_repr.dst_addr), self.now)
-                    .map_or(true, |router_addr| !self.has_ip_addr(router_addr))
-            {
+            if !self.any_ip_accepts(ipv4_repr.dst_addr) {
                 return None;
             }
         }
 
+        // Drop datagrams from sources excluded by the IGMPv3 source filter of their group.
+        #[cfg(feature = "proto-igmp")]
+        if ipv4_repr.next_header != IpProtocol::Igmp
+            && !self.igmp_accepts_source(ipv4_repr.dst_addr, ipv4_repr.src_addr)
+        {
+            net_debug!(
+                "source {} filtered for group {}",
+                ipv4_repr.src_addr,
+                ipv4_repr.dst_addr
+            );
+            return None;
+        }
+
         match ipv4_repr.next_header {
             IpProtocol::Icmp => self.process_icmpv4(sockets, ip_repr, ip_payload),
 
@@ -201,12 +280,12 @@ impl<'a> InterfaceInner<'a> {
     }
 
     #[cfg(feature = "medium-ethernet")]
-    pub(super) fn process_arp<'frame, T: AsRef<[u8]>>(
+    pub(super) fn process_arp<'frame>(
         &mut self,
         timestamp: Instant,
-        eth_frame: &EthernetFrame<&'frame T>,
+        arp_payload: &'frame [u8],
     ) -> Option<EthernetPacket<'frame>> {
-        let arp_packet = check!(ArpPacket::new_checked(eth_frame.payload()));
+        let arp_packet = check!(ArpPacket::new_checked(arp_payload));
         let arp_repr = check!(ArpRepr::parse(&arp_packet));
 
         match arp_repr {
@@ -217,6 +296,14 @@ impl<'a> InterfaceInner<'a> {
                 target_protocol_addr,
                 ..
             } => {
+                // Ignore our own announcements and probes.
+                if self.hardware_addr == Some(HardwareAddress::Ethernet(source_hardware_addr)) {
+                    return None;
+                }
+
+                self.zeroconf_process_arp(operation, source_protocol_addr, target_protocol_addr);
+                self.arp_check_conflict(source_hardware_addr, source_protocol_addr);
+
                 // Only process ARP packets for us.
                 if !self.has_ip_addr(target_protocol_addr) {
                     return None;
@@ -228,6 +315,27 @@ impl<'a> InterfaceInner<'a> {
                     return None;
                 }
 
+                // RFC 5227 § 2.1.1: answer probes for our addresses, which are sent from the
+                // unspecified address, so that the prober sees the conflict. They don't fill
+                // the ARP cache.
+                if operation == ArpOperation::Request
+                    && source_protocol_addr.is_unspecified()
+                    && source_hardware_addr.is_unicast()
+                {
+                    let src_hardware_addr = match self.hardware_addr {
+                        Some(HardwareAddress::Ethernet(addr)) => addr,
+                        _ => unreachable!(),
+                    };
+                    net_debug!("arp: answering probe for {}", target_protocol_addr);
+                    return Some(EthernetPacket::Arp(ArpRepr::EthernetIpv4 {
+                        operation: ArpOperation::Reply,
+                        source_hardware_addr: src_hardware_addr,
+                        source_protocol_addr: target_protocol_addr,
+                        target_hardware_addr: source_hardware_addr,
+                        target_protocol_addr: source_protocol_addr,
+                    }));
+                }
+
                 // Discard packets with non-unicast source addresses.
                 if !source_protocol_addr.is_unicast() || !source_hardware_addr.is_unicast() {
                     net_debug!("arp: non-unicast source address");
@@ -242,12 +350,22 @@ impl<'a> InterfaceInner<'a> {
                 // Fill the ARP cache from any ARP packet aimed at us (both request or response).
                 // We fill from requests too because if someone is requesting our address they
                 // are probably going to talk to us, so we avoid having to request their address
-                // when we later reply to them.
-                self.neighbor_cache.as_mut().unwrap().fill(
-                    source_protocol_addr.into(),
-                    source_hardware_addr.into(),
-                    timestamp,
-                );
+                // when we later reply to them. Only a response confirms that the neighbor is
+                // reachable, though.
+                let cache = self.neighbor_cache.as_mut().unwrap();
+                if operation == ArpOperation::Reply {
+                    cache.confirm(
+                        source_protocol_addr.into(),
+                        Some(source_hardware_addr.into()),
+                        timestamp,
+                    );
+                } else {
+                    cache.fill(
+                        source_protocol_addr.into(),
+                        source_hardware_addr.into(),
+                        timestamp,
+                    );
+                }
 
                 if operation == ArpOperation::Request {
                     let src_hardware_addr = match self.hardware_addr {
@@ -269,7 +387,33 @@ impl<'a> InterfaceInner<'a> {
         }
     }
 
-    /// Host duties of the **IGMPv2** protocol.
+    /// Broadcast an ARP request for `target_protocol_addr`. Probes are sent from the
+    /// unspecified address, and announcements from the target address itself, see
+    /// RFC 5227 § 2.
+    #[cfg(feature = "medium-ethernet")]
+    pub(super) fn broadcast_arp_request<Tx: TxToken>(
+        &mut self,
+        tx_token: Tx,
+        source_hardware_addr: EthernetAddress,
+        source_protocol_addr: Ipv4Address,
+        target_protocol_addr: Ipv4Address,
+    ) -> Result<()> {
+        let arp_repr = ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Request,
+            source_hardware_addr,
+            source_protocol_addr,
+            target_hardware_addr: EthernetAddress::default(),
+            target_protocol_addr,
+        };
+        self.dispatch_ethernet(tx_token, arp_repr.buffer_len(), |mut frame| {
+            frame.set_dst_addr(EthernetAddress::BROADCAST);
+            frame.set_ethertype(EthernetProtocol::Arp);
+
+            arp_repr.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()))
+        })
+    }
+
+    /// Host duties of the **IGMPv2** and **IGMPv3** protocols.
     ///
     /// Sets up `igmp_report_state` for responding to IGMP general/specific membership queries.
     /// Membership must not be reported immediately in order to avoid flooding the network
@@ -298,7 +442,7 @@ impl<'a> InterfaceInner<'a> {
                     if self.ipv4_multicast_groups.iter().next().is_some() {
                         let interval = match version {
                             IgmpVersion::Version1 => Duration::from_millis(100),
-                            IgmpVersion::Version2 => {
+                            IgmpVersion::Version2 | IgmpVersion::Version3 => {
                                 // No dependence on a random generator
                                 // (see [#24](https://github.com/m-labs/smoltcp/issues/24))
                                 // but at least spread reports evenly across max_resp_time.
@@ -327,7 +471,9 @@ impl<'a> InterfaceInner<'a> {
                 }
             }
             // Ignore membership reports
-            IgmpRepr::MembershipReport { .. } => (),
+            IgmpRepr::MembershipReport { .. }
+            | IgmpRepr::MembershipReportV3 { .. }
+            | IgmpRepr::MembershipReportV3Records(_) => (),
             // Ignore hosts leaving groups
             IgmpRepr::LeaveGroup { .. } => (),
         }
@@ -358,6 +504,23 @@ impl<'a> InterfaceInner<'a> {
             }
         }
 
+        // A packet we sent with the Don't Fragment flag didn't fit the path.
+        if let Icmpv4Repr::DstUnreachable {
+            reason: Icmpv4DstUnreachable::FragRequired,
+            header,
+            ..
+        } = icmp_repr
+        {
+            let mtu = match icmp_packet.next_hop_mtu() {
+                0 => {
+                    let ip_packet = Ipv4Packet::new_unchecked(icmp_packet.data());
+                    pmtu::plateau_mtu(ip_packet.total_len() as usize)
+                }
+                mtu => mtu as usize,
+            };
+            self.pmtu_update(header.src_addr.into(), header.dst_addr.into(), mtu);
+        }
+
         match icmp_repr {
             // Respond to echo requests.
             #[cfg(feature = "proto-ipv4")]
@@ -381,6 +544,23 @@ impl<'a> InterfaceInner<'a> {
             // Ignore any echo replies.
             Icmpv4Repr::EchoReply { .. } => None,
 
+            // Report errors to the socket that sent the packet. The path MTU was
+            // lowered above.
+            #[cfg(any(feature = "socket-tcp", feature = "socket-udp"))]
+            Icmpv4Repr::DstUnreachable {
+                reason,
+                header,
+                data,
+            } if reason != Icmpv4DstUnreachable::FragRequired => {
+                let error = match reason {
+                    Icmpv4DstUnreachable::ProtoUnreachable
+                    | Icmpv4DstUnreachable::PortUnreachable => Unreachable::Refused,
+                    _ => Unreachable::Unreachable,
+                };
+                self.process_unreachable(_sockets, IpRepr::Ipv4(header), data, error);
+                None
+            }
+
             // Don't report an error if a packet with unknown type
             // has been handled by an ICMP socket
             #[cfg(feature = "socket-icmp")]
@@ -391,6 +571,16 @@ impl<'a> InterfaceInner<'a> {
         }
     }
 
+    /// Return whether AnyIP accepts packets to `addr`, i.e. it's routed to one of our addresses.
+    fn any_ip_accepts(&self, addr: Ipv4Address) -> bool {
+        self.any_ip
+            && addr.is_unicast()
+            && self
+                .routes
+                .lookup(&IpAddress::Ipv4(addr), self.now)
+                .map_or(false, |router_addr| self.has_ip_addr(router_addr))
+    }
+
     pub(super) fn icmpv4_reply<'frame, 'icmp: 'frame>(
         &self,
         ipv4_repr: Ipv4Repr,
@@ -451,9 +641,10 @@ impl<'a> InterfaceInner<'a> {
 
         let caps = self.caps.clone();
 
-        let mtu_max = self.ip_mtu();
-        let ip_len = (*packet_len - *sent_bytes + repr.buffer_len()).min(mtu_max);
-        let payload_len = ip_len - repr.buffer_len();
+        // The payload of every fragment but the last is a multiple of 8 octets.
+        let mtu_max = self.path_mtu(&IpAddress::Ipv4(repr.dst_addr));
+        let payload_len = (*packet_len - *sent_bytes).min((mtu_max - repr.buffer_len()) & !7);
+        let ip_len = repr.buffer_len() + payload_len;
 
         let more_frags = (*packet_len - *sent_bytes) != payload_len;
         repr.payload_len = payload_len;
@@ -462,7 +653,7 @@ impl<'a> InterfaceInner<'a> {
         let mut tx_len = ip_len;
         #[cfg(feature = "medium-ethernet")]
         if matches!(caps.medium, Medium::Ethernet) {
-            tx_len += EthernetFrame::<&[u8]>::header_len();
+            tx_len += EthernetFrame::<&[u8]>::header_len() + self.vlan_tag_len();
         }
 
         // Emit function for the Ethernet header.
@@ -491,8 +682,10 @@ impl<'a> InterfaceInner<'a> {
         tx_token.consume(self.now, tx_len, |mut tx_buffer| {
             #[cfg(feature = "medium-ethernet")]
             if matches!(self.caps.medium, Medium::Ethernet) {
-                emit_ethernet(&IpRepr::Ipv4(*repr), tx_buffer)?;
-                tx_buffer = &mut tx_buffer[EthernetFrame::<&[u8]>::header_len()..];
+                let tag_len = self.vlan_tag_len();
+                emit_ethernet(&IpRepr::Ipv4(*repr), &mut tx_buffer[tag_len..])?;
+                self.insert_vlan_tag(tx_buffer);
+                tx_buffer = &mut tx_buffer[tag_len + EthernetFrame::<&[u8]>::header_len()..];
             }
 
             let mut packet = Ipv4Packet::new_unchecked(&mut tx_buffer[..repr.buffer_len()]);
@@ -528,11 +721,15 @@ impl<'a> InterfaceInner<'a> {
             group_addr,
             version,
         };
+        // Send to the group being reported, or to all IGMPv3 routers
+        let dst_addr = match version {
+            IgmpVersion::Version3 => Ipv4Address::MULTICAST_ALL_IGMPV3_ROUTERS,
+            _ => group_addr,
+        };
         let pkt = IpPacket::Igmp((
             Ipv4Repr {
                 src_addr: iface_addr,
-                // Send to the group being reported
-                dst_addr: group_addr,
+                dst_addr,
                 next_header: IpProtocol::Igmp,
                 payload_len: igmp_repr.buffer_len(),
                 hop_limit: 1,
@@ -1,15 +1,53 @@
 use super::check;
 use super::icmp_reply_payload_len;
+#[cfg(any(feature = "socket-tcp", feature = "socket-udp"))]
+use super::unreachable::Unreachable;
 use super::InterfaceInner;
 use super::IpPacket;
 use super::SocketSet;
 
+#[cfg(feature = "proto-mld")]
+use super::MldReportState;
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+use super::NeighborAnswer;
+
+#[cfg(feature = "socket-dhcpv6")]
+use crate::socket::dhcpv6;
 #[cfg(feature = "socket-icmp")]
 use crate::socket::icmp;
 use crate::socket::AnySocket;
 
+#[cfg(all(feature = "proto-mld", feature = "medium-ieee802154"))]
+use crate::phy::Medium;
+#[cfg(feature = "proto-mld")]
+use crate::time::Duration;
 use crate::wire::*;
 
+/// How long to keep sending MLDv1 messages after hearing an MLDv1 query, i.e. the
+/// Older Version Querier Present Timeout of RFC 3810 § 9.12 with default values.
+#[cfg(feature = "proto-mld")]
+const MLD_OLDER_VERSION_QUERIER_PRESENT_TIMEOUT: Duration = Duration::from_secs(260);
+
+/// Return whether listening to `group` must be reported, see RFC 3810 § 6.
+#[cfg(feature = "proto-mld")]
+pub(super) fn is_mld_reportable(group: Ipv6Address) -> bool {
+    // Neither the all-nodes address nor groups of interface-local or reserved scope
+    // are reported.
+    group != Ipv6Address::LINK_LOCAL_ALL_NODES && group.0[1] & 0x0f > 1
+}
+
+/// Return the solicited-node multicast group that the address of `cidr` belongs to, if any.
+#[cfg(feature = "proto-mld")]
+pub(super) fn solicited_node_group(cidr: &IpCidr) -> Option<Ipv6Address> {
+    match cidr {
+        IpCidr::Ipv6(cidr) if cidr.address().is_unicast() && !cidr.address().is_loopback() => {
+            Some(cidr.address().solicited_node())
+        }
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
 impl<'a> InterfaceInner<'a> {
     #[cfg(feature = "proto-ipv6")]
     pub(super) fn process_ipv6<'frame, T: AsRef<[u8]> + ?Sized>(
@@ -19,14 +57,36 @@ impl<'a> InterfaceInner<'a> {
     ) -> Option<IpPacket<'frame>> {
         let ipv6_repr = check!(Ipv6Repr::parse(ipv6_packet));
 
-        if !ipv6_repr.src_addr.is_unicast() {
+        // The Neighbor Solicitations of Duplicate Address Detection are sent from the
+        // unspecified address, to the solicited-node group of the address probed for,
+        // see RFC 4862 § 5.4.
+        let is_dad_probe = ipv6_repr.src_addr.is_unspecified()
+            && ipv6_repr.dst_addr.is_solicited_node_multicast()
+            && ipv6_repr.next_header == IpProtocol::Icmpv6
+            && ipv6_packet.payload().first() == Some(&Icmpv6Message::NeighborSolicit.into());
+
+        if !ipv6_repr.src_addr.is_unicast() && !is_dad_probe {
             // Discard packets with non-unicast source addresses.
             net_debug!("non-unicast source address");
             return None;
         }
 
+        #[cfg(feature = "iface-forwarding")]
+        if self.is_forwarding()
+            && ipv6_repr.dst_addr.is_unicast()
+            && !self.has_ip_addr(ipv6_repr.dst_addr)
+        {
+            let packet = ipv6_packet.clone().into_inner().as_ref();
+            return self.forward_ip(IpRepr::Ipv6(ipv6_repr), &packet[..ipv6_packet.total_len()]);
+        }
+
         let ip_payload = ipv6_packet.payload();
 
+        #[cfg(feature = "iface-filter")]
+        if let Some(response) = self.filter_ingress(&IpRepr::Ipv6(ipv6_repr), ip_payload) {
+            return response;
+        }
+
         #[cfg(feature = "socket-raw")]
         let handled_by_raw_socket = self.raw_socket_filter(sockets, &ipv6_repr.into(), ip_payload);
         #[cfg(not(feature = "socket-raw"))]
@@ -55,7 +115,11 @@ impl<'a> InterfaceInner<'a> {
         match nxt_hdr {
             IpProtocol::Icmpv6 => self.process_icmpv6(sockets, ipv6_repr.into(), ip_payload),
 
-            #[cfg(any(feature = "socket-udp", feature = "socket-dns"))]
+            #[cfg(any(
+                feature = "socket-udp",
+                feature = "socket-dns",
+                feature = "socket-dhcpv6"
+            ))]
             IpProtocol::Udp => {
                 let udp_packet = check!(UdpPacket::new_checked(ip_payload));
                 let udp_repr = check!(UdpRepr::parse(
@@ -65,14 +129,32 @@ impl<'a> InterfaceInner<'a> {
                     &self.checksum_caps(),
                 ));
 
-                self.process_udp(
+                #[cfg(feature = "socket-dhcpv6")]
+                if udp_repr.src_port == DHCPV6_SERVER_PORT
+                    && udp_repr.dst_port == DHCPV6_CLIENT_PORT
+                    && self.hardware_addr.is_some()
+                {
+                    if let Some(dhcp_socket) = sockets
+                        .items_mut()
+                        .find_map(|i| dhcpv6::Socket::downcast_mut(&mut i.socket))
+                    {
+                        dhcp_socket.process(self, &ipv6_repr, &udp_repr, udp_packet.payload());
+                        return None;
+                    }
+                }
+
+                #[cfg(any(feature = "socket-udp", feature = "socket-dns"))]
+                return self.process_udp(
                     sockets,
                     ipv6_repr.into(),
                     udp_repr,
                     handled_by_raw_socket,
                     udp_packet.payload(),
                     ip_payload,
-                )
+                );
+
+                #[cfg(not(any(feature = "socket-udp", feature = "socket-dns")))]
+                None
             }
 
             #[cfg(feature = "socket-tcp")]
@@ -130,6 +212,11 @@ impl<'a> InterfaceInner<'a> {
             }
         }
 
+        // A packet we sent didn't fit the path, and IPv6 routers don't fragment.
+        if let Icmpv6Repr::PktTooBig { mtu, header, .. } = icmp_repr {
+            self.pmtu_update(header.src_addr.into(), header.dst_addr.into(), mtu as usize);
+        }
+
         match icmp_repr {
             // Respond to echo requests.
             Icmpv6Repr::EchoRequest {
@@ -152,6 +239,21 @@ impl<'a> InterfaceInner<'a> {
             // Ignore any echo replies.
             Icmpv6Repr::EchoReply { .. } => None,
 
+            // Report errors to the socket that sent the packet.
+            #[cfg(any(feature = "socket-tcp", feature = "socket-udp"))]
+            Icmpv6Repr::DstUnreachable {
+                reason,
+                header,
+                data,
+            } => {
+                let error = match reason {
+                    Icmpv6DstUnreachable::PortUnreachable => Unreachable::Refused,
+                    _ => Unreachable::Unreachable,
+                };
+                self.process_unreachable(_sockets, IpRepr::Ipv6(header), data, error);
+                None
+            }
+
             // Forward any NDISC packets to the ndisc packet handler
             #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
             Icmpv6Repr::Ndisc(repr) if ip_repr.hop_limit() == 0xff => match ip_repr {
@@ -160,6 +262,13 @@ impl<'a> InterfaceInner<'a> {
                 _ => unreachable!(),
             },
 
+            #[cfg(feature = "proto-mld")]
+            Icmpv6Repr::Mld(repr) => match ip_repr {
+                IpRepr::Ipv6(ipv6_repr) => self.process_mld(ipv6_repr, repr),
+                #[allow(unreachable_patterns)]
+                _ => unreachable!(),
+            },
+
             // Don't report an error if a packet with unknown type
             // has been handled by an ICMP socket
             #[cfg(feature = "socket-icmp")]
@@ -185,25 +294,34 @@ impl<'a> InterfaceInner<'a> {
                 target_addr,
                 flags,
             } => {
+                self.dad_process_advert(target_addr);
+
                 let ip_addr = ip_repr.src_addr.into();
-                if let Some(lladdr) = lladdr {
-                    let lladdr = check!(lladdr.parse(self.caps.medium));
-                    if !lladdr.is_unicast() || !target_addr.is_unicast() {
-                        return None;
-                    }
-                    if flags.contains(NdiscNeighborFlags::OVERRIDE)
-                        || !self
-                            .neighbor_cache
-                            .as_mut()
-                            .unwrap()
-                            .lookup(&ip_addr, self.now)
-                            .found()
-                    {
-                        self.neighbor_cache
-                            .as_mut()
-                            .unwrap()
-                            .fill(ip_addr, lladdr, self.now)
+                let solicited = flags.contains(NdiscNeighborFlags::SOLICITED);
+                let cache = self.neighbor_cache.as_mut().unwrap();
+                let cached_lladdr = match cache.lookup(&ip_addr, self.now) {
+                    NeighborAnswer::Found(lladdr) => Some(lladdr),
+                    _ => None,
+                };
+                match lladdr {
+                    Some(lladdr) => {
+                        let lladdr = check!(lladdr.parse(self.caps.medium));
+                        if !lladdr.is_unicast() || !target_addr.is_unicast() {
+                            return None;
+                        }
+                        // RFC 4861 § 7.2.5: a solicited advertisement confirms that the
+                        // neighbor is reachable, unless it doesn't override a different
+                        // cached link-layer address.
+                        let overrides =
+                            flags.contains(NdiscNeighborFlags::OVERRIDE) || cached_lladdr.is_none();
+                        if solicited && (overrides || cached_lladdr == Some(lladdr)) {
+                            cache.confirm(ip_addr, Some(lladdr), self.now)
+                        } else if overrides {
+                            cache.fill(ip_addr, lladdr, self.now)
+                        }
                     }
+                    None if solicited => cache.confirm(ip_addr, None, self.now),
+                    None => (),
                 }
                 None
             }
@@ -212,6 +330,10 @@ impl<'a> InterfaceInner<'a> {
                 lladdr,
                 ..
             } => {
+                if self.dad_process_solicit(ip_repr.src_addr, target_addr) {
+                    return None;
+                }
+
                 if let Some(lladdr) = lladdr {
                     let lladdr = check!(lladdr.parse(self.caps.medium));
                     if !lladdr.is_unicast() || !target_addr.is_unicast() {
@@ -225,15 +347,25 @@ impl<'a> InterfaceInner<'a> {
                 }
 
                 if self.has_solicited_node(ip_repr.dst_addr) && self.has_ip_addr(target_addr) {
+                    // RFC 4861 § 7.2.4: a node performing Duplicate Address Detection can't
+                    // be answered directly, so tell every node that the address is taken.
+                    let (flags, dst_addr) = if ip_repr.src_addr.is_unspecified() {
+                        (
+                            NdiscNeighborFlags::OVERRIDE,
+                            Ipv6Address::LINK_LOCAL_ALL_NODES,
+                        )
+                    } else {
+                        (NdiscNeighborFlags::SOLICITED, ip_repr.src_addr)
+                    };
                     let advert = Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert {
-                        flags: NdiscNeighborFlags::SOLICITED,
+                        flags,
                         target_addr,
                         #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
                         lladdr: Some(self.hardware_addr.unwrap().into()),
                     });
                     let ip_repr = Ipv6Repr {
                         src_addr: target_addr,
-                        dst_addr: ip_repr.src_addr,
+                        dst_addr,
                         next_header: IpProtocol::Icmpv6,
                         hop_limit: 0xff,
                         payload_len: advert.buffer_len(),
@@ -243,10 +375,181 @@ impl<'a> InterfaceInner<'a> {
                     None
                 }
             }
+            NdiscRepr::RouterAdvert {
+                router_lifetime,
+                prefix_info,
+                ..
+            } => {
+                self.slaac_process_router_advert(ip_repr, router_lifetime, prefix_info);
+                None
+            }
             _ => None,
         }
     }
 
+    /// Schedule listener reports in response to MLD queries. Reports and done messages of
+    /// other listeners are ignored.
+    #[cfg(feature = "proto-mld")]
+    pub(super) fn process_mld<'frame>(
+        &mut self,
+        ip_repr: Ipv6Repr,
+        repr: MldRepr<'frame>,
+    ) -> Option<IpPacket<'frame>> {
+        let (max_resp, group_addr) = match repr {
+            MldRepr::QueryV1 {
+                max_resp_delay,
+                mcast_addr,
+            } => {
+                self.mld_v1_querier_until =
+                    Some(self.now + MLD_OLDER_VERSION_QUERIER_PRESENT_TIMEOUT);
+                (Duration::from_millis(max_resp_delay as u64), mcast_addr)
+            }
+            MldRepr::Query {
+                max_resp_code,
+                mcast_addr,
+                ..
+            } => (mld_max_resp_delay(max_resp_code), mcast_addr),
+            _ => return None,
+        };
+
+        // RFC 3810 § 5.1.14: queries must come from a link-local address.
+        if !ip_repr.src_addr.is_link_local() {
+            net_debug!("mld: ignoring query from {}", ip_repr.src_addr);
+            return None;
+        }
+
+        if group_addr.is_unspecified() {
+            // General query
+            let groups = self.mld_groups().count();
+            if groups > 0 {
+                // Spread the reports evenly over the maximum response delay.
+                let interval = max_resp / (groups as u32 + 1);
+                self.mld_report_state = MldReportState::ToGeneralQuery {
+                    timeout: self.now + interval,
+                    interval,
+                    next_index: 0,
+                };
+            }
+        } else if self.mld_groups().any(|group| group == group_addr) {
+            // Multicast-address-specific query
+            let timeout = max_resp / 4;
+            self.mld_report_state = MldReportState::ToSpecificQuery {
+                timeout: self.now + timeout,
+                group: group_addr,
+            };
+        }
+
+        None
+    }
+
+    /// Return the multicast groups that are reported in response to general queries:
+    /// the joined groups, and the solicited-node groups of the assigned addresses.
+    #[cfg(feature = "proto-mld")]
+    pub(super) fn mld_groups(&self) -> impl Iterator<Item = Ipv6Address> + '_ {
+        let (ip_addrs, groups) = (&self.ip_addrs, &self.ipv6_multicast_groups);
+        let joined = groups
+            .iter()
+            .map(|(group, ())| *group)
+            .filter(|group| is_mld_reportable(*group));
+        let solicited = ip_addrs.iter().enumerate().filter_map(move |(i, cidr)| {
+            let group = solicited_node_group(cidr)?;
+            let seen = ip_addrs[..i]
+                .iter()
+                .any(|cidr| solicited_node_group(cidr) == Some(group));
+            if seen || groups.get(&group).is_some() {
+                None
+            } else {
+                Some(group)
+            }
+        });
+        joined.chain(solicited)
+    }
+
+    /// Return a listener report or done message for `record`, in the MLD version in use
+    /// on the link.
+    #[cfg(feature = "proto-mld")]
+    pub(super) fn mld_report_packet<'any>(
+        &self,
+        record: &'any MldAddressRecordRepr<'any>,
+    ) -> Option<IpPacket<'any>> {
+        // 6LoWPAN doesn't compress the Hop-by-Hop header MLD messages need.
+        #[cfg(feature = "medium-ieee802154")]
+        if self.caps.medium == Medium::Ieee802154 {
+            return None;
+        }
+
+        // RFC 3810 § 5.2.13: reports are sent from a link-local address, or from the
+        // unspecified address while there's no usable one.
+        let src_addr = self
+            .ip_addrs
+            .iter()
+            .find_map(|cidr| match cidr {
+                IpCidr::Ipv6(cidr)
+                    if cidr.address().is_link_local()
+                        && self.is_usable_mld_src_addr(cidr.address()) =>
+                {
+                    Some(cidr.address())
+                }
+                #[allow(unreachable_patterns)]
+                _ => None,
+            })
+            .unwrap_or(Ipv6Address::UNSPECIFIED);
+
+        let (dst_addr, repr) = if self.is_mld_v1_compat() {
+            // MLDv1 has no way to send messages from the unspecified address.
+            if src_addr.is_unspecified() {
+                return None;
+            }
+            match record.record_type {
+                MldRecordType::ChangeToInclude => (
+                    Ipv6Address::LINK_LOCAL_ALL_ROUTERS,
+                    MldRepr::Done {
+                        mcast_addr: record.mcast_addr,
+                    },
+                ),
+                _ => (
+                    record.mcast_addr,
+                    MldRepr::ReportV1 {
+                        mcast_addr: record.mcast_addr,
+                    },
+                ),
+            }
+        } else {
+            (
+                Ipv6Address::LINK_LOCAL_ALL_MLDV2_ROUTERS,
+                MldRepr::ReportRecordReprs(core::slice::from_ref(record)),
+            )
+        };
+
+        Some(IpPacket::Mld((
+            Ipv6Repr {
+                src_addr,
+                dst_addr,
+                next_header: IpProtocol::HopByHop,
+                // The Hop-by-Hop Options header takes 8 octets.
+                payload_len: 8 + repr.buffer_len(),
+                hop_limit: 1,
+            },
+            repr,
+        )))
+    }
+
+    #[cfg(feature = "proto-mld")]
+    fn is_mld_v1_compat(&self) -> bool {
+        match self.mld_v1_querier_until {
+            Some(until) => self.now < until,
+            None => false,
+        }
+    }
+
+    #[cfg(feature = "proto-mld")]
+    fn is_usable_mld_src_addr(&self, _addr: Ipv6Address) -> bool {
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        return self.is_usable_ipv6_addr(_addr);
+        #[cfg(not(any(feature = "medium-ethernet", feature = "medium-ieee802154")))]
+        return true;
+    }
+
     #[cfg(feature = "proto-ipv6")]
     pub(super) fn process_hopbyhop<'frame>(
         &mut self,
@@ -306,3 +609,16 @@ impl<'a> InterfaceInner<'a> {
         }
     }
 }
+
+/// Decode the Maximum Response Code of an MLDv2 query, see RFC 3810 § 5.1.3.
+#[cfg(feature = "proto-mld")]
+fn mld_max_resp_delay(max_resp_code: u16) -> Duration {
+    let millis = if max_resp_code < 0x8000 {
+        max_resp_code as u64
+    } else {
+        let exp = (max_resp_code >> 12) & 0x7;
+        let mant = max_resp_code & 0x0fff;
+        ((mant | 0x1000) as u64) << (exp + 3)
+    };
+    Duration::from_millis(millis)
+}
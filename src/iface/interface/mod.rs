@@ -10,18 +10,77 @@ mod ethernet;
 #[cfg(feature = "proto-sixlowpan")]
 mod sixlowpan;
 
+#[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+mod arp;
+#[cfg(all(
+    feature = "proto-ipv6",
+    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+))]
+mod dad;
+#[cfg(feature = "iface-filter")]
+mod filter;
+#[cfg(feature = "iface-forwarding")]
+mod forwarding;
+#[cfg(feature = "medium-ethernet")]
+mod hold;
+#[cfg(feature = "proto-igmp")]
+mod igmp;
 #[cfg(feature = "proto-ipv4")]
 mod ipv4;
 #[cfg(feature = "proto-ipv6")]
 mod ipv6;
-
-use core::cmp;
+#[cfg(feature = "iface-nat")]
+mod nat;
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+mod nud;
+mod pmtu;
+#[cfg(all(
+    feature = "proto-ipv6",
+    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+))]
+mod slaac;
+#[cfg(any(feature = "socket-tcp", feature = "socket-udp"))]
+mod unreachable;
+#[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+mod zeroconf;
+
+#[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+pub use self::arp::{Conflict as ArpConflict, ARP_MAX_ADDRESS_COUNT, ARP_MAX_CONFLICT_COUNT};
+#[cfg(all(
+    feature = "proto-ipv6",
+    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+))]
+pub use self::dad::{AddressState as Ipv6AddressState, DAD_MAX_ADDRESS_COUNT};
+#[cfg(feature = "iface-forwarding")]
+pub use self::forwarding::{ForwardingBuffer, ForwardingMetadata};
+#[cfg(feature = "medium-ethernet")]
+pub use self::hold::HoldQueue as NeighborHoldQueue;
+#[cfg(feature = "proto-igmp")]
+pub use self::igmp::{
+    FilterMode as IgmpFilterMode, IGMP_MAX_SOURCE_COUNT, IGMP_MAX_SOURCE_FILTER_COUNT,
+};
+pub use self::pmtu::PMTU_MAX_DESTINATION_COUNT;
+#[cfg(all(
+    feature = "proto-ipv6",
+    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+))]
+pub use self::slaac::{
+    Address as SlaacAddress, Config as SlaacConfig, Event as SlaacEvent, SLAAC_MAX_ADDRESS_COUNT,
+};
+#[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+pub use self::zeroconf::Event as ZeroconfEvent;
+
+use core::{cmp, fmt};
 use managed::{ManagedMap, ManagedSlice};
 
 #[cfg(any(feature = "proto-ipv4", feature = "proto-sixlowpan"))]
 use super::fragmentation::PacketAssemblerSet;
 use super::socket_set::SocketSet;
+#[cfg(feature = "iface-nat")]
+use crate::iface::NatTable;
 use crate::iface::Routes;
+#[cfg(feature = "iface-filter")]
+use crate::iface::{Filter, FilterAction};
 #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
 use crate::iface::{NeighborAnswer, NeighborCache};
 use crate::phy::{ChecksumCapabilities, Device, DeviceCapabilities, Medium, RxToken, TxToken};
@@ -227,6 +286,21 @@ macro_rules! check {
 }
 use check;
 
+/// Identifies an interface among the interfaces of a node, e.g. in [routes].
+///
+/// Interfaces are identified by `InterfaceId(0)` unless built with [`InterfaceBuilder::id`].
+///
+/// [routes]: struct.Route.html#structfield.interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InterfaceId(pub u8);
+
+impl fmt::Display for InterfaceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "if{}", self.0)
+    }
+}
+
 /// A  network interface.
 ///
 /// The network interface logically owns a number of other data structures; to avoid
@@ -249,6 +323,7 @@ pub struct InterfaceInner<'a> {
     caps: DeviceCapabilities,
     now: Instant,
     rand: Rand,
+    id: InterfaceId,
 
     #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
     neighbor_cache: Option<NeighborCache<'a>>,
@@ -258,6 +333,13 @@ pub struct InterfaceInner<'a> {
     sequence_no: u8,
     #[cfg(feature = "medium-ieee802154")]
     pan_id: Option<Ieee802154Pan>,
+    #[cfg(feature = "medium-ethernet")]
+    vlan_id: Option<u16>,
+    #[cfg(feature = "medium-ethernet")]
+    vlan_priority: u8,
+    /// Packets waiting for the hardware address of their next hop.
+    #[cfg(feature = "medium-ethernet")]
+    hold_queues: ManagedSlice<'a, NeighborHoldQueue<'a>>,
     #[cfg(feature = "proto-ipv4-fragmentation")]
     ipv4_id: u16,
     #[cfg(feature = "proto-sixlowpan")]
@@ -273,16 +355,65 @@ pub struct InterfaceInner<'a> {
     /// When to report for (all or) the next multicast group membership via IGMP
     #[cfg(feature = "proto-igmp")]
     igmp_report_state: IgmpReportState,
+    /// IGMPv3 source filters of the groups in `ipv4_multicast_groups` that have one
+    #[cfg(feature = "proto-igmp")]
+    igmp_source_filters: igmp::SourceFilters,
+    #[cfg(feature = "proto-mld")]
+    ipv6_multicast_groups: ManagedMap<'a, Ipv6Address, ()>,
+    /// When to report for (all or) the next multicast group membership via MLD
+    #[cfg(feature = "proto-mld")]
+    mld_report_state: MldReportState,
+    /// Until when an MLDv1 querier is considered present on the link, in which case
+    /// MLDv1 messages are sent instead of MLDv2 ones.
+    #[cfg(feature = "proto-mld")]
+    mld_v1_querier_until: Option<Instant>,
+    /// Stateless address autoconfiguration, if enabled.
+    #[cfg(all(
+        feature = "proto-ipv6",
+        any(feature = "medium-ethernet", feature = "medium-ieee802154")
+    ))]
+    slaac: Option<slaac::State>,
+    /// Duplicate Address Detection for the IPv6 addresses.
+    #[cfg(all(
+        feature = "proto-ipv6",
+        any(feature = "medium-ethernet", feature = "medium-ieee802154")
+    ))]
+    dad: dad::State,
+    /// Announcements of, and conflicts on, the IPv4 addresses.
+    #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+    arp: arp::State,
+    /// IPv4 link-local address autoconfiguration, if enabled.
+    #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+    zeroconf: Option<zeroconf::State>,
+    /// The path MTUs learned from ICMP errors.
+    pmtu: pmtu::Cache,
+    /// Packets received to be forwarded by other interfaces, if forwarding is enabled.
+    #[cfg(feature = "iface-forwarding")]
+    forwarding: Option<ForwardingBuffer<'a>>,
+    /// Translations of private endpoints to ports of our address, if this interface
+    /// masquerades the packets it forwards.
+    #[cfg(feature = "iface-nat")]
+    nat: Option<NatTable<'a>>,
+    /// The packet filter, if any.
+    #[cfg(feature = "iface-filter")]
+    filter: Option<Filter<'a>>,
 }
 
 /// A builder structure used for creating a network interface.
 pub struct InterfaceBuilder<'a> {
+    id: InterfaceId,
     #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
     hardware_addr: Option<HardwareAddress>,
     #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
     neighbor_cache: Option<NeighborCache<'a>>,
     #[cfg(feature = "medium-ieee802154")]
     pan_id: Option<Ieee802154Pan>,
+    #[cfg(feature = "medium-ethernet")]
+    vlan_id: Option<u16>,
+    #[cfg(feature = "medium-ethernet")]
+    vlan_priority: u8,
+    #[cfg(feature = "medium-ethernet")]
+    hold_queues: ManagedSlice<'a, NeighborHoldQueue<'a>>,
     ip_addrs: ManagedSlice<'a, IpCidr>,
     #[cfg(feature = "proto-ipv4")]
     any_ip: bool,
@@ -290,7 +421,23 @@ pub struct InterfaceBuilder<'a> {
     /// Does not share storage with `ipv6_multicast_groups` to avoid IPv6 size overhead.
     #[cfg(feature = "proto-igmp")]
     ipv4_multicast_groups: ManagedMap<'a, Ipv4Address, ()>,
+    /// Does not share storage with `ipv4_multicast_groups` to avoid IPv4 size overhead.
+    #[cfg(feature = "proto-mld")]
+    ipv6_multicast_groups: ManagedMap<'a, Ipv6Address, ()>,
+    #[cfg(all(
+        feature = "proto-ipv6",
+        any(feature = "medium-ethernet", feature = "medium-ieee802154")
+    ))]
+    slaac: bool,
+    #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+    zeroconf: bool,
     random_seed: u64,
+    #[cfg(feature = "iface-forwarding")]
+    forwarding: Option<ForwardingBuffer<'a>>,
+    #[cfg(feature = "iface-nat")]
+    nat: Option<NatTable<'a>>,
+    #[cfg(feature = "iface-filter")]
+    filter: Option<Filter<'a>>,
 
     #[cfg(feature = "proto-ipv4-fragmentation")]
     ipv4_fragments: PacketAssemblerSet<'a, Ipv4FragKey>,
@@ -352,6 +499,7 @@ let iface = builder.finalize(&mut device);
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         InterfaceBuilder {
+            id: InterfaceId::default(),
             #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
             hardware_addr: None,
             #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
@@ -359,6 +507,12 @@ let iface = builder.finalize(&mut device);
 
             #[cfg(feature = "medium-ieee802154")]
             pan_id: None,
+            #[cfg(feature = "medium-ethernet")]
+            vlan_id: None,
+            #[cfg(feature = "medium-ethernet")]
+            vlan_priority: 0,
+            #[cfg(feature = "medium-ethernet")]
+            hold_queues: ManagedSlice::Borrowed(&mut []),
 
             ip_addrs: ManagedSlice::Borrowed(&mut []),
             #[cfg(feature = "proto-ipv4")]
@@ -366,7 +520,22 @@ let iface = builder.finalize(&mut device);
             routes: Routes::new(ManagedMap::Borrowed(&mut [])),
             #[cfg(feature = "proto-igmp")]
             ipv4_multicast_groups: ManagedMap::Borrowed(&mut []),
+            #[cfg(feature = "proto-mld")]
+            ipv6_multicast_groups: ManagedMap::Borrowed(&mut []),
+            #[cfg(all(
+                feature = "proto-ipv6",
+                any(feature = "medium-ethernet", feature = "medium-ieee802154")
+            ))]
+            slaac: false,
+            #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+            zeroconf: false,
             random_seed: 0,
+            #[cfg(feature = "iface-forwarding")]
+            forwarding: None,
+            #[cfg(feature = "iface-nat")]
+            nat: None,
+            #[cfg(feature = "iface-filter")]
+            filter: None,
 
             #[cfg(feature = "proto-ipv4-fragmentation")]
             ipv4_fragments: PacketAssemblerSet::new(&mut [][..], &mut [][..]),
@@ -385,6 +554,15 @@ let iface = builder.finalize(&mut device);
         }
     }
 
+    /// Set the identifier of this interface, which the routes of other interfaces
+    /// forwarding to it refer to. See also [`Routes::add_interface_route`].
+    ///
+    /// [`Routes::add_interface_route`]: struct.Routes.html#method.add_interface_route
+    pub fn id(mut self, id: InterfaceId) -> Self {
+        self.id = id;
+        self
+    }
+
     /// Set the random seed for this interface.
     ///
     /// It is strongly recommended that the random seed is different on each boot,
@@ -419,6 +597,57 @@ let iface = builder.finalize(&mut device);
         self
     }
 
+    /// Tag the frames the interface sends with the IEEE 802.1Q VLAN ID `vlan_id`,
+    /// and only accept received frames tagged with it.
+    ///
+    /// Several interfaces on different VLANs can share a device through a
+    /// [`VlanTrunk`]. The usable MTU shrinks by the length of the tag.
+    ///
+    /// # Panics
+    /// This function panics if the VLAN ID is 0 or larger than [`VLAN_MAX_ID`].
+    ///
+    /// [`VlanTrunk`]: ../phy/struct.VlanTrunk.html
+    /// [`VLAN_MAX_ID`]: ../wire/constant.VLAN_MAX_ID.html
+    #[cfg(feature = "medium-ethernet")]
+    pub fn vlan_id(mut self, vlan_id: u16) -> Self {
+        if vlan_id == 0 || vlan_id > VLAN_MAX_ID {
+            panic!("VLAN ID {} is not assignable", vlan_id)
+        }
+        self.vlan_id = Some(vlan_id);
+        self
+    }
+
+    /// Set the IEEE 802.1Q priority code point of the frames the interface sends,
+    /// from 0 (the default) to 7. It only applies when a [vlan_id] is set.
+    ///
+    /// # Panics
+    /// This function panics if the priority is larger than 7.
+    ///
+    /// [vlan_id]: #method.vlan_id
+    #[cfg(feature = "medium-ethernet")]
+    pub fn vlan_priority(mut self, priority: u8) -> Self {
+        if priority > 7 {
+            panic!("VLAN priority {} is out of range", priority)
+        }
+        self.vlan_priority = priority;
+        self
+    }
+
+    /// Set the queues holding packets that wait for the hardware address of their
+    /// next hop, instead of dropping them.
+    ///
+    /// Each neighbor being resolved takes a free queue. Its packets are sent once its
+    /// address is known, or dropped if it doesn't answer, in which case the UDP and
+    /// ICMP sockets that sent them receive an `Unreachable` error.
+    #[cfg(feature = "medium-ethernet")]
+    pub fn neighbor_hold_queues<T>(mut self, queues: T) -> Self
+    where
+        T: Into<ManagedSlice<'a, NeighborHoldQueue<'a>>>,
+    {
+        self.hold_queues = queues.into();
+        self
+    }
+
     /// Set the IP addresses the interface will use. See also
     /// [ip_addrs].
     ///
@@ -486,6 +715,102 @@ let iface = builder.finalize(&mut device);
         self
     }
 
+    /// Provide storage for IPv6 multicast groups.
+    ///
+    /// Join multicast groups by calling [`join_multicast_group()`] on an `Interface`.
+    /// Using [`join_multicast_group()`] will send initial listener reports.
+    ///
+    /// A previously destroyed interface can be recreated by reusing the multicast group
+    /// storage, i.e. providing a non-empty storage to `ipv6_multicast_groups()`.
+    /// Note that this way initial listener reports are **not** sent.
+    ///
+    /// [`join_multicast_group()`]: struct.Interface.html#method.join_multicast_group
+    #[cfg(feature = "proto-mld")]
+    pub fn ipv6_multicast_groups<T>(mut self, ipv6_multicast_groups: T) -> Self
+    where
+        T: Into<ManagedMap<'a, Ipv6Address, ()>>,
+    {
+        self.ipv6_multicast_groups = ipv6_multicast_groups.into();
+        self
+    }
+
+    /// Enable or disable IPv6 stateless address autoconfiguration (SLAAC).
+    ///
+    /// When enabled, the interface forms a link-local address from its hardware address
+    /// (unless it already has one), sends Router Solicitations, and configures an address
+    /// for every prefix advertised for autoconfiguration, as well as the default route
+    /// via the advertising router. Addresses are placed in the unspecified IPv6 slots of
+    /// [ip_addrs], so there must be enough of them, e.g.
+    /// `IpCidr::new(Ipv6Address::UNSPECIFIED.into(), 0)`. Changes are reported by
+    /// [poll_slaac].
+    ///
+    /// [ip_addrs]: #method.ip_addrs
+    /// [poll_slaac]: struct.Interface.html#method.poll_slaac
+    #[cfg(all(
+        feature = "proto-ipv6",
+        any(feature = "medium-ethernet", feature = "medium-ieee802154")
+    ))]
+    pub fn slaac(mut self, enabled: bool) -> Self {
+        self.slaac = enabled;
+        self
+    }
+
+    /// Enable IPv4 link-local address autoconfiguration (zeroconf, RFC 3927).
+    ///
+    /// When the interface has no routable IPv4 address, zeroconf probes for a free
+    /// address in 169.254/16, assigns it and defends it against other hosts. It gives
+    /// way when a routable address is assigned, for example by a DHCP client, and
+    /// comes back once that one is removed. The address is placed in an unspecified
+    /// IPv4 slot of [ip_addrs], so there must be one, e.g.
+    /// `IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 0)`. Changes are reported by
+    /// [poll_zeroconf].
+    ///
+    /// [ip_addrs]: #method.ip_addrs
+    /// [poll_zeroconf]: struct.Interface.html#method.poll_zeroconf
+    #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+    pub fn zeroconf(mut self, enabled: bool) -> Self {
+        self.zeroconf = enabled;
+        self
+    }
+
+    /// Enable forwarding of IP packets not addressed to this interface, queueing
+    /// those routed through other interfaces in `storage`.
+    ///
+    /// Packets are routed with the routing table of this interface. Those routed back
+    /// through it are sent right away, and those routed through other interfaces wait
+    /// in `storage` until [`Interface::forward`] is called on them.
+    ///
+    /// [`Interface::forward`]: struct.Interface.html#method.forward
+    #[cfg(feature = "iface-forwarding")]
+    pub fn forwarding_buffer(mut self, storage: ForwardingBuffer<'a>) -> Self {
+        self.forwarding = Some(storage);
+        self
+    }
+
+    /// Masquerade the IPv4 packets forwarded through this interface behind its own address,
+    /// keeping track of the translations in `nat_table`.
+    ///
+    /// TCP, UDP and ICMP echo packets are translated, other packets are dropped. Replies,
+    /// and packets for the port forwards of `nat_table`, are translated back and forwarded
+    /// to the private endpoints, which requires a [`forwarding_buffer`] as well.
+    ///
+    /// [`forwarding_buffer`]: #method.forwarding_buffer
+    #[cfg(feature = "iface-nat")]
+    pub fn nat_table(mut self, nat_table: NatTable<'a>) -> Self {
+        self.nat = Some(nat_table);
+        self
+    }
+
+    /// Filter the packets received for this interface and the packets it sends,
+    /// forwarded ones included, with `filter`.
+    ///
+    /// Received packets are filtered after reassembly, before any socket sees them.
+    #[cfg(feature = "iface-filter")]
+    pub fn filter(mut self, filter: Filter<'a>) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
     /// Set the Neighbor Cache the interface will use.
     #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
     pub fn neighbor_cache(mut self, neighbor_cache: NeighborCache<'a>) -> Self {
@@ -565,7 +890,13 @@ let iface = builder.finalize(&mut device);
     where
         D: Device + ?Sized,
     {
-        let caps = device.capabilities();
+        #[allow(unused_mut)]
+        let mut caps = device.capabilities();
+
+        #[cfg(feature = "medium-ethernet")]
+        if caps.medium == Medium::Ethernet && self.vlan_id.is_some() {
+            caps.max_transmission_unit -= VLAN_HEADER_LEN;
+        }
 
         #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
         let (hardware_addr, neighbor_cache) = match caps.medium {
@@ -607,6 +938,15 @@ let iface = builder.finalize(&mut device);
 
         let mut rand = Rand::new(self.random_seed);
 
+        #[cfg(all(
+            feature = "proto-ipv6",
+            any(feature = "medium-ethernet", feature = "medium-ieee802154")
+        ))]
+        let dad = dad::State::new(&self.ip_addrs);
+
+        #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+        let arp = arp::State::new(&self.ip_addrs);
+
         #[cfg(feature = "medium-ieee802154")]
         let mut sequence_no;
         #[cfg(feature = "medium-ieee802154")]
@@ -666,6 +1006,7 @@ let iface = builder.finalize(&mut device);
             inner: InterfaceInner {
                 now: Instant::from_secs(0),
                 caps,
+                id: self.id,
                 #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
                 hardware_addr,
                 ip_addrs: self.ip_addrs,
@@ -678,16 +1019,51 @@ let iface = builder.finalize(&mut device);
                 ipv4_multicast_groups: self.ipv4_multicast_groups,
                 #[cfg(feature = "proto-igmp")]
                 igmp_report_state: IgmpReportState::Inactive,
+                #[cfg(feature = "proto-igmp")]
+                igmp_source_filters: igmp::SourceFilters::new(),
+                #[cfg(feature = "proto-mld")]
+                ipv6_multicast_groups: self.ipv6_multicast_groups,
+                #[cfg(feature = "proto-mld")]
+                mld_report_state: MldReportState::Inactive,
+                #[cfg(feature = "proto-mld")]
+                mld_v1_querier_until: None,
+                #[cfg(all(
+                    feature = "proto-ipv6",
+                    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+                ))]
+                slaac: self.slaac.then(slaac::State::new),
+                #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+                zeroconf: self.zeroconf.then(zeroconf::State::new),
+                #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+                arp,
+                pmtu: pmtu::Cache::new(),
+                #[cfg(all(
+                    feature = "proto-ipv6",
+                    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+                ))]
+                dad,
                 #[cfg(feature = "medium-ieee802154")]
                 sequence_no,
                 #[cfg(feature = "medium-ieee802154")]
                 pan_id: self.pan_id,
+                #[cfg(feature = "medium-ethernet")]
+                vlan_id: self.vlan_id,
+                #[cfg(feature = "medium-ethernet")]
+                vlan_priority: self.vlan_priority,
+                #[cfg(feature = "medium-ethernet")]
+                hold_queues: self.hold_queues,
                 #[cfg(feature = "proto-sixlowpan-fragmentation")]
                 tag,
                 #[cfg(feature = "proto-ipv4-fragmentation")]
                 ipv4_id,
                 #[cfg(feature = "proto-sixlowpan")]
                 sixlowpan_address_context: &[],
+                #[cfg(feature = "iface-forwarding")]
+                forwarding: self.forwarding,
+                #[cfg(feature = "iface-nat")]
+                nat: self.nat,
+                #[cfg(feature = "iface-filter")]
+                filter: self.filter,
                 rand,
             },
         }
@@ -697,6 +1073,7 @@ let iface = builder.finalize(&mut device);
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[cfg(feature = "medium-ethernet")]
+#[allow(clippy::large_enum_variant)]
 enum EthernetPacket<'a> {
     #[cfg(feature = "proto-ipv4")]
     Arp(ArpRepr),
@@ -709,9 +1086,11 @@ pub(crate) enum IpPacket<'a> {
     #[cfg(feature = "proto-ipv4")]
     Icmpv4((Ipv4Repr, Icmpv4Repr<'a>)),
     #[cfg(feature = "proto-igmp")]
-    Igmp((Ipv4Repr, IgmpRepr)),
+    Igmp((Ipv4Repr, IgmpRepr<'a>)),
     #[cfg(feature = "proto-ipv6")]
     Icmpv6((Ipv6Repr, Icmpv6Repr<'a>)),
+    #[cfg(feature = "proto-mld")]
+    Mld((Ipv6Repr, MldRepr<'a>)),
     #[cfg(feature = "socket-raw")]
     Raw((IpRepr, &'a [u8])),
     #[cfg(any(feature = "socket-udp", feature = "socket-dns"))]
@@ -720,6 +1099,11 @@ pub(crate) enum IpPacket<'a> {
     Tcp((IpRepr, TcpRepr<'a>)),
     #[cfg(feature = "socket-dhcpv4")]
     Dhcpv4((Ipv4Repr, UdpRepr, DhcpRepr<'a>)),
+    #[cfg(feature = "socket-dhcpv6")]
+    Dhcpv6((Ipv6Repr, UdpRepr, Dhcpv6Repr<'a>)),
+    /// A received IP packet, header included, sent on with the hop limit of the repr.
+    #[cfg(feature = "iface-forwarding")]
+    Forward((IpRepr, &'a [u8])),
 }
 
 impl<'a> IpPacket<'a> {
@@ -731,6 +1115,8 @@ impl<'a> IpPacket<'a> {
             IpPacket::Igmp((ipv4_repr, _)) => IpRepr::Ipv4(*ipv4_repr),
             #[cfg(feature = "proto-ipv6")]
             IpPacket::Icmpv6((ipv6_repr, _)) => IpRepr::Ipv6(*ipv6_repr),
+            #[cfg(feature = "proto-mld")]
+            IpPacket::Mld((ipv6_repr, _)) => IpRepr::Ipv6(*ipv6_repr),
             #[cfg(feature = "socket-raw")]
             IpPacket::Raw((ip_repr, _)) => ip_repr.clone(),
             #[cfg(any(feature = "socket-udp", feature = "socket-dns"))]
@@ -739,6 +1125,10 @@ impl<'a> IpPacket<'a> {
             IpPacket::Tcp((ip_repr, _)) => ip_repr.clone(),
             #[cfg(feature = "socket-dhcpv4")]
             IpPacket::Dhcpv4((ipv4_repr, _, _)) => IpRepr::Ipv4(*ipv4_repr),
+            #[cfg(feature = "socket-dhcpv6")]
+            IpPacket::Dhcpv6((ipv6_repr, _, _)) => IpRepr::Ipv6(*ipv6_repr),
+            #[cfg(feature = "iface-forwarding")]
+            IpPacket::Forward((ip_repr, _)) => ip_repr.clone(),
         }
     }
 
@@ -764,6 +1154,23 @@ impl<'a> IpPacket<'a> {
                 &mut Icmpv6Packet::new_unchecked(payload),
                 &caps.checksum,
             ),
+            #[cfg(feature = "proto-mld")]
+            IpPacket::Mld((_, mld_repr)) => {
+                // MLD messages follow a Hop-by-Hop Options header with a Router Alert option.
+                let hbh_repr = Ipv6HopByHopRepr {
+                    next_header: IpProtocol::Icmpv6,
+                    length: 0,
+                    options: &MLD_HOP_BY_HOP_OPTIONS,
+                };
+                let (hbh_payload, icmp_payload) = payload.split_at_mut(hbh_repr.buffer_len());
+                hbh_repr.emit(&mut Ipv6HopByHopHeader::new_unchecked(hbh_payload));
+                Icmpv6Repr::Mld(*mld_repr).emit(
+                    &_ip_repr.src_addr(),
+                    &_ip_repr.dst_addr(),
+                    &mut Icmpv6Packet::new_unchecked(icmp_payload),
+                    &caps.checksum,
+                )
+            }
             #[cfg(feature = "socket-raw")]
             IpPacket::Raw((_, raw_packet)) => payload.copy_from_slice(raw_packet),
             #[cfg(any(feature = "socket-udp", feature = "socket-dns"))]
@@ -811,6 +1218,23 @@ impl<'a> IpPacket<'a> {
                 |buf| dhcp_repr.emit(&mut DhcpPacket::new_unchecked(buf)).unwrap(),
                 &caps.checksum,
             ),
+            #[cfg(feature = "socket-dhcpv6")]
+            IpPacket::Dhcpv6((_, udp_repr, dhcp_repr)) => udp_repr.emit(
+                &mut UdpPacket::new_unchecked(payload),
+                &_ip_repr.src_addr(),
+                &_ip_repr.dst_addr(),
+                dhcp_repr.buffer_len(),
+                |buf| {
+                    dhcp_repr
+                        .emit(&mut Dhcpv6Packet::new_unchecked(buf))
+                        .unwrap()
+                },
+                &caps.checksum,
+            ),
+            #[cfg(feature = "iface-forwarding")]
+            IpPacket::Forward((_, packet)) => {
+                payload.copy_from_slice(&packet[_ip_repr.header_len()..])
+            }
         }
     }
 }
@@ -844,6 +1268,25 @@ enum IgmpReportState {
     },
 }
 
+#[cfg(feature = "proto-mld")]
+enum MldReportState {
+    Inactive,
+    ToGeneralQuery {
+        timeout: Instant,
+        interval: Duration,
+        next_index: usize,
+    },
+    ToSpecificQuery {
+        timeout: Instant,
+        group: Ipv6Address,
+    },
+}
+
+/// Options of the Hop-by-Hop header preceding MLD messages: a Router Alert option with
+/// the MLD value (RFC 2711, RFC 3810 § 5), padded to 8 octets.
+#[cfg(feature = "proto-mld")]
+const MLD_HOP_BY_HOP_OPTIONS: [u8; 6] = [0x05, 0x02, 0x00, 0x00, 0x01, 0x00];
+
 impl<'a> Interface<'a> {
     /// Get the socket context.
     ///
@@ -891,7 +1334,13 @@ impl<'a> Interface<'a> {
         );
 
         InterfaceInner::check_hardware_addr(&addr);
+        #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+        let changed = self.inner.hardware_addr != Some(addr);
         self.inner.hardware_addr = Some(addr);
+        #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+        if changed {
+            self.inner.arp_announce_all();
+        }
     }
 
     /// Add an address to a list of subscribed multicast IP addresses.
@@ -930,6 +1379,26 @@ impl<'a> Interface<'a> {
                     Ok(false)
                 }
             }
+            #[cfg(feature = "proto-mld")]
+            IpAddress::Ipv6(addr) => {
+                let is_not_new = self
+                    .inner
+                    .ipv6_multicast_groups
+                    .insert(addr, ())
+                    .map_err(|_| Error::Exhausted)?
+                    .is_some();
+                let record = MldAddressRecordRepr::new(MldRecordType::ChangeToExclude, addr);
+                if is_not_new || !ipv6::is_mld_reportable(addr) {
+                    Ok(false)
+                } else if let Some(pkt) = self.inner.mld_report_packet(&record) {
+                    // Send initial listener report
+                    let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+                    self.inner.dispatch_ip(tx_token, pkt, None)?;
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
             // Multicast is not yet implemented for other address families
             #[allow(unreachable_patterns)]
             _ => Err(Error::Unaddressable),
@@ -957,6 +1426,9 @@ impl<'a> Interface<'a> {
                 let was_not_present = self.inner.ipv4_multicast_groups.remove(&addr).is_none();
                 if was_not_present {
                     Ok(false)
+                } else if let Some(result) = self.igmp_leave_with_sources(device, addr) {
+                    // Groups with a source filter are left with an IGMPv3 report
+                    result
                 } else if let Some(pkt) = self.inner.igmp_leave_packet(addr) {
                     // Send group leave packet
                     let tx_token = device.transmit().ok_or(Error::Exhausted)?;
@@ -966,6 +1438,21 @@ impl<'a> Interface<'a> {
                     Ok(false)
                 }
             }
+            #[cfg(feature = "proto-mld")]
+            IpAddress::Ipv6(addr) => {
+                let was_not_present = self.inner.ipv6_multicast_groups.remove(&addr).is_none();
+                let record = MldAddressRecordRepr::new(MldRecordType::ChangeToInclude, addr);
+                if was_not_present || !ipv6::is_mld_reportable(addr) {
+                    Ok(false)
+                } else if let Some(pkt) = self.inner.mld_report_packet(&record) {
+                    // Send listener done packet
+                    let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+                    self.inner.dispatch_ip(tx_token, pkt, None)?;
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
             // Multicast is not yet implemented for other address families
             #[allow(unreachable_patterns)]
             _ => Err(Error::Unaddressable),
@@ -996,12 +1483,24 @@ impl<'a> Interface<'a> {
 
     /// Update the IP addresses of the interface.
     ///
+    /// IPv6 addresses that weren't assigned before are tentative until Duplicate Address
+    /// Detection completes, see [ipv6_addr_state].
+    ///
     /// # Panics
     /// This function panics if any of the addresses are not unicast.
+    ///
+    /// [ipv6_addr_state]: #method.ipv6_addr_state
     pub fn update_ip_addrs<F: FnOnce(&mut ManagedSlice<'a, IpCidr>)>(&mut self, f: F) {
         f(&mut self.inner.ip_addrs);
         InterfaceInner::flush_cache(&mut self.inner);
-        InterfaceInner::check_ip_addrs(&self.inner.ip_addrs)
+        InterfaceInner::check_ip_addrs(&self.inner.ip_addrs);
+        #[cfg(all(
+            feature = "proto-ipv6",
+            any(feature = "medium-ethernet", feature = "medium-ieee802154")
+        ))]
+        self.inner.dad_sync();
+        #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+        self.inner.arp_sync();
     }
 
     /// Check whether the interface has the given IP address assigned.
@@ -1009,12 +1508,25 @@ impl<'a> Interface<'a> {
         self.inner.has_ip_addr(addr)
     }
 
+    /// Get the MTU of the path to the given destination, as lowered by ICMP errors.
+    ///
+    /// This is the IP MTU of the link unless a smaller one was learned for the
+    /// destination in the last 10 minutes.
+    pub fn path_mtu<T: Into<IpAddress>>(&self, addr: T) -> usize {
+        self.inner.path_mtu(&addr.into())
+    }
+
     /// Get the first IPv4 address of the interface.
     #[cfg(feature = "proto-ipv4")]
     pub fn ipv4_address(&self) -> Option<Ipv4Address> {
         self.inner.ipv4_address()
     }
 
+    /// Get the identifier of the interface.
+    pub fn id(&self) -> InterfaceId {
+        self.inner.id
+    }
+
     pub fn routes(&self) -> &Routes<'a> {
         &self.inner.routes
     }
@@ -1023,6 +1535,36 @@ impl<'a> Interface<'a> {
         &mut self.inner.routes
     }
 
+    /// Get the IEEE 802.1Q VLAN ID of the interface, if its frames are tagged.
+    #[cfg(feature = "medium-ethernet")]
+    pub fn vlan_id(&self) -> Option<u16> {
+        self.inner.vlan_id
+    }
+
+    /// Get the translation table of the interface, if it masquerades forwarded packets.
+    #[cfg(feature = "iface-nat")]
+    pub fn nat_table(&self) -> Option<&NatTable<'a>> {
+        self.inner.nat.as_ref()
+    }
+
+    /// Get the translation table of the interface mutably, e.g. to add port forwards.
+    #[cfg(feature = "iface-nat")]
+    pub fn nat_table_mut(&mut self) -> Option<&mut NatTable<'a>> {
+        self.inner.nat.as_mut()
+    }
+
+    /// Get the packet filter of the interface, if any.
+    #[cfg(feature = "iface-filter")]
+    pub fn filter(&self) -> Option<&Filter<'a>> {
+        self.inner.filter.as_ref()
+    }
+
+    /// Get the packet filter of the interface mutably, e.g. to update its rules.
+    #[cfg(feature = "iface-filter")]
+    pub fn filter_mut(&mut self) -> Option<&mut Filter<'a>> {
+        self.inner.filter.as_mut()
+    }
+
     /// Transmit packets queued in the given sockets, and receive packets queued
     /// in the device.
     ///
@@ -1090,6 +1632,33 @@ impl<'a> Interface<'a> {
             #[cfg(feature = "proto-igmp")]
             self.igmp_egress(device)?;
 
+            #[cfg(feature = "proto-mld")]
+            self.mld_egress(device)?;
+
+            #[cfg(all(
+                feature = "proto-ipv6",
+                any(feature = "medium-ethernet", feature = "medium-ieee802154")
+            ))]
+            let emitted_any = self.slaac_egress(device)? || emitted_any;
+
+            #[cfg(all(
+                feature = "proto-ipv6",
+                any(feature = "medium-ethernet", feature = "medium-ieee802154")
+            ))]
+            let emitted_any = self.dad_egress(device)? || emitted_any;
+
+            #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+            let emitted_any = self.zeroconf_egress(device)? || emitted_any;
+
+            #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+            let emitted_any = self.arp_egress(device)? || emitted_any;
+
+            #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+            let emitted_any = self.neighbor_egress(device)? || emitted_any;
+
+            #[cfg(feature = "medium-ethernet")]
+            let emitted_any = self.hold_egress(device, sockets)? || emitted_any;
+
             if processed_any || emitted_any {
                 readiness_may_have_changed = true;
             } else {
@@ -1116,6 +1685,60 @@ impl<'a> Interface<'a> {
             return Some(Instant::from_millis(0));
         }
 
+        #[cfg(all(
+            feature = "proto-ipv6",
+            any(feature = "medium-ethernet", feature = "medium-ieee802154")
+        ))]
+        let slaac_poll_at = self.inner.slaac.as_ref().and_then(|state| state.poll_at());
+        #[cfg(not(all(
+            feature = "proto-ipv6",
+            any(feature = "medium-ethernet", feature = "medium-ieee802154")
+        )))]
+        let slaac_poll_at = None;
+
+        #[cfg(all(
+            feature = "proto-ipv6",
+            any(feature = "medium-ethernet", feature = "medium-ieee802154")
+        ))]
+        let dad_poll_at = self.inner.dad.poll_at();
+        #[cfg(not(all(
+            feature = "proto-ipv6",
+            any(feature = "medium-ethernet", feature = "medium-ieee802154")
+        )))]
+        let dad_poll_at = None;
+
+        #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+        let zeroconf_poll_at = self
+            .inner
+            .zeroconf
+            .as_ref()
+            .and_then(|state| state.poll_at());
+        #[cfg(not(all(feature = "proto-ipv4", feature = "medium-ethernet")))]
+        let zeroconf_poll_at = None;
+
+        #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+        let arp_poll_at = self.inner.arp.poll_at();
+        #[cfg(not(all(feature = "proto-ipv4", feature = "medium-ethernet")))]
+        let arp_poll_at = None;
+
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        let neighbor_poll_at = self
+            .inner
+            .neighbor_cache
+            .as_ref()
+            .and_then(|cache| cache.poll_at());
+        #[cfg(not(any(feature = "medium-ethernet", feature = "medium-ieee802154")))]
+        let neighbor_poll_at = None;
+
+        #[cfg(feature = "proto-mld")]
+        let mld_poll_at = match self.inner.mld_report_state {
+            MldReportState::Inactive => None,
+            MldReportState::ToGeneralQuery { timeout, .. }
+            | MldReportState::ToSpecificQuery { timeout, .. } => Some(timeout),
+        };
+        #[cfg(not(feature = "proto-mld"))]
+        let mld_poll_at = None;
+
         let inner = &mut self.inner;
 
         sockets
@@ -1131,6 +1754,12 @@ impl<'a> Interface<'a> {
                     PollAt::Now => Some(Instant::from_millis(0)),
                 }
             })
+            .chain(slaac_poll_at)
+            .chain(dad_poll_at)
+            .chain(zeroconf_poll_at)
+            .chain(arp_poll_at)
+            .chain(neighbor_poll_at)
+            .chain(mld_poll_at)
             .min()
     }
 
@@ -1210,6 +1839,9 @@ impl<'a> Interface<'a> {
     where
         D: Device + ?Sized,
     {
+        #[cfg(all(feature = "socket-dns", feature = "socket-tcp"))]
+        self.dns_tcp_egress(sockets);
+
         let Self {
             inner,
             out_packets: _out_packets,
@@ -1227,8 +1859,20 @@ impl<'a> Interface<'a> {
             }
 
             let mut neighbor_addr = None;
+            let mut routed_elsewhere = false;
             let mut respond = |inner: &mut InterfaceInner, response: IpPacket| {
-                neighbor_addr = Some(response.ip_repr().dst_addr());
+                let dst_addr = response.ip_repr().dst_addr();
+                neighbor_addr = Some(dst_addr);
+                // Leave the packet to the interface it's routed through, when several
+                // interfaces share the socket set.
+                if inner
+                    .egress_interface(&dst_addr)
+                    .map_or(false, |id| id != inner.id)
+                {
+                    routed_elsewhere = true;
+                    return Err(Error::Unaddressable);
+                }
+
                 let t = device.transmit().ok_or_else(|| {
                     net_debug!("failed to transmit IP: {}", Error::Exhausted);
                     Error::Exhausted
@@ -1281,14 +1925,26 @@ impl<'a> Interface<'a> {
                 Socket::Dhcpv4(socket) => socket.dispatch(inner, |inner, response| {
                     respond(inner, IpPacket::Dhcpv4(response))
                 }),
+                // The server replies while processing the client's message.
+                #[cfg(feature = "socket-dhcpv4")]
+                Socket::Dhcpv4Server(_) => Ok(()),
+                #[cfg(feature = "socket-dhcpv6")]
+                Socket::Dhcpv6(socket) => socket.dispatch(inner, |inner, response| {
+                    respond(inner, IpPacket::Dhcpv6(response))
+                }),
                 #[cfg(feature = "socket-dns")]
                 Socket::Dns(ref mut socket) => socket.dispatch(inner, |inner, response| {
                     respond(inner, IpPacket::Udp(response))
                 }),
+                #[cfg(feature = "socket-mdns")]
+                Socket::MdnsResponder(socket) => socket.dispatch(inner, |inner, response| {
+                    respond(inner, IpPacket::Udp(response))
+                }),
             };
 
             match result {
                 Err(Error::Exhausted) => break, // Device buffer full.
+                Err(Error::Unaddressable) if routed_elsewhere => {}
                 Err(Error::Unaddressable) => {
                     // `NeighborCache` already takes care of rate limiting the neighbor discovery
                     // requests from the socket. However, without an additional rate limiting
@@ -1313,6 +1969,36 @@ impl<'a> Interface<'a> {
         emitted_any
     }
 
+    /// Let the DNS sockets retrying queries over TCP use the TCP sockets they were given,
+    /// before the TCP sockets dispatch.
+    #[cfg(all(feature = "socket-dns", feature = "socket-tcp"))]
+    fn dns_tcp_egress(&mut self, sockets: &mut SocketSet<'_>) {
+        let mut last = None;
+        loop {
+            let next = sockets
+                .items()
+                .filter(|item| last.map_or(true, |last| item.meta.handle > last))
+                .find_map(|item| {
+                    let tcp_handle = dns::Socket::downcast(&item.socket)?.tcp_socket()?;
+                    Some((item.meta.handle, tcp_handle))
+                });
+            let (dns_handle, tcp_handle) = match next {
+                Some(handles) => handles,
+                None => break,
+            };
+
+            last = Some(dns_handle);
+            if let Some((dns_item, tcp_item)) = sockets.get_pair_mut(dns_handle, tcp_handle) {
+                if let (Some(dns_socket), Some(tcp_socket)) = (
+                    dns::Socket::downcast_mut(&mut dns_item.socket),
+                    tcp::Socket::downcast_mut(&mut tcp_item.socket),
+                ) {
+                    dns_socket.poll_tcp(&mut self.inner, tcp_socket);
+                }
+            }
+        }
+    }
+
     /// Depending on `igmp_report_state` and the therein contained
     /// timeouts, send IGMP membership reports.
     #[cfg(feature = "proto-igmp")]
@@ -1326,11 +2012,7 @@ impl<'a> Interface<'a> {
                 timeout,
                 group,
             } if self.inner.now >= timeout => {
-                if let Some(pkt) = self.inner.igmp_report_packet(version, group) {
-                    // Send initial membership report
-                    let tx_token = device.transmit().ok_or(Error::Exhausted)?;
-                    self.inner.dispatch_ip(tx_token, pkt, None)?;
-                }
+                self.igmp_send_report(device, version, group)?;
 
                 self.inner.igmp_report_state = IgmpReportState::Inactive;
                 Ok(true)
@@ -1350,11 +2032,7 @@ impl<'a> Interface<'a> {
 
                 match addr {
                     Some(addr) => {
-                        if let Some(pkt) = self.inner.igmp_report_packet(version, addr) {
-                            // Send initial membership report
-                            let tx_token = device.transmit().ok_or(Error::Exhausted)?;
-                            self.inner.dispatch_ip(tx_token, pkt, None)?;
-                        }
+                        self.igmp_send_report(device, version, addr)?;
 
                         let next_timeout = (timeout + interval).max(self.inner.now);
                         self.inner.igmp_report_state = IgmpReportState::ToGeneralQuery {
@@ -1376,6 +2054,56 @@ impl<'a> Interface<'a> {
         }
     }
 
+    /// Depending on `mld_report_state` and the therein contained
+    /// timeouts, send MLD listener reports.
+    #[cfg(feature = "proto-mld")]
+    fn mld_egress<D>(&mut self, device: &mut D) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        match self.inner.mld_report_state {
+            MldReportState::ToSpecificQuery { timeout, group } if self.inner.now >= timeout => {
+                let record = MldAddressRecordRepr::new(MldRecordType::ModeIsExclude, group);
+                if let Some(pkt) = self.inner.mld_report_packet(&record) {
+                    let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+                    self.inner.dispatch_ip(tx_token, pkt, None)?;
+                }
+
+                self.inner.mld_report_state = MldReportState::Inactive;
+                Ok(true)
+            }
+            MldReportState::ToGeneralQuery {
+                timeout,
+                interval,
+                next_index,
+            } if self.inner.now >= timeout => {
+                let group = self.inner.mld_groups().nth(next_index);
+                match group {
+                    Some(group) => {
+                        let record = MldAddressRecordRepr::new(MldRecordType::ModeIsExclude, group);
+                        if let Some(pkt) = self.inner.mld_report_packet(&record) {
+                            let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+                            self.inner.dispatch_ip(tx_token, pkt, None)?;
+                        }
+
+                        let next_timeout = (timeout + interval).max(self.inner.now);
+                        self.inner.mld_report_state = MldReportState::ToGeneralQuery {
+                            timeout: next_timeout,
+                            interval,
+                            next_index: next_index + 1,
+                        };
+                        Ok(true)
+                    }
+                    None => {
+                        self.inner.mld_report_state = MldReportState::Inactive;
+                        Ok(false)
+                    }
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
     /// Process fragments that still need to be sent for IPv4 packets.
     ///
     /// This function returns a boolean value indicating whether any packets were
@@ -1483,14 +2211,20 @@ impl<'a> InterfaceInner<'a> {
 
     #[allow(unused)] // unused depending on which sockets are enabled
     pub(crate) fn get_source_address(&mut self, dst_addr: IpAddress) -> Option<IpAddress> {
-        let v = dst_addr.version();
-        for cidr in self.ip_addrs.iter() {
-            let addr = cidr.address();
-            if addr.version() == v {
-                return Some(addr);
+        match dst_addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(dst_addr) => self.get_source_address_ipv6(dst_addr).map(Into::into),
+            _ => {
+                let v = dst_addr.version();
+                for cidr in self.ip_addrs.iter() {
+                    let addr = cidr.address();
+                    if addr.version() == v {
+                        return Some(addr);
+                    }
+                }
+                None
             }
         }
-        None
     }
 
     #[cfg(feature = "proto-ipv4")]
@@ -1510,17 +2244,69 @@ impl<'a> InterfaceInner<'a> {
 
     #[cfg(feature = "proto-ipv6")]
     #[allow(unused)]
-    pub(crate) fn get_source_address_ipv6(
-        &mut self,
-        _dst_addr: Ipv6Address,
-    ) -> Option<Ipv6Address> {
+    pub(crate) fn get_source_address_ipv6(&mut self, dst_addr: Ipv6Address) -> Option<Ipv6Address> {
+        // Unspecified addresses are free slots for autoconfiguration. Prefer an address
+        // with the same scope as the destination, then one that isn't deprecated
+        // (RFC 6724 § 5, rules 2 and 3).
+        // Multicast addresses such as ff02::1:2 have their scope in the second byte.
+        let dst_is_link_local =
+            dst_addr.is_link_local() || (dst_addr.is_multicast() && dst_addr.0[1] & 0x0f == 0x02);
+        let (mut same_scope, mut other_scope) = (None, None);
         for cidr in self.ip_addrs.iter() {
             #[allow(irrefutable_let_patterns)] // if only ipv6 is enabled
             if let IpCidr::Ipv6(cidr) = cidr {
-                return Some(cidr.address());
+                let addr = cidr.address();
+                if addr.is_unspecified() || !self.is_usable_ipv6_addr(addr) {
+                    continue;
+                }
+
+                if addr.is_link_local() != dst_is_link_local {
+                    other_scope = other_scope.or(Some(addr));
+                } else if self.is_deprecated_ipv6_addr(addr) {
+                    same_scope = same_scope.or(Some(addr));
+                } else {
+                    return Some(addr);
+                }
             }
         }
-        None
+        same_scope.or(other_scope)
+    }
+
+    #[cfg(feature = "socket-mdns")]
+    pub(crate) fn ip_addrs(&self) -> &[IpCidr] {
+        self.ip_addrs.as_ref()
+    }
+
+    /// Return whether other hosts can reach the interface at `addr`, i.e. it's
+    /// specified and, for IPv6, neither tentative nor a duplicate.
+    #[cfg(feature = "socket-mdns")]
+    pub(crate) fn is_usable_ip_addr(&self, addr: IpAddress) -> bool {
+        match addr {
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(addr) => !addr.is_unspecified() && self.is_usable_ipv6_addr(addr),
+            #[allow(unreachable_patterns)]
+            addr => !addr.is_unspecified(),
+        }
+    }
+
+    /// Return whether `addr` can be used, i.e. it's neither tentative nor a duplicate.
+    #[cfg(all(
+        feature = "proto-ipv6",
+        not(any(feature = "medium-ethernet", feature = "medium-ieee802154"))
+    ))]
+    fn is_usable_ipv6_addr(&self, _addr: Ipv6Address) -> bool {
+        true
+    }
+
+    /// Return whether `addr` was configured by SLAAC and has been deprecated, i.e.
+    /// shouldn't be used as the source address of new connections.
+    #[cfg(feature = "proto-ipv6")]
+    fn is_deprecated_ipv6_addr(&self, _addr: Ipv6Address) -> bool {
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        if let Some(state) = self.slaac.as_ref() {
+            return state.is_deprecated(_addr, self.now);
+        }
+        false
     }
 
     #[cfg(test)]
@@ -1558,6 +2344,7 @@ impl<'a> InterfaceInner<'a> {
                 )),
             ]),
             rand: Rand::new(1234),
+            id: InterfaceId::default(),
             routes: Routes::new(&mut [][..]),
 
             #[cfg(feature = "proto-ipv4")]
@@ -1565,6 +2352,12 @@ impl<'a> InterfaceInner<'a> {
 
             #[cfg(feature = "medium-ieee802154")]
             pan_id: Some(crate::wire::Ieee802154Pan(0xabcd)),
+            #[cfg(feature = "medium-ethernet")]
+            vlan_id: None,
+            #[cfg(feature = "medium-ethernet")]
+            vlan_priority: 0,
+            #[cfg(feature = "medium-ethernet")]
+            hold_queues: ManagedSlice::Borrowed(&mut []),
             #[cfg(feature = "medium-ieee802154")]
             sequence_no: 1,
 
@@ -1588,6 +2381,35 @@ impl<'a> InterfaceInner<'a> {
             igmp_report_state: IgmpReportState::Inactive,
             #[cfg(feature = "proto-igmp")]
             ipv4_multicast_groups: ManagedMap::Borrowed(&mut []),
+            #[cfg(feature = "proto-igmp")]
+            igmp_source_filters: igmp::SourceFilters::new(),
+            #[cfg(feature = "proto-mld")]
+            mld_report_state: MldReportState::Inactive,
+            #[cfg(feature = "proto-mld")]
+            ipv6_multicast_groups: ManagedMap::Borrowed(&mut []),
+            #[cfg(feature = "proto-mld")]
+            mld_v1_querier_until: None,
+            #[cfg(all(
+                feature = "proto-ipv6",
+                any(feature = "medium-ethernet", feature = "medium-ieee802154")
+            ))]
+            slaac: None,
+            #[cfg(all(
+                feature = "proto-ipv6",
+                any(feature = "medium-ethernet", feature = "medium-ieee802154")
+            ))]
+            dad: dad::State::new(&[]),
+            #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+            arp: arp::State::new(&[]),
+            #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+            zeroconf: None,
+            pmtu: pmtu::Cache::new(),
+            #[cfg(feature = "iface-forwarding")]
+            forwarding: None,
+            #[cfg(feature = "iface-nat")]
+            nat: None,
+            #[cfg(feature = "iface-filter")]
+            filter: None,
         }
     }
 
@@ -1642,7 +2464,10 @@ impl<'a> InterfaceInner<'a> {
     pub fn has_solicited_node(&self, addr: Ipv6Address) -> bool {
         self.ip_addrs.iter().any(|cidr| {
             match *cidr {
-                IpCidr::Ipv6(cidr) if cidr.address() != Ipv6Address::LOOPBACK => {
+                IpCidr::Ipv6(cidr)
+                    if cidr.address() != Ipv6Address::LOOPBACK
+                        && !cidr.address().is_unspecified() =>
+                {
                     // Take the lower order 24 bits of the IPv6 address and
                     // append those bits to FF02:0:0:0:0:1:FF00::/104.
                     addr.as_bytes()[14..] == cidr.address().as_bytes()[14..]
@@ -1653,7 +2478,7 @@ impl<'a> InterfaceInner<'a> {
     }
 
     /// Check whether the interface has the given IP address assigned.
-    fn has_ip_addr<T: Into<IpAddress>>(&self, addr: T) -> bool {
+    pub(crate) fn has_ip_addr<T: Into<IpAddress>>(&self, addr: T) -> bool {
         let addr = addr.into();
         self.ip_addrs.iter().any(|probe| probe.address() == addr)
     }
@@ -1671,7 +2496,8 @@ impl<'a> InterfaceInner<'a> {
     /// Check whether the interface listens to given destination multicast IP address.
     ///
     /// If built without feature `proto-igmp` this function will
-    /// always return `false`.
+    /// always return `false` for IPv4 addresses, and without feature
+    /// `proto-mld` for IPv6 addresses.
     pub fn has_multicast_group<T: Into<IpAddress>>(&self, addr: T) -> bool {
         match addr.into() {
             #[cfg(feature = "proto-igmp")]
@@ -1679,6 +2505,15 @@ impl<'a> InterfaceInner<'a> {
                 key == Ipv4Address::MULTICAST_ALL_SYSTEMS
                     || self.ipv4_multicast_groups.get(&key).is_some()
             }
+            #[cfg(feature = "proto-mld")]
+            IpAddress::Ipv6(key) => {
+                key == Ipv6Address::LINK_LOCAL_ALL_NODES
+                    || self.ipv6_multicast_groups.get(&key).is_some()
+                    || self
+                        .ip_addrs
+                        .iter()
+                        .any(|cidr| ipv6::solicited_node_group(cidr) == Some(key))
+            }
             #[allow(unreachable_patterns)]
             _ => false,
         }
@@ -1785,6 +2620,21 @@ impl<'a> InterfaceInner<'a> {
             }
         }
 
+        // mDNS queries and responses are for every responder, and responses may
+        // also answer a query of a DNS socket.
+        #[cfg(feature = "socket-mdns")]
+        let mut handled_by_mdns_responder = false;
+        #[cfg(feature = "socket-mdns")]
+        for responder in sockets
+            .items_mut()
+            .filter_map(|i| dns::Responder::downcast_mut(&mut i.socket))
+        {
+            if responder.accepts(&ip_repr, &udp_repr) {
+                responder.process(self, &ip_repr, &udp_repr, udp_payload);
+                handled_by_mdns_responder = true;
+            }
+        }
+
         #[cfg(feature = "socket-dns")]
         for dns_socket in sockets
             .items_mut()
@@ -1796,6 +2646,11 @@ impl<'a> InterfaceInner<'a> {
             }
         }
 
+        #[cfg(feature = "socket-mdns")]
+        if handled_by_mdns_responder {
+            return None;
+        }
+
         // The packet wasn't handled by a socket, send an ICMP port unreachable packet.
         match ip_repr {
             #[cfg(feature = "proto-ipv4")]
@@ -1896,7 +2751,9 @@ impl<'a> InterfaceInner<'a> {
     }
 
     fn in_same_network(&self, addr: &IpAddress) -> bool {
-        self.ip_addrs.iter().any(|cidr| cidr.contains_addr(addr))
+        self.ip_addrs
+            .iter()
+            .any(|cidr| !cidr.address().is_unspecified() && cidr.contains_addr(addr))
     }
 
     fn route(&self, addr: &IpAddress, timestamp: Instant) -> Result<IpAddress> {
@@ -1905,13 +2762,29 @@ impl<'a> InterfaceInner<'a> {
             return Ok(*addr);
         }
 
-        // Route via a router.
-        match self.routes.lookup(addr, timestamp) {
-            Some(router_addr) => Ok(router_addr),
+        // Route via a router, unless another interface has to send it.
+        match self.routes.lookup_route(addr, timestamp) {
+            Some(route) if route.interface.map_or(false, |id| id != self.id) => {
+                Err(Error::Unaddressable)
+            }
+            Some(route) if route.via_router.is_unspecified() => Ok(*addr),
+            Some(route) => Ok(route.via_router),
             None => Err(Error::Unaddressable),
         }
     }
 
+    /// Return the interface that packets to `addr` are sent through, if there's
+    /// a route to it.
+    fn egress_interface(&self, addr: &IpAddress) -> Option<InterfaceId> {
+        if self.in_same_network(addr) || !addr.is_unicast() {
+            return Some(self.id);
+        }
+
+        self.routes
+            .lookup_route(addr, self.now)
+            .map(|route| route.interface.unwrap_or(self.id))
+    }
+
     fn has_neighbor(&self, addr: &IpAddress) -> bool {
         match self.route(addr, self.now) {
             Ok(_routed_addr) => match self.caps.medium {
@@ -1936,6 +2809,18 @@ impl<'a> InterfaceInner<'a> {
         }
     }
 
+    /// Confirm that the neighbor packets to `addr` are sent through is reachable,
+    /// e.g. because a TCP connection to `addr` made forward progress.
+    #[allow(unused)] // unused depending on which sockets are enabled
+    pub(crate) fn confirm_reachable(&mut self, addr: &IpAddress) {
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        if let Ok(neighbor_addr) = self.route(addr, self.now) {
+            if let Some(cache) = self.neighbor_cache.as_mut() {
+                cache.confirm(neighbor_addr, None, self.now);
+            }
+        }
+    }
+
     #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
     fn lookup_hardware_addr<Tx>(
         &mut self,
@@ -1994,78 +2879,23 @@ impl<'a> InterfaceInner<'a> {
 
         let dst_addr = self.route(dst_addr, self.now)?;
 
-        match self
-            .neighbor_cache
-            .as_mut()
-            .unwrap()
-            .lookup(&dst_addr, self.now)
-        {
-            NeighborAnswer::Found(hardware_addr) => return Ok((hardware_addr, tx_token)),
+        let cache = self.neighbor_cache.as_mut().unwrap();
+        match cache.lookup(&dst_addr, self.now) {
+            NeighborAnswer::Found(hardware_addr) => {
+                cache.used(&dst_addr, self.now);
+                return Ok((hardware_addr, tx_token));
+            }
             NeighborAnswer::RateLimited => return Err(Error::Unaddressable),
             _ => (), // XXX
         }
 
-        match (src_addr, dst_addr) {
-            #[cfg(feature = "proto-ipv4")]
-            (&IpAddress::Ipv4(src_addr), IpAddress::Ipv4(dst_addr)) => {
-                net_debug!(
-                    "address {} not in neighbor cache, sending ARP request",
-                    dst_addr
-                );
-                let src_hardware_addr =
-                    if let Some(HardwareAddress::Ethernet(addr)) = self.hardware_addr {
-                        addr
-                    } else {
-                        return Err(Error::Malformed);
-                    };
-
-                let arp_repr = ArpRepr::EthernetIpv4 {
-                    operation: ArpOperation::Request,
-                    source_hardware_addr: src_hardware_addr,
-                    source_protocol_addr: src_addr,
-                    target_hardware_addr: EthernetAddress::BROADCAST,
-                    target_protocol_addr: dst_addr,
-                };
-
-                self.dispatch_ethernet(tx_token, arp_repr.buffer_len(), |mut frame| {
-                    frame.set_dst_addr(EthernetAddress::BROADCAST);
-                    frame.set_ethertype(EthernetProtocol::Arp);
+        net_debug!("address {} not in neighbor cache", dst_addr);
+        self.solicit_neighbor(tx_token, src_addr, &dst_addr, None)?;
 
-                    arp_repr.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()))
-                })?;
-            }
-
-            #[cfg(feature = "proto-ipv6")]
-            (&IpAddress::Ipv6(src_addr), IpAddress::Ipv6(dst_addr)) => {
-                net_debug!(
-                    "address {} not in neighbor cache, sending Neighbor Solicitation",
-                    dst_addr
-                );
-
-                let solicit = Icmpv6Repr::Ndisc(NdiscRepr::NeighborSolicit {
-                    target_addr: dst_addr,
-                    lladdr: Some(self.hardware_addr.unwrap().into()),
-                });
-
-                let packet = IpPacket::Icmpv6((
-                    Ipv6Repr {
-                        src_addr,
-                        dst_addr: dst_addr.solicited_node(),
-                        next_header: IpProtocol::Icmpv6,
-                        payload_len: solicit.buffer_len(),
-                        hop_limit: 0xff,
-                    },
-                    solicit,
-                ));
-
-                self.dispatch_ip(tx_token, packet, None)?;
-            }
-
-            #[allow(unreachable_patterns)]
-            _ => (),
-        }
         // The request got dispatched, limit the rate on the cache.
-        self.neighbor_cache.as_mut().unwrap().limit_rate(self.now);
+        let cache = self.neighbor_cache.as_mut().unwrap();
+        cache.limit_rate(self.now);
+        cache.resolve(dst_addr, self.now);
         Err(Error::Unaddressable)
     }
 
@@ -2085,6 +2915,13 @@ impl<'a> InterfaceInner<'a> {
         let mut ip_repr = packet.ip_repr();
         assert!(!ip_repr.dst_addr().is_unspecified());
 
+        #[cfg(feature = "iface-filter")]
+        if self.filter_egress(&packet) != FilterAction::Accept {
+            // Sockets would retry the packet if they were told, it's dropped instead.
+            net_debug!("filter: dropped packet to {}", ip_repr.dst_addr());
+            return Ok(());
+        }
+
         // Dispatch IEEE802.15.4:
 
         #[cfg(feature = "medium-ieee802154")]
@@ -2109,6 +2946,16 @@ impl<'a> InterfaceInner<'a> {
 
         // Dispatch IP/Ethernet:
 
+        // Forwarded packets are never fragmented.
+        #[cfg(feature = "iface-forwarding")]
+        if matches!(packet, IpPacket::Forward(_)) && ip_repr.buffer_len() > self.caps.ip_mtu() {
+            net_debug!(
+                "forwarded packet to {} exceeds the MTU, dropping",
+                ip_repr.dst_addr()
+            );
+            return Ok(());
+        }
+
         let caps = self.caps.clone();
 
         #[cfg(feature = "proto-ipv4-fragmentation")]
@@ -2120,17 +2967,28 @@ impl<'a> InterfaceInner<'a> {
         // Add the size of the Ethernet header if the medium is Ethernet.
         #[cfg(feature = "medium-ethernet")]
         if matches!(self.caps.medium, Medium::Ethernet) {
-            total_len = EthernetFrame::<&[u8]>::buffer_len(total_len);
+            total_len = EthernetFrame::<&[u8]>::buffer_len(total_len) + self.vlan_tag_len();
         }
 
         // If the medium is Ethernet, then we need to retrieve the destination hardware address.
         #[cfg(feature = "medium-ethernet")]
-        let (dst_hardware_addr, tx_token) =
-            match self.lookup_hardware_addr(tx_token, &ip_repr.src_addr(), &ip_repr.dst_addr())? {
-                (HardwareAddress::Ethernet(addr), tx_token) => (addr, tx_token),
-                #[cfg(feature = "medium-ieee802154")]
-                (HardwareAddress::Ieee802154(_), _) => unreachable!(),
-            };
+        let (dst_hardware_addr, tx_token) = match self.caps.medium {
+            Medium::Ethernet => {
+                let src_addr = ip_repr.src_addr();
+                match self.lookup_hardware_addr(tx_token, &src_addr, &ip_repr.dst_addr()) {
+                    Ok((HardwareAddress::Ethernet(addr), tx_token)) => (addr, tx_token),
+                    #[cfg(feature = "medium-ieee802154")]
+                    Ok((HardwareAddress::Ieee802154(_), _)) => unreachable!(),
+                    // The packet is sent once the address is resolved.
+                    Err(Error::Unaddressable) if self.hold_packet(&ip_repr, &packet) => {
+                        return Ok(())
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            #[allow(unreachable_patterns)]
+            _ => (EthernetAddress::default(), tx_token),
+        };
 
         // Emit function for the Ethernet header.
         #[cfg(feature = "medium-ethernet")]
@@ -2158,6 +3016,11 @@ impl<'a> InterfaceInner<'a> {
 
         // Emit function for the IP header and payload.
         let emit_ip = |repr: &IpRepr, mut tx_buffer: &mut [u8]| {
+            #[cfg(feature = "iface-forwarding")]
+            if let IpPacket::Forward((_, raw_packet)) = &packet {
+                return forwarding::emit(repr, raw_packet, tx_buffer, &caps.checksum);
+            }
+
             repr.emit(&mut tx_buffer, &self.caps.checksum);
 
             let payload = &mut tx_buffer[repr.header_len()..];
@@ -2170,7 +3033,8 @@ impl<'a> InterfaceInner<'a> {
             #[cfg(feature = "proto-ipv4")]
             IpRepr::Ipv4(ref mut repr) => {
                 // If we have an IPv4 packet, then we need to check if we need to fragment it.
-                if total_ip_len > self.caps.max_transmission_unit {
+                let path_mtu = self.path_mtu(&IpAddress::Ipv4(repr.dst_addr));
+                if total_ip_len > path_mtu {
                     #[cfg(feature = "proto-ipv4-fragmentation")]
                     {
                         net_debug!("start fragmentation");
@@ -2185,11 +3049,17 @@ impl<'a> InterfaceInner<'a> {
                             dst_hardware_addr: dst_address,
                         } = &mut _out_packet.unwrap().ipv4_out_packet;
 
-                        // Calculate how much we will send now (including the Ethernet header).
-                        let tx_len = self.caps.max_transmission_unit;
-
+                        // The payload of every fragment but the last is a multiple of 8 octets.
                         let ip_header_len = repr.buffer_len();
-                        let first_frag_ip_len = self.caps.ip_mtu();
+                        let first_frag_ip_len = ip_header_len + ((path_mtu - ip_header_len) & !7);
+
+                        // Calculate how much we will send now (including the Ethernet header).
+                        #[allow(unused_mut)]
+                        let mut tx_len = first_frag_ip_len;
+                        #[cfg(feature = "medium-ethernet")]
+                        if matches!(self.caps.medium, Medium::Ethernet) {
+                            tx_len += EthernetFrame::<&[u8]>::header_len() + self.vlan_tag_len();
+                        }
 
                         if buffer.len() < first_frag_ip_len {
                             net_debug!("Fragmentation buffer is too small");
@@ -2228,8 +3098,11 @@ impl<'a> InterfaceInner<'a> {
                         tx_token.consume(self.now, tx_len, |mut tx_buffer| {
                             #[cfg(feature = "medium-ethernet")]
                             if matches!(self.caps.medium, Medium::Ethernet) {
-                                emit_ethernet(&ip_repr, tx_buffer)?;
-                                tx_buffer = &mut tx_buffer[EthernetFrame::<&[u8]>::header_len()..];
+                                let tag_len = self.vlan_tag_len();
+                                emit_ethernet(&ip_repr, &mut tx_buffer[tag_len..])?;
+                                self.insert_vlan_tag(tx_buffer);
+                                tx_buffer = &mut tx_buffer
+                                    [tag_len + EthernetFrame::<&[u8]>::header_len()..];
                             }
 
                             // Change the offset for the next packet.
@@ -2253,8 +3126,11 @@ impl<'a> InterfaceInner<'a> {
                     tx_token.consume(self.now, total_len, |mut tx_buffer| {
                         #[cfg(feature = "medium-ethernet")]
                         if matches!(self.caps.medium, Medium::Ethernet) {
-                            emit_ethernet(&ip_repr, tx_buffer)?;
-                            tx_buffer = &mut tx_buffer[EthernetFrame::<&[u8]>::header_len()..];
+                            let tag_len = self.vlan_tag_len();
+                            emit_ethernet(&ip_repr, &mut tx_buffer[tag_len..])?;
+                            self.insert_vlan_tag(tx_buffer);
+                            tx_buffer =
+                                &mut tx_buffer[tag_len + EthernetFrame::<&[u8]>::header_len()..];
                         }
 
                         emit_ip(&ip_repr, tx_buffer);
@@ -2267,8 +3143,10 @@ impl<'a> InterfaceInner<'a> {
             IpRepr::Ipv6(_) => tx_token.consume(self.now, total_len, |mut tx_buffer| {
                 #[cfg(feature = "medium-ethernet")]
                 if matches!(self.caps.medium, Medium::Ethernet) {
-                    emit_ethernet(&ip_repr, tx_buffer)?;
-                    tx_buffer = &mut tx_buffer[EthernetFrame::<&[u8]>::header_len()..];
+                    let tag_len = self.vlan_tag_len();
+                    emit_ethernet(&ip_repr, &mut tx_buffer[tag_len..])?;
+                    self.insert_vlan_tag(tx_buffer);
+                    tx_buffer = &mut tx_buffer[tag_len + EthernetFrame::<&[u8]>::header_len()..];
                 }
 
                 emit_ip(&ip_repr, tx_buffer);
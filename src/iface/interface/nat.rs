@@ -0,0 +1,232 @@
+use super::forwarding::set_hop_limit;
+use super::InterfaceInner;
+use super::IpPacket;
+
+use crate::wire::ip::checksum;
+use crate::wire::*;
+
+/// Which endpoint of a packet is translated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    Source,
+    Destination,
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Return whether packets forwarded through this interface are translated.
+    pub(super) fn is_translating(&self) -> bool {
+        self.nat.is_some()
+    }
+
+    /// Translate a received IPv4 `packet` addressed to us for a private endpoint, and
+    /// forward it there.
+    ///
+    /// Returns `None` if no mapping matches the packet, which is then ours to process.
+    pub(super) fn nat_inbound<'frame>(
+        &mut self,
+        mut ipv4_repr: Ipv4Repr,
+        packet: &'frame [u8],
+    ) -> Option<Option<IpPacket<'frame>>> {
+        let ip_payload = &packet[packet.len() - ipv4_repr.payload_len..];
+        let (protocol, public_port) =
+            translated_port(ipv4_repr.next_header, ip_payload, Endpoint::Destination)?;
+        let (private_addr, private_port) =
+            self.nat
+                .as_mut()?
+                .inbound(protocol, public_port, self.now)?;
+
+        let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+            dst_addr: private_addr,
+            ..ipv4_repr
+        });
+        let egress = match self.forwarding_egress(&ip_repr) {
+            Ok(egress) => egress,
+            Err(reason) => {
+                return Some(self.forwarding_error(IpRepr::Ipv4(ipv4_repr), ip_payload, reason))
+            }
+        };
+        if egress == self.id {
+            net_debug!("NAT: not forwarding back to {}", private_addr);
+            return Some(None);
+        }
+
+        ipv4_repr.hop_limit -= 1;
+        if let Some(buffer) = self.enqueue_forwarded(egress, packet.len()) {
+            buffer.copy_from_slice(packet);
+            set_hop_limit(&IpRepr::Ipv4(ipv4_repr), buffer);
+            translate(
+                buffer,
+                protocol,
+                Endpoint::Destination,
+                private_addr,
+                private_port,
+            );
+        }
+        Some(None)
+    }
+
+    /// Translate the source of an IPv4 `packet` about to be forwarded through this
+    /// interface to our own address.
+    ///
+    /// Returns `false` if the packet can't be translated, and must be dropped.
+    pub(super) fn nat_outbound(&mut self, packet: &mut [u8]) -> bool {
+        let ipv4_packet = match Ipv4Packet::new_checked(&*packet) {
+            Ok(ipv4_packet) => ipv4_packet,
+            // Only IPv4 is translated.
+            Err(_) => return true,
+        };
+        if ipv4_packet.more_frags() || ipv4_packet.frag_offset() != 0 {
+            net_debug!("NAT: fragments are not supported");
+            return false;
+        }
+        let src_addr = ipv4_packet.src_addr();
+        let dst_addr = ipv4_packet.dst_addr();
+        let ip_payload = ipv4_packet.payload();
+
+        let (protocol, private_port) =
+            match translated_port(ipv4_packet.next_header(), ip_payload, Endpoint::Source) {
+                Some(port) => port,
+                None => {
+                    net_debug!(
+                        "NAT: can't translate {} packet from {}",
+                        ipv4_packet.next_header(),
+                        src_addr
+                    );
+                    return false;
+                }
+            };
+        let public_addr = match self.get_source_address_ipv4(dst_addr) {
+            Some(addr) => addr,
+            None => return false,
+        };
+        let now = self.now;
+        let public_port = match self
+            .nat
+            .as_mut()
+            .and_then(|nat| nat.outbound(protocol, src_addr, private_port, now))
+        {
+            Some(port) => port,
+            None => return false,
+        };
+
+        translate(packet, protocol, Endpoint::Source, public_addr, public_port);
+        true
+    }
+}
+
+/// Return the protocol and port, or ICMP echo identifier, of the `endpoint` of a packet.
+fn translated_port(
+    next_header: IpProtocol,
+    ip_payload: &[u8],
+    endpoint: Endpoint,
+) -> Option<(IpProtocol, u16)> {
+    let port = match next_header {
+        IpProtocol::Tcp => {
+            let tcp_packet = TcpPacket::new_checked(ip_payload).ok()?;
+            match endpoint {
+                Endpoint::Source => tcp_packet.src_port(),
+                Endpoint::Destination => tcp_packet.dst_port(),
+            }
+        }
+        IpProtocol::Udp => {
+            let udp_packet = UdpPacket::new_checked(ip_payload).ok()?;
+            match endpoint {
+                Endpoint::Source => udp_packet.src_port(),
+                Endpoint::Destination => udp_packet.dst_port(),
+            }
+        }
+        IpProtocol::Icmp => {
+            // Requests go out and replies come back.
+            let icmp_packet = Icmpv4Packet::new_checked(ip_payload).ok()?;
+            match (endpoint, icmp_packet.msg_type()) {
+                (Endpoint::Source, Icmpv4Message::EchoRequest)
+                | (Endpoint::Destination, Icmpv4Message::EchoReply) => icmp_packet.echo_ident(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some((next_header, port))
+}
+
+/// Rewrite the address and port of the `endpoint` of the IPv4 packet in `buffer`,
+/// updating the transport checksum.
+///
+/// The IPv4 header checksum is left to be filled when the packet is sent.
+fn translate(
+    buffer: &mut [u8],
+    protocol: IpProtocol,
+    endpoint: Endpoint,
+    addr: Ipv4Address,
+    port: u16,
+) {
+    let mut ipv4_packet = Ipv4Packet::new_unchecked(&mut *buffer);
+    let old_addr = match endpoint {
+        Endpoint::Source => {
+            let old_addr = ipv4_packet.src_addr();
+            ipv4_packet.set_src_addr(addr);
+            old_addr
+        }
+        Endpoint::Destination => {
+            let old_addr = ipv4_packet.dst_addr();
+            ipv4_packet.set_dst_addr(addr);
+            old_addr
+        }
+    };
+    let header_len = ipv4_packet.header_len() as usize;
+    let total_len = ipv4_packet.total_len() as usize;
+    let ip_payload = &mut buffer[header_len..total_len];
+
+    // The pseudo header only covers TCP and UDP.
+    let update = |checksum: u16, old_port: u16| {
+        let checksum = checksum::update(checksum, &old_port.to_be_bytes(), &port.to_be_bytes());
+        if protocol == IpProtocol::Icmp {
+            checksum
+        } else {
+            checksum::update(checksum, old_addr.as_bytes(), addr.as_bytes())
+        }
+    };
+
+    match protocol {
+        IpProtocol::Tcp => {
+            let mut tcp_packet = TcpPacket::new_unchecked(ip_payload);
+            let old_port = match endpoint {
+                Endpoint::Source => tcp_packet.src_port(),
+                Endpoint::Destination => tcp_packet.dst_port(),
+            };
+            match endpoint {
+                Endpoint::Source => tcp_packet.set_src_port(port),
+                Endpoint::Destination => tcp_packet.set_dst_port(port),
+            }
+            let checksum = update(tcp_packet.checksum(), old_port);
+            tcp_packet.set_checksum(checksum);
+        }
+        IpProtocol::Udp => {
+            let mut udp_packet = UdpPacket::new_unchecked(ip_payload);
+            let old_port = match endpoint {
+                Endpoint::Source => udp_packet.src_port(),
+                Endpoint::Destination => udp_packet.dst_port(),
+            };
+            match endpoint {
+                Endpoint::Source => udp_packet.set_src_port(port),
+                Endpoint::Destination => udp_packet.set_dst_port(port),
+            }
+            // A zero checksum means there's none over IPv4.
+            if udp_packet.checksum() != 0 {
+                let checksum = match update(udp_packet.checksum(), old_port) {
+                    0 => 0xffff,
+                    checksum => checksum,
+                };
+                udp_packet.set_checksum(checksum);
+            }
+        }
+        IpProtocol::Icmp => {
+            let mut icmp_packet = Icmpv4Packet::new_unchecked(ip_payload);
+            let old_port = icmp_packet.echo_ident();
+            icmp_packet.set_echo_ident(port);
+            let checksum = update(icmp_packet.checksum(), old_port);
+            icmp_packet.set_checksum(checksum);
+        }
+        _ => unreachable!(),
+    }
+}
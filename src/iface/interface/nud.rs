@@ -0,0 +1,125 @@
+// Neighbor Unreachability Detection, as described in RFC 4861 § 7.3, for both ARP
+// and NDISC. The neighbor cache keeps track of the reachability state of neighbors;
+// this sends the solicitations it asks for: multicast ones while resolving a neighbor,
+// and unicast probes for a stale neighbor that packets are sent to.
+
+#[cfg(feature = "proto-ipv6")]
+use super::IpPacket;
+use super::{Interface, InterfaceInner};
+use crate::phy::{Device, TxToken};
+use crate::wire::*;
+use crate::{Error, Result};
+
+impl<'a> InterfaceInner<'a> {
+    /// Send a solicitation for `dst_addr`, to `hardware_addr` if it's known and
+    /// multicast otherwise.
+    pub(super) fn solicit_neighbor<Tx>(
+        &mut self,
+        tx_token: Tx,
+        src_addr: &IpAddress,
+        dst_addr: &IpAddress,
+        hardware_addr: Option<HardwareAddress>,
+    ) -> Result<()>
+    where
+        Tx: TxToken,
+    {
+        match (*src_addr, *dst_addr) {
+            #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+            (IpAddress::Ipv4(src_addr), IpAddress::Ipv4(dst_addr)) => {
+                net_debug!("sending ARP request for {}", dst_addr);
+                let src_hardware_addr =
+                    if let Some(HardwareAddress::Ethernet(addr)) = self.hardware_addr {
+                        addr
+                    } else {
+                        return Err(Error::Malformed);
+                    };
+                // RFC 1122 § 2.3.2.1: probes are unicast to the cached hardware address.
+                let dst_hardware_addr = match hardware_addr {
+                    Some(HardwareAddress::Ethernet(addr)) => addr,
+                    _ => EthernetAddress::BROADCAST,
+                };
+
+                let arp_repr = ArpRepr::EthernetIpv4 {
+                    operation: ArpOperation::Request,
+                    source_hardware_addr: src_hardware_addr,
+                    source_protocol_addr: src_addr,
+                    target_hardware_addr: dst_hardware_addr,
+                    target_protocol_addr: dst_addr,
+                };
+
+                self.dispatch_ethernet(tx_token, arp_repr.buffer_len(), |mut frame| {
+                    frame.set_dst_addr(dst_hardware_addr);
+                    frame.set_ethertype(EthernetProtocol::Arp);
+
+                    arp_repr.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()))
+                })
+            }
+
+            #[cfg(feature = "proto-ipv6")]
+            (IpAddress::Ipv6(src_addr), IpAddress::Ipv6(dst_addr)) => {
+                net_debug!("sending Neighbor Solicitation for {}", dst_addr);
+
+                let solicit = Icmpv6Repr::Ndisc(NdiscRepr::NeighborSolicit {
+                    target_addr: dst_addr,
+                    lladdr: Some(self.hardware_addr.unwrap().into()),
+                });
+
+                // Probes are sent to the neighbor itself, whose hardware address is
+                // in the cache.
+                let packet = IpPacket::Icmpv6((
+                    Ipv6Repr {
+                        src_addr,
+                        dst_addr: match hardware_addr {
+                            Some(_) => dst_addr,
+                            None => dst_addr.solicited_node(),
+                        },
+                        next_header: IpProtocol::Icmpv6,
+                        payload_len: solicit.buffer_len(),
+                        hop_limit: 0xff,
+                    },
+                    solicit,
+                ));
+
+                self.dispatch_ip(tx_token, packet, None)
+            }
+
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a> Interface<'a> {
+    /// Send the next solicitation the neighbor cache asks for, if one is due.
+    pub(super) fn neighbor_egress<D>(&mut self, device: &mut D) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        let now = self.inner.now;
+        let solicit = match self
+            .inner
+            .neighbor_cache
+            .as_mut()
+            .and_then(|cache| cache.poll(now))
+        {
+            Some(solicit) => solicit,
+            None => return Ok(false),
+        };
+        let src_addr = match self.inner.get_source_address(solicit.protocol_addr) {
+            Some(src_addr) if !src_addr.is_unspecified() => src_addr,
+            _ => {
+                net_debug!("no source address to solicit {}", solicit.protocol_addr);
+                return Ok(false);
+            }
+        };
+
+        let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+        self.inner.solicit_neighbor(
+            tx_token,
+            &src_addr,
+            &solicit.protocol_addr,
+            solicit.hardware_addr,
+        )?;
+        Ok(true)
+    }
+}
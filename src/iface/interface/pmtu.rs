@@ -0,0 +1,127 @@
+// Path MTU discovery, as described in RFC 1191 for IPv4 and RFC 8201 for IPv6. IPv4
+// packets are sent with the Don't Fragment flag, so a router that can't forward one
+// answers with an ICMP "Fragmentation Needed" error, and IPv6 routers with a "Packet
+// Too Big" one, giving the MTU of their next hop. That MTU is cached for the
+// destination, lowering the TCP MSS and the size of IPv4 fragments sent to it, until
+// it ages back to the MTU of the link.
+
+use heapless::Vec;
+
+use super::InterfaceInner;
+use crate::time::{Duration, Instant};
+use crate::wire::*;
+
+/// Maximum number of destinations whose path MTU is cached. When it's full, the entry
+/// closest to aging is replaced.
+pub const PMTU_MAX_DESTINATION_COUNT: usize = 8;
+
+// RFC 1191 § 6.3 and RFC 8201 § 4: a path MTU estimate is given up after 10 minutes.
+const PMTU_TIMEOUT: Duration = Duration::from_secs(600);
+
+// RFC 1191 § 7: the MTU plateaus, used when a router doesn't report its next-hop MTU.
+#[cfg(feature = "proto-ipv4")]
+const PLATEAUS: [usize; 10] = [32000, 17914, 8166, 4352, 2002, 1492, 1006, 508, 296, 68];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Entry {
+    addr: IpAddress,
+    mtu: usize,
+    expires_at: Instant,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) struct Cache {
+    entries: Vec<Entry, PMTU_MAX_DESTINATION_COUNT>,
+}
+
+impl Cache {
+    pub(super) fn new() -> Self {
+        Cache {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Return the path MTU to `addr`, if one was learned and hasn't aged yet.
+    fn get(&self, addr: &IpAddress, now: Instant) -> Option<usize> {
+        self.entries
+            .iter()
+            .find(|entry| entry.addr == *addr && now < entry.expires_at)
+            .map(|entry| entry.mtu)
+    }
+
+    fn insert(&mut self, addr: IpAddress, mtu: usize, now: Instant) {
+        let entry = Entry {
+            addr,
+            mtu,
+            expires_at: now + PMTU_TIMEOUT,
+        };
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.addr == addr)
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .position(|entry| now >= entry.expires_at)
+            });
+        match index {
+            Some(index) => self.entries[index] = entry,
+            None => {
+                if let Err(entry) = self.entries.push(entry) {
+                    let index = (0..self.entries.len())
+                        .min_by_key(|&index| self.entries[index].expires_at)
+                        .unwrap();
+                    self.entries[index] = entry;
+                }
+            }
+        }
+    }
+}
+
+/// Estimate the MTU of a path that a packet of `total_len` octets didn't fit through,
+/// when the router didn't report it.
+#[cfg(feature = "proto-ipv4")]
+pub(super) fn plateau_mtu(total_len: usize) -> usize {
+    PLATEAUS
+        .iter()
+        .copied()
+        .find(|&mtu| mtu < total_len)
+        .unwrap_or(PLATEAUS[PLATEAUS.len() - 1])
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Return the MTU of the path to `dst_addr`: the one learned from ICMP errors, if
+    /// any, and the IP MTU of the link otherwise.
+    pub(crate) fn path_mtu(&self, dst_addr: &IpAddress) -> usize {
+        let mtu = self.ip_mtu();
+        self.pmtu
+            .get(dst_addr, self.now)
+            .map_or(mtu, |path_mtu| path_mtu.min(mtu))
+    }
+
+    /// Lower the path MTU to `dst_addr` to `mtu`, as reported by an ICMP error about a
+    /// packet sent from `src_addr`.
+    pub(super) fn pmtu_update(&mut self, src_addr: IpAddress, dst_addr: IpAddress, mtu: usize) {
+        if !self.has_ip_addr(src_addr) {
+            return;
+        }
+
+        // Don't let a forged error make us send tiny packets.
+        let min_mtu = match dst_addr {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddress::Ipv4(_) => IPV4_MIN_MTU,
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(_) => IPV6_MIN_MTU,
+        };
+        let mtu = mtu.max(min_mtu);
+        if mtu >= self.path_mtu(&dst_addr) {
+            return;
+        }
+
+        net_debug!("pmtu: path MTU to {} is {}", dst_addr, mtu);
+        let now = self.now;
+        self.pmtu.insert(dst_addr, mtu, now);
+    }
+}
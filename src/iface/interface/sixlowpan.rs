@@ -113,6 +113,14 @@ impl<'a> InterfaceInner<'a> {
             payload_len: 40,
         };
 
+        #[cfg(feature = "iface-forwarding")]
+        if self.is_forwarding()
+            && ipv6_repr.dst_addr.is_unicast()
+            && !self.has_ip_addr(ipv6_repr.dst_addr)
+        {
+            return self.forward_sixlowpan(ipv6_repr, iphc_repr.next_header, payload);
+        }
+
         match iphc_repr.next_header {
             SixlowpanNextHeader::Compressed => {
                 match check!(SixlowpanNhcPacket::dispatch(payload)) {
@@ -333,6 +341,8 @@ impl<'a> InterfaceInner<'a> {
                 IpPacket::Tcp(_) => SixlowpanNextHeader::Uncompressed(IpProtocol::Tcp),
                 #[cfg(feature = "socket-udp")]
                 IpPacket::Udp(_) => SixlowpanNextHeader::Compressed,
+                #[cfg(feature = "iface-forwarding")]
+                IpPacket::Forward(_) => SixlowpanNextHeader::Uncompressed(ip_repr.next_header()),
                 #[allow(unreachable_patterns)]
                 _ => return Err(Error::Unrecognized),
             },
@@ -366,6 +376,10 @@ impl<'a> InterfaceInner<'a> {
             IpPacket::Icmpv6((_, icmp_repr)) => {
                 total_size += icmp_repr.buffer_len();
             }
+            #[cfg(feature = "iface-forwarding")]
+            IpPacket::Forward((_, raw_packet)) => {
+                total_size += raw_packet.len() - ip_repr.header_len();
+            }
             _ => return Err(Error::Unrecognized),
         }
 
@@ -447,6 +461,11 @@ impl<'a> InterfaceInner<'a> {
                             &self.caps.checksum,
                         );
                     }
+                    #[cfg(feature = "iface-forwarding")]
+                    IpPacket::Forward((_, raw_packet)) => {
+                        let payload = &raw_packet[ip_repr.header_len()..];
+                        b[..payload.len()].copy_from_slice(payload);
+                    }
                     _ => return Err(Error::Unrecognized),
                 }
 
@@ -568,6 +587,11 @@ impl<'a> InterfaceInner<'a> {
                             &self.caps.checksum,
                         );
                     }
+                    #[cfg(feature = "iface-forwarding")]
+                    IpPacket::Forward((_, raw_packet)) => {
+                        let payload = &raw_packet[ip_repr.header_len()..];
+                        tx_buf[..payload.len()].copy_from_slice(payload);
+                    }
                     _ => return Err(Error::Unrecognized),
                 }
                 Ok(())
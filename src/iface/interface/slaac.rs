@@ -0,0 +1,486 @@
+// IPv6 Stateless Address Autoconfiguration, as described in RFC 4862. Router Solicitations
+// follow RFC 4861 § 6.3.7, and interface identifiers are formed from the hardware address
+// as in RFC 2464 § 4 for Ethernet and RFC 4944 § 6 for IEEE 802.15.4.
+
+use heapless::Vec;
+use managed::ManagedSlice;
+
+use super::{Interface, InterfaceInner, IpPacket};
+use crate::iface::{Route, Routes};
+use crate::phy::Device;
+use crate::time::{Duration, Instant};
+use crate::wire::*;
+use crate::{Error, Result};
+
+/// Maximum number of addresses SLAAC keeps configured at the same time.
+pub const SLAAC_MAX_ADDRESS_COUNT: usize = 4;
+
+// Constants from RFC 4861 § 10.
+const MAX_RTR_SOLICITATION_DELAY: Duration = Duration::from_secs(1);
+const RTR_SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+const MAX_RTR_SOLICITATIONS: u8 = 3;
+
+/// Router Advertisements can't shorten the valid lifetime of an address below this,
+/// see RFC 4862 § 5.5.3 e).
+const MIN_VALID_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Lifetime meaning "forever" in Prefix Information options.
+const INFINITE_LIFETIME: Duration = Duration::from_secs(0xffff_ffff);
+
+/// An address configured from a prefix advertised by a router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Address {
+    /// The address, along with the length of the advertised prefix.
+    pub cidr: Ipv6Cidr,
+    /// When the address becomes deprecated. `None` means "never".
+    pub preferred_until: Option<Instant>,
+    /// When the address is removed from the interface. `None` means "never".
+    pub expires_at: Option<Instant>,
+}
+
+/// IPv6 configuration learned from Router Advertisements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Addresses formed from the advertised prefixes.
+    pub addresses: Vec<Address, SLAAC_MAX_ADDRESS_COUNT>,
+    /// Default router, installed as the default IPv6 route.
+    pub router: Option<Ipv6Address>,
+}
+
+/// A change in the configuration learned by SLAAC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(clippy::large_enum_variant)]
+pub enum Event {
+    /// Configuration has been lost (every address and the default router have expired).
+    Deconfigured,
+    /// Configuration has been newly acquired, or modified.
+    Configured(Config),
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum SolicitState {
+    /// Autoconfiguration hasn't started yet; it starts on the next poll.
+    Start,
+    /// Sending Router Solicitations until a router answers.
+    Soliciting { retry_at: Instant, count: u8 },
+    /// A router has answered, or we gave up soliciting.
+    Done,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) struct State {
+    solicit: SolicitState,
+    pub(super) addresses: Vec<Address, SLAAC_MAX_ADDRESS_COUNT>,
+    /// Default router, and when it stops being one.
+    router: Option<(Ipv6Address, Instant)>,
+    config_changed: bool,
+}
+
+impl State {
+    pub(super) fn new() -> Self {
+        State {
+            solicit: SolicitState::Start,
+            addresses: Vec::new(),
+            router: None,
+            config_changed: false,
+        }
+    }
+
+    /// Return the next time SLAAC needs to be polled, either to send a Router Solicitation
+    /// or to remove an expired address or router.
+    pub(super) fn poll_at(&self) -> Option<Instant> {
+        let solicit_at = match self.solicit {
+            SolicitState::Start => Some(Instant::ZERO),
+            SolicitState::Soliciting { retry_at, .. } => Some(retry_at),
+            SolicitState::Done => None,
+        };
+        let router_expires_at = self.router.map(|(_, expires_at)| expires_at);
+
+        self.addresses
+            .iter()
+            .filter_map(|address| address.expires_at)
+            .chain(solicit_at)
+            .chain(router_expires_at)
+            .min()
+    }
+
+    /// Return whether `addr` was configured by SLAAC and has been deprecated.
+    pub(super) fn is_deprecated(&self, addr: Ipv6Address, now: Instant) -> bool {
+        self.addresses.iter().any(|address| {
+            address.cidr.address() == addr && address.preferred_until.map_or(false, |t| now >= t)
+        })
+    }
+
+    fn update_router(
+        &mut self,
+        routes: &mut Routes,
+        router: Ipv6Address,
+        lifetime: Duration,
+        now: Instant,
+    ) {
+        let default_cidr = IpCidr::new(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED), 0);
+        let is_current = self.router.map(|(addr, _)| addr) == Some(router);
+
+        if lifetime == Duration::ZERO {
+            // The router is no longer a default router.
+            if is_current {
+                net_debug!("slaac: router {} withdrawn", router);
+                remove_default_route(routes, router);
+                self.router = None;
+                self.config_changed = true;
+            }
+            return;
+        }
+
+        let expires_at = now + lifetime;
+        let route = Route {
+            via_router: router.into(),
+            preferred_until: None,
+            expires_at: Some(expires_at),
+            interface: None,
+        };
+        let mut installed = false;
+        routes.update(|storage| installed = storage.insert(default_cidr, route).is_ok());
+        if !installed {
+            net_debug!("slaac: no space in the routing table for router {}", router);
+            return;
+        }
+
+        if !is_current {
+            net_debug!("slaac: default router {}", router);
+            self.config_changed = true;
+        }
+        self.router = Some((router, expires_at));
+    }
+
+    fn update_prefix(
+        &mut self,
+        ip_addrs: &mut ManagedSlice<IpCidr>,
+        info: NdiscPrefixInformation,
+        interface_id: [u8; 8],
+        now: Instant,
+    ) {
+        // RFC 4862 § 5.5.3 a) to c).
+        if !info.flags.contains(NdiscPrefixInfoFlags::ADDRCONF)
+            || info.prefix.is_link_local()
+            || info.preferred_lifetime > info.valid_lifetime
+        {
+            return;
+        }
+        // RFC 4862 § 5.5.3 d): the prefix and the interface identifier must add up to
+        // exactly 128 bits.
+        if info.prefix_len != 64 {
+            net_debug!(
+                "slaac: cannot form an address from prefix {}/{}",
+                info.prefix,
+                info.prefix_len
+            );
+            return;
+        }
+
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&info.prefix.as_bytes()[..8]);
+        bytes[8..].copy_from_slice(&interface_id);
+        let cidr = Ipv6Cidr::new(Ipv6Address::from_bytes(&bytes), info.prefix_len);
+        let preferred_until = deadline(now, info.preferred_lifetime);
+
+        if let Some(address) = self
+            .addresses
+            .iter_mut()
+            .find(|address| address.cidr == cidr)
+        {
+            // RFC 4862 § 5.5.3 e): refresh the lifetimes, but don't let a single (possibly
+            // spoofed) advertisement cut the valid lifetime below two hours.
+            let remaining = address
+                .expires_at
+                .map(|t| if t > now { t - now } else { Duration::ZERO });
+            let expires_at = if info.valid_lifetime > MIN_VALID_LIFETIME
+                || remaining.map_or(false, |remaining| info.valid_lifetime > remaining)
+            {
+                deadline(now, info.valid_lifetime)
+            } else if remaining.map_or(true, |remaining| remaining > MIN_VALID_LIFETIME) {
+                Some(now + MIN_VALID_LIFETIME)
+            } else {
+                address.expires_at
+            };
+
+            if address.preferred_until != preferred_until || address.expires_at != expires_at {
+                address.preferred_until = preferred_until;
+                address.expires_at = expires_at;
+                self.config_changed = true;
+            }
+            return;
+        }
+
+        if info.valid_lifetime == Duration::ZERO {
+            return;
+        }
+        if self.addresses.is_full() {
+            net_debug!("slaac: too many addresses, ignoring {}", cidr);
+            return;
+        }
+        if !add_ip_addr(ip_addrs, cidr) {
+            net_debug!("slaac: no free slot in ip_addrs for {}", cidr);
+            return;
+        }
+
+        net_debug!("slaac: configured {}", cidr);
+        let _ = self.addresses.push(Address {
+            cidr,
+            preferred_until,
+            expires_at: deadline(now, info.valid_lifetime),
+        });
+        self.config_changed = true;
+    }
+
+    /// Remove the addresses and the router whose lifetime has run out.
+    fn expire(&mut self, ip_addrs: &mut ManagedSlice<IpCidr>, routes: &mut Routes, now: Instant) {
+        let mut i = 0;
+        while i < self.addresses.len() {
+            let address = self.addresses[i];
+            if address.expires_at.map_or(false, |t| now >= t) {
+                net_debug!("slaac: address {} expired", address.cidr);
+                remove_ip_addr(ip_addrs, address.cidr.address());
+                self.addresses.remove(i);
+                self.config_changed = true;
+            } else {
+                i += 1;
+            }
+        }
+
+        if let Some((router, expires_at)) = self.router {
+            if now >= expires_at {
+                net_debug!("slaac: router {} expired", router);
+                remove_default_route(routes, router);
+                self.router = None;
+                self.config_changed = true;
+            }
+        }
+    }
+
+    fn poll(&mut self) -> Option<Event> {
+        if !self.config_changed {
+            None
+        } else if self.addresses.is_empty() && self.router.is_none() {
+            self.config_changed = false;
+            Some(Event::Deconfigured)
+        } else {
+            self.config_changed = false;
+            Some(Event::Configured(Config {
+                addresses: self.addresses.clone(),
+                router: self.router.map(|(router, _)| router),
+            }))
+        }
+    }
+}
+
+/// Return the time a lifetime from a Prefix Information option runs out at.
+fn deadline(now: Instant, lifetime: Duration) -> Option<Instant> {
+    if lifetime == INFINITE_LIFETIME {
+        None
+    } else {
+        Some(now + lifetime)
+    }
+}
+
+/// Put `cidr` in the first unspecified IPv6 slot of `ip_addrs`.
+fn add_ip_addr(ip_addrs: &mut ManagedSlice<IpCidr>, cidr: Ipv6Cidr) -> bool {
+    let slot = ip_addrs.iter_mut().find(|slot| match slot {
+        IpCidr::Ipv6(slot) => slot.address().is_unspecified(),
+        #[allow(unreachable_patterns)]
+        _ => false,
+    });
+    match slot {
+        Some(slot) => {
+            *slot = IpCidr::Ipv6(cidr);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Free the slot of `addr` in `ip_addrs`, putting back an unspecified address.
+fn remove_ip_addr(ip_addrs: &mut ManagedSlice<IpCidr>, addr: Ipv6Address) {
+    for slot in ip_addrs.iter_mut() {
+        if slot.address() == IpAddress::Ipv6(addr) {
+            *slot = IpCidr::Ipv6(Ipv6Cidr::new(Ipv6Address::UNSPECIFIED, 0));
+        }
+    }
+}
+
+/// Remove the default IPv6 route, unless it was replaced by one via another router.
+fn remove_default_route(routes: &mut Routes, router: Ipv6Address) {
+    let default_cidr = IpCidr::new(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED), 0);
+    routes.update(|storage| {
+        if storage.get(&default_cidr).map(|route| route.via_router) == Some(router.into()) {
+            storage.remove(&default_cidr);
+        }
+    });
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Return the interface identifier addresses are formed with, derived from the
+    /// hardware address.
+    fn slaac_interface_id(&self) -> Option<[u8; 8]> {
+        match self.hardware_addr? {
+            #[cfg(feature = "medium-ethernet")]
+            HardwareAddress::Ethernet(addr) => {
+                let b = addr.as_bytes();
+                Some([b[0] ^ 0x02, b[1], b[2], 0xff, 0xfe, b[3], b[4], b[5]])
+            }
+            #[cfg(feature = "medium-ieee802154")]
+            HardwareAddress::Ieee802154(addr) => match addr {
+                Ieee802154Address::Extended(_) => addr.as_eui_64(),
+                Ieee802154Address::Short(b) => Some([0, 0, 0, 0xff, 0xfe, 0, b[0], b[1]]),
+                Ieee802154Address::Absent => None,
+            },
+        }
+    }
+
+    /// Return the first link-local IPv6 address of the interface.
+    fn link_local_ipv6_addr(&self) -> Option<Ipv6Address> {
+        self.ip_addrs.iter().find_map(|cidr| match cidr {
+            IpCidr::Ipv6(cidr) if cidr.address().is_link_local() => Some(cidr.address()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        })
+    }
+
+    /// Process a Router Advertisement, learning the default router and the addresses
+    /// formed from the advertised prefix.
+    pub(super) fn slaac_process_router_advert(
+        &mut self,
+        ip_repr: Ipv6Repr,
+        router_lifetime: Duration,
+        prefix_info: Option<NdiscPrefixInformation>,
+    ) {
+        let interface_id = self.slaac_interface_id();
+        let state = match self.slaac.as_mut() {
+            Some(state) => state,
+            None => return,
+        };
+
+        // RFC 4861 § 6.1.2: routers advertise from their link-local address.
+        if !ip_repr.src_addr.is_link_local() {
+            net_debug!(
+                "slaac: ignoring router advertisement from {}",
+                ip_repr.src_addr
+            );
+            return;
+        }
+
+        state.solicit = SolicitState::Done;
+        state.update_router(
+            &mut self.routes,
+            ip_repr.src_addr,
+            router_lifetime,
+            self.now,
+        );
+
+        if let (Some(info), Some(interface_id)) = (prefix_info, interface_id) {
+            state.update_prefix(&mut self.ip_addrs, info, interface_id, self.now);
+        }
+        self.dad_sync();
+    }
+
+    fn router_solicit_packet(&self) -> IpPacket<'static> {
+        // RFC 4861 § 4.1: the source link-layer address option must not be included when
+        // soliciting from the unspecified address.
+        // A link-local address that's still tentative can't be used yet either.
+        let src_addr = self
+            .link_local_ipv6_addr()
+            .filter(|addr| self.is_usable_ipv6_addr(*addr))
+            .unwrap_or(Ipv6Address::UNSPECIFIED);
+        let lladdr = if src_addr.is_unspecified() {
+            None
+        } else {
+            self.hardware_addr.map(|addr| addr.into())
+        };
+
+        let solicit = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit { lladdr });
+        IpPacket::Icmpv6((
+            Ipv6Repr {
+                src_addr,
+                dst_addr: Ipv6Address::LINK_LOCAL_ALL_ROUTERS,
+                next_header: IpProtocol::Icmpv6,
+                payload_len: solicit.buffer_len(),
+                hop_limit: 0xff,
+            },
+            solicit,
+        ))
+    }
+}
+
+impl<'a> Interface<'a> {
+    /// Get the next SLAAC event, if autoconfiguration is enabled.
+    ///
+    /// Addresses and the default route are applied to the interface by SLAAC itself;
+    /// the events tell the application when they change, for example to log them or
+    /// to restart connections bound to an expired address.
+    pub fn poll_slaac(&mut self) -> Option<Event> {
+        self.inner.slaac.as_mut().and_then(|state| state.poll())
+    }
+
+    /// Depending on the SLAAC state, form the link-local address, send Router
+    /// Solicitations, and remove expired addresses and routes.
+    pub(super) fn slaac_egress<D>(&mut self, device: &mut D) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        let now = self.inner.now;
+        let state = match self.inner.slaac.as_mut() {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+        state.expire(&mut self.inner.ip_addrs, &mut self.inner.routes, now);
+
+        match state.solicit {
+            SolicitState::Start => {
+                // RFC 4862 § 5.3: form the link-local address, unless one has been
+                // assigned already.
+                if self.inner.link_local_ipv6_addr().is_none() {
+                    if let Some(interface_id) = self.inner.slaac_interface_id() {
+                        let mut bytes = [0; 16];
+                        bytes[..2].copy_from_slice(&[0xfe, 0x80]);
+                        bytes[8..].copy_from_slice(&interface_id);
+                        let cidr = Ipv6Cidr::new(Ipv6Address::from_bytes(&bytes), 64);
+                        if !add_ip_addr(&mut self.inner.ip_addrs, cidr) {
+                            net_debug!("slaac: no free slot in ip_addrs for {}", cidr);
+                        }
+                        self.inner.dad_sync();
+                    }
+                }
+
+                // RFC 4861 § 6.3.7: delay the first solicitation by a random amount.
+                let delay =
+                    self.inner.rand.rand_u32() % (MAX_RTR_SOLICITATION_DELAY.total_millis() as u32);
+                self.inner.slaac.as_mut().unwrap().solicit = SolicitState::Soliciting {
+                    retry_at: now + Duration::from_millis(delay as u64),
+                    count: 0,
+                };
+                Ok(false)
+            }
+            SolicitState::Soliciting { retry_at, count } if now >= retry_at => {
+                let pkt = self.inner.router_solicit_packet();
+                let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+                self.inner.dispatch_ip(tx_token, pkt, None)?;
+
+                self.inner.slaac.as_mut().unwrap().solicit = if count + 1 < MAX_RTR_SOLICITATIONS {
+                    SolicitState::Soliciting {
+                        retry_at: now + RTR_SOLICITATION_INTERVAL,
+                        count: count + 1,
+                    }
+                } else {
+                    SolicitState::Done
+                };
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
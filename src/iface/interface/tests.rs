@@ -1,5 +1,11 @@
+#[allow(unused_imports)] // unused depending on which features are enabled
 use std::collections::BTreeMap;
-#[cfg(feature = "proto-igmp")]
+#[cfg(any(
+    feature = "proto-igmp",
+    feature = "medium-ip",
+    all(feature = "medium-ethernet", feature = "proto-ipv4"),
+    all(feature = "medium-ethernet", feature = "proto-ipv6")
+))]
 use std::vec::Vec;
 
 use super::*;
@@ -8,7 +14,12 @@ use crate::iface::Interface;
 #[cfg(feature = "medium-ethernet")]
 use crate::iface::NeighborCache;
 use crate::phy::{ChecksumCapabilities, Loopback};
-#[cfg(feature = "proto-igmp")]
+#[cfg(any(
+    feature = "proto-igmp",
+    feature = "medium-ip",
+    all(feature = "medium-ethernet", feature = "proto-ipv4"),
+    all(feature = "medium-ethernet", feature = "proto-ipv6")
+))]
 use crate::time::Instant;
 use crate::{Error, Result};
 
@@ -49,6 +60,8 @@ fn create_ip<'a>() -> (Interface<'a>, SocketSet<'a>, Loopback) {
 
     #[cfg(feature = "proto-igmp")]
     let iface_builder = iface_builder.ipv4_multicast_groups(BTreeMap::new());
+    #[cfg(feature = "proto-mld")]
+    let iface_builder = iface_builder.ipv6_multicast_groups(BTreeMap::new());
     let iface = iface_builder.finalize(&mut device);
 
     (iface, SocketSet::new(vec![]), device)
@@ -84,12 +97,19 @@ fn create_ethernet<'a>() -> (Interface<'a>, SocketSet<'a>, Loopback) {
 
     #[cfg(feature = "proto-igmp")]
     let iface_builder = iface_builder.ipv4_multicast_groups(BTreeMap::new());
+    #[cfg(feature = "proto-mld")]
+    let iface_builder = iface_builder.ipv6_multicast_groups(BTreeMap::new());
     let iface = iface_builder.finalize(&mut device);
 
     (iface, SocketSet::new(vec![]), device)
 }
 
-#[cfg(feature = "proto-igmp")]
+#[cfg(any(
+    feature = "proto-igmp",
+    feature = "medium-ip",
+    all(feature = "medium-ethernet", feature = "proto-ipv4"),
+    all(feature = "medium-ethernet", feature = "proto-ipv6")
+))]
 fn recv_all(device: &mut Loopback, timestamp: Instant) -> Vec<Vec<u8>> {
     let mut pkts = Vec::new();
     while let Some((rx, _tx)) = device.receive() {
@@ -751,6 +771,56 @@ fn test_handle_valid_arp_request() {
     );
 }
 
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_handle_arp_probe() {
+    let (mut iface, mut sockets, _device) = create_ethernet();
+
+    let local_ip_addr = Ipv4Address([0x7f, 0x00, 0x00, 0x01]);
+    let local_hw_addr = EthernetAddress([0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    let remote_hw_addr = EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]);
+
+    let probe = |target_protocol_addr| {
+        let repr = ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Request,
+            source_hardware_addr: remote_hw_addr,
+            source_protocol_addr: Ipv4Address::UNSPECIFIED,
+            target_hardware_addr: EthernetAddress::default(),
+            target_protocol_addr,
+        };
+        let mut frame = EthernetFrame::new_unchecked(vec![0u8; 42]);
+        frame.set_dst_addr(EthernetAddress::BROADCAST);
+        frame.set_src_addr(remote_hw_addr);
+        frame.set_ethertype(EthernetProtocol::Arp);
+        repr.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+        frame.into_inner()
+    };
+
+    // A probe for our address is answered, so the prober sees the conflict.
+    let frame = probe(local_ip_addr);
+    assert_eq!(
+        iface
+            .inner
+            .process_ethernet(&mut sockets, &frame, &mut iface.fragments),
+        Some(EthernetPacket::Arp(ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Reply,
+            source_hardware_addr: local_hw_addr,
+            source_protocol_addr: local_ip_addr,
+            target_hardware_addr: remote_hw_addr,
+            target_protocol_addr: Ipv4Address::UNSPECIFIED
+        }))
+    );
+
+    // Probes for other addresses aren't.
+    let frame = probe(Ipv4Address([0x7f, 0x00, 0x00, 0x05]));
+    assert_eq!(
+        iface
+            .inner
+            .process_ethernet(&mut sockets, &frame, &mut iface.fragments),
+        None
+    );
+}
+
 #[test]
 #[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
 fn test_handle_valid_ndisc_request() {
@@ -1098,10 +1168,12 @@ fn test_icmpv6_nxthdr_unknown() {
 #[test]
 #[cfg(feature = "proto-igmp")]
 fn test_handle_igmp() {
-    fn recv_igmp(device: &mut Loopback, timestamp: Instant) -> Vec<(Ipv4Repr, IgmpRepr)> {
-        let caps = device.capabilities();
+    fn recv_igmp<'a>(
+        caps: &DeviceCapabilities,
+        frames: &'a [Vec<u8>],
+    ) -> Vec<(Ipv4Repr, IgmpRepr<'a>)> {
         let checksum_caps = &caps.checksum;
-        recv_all(device, timestamp)
+        frames
             .iter()
             .filter_map(|frame| {
                 let ipv4_packet = match caps.medium {
@@ -1139,7 +1211,8 @@ fn test_handle_igmp() {
             .unwrap();
     }
 
-    let reports = recv_igmp(&mut device, timestamp);
+    let frames = recv_all(&mut device, timestamp);
+    let reports = recv_igmp(&device.capabilities(), &frames);
     assert_eq!(reports.len(), 2);
     for (i, group_addr) in groups.iter().enumerate() {
         assert_eq!(reports[i].0.next_header, IpProtocol::Igmp);
@@ -1185,7 +1258,8 @@ fn test_handle_igmp() {
             .unwrap();
     }
 
-    let leaves = recv_igmp(&mut device, timestamp);
+    let frames = recv_all(&mut device, timestamp);
+    let leaves = recv_igmp(&device.capabilities(), &frames);
     assert_eq!(leaves.len(), 2);
     for (i, group_addr) in groups.iter().cloned().enumerate() {
         assert_eq!(leaves[i].0.next_header, IpProtocol::Igmp);
@@ -1194,6 +1268,171 @@ fn test_handle_igmp() {
     }
 }
 
+#[cfg(all(feature = "medium-ethernet", feature = "proto-igmp"))]
+type IgmpV3Record = (IgmpGroupRecordType, Ipv4Address, Vec<Ipv4Address>);
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-igmp"))]
+fn recv_igmp_v3(device: &mut Loopback, timestamp: Instant) -> Vec<(Ipv4Repr, Vec<IgmpV3Record>)> {
+    recv_all(device, timestamp)
+        .iter()
+        .filter_map(|frame| {
+            let eth_frame = EthernetFrame::new_checked(&frame[..]).ok()?;
+            let ipv4_packet = Ipv4Packet::new_checked(eth_frame.payload()).ok()?;
+            let ipv4_repr = Ipv4Repr::parse(&ipv4_packet, &ChecksumCapabilities::default()).ok()?;
+            let igmp_packet = IgmpPacket::new_checked(ipv4_packet.payload()).ok()?;
+            let mut data = match IgmpRepr::parse(&igmp_packet).ok()? {
+                IgmpRepr::MembershipReportV3 { data, .. } => data,
+                _ => return None,
+            };
+
+            let mut records = Vec::new();
+            for _ in 0..igmp_packet.num_group_records() {
+                let record = IgmpGroupRecord::new_checked(data).unwrap();
+                let sources = (0..record.num_srcs() as usize)
+                    .map(|index| record.source_addr(index))
+                    .collect();
+                records.push((record.record_type(), record.group_addr(), sources));
+                data = &data[record.buffer_len()..];
+            }
+            Some((ipv4_repr, records))
+        })
+        .collect()
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-igmp"))]
+fn test_igmp_source_filter() {
+    let (mut iface, _sockets, mut device) = create_ethernet();
+    let group = Ipv4Address::new(232, 1, 1, 1);
+    let sources = [
+        Ipv4Address::new(192, 168, 1, 1),
+        Ipv4Address::new(192, 168, 1, 2),
+    ];
+
+    // Joining with sources is reported with IGMPv3.
+    let timestamp = Instant::from_millis(0);
+    assert_eq!(
+        iface.join_multicast_group_with_sources(
+            &mut device,
+            group,
+            IgmpFilterMode::Include,
+            &sources[..1],
+            timestamp
+        ),
+        Ok(true)
+    );
+    assert!(iface.has_multicast_group(group));
+    assert!(iface.inner.igmp_accepts_source(group, sources[0]));
+    assert!(!iface.inner.igmp_accepts_source(group, sources[1]));
+    let reports = recv_igmp_v3(&mut device, timestamp);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(
+        reports[0].0.dst_addr,
+        Ipv4Address::MULTICAST_ALL_IGMPV3_ROUTERS
+    );
+    assert_eq!(
+        reports[0].1,
+        vec![(
+            IgmpGroupRecordType::AllowNewSources,
+            group,
+            vec![sources[0]]
+        )]
+    );
+
+    // Only the change of sources is reported.
+    assert_eq!(
+        iface.join_multicast_group_with_sources(
+            &mut device,
+            group,
+            IgmpFilterMode::Include,
+            &sources[1..],
+            timestamp
+        ),
+        Ok(true)
+    );
+    assert_eq!(
+        recv_igmp_v3(&mut device, timestamp)[0].1,
+        vec![
+            (
+                IgmpGroupRecordType::AllowNewSources,
+                group,
+                vec![sources[1]]
+            ),
+            (
+                IgmpGroupRecordType::BlockOldSources,
+                group,
+                vec![sources[0]]
+            ),
+        ]
+    );
+    assert_eq!(
+        iface.join_multicast_group_with_sources(
+            &mut device,
+            group,
+            IgmpFilterMode::Include,
+            &sources[1..],
+            timestamp
+        ),
+        Ok(false)
+    );
+
+    // Changing the filter mode.
+    assert_eq!(
+        iface.join_multicast_group_with_sources(
+            &mut device,
+            group,
+            IgmpFilterMode::Exclude,
+            &sources[..1],
+            timestamp
+        ),
+        Ok(true)
+    );
+    assert_eq!(
+        recv_igmp_v3(&mut device, timestamp)[0].1,
+        vec![(
+            IgmpGroupRecordType::ChangeToExclude,
+            group,
+            vec![sources[0]]
+        )]
+    );
+    assert!(!iface.inner.igmp_accepts_source(group, sources[0]));
+    assert!(iface.inner.igmp_accepts_source(group, sources[1]));
+
+    // IGMPv3 queries are answered with the current state of the filter.
+    let query = IgmpRepr::MembershipQuery {
+        max_resp_time: Duration::from_secs(1),
+        group_addr: Ipv4Address::UNSPECIFIED,
+        version: IgmpVersion::Version3,
+    };
+    let mut bytes = vec![0; query.buffer_len()];
+    query.emit(&mut IgmpPacket::new_unchecked(&mut bytes[..]));
+    let ipv4_repr = Ipv4Repr {
+        src_addr: Ipv4Address::new(127, 0, 0, 100),
+        dst_addr: Ipv4Address::MULTICAST_ALL_SYSTEMS,
+        next_header: IpProtocol::Igmp,
+        payload_len: bytes.len(),
+        hop_limit: 1,
+    };
+    assert_eq!(iface.inner.process_igmp(ipv4_repr, &bytes), None);
+    iface.inner.now = Instant::from_millis(1000);
+    iface.igmp_egress(&mut device).unwrap();
+    assert_eq!(
+        recv_igmp_v3(&mut device, iface.inner.now)[0].1,
+        vec![(IgmpGroupRecordType::ModeIsExclude, group, vec![sources[0]])]
+    );
+
+    // Leaving the group
+    assert_eq!(
+        iface.leave_multicast_group(&mut device, group, timestamp),
+        Ok(true)
+    );
+    assert!(!iface.has_multicast_group(group));
+    assert_eq!(
+        recv_igmp_v3(&mut device, timestamp)[0].1,
+        vec![(IgmpGroupRecordType::ChangeToInclude, group, vec![])]
+    );
+}
+
 #[test]
 #[cfg(all(feature = "proto-ipv4", feature = "socket-raw"))]
 fn test_raw_socket_no_reply() {
@@ -1367,3 +1606,3135 @@ fn test_raw_socket_with_udp_socket() {
         Ok((&UDP_PAYLOAD[..], IpEndpoint::new(src_addr.into(), 67)))
     );
 }
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn create_ethernet_slaac<'a>() -> (Interface<'a>, SocketSet<'a>, Loopback) {
+    let mut device = Loopback::new(Medium::Ethernet);
+    let ip_addrs = [
+        IpCidr::new(Ipv6Address::UNSPECIFIED.into(), 0),
+        IpCidr::new(Ipv6Address::UNSPECIFIED.into(), 0),
+        IpCidr::new(Ipv6Address::UNSPECIFIED.into(), 0),
+    ];
+
+    let iface = InterfaceBuilder::new()
+        .hardware_addr(EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]).into())
+        .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+        .ip_addrs(ip_addrs)
+        .routes(Routes::new(BTreeMap::new()))
+        .slaac(true)
+        .finalize(&mut device);
+
+    (iface, SocketSet::new(vec![]), device)
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn complete_dad(iface: &mut Interface, device: &mut Loopback) {
+    while let Some(probe_at) = iface.inner.dad.poll_at() {
+        iface.inner.now = cmp::max(iface.inner.now, probe_at);
+        iface.dad_egress(device).unwrap();
+    }
+    recv_all(device, iface.inner.now);
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn router_advert(
+    router_lifetime: u64,
+    prefix_info: Option<NdiscPrefixInformation>,
+) -> (Ipv6Repr, NdiscRepr<'static>) {
+    let repr = NdiscRepr::RouterAdvert {
+        hop_limit: 64,
+        flags: NdiscRouterFlags::empty(),
+        router_lifetime: Duration::from_secs(router_lifetime),
+        reachable_time: Duration::from_millis(0),
+        retrans_time: Duration::from_millis(0),
+        lladdr: None,
+        mtu: None,
+        prefix_info,
+    };
+    let ip_repr = Ipv6Repr {
+        src_addr: Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+        dst_addr: Ipv6Address::LINK_LOCAL_ALL_NODES,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: Icmpv6Repr::Ndisc(repr).buffer_len(),
+        hop_limit: 0xff,
+    };
+    (ip_repr, repr)
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn prefix_info(valid_lifetime: u64, preferred_lifetime: u64) -> NdiscPrefixInformation {
+    NdiscPrefixInformation {
+        prefix_len: 64,
+        flags: NdiscPrefixInfoFlags::ON_LINK | NdiscPrefixInfoFlags::ADDRCONF,
+        valid_lifetime: Duration::from_secs(valid_lifetime),
+        preferred_lifetime: Duration::from_secs(preferred_lifetime),
+        prefix: Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0),
+    }
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_slaac_router_solicit() {
+    let (mut iface, _sockets, mut device) = create_ethernet_slaac();
+    let link_local_addr = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0x00ff, 0xfe00, 0x0001);
+
+    // The link-local address is formed from the EUI-64 right away...
+    iface.inner.now = Instant::from_millis(0);
+    assert_eq!(iface.slaac_egress(&mut device), Ok(false));
+    assert!(iface.has_ip_addr(link_local_addr));
+
+    // ...but solicitations are delayed by up to a second, then retransmitted twice.
+    // The first one goes out before Duplicate Address Detection for the link-local
+    // address completes, so it's sent from the unspecified address.
+    let mut sent = Vec::new();
+    for ms in (0..=12_000).step_by(500) {
+        iface.inner.now = Instant::from_millis(ms);
+        if iface.slaac_egress(&mut device).unwrap() {
+            sent.push(ms);
+        }
+        iface.dad_egress(&mut device).unwrap();
+        for frame in recv_all(&mut device, iface.inner.now) {
+            let eth_frame = EthernetFrame::new_checked(&frame[..]).unwrap();
+            let ipv6_packet = Ipv6Packet::new_checked(eth_frame.payload()).unwrap();
+            let ipv6_repr = Ipv6Repr::parse(&ipv6_packet).unwrap();
+            let icmp_packet = Icmpv6Packet::new_checked(ipv6_packet.payload()).unwrap();
+            let icmp_repr = Icmpv6Repr::parse(
+                &ipv6_repr.src_addr.into(),
+                &ipv6_repr.dst_addr.into(),
+                &icmp_packet,
+                &ChecksumCapabilities::default(),
+            )
+            .unwrap();
+
+            if let Icmpv6Repr::Ndisc(NdiscRepr::NeighborSolicit { .. }) = icmp_repr {
+                continue;
+            }
+
+            assert_eq!(ipv6_repr.dst_addr, Ipv6Address::LINK_LOCAL_ALL_ROUTERS);
+            assert_eq!(ipv6_repr.hop_limit, 0xff);
+            if sent.len() == 1 {
+                assert_eq!(ipv6_repr.src_addr, Ipv6Address::UNSPECIFIED);
+                assert_eq!(
+                    icmp_repr,
+                    Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit { lladdr: None })
+                );
+            } else {
+                assert_eq!(ipv6_repr.src_addr, link_local_addr);
+                assert_eq!(
+                    icmp_repr,
+                    Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+                        lladdr: Some(iface.hardware_addr().into())
+                    })
+                );
+            }
+        }
+    }
+    assert_eq!(sent.len(), 3);
+    assert!(sent[0] <= 1000);
+    assert_eq!(sent[1], sent[0] + 4000);
+    assert_eq!(sent[2], sent[1] + 4000);
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_slaac_router_advert() {
+    let (mut iface, _sockets, mut device) = create_ethernet_slaac();
+    let router = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    let addr = Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0x00ff, 0xfe00, 0x0001);
+    let remote = Ipv6Address::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 1);
+
+    iface.inner.now = Instant::from_secs(0);
+    iface.slaac_egress(&mut device).unwrap();
+    assert_eq!(iface.poll_slaac(), None);
+
+    let (ip_repr, repr) = router_advert(1800, Some(prefix_info(3600, 600)));
+    assert_eq!(iface.inner.process_ndisc(ip_repr, repr), None);
+    assert!(iface.has_ip_addr(addr));
+    assert_eq!(
+        iface.routes().lookup(&remote.into(), iface.inner.now),
+        Some(router.into())
+    );
+
+    // The address is only used once it's known to be unique.
+    assert_eq!(
+        iface.ipv6_addr_state(addr),
+        Some(Ipv6AddressState::Tentative)
+    );
+    assert_eq!(iface.inner.get_source_address_ipv6(remote), None);
+    complete_dad(&mut iface, &mut device);
+    assert_eq!(
+        iface.ipv6_addr_state(addr),
+        Some(Ipv6AddressState::Preferred)
+    );
+    assert_eq!(iface.inner.get_source_address_ipv6(remote), Some(addr));
+
+    let mut addresses = heapless::Vec::new();
+    addresses
+        .push(SlaacAddress {
+            cidr: Ipv6Cidr::new(addr, 64),
+            preferred_until: Some(Instant::from_secs(600)),
+            expires_at: Some(Instant::from_secs(3600)),
+        })
+        .unwrap();
+    assert_eq!(
+        iface.poll_slaac(),
+        Some(SlaacEvent::Configured(SlaacConfig {
+            addresses,
+            router: Some(router),
+        }))
+    );
+    assert_eq!(iface.poll_slaac(), None);
+
+    // No more solicitations once a router has answered.
+    iface.inner.now = Instant::from_secs(10);
+    assert_eq!(iface.slaac_egress(&mut device), Ok(false));
+
+    // Once deprecated, the address is still used if there's nothing better...
+    iface.inner.now = Instant::from_secs(700);
+    assert_eq!(iface.inner.get_source_address_ipv6(remote), Some(addr));
+    assert_eq!(
+        iface.ipv6_addr_state(addr),
+        Some(Ipv6AddressState::Deprecated)
+    );
+
+    // ...but a new prefix takes over.
+    let mut info = prefix_info(600, 600);
+    info.prefix = Ipv6Address::new(0x2001, 0xdb8, 2, 0, 0, 0, 0, 0);
+    let (ip_repr, repr) = router_advert(1800, Some(info));
+    iface.inner.process_ndisc(ip_repr, repr);
+    complete_dad(&mut iface, &mut device);
+    assert_eq!(
+        iface.inner.get_source_address_ipv6(remote),
+        Some(Ipv6Address::new(
+            0x2001, 0xdb8, 2, 0, 0, 0x00ff, 0xfe00, 0x0001
+        )),
+    );
+    assert!(iface.poll_slaac().is_some());
+
+    // The addresses and the router expire in turn.
+    iface.inner.now = Instant::from_secs(1300);
+    iface.slaac_egress(&mut device).unwrap();
+    assert!(
+        matches!(iface.poll_slaac(), Some(SlaacEvent::Configured(config)) if config.addresses.len() == 1)
+    );
+
+    iface.inner.now = Instant::from_secs(2500);
+    iface.slaac_egress(&mut device).unwrap();
+    assert!(
+        matches!(iface.poll_slaac(), Some(SlaacEvent::Configured(config)) if config.router.is_none())
+    );
+    assert_eq!(iface.routes().lookup(&remote.into(), iface.inner.now), None);
+
+    assert_eq!(
+        iface.inner.slaac.as_ref().unwrap().poll_at(),
+        Some(Instant::from_secs(3600))
+    );
+    iface.inner.now = Instant::from_secs(3600);
+    iface.slaac_egress(&mut device).unwrap();
+    assert!(!iface.has_ip_addr(addr));
+    assert_eq!(iface.poll_slaac(), Some(SlaacEvent::Deconfigured));
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_slaac_valid_lifetime_update() {
+    let (mut iface, _sockets, mut device) = create_ethernet_slaac();
+    iface.inner.now = Instant::from_secs(0);
+    iface.slaac_egress(&mut device).unwrap();
+
+    let lifetimes = |iface: &Interface| {
+        let address = iface.inner.slaac.as_ref().unwrap().addresses[0];
+        (address.preferred_until, address.expires_at)
+    };
+
+    let (ip_repr, repr) = router_advert(0, Some(prefix_info(0xffff_ffff, 0xffff_ffff)));
+    iface.inner.process_ndisc(ip_repr, repr);
+    assert_eq!(lifetimes(&iface), (None, None));
+
+    // A short valid lifetime only brings the remaining lifetime down to two hours...
+    let (ip_repr, repr) = router_advert(0, Some(prefix_info(60, 30)));
+    iface.inner.process_ndisc(ip_repr, repr);
+    assert_eq!(
+        lifetimes(&iface),
+        (Some(Instant::from_secs(30)), Some(Instant::from_secs(7200)))
+    );
+
+    // ...and is ignored below that.
+    iface.inner.now = Instant::from_secs(3600);
+    let (ip_repr, repr) = router_advert(0, Some(prefix_info(60, 30)));
+    iface.inner.process_ndisc(ip_repr, repr);
+    assert_eq!(
+        lifetimes(&iface),
+        (
+            Some(Instant::from_secs(3630)),
+            Some(Instant::from_secs(7200))
+        )
+    );
+
+    // A longer lifetime is always accepted.
+    let (ip_repr, repr) = router_advert(0, Some(prefix_info(86400, 3600)));
+    iface.inner.process_ndisc(ip_repr, repr);
+    assert_eq!(
+        lifetimes(&iface),
+        (
+            Some(Instant::from_secs(7200)),
+            Some(Instant::from_secs(90000))
+        )
+    );
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_slaac_ignored() {
+    let (mut iface, _sockets, mut device) = create_ethernet_slaac();
+    iface.inner.now = Instant::from_secs(0);
+    iface.slaac_egress(&mut device).unwrap();
+
+    // Advertisements must come from a link-local address...
+    let (mut ip_repr, repr) = router_advert(1800, Some(prefix_info(3600, 600)));
+    ip_repr.src_addr = Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    iface.inner.process_ndisc(ip_repr, repr);
+
+    // ...prefixes need the autonomous flag...
+    let mut info = prefix_info(3600, 600);
+    info.flags = NdiscPrefixInfoFlags::ON_LINK;
+    let (ip_repr, repr) = router_advert(0, Some(info));
+    iface.inner.process_ndisc(ip_repr, repr);
+
+    // ...to be 64 bits long...
+    let mut info = prefix_info(3600, 600);
+    info.prefix_len = 48;
+    let (ip_repr, repr) = router_advert(0, Some(info));
+    iface.inner.process_ndisc(ip_repr, repr);
+
+    // ...and to have a preferred lifetime no longer than the valid one.
+    let (ip_repr, repr) = router_advert(0, Some(prefix_info(600, 3600)));
+    iface.inner.process_ndisc(ip_repr, repr);
+
+    assert_eq!(iface.poll_slaac(), None);
+    assert!(iface.inner.slaac.as_ref().unwrap().addresses.is_empty());
+
+    // Without SLAAC, advertisements are ignored altogether.
+    let (mut iface, _sockets, _device) = create_ethernet();
+    let ip_addrs = iface.ip_addrs().to_vec();
+    let (ip_repr, repr) = router_advert(1800, Some(prefix_info(3600, 600)));
+    iface.inner.process_ndisc(ip_repr, repr);
+    assert_eq!(iface.poll_slaac(), None);
+    assert_eq!(iface.ip_addrs(), &ip_addrs[..]);
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn create_ethernet_dad<'a>() -> (Interface<'a>, SocketSet<'a>, Loopback, Ipv6Address) {
+    let (mut iface, sockets, device) = create_ethernet();
+    let addr = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 2);
+    iface.update_ip_addrs(|addrs| {
+        for cidr in addrs.iter_mut() {
+            if cidr.address() == IpAddress::v6(0xfdbe, 0, 0, 0, 0, 0, 0, 1) {
+                *cidr = IpCidr::new(addr.into(), 64);
+            }
+        }
+    });
+    (iface, sockets, device, addr)
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn neighbor_solicit(
+    src_addr: Ipv6Address,
+    target_addr: Ipv6Address,
+) -> (Ipv6Repr, NdiscRepr<'static>) {
+    let repr = NdiscRepr::NeighborSolicit {
+        target_addr,
+        lladdr: None,
+    };
+    let ip_repr = Ipv6Repr {
+        src_addr,
+        dst_addr: target_addr.solicited_node(),
+        next_header: IpProtocol::Icmpv6,
+        payload_len: Icmpv6Repr::Ndisc(repr).buffer_len(),
+        hop_limit: 0xff,
+    };
+    (ip_repr, repr)
+}
+
+/// Return an Ethernet frame carrying the Neighbor Discovery message `repr`.
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn ndisc_frame(ip_repr: Ipv6Repr, repr: NdiscRepr) -> Vec<u8> {
+    let icmp_repr = Icmpv6Repr::Ndisc(repr);
+    let mut bytes = vec![0; 14 + ip_repr.buffer_len() + icmp_repr.buffer_len()];
+    let mut frame = EthernetFrame::new_unchecked(&mut bytes[..]);
+    let dst = ip_repr.dst_addr.0;
+    frame.set_dst_addr(EthernetAddress([
+        0x33, 0x33, dst[12], dst[13], dst[14], dst[15],
+    ]));
+    frame.set_src_addr(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]));
+    frame.set_ethertype(EthernetProtocol::Ipv6);
+    ip_repr.emit(&mut Ipv6Packet::new_unchecked(frame.payload_mut()));
+    icmp_repr.emit(
+        &ip_repr.src_addr.into(),
+        &ip_repr.dst_addr.into(),
+        &mut Icmpv6Packet::new_unchecked(&mut frame.payload_mut()[ip_repr.buffer_len()..]),
+        &ChecksumCapabilities::default(),
+    );
+    bytes
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_dad_probe() {
+    let (mut iface, _sockets, mut device, addr) = create_ethernet_dad();
+    let remote = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 3);
+
+    // Addresses assigned by the builder are assumed to be unique, new ones are not.
+    assert_eq!(
+        iface.ipv6_addr_state(Ipv6Address::LOOPBACK),
+        Some(Ipv6AddressState::Preferred)
+    );
+    assert_eq!(
+        iface.ipv6_addr_state(addr),
+        Some(Ipv6AddressState::Tentative)
+    );
+    assert_eq!(iface.ipv6_addr_state(remote), None);
+    assert_ne!(iface.inner.get_source_address_ipv6(remote), Some(addr));
+
+    let mut sent = Vec::new();
+    for ms in (0..=3000).step_by(100) {
+        iface.inner.now = Instant::from_millis(ms);
+        if iface.dad_egress(&mut device).unwrap() {
+            sent.push(ms);
+        }
+        for frame in recv_all(&mut device, iface.inner.now) {
+            let eth_frame = EthernetFrame::new_checked(&frame[..]).unwrap();
+            let ipv6_packet = Ipv6Packet::new_checked(eth_frame.payload()).unwrap();
+            let ipv6_repr = Ipv6Repr::parse(&ipv6_packet).unwrap();
+            let icmp_packet = Icmpv6Packet::new_checked(ipv6_packet.payload()).unwrap();
+            let icmp_repr = Icmpv6Repr::parse(
+                &ipv6_repr.src_addr.into(),
+                &ipv6_repr.dst_addr.into(),
+                &icmp_packet,
+                &ChecksumCapabilities::default(),
+            )
+            .unwrap();
+
+            assert_eq!(ipv6_repr.src_addr, Ipv6Address::UNSPECIFIED);
+            assert_eq!(ipv6_repr.dst_addr, addr.solicited_node());
+            assert_eq!(ipv6_repr.hop_limit, 0xff);
+            assert_eq!(
+                icmp_repr,
+                Icmpv6Repr::Ndisc(NdiscRepr::NeighborSolicit {
+                    target_addr: addr,
+                    lladdr: None,
+                })
+            );
+        }
+
+        // The address stays tentative for a second after the probe.
+        if sent.first().map_or(true, |&probe| ms < probe + 1000) {
+            assert_eq!(
+                iface.ipv6_addr_state(addr),
+                Some(Ipv6AddressState::Tentative)
+            );
+        } else {
+            assert_eq!(
+                iface.ipv6_addr_state(addr),
+                Some(Ipv6AddressState::Preferred)
+            );
+        }
+    }
+    assert_eq!(sent.len(), 1);
+    assert!(sent[0] <= 1000);
+    assert_eq!(iface.inner.dad.poll_at(), None);
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_dad_duplicate_advert() {
+    let (mut iface, _sockets, _device, addr) = create_ethernet_dad();
+    let remote = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 3);
+
+    let repr = NdiscRepr::NeighborAdvert {
+        flags: NdiscNeighborFlags::OVERRIDE,
+        target_addr: addr,
+        lladdr: Some(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]).into()),
+    };
+    let ip_repr = Ipv6Repr {
+        src_addr: addr,
+        dst_addr: Ipv6Address::LINK_LOCAL_ALL_NODES,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: Icmpv6Repr::Ndisc(repr).buffer_len(),
+        hop_limit: 0xff,
+    };
+    assert_eq!(iface.inner.process_ndisc(ip_repr, repr), None);
+    assert_eq!(
+        iface.ipv6_addr_state(addr),
+        Some(Ipv6AddressState::Duplicated)
+    );
+    assert_eq!(iface.inner.dad.poll_at(), None);
+
+    // A duplicate address is neither used nor advertised.
+    assert_ne!(iface.inner.get_source_address_ipv6(remote), Some(addr));
+    let (ip_repr, repr) = neighbor_solicit(remote, addr);
+    assert_eq!(iface.inner.process_ndisc(ip_repr, repr), None);
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_dad_duplicate_solicit() {
+    let (mut iface, mut sockets, _device, addr) = create_ethernet_dad();
+    let remote = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 3);
+
+    // Address resolution for a tentative address is ignored...
+    let (ip_repr, repr) = neighbor_solicit(remote, addr);
+    let frame = ndisc_frame(ip_repr, repr);
+    assert_eq!(
+        iface
+            .inner
+            .process_ethernet(&mut sockets, &frame, &mut iface.fragments),
+        None
+    );
+    assert_eq!(
+        iface.ipv6_addr_state(addr),
+        Some(Ipv6AddressState::Tentative)
+    );
+
+    // ...but another node probing for it means it's a duplicate.
+    let (ip_repr, repr) = neighbor_solicit(Ipv6Address::UNSPECIFIED, addr);
+    let frame = ndisc_frame(ip_repr, repr);
+    assert_eq!(
+        iface
+            .inner
+            .process_ethernet(&mut sockets, &frame, &mut iface.fragments),
+        None
+    );
+    assert_eq!(
+        iface.ipv6_addr_state(addr),
+        Some(Ipv6AddressState::Duplicated)
+    );
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv6"))]
+fn test_dad_defend_address() {
+    let (mut iface, mut sockets, _device) = create_ethernet();
+    let addr = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 1);
+
+    // Probes for one of our addresses are answered to all nodes.
+    let (ip_repr, repr) = neighbor_solicit(Ipv6Address::UNSPECIFIED, addr);
+    let frame = ndisc_frame(ip_repr, repr);
+    let advert = Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert {
+        flags: NdiscNeighborFlags::OVERRIDE,
+        target_addr: addr,
+        lladdr: Some(iface.hardware_addr().into()),
+    });
+    assert_eq!(
+        iface
+            .inner
+            .process_ethernet(&mut sockets, &frame, &mut iface.fragments),
+        Some(EthernetPacket::Ip(IpPacket::Icmpv6((
+            Ipv6Repr {
+                src_addr: addr,
+                dst_addr: Ipv6Address::LINK_LOCAL_ALL_NODES,
+                next_header: IpProtocol::Icmpv6,
+                payload_len: advert.buffer_len(),
+                hop_limit: 0xff,
+            },
+            advert
+        ))))
+    );
+
+    // Other packets from the unspecified address are still dropped.
+    let (mut ip_repr, repr) = neighbor_solicit(Ipv6Address::UNSPECIFIED, addr);
+    ip_repr.dst_addr = addr;
+    let frame = ndisc_frame(ip_repr, repr);
+    assert_eq!(
+        iface
+            .inner
+            .process_ethernet(&mut sockets, &frame, &mut iface.fragments),
+        None
+    );
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-mld"))]
+fn recv_mld(
+    device: &mut Loopback,
+    timestamp: Instant,
+) -> Vec<(Ipv6Repr, Icmpv6Message, Ipv6Address, Option<MldRecordType>)> {
+    recv_all(device, timestamp)
+        .iter()
+        .filter_map(|frame| {
+            let eth_frame = EthernetFrame::new_checked(&frame[..]).ok()?;
+            let ipv6_packet = Ipv6Packet::new_checked(eth_frame.payload()).ok()?;
+            let ipv6_repr = Ipv6Repr::parse(&ipv6_packet).ok()?;
+            if ipv6_repr.next_header != IpProtocol::HopByHop {
+                return None;
+            }
+            assert_eq!(ipv6_repr.hop_limit, 1);
+
+            let hbh_header = Ipv6HopByHopHeader::new_checked(ipv6_packet.payload()).unwrap();
+            let hbh_repr = Ipv6HopByHopRepr::parse(&hbh_header).unwrap();
+            assert_eq!(hbh_repr.next_header, IpProtocol::Icmpv6);
+            assert_eq!(hbh_repr.options, &MLD_HOP_BY_HOP_OPTIONS);
+
+            let icmp_payload = &ipv6_packet.payload()[hbh_repr.buffer_len()..];
+            let icmp_packet = Icmpv6Packet::new_checked(icmp_payload).unwrap();
+            let icmp_repr = Icmpv6Repr::parse(
+                &ipv6_repr.src_addr.into(),
+                &ipv6_repr.dst_addr.into(),
+                &icmp_packet,
+                &ChecksumCapabilities::default(),
+            )
+            .unwrap();
+            let message = icmp_packet.msg_type();
+            match icmp_repr {
+                Icmpv6Repr::Mld(MldRepr::Report {
+                    nr_mcast_addr_rcrds,
+                    data,
+                }) => {
+                    assert_eq!(nr_mcast_addr_rcrds, 1);
+                    let record = MldAddressRecord::new_checked(data).unwrap();
+                    let record = (record.mcast_addr(), Some(record.record_type()));
+                    Some((ipv6_repr, message, record.0, record.1))
+                }
+                Icmpv6Repr::Mld(MldRepr::ReportV1 { mcast_addr })
+                | Icmpv6Repr::Mld(MldRepr::Done { mcast_addr }) => {
+                    Some((ipv6_repr, message, mcast_addr, None))
+                }
+                _ => panic!("unexpected {:?}", icmp_repr),
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-mld"))]
+fn mld_query(src_addr: Ipv6Address, repr: MldRepr<'static>) -> (Ipv6Repr, MldRepr<'static>) {
+    let ip_repr = Ipv6Repr {
+        src_addr,
+        dst_addr: Ipv6Address::LINK_LOCAL_ALL_NODES,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: repr.buffer_len(),
+        hop_limit: 1,
+    };
+    (ip_repr, repr)
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-mld"))]
+fn test_handle_mld() {
+    let groups = [
+        Ipv6Address::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb),
+        Ipv6Address::new(0xff05, 0, 0, 0, 0, 0, 0x1, 0x3),
+    ];
+    let querier = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x100);
+
+    let (mut iface, _sockets, mut device) = create_ethernet();
+
+    // Join multicast groups
+    let timestamp = Instant::from_millis(0);
+    for group in &groups {
+        assert_eq!(
+            iface.join_multicast_group(&mut device, *group, timestamp),
+            Ok(true)
+        );
+        assert!(iface.has_multicast_group(*group));
+    }
+    // Groups of interface-local scope aren't reported.
+    let local_group = Ipv6Address::new(0xff01, 0, 0, 0, 0, 0, 0, 0xfb);
+    assert_eq!(
+        iface.join_multicast_group(&mut device, local_group, timestamp),
+        Ok(false)
+    );
+    assert!(iface.has_multicast_group(Ipv6Address::LINK_LOCAL_ALL_NODES));
+    assert!(
+        iface.has_multicast_group(Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 1).solicited_node())
+    );
+
+    // Without a link-local address, MLDv2 reports come from the unspecified address.
+    let reports = recv_mld(&mut device, timestamp);
+    assert_eq!(reports.len(), 2);
+    for (i, group) in groups.iter().enumerate() {
+        assert_eq!(reports[i].0.src_addr, Ipv6Address::UNSPECIFIED);
+        assert_eq!(
+            reports[i].0.dst_addr,
+            Ipv6Address::LINK_LOCAL_ALL_MLDV2_ROUTERS
+        );
+        assert_eq!(reports[i].1, Icmpv6Message::MldReport);
+        assert_eq!(reports[i].2, *group);
+        assert_eq!(reports[i].3, Some(MldRecordType::ChangeToExclude));
+    }
+
+    // Queries from outside the link are ignored.
+    let query = |mcast_addr| MldRepr::Query {
+        max_resp_code: 1000,
+        mcast_addr,
+        s_flag: false,
+        qrv: 2,
+        qqic: 125,
+        num_srcs: 0,
+        data: &[],
+    };
+    let (ip_repr, repr) = mld_query(
+        Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 0x100),
+        query(Ipv6Address::UNSPECIFIED),
+    );
+    assert_eq!(iface.inner.process_mld(ip_repr, repr), None);
+    assert_eq!(iface.poll_at(timestamp, &_sockets), None);
+
+    // General queries are answered for every group, including solicited-node ones,
+    // spread over the maximum response delay.
+    let (ip_repr, repr) = mld_query(querier, query(Ipv6Address::UNSPECIFIED));
+    assert_eq!(iface.inner.process_mld(ip_repr, repr), None);
+    let mut reports = Vec::new();
+    for ms in (0..=1000).step_by(50) {
+        iface.inner.now = Instant::from_millis(ms);
+        iface.mld_egress(&mut device).unwrap();
+        reports.extend(recv_mld(&mut device, iface.inner.now));
+    }
+    let solicited = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 1).solicited_node();
+    assert_eq!(reports.len(), 3);
+    for (report, group) in reports.iter().zip(groups.iter().chain([solicited].iter())) {
+        assert_eq!(report.1, Icmpv6Message::MldReport);
+        assert_eq!(report.2, *group);
+        assert_eq!(report.3, Some(MldRecordType::ModeIsExclude));
+    }
+
+    // Multicast-address-specific queries only for groups we're listening to.
+    let (ip_repr, repr) = mld_query(querier, query(groups[1]));
+    assert_eq!(iface.inner.process_mld(ip_repr, repr), None);
+    iface.inner.now = Instant::from_millis(1250);
+    iface.mld_egress(&mut device).unwrap();
+    let reports = recv_mld(&mut device, iface.inner.now);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].2, groups[1]);
+    assert_eq!(reports[0].3, Some(MldRecordType::ModeIsExclude));
+
+    // Leave multicast groups
+    let timestamp = Instant::from_millis(2000);
+    for group in &groups {
+        assert_eq!(
+            iface.leave_multicast_group(&mut device, *group, timestamp),
+            Ok(true)
+        );
+        assert!(!iface.has_multicast_group(*group));
+    }
+
+    let leaves = recv_mld(&mut device, timestamp);
+    assert_eq!(leaves.len(), 2);
+    for (i, group) in groups.iter().enumerate() {
+        assert_eq!(
+            leaves[i].0.dst_addr,
+            Ipv6Address::LINK_LOCAL_ALL_MLDV2_ROUTERS
+        );
+        assert_eq!(leaves[i].2, *group);
+        assert_eq!(leaves[i].3, Some(MldRecordType::ChangeToInclude));
+    }
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-mld"))]
+fn test_mld_v1_compat() {
+    let (mut iface, _sockets, mut device) = create_ethernet();
+    let link_local = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    iface.update_ip_addrs(|addrs| {
+        for cidr in addrs.iter_mut() {
+            if cidr.address() == IpAddress::v6(0xfdbe, 0, 0, 0, 0, 0, 0, 1) {
+                *cidr = IpCidr::new(link_local.into(), 64);
+            }
+        }
+    });
+    complete_dad(&mut iface, &mut device);
+
+    let group = Ipv6Address::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+    let querier = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x100);
+    let timestamp = iface.inner.now;
+    iface
+        .join_multicast_group(&mut device, group, timestamp)
+        .unwrap();
+    let reports = recv_mld(&mut device, timestamp);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].0.src_addr, link_local);
+    assert_eq!(reports[0].1, Icmpv6Message::MldReport);
+
+    // An MLDv1 querier switches the interface to MLDv1.
+    let query = MldRepr::QueryV1 {
+        max_resp_delay: 1000,
+        mcast_addr: group,
+    };
+    let (ip_repr, repr) = mld_query(querier, query);
+    assert_eq!(iface.inner.process_mld(ip_repr, repr), None);
+    iface.inner.now += Duration::from_millis(250);
+    assert!(iface.mld_egress(&mut device).unwrap());
+    let reports = recv_mld(&mut device, iface.inner.now);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].0.src_addr, link_local);
+    assert_eq!(reports[0].0.dst_addr, group);
+    assert_eq!(reports[0].1, Icmpv6Message::MldV1Report);
+    assert_eq!(reports[0].2, group);
+
+    let timestamp = iface.inner.now;
+    iface
+        .leave_multicast_group(&mut device, group, timestamp)
+        .unwrap();
+    let leaves = recv_mld(&mut device, timestamp);
+    assert_eq!(leaves.len(), 1);
+    assert_eq!(leaves[0].0.dst_addr, Ipv6Address::LINK_LOCAL_ALL_ROUTERS);
+    assert_eq!(leaves[0].1, Icmpv6Message::MldDone);
+    assert_eq!(leaves[0].2, group);
+
+    // Once the MLDv1 querier is gone, MLDv2 is used again.
+    let timestamp = iface.inner.now + Duration::from_secs(260);
+    iface
+        .join_multicast_group(&mut device, group, timestamp)
+        .unwrap();
+    let reports = recv_mld(&mut device, timestamp);
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].1, Icmpv6Message::MldReport);
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "socket-dhcpv4"))]
+fn test_dhcpv4_server() {
+    use crate::socket::dhcpv4;
+
+    let (mut iface, mut sockets, _device) = create_ethernet();
+
+    let server_ip = Ipv4Address::new(127, 0, 0, 1);
+    let pool_start = Ipv4Address::new(127, 0, 0, 100);
+    let config = dhcpv4::ServerConfig {
+        server_ip,
+        subnet_mask: Ipv4Address::new(255, 0, 0, 0),
+        router: None,
+        dns_servers: heapless::Vec::new(),
+        pool_start,
+        pool_end: pool_start,
+        lease_duration: Duration::from_secs(1000),
+    };
+    let handle = sockets.add(dhcpv4::Server::new(config, BTreeMap::new()));
+
+    // DHCP clients send from the unspecified address until they're given one.
+    let dhcp_repr = DhcpRepr {
+        message_type: DhcpMessageType::Discover,
+        transaction_id: 0x12345678,
+        secs: 0,
+        client_hardware_address: EthernetAddress([0x02, 0x02, 0x02, 0x02, 0x02, 0x02]),
+        client_ip: Ipv4Address::UNSPECIFIED,
+        your_ip: Ipv4Address::UNSPECIFIED,
+        server_ip: Ipv4Address::UNSPECIFIED,
+        router: None,
+        subnet_mask: None,
+        relay_agent_ip: Ipv4Address::UNSPECIFIED,
+        broadcast: false,
+        requested_ip: None,
+        client_identifier: None,
+        server_identifier: None,
+        parameter_request_list: None,
+        dns_servers: None,
+        max_size: None,
+        lease_duration: None,
+        renew_duration: None,
+        rebind_duration: None,
+        additional_options: &[],
+    };
+    let frame = |udp_repr: UdpRepr| {
+        let ipv4_repr = Ipv4Repr {
+            src_addr: Ipv4Address::UNSPECIFIED,
+            dst_addr: Ipv4Address::BROADCAST,
+            next_header: IpProtocol::Udp,
+            payload_len: udp_repr.header_len() + dhcp_repr.buffer_len(),
+            hop_limit: 64,
+        };
+        let mut bytes = vec![0u8; ipv4_repr.buffer_len() + ipv4_repr.payload_len];
+        ipv4_repr.emit(
+            &mut Ipv4Packet::new_unchecked(&mut bytes),
+            &ChecksumCapabilities::default(),
+        );
+        udp_repr.emit(
+            &mut UdpPacket::new_unchecked(&mut bytes[ipv4_repr.buffer_len()..]),
+            &ipv4_repr.src_addr.into(),
+            &ipv4_repr.dst_addr.into(),
+            dhcp_repr.buffer_len(),
+            |buf| dhcp_repr.emit(&mut DhcpPacket::new_unchecked(buf)).unwrap(),
+            &ChecksumCapabilities::default(),
+        );
+        bytes
+    };
+
+    let discover = frame(UdpRepr {
+        src_port: DHCP_CLIENT_PORT,
+        dst_port: DHCP_SERVER_PORT,
+    });
+    #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+    let reply = iface
+        .inner
+        .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&discover), None);
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    let reply = iface.inner.process_ipv4(
+        &mut sockets,
+        &Ipv4Packet::new_unchecked(&discover),
+        Some(&mut iface.fragments.ipv4_fragments),
+    );
+    match reply {
+        Some(IpPacket::Dhcpv4((ipv4_repr, udp_repr, offer))) => {
+            assert_eq!(ipv4_repr.src_addr, server_ip);
+            assert_eq!(ipv4_repr.dst_addr, Ipv4Address::BROADCAST);
+            assert_eq!(udp_repr.dst_port, DHCP_CLIENT_PORT);
+            assert_eq!(offer.message_type, DhcpMessageType::Offer);
+            assert_eq!(offer.your_ip, pool_start);
+        }
+        _ => panic!("expected a DHCP offer"),
+    }
+    let server = sockets.get::<dhcpv4::Server>(handle);
+    assert_eq!(
+        server.lease(pool_start).unwrap().state,
+        dhcpv4::LeaseState::Offered
+    );
+
+    // Anything else sent from the unspecified address is still discarded.
+    let other = frame(UdpRepr {
+        src_port: 1234,
+        dst_port: DHCP_SERVER_PORT,
+    });
+    #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+    let reply = iface
+        .inner
+        .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&other), None);
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    let reply = iface.inner.process_ipv4(
+        &mut sockets,
+        &Ipv4Packet::new_unchecked(&other),
+        Some(&mut iface.fragments.ipv4_fragments),
+    );
+    assert!(reply.is_none());
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "socket-dhcpv6"))]
+fn test_dhcpv6_client() {
+    use crate::socket::dhcpv6;
+
+    let (mut iface, mut sockets, mut device) = create_ethernet();
+
+    let link_local = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    iface.update_ip_addrs(|addrs| {
+        for addr in addrs.iter_mut() {
+            if *addr == IpCidr::new(IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 1), 128) {
+                *addr = IpCidr::new(link_local.into(), 64);
+            }
+        }
+    });
+    complete_dad(&mut iface, &mut device);
+
+    // Messages to all DHCPv6 servers on the link are sent from the link-local address.
+    assert_eq!(
+        iface
+            .inner
+            .get_source_address_ipv6(Ipv6Address::LINK_LOCAL_ALL_DHCP_RELAY_AGENTS_AND_SERVERS),
+        Some(link_local)
+    );
+    assert_eq!(
+        iface
+            .inner
+            .get_source_address_ipv6(Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        Some(Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 1))
+    );
+
+    let client_id = [0, 3, 0, 1, 0, 0, 0, 0, 0, 0];
+    let dhcp_repr = Dhcpv6Repr {
+        message_type: Dhcpv6MessageType::Advertise,
+        transaction_id: 1,
+        client_id: Some(&client_id),
+        server_id: Some(&[0, 3, 0, 1, 2, 2, 2, 2, 2, 2]),
+        ia_na: None,
+        ia_pd: None,
+        option_request: None,
+        elapsed_time: None,
+        preference: None,
+        status: None,
+        rapid_commit: false,
+        dns_servers: None,
+        information_refresh_time: None,
+        additional_options: &[],
+    };
+    let udp_repr = UdpRepr {
+        src_port: DHCPV6_SERVER_PORT,
+        dst_port: DHCPV6_CLIENT_PORT,
+    };
+    let ipv6_repr = Ipv6Repr {
+        src_addr: Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 2),
+        dst_addr: link_local,
+        next_header: IpProtocol::Udp,
+        payload_len: udp_repr.header_len() + dhcp_repr.buffer_len(),
+        hop_limit: 64,
+    };
+    let mut bytes = vec![0u8; ipv6_repr.buffer_len() + ipv6_repr.payload_len];
+    ipv6_repr.emit(&mut Ipv6Packet::new_unchecked(&mut bytes));
+    udp_repr.emit(
+        &mut UdpPacket::new_unchecked(&mut bytes[ipv6_repr.buffer_len()..]),
+        &ipv6_repr.src_addr.into(),
+        &ipv6_repr.dst_addr.into(),
+        dhcp_repr.buffer_len(),
+        |buf| {
+            dhcp_repr
+                .emit(&mut Dhcpv6Packet::new_unchecked(buf))
+                .unwrap()
+        },
+        &ChecksumCapabilities::default(),
+    );
+
+    // Without a DHCPv6 socket, the port is unreachable.
+    #[cfg(feature = "socket-udp")]
+    assert!(matches!(
+        iface
+            .inner
+            .process_ipv6(&mut sockets, &Ipv6Packet::new_unchecked(&bytes)),
+        Some(IpPacket::Icmpv6(_))
+    ));
+
+    sockets.add(dhcpv6::Socket::new());
+    assert_eq!(
+        iface
+            .inner
+            .process_ipv6(&mut sockets, &Ipv6Packet::new_unchecked(&bytes)),
+        None
+    );
+}
+
+#[cfg(all(
+    feature = "medium-ip",
+    any(
+        feature = "iface-forwarding",
+        all(feature = "proto-ipv4", feature = "socket-udp")
+    )
+))]
+fn create_routed_ip<'a>(id: u8, ip_addr: IpCidr) -> (Interface<'a>, Loopback) {
+    let mut device = Loopback::new(Medium::Ip);
+    let iface_builder = InterfaceBuilder::new()
+        .id(InterfaceId(id))
+        .ip_addrs(vec![ip_addr])
+        .routes(Routes::new(BTreeMap::new()));
+
+    #[cfg(feature = "iface-forwarding")]
+    let iface_builder = iface_builder.forwarding_buffer(ForwardingBuffer::new(
+        vec![ForwardingMetadata::EMPTY; 4],
+        vec![0; 1500],
+    ));
+    let iface = iface_builder.finalize(&mut device);
+
+    (iface, device)
+}
+
+#[cfg(all(
+    feature = "medium-ip",
+    any(
+        all(
+            feature = "iface-forwarding",
+            any(feature = "proto-ipv4", feature = "medium-ieee802154")
+        ),
+        all(
+            feature = "iface-filter",
+            feature = "proto-ipv4",
+            feature = "socket-udp"
+        )
+    )
+))]
+fn udp_packet_bytes(src_addr: IpAddress, dst_addr: IpAddress, hop_limit: u8) -> Vec<u8> {
+    let udp_repr = UdpRepr {
+        src_port: 67,
+        dst_port: 68,
+    };
+    let payload = b"abcd";
+    let ip_repr = IpRepr::new(
+        src_addr,
+        dst_addr,
+        IpProtocol::Udp,
+        udp_repr.header_len() + payload.len(),
+        hop_limit,
+    );
+
+    let mut bytes = vec![0u8; ip_repr.buffer_len()];
+    ip_repr.emit(&mut bytes[..], &ChecksumCapabilities::default());
+    udp_repr.emit(
+        &mut UdpPacket::new_unchecked(&mut bytes[ip_repr.header_len()..]),
+        &src_addr,
+        &dst_addr,
+        payload.len(),
+        |buf| buf.copy_from_slice(payload),
+        &ChecksumCapabilities::default(),
+    );
+    bytes
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ip",
+    feature = "proto-ipv4"
+))]
+fn test_forward_ipv4() {
+    let (mut iface, mut device) =
+        create_routed_ip(0, IpCidr::new(IpAddress::v4(192, 168, 1, 1), 24));
+    let mut sockets = SocketSet::new(vec![]);
+    iface
+        .routes_mut()
+        .add_default_ipv4_route(Ipv4Address::new(192, 168, 1, 254))
+        .unwrap();
+
+    let src_addr = Ipv4Address::new(192, 168, 1, 100);
+    let dst_addr = Ipv4Address::new(10, 1, 2, 3);
+    let bytes = udp_packet_bytes(src_addr.into(), dst_addr.into(), 64);
+
+    // Routed back through the interface, with its TTL decremented.
+    let expected_repr = IpRepr::Ipv4(Ipv4Repr {
+        src_addr,
+        dst_addr,
+        next_header: IpProtocol::Udp,
+        payload_len: 12,
+        hop_limit: 63,
+    });
+    let packet = iface
+        .inner
+        .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None)
+        .unwrap();
+    assert_eq!(packet, IpPacket::Forward((expected_repr, &bytes[..])));
+
+    let tx_token = device.transmit().unwrap();
+    iface.inner.dispatch_ip(tx_token, packet, None).unwrap();
+    let frames = recv_all(&mut device, Instant::from_millis(0));
+    assert_eq!(frames.len(), 1);
+
+    let mut expected = bytes.clone();
+    let mut expected_packet = Ipv4Packet::new_unchecked(&mut expected[..]);
+    expected_packet.set_hop_limit(63);
+    expected_packet.fill_checksum();
+    assert_eq!(frames[0], expected);
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ip",
+    feature = "proto-ipv4"
+))]
+fn test_forward_ipv4_icmp_errors() {
+    let our_addr = Ipv4Address::new(192, 168, 1, 1);
+    let (mut iface, _device) = create_routed_ip(0, IpCidr::new(our_addr.into(), 24));
+    let mut sockets = SocketSet::new(vec![]);
+
+    let src_addr = Ipv4Address::new(192, 168, 1, 100);
+    let dst_addr = Ipv4Address::new(10, 1, 2, 3);
+    let reply_repr = Ipv4Repr {
+        src_addr: our_addr,
+        dst_addr: src_addr,
+        next_header: IpProtocol::Icmp,
+        payload_len: 40,
+        hop_limit: 64,
+    };
+
+    // No route to the destination.
+    let bytes = udp_packet_bytes(src_addr.into(), dst_addr.into(), 64);
+    let expected = IpPacket::Icmpv4((
+        reply_repr,
+        Icmpv4Repr::DstUnreachable {
+            reason: Icmpv4DstUnreachable::NetUnreachable,
+            header: Ipv4Repr {
+                src_addr,
+                dst_addr,
+                next_header: IpProtocol::Udp,
+                payload_len: 12,
+                hop_limit: 64,
+            },
+            data: &bytes[20..],
+        },
+    ));
+    assert_eq!(
+        iface
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        Some(expected)
+    );
+
+    // The TTL runs out, whether there's a route or not.
+    iface
+        .routes_mut()
+        .add_default_ipv4_route(Ipv4Address::new(192, 168, 1, 254))
+        .unwrap();
+    let bytes = udp_packet_bytes(src_addr.into(), dst_addr.into(), 1);
+    let expected = IpPacket::Icmpv4((
+        reply_repr,
+        Icmpv4Repr::TimeExceeded {
+            reason: Icmpv4TimeExceeded::TtlExpired,
+            header: Ipv4Repr {
+                src_addr,
+                dst_addr,
+                next_header: IpProtocol::Udp,
+                payload_len: 12,
+                hop_limit: 1,
+            },
+            data: &bytes[20..],
+        },
+    ));
+    assert_eq!(
+        iface
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        Some(expected)
+    );
+
+    // Link-local packets stay on their link.
+    let bytes = udp_packet_bytes(Ipv4Address::new(169, 254, 0, 1).into(), dst_addr.into(), 64);
+    assert_eq!(
+        iface
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        None
+    );
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ip",
+    feature = "proto-ipv4"
+))]
+fn test_forward_no_icmp_error_about_icmp_error() {
+    let (mut iface, _device) = create_routed_ip(0, IpCidr::new(IpAddress::v4(192, 168, 1, 1), 24));
+    let mut sockets = SocketSet::new(vec![]);
+
+    let icmp_packet_bytes = |icmp_repr: Icmpv4Repr| {
+        let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+            src_addr: Ipv4Address::new(192, 168, 1, 100),
+            dst_addr: Ipv4Address::new(10, 1, 2, 3),
+            next_header: IpProtocol::Icmp,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit: 64,
+        });
+        let mut bytes = vec![0u8; ip_repr.buffer_len()];
+        ip_repr.emit(&mut bytes[..], &ChecksumCapabilities::default());
+        icmp_repr.emit(
+            &mut Icmpv4Packet::new_unchecked(&mut bytes[ip_repr.header_len()..]),
+            &ChecksumCapabilities::default(),
+        );
+        bytes
+    };
+
+    // There's no route to the destination, which is reported for ICMP echo requests...
+    let bytes = icmp_packet_bytes(Icmpv4Repr::EchoRequest {
+        ident: 1,
+        seq_no: 1,
+        data: b"abcd",
+    });
+    assert!(matches!(
+        iface
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        Some(IpPacket::Icmpv4(_))
+    ));
+
+    // ... but not for ICMP errors.
+    let bytes = icmp_packet_bytes(Icmpv4Repr::DstUnreachable {
+        reason: Icmpv4DstUnreachable::PortUnreachable,
+        header: Ipv4Repr {
+            src_addr: Ipv4Address::new(10, 1, 2, 3),
+            dst_addr: Ipv4Address::new(192, 168, 1, 200),
+            next_header: IpProtocol::Udp,
+            payload_len: 12,
+            hop_limit: 64,
+        },
+        data: &[0; 8],
+    });
+    assert_eq!(
+        iface
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        None
+    );
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ip",
+    feature = "proto-ipv6"
+))]
+fn test_forward_no_icmp_error_about_later_fragments() {
+    let (mut iface, _device) = create_routed_ip(
+        0,
+        IpCidr::new(IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 1), 64),
+    );
+    let mut sockets = SocketSet::new(vec![]);
+
+    let fragment_bytes = |frag_offset| {
+        let ipv6_repr = Ipv6Repr {
+            src_addr: Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 100),
+            dst_addr: Ipv6Address::new(0xfd01, 0, 0, 0, 0, 0, 0, 3),
+            next_header: IpProtocol::Ipv6Frag,
+            payload_len: 8 + 16,
+            hop_limit: 64,
+        };
+        let frag_repr = Ipv6FragmentRepr {
+            next_header: IpProtocol::Udp,
+            frag_offset,
+            more_frags: true,
+            ident: 1,
+        };
+        let mut bytes = vec![0u8; ipv6_repr.buffer_len() + ipv6_repr.payload_len];
+        ipv6_repr.emit(&mut Ipv6Packet::new_unchecked(&mut bytes));
+        frag_repr.emit(&mut Ipv6FragmentHeader::new_unchecked(
+            &mut bytes[ipv6_repr.buffer_len()..],
+        ));
+        bytes
+    };
+
+    // There's no route to the destination, which is reported for the first fragment...
+    let bytes = fragment_bytes(0);
+    assert!(matches!(
+        iface
+            .inner
+            .process_ipv6(&mut sockets, &Ipv6Packet::new_unchecked(&bytes)),
+        Some(IpPacket::Icmpv6(_))
+    ));
+
+    // ... but not for the others.
+    let bytes = fragment_bytes(16);
+    assert_eq!(
+        iface
+            .inner
+            .process_ipv6(&mut sockets, &Ipv6Packet::new_unchecked(&bytes)),
+        None
+    );
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ip",
+    feature = "proto-ipv4"
+))]
+fn test_forward_ipv4_other_interface() {
+    let (mut iface0, _device0) =
+        create_routed_ip(0, IpCidr::new(IpAddress::v4(192, 168, 1, 1), 24));
+    let (mut iface1, mut device1) = create_routed_ip(1, IpCidr::new(IpAddress::v4(10, 0, 0, 1), 8));
+    let (mut iface2, mut device2) =
+        create_routed_ip(2, IpCidr::new(IpAddress::v4(172, 16, 0, 1), 12));
+    let mut sockets = SocketSet::new(vec![]);
+    iface0
+        .routes_mut()
+        .add_interface_route(IpCidr::new(IpAddress::v4(10, 0, 0, 0), 8), InterfaceId(1))
+        .unwrap();
+
+    let bytes = udp_packet_bytes(
+        IpAddress::v4(192, 168, 1, 100),
+        IpAddress::v4(10, 1, 2, 3),
+        64,
+    );
+    assert_eq!(
+        iface0
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        None
+    );
+
+    // Only the interface the packet is routed through sends it.
+    let timestamp = Instant::from_millis(0);
+    assert!(!iface2.forward(timestamp, &mut device2, &mut iface0));
+    assert!(recv_all(&mut device2, timestamp).is_empty());
+    assert!(iface1.forward(timestamp, &mut device1, &mut iface0));
+    assert!(!iface1.forward(timestamp, &mut device1, &mut iface0));
+
+    let frames = recv_all(&mut device1, timestamp);
+    assert_eq!(frames.len(), 1);
+    let mut expected = bytes.clone();
+    let mut expected_packet = Ipv4Packet::new_unchecked(&mut expected[..]);
+    expected_packet.set_hop_limit(63);
+    expected_packet.fill_checksum();
+    assert_eq!(frames[0], expected);
+}
+
+#[test]
+#[cfg(all(feature = "medium-ip", feature = "proto-ipv4", feature = "socket-udp"))]
+fn test_socket_egress_other_interface() {
+    let (mut iface0, mut device0) =
+        create_routed_ip(0, IpCidr::new(IpAddress::v4(192, 168, 1, 1), 24));
+    let (mut iface1, mut device1) = create_routed_ip(1, IpCidr::new(IpAddress::v4(10, 0, 0, 1), 8));
+    iface0
+        .routes_mut()
+        .add_interface_route(IpCidr::new(IpAddress::v4(10, 0, 0, 0), 8), InterfaceId(1))
+        .unwrap();
+
+    let mut sockets = SocketSet::new(vec![]);
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+    );
+    socket.bind(1234).unwrap();
+    let remote = IpEndpoint::new(IpAddress::v4(10, 1, 2, 3), 53);
+    socket.send_slice(b"abcd", remote).unwrap();
+    let handle = sockets.add(socket);
+
+    // The interfaces share the sockets, the packet is left to the one it's routed through.
+    let timestamp = Instant::from_millis(0);
+    iface0.inner.now = timestamp;
+    assert!(!iface0.socket_egress(&mut device0, &mut sockets));
+    assert!(recv_all(&mut device0, timestamp).is_empty());
+    assert!(!sockets.get::<udp::Socket>(handle).can_send());
+
+    iface1.inner.now = timestamp;
+    assert!(iface1.socket_egress(&mut device1, &mut sockets));
+    let frames = recv_all(&mut device1, timestamp);
+    assert_eq!(frames.len(), 1);
+    let packet = Ipv4Packet::new_checked(&frames[0][..]).unwrap();
+    assert_eq!(packet.src_addr(), Ipv4Address::new(10, 0, 0, 1));
+    assert_eq!(packet.dst_addr(), Ipv4Address::new(10, 1, 2, 3));
+    assert!(sockets.get::<udp::Socket>(handle).can_send());
+}
+
+/// Create a private interface, masqueraded behind a public one.
+#[cfg(all(feature = "iface-nat", feature = "medium-ip"))]
+fn create_nat<'a>() -> ((Interface<'a>, Loopback), (Interface<'a>, Loopback)) {
+    let (mut private, private_device) =
+        create_routed_ip(0, IpCidr::new(IpAddress::v4(10, 0, 0, 1), 8));
+    let (mut public, public_device) =
+        create_routed_ip(1, IpCidr::new(IpAddress::v4(203, 0, 113, 2), 24));
+    public.inner.nat = Some(NatTable::new(BTreeMap::new()));
+
+    private
+        .routes_mut()
+        .add_interface_route(IpCidr::new(IpAddress::v4(0, 0, 0, 0), 0), InterfaceId(1))
+        .unwrap();
+    public
+        .routes_mut()
+        .add_default_ipv4_route(Ipv4Address::new(203, 0, 113, 1))
+        .unwrap();
+    public
+        .routes_mut()
+        .add_interface_route(IpCidr::new(IpAddress::v4(10, 0, 0, 0), 8), InterfaceId(0))
+        .unwrap();
+
+    ((private, private_device), (public, public_device))
+}
+
+#[test]
+#[cfg(all(feature = "iface-nat", feature = "medium-ip"))]
+fn test_nat_udp() {
+    let ((mut private, mut private_device), (mut public, mut public_device)) = create_nat();
+    let mut sockets = SocketSet::new(vec![]);
+    let timestamp = Instant::from_millis(0);
+    let host = Ipv4Address::new(10, 0, 0, 5);
+    let public_addr = Ipv4Address::new(203, 0, 113, 2);
+    let remote = Ipv4Address::new(198, 51, 100, 7);
+
+    let bytes = udp_packet_bytes(host.into(), remote.into(), 64);
+    assert_eq!(
+        private
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        None
+    );
+    assert!(public.forward(timestamp, &mut public_device, &mut private));
+
+    // The packet leaves from our address and a port of the NAT range.
+    let frames = recv_all(&mut public_device, timestamp);
+    assert_eq!(frames.len(), 1);
+    let packet = Ipv4Packet::new_checked(&frames[0][..]).unwrap();
+    assert!(packet.verify_checksum());
+    assert_eq!(packet.src_addr(), public_addr);
+    assert_eq!(packet.dst_addr(), remote);
+    assert_eq!(packet.hop_limit(), 63);
+    let udp_packet = UdpPacket::new_checked(packet.payload()).unwrap();
+    assert!(udp_packet.verify_checksum(&public_addr.into(), &remote.into()));
+    assert_eq!(udp_packet.src_port(), 49152);
+    assert_eq!(udp_packet.dst_port(), 68);
+    assert_eq!(udp_packet.payload(), b"abcd");
+
+    // The reply is translated back to the host.
+    let mut reply = udp_packet_bytes(remote.into(), public_addr.into(), 64);
+    let ip_header_len = Ipv4Packet::new_unchecked(&reply[..]).header_len() as usize;
+    {
+        let mut udp_packet = UdpPacket::new_unchecked(&mut reply[ip_header_len..]);
+        udp_packet.set_src_port(68);
+        udp_packet.set_dst_port(49152);
+        udp_packet.fill_checksum(&remote.into(), &public_addr.into());
+    }
+    assert_eq!(
+        public
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&reply), None),
+        None
+    );
+    assert!(private.forward(timestamp, &mut private_device, &mut public));
+
+    let frames = recv_all(&mut private_device, timestamp);
+    assert_eq!(frames.len(), 1);
+    let packet = Ipv4Packet::new_checked(&frames[0][..]).unwrap();
+    assert!(packet.verify_checksum());
+    assert_eq!(packet.src_addr(), remote);
+    assert_eq!(packet.dst_addr(), host);
+    let udp_packet = UdpPacket::new_checked(packet.payload()).unwrap();
+    assert!(udp_packet.verify_checksum(&remote.into(), &host.into()));
+    assert_eq!(udp_packet.src_port(), 68);
+    assert_eq!(udp_packet.dst_port(), 67);
+
+    // Packets for ports without a mapping are ours to process.
+    let bytes = udp_packet_bytes(remote.into(), public_addr.into(), 64);
+    #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+    public
+        .inner
+        .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None);
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    public.inner.process_ipv4(
+        &mut sockets,
+        &Ipv4Packet::new_unchecked(&bytes),
+        Some(&mut public.fragments.ipv4_fragments),
+    );
+    assert!(!private.forward(timestamp, &mut private_device, &mut public));
+}
+
+#[test]
+#[cfg(all(feature = "iface-nat", feature = "medium-ip"))]
+fn test_nat_port_forward() {
+    let ((mut private, mut private_device), (mut public, _public_device)) = create_nat();
+    let mut sockets = SocketSet::new(vec![]);
+    let timestamp = Instant::from_millis(0);
+    let host = Ipv4Address::new(10, 0, 0, 6);
+    let public_addr = Ipv4Address::new(203, 0, 113, 2);
+    let remote = Ipv4Address::new(198, 51, 100, 7);
+
+    public
+        .nat_table_mut()
+        .unwrap()
+        .add_port_forward(IpProtocol::Udp, 68, host, 6800)
+        .unwrap();
+
+    let bytes = udp_packet_bytes(remote.into(), public_addr.into(), 64);
+    assert_eq!(
+        public
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        None
+    );
+    assert!(private.forward(timestamp, &mut private_device, &mut public));
+
+    let frames = recv_all(&mut private_device, timestamp);
+    assert_eq!(frames.len(), 1);
+    let packet = Ipv4Packet::new_checked(&frames[0][..]).unwrap();
+    assert_eq!(packet.dst_addr(), host);
+    let udp_packet = UdpPacket::new_checked(packet.payload()).unwrap();
+    assert!(udp_packet.verify_checksum(&remote.into(), &host.into()));
+    assert_eq!(udp_packet.dst_port(), 6800);
+}
+
+#[test]
+#[cfg(all(feature = "iface-nat", feature = "medium-ip"))]
+fn test_nat_icmp_echo() {
+    let ((mut private, _private_device), (mut public, mut public_device)) = create_nat();
+    let mut sockets = SocketSet::new(vec![]);
+    let timestamp = Instant::from_millis(0);
+    let host = Ipv4Address::new(10, 0, 0, 5);
+    let remote = Ipv4Address::new(198, 51, 100, 7);
+
+    let icmp_repr = Icmpv4Repr::EchoRequest {
+        ident: 0x1234,
+        seq_no: 1,
+        data: b"ping",
+    };
+    let ip_repr = Ipv4Repr {
+        src_addr: host,
+        dst_addr: remote,
+        next_header: IpProtocol::Icmp,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 64,
+    };
+    let mut bytes = vec![0u8; ip_repr.buffer_len() + icmp_repr.buffer_len()];
+    ip_repr.emit(
+        &mut Ipv4Packet::new_unchecked(&mut bytes[..]),
+        &ChecksumCapabilities::default(),
+    );
+    icmp_repr.emit(
+        &mut Icmpv4Packet::new_unchecked(&mut bytes[ip_repr.buffer_len()..]),
+        &ChecksumCapabilities::default(),
+    );
+    assert_eq!(
+        private
+            .inner
+            .process_ipv4(&mut sockets, &Ipv4Packet::new_unchecked(&bytes), None),
+        None
+    );
+    assert!(public.forward(timestamp, &mut public_device, &mut private));
+
+    let frames = recv_all(&mut public_device, timestamp);
+    assert_eq!(frames.len(), 1);
+    let packet = Ipv4Packet::new_checked(&frames[0][..]).unwrap();
+    let icmp_packet = Icmpv4Packet::new_checked(packet.payload()).unwrap();
+    assert!(icmp_packet.verify_checksum());
+    assert_eq!(icmp_packet.echo_ident(), 49152);
+    assert_eq!(icmp_packet.echo_seq_no(), 1);
+}
+
+#[cfg(all(
+    feature = "iface-filter",
+    feature = "medium-ip",
+    feature = "proto-ipv4",
+    feature = "socket-udp"
+))]
+fn create_filtered_ip<'a>(filter: Filter<'a>) -> (Interface<'a>, Loopback) {
+    let (mut iface, device) = create_routed_ip(0, IpCidr::new(IpAddress::v4(192, 168, 1, 1), 24));
+    iface.inner.filter = Some(filter);
+    (iface, device)
+}
+
+#[cfg(all(
+    feature = "iface-filter",
+    feature = "medium-ip",
+    feature = "proto-ipv4",
+    feature = "socket-udp"
+))]
+fn process_ipv4_bytes<'frame>(
+    iface: &'frame mut Interface<'_>,
+    sockets: &mut SocketSet,
+    bytes: &'frame [u8],
+) -> Option<IpPacket<'frame>> {
+    #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+    return iface
+        .inner
+        .process_ipv4(sockets, &Ipv4Packet::new_unchecked(bytes), None);
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    return iface.inner.process_ipv4(
+        sockets,
+        &Ipv4Packet::new_unchecked(bytes),
+        Some(&mut iface.fragments.ipv4_fragments),
+    );
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-filter",
+    feature = "medium-ip",
+    feature = "proto-ipv4",
+    feature = "socket-udp"
+))]
+fn test_filter_ingress() {
+    use crate::iface::{FilterDirection, FilterRule, PortRange};
+
+    let rules = vec![
+        FilterRule::new(FilterDirection::Ingress, FilterAction::Reject)
+            .src_addr(IpCidr::new(IpAddress::v4(192, 168, 1, 100), 32))
+            .protocol(IpProtocol::Udp),
+        FilterRule::new(FilterDirection::Ingress, FilterAction::Drop)
+            .protocol(IpProtocol::Udp)
+            .dst_port(PortRange::new(60, 69)),
+    ];
+    let (mut iface, _device) = create_filtered_ip(Filter::new(rules, BTreeMap::new()));
+    let mut sockets = SocketSet::new(vec![]);
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+    );
+    socket.bind(68).unwrap();
+    let handle = sockets.add(socket);
+
+    // Rejected packets are answered with an ICMP error.
+    let bytes = udp_packet_bytes(
+        IpAddress::v4(192, 168, 1, 100),
+        IpAddress::v4(192, 168, 1, 1),
+        64,
+    );
+    match process_ipv4_bytes(&mut iface, &mut sockets, &bytes) {
+        Some(IpPacket::Icmpv4((
+            ipv4_repr,
+            Icmpv4Repr::DstUnreachable {
+                reason: Icmpv4DstUnreachable::CommProhibited,
+                ..
+            },
+        ))) => assert_eq!(ipv4_repr.dst_addr, Ipv4Address::new(192, 168, 1, 100)),
+        response => panic!("unexpected response {:?}", response),
+    }
+
+    // Dropped ones are not.
+    let bytes = udp_packet_bytes(
+        IpAddress::v4(192, 168, 1, 101),
+        IpAddress::v4(192, 168, 1, 1),
+        64,
+    );
+    assert_eq!(process_ipv4_bytes(&mut iface, &mut sockets, &bytes), None);
+    assert!(!sockets.get::<udp::Socket>(handle).can_recv());
+
+    // Packets no rule matches reach the sockets.
+    iface.filter_mut().unwrap().update_rules(|rules| {
+        if let ManagedSlice::Owned(rules) = rules {
+            rules.pop();
+        }
+    });
+    assert_eq!(process_ipv4_bytes(&mut iface, &mut sockets, &bytes), None);
+    assert!(sockets.get::<udp::Socket>(handle).can_recv());
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-filter",
+    feature = "medium-ip",
+    feature = "proto-ipv4",
+    feature = "socket-udp"
+))]
+fn test_filter_tracks_connections() {
+    use crate::iface::FilterDirection;
+
+    let mut filter = Filter::new(vec![], BTreeMap::new());
+    filter.set_policy(FilterDirection::Ingress, FilterAction::Drop);
+    let (mut iface, mut device) = create_filtered_ip(filter);
+    let mut sockets = SocketSet::new(vec![]);
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+    );
+    socket.bind(68).unwrap();
+    let remote = IpEndpoint::new(IpAddress::v4(192, 168, 1, 100), 67);
+    socket.send_slice(b"abcd", remote).unwrap();
+    let handle = sockets.add(socket);
+
+    let reply = udp_packet_bytes(
+        IpAddress::v4(192, 168, 1, 100),
+        IpAddress::v4(192, 168, 1, 1),
+        64,
+    );
+    assert_eq!(process_ipv4_bytes(&mut iface, &mut sockets, &reply), None);
+    assert!(!sockets.get::<udp::Socket>(handle).can_recv());
+
+    // Once we've sent a packet, replies are let through.
+    let timestamp = Instant::from_millis(0);
+    iface.inner.now = timestamp;
+    assert!(iface.socket_egress(&mut device, &mut sockets));
+    assert_eq!(recv_all(&mut device, timestamp).len(), 1);
+
+    assert_eq!(process_ipv4_bytes(&mut iface, &mut sockets, &reply), None);
+    let socket = sockets.get_mut::<udp::Socket>(handle);
+    assert_eq!(socket.recv(), Ok((&b"abcd"[..], remote)));
+
+    // Packets from other hosts still aren't.
+    let other = udp_packet_bytes(
+        IpAddress::v4(192, 168, 1, 101),
+        IpAddress::v4(192, 168, 1, 1),
+        64,
+    );
+    assert_eq!(process_ipv4_bytes(&mut iface, &mut sockets, &other), None);
+    assert!(!sockets.get::<udp::Socket>(handle).can_recv());
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-filter",
+    feature = "medium-ip",
+    feature = "proto-ipv4",
+    feature = "socket-udp"
+))]
+fn test_filter_accepts_related_icmp_errors() {
+    use crate::iface::FilterDirection;
+
+    let mut filter = Filter::new(vec![], BTreeMap::new());
+    filter.set_policy(FilterDirection::Ingress, FilterAction::Drop);
+    let (mut iface, mut device) = create_filtered_ip(filter);
+    let mut sockets = SocketSet::new(vec![]);
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+    );
+    socket.bind(68).unwrap();
+    let remote_addr = Ipv4Address::new(192, 168, 1, 100);
+    socket
+        .send_slice(b"abcd", IpEndpoint::new(remote_addr.into(), 67))
+        .unwrap();
+    sockets.add(socket);
+
+    let local_addr = Ipv4Address::new(192, 168, 1, 1);
+    let icmp_repr = Icmpv4Repr::DstUnreachable {
+        reason: Icmpv4DstUnreachable::FragRequired,
+        header: Ipv4Repr {
+            src_addr: local_addr,
+            dst_addr: remote_addr,
+            next_header: IpProtocol::Udp,
+            payload_len: 12,
+            hop_limit: 64,
+        },
+        data: &[0x00, 0x44, 0x00, 0x43, 0x00, 0x0c, 0x00, 0x00],
+    };
+    let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+        src_addr: Ipv4Address::new(192, 168, 1, 254),
+        dst_addr: local_addr,
+        next_header: IpProtocol::Icmp,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 64,
+    });
+    let mut error = vec![0; ip_repr.buffer_len()];
+    ip_repr.emit(&mut error, &ChecksumCapabilities::default());
+    let mut icmp_packet = Icmpv4Packet::new_unchecked(&mut error[ip_repr.header_len()..]);
+    icmp_repr.emit(&mut icmp_packet, &ChecksumCapabilities::default());
+    icmp_packet.set_next_hop_mtu(576);
+    icmp_packet.fill_checksum();
+
+    // Errors about connections that aren't tracked are dropped.
+    let link_mtu = iface.inner.ip_mtu();
+    assert_eq!(process_ipv4_bytes(&mut iface, &mut sockets, &error), None);
+    assert_eq!(iface.path_mtu(remote_addr), link_mtu);
+
+    // Once we've sent a packet, errors about it are let through.
+    let timestamp = Instant::from_millis(0);
+    iface.inner.now = timestamp;
+    assert!(iface.socket_egress(&mut device, &mut sockets));
+    assert_eq!(recv_all(&mut device, timestamp).len(), 1);
+
+    assert_eq!(process_ipv4_bytes(&mut iface, &mut sockets, &error), None);
+    assert_eq!(iface.path_mtu(remote_addr), 576);
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-filter",
+    feature = "medium-ip",
+    feature = "proto-ipv4",
+    feature = "socket-udp"
+))]
+fn test_filter_egress() {
+    use crate::iface::{FilterDirection, FilterRule};
+
+    let rules = vec![
+        FilterRule::new(FilterDirection::Egress, FilterAction::Reject)
+            .dst_addr(IpCidr::new(IpAddress::v4(192, 168, 1, 100), 32)),
+    ];
+    let (mut iface, mut device) = create_filtered_ip(Filter::new(rules, BTreeMap::new()));
+    let mut sockets = SocketSet::new(vec![]);
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 2], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 2], vec![0; 64]),
+    );
+    socket.bind(68).unwrap();
+    socket
+        .send_slice(
+            b"abcd",
+            IpEndpoint::new(IpAddress::v4(192, 168, 1, 100), 67),
+        )
+        .unwrap();
+    socket
+        .send_slice(
+            b"abcd",
+            IpEndpoint::new(IpAddress::v4(192, 168, 1, 101), 67),
+        )
+        .unwrap();
+    sockets.add(socket);
+
+    let timestamp = Instant::from_millis(0);
+    iface.inner.now = timestamp;
+    // The rejected packet doesn't hold up the next one.
+    iface.socket_egress(&mut device, &mut sockets);
+    iface.socket_egress(&mut device, &mut sockets);
+    let frames = recv_all(&mut device, timestamp);
+    assert_eq!(frames.len(), 1);
+    let packet = Ipv4Packet::new_checked(&frames[0][..]).unwrap();
+    assert_eq!(packet.dst_addr(), Ipv4Address::new(192, 168, 1, 101));
+}
+
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ieee802154",
+    feature = "medium-ip"
+))]
+fn create_routed_ieee802154<'a>(id: u8, ip_addr: IpCidr) -> (Interface<'a>, Loopback) {
+    let mut device = Loopback::new(Medium::Ieee802154);
+    let iface = InterfaceBuilder::new()
+        .id(InterfaceId(id))
+        .hardware_addr(Ieee802154Address::Extended([0x01; 8]).into())
+        .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+        .ip_addrs(vec![ip_addr])
+        .routes(Routes::new(BTreeMap::new()))
+        .forwarding_buffer(ForwardingBuffer::new(
+            vec![ForwardingMetadata::EMPTY; 4],
+            vec![0; 1500],
+        ))
+        .finalize(&mut device);
+
+    (iface, device)
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ieee802154",
+    feature = "medium-ip"
+))]
+fn test_forward_ipv6_to_sixlowpan() {
+    let (mut iface0, _device0) = create_routed_ip(
+        0,
+        IpCidr::new(IpAddress::v6(0xfd00, 1, 0, 0, 0, 0, 0, 1), 64),
+    );
+    let (mut iface1, mut device1) = create_routed_ieee802154(
+        1,
+        IpCidr::new(IpAddress::v6(0xfd00, 2, 0, 0, 0, 0, 0, 1), 64),
+    );
+    let mut sockets = SocketSet::new(vec![]);
+    iface0
+        .routes_mut()
+        .add_interface_route(
+            IpCidr::new(IpAddress::v6(0xfd00, 2, 0, 0, 0, 0, 0, 0), 64),
+            InterfaceId(1),
+        )
+        .unwrap();
+
+    let src_addr = Ipv6Address::new(0xfd00, 1, 0, 0, 0, 0, 0, 0x100);
+    let dst_addr = Ipv6Address::new(0xfd00, 2, 0, 0, 0, 0, 0, 2);
+    let dst_ll_addr = Ieee802154Address::Extended([0x02; 8]);
+    let timestamp = Instant::from_millis(0);
+    iface1.inner.neighbor_cache.as_mut().unwrap().fill(
+        dst_addr.into(),
+        dst_ll_addr.into(),
+        timestamp,
+    );
+
+    let bytes = udp_packet_bytes(src_addr.into(), dst_addr.into(), 64);
+    assert_eq!(
+        iface0
+            .inner
+            .process_ipv6(&mut sockets, &Ipv6Packet::new_unchecked(&bytes)),
+        None
+    );
+    assert!(iface1.forward(timestamp, &mut device1, &mut iface0));
+
+    // The IPv6 header is compressed, the rest of the packet is carried as it is.
+    let frames = recv_all(&mut device1, timestamp);
+    assert_eq!(frames.len(), 1);
+    let ieee802154_frame = Ieee802154Frame::new_checked(&frames[0][..]).unwrap();
+    let ieee802154_repr = Ieee802154Repr::parse(&ieee802154_frame).unwrap();
+    assert_eq!(ieee802154_repr.dst_addr, Some(dst_ll_addr));
+    let iphc_packet =
+        SixlowpanIphcPacket::new_checked(ieee802154_frame.payload().unwrap()).unwrap();
+    let iphc_repr = SixlowpanIphcRepr::parse(
+        &iphc_packet,
+        ieee802154_repr.src_addr,
+        ieee802154_repr.dst_addr,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(iphc_repr.src_addr, src_addr);
+    assert_eq!(iphc_repr.dst_addr, dst_addr);
+    assert_eq!(iphc_repr.hop_limit, 63);
+    assert_eq!(
+        iphc_repr.next_header,
+        SixlowpanNextHeader::Uncompressed(IpProtocol::Udp)
+    );
+    assert_eq!(iphc_packet.payload(), &bytes[40..]);
+}
+
+#[test]
+#[cfg(all(
+    feature = "iface-forwarding",
+    feature = "medium-ieee802154",
+    feature = "medium-ip"
+))]
+fn test_forward_sixlowpan_to_ipv6() {
+    let (mut iface0, _device0) = create_routed_ieee802154(
+        0,
+        IpCidr::new(IpAddress::v6(0xfd00, 2, 0, 0, 0, 0, 0, 1), 64),
+    );
+    let (mut iface1, mut device1) = create_routed_ip(
+        1,
+        IpCidr::new(IpAddress::v6(0xfd00, 1, 0, 0, 0, 0, 0, 1), 64),
+    );
+    let mut sockets = SocketSet::new(vec![]);
+    iface0
+        .routes_mut()
+        .add_interface_route(
+            IpCidr::new(IpAddress::v6(0xfd00, 1, 0, 0, 0, 0, 0, 0), 64),
+            InterfaceId(1),
+        )
+        .unwrap();
+
+    let src_addr = Ipv6Address::new(0xfd00, 2, 0, 0, 0, 0, 0, 2);
+    let dst_addr = Ipv6Address::new(0xfd00, 1, 0, 0, 0, 0, 0, 0x100);
+    let src_ll_addr = Ieee802154Address::Extended([0x02; 8]);
+    let dst_ll_addr = Ieee802154Address::Extended([0x01; 8]);
+    let ieee802154_repr = Ieee802154Repr {
+        frame_type: Ieee802154FrameType::Data,
+        security_enabled: false,
+        frame_pending: false,
+        ack_request: false,
+        sequence_number: Some(1),
+        pan_id_compression: true,
+        frame_version: Ieee802154FrameVersion::Ieee802154_2003,
+        dst_pan_id: None,
+        dst_addr: Some(dst_ll_addr),
+        src_pan_id: None,
+        src_addr: Some(src_ll_addr),
+    };
+
+    // A UDP datagram, with both its IPv6 and UDP headers compressed.
+    let iphc_repr = SixlowpanIphcRepr {
+        src_addr,
+        ll_src_addr: Some(src_ll_addr),
+        dst_addr,
+        ll_dst_addr: Some(dst_ll_addr),
+        next_header: SixlowpanNextHeader::Compressed,
+        hop_limit: 64,
+        ecn: None,
+        dscp: None,
+        flow_label: None,
+    };
+    let udp_repr = SixlowpanUdpNhcRepr(UdpRepr {
+        src_port: 67,
+        dst_port: 68,
+    });
+    let payload = b"abcd";
+    let mut bytes = vec![0u8; iphc_repr.buffer_len() + udp_repr.header_len() + payload.len()];
+    iphc_repr.emit(&mut SixlowpanIphcPacket::new_unchecked(
+        &mut bytes[..iphc_repr.buffer_len()],
+    ));
+    udp_repr.emit(
+        &mut SixlowpanUdpNhcPacket::new_unchecked(&mut bytes[iphc_repr.buffer_len()..]),
+        &src_addr,
+        &dst_addr,
+        payload.len(),
+        |buf| buf.copy_from_slice(payload),
+    );
+
+    assert_eq!(
+        iface0
+            .inner
+            .process_sixlowpan(&mut sockets, &ieee802154_repr, &bytes, None),
+        None
+    );
+    let timestamp = Instant::from_millis(0);
+    assert!(iface1.forward(timestamp, &mut device1, &mut iface0));
+
+    // The packet is sent on as a whole IPv6 packet.
+    let frames = recv_all(&mut device1, timestamp);
+    assert_eq!(
+        frames,
+        vec![udp_packet_bytes(src_addr.into(), dst_addr.into(), 63)]
+    );
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn create_vlan<'a>(vlan_id: u16, device: &mut impl Device) -> Interface<'a> {
+    let iface_builder = InterfaceBuilder::new()
+        .hardware_addr(EthernetAddress([0x02, 0, 0, 0, 0, vlan_id as u8]).into())
+        .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+        .ip_addrs([IpCidr::new(IpAddress::v4(192, 168, vlan_id as u8, 1), 24)])
+        .vlan_id(vlan_id)
+        .vlan_priority(3);
+
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    let iface_builder = iface_builder
+        .ipv4_reassembly_buffer(PacketAssemblerSet::new(vec![], BTreeMap::new()))
+        .ipv4_fragmentation_buffer(vec![]);
+
+    #[cfg(feature = "proto-igmp")]
+    let iface_builder = iface_builder.ipv4_multicast_groups(BTreeMap::new());
+    iface_builder.finalize(device)
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn vlan_arp_request(vlan_id: Option<u16>, target_protocol_addr: Ipv4Address) -> Vec<u8> {
+    let repr = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Request,
+        source_hardware_addr: EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]),
+        source_protocol_addr: Ipv4Address::new(192, 168, target_protocol_addr.0[2], 2),
+        target_hardware_addr: EthernetAddress::default(),
+        target_protocol_addr,
+    };
+
+    let tag_len = vlan_id.map_or(0, |_| VLAN_HEADER_LEN);
+    let mut bytes = vec![0u8; ETHERNET_HEADER_LEN + tag_len + repr.buffer_len()];
+    let mut frame = EthernetFrame::new_unchecked(&mut bytes);
+    frame.set_dst_addr(EthernetAddress::BROADCAST);
+    frame.set_src_addr(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]));
+    let payload = match vlan_id {
+        Some(vlan_id) => {
+            frame.set_ethertype(EthernetProtocol::Vlan);
+            let mut packet = VlanPacket::new_unchecked(frame.payload_mut());
+            VlanRepr {
+                priority: 0,
+                drop_eligible: false,
+                vlan_id,
+                ethertype: EthernetProtocol::Arp,
+            }
+            .emit(&mut packet);
+            &mut frame.into_inner()[ETHERNET_HEADER_LEN + VLAN_HEADER_LEN..]
+        }
+        None => {
+            frame.set_ethertype(EthernetProtocol::Arp);
+            &mut frame.into_inner()[ETHERNET_HEADER_LEN..]
+        }
+    };
+    repr.emit(&mut ArpPacket::new_unchecked(payload));
+    bytes
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_vlan_tagging() {
+    let mut device = Loopback::new(Medium::Ethernet);
+    let mut iface = create_vlan(10, &mut device);
+    let mut sockets = SocketSet::new(vec![]);
+    let local_ip_addr = Ipv4Address::new(192, 168, 10, 1);
+
+    assert_eq!(iface.vlan_id(), Some(10));
+    assert_eq!(
+        iface.inner.ip_mtu(),
+        device.capabilities().ip_mtu() - VLAN_HEADER_LEN
+    );
+
+    // Untagged frames and frames of other VLANs are ignored.
+    for vlan_id in [None, Some(20)] {
+        let request = vlan_arp_request(vlan_id, local_ip_addr);
+        assert_eq!(
+            iface
+                .inner
+                .process_ethernet(&mut sockets, &request, &mut iface.fragments),
+            None
+        );
+    }
+
+    let request = vlan_arp_request(Some(10), local_ip_addr);
+    let reply = iface
+        .inner
+        .process_ethernet(&mut sockets, &request, &mut iface.fragments)
+        .unwrap();
+    let arp_repr = match reply {
+        EthernetPacket::Arp(arp_repr) => arp_repr,
+        _ => panic!("expected an ARP reply"),
+    };
+    let tx_token = device.transmit().unwrap();
+    iface.inner.dispatch(tx_token, reply, None).unwrap();
+
+    // The reply is tagged, with the addresses in front of the tag.
+    let (rx_token, _) = device.receive().unwrap();
+    rx_token
+        .consume(Instant::from_millis(0), |buffer| {
+            let frame = EthernetFrame::new_checked(&*buffer).unwrap();
+            assert_eq!(frame.src_addr(), EthernetAddress([0x02, 0, 0, 0, 0, 10]));
+            assert_eq!(
+                frame.dst_addr(),
+                EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00])
+            );
+            assert_eq!(frame.ethertype(), EthernetProtocol::Vlan);
+            let packet = VlanPacket::new_checked(frame.payload()).unwrap();
+            assert_eq!(
+                VlanRepr::parse(&packet).unwrap(),
+                VlanRepr {
+                    priority: 3,
+                    drop_eligible: false,
+                    vlan_id: 10,
+                    ethertype: EthernetProtocol::Arp,
+                }
+            );
+            let arp_packet = ArpPacket::new_checked(packet.payload()).unwrap();
+            assert_eq!(ArpRepr::parse(&arp_packet).unwrap(), arp_repr);
+            Ok(())
+        })
+        .unwrap();
+
+    // So are IP packets to the neighbor.
+    let icmp_repr = Icmpv4Repr::EchoRequest {
+        ident: 0x1234,
+        seq_no: 0x5432,
+        data: &[0xaa; 8],
+    };
+    let ipv4_repr = Ipv4Repr {
+        src_addr: local_ip_addr,
+        dst_addr: Ipv4Address::new(192, 168, 10, 2),
+        next_header: IpProtocol::Icmp,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 64,
+    };
+    let tx_token = device.transmit().unwrap();
+    iface
+        .inner
+        .dispatch_ip(tx_token, IpPacket::Icmpv4((ipv4_repr, icmp_repr)), None)
+        .unwrap();
+    let (rx_token, _) = device.receive().unwrap();
+    rx_token
+        .consume(Instant::from_millis(0), |buffer| {
+            let frame = EthernetFrame::new_checked(&*buffer).unwrap();
+            assert_eq!(frame.ethertype(), EthernetProtocol::Vlan);
+            let packet = VlanPacket::new_checked(frame.payload()).unwrap();
+            assert_eq!(packet.vlan_id(), 10);
+            assert_eq!(packet.ethertype(), EthernetProtocol::Ipv4);
+            let ipv4_packet = Ipv4Packet::new_checked(packet.payload()).unwrap();
+            assert_eq!(
+                Ipv4Repr::parse(&ipv4_packet, &ChecksumCapabilities::default()).unwrap(),
+                ipv4_repr
+            );
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_vlan_trunk() {
+    use crate::phy::VlanTrunk;
+    use crate::storage::{PacketBuffer, PacketMetadata};
+
+    let queue = || PacketBuffer::new(vec![PacketMetadata::EMPTY; 2], vec![0; 256]);
+    let mut trunk = VlanTrunk::new(
+        Loopback::new(Medium::Ethernet),
+        vec![(10, queue()), (20, queue())],
+    );
+    let mut iface10 = create_vlan(10, &mut trunk.port(10));
+    let mut iface20 = create_vlan(20, &mut trunk.port(20));
+    let mut sockets = SocketSet::new(vec![]);
+
+    // Frames of VLAN 30, without a port, are dropped.
+    for vlan_id in [30, 10, 20] {
+        let request = vlan_arp_request(Some(vlan_id), Ipv4Address::new(192, 168, vlan_id as u8, 1));
+        let tx_token = trunk.get_mut().transmit().unwrap();
+        tx_token
+            .consume(Instant::from_millis(0), request.len(), |buffer| {
+                buffer.copy_from_slice(&request);
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    let is_cached = |iface: &mut Interface, vlan_id: u8| {
+        iface
+            .inner
+            .lookup_hardware_addr(
+                MockTxToken,
+                &IpAddress::v4(192, 168, vlan_id, 1),
+                &IpAddress::v4(192, 168, vlan_id, 2),
+            )
+            .is_ok()
+    };
+
+    // Polling VLAN 20 queues the request of VLAN 10 on the way.
+    iface20
+        .poll(Instant::from_millis(0), &mut trunk.port(20), &mut sockets)
+        .unwrap();
+    assert!(is_cached(&mut iface20, 20));
+    assert!(!is_cached(&mut iface10, 10));
+
+    iface10
+        .poll(Instant::from_millis(0), &mut trunk.port(10), &mut sockets)
+        .unwrap();
+    assert!(is_cached(&mut iface10, 10));
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn create_zeroconf<'a>() -> (Interface<'a>, SocketSet<'a>, Loopback) {
+    let mut device = Loopback::new(Medium::Ethernet);
+    let iface_builder = InterfaceBuilder::new()
+        .hardware_addr(EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]).into())
+        .neighbor_cache(NeighborCache::new(BTreeMap::new()))
+        .ip_addrs(vec![IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 0)])
+        .zeroconf(true);
+
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    let iface_builder = iface_builder
+        .ipv4_reassembly_buffer(PacketAssemblerSet::new(vec![], BTreeMap::new()))
+        .ipv4_fragmentation_buffer(vec![]);
+
+    #[cfg(feature = "proto-igmp")]
+    let iface_builder = iface_builder.ipv4_multicast_groups(BTreeMap::new());
+    let iface = iface_builder.finalize(&mut device);
+
+    (iface, SocketSet::new(vec![]), device)
+}
+
+/// Poll the interface every 100 ms during `duration`, returning the last zeroconf event.
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn poll_zeroconf_for(
+    iface: &mut Interface,
+    sockets: &mut SocketSet,
+    device: &mut Loopback,
+    now: &mut crate::time::Instant,
+    duration: crate::time::Duration,
+) -> Option<ZeroconfEvent> {
+    let until = *now + duration;
+    let mut event = None;
+    while *now < until {
+        iface.poll(*now, device, sockets).unwrap();
+        event = iface.poll_zeroconf().or(event);
+        *now += crate::time::Duration::from_millis(100);
+    }
+    event
+}
+
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn conflicting_arp(iface: &mut Interface, sockets: &mut SocketSet, addr: Ipv4Address) {
+    let repr = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Reply,
+        source_hardware_addr: EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]),
+        source_protocol_addr: addr,
+        target_hardware_addr: EthernetAddress::BROADCAST,
+        target_protocol_addr: addr,
+    };
+    let mut bytes = vec![0u8; ETHERNET_HEADER_LEN + repr.buffer_len()];
+    let mut frame = EthernetFrame::new_unchecked(&mut bytes);
+    frame.set_dst_addr(EthernetAddress::BROADCAST);
+    frame.set_src_addr(EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]));
+    frame.set_ethertype(EthernetProtocol::Arp);
+    repr.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+    iface
+        .inner
+        .process_ethernet(sockets, &bytes, &mut iface.fragments);
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_zeroconf_claims_address() {
+    let (mut iface, mut sockets, mut device) = create_zeroconf();
+    let mut now = crate::time::Instant::ZERO;
+
+    // Probing takes at most 1 + 3 * 2 + 2 seconds.
+    let event = poll_zeroconf_for(
+        &mut iface,
+        &mut sockets,
+        &mut device,
+        &mut now,
+        crate::time::Duration::from_secs(10),
+    );
+    let cidr = match event {
+        Some(ZeroconfEvent::Configured(cidr)) => cidr,
+        event => panic!("unexpected event {:?}", event),
+    };
+    assert!(cidr.address().is_link_local());
+    assert_eq!(cidr.prefix_len(), 16);
+    assert!(iface.has_ip_addr(cidr.address()));
+
+    // Another interface with the same hardware address picks the same address.
+    let (mut other, mut sockets, mut device) = create_zeroconf();
+    let mut now = crate::time::Instant::ZERO;
+    poll_zeroconf_for(
+        &mut other,
+        &mut sockets,
+        &mut device,
+        &mut now,
+        crate::time::Duration::from_secs(10),
+    );
+    assert!(other.has_ip_addr(cidr.address()));
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_zeroconf_conflict() {
+    let (mut iface, mut sockets, mut device) = create_zeroconf();
+    let mut now = crate::time::Instant::ZERO;
+
+    // A host using the candidate address while probing makes us pick another one.
+    iface.poll(now, &mut device, &mut sockets).unwrap();
+    let candidate = iface.inner.zeroconf.as_ref().unwrap().addr;
+    conflicting_arp(&mut iface, &mut sockets, candidate);
+    let addr = iface.inner.zeroconf.as_ref().unwrap().addr;
+    assert_ne!(addr, candidate);
+
+    poll_zeroconf_for(
+        &mut iface,
+        &mut sockets,
+        &mut device,
+        &mut now,
+        crate::time::Duration::from_secs(10),
+    );
+    assert!(iface.has_ip_addr(addr));
+
+    // The address is defended once...
+    conflicting_arp(&mut iface, &mut sockets, addr);
+    assert_eq!(
+        poll_zeroconf_for(
+            &mut iface,
+            &mut sockets,
+            &mut device,
+            &mut now,
+            crate::time::Duration::from_secs(1),
+        ),
+        None
+    );
+    assert!(iface.has_ip_addr(addr));
+
+    // ...and given up on a second conflict within 10 seconds.
+    conflicting_arp(&mut iface, &mut sockets, addr);
+    assert_eq!(iface.poll_zeroconf(), Some(ZeroconfEvent::Deconfigured));
+    assert!(!iface.has_ip_addr(addr));
+
+    match poll_zeroconf_for(
+        &mut iface,
+        &mut sockets,
+        &mut device,
+        &mut now,
+        crate::time::Duration::from_secs(10),
+    ) {
+        Some(ZeroconfEvent::Configured(cidr)) => assert_ne!(cidr.address(), addr),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_zeroconf_gives_way() {
+    let (mut iface, mut sockets, mut device) = create_zeroconf();
+    let mut now = crate::time::Instant::ZERO;
+    poll_zeroconf_for(
+        &mut iface,
+        &mut sockets,
+        &mut device,
+        &mut now,
+        crate::time::Duration::from_secs(10),
+    );
+    let addr = iface.inner.zeroconf.as_ref().unwrap().addr;
+    assert!(iface.has_ip_addr(addr));
+
+    // A DHCP lease replaces the link-local address.
+    let lease = IpCidr::new(IpAddress::v4(192, 168, 1, 10), 24);
+    iface.update_ip_addrs(|addrs| addrs[0] = lease);
+    assert_eq!(
+        poll_zeroconf_for(
+            &mut iface,
+            &mut sockets,
+            &mut device,
+            &mut now,
+            crate::time::Duration::from_secs(10),
+        ),
+        Some(ZeroconfEvent::Deconfigured)
+    );
+    assert_eq!(iface.ip_addrs(), &[lease]);
+
+    // The same address is claimed again once the lease is gone.
+    iface.update_ip_addrs(|addrs| addrs[0] = IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 0));
+    assert_eq!(
+        poll_zeroconf_for(
+            &mut iface,
+            &mut sockets,
+            &mut device,
+            &mut now,
+            crate::time::Duration::from_secs(10),
+        ),
+        Some(ZeroconfEvent::Configured(Ipv4Cidr::new(addr, 16)))
+    );
+}
+
+/// Return the source and target addresses of the gratuitous ARP requests sent.
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn recv_arp_announcements(device: &mut Loopback) -> Vec<(EthernetAddress, Ipv4Address)> {
+    recv_all(device, Instant::from_millis(0))
+        .iter()
+        .map(|bytes| {
+            let frame = EthernetFrame::new_checked(&bytes[..]).unwrap();
+            assert_eq!(frame.dst_addr(), EthernetAddress::BROADCAST);
+            let arp_packet = ArpPacket::new_checked(frame.payload()).unwrap();
+            match ArpRepr::parse(&arp_packet).unwrap() {
+                ArpRepr::EthernetIpv4 {
+                    operation: ArpOperation::Request,
+                    source_hardware_addr,
+                    source_protocol_addr,
+                    target_protocol_addr,
+                    ..
+                } if source_protocol_addr == target_protocol_addr => {
+                    (source_hardware_addr, source_protocol_addr)
+                }
+                repr => panic!("unexpected ARP packet {:?}", repr),
+            }
+        })
+        .collect()
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_arp_announcements() {
+    let (mut iface, _sockets, mut device) = create_ethernet();
+    let local_hw_addr = EthernetAddress::default();
+    let ip_addr = Ipv4Address::new(192, 168, 1, 1);
+
+    // Addresses assigned when the interface is built aren't announced.
+    assert!(!iface.arp_egress(&mut device).unwrap());
+
+    iface.update_ip_addrs(|addrs| addrs[0] = IpCidr::new(ip_addr.into(), 24));
+    assert!(iface.arp_egress(&mut device).unwrap());
+    assert!(!iface.arp_egress(&mut device).unwrap());
+    iface.inner.now += Duration::from_secs(2);
+    assert!(iface.arp_egress(&mut device).unwrap());
+    assert!(!iface.arp_egress(&mut device).unwrap());
+    assert_eq!(
+        recv_arp_announcements(&mut device),
+        vec![(local_hw_addr, ip_addr), (local_hw_addr, ip_addr)]
+    );
+    assert_eq!(iface.inner.arp.poll_at(), None);
+
+    // Every address is announced again with a new hardware address.
+    let new_hw_addr = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]);
+    iface.set_hardware_addr(new_hw_addr.into());
+    while iface.arp_egress(&mut device).unwrap() {}
+    iface.inner.now += Duration::from_secs(2);
+    while iface.arp_egress(&mut device).unwrap() {}
+    assert_eq!(
+        recv_arp_announcements(&mut device),
+        vec![(new_hw_addr, ip_addr), (new_hw_addr, ip_addr)]
+    );
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_arp_conflict() {
+    let (mut iface, mut sockets, mut device) = create_ethernet();
+    let ip_addr = Ipv4Address::new(192, 168, 1, 1);
+    let remote_hw_addr = EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]);
+    iface.update_ip_addrs(|addrs| addrs[0] = IpCidr::new(ip_addr.into(), 24));
+    while iface.arp_egress(&mut device).unwrap() {}
+    iface.inner.now += Duration::from_secs(2);
+    while iface.arp_egress(&mut device).unwrap() {}
+    recv_all(&mut device, Instant::from_millis(0));
+    assert_eq!(iface.poll_arp_conflict(), None);
+
+    // Another host claims the address, which is defended once.
+    conflicting_arp(&mut iface, &mut sockets, ip_addr);
+    let conflict = ArpConflict {
+        addr: ip_addr,
+        hardware_addr: remote_hw_addr,
+        timestamp: iface.inner.now,
+    };
+    assert_eq!(iface.poll_arp_conflict(), Some(conflict));
+    assert_eq!(iface.poll_arp_conflict(), None);
+    while iface.arp_egress(&mut device).unwrap() {}
+    assert_eq!(
+        recv_arp_announcements(&mut device),
+        vec![(EthernetAddress::default(), ip_addr)]
+    );
+
+    // Further conflicts are reported, but not defended until 10 seconds have passed.
+    iface.inner.now += Duration::from_secs(5);
+    conflicting_arp(&mut iface, &mut sockets, ip_addr);
+    assert_eq!(
+        iface.poll_arp_conflict(),
+        Some(ArpConflict {
+            timestamp: iface.inner.now,
+            ..conflict
+        })
+    );
+    assert!(!iface.arp_egress(&mut device).unwrap());
+
+    iface.inner.now += Duration::from_secs(5);
+    conflicting_arp(&mut iface, &mut sockets, ip_addr);
+    assert!(iface.poll_arp_conflict().is_some());
+    assert!(iface.arp_egress(&mut device).unwrap());
+}
+
+#[test]
+#[cfg(all(feature = "medium-ethernet", feature = "proto-ipv4"))]
+fn test_neighbor_unreachability_detection() {
+    use crate::iface::neighbor::State as NeighborState;
+
+    let (mut iface, _sockets, mut device) = create_ethernet();
+    let local_ip_addr = IpAddress::v4(127, 0, 0, 1);
+    let remote_ip_addr = IpAddress::v4(127, 0, 0, 2);
+    let remote_hw_addr = EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]);
+
+    // Return the destination and target hardware addresses of the ARP requests sent.
+    let recv_arp_requests = |device: &mut Loopback| -> Vec<(EthernetAddress, EthernetAddress)> {
+        recv_all(device, Instant::from_millis(0))
+            .iter()
+            .map(|bytes| {
+                let frame = EthernetFrame::new_checked(&bytes[..]).unwrap();
+                let arp_packet = ArpPacket::new_checked(frame.payload()).unwrap();
+                match ArpRepr::parse(&arp_packet).unwrap() {
+                    ArpRepr::EthernetIpv4 {
+                        operation: ArpOperation::Request,
+                        target_hardware_addr,
+                        target_protocol_addr,
+                        ..
+                    } if IpAddress::Ipv4(target_protocol_addr) == remote_ip_addr => {
+                        (frame.dst_addr(), target_hardware_addr)
+                    }
+                    repr => panic!("unexpected ARP packet {:?}", repr),
+                }
+            })
+            .collect()
+    };
+
+    // A neighbor learned from its own request is stale, and probed once used.
+    iface.inner.neighbor_cache.as_mut().unwrap().fill(
+        remote_ip_addr,
+        remote_hw_addr.into(),
+        iface.inner.now,
+    );
+    let tx_token = device.transmit().unwrap();
+    assert_eq!(
+        iface
+            .inner
+            .lookup_hardware_addr(tx_token, &local_ip_addr, &remote_ip_addr)
+            .map(|(hardware_addr, _)| hardware_addr),
+        Ok(remote_hw_addr.into())
+    );
+    assert!(!iface.neighbor_egress(&mut device).unwrap());
+    iface.inner.now += Duration::from_secs(5);
+    assert!(iface.neighbor_egress(&mut device).unwrap());
+    assert_eq!(
+        recv_arp_requests(&mut device),
+        vec![(remote_hw_addr, remote_hw_addr)]
+    );
+
+    // Forward progress of a TCP connection confirms it.
+    iface.inner.confirm_reachable(&remote_ip_addr);
+    assert_eq!(
+        iface
+            .inner
+            .neighbor_cache
+            .as_ref()
+            .unwrap()
+            .state(&remote_ip_addr, iface.inner.now),
+        Some(NeighborState::Reachable)
+    );
+    assert!(!iface.neighbor_egress(&mut device).unwrap());
+
+    // A neighbor that doesn't answer probes is forgotten after a few seconds, and
+    // resolved again with a broadcast request.
+    iface.inner.now += Duration::from_secs(30);
+    let tx_token = device.transmit().unwrap();
+    assert!(iface
+        .inner
+        .lookup_hardware_addr(tx_token, &local_ip_addr, &remote_ip_addr)
+        .is_ok());
+    for _ in 0..8 {
+        iface.inner.now += Duration::from_secs(1);
+        while iface.neighbor_egress(&mut device).unwrap() {}
+    }
+    assert_eq!(
+        recv_arp_requests(&mut device),
+        vec![(remote_hw_addr, remote_hw_addr); 3]
+    );
+    let tx_token = device.transmit().unwrap();
+    assert_eq!(
+        iface
+            .inner
+            .lookup_hardware_addr(tx_token, &local_ip_addr, &remote_ip_addr)
+            .map(|(hardware_addr, _)| hardware_addr),
+        Err(Error::Unaddressable)
+    );
+    assert_eq!(
+        recv_arp_requests(&mut device),
+        vec![(EthernetAddress::BROADCAST, EthernetAddress::BROADCAST)]
+    );
+}
+
+#[test]
+#[cfg(all(
+    feature = "medium-ethernet",
+    feature = "proto-ipv4",
+    feature = "socket-udp"
+))]
+fn test_neighbor_hold_queue() {
+    use crate::storage::{PacketBuffer, PacketMetadata};
+    use crate::wire::{IpEndpoint, Ipv4Packet, UdpPacket};
+
+    let (mut iface, mut sockets, mut device) = create_ethernet();
+    iface.inner.hold_queues = vec![NeighborHoldQueue::new(PacketBuffer::new(
+        vec![PacketMetadata::EMPTY; 2],
+        vec![0; 128],
+    ))]
+    .into();
+    let local_ip_addr = Ipv4Address::new(127, 0, 0, 1);
+    let remote_ip_addr = Ipv4Address::new(127, 0, 0, 2);
+    let remote_hw_addr = EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]);
+
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+    );
+    socket.bind(1234).unwrap();
+    let handle = sockets.add(socket);
+
+    // The first datagram to a new neighbor is held while it's resolved.
+    let remote_endpoint = IpEndpoint::new(remote_ip_addr.into(), 5678);
+    sockets
+        .get_mut::<udp::Socket>(handle)
+        .send_slice(b"abc", remote_endpoint)
+        .unwrap();
+    assert!(iface.socket_egress(&mut device, &mut sockets));
+    let frames = recv_all(&mut device, Instant::from_millis(0));
+    assert_eq!(frames.len(), 1);
+    let frame = EthernetFrame::new_checked(&frames[0][..]).unwrap();
+    assert_eq!(frame.ethertype(), EthernetProtocol::Arp);
+    assert!(!iface.hold_egress(&mut device, &mut sockets).unwrap());
+
+    // It's sent once the neighbor answers.
+    let arp_repr = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Reply,
+        source_hardware_addr: remote_hw_addr,
+        source_protocol_addr: remote_ip_addr,
+        target_hardware_addr: EthernetAddress::default(),
+        target_protocol_addr: local_ip_addr,
+    };
+    let mut bytes = vec![0; arp_repr.buffer_len()];
+    arp_repr.emit(&mut ArpPacket::new_unchecked(&mut bytes[..]));
+    iface.inner.process_arp(iface.inner.now, &bytes);
+    assert!(iface.hold_egress(&mut device, &mut sockets).unwrap());
+    assert!(!iface.hold_egress(&mut device, &mut sockets).unwrap());
+
+    let frames = recv_all(&mut device, Instant::from_millis(0));
+    assert_eq!(frames.len(), 1);
+    let frame = EthernetFrame::new_checked(&frames[0][..]).unwrap();
+    assert_eq!(frame.dst_addr(), remote_hw_addr);
+    assert_eq!(frame.ethertype(), EthernetProtocol::Ipv4);
+    let ipv4_packet = Ipv4Packet::new_checked(frame.payload()).unwrap();
+    assert_eq!(ipv4_packet.dst_addr(), remote_ip_addr);
+    let udp_packet = UdpPacket::new_checked(ipv4_packet.payload()).unwrap();
+    assert_eq!(udp_packet.dst_port(), 5678);
+    assert_eq!(udp_packet.payload(), b"abc");
+
+    // A datagram to a neighbor that doesn't answer is dropped, and reported to the
+    // socket that sent it.
+    iface.inner.now += Duration::from_secs(1);
+    let unreachable_endpoint = IpEndpoint::new(Ipv4Address::new(127, 0, 0, 3).into(), 5678);
+    sockets
+        .get_mut::<udp::Socket>(handle)
+        .send_slice(b"def", unreachable_endpoint)
+        .unwrap();
+    assert!(iface.socket_egress(&mut device, &mut sockets));
+    for _ in 0..8 {
+        iface.inner.now += Duration::from_secs(1);
+        while iface.neighbor_egress(&mut device).unwrap() {}
+        while iface.hold_egress(&mut device, &mut sockets).unwrap() {}
+    }
+    let frames = recv_all(&mut device, Instant::from_millis(0));
+    assert!(frames.iter().all(|bytes| {
+        EthernetFrame::new_checked(&bytes[..]).unwrap().ethertype() == EthernetProtocol::Arp
+    }));
+
+    let socket = sockets.get_mut::<udp::Socket>(handle);
+    assert!(socket.can_recv());
+    assert_eq!(
+        socket.recv(),
+        Err(udp::RecvError::Unreachable(unreachable_endpoint))
+    );
+    assert_eq!(socket.recv(), Err(udp::RecvError::Exhausted));
+}
+
+#[cfg(feature = "proto-ipv4")]
+fn frag_required_packet(dst_addr: Ipv4Address, total_len: usize, next_hop_mtu: u16) -> Vec<u8> {
+    let icmp_repr = Icmpv4Repr::DstUnreachable {
+        reason: Icmpv4DstUnreachable::FragRequired,
+        header: Ipv4Repr {
+            src_addr: Ipv4Address::new(127, 0, 0, 1),
+            dst_addr,
+            next_header: IpProtocol::Udp,
+            payload_len: total_len - IPV4_HEADER_LEN,
+            hop_limit: 64,
+        },
+        data: &[0; 8],
+    };
+    let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+        src_addr: Ipv4Address::new(127, 0, 0, 254),
+        dst_addr: Ipv4Address::new(127, 0, 0, 1),
+        next_header: IpProtocol::Icmp,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 64,
+    });
+
+    let mut bytes = vec![0; ip_repr.buffer_len()];
+    ip_repr.emit(&mut bytes, &ChecksumCapabilities::default());
+    let mut icmp_packet = Icmpv4Packet::new_unchecked(&mut bytes[ip_repr.header_len()..]);
+    icmp_repr.emit(&mut icmp_packet, &ChecksumCapabilities::default());
+    icmp_packet.set_next_hop_mtu(next_hop_mtu);
+    icmp_packet.fill_checksum();
+    bytes
+}
+
+#[test]
+#[cfg(feature = "proto-ipv4")]
+fn test_pmtu_icmpv4_frag_required() {
+    let (mut iface, mut sockets, _device) = create();
+    let link_mtu = iface.inner.ip_mtu();
+    let remote_ip_addr = Ipv4Address::new(127, 0, 0, 2);
+    let other_ip_addr = Ipv4Address::new(127, 0, 0, 3);
+
+    let mut process = |iface: &mut Interface, bytes: &[u8]| {
+        let frame = Ipv4Packet::new_unchecked(bytes);
+        #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+        let reply = iface.inner.process_ipv4(&mut sockets, &frame, None);
+        #[cfg(feature = "proto-ipv4-fragmentation")]
+        let reply = iface.inner.process_ipv4(
+            &mut sockets,
+            &frame,
+            Some(&mut iface.fragments.ipv4_fragments),
+        );
+        assert_eq!(reply, None);
+    };
+
+    // The router reports its next-hop MTU.
+    process(
+        &mut iface,
+        &frag_required_packet(remote_ip_addr, link_mtu, 1400),
+    );
+    assert_eq!(iface.path_mtu(remote_ip_addr), 1400);
+    assert_eq!(iface.path_mtu(other_ip_addr), link_mtu);
+
+    // A larger MTU doesn't raise it.
+    process(
+        &mut iface,
+        &frag_required_packet(remote_ip_addr, 1400, 1450),
+    );
+    assert_eq!(iface.path_mtu(remote_ip_addr), 1400);
+
+    // A router that doesn't report it gets the next plateau below the packet size.
+    process(&mut iface, &frag_required_packet(other_ip_addr, 1500, 0));
+    assert_eq!(iface.path_mtu(other_ip_addr), 1492);
+
+    // The estimate ages back to the MTU of the link.
+    iface.inner.now += Duration::from_secs(600);
+    assert_eq!(iface.path_mtu(remote_ip_addr), link_mtu);
+    assert_eq!(iface.path_mtu(other_ip_addr), link_mtu);
+}
+
+#[test]
+#[cfg(all(feature = "proto-ipv4", feature = "socket-udp"))]
+fn test_pmtu_icmpv4_ignores_forged_errors() {
+    let (mut iface, mut sockets, _device) = create();
+    let link_mtu = iface.inner.ip_mtu();
+    let remote_ip_addr = Ipv4Address::new(127, 0, 0, 2);
+
+    // An error about a packet that we didn't send is ignored.
+    let mut bytes = frag_required_packet(remote_ip_addr, link_mtu, 1400);
+    {
+        let mut ip_packet = Ipv4Packet::new_unchecked(&mut bytes[..]);
+        let mut icmp_packet = Icmpv4Packet::new_unchecked(ip_packet.payload_mut());
+        let mut quoted_packet = Ipv4Packet::new_unchecked(icmp_packet.data_mut());
+        quoted_packet.set_src_addr(Ipv4Address::new(127, 0, 0, 99));
+        quoted_packet.fill_checksum();
+        icmp_packet.fill_checksum();
+    }
+    let frame = Ipv4Packet::new_unchecked(&bytes[..]);
+    #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+    iface.inner.process_ipv4(&mut sockets, &frame, None);
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    iface.inner.process_ipv4(
+        &mut sockets,
+        &frame,
+        Some(&mut iface.fragments.ipv4_fragments),
+    );
+    assert_eq!(iface.path_mtu(remote_ip_addr), link_mtu);
+
+    // One reporting a tiny MTU is clamped to the minimum.
+    let bytes = frag_required_packet(remote_ip_addr, link_mtu, 20);
+    let frame = Ipv4Packet::new_unchecked(&bytes[..]);
+    #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+    iface.inner.process_ipv4(&mut sockets, &frame, None);
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    iface.inner.process_ipv4(
+        &mut sockets,
+        &frame,
+        Some(&mut iface.fragments.ipv4_fragments),
+    );
+    assert_eq!(iface.path_mtu(remote_ip_addr), IPV4_MIN_MTU);
+}
+
+#[test]
+#[cfg(feature = "proto-ipv6")]
+fn test_pmtu_icmpv6_pkt_too_big() {
+    let (mut iface, mut sockets, _device) = create();
+    let link_mtu = iface.inner.ip_mtu();
+    let remote_ip_addr = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 2);
+
+    let mut process = |iface: &mut Interface, mtu: u32| {
+        let icmp_repr = Icmpv6Repr::PktTooBig {
+            mtu,
+            header: Ipv6Repr {
+                src_addr: Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 1),
+                dst_addr: remote_ip_addr,
+                next_header: IpProtocol::Udp,
+                payload_len: 8,
+                hop_limit: 64,
+            },
+            data: &[0; 8],
+        };
+        let ip_repr = Ipv6Repr {
+            src_addr: Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 0xfe),
+            dst_addr: Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 1),
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit: 64,
+        };
+        let mut bytes = vec![0; ip_repr.buffer_len() + icmp_repr.buffer_len()];
+        IpRepr::Ipv6(ip_repr).emit(&mut bytes, &ChecksumCapabilities::default());
+        icmp_repr.emit(
+            &ip_repr.src_addr.into(),
+            &ip_repr.dst_addr.into(),
+            &mut Icmpv6Packet::new_unchecked(&mut bytes[ip_repr.buffer_len()..]),
+            &ChecksumCapabilities::default(),
+        );
+        let frame = Ipv6Packet::new_unchecked(&bytes[..]);
+        assert_eq!(iface.inner.process_ipv6(&mut sockets, &frame), None);
+    };
+
+    process(&mut iface, 1400);
+    assert_eq!(iface.path_mtu(remote_ip_addr), 1400);
+
+    // A forged error can't lower it below the minimum IPv6 MTU.
+    process(&mut iface, 500);
+    assert_eq!(iface.path_mtu(remote_ip_addr), IPV6_MIN_MTU);
+
+    iface.inner.now += Duration::from_secs(600);
+    assert_eq!(iface.path_mtu(remote_ip_addr), link_mtu);
+}
+
+#[test]
+#[cfg(all(feature = "proto-ipv6", feature = "socket-udp"))]
+fn test_pmtu_udp_too_long() {
+    let (mut iface, mut sockets, mut device) = create();
+    let local_ip_addr = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 1);
+    let remote_ip_addr = Ipv6Address::new(0xfdbe, 0, 0, 0, 0, 0, 0, 2);
+    iface
+        .inner
+        .pmtu_update(local_ip_addr.into(), remote_ip_addr.into(), 1400);
+
+    let mut socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 1500]),
+    );
+    socket.bind((local_ip_addr, 68)).unwrap();
+    let remote = IpEndpoint::new(remote_ip_addr.into(), 67);
+    socket.send_slice(&[0; 1400], remote).unwrap();
+    assert!(!socket.can_send());
+    let handle = sockets.add(socket);
+
+    // The datagram that doesn't fit the path is dropped, and the loss reported.
+    iface.socket_egress(&mut device, &mut sockets);
+    let socket = sockets.get_mut::<udp::Socket>(handle);
+    assert!(socket.can_send());
+    assert_eq!(socket.recv(), Err(udp::RecvError::TooLong(remote)));
+    assert_eq!(socket.recv(), Err(udp::RecvError::Exhausted));
+}
+
+#[test]
+#[cfg(all(feature = "proto-ipv4", feature = "socket-dns", feature = "socket-tcp"))]
+fn test_dns_truncated_retry_over_tcp() {
+    let (mut iface, mut sockets, mut device) = create();
+    let server_addr = Ipv4Address::new(127, 0, 0, 2);
+
+    let tcp_socket = tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 1024]),
+        tcp::SocketBuffer::new(vec![0; 1024]),
+    );
+    let tcp_handle = sockets.add(tcp_socket);
+    let mut dns_socket = dns::Socket::new(&[server_addr.into()], vec![None]);
+    dns_socket.set_tcp_socket(Some(tcp_handle));
+    dns_socket
+        .start_query(iface.context(), "example.com", crate::wire::dns::Type::A)
+        .unwrap();
+    let dns_handle = sockets.add(dns_socket);
+
+    let mut query = None;
+    sockets
+        .get_mut::<dns::Socket>(dns_handle)
+        .dispatch(iface.context(), |_, (ip_repr, udp_repr, payload)| {
+            query = Some((ip_repr, udp_repr, payload.to_vec()));
+            Ok::<(), ()>(())
+        })
+        .unwrap();
+    let (ip_repr, udp_repr, payload) = query.unwrap();
+
+    let mut truncated = payload[..2].to_vec();
+    truncated.extend_from_slice(&[0x83, 0x80, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    truncated.extend_from_slice(&payload[12..]);
+    let ip_repr = IpRepr::new(
+        ip_repr.dst_addr(),
+        ip_repr.src_addr(),
+        IpProtocol::Udp,
+        8 + truncated.len(),
+        64,
+    );
+    let udp_repr = UdpRepr {
+        src_port: udp_repr.dst_port,
+        dst_port: udp_repr.src_port,
+    };
+    sockets.get_mut::<dns::Socket>(dns_handle).process(
+        iface.context(),
+        &ip_repr,
+        &udp_repr,
+        &truncated,
+    );
+
+    // The DNS socket opens the TCP socket it was given to the server.
+    iface.socket_egress(&mut device, &mut sockets);
+    let tcp_socket = sockets.get::<tcp::Socket>(tcp_handle);
+    assert_eq!(tcp_socket.state(), tcp::State::SynSent);
+    assert_eq!(
+        tcp_socket.remote_endpoint(),
+        Some(IpEndpoint::new(server_addr.into(), 53))
+    );
+}
+
+#[cfg(all(feature = "proto-ipv4", feature = "socket-udp", feature = "socket-tcp"))]
+fn process_icmpv4_error(
+    iface: &mut Interface,
+    sockets: &mut SocketSet,
+    reason: Icmpv4DstUnreachable,
+    header: Ipv4Repr,
+    data: &[u8],
+) {
+    let icmp_repr = Icmpv4Repr::DstUnreachable {
+        reason,
+        header,
+        data,
+    };
+    let ip_repr = IpRepr::Ipv4(Ipv4Repr {
+        src_addr: header.dst_addr,
+        dst_addr: header.src_addr,
+        next_header: IpProtocol::Icmp,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 64,
+    });
+    let mut bytes = vec![0; ip_repr.buffer_len()];
+    ip_repr.emit(&mut bytes, &ChecksumCapabilities::default());
+    icmp_repr.emit(
+        &mut Icmpv4Packet::new_unchecked(&mut bytes[ip_repr.header_len()..]),
+        &ChecksumCapabilities::default(),
+    );
+
+    let frame = Ipv4Packet::new_unchecked(&bytes[..]);
+    #[cfg(not(feature = "proto-ipv4-fragmentation"))]
+    let reply = iface.inner.process_ipv4(sockets, &frame, None);
+    #[cfg(feature = "proto-ipv4-fragmentation")]
+    let reply =
+        iface
+            .inner
+            .process_ipv4(sockets, &frame, Some(&mut iface.fragments.ipv4_fragments));
+    assert_eq!(reply, None);
+}
+
+#[test]
+#[cfg(all(feature = "proto-ipv4", feature = "socket-udp", feature = "socket-tcp"))]
+fn test_icmpv4_error_to_sockets() {
+    let (mut iface, mut sockets, mut device) = create();
+    let local_ip_addr = Ipv4Address::new(127, 0, 0, 1);
+    let remote_ip_addr = Ipv4Address::new(127, 0, 0, 2);
+
+    // Errors about datagrams are returned by the UDP socket that sent them.
+    let mut udp_socket = udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY], vec![0; 64]),
+    );
+    udp_socket.bind(1234).unwrap();
+    let udp_handle = sockets.add(udp_socket);
+
+    let header = Ipv4Repr {
+        src_addr: local_ip_addr,
+        dst_addr: remote_ip_addr,
+        next_header: IpProtocol::Udp,
+        payload_len: 11,
+        hop_limit: 64,
+    };
+    let data = [0x04, 0xd2, 0x16, 0x2e, 0x00, 0x0b, 0x00, 0x00];
+    for reason in [
+        Icmpv4DstUnreachable::PortUnreachable,
+        Icmpv4DstUnreachable::HostUnreachable,
+    ] {
+        process_icmpv4_error(&mut iface, &mut sockets, reason, header, &data);
+    }
+
+    let remote_endpoint = IpEndpoint::new(remote_ip_addr.into(), 5678);
+    let socket = sockets.get_mut::<udp::Socket>(udp_handle);
+    assert_eq!(socket.recv(), Err(udp::RecvError::Refused(remote_endpoint)));
+    assert_eq!(
+        socket.recv(),
+        Err(udp::RecvError::Unreachable(remote_endpoint))
+    );
+    assert_eq!(socket.recv(), Err(udp::RecvError::Exhausted));
+
+    // A connection attempt to a closed port is refused.
+    let mut tcp_socket = tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 64]),
+        tcp::SocketBuffer::new(vec![0; 64]),
+    );
+    tcp_socket
+        .connect(iface.context(), (remote_ip_addr, 80), 49500)
+        .unwrap();
+    let tcp_handle = sockets.add(tcp_socket);
+
+    #[cfg(feature = "medium-ethernet")]
+    iface.inner.neighbor_cache.as_mut().unwrap().fill(
+        remote_ip_addr.into(),
+        EthernetAddress([0x52, 0x54, 0x00, 0x00, 0x00, 0x00]).into(),
+        iface.inner.now,
+    );
+    assert!(iface.socket_egress(&mut device, &mut sockets));
+    let frames = recv_all(&mut device, Instant::from_millis(0));
+    assert_eq!(frames.len(), 1);
+    #[cfg(feature = "medium-ethernet")]
+    let ip_packet = Ipv4Packet::new_checked(&frames[0][EthernetFrame::<&[u8]>::header_len()..]);
+    #[cfg(not(feature = "medium-ethernet"))]
+    let ip_packet = Ipv4Packet::new_checked(&frames[0][..]);
+    let ip_packet = ip_packet.unwrap();
+    let header = Ipv4Repr {
+        src_addr: local_ip_addr,
+        dst_addr: remote_ip_addr,
+        next_header: IpProtocol::Tcp,
+        payload_len: ip_packet.payload().len(),
+        hop_limit: 64,
+    };
+    process_icmpv4_error(
+        &mut iface,
+        &mut sockets,
+        Icmpv4DstUnreachable::PortUnreachable,
+        header,
+        &ip_packet.payload()[..8],
+    );
+
+    let socket = sockets.get_mut::<tcp::Socket>(tcp_handle);
+    assert_eq!(socket.state(), tcp::State::Closed);
+    assert_eq!(socket.error(), Some(tcp::AbortError::Refused));
+}
+
+#[test]
+#[cfg(all(feature = "socket-mdns", feature = "proto-igmp"))]
+fn test_mdns_responder() {
+    let (mut iface, mut sockets, mut device) = create();
+    iface
+        .join_multicast_group(&mut device, dns::MDNS_IPV4_ADDR, Instant::ZERO)
+        .unwrap();
+    let handle = sockets.add(dns::Responder::new("smoltcp").unwrap());
+
+    // The responder probes and announces, ignoring its own packets when they come back.
+    let mut timestamp = Instant::ZERO;
+    while sockets.get::<dns::Responder>(handle).state() != dns::ResponderState::Running {
+        iface.poll(timestamp, &mut device, &mut sockets).unwrap();
+        timestamp += Duration::from_millis(250);
+        assert!(timestamp < Instant::from_secs(5));
+    }
+    assert_eq!(iface.poll_at(timestamp, &sockets), None);
+
+    // A query for the host name, from another host.
+    let mut payload = vec![
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    payload.extend_from_slice(b"\x07smoltcp\x05local\x00\x00\x01\x00\x01");
+    let udp_repr = UdpRepr {
+        src_port: 5353,
+        dst_port: 5353,
+    };
+    let ip_repr = IpRepr::new(
+        Ipv4Address::new(127, 0, 0, 2).into(),
+        dns::MDNS_IPV4_ADDR,
+        IpProtocol::Udp,
+        udp_repr.header_len() + payload.len(),
+        255,
+    );
+    let mut udp_bytes = vec![0u8; udp_repr.header_len() + payload.len()];
+    let mut packet = UdpPacket::new_unchecked(&mut udp_bytes);
+    udp_repr.emit(
+        &mut packet,
+        &ip_repr.src_addr(),
+        &ip_repr.dst_addr(),
+        payload.len(),
+        |buf| buf.copy_from_slice(&payload),
+        &ChecksumCapabilities::default(),
+    );
+
+    // It's handled, and answered.
+    assert_eq!(
+        iface.inner.process_udp(
+            &mut sockets,
+            ip_repr,
+            udp_repr,
+            false,
+            &payload,
+            packet.into_inner(),
+        ),
+        None
+    );
+    assert_eq!(
+        iface.poll_at(timestamp, &sockets),
+        Some(Instant::from_millis(0))
+    );
+}
@@ -0,0 +1,80 @@
+// Delivery of ICMP Destination Unreachable errors to the TCP and UDP sockets that sent
+// the packet they quote, as described in RFC 1122 § 3.2.2.1. The quoted IP header and
+// the first 8 octets of its payload identify the socket: a TCP connection attempt is
+// aborted, and a UDP socket returns the error from `recv`.
+
+use super::InterfaceInner;
+use super::SocketSet;
+
+#[cfg(feature = "socket-tcp")]
+use crate::socket::tcp;
+#[cfg(feature = "socket-udp")]
+use crate::socket::udp;
+use crate::socket::AnySocket;
+use crate::wire::*;
+
+/// What an ICMP error says about the destination of the packet it quotes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) enum Unreachable {
+    /// The destination doesn't listen on the port, or doesn't support the protocol.
+    Refused,
+    /// The destination network or host can't be reached.
+    Unreachable,
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Report an ICMP error about the packet with the `header` and the start of the
+    /// payload `data` to the socket that sent it, if any.
+    pub(super) fn process_unreachable(
+        &self,
+        sockets: &mut SocketSet,
+        header: IpRepr,
+        data: &[u8],
+        error: Unreachable,
+    ) {
+        // The error must be about a packet we sent, and quote its ports.
+        if !self.has_ip_addr(header.src_addr()) || data.len() < 8 {
+            return;
+        }
+
+        match header.next_header() {
+            #[cfg(feature = "socket-udp")]
+            IpProtocol::Udp => {
+                let packet = UdpPacket::new_unchecked(data);
+                let local_endpoint = IpEndpoint::new(header.src_addr(), packet.src_port());
+                let remote_endpoint = IpEndpoint::new(header.dst_addr(), packet.dst_port());
+                let error = match error {
+                    Unreachable::Refused => udp::RecvError::Refused(remote_endpoint),
+                    Unreachable::Unreachable => udp::RecvError::Unreachable(remote_endpoint),
+                };
+                sockets
+                    .items_mut()
+                    .filter_map(|item| udp::Socket::downcast_mut(&mut item.socket))
+                    .any(|socket| socket.process_error(local_endpoint, error));
+            }
+            #[cfg(feature = "socket-tcp")]
+            IpProtocol::Tcp => {
+                let packet = TcpPacket::new_unchecked(data);
+                let local_endpoint = IpEndpoint::new(header.src_addr(), packet.src_port());
+                let remote_endpoint = IpEndpoint::new(header.dst_addr(), packet.dst_port());
+                let error = match error {
+                    Unreachable::Refused => tcp::AbortError::Refused,
+                    Unreachable::Unreachable => tcp::AbortError::Unreachable,
+                };
+                sockets
+                    .items_mut()
+                    .filter_map(|item| tcp::Socket::downcast_mut(&mut item.socket))
+                    .any(|socket| {
+                        socket.process_error(
+                            local_endpoint,
+                            remote_endpoint,
+                            packet.seq_number(),
+                            error,
+                        )
+                    });
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+// IPv4 link-local address autoconfiguration, as described in RFC 3927. A candidate
+// address in 169.254/16 is probed for with ARP, claimed with announcements and defended
+// against conflicting hosts. It gives way to any routable IPv4 address, such as one
+// leased by a DHCP client, and comes back once that one is gone.
+
+use managed::ManagedSlice;
+
+use super::{Interface, InterfaceInner};
+use crate::phy::Device;
+use crate::rand::Rand;
+use crate::time::{Duration, Instant};
+use crate::wire::*;
+use crate::{Error, Result};
+
+// Constants from RFC 3927 § 9.
+const PROBE_WAIT: Duration = Duration::from_secs(1);
+const PROBE_NUM: u8 = 3;
+const PROBE_MIN: Duration = Duration::from_secs(1);
+const PROBE_MAX: Duration = Duration::from_secs(2);
+const ANNOUNCE_WAIT: Duration = Duration::from_secs(2);
+const ANNOUNCE_NUM: u8 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_CONFLICTS: u8 = 10;
+const RATE_LIMIT_INTERVAL: Duration = Duration::from_secs(60);
+const DEFEND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A change in the link-local address configured by zeroconf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The link-local address has been removed, after a conflict or to give way to a
+    /// routable address.
+    Deconfigured,
+    /// A link-local address has been claimed.
+    Configured(Ipv4Cidr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Phase {
+    /// Autoconfiguration hasn't started yet; it starts on the next poll.
+    Start,
+    /// A routable address is assigned to the interface.
+    Idle,
+    /// Checking that no other host uses the candidate address. Once all the probes are
+    /// sent, `probe_at` is when the address can be claimed.
+    Probing { probe_at: Instant, probes_sent: u8 },
+    /// The address is assigned, and being announced.
+    Announcing {
+        announce_at: Instant,
+        announces_sent: u8,
+    },
+    /// The address is assigned, and announced again when `defend` is set.
+    Bound { defend: bool },
+}
+
+#[derive(Debug)]
+pub(super) struct State {
+    phase: Phase,
+    /// The candidate, or assigned, address.
+    pub(super) addr: Ipv4Address,
+    /// Picks candidate addresses, seeded from the hardware address so that a host
+    /// tends to get the same address on every boot.
+    rand: Rand,
+    conflicts: u8,
+    defended_at: Option<Instant>,
+    config_changed: bool,
+}
+
+impl State {
+    pub(super) fn new() -> Self {
+        State {
+            phase: Phase::Start,
+            addr: Ipv4Address::UNSPECIFIED,
+            rand: Rand::new(0),
+            conflicts: 0,
+            defended_at: None,
+            config_changed: false,
+        }
+    }
+
+    /// Return the next time zeroconf needs to be polled, to send a probe or an
+    /// announcement, or to claim the address.
+    pub(super) fn poll_at(&self) -> Option<Instant> {
+        match self.phase {
+            Phase::Start => Some(Instant::ZERO),
+            Phase::Probing { probe_at, .. } => Some(probe_at),
+            Phase::Announcing { announce_at, .. } => Some(announce_at),
+            Phase::Bound { defend: true } => Some(Instant::ZERO),
+            Phase::Idle | Phase::Bound { defend: false } => None,
+        }
+    }
+
+    fn is_assigned(&self) -> bool {
+        matches!(self.phase, Phase::Announcing { .. } | Phase::Bound { .. })
+    }
+
+    /// Pick a new candidate address, in 169.254.1.0 to 169.254.254.255 (RFC 3927 § 2.1).
+    fn pick_addr(&mut self) {
+        let n = self.rand.rand_u32() % (254 * 256);
+        self.addr = Ipv4Address::new(169, 254, 1 + (n / 256) as u8, n as u8);
+    }
+
+    /// Start probing for the candidate address, after a random delay.
+    fn probe(&mut self, rand: &mut Rand, now: Instant) {
+        // RFC 3927 § 2.2.1: hosts with too many conflicts slow down.
+        let wait = if self.conflicts >= MAX_CONFLICTS {
+            RATE_LIMIT_INTERVAL
+        } else {
+            random_delay(rand, Duration::ZERO, PROBE_WAIT)
+        };
+        net_debug!("zeroconf: probing for {}", self.addr);
+        self.phase = Phase::Probing {
+            probe_at: now + wait,
+            probes_sent: 0,
+        };
+    }
+
+    /// Give up the candidate, or assigned, address for a new one.
+    fn conflict(&mut self, ip_addrs: &mut ManagedSlice<IpCidr>, rand: &mut Rand, now: Instant) {
+        net_debug!("zeroconf: {} is used by another host", self.addr);
+        if self.is_assigned() {
+            remove_ip_addr(ip_addrs, self.addr);
+            self.config_changed = true;
+        }
+        self.conflicts = self.conflicts.saturating_add(1);
+        self.defended_at = None;
+        self.pick_addr();
+        self.probe(rand, now);
+    }
+
+    fn poll(&mut self) -> Option<Event> {
+        if !self.config_changed {
+            None
+        } else if self.is_assigned() {
+            self.config_changed = false;
+            Some(Event::Configured(Ipv4Cidr::new(self.addr, 16)))
+        } else {
+            self.config_changed = false;
+            Some(Event::Deconfigured)
+        }
+    }
+}
+
+/// Return a random delay between `min` and `max`.
+fn random_delay(rand: &mut Rand, min: Duration, max: Duration) -> Duration {
+    let range = (max - min).total_millis() as u32;
+    min + Duration::from_millis((rand.rand_u32() % range) as u64)
+}
+
+/// Return whether `ip_addrs` has an IPv4 address other than link-local or loopback ones.
+fn has_routable_addr(ip_addrs: &ManagedSlice<IpCidr>) -> bool {
+    ip_addrs.iter().any(|cidr| match cidr {
+        IpCidr::Ipv4(cidr) => {
+            let addr = cidr.address();
+            addr.is_unicast() && !addr.is_link_local() && !addr.is_loopback()
+        }
+        #[allow(unreachable_patterns)]
+        _ => false,
+    })
+}
+
+/// Put `cidr` in the first unspecified IPv4 slot of `ip_addrs`.
+fn add_ip_addr(ip_addrs: &mut ManagedSlice<IpCidr>, cidr: Ipv4Cidr) -> bool {
+    let slot = ip_addrs.iter_mut().find(|slot| match slot {
+        IpCidr::Ipv4(slot) => slot.address().is_unspecified(),
+        #[allow(unreachable_patterns)]
+        _ => false,
+    });
+    match slot {
+        Some(slot) => {
+            *slot = IpCidr::Ipv4(cidr);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Free the slot of `addr` in `ip_addrs`, putting back an unspecified address.
+fn remove_ip_addr(ip_addrs: &mut ManagedSlice<IpCidr>, addr: Ipv4Address) {
+    for slot in ip_addrs.iter_mut() {
+        if slot.address() == IpAddress::Ipv4(addr) {
+            *slot = IpCidr::Ipv4(Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0));
+        }
+    }
+}
+
+impl<'a> InterfaceInner<'a> {
+    /// Look for another host using the candidate, or assigned, link-local address in
+    /// a received ARP packet.
+    pub(super) fn zeroconf_process_arp(
+        &mut self,
+        operation: ArpOperation,
+        source_protocol_addr: Ipv4Address,
+        target_protocol_addr: Ipv4Address,
+    ) {
+        let state = match self.zeroconf.as_mut() {
+            Some(state) => state,
+            None => return,
+        };
+        match state.phase {
+            // RFC 3927 § 2.2.1: another host probing for the same address counts as
+            // a conflict too.
+            Phase::Probing { .. }
+                if source_protocol_addr == state.addr
+                    || (operation == ArpOperation::Request
+                        && source_protocol_addr.is_unspecified()
+                        && target_protocol_addr == state.addr) =>
+            {
+                state.conflict(&mut self.ip_addrs, &mut self.rand, self.now);
+            }
+            // RFC 3927 § 2.5: defend the address once, and give it up if the other
+            // host insists.
+            Phase::Announcing { .. } | Phase::Bound { .. }
+                if source_protocol_addr == state.addr =>
+            {
+                match state.defended_at {
+                    Some(defended_at) if self.now < defended_at + DEFEND_INTERVAL => {
+                        state.conflict(&mut self.ip_addrs, &mut self.rand, self.now);
+                        self.flush_cache();
+                    }
+                    _ => {
+                        net_debug!("zeroconf: defending {}", state.addr);
+                        state.defended_at = Some(self.now);
+                        if let Phase::Bound { ref mut defend } = state.phase {
+                            *defend = true;
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<'a> Interface<'a> {
+    /// Get the next zeroconf event, if link-local address autoconfiguration is enabled.
+    ///
+    /// The address is assigned to the interface by zeroconf itself; the events tell the
+    /// application when it changes, for example to log it or to restart connections
+    /// bound to it.
+    pub fn poll_zeroconf(&mut self) -> Option<Event> {
+        self.inner.zeroconf.as_mut().and_then(|state| state.poll())
+    }
+
+    /// Depending on the zeroconf state, send ARP probes and announcements, claim the
+    /// candidate address, or give way to a routable address.
+    pub(super) fn zeroconf_egress<D>(&mut self, device: &mut D) -> Result<bool>
+    where
+        D: Device + ?Sized,
+    {
+        let now = self.inner.now;
+        let hardware_addr = match self.inner.hardware_addr {
+            Some(HardwareAddress::Ethernet(addr)) => addr,
+            _ => return Ok(false),
+        };
+        let InterfaceInner {
+            ref mut zeroconf,
+            ref mut ip_addrs,
+            ref mut rand,
+            ..
+        } = self.inner;
+        let state = match zeroconf.as_mut() {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        if has_routable_addr(ip_addrs) {
+            if state.phase != Phase::Idle {
+                net_debug!("zeroconf: giving way to a routable address");
+                if state.is_assigned() {
+                    remove_ip_addr(ip_addrs, state.addr);
+                    state.config_changed = true;
+                }
+                state.phase = Phase::Idle;
+            }
+            return Ok(false);
+        }
+        if state.is_assigned()
+            && !ip_addrs
+                .iter()
+                .any(|cidr| cidr.address() == IpAddress::Ipv4(state.addr))
+        {
+            // The address was removed behind our back; claim it again.
+            state.config_changed = true;
+            state.probe(rand, now);
+        }
+
+        let (source_addr, target_addr) = match state.phase {
+            Phase::Start => {
+                let mut seed = [0; 8];
+                seed[2..].copy_from_slice(hardware_addr.as_bytes());
+                state.rand = Rand::new(u64::from_be_bytes(seed));
+                state.pick_addr();
+                state.probe(rand, now);
+                return Ok(false);
+            }
+            Phase::Idle => {
+                // The routable address is gone, try to get the previous address back.
+                state.probe(rand, now);
+                return Ok(false);
+            }
+            Phase::Probing {
+                probe_at,
+                probes_sent,
+            } if now >= probe_at => {
+                if probes_sent == PROBE_NUM {
+                    if !add_ip_addr(ip_addrs, Ipv4Cidr::new(state.addr, 16)) {
+                        net_debug!("zeroconf: no free slot in ip_addrs for {}", state.addr);
+                        state.phase = Phase::Probing {
+                            probe_at: now + RATE_LIMIT_INTERVAL,
+                            probes_sent,
+                        };
+                        return Ok(false);
+                    }
+                    net_debug!("zeroconf: configured {}", state.addr);
+                    state.phase = Phase::Announcing {
+                        announce_at: now,
+                        announces_sent: 0,
+                    };
+                    state.config_changed = true;
+                    return Ok(false);
+                }
+                (Ipv4Address::UNSPECIFIED, state.addr)
+            }
+            Phase::Announcing { announce_at, .. } if now >= announce_at => (state.addr, state.addr),
+            Phase::Bound { defend: true } => (state.addr, state.addr),
+            _ => return Ok(false),
+        };
+
+        let tx_token = device.transmit().ok_or(Error::Exhausted)?;
+        self.inner
+            .broadcast_arp_request(tx_token, hardware_addr, source_addr, target_addr)?;
+
+        let state = self.inner.zeroconf.as_mut().unwrap();
+        state.phase = match state.phase {
+            Phase::Probing { probes_sent, .. } => Phase::Probing {
+                probe_at: if probes_sent + 1 < PROBE_NUM {
+                    now + random_delay(&mut self.inner.rand, PROBE_MIN, PROBE_MAX)
+                } else {
+                    now + ANNOUNCE_WAIT
+                },
+                probes_sent: probes_sent + 1,
+            },
+            Phase::Announcing { announces_sent, .. } if announces_sent + 1 < ANNOUNCE_NUM => {
+                Phase::Announcing {
+                    announce_at: now + ANNOUNCE_INTERVAL,
+                    announces_sent: announces_sent + 1,
+                }
+            }
+            _ => Phase::Bound { defend: false },
+        };
+        Ok(true)
+    }
+}
@@ -4,15 +4,26 @@ The `iface` module deals with the *network interfaces*. It filters incoming fram
 provides lookup and caching of hardware addresses, and handles management packets.
 */
 
+#[cfg(feature = "iface-filter")]
+mod filter;
 #[cfg(any(feature = "proto-ipv4", feature = "proto-sixlowpan"))]
 mod fragmentation;
 mod interface;
+#[cfg(feature = "iface-nat")]
+mod nat;
 #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
 mod neighbor;
 mod route;
 mod socket_meta;
 mod socket_set;
 
+#[cfg(feature = "iface-filter")]
+pub use self::filter::{
+    Action as FilterAction, Connection as FilterConnection, Direction as FilterDirection, Filter,
+    PortRange, Rule as FilterRule, TcpFlags,
+};
+#[cfg(feature = "iface-nat")]
+pub use self::nat::{Mapping as NatMapping, Table as NatTable};
 #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
 pub(crate) use self::neighbor::Answer as NeighborAnswer;
 #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
@@ -25,4 +36,29 @@ pub use socket_set::{SocketHandle, SocketSet, SocketStorage};
 #[cfg(any(feature = "proto-ipv4", feature = "proto-sixlowpan"))]
 pub use self::fragmentation::{PacketAssembler, PacketAssemblerSet as ReassemblyBuffer};
 
-pub use self::interface::{Interface, InterfaceBuilder, InterfaceInner as Context};
+pub use self::interface::{
+    Interface, InterfaceBuilder, InterfaceId, InterfaceInner as Context, PMTU_MAX_DESTINATION_COUNT,
+};
+
+#[cfg(feature = "iface-forwarding")]
+pub use self::interface::{ForwardingBuffer, ForwardingMetadata};
+
+#[cfg(feature = "medium-ethernet")]
+pub use self::interface::NeighborHoldQueue;
+
+#[cfg(feature = "proto-igmp")]
+pub use self::interface::{IgmpFilterMode, IGMP_MAX_SOURCE_COUNT, IGMP_MAX_SOURCE_FILTER_COUNT};
+
+#[cfg(all(
+    feature = "proto-ipv6",
+    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+))]
+pub use self::interface::{
+    Ipv6AddressState, SlaacAddress, SlaacConfig, SlaacEvent, DAD_MAX_ADDRESS_COUNT,
+    SLAAC_MAX_ADDRESS_COUNT,
+};
+
+#[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
+pub use self::interface::{
+    ArpConflict, ZeroconfEvent, ARP_MAX_ADDRESS_COUNT, ARP_MAX_CONFLICT_COUNT,
+};
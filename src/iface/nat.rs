@@ -0,0 +1,392 @@
+// Heads up! Before working on this file you should read, at least,
+// RFC 4787 and RFC 5382, which describe how a NAT should behave.
+
+use managed::ManagedMap;
+
+use crate::time::{Duration, Instant};
+use crate::wire::{IpProtocol, Ipv4Address};
+use crate::{Error, Result};
+
+/// A translation between a port of the public address and a private endpoint.
+///
+/// For ICMP, ports are echo identifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Mapping {
+    pub private_addr: Ipv4Address,
+    pub private_port: u16,
+    /// When the mapping expires unless it's used again. `None` means a static
+    /// port forward, which never expires.
+    pub expires_at: Option<Instant>,
+}
+
+impl Mapping {
+    fn is_expired(&self, timestamp: Instant) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= timestamp,
+            None => false,
+        }
+    }
+}
+
+/// A network address and port translation (NAPT) table.
+///
+/// Mappings are keyed by protocol and public port, and are endpoint-independent: once a
+/// private endpoint has been mapped, any remote host can reach it through the public port.
+///
+/// # Examples
+///
+/// On systems with heap, this table can be created with:
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use smoltcp::iface::NatTable;
+/// let mut nat_table = NatTable::new(BTreeMap::new());
+/// ```
+///
+/// On systems without heap, use:
+///
+/// ```rust
+/// use smoltcp::iface::NatTable;
+/// let mut nat_table_storage = [None; 64];
+/// let mut nat_table = NatTable::new(&mut nat_table_storage[..]);
+/// ```
+#[derive(Debug)]
+pub struct Table<'a> {
+    storage: ManagedMap<'a, (IpProtocol, u16), Mapping>,
+    first_port: u16,
+    last_port: u16,
+    next_port: u16,
+}
+
+impl<'a> Table<'a> {
+    /// Idle lifetime of a TCP mapping (RFC 5382 § 5).
+    pub(crate) const TCP_TIMEOUT: Duration = Duration::from_secs(7440);
+
+    /// Idle lifetime of a UDP mapping (RFC 4787 § 4.3).
+    pub(crate) const UDP_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// Idle lifetime of an ICMP echo mapping (RFC 5508 § 3.2).
+    pub(crate) const ICMP_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// First public port handed out by default, the start of the IANA dynamic range.
+    pub const DEFAULT_FIRST_PORT: u16 = 49152;
+
+    /// Last public port handed out by default.
+    pub const DEFAULT_LAST_PORT: u16 = 65535;
+
+    /// Create a translation table. The backing storage is cleared upon creation.
+    pub fn new<T>(storage: T) -> Table<'a>
+    where
+        T: Into<ManagedMap<'a, (IpProtocol, u16), Mapping>>,
+    {
+        let mut storage = storage.into();
+        storage.clear();
+
+        Table {
+            storage,
+            first_port: Self::DEFAULT_FIRST_PORT,
+            last_port: Self::DEFAULT_LAST_PORT,
+            next_port: Self::DEFAULT_FIRST_PORT,
+        }
+    }
+
+    /// Set the range of public ports handed out to private endpoints, `first` and `last`
+    /// included.
+    ///
+    /// Sockets of the translating interface shouldn't be bound to ports in this range.
+    ///
+    /// # Panics
+    /// This function panics if `first` is zero or greater than `last`.
+    pub fn set_port_range(&mut self, first: u16, last: u16) {
+        assert!(first != 0 && first <= last, "invalid NAT port range");
+        self.first_port = first;
+        self.last_port = last;
+        self.next_port = first;
+    }
+
+    /// Forward `public_port` of the public address to `private_port` of `private_addr`.
+    ///
+    /// `protocol` is one of TCP and UDP. Returns the mapping the port forward replaced,
+    /// if any, or `Err(Error::Exhausted)` if the table is full.
+    pub fn add_port_forward(
+        &mut self,
+        protocol: IpProtocol,
+        public_port: u16,
+        private_addr: Ipv4Address,
+        private_port: u16,
+    ) -> Result<Option<Mapping>> {
+        debug_assert!(matches!(protocol, IpProtocol::Tcp | IpProtocol::Udp));
+
+        let mapping = Mapping {
+            private_addr,
+            private_port,
+            expires_at: None,
+        };
+        match self.storage.insert((protocol, public_port), mapping) {
+            Ok(old) => Ok(old),
+            Err(_) => Err(Error::Exhausted),
+        }
+    }
+
+    /// Remove the port forward, or any other mapping, of `public_port`.
+    pub fn remove_port_forward(
+        &mut self,
+        protocol: IpProtocol,
+        public_port: u16,
+    ) -> Option<Mapping> {
+        self.storage.remove(&(protocol, public_port))
+    }
+
+    /// Return the mapping of `public_port`, which may have expired.
+    pub fn get(&self, protocol: IpProtocol, public_port: u16) -> Option<&Mapping> {
+        self.storage.get(&(protocol, public_port))
+    }
+
+    /// Remove the mappings that have been idle for too long.
+    pub fn expire(&mut self, timestamp: Instant) {
+        while let Some(key) = self.find_expired(timestamp) {
+            let _mapping = self.storage.remove(&key);
+            net_trace!("NAT: expired {} port {}", key.0, key.1);
+        }
+    }
+
+    /// Translate a packet received on `public_port` to its private endpoint.
+    pub(crate) fn inbound(
+        &mut self,
+        protocol: IpProtocol,
+        public_port: u16,
+        timestamp: Instant,
+    ) -> Option<(Ipv4Address, u16)> {
+        let mapping = self.storage.get_mut(&(protocol, public_port))?;
+        if mapping.is_expired(timestamp) {
+            return None;
+        }
+        if mapping.expires_at.is_some() {
+            mapping.expires_at = Some(timestamp + Self::timeout(protocol));
+        }
+        Some((mapping.private_addr, mapping.private_port))
+    }
+
+    /// Translate a packet sent from a private endpoint to a public port, mapping a new
+    /// one if needed.
+    pub(crate) fn outbound(
+        &mut self,
+        protocol: IpProtocol,
+        private_addr: Ipv4Address,
+        private_port: u16,
+        timestamp: Instant,
+    ) -> Option<u16> {
+        let expires_at = timestamp + Self::timeout(protocol);
+
+        let existing = self.storage.iter_mut().find(|((proto, _), mapping)| {
+            *proto == protocol
+                && mapping.private_addr == private_addr
+                && mapping.private_port == private_port
+                && !mapping.is_expired(timestamp)
+        });
+        if let Some(((_, public_port), mapping)) = existing {
+            if mapping.expires_at.is_some() {
+                mapping.expires_at = Some(expires_at);
+            }
+            return Some(*public_port);
+        }
+
+        // Keep the private port if we can, some protocols rely on it.
+        let public_port = if self.is_free(protocol, private_port, timestamp) {
+            private_port
+        } else {
+            self.free_port(protocol, timestamp)?
+        };
+
+        let mapping = Mapping {
+            private_addr,
+            private_port,
+            expires_at: Some(expires_at),
+        };
+        let key = (protocol, public_port);
+        if let Err((key, mapping)) = self.storage.insert(key, mapping) {
+            // The storage is full, make room if some mapping is stale.
+            let expired = self.find_expired(timestamp)?;
+            self.storage.remove(&expired);
+            if self.storage.insert(key, mapping).is_err() {
+                unreachable!()
+            }
+        }
+        net_trace!(
+            "NAT: mapped {} {}:{} to port {}",
+            protocol,
+            private_addr,
+            private_port,
+            public_port
+        );
+        Some(public_port)
+    }
+
+    fn timeout(protocol: IpProtocol) -> Duration {
+        match protocol {
+            IpProtocol::Tcp => Self::TCP_TIMEOUT,
+            IpProtocol::Udp => Self::UDP_TIMEOUT,
+            _ => Self::ICMP_TIMEOUT,
+        }
+    }
+
+    fn is_free(&self, protocol: IpProtocol, port: u16, timestamp: Instant) -> bool {
+        if port < self.first_port || port > self.last_port {
+            return false;
+        }
+        match self.storage.get(&(protocol, port)) {
+            Some(mapping) => mapping.is_expired(timestamp),
+            None => true,
+        }
+    }
+
+    fn free_port(&mut self, protocol: IpProtocol, timestamp: Instant) -> Option<u16> {
+        let count = (self.last_port - self.first_port) as u32 + 1;
+        for _ in 0..count {
+            let port = self.next_port;
+            self.next_port = if port == self.last_port {
+                self.first_port
+            } else {
+                port + 1
+            };
+            if self.is_free(protocol, port, timestamp) {
+                return Some(port);
+            }
+        }
+        net_debug!("NAT: no free {} port", protocol);
+        None
+    }
+
+    fn find_expired(&self, timestamp: Instant) -> Option<(IpProtocol, u16)> {
+        self.storage
+            .iter()
+            .find(|(_, mapping)| mapping.is_expired(timestamp))
+            .map(|(key, _)| *key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PRIVATE_ADDR_1: Ipv4Address = Ipv4Address([10, 0, 0, 2]);
+    const PRIVATE_ADDR_2: Ipv4Address = Ipv4Address([10, 0, 0, 3]);
+
+    #[test]
+    fn test_outbound_keeps_port() {
+        let mut storage = [None; 4];
+        let mut table = Table::new(&mut storage[..]);
+        let now = Instant::from_secs(0);
+
+        let port = table.outbound(IpProtocol::Udp, PRIVATE_ADDR_1, 50000, now);
+        assert_eq!(port, Some(50000));
+        // The same endpoint keeps its mapping.
+        let port = table.outbound(IpProtocol::Udp, PRIVATE_ADDR_1, 50000, now);
+        assert_eq!(port, Some(50000));
+        // Another endpoint with the same port gets another one.
+        let port = table.outbound(IpProtocol::Udp, PRIVATE_ADDR_2, 50000, now);
+        assert_eq!(port, Some(49152));
+        // Ports outside the range are never kept.
+        let port = table.outbound(IpProtocol::Tcp, PRIVATE_ADDR_2, 80, now);
+        assert_eq!(port, Some(49153));
+
+        assert_eq!(
+            table.inbound(IpProtocol::Udp, 49152, now),
+            Some((PRIVATE_ADDR_2, 50000))
+        );
+        assert_eq!(table.inbound(IpProtocol::Tcp, 50000, now), None);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut storage = [None; 2];
+        let mut table = Table::new(&mut storage[..]);
+        let now = Instant::from_secs(0);
+
+        table.outbound(IpProtocol::Icmp, PRIVATE_ADDR_1, 1, now);
+        table.outbound(IpProtocol::Udp, PRIVATE_ADDR_1, 1, now);
+        assert_eq!(
+            table.outbound(
+                IpProtocol::Udp,
+                PRIVATE_ADDR_2,
+                1,
+                now + Duration::from_secs(1)
+            ),
+            None
+        );
+
+        // Using the UDP mapping keeps it alive.
+        let later = now + Table::ICMP_TIMEOUT;
+        assert_eq!(
+            table.inbound(IpProtocol::Udp, 49153, later),
+            Some((PRIVATE_ADDR_1, 1))
+        );
+        assert_eq!(table.inbound(IpProtocol::Icmp, 49152, later), None);
+
+        // The expired ICMP mapping makes room for a new one.
+        assert_eq!(
+            table.outbound(IpProtocol::Udp, PRIVATE_ADDR_2, 1, later),
+            Some(49155)
+        );
+        assert!(table.get(IpProtocol::Icmp, 49152).is_none());
+
+        table.expire(later + Table::UDP_TIMEOUT);
+        assert!(table.get(IpProtocol::Udp, 49153).is_none());
+        assert!(table.get(IpProtocol::Udp, 49155).is_none());
+    }
+
+    #[test]
+    fn test_port_forward() {
+        let mut storage = [None; 2];
+        let mut table = Table::new(&mut storage[..]);
+        let now = Instant::from_secs(0);
+
+        assert!(table
+            .add_port_forward(IpProtocol::Tcp, 8080, PRIVATE_ADDR_1, 80)
+            .unwrap()
+            .is_none());
+
+        let later = now + Duration::from_secs(86400);
+        assert_eq!(
+            table.inbound(IpProtocol::Tcp, 8080, later),
+            Some((PRIVATE_ADDR_1, 80))
+        );
+        assert_eq!(
+            table.outbound(IpProtocol::Tcp, PRIVATE_ADDR_1, 80, later),
+            Some(8080)
+        );
+        table.expire(later);
+        assert!(table.get(IpProtocol::Tcp, 8080).is_some());
+
+        let mapping = table.remove_port_forward(IpProtocol::Tcp, 8080).unwrap();
+        assert_eq!(mapping.expires_at, None);
+        assert_eq!(table.inbound(IpProtocol::Tcp, 8080, later), None);
+    }
+
+    #[test]
+    fn test_port_range() {
+        let mut storage = [None; 4];
+        let mut table = Table::new(&mut storage[..]);
+        table.set_port_range(1000, 1001);
+        let now = Instant::from_secs(0);
+
+        assert_eq!(
+            table.outbound(IpProtocol::Udp, PRIVATE_ADDR_1, 1, now),
+            Some(1000)
+        );
+        assert_eq!(
+            table.outbound(IpProtocol::Udp, PRIVATE_ADDR_1, 2, now),
+            Some(1001)
+        );
+        assert_eq!(
+            table.outbound(IpProtocol::Udp, PRIVATE_ADDR_1, 3, now),
+            None
+        );
+        // Ports are per protocol.
+        assert_eq!(
+            table.outbound(IpProtocol::Tcp, PRIVATE_ADDR_1, 3, now),
+            Some(1000)
+        );
+    }
+}
@@ -6,17 +6,94 @@ use managed::ManagedMap;
 use crate::time::{Duration, Instant};
 use crate::wire::{HardwareAddress, IpAddress};
 
+/// The reachability state of a cached neighbor, as described in RFC 4861 § 7.3.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum State {
+    /// Address resolution is in progress, and the hardware address isn't known yet.
+    Incomplete,
+    /// The neighbor was recently known to be reachable.
+    Reachable,
+    /// The neighbor isn't known to be reachable anymore, but nothing is sent to it.
+    Stale,
+    /// Packets were sent to a stale neighbor, which is given some time to be confirmed
+    /// reachable by an upper-layer protocol before it's probed.
+    Delay,
+    /// The neighbor is being probed with unicast solicitations.
+    Probe,
+}
+
 /// A cached neighbor.
 ///
 /// A neighbor mapping translates from a protocol address to a hardware address,
-/// and contains the timestamp past which the mapping should be discarded.
+/// and contains the reachability state of the neighbor and the timestamp past which
+/// the mapping should be discarded.
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Neighbor {
-    hardware_addr: HardwareAddress,
+    /// The hardware address, unless the neighbor is `Incomplete`.
+    hardware_addr: Option<HardwareAddress>,
+    state: State,
+    /// When the `Reachable` state times out, or when the next solicitation is due
+    /// in the `Incomplete`, `Delay` and `Probe` states.
+    timer: Instant,
+    /// The number of solicitations sent in the `Incomplete` or `Probe` state.
+    solicits_sent: u8,
     expires_at: Instant,
 }
 
+impl Neighbor {
+    /// Return the state of the neighbor at `timestamp`.
+    fn state(&self, timestamp: Instant) -> State {
+        match self.state {
+            State::Reachable if timestamp >= self.timer => State::Stale,
+            state => state,
+        }
+    }
+
+    /// Return whether all the solicitations were sent without getting an answer.
+    fn is_unreachable(&self, timestamp: Instant) -> bool {
+        let max_solicits = match self.state {
+            State::Incomplete => Cache::MAX_MULTICAST_SOLICIT,
+            State::Probe => Cache::MAX_UNICAST_SOLICIT,
+            _ => return false,
+        };
+        self.solicits_sent >= max_solicits && timestamp >= self.timer
+    }
+
+    fn is_expired(&self, timestamp: Instant) -> bool {
+        timestamp >= self.expires_at || self.is_unreachable(timestamp)
+    }
+
+    /// Move on to the next state if a solicitation is due, and return its destination.
+    fn poll(&mut self, timestamp: Instant) -> Option<Option<HardwareAddress>> {
+        if timestamp < self.timer {
+            return None;
+        }
+        match self.state {
+            State::Delay => {
+                self.state = State::Probe;
+                self.solicits_sent = 0;
+            }
+            State::Incomplete if self.solicits_sent < Cache::MAX_MULTICAST_SOLICIT => (),
+            State::Probe if self.solicits_sent < Cache::MAX_UNICAST_SOLICIT => (),
+            _ => return None,
+        }
+        self.solicits_sent += 1;
+        self.timer = timestamp + Cache::RETRANS_TIME;
+        Some(self.hardware_addr)
+    }
+}
+
+/// A solicitation to send for a cached neighbor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct Solicit {
+    pub protocol_addr: IpAddress,
+    /// The hardware address to probe, or `None` to multicast the solicitation.
+    pub hardware_addr: Option<HardwareAddress>,
+}
+
 /// An answer to a neighbor cache lookup.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -26,7 +103,7 @@ pub(crate) enum Answer {
     /// The neighbor address is not in the cache, or has expired.
     NotFound,
     /// The neighbor address is not in the cache, or has expired,
-    /// and a lookup has been made recently.
+    /// and a lookup has been made recently or is in progress.
     RateLimited,
 }
 
@@ -74,6 +151,13 @@ impl<'a> Cache<'a> {
     /// Neighbor entry lifetime, in milliseconds.
     pub(crate) const ENTRY_LIFETIME: Duration = Duration::from_millis(60_000);
 
+    // Constants from RFC 4861 § 10, without the randomization of the reachable time.
+    pub(crate) const MAX_MULTICAST_SOLICIT: u8 = 3;
+    pub(crate) const MAX_UNICAST_SOLICIT: u8 = 3;
+    pub(crate) const REACHABLE_TIME: Duration = Duration::from_millis(30_000);
+    pub(crate) const RETRANS_TIME: Duration = Duration::from_millis(1_000);
+    pub(crate) const DELAY_FIRST_PROBE_TIME: Duration = Duration::from_millis(5_000);
+
     /// Default number of entries in the cache before GC kicks in
     #[cfg(any(feature = "std", feature = "alloc"))]
     pub(crate) const GC_THRESHOLD: usize = 1024;
@@ -112,6 +196,11 @@ impl<'a> Cache<'a> {
         }
     }
 
+    /// Fill the cache with a mapping that wasn't solicited, e.g. from a request of
+    /// the neighbor.
+    ///
+    /// The neighbor is `Stale` until its reachability is confirmed, unless it's
+    /// already known with the same hardware address.
     pub fn fill(
         &mut self,
         protocol_addr: IpAddress,
@@ -121,6 +210,104 @@ impl<'a> Cache<'a> {
         debug_assert!(protocol_addr.is_unicast());
         debug_assert!(hardware_addr.is_unicast());
 
+        let neighbor = match self.get(&protocol_addr, timestamp) {
+            Some(neighbor) if neighbor.hardware_addr == Some(hardware_addr) => Neighbor {
+                expires_at: timestamp + Self::ENTRY_LIFETIME,
+                ..*neighbor
+            },
+            _ => Neighbor {
+                hardware_addr: Some(hardware_addr),
+                state: State::Stale,
+                timer: timestamp,
+                solicits_sent: 0,
+                expires_at: timestamp + Self::ENTRY_LIFETIME,
+            },
+        };
+        self.insert(protocol_addr, neighbor, timestamp)
+    }
+
+    /// Mark a neighbor as `Reachable`, e.g. after a solicited answer or forward
+    /// progress of an upper-layer protocol.
+    ///
+    /// Without `hardware_addr`, only a neighbor whose hardware address is known
+    /// is confirmed.
+    pub(crate) fn confirm(
+        &mut self,
+        protocol_addr: IpAddress,
+        hardware_addr: Option<HardwareAddress>,
+        timestamp: Instant,
+    ) {
+        let hardware_addr = match hardware_addr.or_else(|| {
+            self.get(&protocol_addr, timestamp)
+                .and_then(|neighbor| neighbor.hardware_addr)
+        }) {
+            Some(hardware_addr) => hardware_addr,
+            None => return,
+        };
+        debug_assert!(protocol_addr.is_unicast());
+        debug_assert!(hardware_addr.is_unicast());
+
+        let neighbor = Neighbor {
+            hardware_addr: Some(hardware_addr),
+            state: State::Reachable,
+            timer: timestamp + Self::REACHABLE_TIME,
+            solicits_sent: 0,
+            expires_at: timestamp + Self::ENTRY_LIFETIME,
+        };
+        self.insert(protocol_addr, neighbor, timestamp)
+    }
+
+    /// Start the resolution of a neighbor, whose first solicitation was just sent.
+    pub(crate) fn resolve(&mut self, protocol_addr: IpAddress, timestamp: Instant) {
+        debug_assert!(protocol_addr.is_unicast());
+
+        if self.get(&protocol_addr, timestamp).is_some() {
+            return;
+        }
+        let neighbor = Neighbor {
+            hardware_addr: None,
+            state: State::Incomplete,
+            timer: timestamp + Self::RETRANS_TIME,
+            solicits_sent: 1,
+            expires_at: timestamp + Self::ENTRY_LIFETIME,
+        };
+        self.insert(protocol_addr, neighbor, timestamp)
+    }
+
+    /// Note that a packet is sent to a neighbor, which is probed if it's `Stale`
+    /// and doesn't get confirmed in the meantime.
+    pub(crate) fn used(&mut self, protocol_addr: &IpAddress, timestamp: Instant) {
+        if let Some(neighbor) = self.storage.get_mut(protocol_addr) {
+            if !neighbor.is_expired(timestamp) && neighbor.state(timestamp) == State::Stale {
+                net_trace!("neighbor {} is stale, delaying probe", protocol_addr);
+                neighbor.state = State::Delay;
+                neighbor.timer = timestamp + Self::DELAY_FIRST_PROBE_TIME;
+                // Keep the neighbor while it's probed.
+                let probed_until =
+                    neighbor.timer + Self::RETRANS_TIME * Self::MAX_UNICAST_SOLICIT as u32;
+                if neighbor.expires_at < probed_until {
+                    neighbor.expires_at = probed_until;
+                }
+            }
+        }
+    }
+
+    /// Return the reachability state of a neighbor, if it's in the cache.
+    pub(crate) fn state(&self, protocol_addr: &IpAddress, timestamp: Instant) -> Option<State> {
+        self.get(protocol_addr, timestamp)
+            .map(|neighbor| neighbor.state(timestamp))
+    }
+
+    fn get(&self, protocol_addr: &IpAddress, timestamp: Instant) -> Option<&Neighbor> {
+        self.storage
+            .get(protocol_addr)
+            .filter(|neighbor| !neighbor.is_expired(timestamp))
+    }
+
+    fn insert(&mut self, protocol_addr: IpAddress, neighbor: Neighbor, _timestamp: Instant) {
+        // Only needed to collect expired neighbors from owned storage.
+        #[cfg(any(feature = "std", feature = "alloc"))]
+        let timestamp = _timestamp;
         #[cfg(any(feature = "std", feature = "alloc"))]
         let current_storage_size = self.storage.len();
 
@@ -132,30 +319,30 @@ impl<'a> Cache<'a> {
                     let new_btree_map = map
                         .iter_mut()
                         .map(|(key, value)| (*key, *value))
-                        .filter(|(_, v)| timestamp < v.expires_at)
+                        .filter(|(_, v)| !v.is_expired(timestamp))
                         .collect();
 
                     *map = new_btree_map;
                 }
             }
         };
-        let neighbor = Neighbor {
-            expires_at: timestamp + Self::ENTRY_LIFETIME,
-            hardware_addr,
-        };
         match self.storage.insert(protocol_addr, neighbor) {
             Ok(Some(old_neighbor)) => {
-                if old_neighbor.hardware_addr != hardware_addr {
+                if old_neighbor.hardware_addr != neighbor.hardware_addr {
                     net_trace!(
-                        "replaced {} => {} (was {})",
+                        "replaced {} => {:?} (was {:?})",
                         protocol_addr,
-                        hardware_addr,
+                        neighbor.hardware_addr,
                         old_neighbor.hardware_addr
                     );
                 }
             }
             Ok(None) => {
-                net_trace!("filled {} => {} (was empty)", protocol_addr, hardware_addr);
+                net_trace!(
+                    "filled {} => {:?} (was empty)",
+                    protocol_addr,
+                    neighbor.hardware_addr
+                );
             }
             Err((protocol_addr, neighbor)) => {
                 // If we're going down this branch, it means that a fixed-size cache storage
@@ -181,9 +368,9 @@ impl<'a> Cache<'a> {
                 match self.storage.insert(protocol_addr, neighbor) {
                     Ok(None) => {
                         net_trace!(
-                            "filled {} => {} (evicted {} => {})",
+                            "filled {} => {:?} (evicted {} => {:?})",
                             protocol_addr,
-                            hardware_addr,
+                            neighbor.hardware_addr,
                             old_protocol_addr,
                             _old_neighbor.hardware_addr
                         );
@@ -198,14 +385,14 @@ impl<'a> Cache<'a> {
     pub(crate) fn lookup(&self, protocol_addr: &IpAddress, timestamp: Instant) -> Answer {
         assert!(protocol_addr.is_unicast());
 
-        if let Some(&Neighbor {
-            expires_at,
-            hardware_addr,
-        }) = self.storage.get(protocol_addr)
-        {
-            if timestamp < expires_at {
-                return Answer::Found(hardware_addr);
-            }
+        match self.get(protocol_addr, timestamp) {
+            Some(Neighbor {
+                hardware_addr: Some(hardware_addr),
+                ..
+            }) => return Answer::Found(*hardware_addr),
+            // The neighbor is being resolved.
+            Some(_) => return Answer::RateLimited,
+            None => (),
         }
 
         if timestamp < self.silent_until {
@@ -219,6 +406,44 @@ impl<'a> Cache<'a> {
         self.silent_until = timestamp + Self::SILENT_TIME;
     }
 
+    /// Forget the neighbors that didn't answer solicitations, and return the next
+    /// solicitation that's due, if any.
+    pub(crate) fn poll(&mut self, timestamp: Instant) -> Option<Solicit> {
+        while let Some(protocol_addr) = self
+            .storage
+            .iter()
+            .find(|(_, neighbor)| neighbor.is_unreachable(timestamp))
+            .map(|(protocol_addr, _)| *protocol_addr)
+        {
+            net_debug!("neighbor {} is unreachable", protocol_addr);
+            self.storage.remove(&protocol_addr);
+        }
+
+        self.storage
+            .iter_mut()
+            .filter(|(_, neighbor)| timestamp < neighbor.expires_at)
+            .find_map(|(protocol_addr, neighbor)| {
+                neighbor.poll(timestamp).map(|hardware_addr| Solicit {
+                    protocol_addr: *protocol_addr,
+                    hardware_addr,
+                })
+            })
+    }
+
+    /// Return the next time a solicitation is due, or a neighbor becomes unreachable.
+    pub(crate) fn poll_at(&self) -> Option<Instant> {
+        self.storage
+            .iter()
+            .filter(|(_, neighbor)| {
+                matches!(
+                    neighbor.state,
+                    State::Incomplete | State::Delay | State::Probe
+                )
+            })
+            .map(|(_, neighbor)| neighbor.timer)
+            .min()
+    }
+
     pub(crate) fn flush(&mut self) {
         self.storage.clear()
     }
@@ -397,4 +622,126 @@ mod test {
             .lookup(&MOCK_IP_ADDR_1, Instant::from_millis(0))
             .found());
     }
+
+    #[test]
+    fn test_resolve() {
+        let mut cache_storage = [Default::default(); 3];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        cache.resolve(MOCK_IP_ADDR_1, Instant::from_millis(0));
+        assert_eq!(
+            cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(0)),
+            Some(State::Incomplete)
+        );
+        assert_eq!(
+            cache.lookup(&MOCK_IP_ADDR_1, Instant::from_millis(0)),
+            Answer::RateLimited
+        );
+
+        // Solicitations are multicast again until the neighbor is given up on.
+        assert_eq!(cache.poll(Instant::from_millis(500)), None);
+        for timestamp in [1000, 2000] {
+            assert_eq!(
+                cache.poll(Instant::from_millis(timestamp)),
+                Some(Solicit {
+                    protocol_addr: MOCK_IP_ADDR_1,
+                    hardware_addr: None
+                })
+            );
+        }
+        assert_eq!(cache.poll_at(), Some(Instant::from_millis(3000)));
+        assert_eq!(cache.poll(Instant::from_millis(3000)), None);
+        assert_eq!(
+            cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(3000)),
+            None
+        );
+        assert_eq!(
+            cache.lookup(&MOCK_IP_ADDR_1, Instant::from_millis(3000)),
+            Answer::NotFound
+        );
+
+        // An answer completes the resolution.
+        cache.resolve(MOCK_IP_ADDR_1, Instant::from_millis(3000));
+        cache.confirm(MOCK_IP_ADDR_1, Some(HADDR_A), Instant::from_millis(3100));
+        assert_eq!(
+            cache.lookup(&MOCK_IP_ADDR_1, Instant::from_millis(3100)),
+            Answer::Found(HADDR_A)
+        );
+        assert_eq!(cache.poll_at(), None);
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let mut cache_storage = [Default::default(); 3];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        cache.confirm(MOCK_IP_ADDR_1, Some(HADDR_A), Instant::from_millis(0));
+        assert_eq!(
+            cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(0)),
+            Some(State::Reachable)
+        );
+        let stale_at = Instant::from_millis(0) + Cache::REACHABLE_TIME;
+        assert_eq!(cache.state(&MOCK_IP_ADDR_1, stale_at), Some(State::Stale));
+
+        // Stale neighbors aren't probed until they're used.
+        assert_eq!(cache.poll(stale_at), None);
+        cache.used(&MOCK_IP_ADDR_1, stale_at);
+        assert_eq!(cache.state(&MOCK_IP_ADDR_1, stale_at), Some(State::Delay));
+        let probe_at = stale_at + Cache::DELAY_FIRST_PROBE_TIME;
+        assert_eq!(cache.poll_at(), Some(probe_at));
+        for i in 0..3 {
+            assert_eq!(
+                cache.poll(probe_at + Cache::RETRANS_TIME * i),
+                Some(Solicit {
+                    protocol_addr: MOCK_IP_ADDR_1,
+                    hardware_addr: Some(HADDR_A)
+                })
+            );
+            assert_eq!(
+                cache.lookup(&MOCK_IP_ADDR_1, probe_at + Cache::RETRANS_TIME * i),
+                Answer::Found(HADDR_A)
+            );
+        }
+        assert_eq!(cache.poll(probe_at + Cache::RETRANS_TIME * 3), None);
+        assert_eq!(
+            cache.lookup(&MOCK_IP_ADDR_1, probe_at + Cache::RETRANS_TIME * 3),
+            Answer::NotFound
+        );
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut cache_storage = [Default::default(); 3];
+        let mut cache = Cache::new(&mut cache_storage[..]);
+
+        // Neighbors whose hardware address isn't known can't be confirmed.
+        cache.confirm(MOCK_IP_ADDR_1, None, Instant::from_millis(0));
+        assert_eq!(cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(0)), None);
+
+        cache.fill(MOCK_IP_ADDR_1, HADDR_A, Instant::from_millis(0));
+        assert_eq!(
+            cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(0)),
+            Some(State::Stale)
+        );
+        cache.used(&MOCK_IP_ADDR_1, Instant::from_millis(0));
+        cache.confirm(MOCK_IP_ADDR_1, None, Instant::from_millis(1000));
+        assert_eq!(
+            cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(1000)),
+            Some(State::Reachable)
+        );
+        assert_eq!(cache.poll_at(), None);
+
+        // Unsolicited information only changes the state of a neighbor if its
+        // hardware address changed.
+        cache.fill(MOCK_IP_ADDR_1, HADDR_A, Instant::from_millis(2000));
+        assert_eq!(
+            cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(2000)),
+            Some(State::Reachable)
+        );
+        cache.fill(MOCK_IP_ADDR_1, HADDR_B, Instant::from_millis(2000));
+        assert_eq!(
+            cache.state(&MOCK_IP_ADDR_1, Instant::from_millis(2000)),
+            Some(State::Stale)
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use crate::iface::InterfaceId;
 use crate::time::Instant;
 use core::ops::Bound;
 use managed::ManagedMap;
@@ -18,6 +19,9 @@ pub struct Route {
     pub preferred_until: Option<Instant>,
     /// `None` means "forever".
     pub expires_at: Option<Instant>,
+    /// The interface to send through, routing with its own table. `None` means the
+    /// interface owning this table.
+    pub interface: Option<InterfaceId>,
 }
 
 impl Route {
@@ -28,6 +32,7 @@ impl Route {
             via_router: gateway.into(),
             preferred_until: None,
             expires_at: None,
+            interface: None,
         }
     }
 
@@ -38,6 +43,7 @@ impl Route {
             via_router: gateway.into(),
             preferred_until: None,
             expires_at: None,
+            interface: None,
         }
     }
 }
@@ -108,6 +114,33 @@ impl<'a> Routes<'a> {
         }
     }
 
+    /// Route `cidr` through another interface (ie. "ip route add `cidr` dev `interface`").
+    ///
+    /// Packets forwarded to `cidr` are handed to that interface, which routes them with
+    /// its own table. On success, returns the previous route for `cidr`, if any.
+    pub fn add_interface_route(
+        &mut self,
+        cidr: IpCidr,
+        interface: InterfaceId,
+    ) -> Result<Option<Route>> {
+        let via_router = match cidr {
+            #[cfg(feature = "proto-ipv4")]
+            IpCidr::Ipv4(_) => IpAddress::Ipv4(Ipv4Address::UNSPECIFIED),
+            #[cfg(feature = "proto-ipv6")]
+            IpCidr::Ipv6(_) => IpAddress::Ipv6(Ipv6Address::UNSPECIFIED),
+        };
+        let route = Route {
+            via_router,
+            preferred_until: None,
+            expires_at: None,
+            interface: Some(interface),
+        };
+        match self.storage.insert(cidr, route) {
+            Ok(route) => Ok(route),
+            Err((_cidr, _route)) => Err(Error::Exhausted),
+        }
+    }
+
     /// Remove the default ipv4 gateway
     ///
     /// On success, returns the previous default route, if any.
@@ -126,7 +159,13 @@ impl<'a> Routes<'a> {
         self.storage.remove(&cidr)
     }
 
+    #[cfg(any(feature = "proto-ipv4", test))]
     pub(crate) fn lookup(&self, addr: &IpAddress, timestamp: Instant) -> Option<IpAddress> {
+        self.lookup_route(addr, timestamp)
+            .map(|route| route.via_router)
+    }
+
+    pub(crate) fn lookup_route(&self, addr: &IpAddress, timestamp: Instant) -> Option<&Route> {
         assert!(addr.is_unicast());
 
         let cidr = match addr {
@@ -149,7 +188,7 @@ impl<'a> Routes<'a> {
             }
 
             if prefix.contains_addr(addr) {
-                return Some(route.via_router);
+                return Some(route);
             }
         }
 
@@ -237,6 +276,7 @@ mod test {
             via_router: ADDR_1A.into(),
             preferred_until: None,
             expires_at: None,
+            interface: None,
         };
         routes.update(|storage| {
             storage.insert(cidr_1().into(), route).unwrap();
@@ -267,6 +307,7 @@ mod test {
             via_router: ADDR_2A.into(),
             preferred_until: Some(Instant::from_millis(10)),
             expires_at: Some(Instant::from_millis(10)),
+            interface: None,
         };
         routes.update(|storage| {
             storage.insert(cidr_2().into(), route2).unwrap();
@@ -314,4 +355,29 @@ mod test {
             Some(ADDR_2A.into())
         );
     }
+
+    #[test]
+    fn test_interface_route() {
+        let mut routes_storage = [None, None];
+        let mut routes = Routes::new(&mut routes_storage[..]);
+
+        assert!(routes
+            .add_interface_route(cidr_1().into(), InterfaceId(1))
+            .unwrap()
+            .is_none());
+        let route = routes
+            .lookup_route(&ADDR_1B.into(), Instant::from_millis(0))
+            .unwrap();
+        assert_eq!(route.interface, Some(InterfaceId(1)));
+        assert!(route.via_router.is_unspecified());
+        assert!(routes
+            .lookup_route(&ADDR_2A.into(), Instant::from_millis(0))
+            .is_none());
+
+        let previous = routes
+            .add_interface_route(cidr_1().into(), InterfaceId(2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(previous.interface, Some(InterfaceId(1)));
+    }
 }
@@ -115,6 +115,30 @@ impl<'a> SocketSet<'a> {
         }
     }
 
+    /// Get two distinct sockets from the set by their handles, as mutable.
+    ///
+    /// Returns `None` if the handles are the same, or either doesn't refer to a socket.
+    #[cfg(all(feature = "socket-dns", feature = "socket-tcp"))]
+    pub(crate) fn get_pair_mut(
+        &mut self,
+        a: SocketHandle,
+        b: SocketHandle,
+    ) -> Option<(&mut Item<'a>, &mut Item<'a>)> {
+        let (low, high) = (a.0.min(b.0), a.0.max(b.0));
+        if low == high || high >= self.sockets.len() {
+            return None;
+        }
+
+        let (head, tail) = self.sockets.split_at_mut(high);
+        let low = head[low].inner.as_mut()?;
+        let high = tail[0].inner.as_mut()?;
+        if a.0 < b.0 {
+            Some((low, high))
+        } else {
+            Some((high, low))
+        }
+    }
+
     /// Remove a socket from the set, without changing its state.
     ///
     /// # Panics
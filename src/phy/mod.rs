@@ -111,6 +111,8 @@ mod tracer;
     any(target_os = "linux", target_os = "android")
 ))]
 mod tuntap_interface;
+#[cfg(feature = "medium-ethernet")]
+mod vlan_trunk;
 
 #[cfg(all(
     any(feature = "phy-raw_socket", feature = "phy-tuntap_interface"),
@@ -131,6 +133,8 @@ pub use self::tracer::Tracer;
     any(target_os = "linux", target_os = "android")
 ))]
 pub use self::tuntap_interface::TunTapInterface;
+#[cfg(feature = "medium-ethernet")]
+pub use self::vlan_trunk::{VlanPort, VlanTrunk};
 
 /// A description of checksum behavior for a particular protocol.
 #[derive(Debug, Clone, Copy)]
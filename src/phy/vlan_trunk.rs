@@ -0,0 +1,169 @@
+use managed::ManagedSlice;
+
+use crate::phy::{self, Device, DeviceCapabilities};
+use crate::storage::PacketBuffer;
+use crate::time::Instant;
+use crate::wire::{EthernetFrame, EthernetProtocol, VlanPacket};
+use crate::{Error, Result};
+
+/// A VLAN trunk device.
+///
+/// A trunk shares an Ethernet device between several interfaces, each on its own
+/// IEEE 802.1Q VLAN, through the [port] of its VLAN ID. Interfaces are tagged with
+/// [`InterfaceBuilder::vlan_id`]; untagged frames go to the port of VLAN ID 0.
+///
+/// Frames are received in order: a port receiving a frame for another VLAN queues
+/// it for that port, and fails to consume it. Frames for VLANs without a port, or
+/// whose queue is full, are dropped.
+///
+/// [port]: #method.port
+/// [`InterfaceBuilder::vlan_id`]: ../iface/struct.InterfaceBuilder.html#method.vlan_id
+pub struct VlanTrunk<'a, D: Device> {
+    inner: D,
+    queues: ManagedSlice<'a, (u16, PacketBuffer<'a, ()>)>,
+}
+
+impl<'a, D: Device> VlanTrunk<'a, D> {
+    /// Create a trunk device, with a receive queue for each VLAN ID in `queues`.
+    pub fn new<Q>(inner: D, queues: Q) -> VlanTrunk<'a, D>
+    where
+        Q: Into<ManagedSlice<'a, (u16, PacketBuffer<'a, ()>)>>,
+    {
+        VlanTrunk {
+            inner,
+            queues: queues.into(),
+        }
+    }
+
+    /// Get the device for the interface on the VLAN `vlan_id`.
+    ///
+    /// # Panics
+    /// This function panics if there's no queue for `vlan_id`.
+    pub fn port(&mut self, vlan_id: u16) -> VlanPort<'_, 'a, D> {
+        let index = self
+            .queues
+            .iter()
+            .position(|(id, _)| *id == vlan_id)
+            .unwrap_or_else(|| panic!("no queue for VLAN ID {}", vlan_id));
+        VlanPort { trunk: self, index }
+    }
+
+    /// Get a reference to the underlying device.
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying device.
+    ///
+    /// It is inadvisable to directly read from the device as doing so will circumvent the queues.
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// Return the underlying device, consuming the trunk.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+/// The device of one VLAN of a [`VlanTrunk`].
+pub struct VlanPort<'t, 'a, D: Device> {
+    trunk: &'t mut VlanTrunk<'a, D>,
+    index: usize,
+}
+
+impl<'t, 'a, D: Device> Device for VlanPort<'t, 'a, D> {
+    type RxToken<'b>
+        = RxToken<'b, 'a, D::RxToken<'b>>
+    where
+        Self: 'b;
+    type TxToken<'b>
+        = D::TxToken<'b>
+    where
+        Self: 'b;
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.trunk.inner.capabilities()
+    }
+
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let VlanTrunk {
+            ref mut inner,
+            ref mut queues,
+        } = *self.trunk;
+        let index = self.index;
+        if !queues[index].1.is_empty() {
+            let tx_token = inner.transmit()?;
+            return Some((RxToken::Queued(&mut queues[index].1), tx_token));
+        }
+        inner.receive().map(move |(token, tx_token)| {
+            let rx_token = RxToken::Trunk {
+                token,
+                queues,
+                index,
+            };
+            (rx_token, tx_token)
+        })
+    }
+
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
+        self.trunk.inner.transmit()
+    }
+}
+
+#[doc(hidden)]
+pub enum RxToken<'b, 'a, Rx: phy::RxToken> {
+    Queued(&'b mut PacketBuffer<'a, ()>),
+    Trunk {
+        token: Rx,
+        queues: &'b mut ManagedSlice<'a, (u16, PacketBuffer<'a, ()>)>,
+        index: usize,
+    },
+}
+
+impl<'b, 'a, Rx: phy::RxToken> phy::RxToken for RxToken<'b, 'a, Rx> {
+    fn consume<R, F>(self, timestamp: Instant, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut [u8]) -> Result<R>,
+    {
+        match self {
+            RxToken::Queued(queue) => match queue.dequeue() {
+                Ok((_, buffer)) => f(buffer),
+                Err(_) => Err(Error::Exhausted),
+            },
+            RxToken::Trunk {
+                token,
+                queues,
+                index,
+            } => token.consume(timestamp, |buffer| {
+                let vlan_id = vlan_id_of(buffer)?;
+                if vlan_id == queues[index].0 {
+                    return f(buffer);
+                }
+
+                match queues.iter_mut().find(|(id, _)| *id == vlan_id) {
+                    Some((_, queue)) => match queue.enqueue(buffer.len(), ()) {
+                        Ok(queued) => {
+                            queued.copy_from_slice(buffer);
+                            net_trace!("vlan: queued a frame for VLAN {}", vlan_id);
+                        }
+                        Err(_) => net_trace!("vlan: queue of VLAN {} is full", vlan_id),
+                    },
+                    None => net_trace!("vlan: dropping a frame for VLAN {}", vlan_id),
+                }
+                Err(Error::Dropped)
+            }),
+        }
+    }
+}
+
+/// Return the VLAN ID of an Ethernet frame, 0 if it's untagged or priority tagged.
+fn vlan_id_of(buffer: &[u8]) -> Result<u16> {
+    let frame = EthernetFrame::new_checked(buffer).map_err(|_| Error::Truncated)?;
+    match frame.ethertype() {
+        EthernetProtocol::Vlan => VlanPacket::new_checked(frame.payload())
+            .map(|packet| packet.vlan_id())
+            .map_err(|_| Error::Truncated),
+        _ => Ok(0),
+    }
+}
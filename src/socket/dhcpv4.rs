@@ -16,6 +16,10 @@ use super::WakerRegistration;
 
 use super::PollAt;
 
+mod server;
+
+pub use self::server::{Lease, LeaseState, Server, ServerConfig};
+
 const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(120);
 
 const DEFAULT_PARAMETER_REQUEST_LIST: &[u8] = &[
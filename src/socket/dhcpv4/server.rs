@@ -0,0 +1,740 @@
+// A DHCPv4 server handing out leases from a single address pool, as described in
+// RFC 2131 § 4.3. Replies are built while processing the client's message, so the
+// server never has anything to send on its own.
+
+use managed::ManagedMap;
+
+use crate::iface::Context;
+use crate::socket::PollAt;
+use crate::time::{Duration, Instant};
+use crate::wire::{
+    DhcpMessageType, DhcpPacket, DhcpRepr, EthernetAddress, IpProtocol, Ipv4Address, Ipv4Repr,
+    UdpRepr, DHCP_CLIENT_PORT, DHCP_MAX_DNS_SERVER_COUNT, DHCP_SERVER_PORT, UDP_HEADER_LEN,
+};
+use heapless::Vec;
+
+/// How long an offered address is set aside for the client it was offered to.
+const OFFER_DURATION: Duration = Duration::from_secs(60);
+
+/// Configuration of a DHCPv4 server.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServerConfig {
+    /// Address of the server, used as the source of replies and as the server identifier.
+    pub server_ip: Ipv4Address,
+    /// Subnet mask handed out to clients.
+    pub subnet_mask: Ipv4Address,
+    /// Router address handed out to clients, also known as default gateway.
+    pub router: Option<Ipv4Address>,
+    /// DNS servers handed out to clients.
+    pub dns_servers: Vec<Ipv4Address, DHCP_MAX_DNS_SERVER_COUNT>,
+    /// First address of the pool.
+    pub pool_start: Ipv4Address,
+    /// Last address of the pool, inclusive.
+    pub pool_end: Ipv4Address,
+    /// Duration of the leases granted to clients.
+    pub lease_duration: Duration,
+}
+
+/// The state of a lease.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LeaseState {
+    /// The address has been offered to the client, which hasn't requested it yet.
+    Offered,
+    /// The address has been acknowledged to the client.
+    Bound,
+    /// The client found the address to be already in use, so it isn't handed out
+    /// until the lease expires.
+    Declined,
+}
+
+/// An address handed out to a client.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Lease {
+    /// Hardware address of the client.
+    pub hardware_addr: EthernetAddress,
+    /// State of the lease.
+    pub state: LeaseState,
+    /// When the lease expires, after which the address can be handed out again.
+    pub expires_at: Instant,
+}
+
+impl Lease {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at <= now
+    }
+
+    fn is_held_by(&self, hardware_addr: EthernetAddress, now: Instant) -> bool {
+        !self.is_expired(now)
+            && self.state != LeaseState::Declined
+            && self.hardware_addr == hardware_addr
+    }
+}
+
+/// A DHCPv4 server socket.
+///
+/// The server hands out addresses from the pool in its [`ServerConfig`], keeping track
+/// of them in the lease storage provided to [`Server::new`]. Clients can also be given
+/// a fixed address with [`Server::set_reservations`]; reserved addresses don't need to
+/// be in the pool, and are never handed out to other clients.
+///
+/// [`ServerConfig`]: struct.ServerConfig.html
+/// [`Server::new`]: #method.new
+/// [`Server::set_reservations`]: #method.set_reservations
+#[derive(Debug)]
+pub struct Server<'a> {
+    config: ServerConfig,
+    leases: ManagedMap<'a, Ipv4Address, Lease>,
+    reservations: ManagedMap<'a, EthernetAddress, Ipv4Address>,
+}
+
+impl<'a> Server<'a> {
+    /// Create a DHCPv4 server socket, storing its leases in `leases`.
+    pub fn new<L>(config: ServerConfig, leases: L) -> Self
+    where
+        L: Into<ManagedMap<'a, Ipv4Address, Lease>>,
+    {
+        Server {
+            config,
+            leases: leases.into(),
+            reservations: ManagedMap::Borrowed(&mut []),
+        }
+    }
+
+    /// Set the static reservations of the server, mapping client hardware addresses to
+    /// the address they're always given.
+    pub fn set_reservations<R>(&mut self, reservations: R)
+    where
+        R: Into<ManagedMap<'a, EthernetAddress, Ipv4Address>>,
+    {
+        self.reservations = reservations.into();
+    }
+
+    /// Return the configuration of the server.
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Return the lease of `addr`, if any. The lease may have expired.
+    pub fn lease(&self, addr: Ipv4Address) -> Option<&Lease> {
+        self.leases.get(&addr)
+    }
+
+    /// Return an iterator over the leases of the server, some of which may have expired.
+    pub fn leases(&self) -> impl Iterator<Item = (&Ipv4Address, &Lease)> {
+        self.leases.iter()
+    }
+
+    pub(crate) fn poll_at(&self, _cx: &mut Context) -> PollAt {
+        PollAt::Ingress
+    }
+
+    pub(crate) fn process(
+        &mut self,
+        cx: &mut Context,
+        ip_repr: &Ipv4Repr,
+        repr: &UdpRepr,
+        payload: &[u8],
+    ) -> Option<(Ipv4Repr, UdpRepr, DhcpRepr<'static>)> {
+        let src_ip = ip_repr.src_addr;
+
+        // This is enforced in interface.rs.
+        assert!(repr.src_port == DHCP_CLIENT_PORT && repr.dst_port == DHCP_SERVER_PORT);
+
+        let dhcp_packet = match DhcpPacket::new_checked(payload) {
+            Ok(dhcp_packet) => dhcp_packet,
+            Err(e) => {
+                net_debug!("DHCP invalid pkt from {}: {:?}", src_ip, e);
+                return None;
+            }
+        };
+        let dhcp_repr = match DhcpRepr::parse(&dhcp_packet) {
+            Ok(dhcp_repr) => dhcp_repr,
+            Err(e) => {
+                net_debug!("DHCP error parsing pkt from {}: {:?}", src_ip, e);
+                return None;
+            }
+        };
+
+        let now = cx.now();
+        let client = dhcp_repr.client_hardware_address;
+        net_debug!(
+            "DHCP recv {:?} from {}: {:?}",
+            dhcp_repr.message_type,
+            src_ip,
+            dhcp_repr
+        );
+
+        match dhcp_repr.message_type {
+            DhcpMessageType::Discover => {
+                let addr = match self.select_address(client, dhcp_repr.requested_ip, now) {
+                    Some(addr) => addr,
+                    None => {
+                        net_debug!("DHCP no address available for {}", client);
+                        return None;
+                    }
+                };
+                let lease = Lease {
+                    hardware_addr: client,
+                    state: LeaseState::Offered,
+                    expires_at: now + OFFER_DURATION,
+                };
+                self.insert_lease(addr, lease, now)?;
+                Some(self.reply(&dhcp_repr, DhcpMessageType::Offer, addr))
+            }
+            DhcpMessageType::Request => {
+                let addr = match (dhcp_repr.server_identifier, dhcp_repr.requested_ip) {
+                    // SELECTING state, the client chose another server.
+                    (Some(server_identifier), _) if server_identifier != self.config.server_ip => {
+                        self.withdraw_offer(client);
+                        return None;
+                    }
+                    // SELECTING state, the client chose our offer.
+                    (Some(_), Some(addr)) => addr,
+                    (Some(_), None) => return None,
+                    // INIT-REBOOT state.
+                    (None, Some(addr)) => addr,
+                    // RENEWING or REBINDING state.
+                    (None, None) if !dhcp_repr.client_ip.is_unspecified() => dhcp_repr.client_ip,
+                    (None, None) => return None,
+                };
+
+                if !self.is_assignable(addr, client, now) {
+                    net_debug!("DHCP can't assign {} to {}", addr, client);
+                    return Some(self.reply(
+                        &dhcp_repr,
+                        DhcpMessageType::Nak,
+                        Ipv4Address::UNSPECIFIED,
+                    ));
+                }
+
+                let lease = Lease {
+                    hardware_addr: client,
+                    state: LeaseState::Bound,
+                    expires_at: now + self.config.lease_duration,
+                };
+                self.insert_lease(addr, lease, now)?;
+                Some(self.reply(&dhcp_repr, DhcpMessageType::Ack, addr))
+            }
+            DhcpMessageType::Release => {
+                let addr = dhcp_repr.client_ip;
+                if self.is_for_us(&dhcp_repr) && self.is_held_by(addr, client, now) {
+                    net_debug!("DHCP released {} from {}", addr, client);
+                    self.leases.remove(&addr);
+                }
+                None
+            }
+            DhcpMessageType::Decline => {
+                let addr = dhcp_repr.requested_ip?;
+                if self.is_for_us(&dhcp_repr) && self.is_held_by(addr, client, now) {
+                    net_debug!("DHCP {} declined {}", client, addr);
+                    let lease = self.leases.get_mut(&addr).unwrap();
+                    lease.state = LeaseState::Declined;
+                    lease.expires_at = now + self.config.lease_duration;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn is_for_us(&self, dhcp_repr: &DhcpRepr) -> bool {
+        dhcp_repr.server_identifier == Some(self.config.server_ip)
+    }
+
+    fn in_pool(&self, addr: Ipv4Address) -> bool {
+        self.config.pool_start <= addr && addr <= self.config.pool_end
+    }
+
+    fn reservation(&self, client: EthernetAddress) -> Option<Ipv4Address> {
+        self.reservations.get(&client).cloned()
+    }
+
+    fn is_reserved(&self, addr: Ipv4Address) -> bool {
+        self.reservations
+            .iter()
+            .any(|(_, reserved)| *reserved == addr)
+    }
+
+    fn is_held_by(&self, addr: Ipv4Address, client: EthernetAddress, now: Instant) -> bool {
+        match self.leases.get(&addr) {
+            Some(lease) => lease.is_held_by(client, now),
+            None => false,
+        }
+    }
+
+    fn is_free(&self, addr: Ipv4Address, now: Instant) -> bool {
+        match self.leases.get(&addr) {
+            Some(lease) => lease.is_expired(now),
+            None => true,
+        }
+    }
+
+    /// Return whether `addr` can be leased to `client`.
+    fn is_assignable(&self, addr: Ipv4Address, client: EthernetAddress, now: Instant) -> bool {
+        if let Some(reserved) = self.reservation(client) {
+            return addr == reserved
+                && (self.is_free(addr, now) || self.is_held_by(addr, client, now));
+        }
+        if self.is_held_by(addr, client, now) {
+            return true;
+        }
+        self.in_pool(addr) && !self.is_reserved(addr) && self.is_free(addr, now)
+    }
+
+    /// Pick the address to offer to `client`, see RFC 2131 § 4.3.1.
+    fn select_address(
+        &self,
+        client: EthernetAddress,
+        requested_ip: Option<Ipv4Address>,
+        now: Instant,
+    ) -> Option<Ipv4Address> {
+        if let Some(reserved) = self.reservation(client) {
+            return Some(reserved).filter(|addr| self.is_assignable(*addr, client, now));
+        }
+
+        let current = self
+            .leases
+            .iter()
+            .find(|(_, lease)| lease.is_held_by(client, now))
+            .map(|(addr, _)| *addr);
+        if current.is_some() {
+            return current;
+        }
+
+        if let Some(addr) = requested_ip {
+            if self.is_assignable(addr, client, now) {
+                return Some(addr);
+            }
+        }
+
+        let start = u32::from_be_bytes(self.config.pool_start.0);
+        let end = u32::from_be_bytes(self.config.pool_end.0);
+        (start..=end)
+            .map(|addr| Ipv4Address(addr.to_be_bytes()))
+            .find(|addr| self.is_assignable(*addr, client, now))
+    }
+
+    /// Lease `addr` to `client`, replacing the lease the client held before.
+    fn insert_lease(&mut self, addr: Ipv4Address, lease: Lease, now: Instant) -> Option<()> {
+        let previous = self
+            .leases
+            .iter()
+            .find(|(probe, old)| **probe != addr && old.is_held_by(lease.hardware_addr, now))
+            .map(|(addr, _)| *addr);
+        if let Some(previous) = previous {
+            self.leases.remove(&previous);
+        }
+
+        if let Err((addr, lease)) = self.leases.insert(addr, lease) {
+            // Make room by forgetting a lease that has expired.
+            let expired = self
+                .leases
+                .iter()
+                .find(|(_, old)| old.is_expired(now))
+                .map(|(addr, _)| *addr);
+            match expired {
+                Some(expired) => {
+                    self.leases.remove(&expired);
+                    self.leases.insert(addr, lease).ok()?;
+                }
+                None => {
+                    net_debug!("DHCP lease storage is full");
+                    return None;
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn withdraw_offer(&mut self, client: EthernetAddress) {
+        let offered = self
+            .leases
+            .iter()
+            .find(|(_, lease)| lease.hardware_addr == client && lease.state == LeaseState::Offered)
+            .map(|(addr, _)| *addr);
+        if let Some(addr) = offered {
+            self.leases.remove(&addr);
+        }
+    }
+
+    fn reply(
+        &self,
+        request: &DhcpRepr,
+        message_type: DhcpMessageType,
+        your_ip: Ipv4Address,
+    ) -> (Ipv4Repr, UdpRepr, DhcpRepr<'static>) {
+        let is_nak = message_type == DhcpMessageType::Nak;
+        let mut dhcp_repr = DhcpRepr {
+            message_type,
+            transaction_id: request.transaction_id,
+            secs: 0,
+            client_hardware_address: request.client_hardware_address,
+            client_ip: Ipv4Address::UNSPECIFIED,
+            your_ip,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: None,
+            subnet_mask: None,
+            relay_agent_ip: request.relay_agent_ip,
+            broadcast: request.broadcast,
+            requested_ip: None,
+            client_identifier: None,
+            server_identifier: Some(self.config.server_ip),
+            parameter_request_list: None,
+            dns_servers: None,
+            max_size: None,
+            lease_duration: None,
+            renew_duration: None,
+            rebind_duration: None,
+            additional_options: &[],
+        };
+        if !is_nak {
+            dhcp_repr.client_ip = request.client_ip;
+            dhcp_repr.router = self.config.router;
+            dhcp_repr.subnet_mask = Some(self.config.subnet_mask);
+            if !self.config.dns_servers.is_empty() {
+                dhcp_repr.dns_servers = Some(self.config.dns_servers.clone());
+            }
+            dhcp_repr.lease_duration = Some(self.config.lease_duration.secs() as u32);
+        }
+
+        // See RFC 2131 § 4.1 for where replies are sent.
+        let (dst_addr, dst_port) = if !request.relay_agent_ip.is_unspecified() {
+            (request.relay_agent_ip, DHCP_SERVER_PORT)
+        } else if !is_nak && !request.client_ip.is_unspecified() {
+            (request.client_ip, DHCP_CLIENT_PORT)
+        } else {
+            (Ipv4Address::BROADCAST, DHCP_CLIENT_PORT)
+        };
+
+        let udp_repr = UdpRepr {
+            src_port: DHCP_SERVER_PORT,
+            dst_port,
+        };
+        let ip_repr = Ipv4Repr {
+            src_addr: self.config.server_ip,
+            dst_addr,
+            next_header: IpProtocol::Udp,
+            payload_len: UDP_HEADER_LEN + dhcp_repr.buffer_len(),
+            hop_limit: 64,
+        };
+        (ip_repr, udp_repr, dhcp_repr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    const SERVER_IP: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const MASK_24: Ipv4Address = Ipv4Address([255, 255, 255, 0]);
+    const POOL_START: Ipv4Address = Ipv4Address([192, 168, 1, 100]);
+    const POOL_END: Ipv4Address = Ipv4Address([192, 168, 1, 101]);
+    const OTHER_SERVER_IP: Ipv4Address = Ipv4Address([192, 168, 1, 2]);
+    const CLIENT_MAC: EthernetAddress = EthernetAddress([0x02, 0x02, 0x02, 0x02, 0x02, 0x02]);
+    const OTHER_MAC: EthernetAddress = EthernetAddress([0x02, 0x02, 0x02, 0x02, 0x02, 0x03]);
+    const LEASE_DURATION: Duration = Duration::from_secs(1000);
+
+    const UDP_REQUEST: UdpRepr = UdpRepr {
+        src_port: DHCP_CLIENT_PORT,
+        dst_port: DHCP_SERVER_PORT,
+    };
+
+    const DHCP_DEFAULT: DhcpRepr = DhcpRepr {
+        message_type: DhcpMessageType::Unknown(99),
+        transaction_id: 0x12345678,
+        secs: 0,
+        client_hardware_address: CLIENT_MAC,
+        client_ip: Ipv4Address::UNSPECIFIED,
+        your_ip: Ipv4Address::UNSPECIFIED,
+        server_ip: Ipv4Address::UNSPECIFIED,
+        router: None,
+        subnet_mask: None,
+        relay_agent_ip: Ipv4Address::UNSPECIFIED,
+        broadcast: false,
+        requested_ip: None,
+        client_identifier: None,
+        server_identifier: None,
+        parameter_request_list: None,
+        dns_servers: None,
+        max_size: None,
+        lease_duration: None,
+        renew_duration: None,
+        rebind_duration: None,
+        additional_options: &[],
+    };
+
+    const DHCP_DISCOVER: DhcpRepr = DhcpRepr {
+        message_type: DhcpMessageType::Discover,
+        ..DHCP_DEFAULT
+    };
+
+    const DHCP_REQUEST: DhcpRepr = DhcpRepr {
+        message_type: DhcpMessageType::Request,
+        server_identifier: Some(SERVER_IP),
+        requested_ip: Some(POOL_START),
+        ..DHCP_DEFAULT
+    };
+
+    fn server() -> (Server<'static>, Context<'static>) {
+        let config = ServerConfig {
+            server_ip: SERVER_IP,
+            subnet_mask: MASK_24,
+            router: Some(SERVER_IP),
+            dns_servers: Vec::from_slice(&[SERVER_IP]).unwrap(),
+            pool_start: POOL_START,
+            pool_end: POOL_END,
+            lease_duration: LEASE_DURATION,
+        };
+        (Server::new(config, BTreeMap::new()), Context::mock())
+    }
+
+    fn send(
+        server: &mut Server,
+        cx: &mut Context,
+        timestamp: Instant,
+        dhcp_repr: &DhcpRepr,
+    ) -> Option<(Ipv4Repr, UdpRepr, DhcpRepr<'static>)> {
+        cx.set_now(timestamp);
+
+        let mut payload = vec![0; dhcp_repr.buffer_len()];
+        dhcp_repr
+            .emit(&mut DhcpPacket::new_unchecked(&mut payload))
+            .unwrap();
+        let ip_repr = Ipv4Repr {
+            src_addr: dhcp_repr.client_ip,
+            dst_addr: Ipv4Address::BROADCAST,
+            next_header: IpProtocol::Udp,
+            payload_len: UDP_HEADER_LEN + payload.len(),
+            hop_limit: 64,
+        };
+        server.process(cx, &ip_repr, &UDP_REQUEST, &payload)
+    }
+
+    fn bind(server: &mut Server, cx: &mut Context, client: EthernetAddress) -> Ipv4Address {
+        let discover = DhcpRepr {
+            client_hardware_address: client,
+            ..DHCP_DISCOVER
+        };
+        let (_, _, offer) = send(server, cx, Instant::from_secs(0), &discover).unwrap();
+        let request = DhcpRepr {
+            client_hardware_address: client,
+            requested_ip: Some(offer.your_ip),
+            ..DHCP_REQUEST
+        };
+        let (_, _, ack) = send(server, cx, Instant::from_secs(1), &request).unwrap();
+        assert_eq!(ack.message_type, DhcpMessageType::Ack);
+        ack.your_ip
+    }
+
+    #[test]
+    fn test_discover_request() {
+        let (mut s, mut cx) = server();
+
+        let (ip_repr, udp_repr, offer) =
+            send(&mut s, &mut cx, Instant::from_secs(0), &DHCP_DISCOVER).unwrap();
+        assert_eq!(ip_repr.src_addr, SERVER_IP);
+        assert_eq!(ip_repr.dst_addr, Ipv4Address::BROADCAST);
+        assert_eq!(
+            ip_repr.payload_len,
+            udp_repr.header_len() + offer.buffer_len()
+        );
+        assert_eq!(udp_repr.dst_port, DHCP_CLIENT_PORT);
+        assert_eq!(offer.message_type, DhcpMessageType::Offer);
+        assert_eq!(offer.transaction_id, DHCP_DEFAULT.transaction_id);
+        assert_eq!(offer.your_ip, POOL_START);
+        assert_eq!(offer.server_identifier, Some(SERVER_IP));
+        assert_eq!(offer.subnet_mask, Some(MASK_24));
+        assert_eq!(offer.router, Some(SERVER_IP));
+        assert_eq!(offer.lease_duration, Some(1000));
+        assert_eq!(s.lease(POOL_START).unwrap().state, LeaseState::Offered);
+
+        let (_, _, ack) = send(&mut s, &mut cx, Instant::from_secs(1), &DHCP_REQUEST).unwrap();
+        assert_eq!(ack.message_type, DhcpMessageType::Ack);
+        assert_eq!(ack.your_ip, POOL_START);
+        assert_eq!(
+            s.lease(POOL_START),
+            Some(&Lease {
+                hardware_addr: CLIENT_MAC,
+                state: LeaseState::Bound,
+                expires_at: Instant::from_secs(1) + LEASE_DURATION,
+            })
+        );
+
+        // A client discovering again is offered the address it already holds.
+        let (_, _, offer) = send(&mut s, &mut cx, Instant::from_secs(2), &DHCP_DISCOVER).unwrap();
+        assert_eq!(offer.your_ip, POOL_START);
+    }
+
+    #[test]
+    fn test_renew() {
+        let (mut s, mut cx) = server();
+        let addr = bind(&mut s, &mut cx, CLIENT_MAC);
+
+        let renew = DhcpRepr {
+            client_ip: addr,
+            server_identifier: None,
+            requested_ip: None,
+            ..DHCP_REQUEST
+        };
+        let (ip_repr, _, ack) = send(&mut s, &mut cx, Instant::from_secs(500), &renew).unwrap();
+        assert_eq!(ip_repr.dst_addr, addr);
+        assert_eq!(ack.message_type, DhcpMessageType::Ack);
+        assert_eq!(ack.your_ip, addr);
+        assert_eq!(
+            s.lease(addr).unwrap().expires_at,
+            Instant::from_secs(500) + LEASE_DURATION
+        );
+    }
+
+    #[test]
+    fn test_request_nak() {
+        let (mut s, mut cx) = server();
+        let addr = bind(&mut s, &mut cx, CLIENT_MAC);
+
+        // INIT-REBOOT for an address held by another client.
+        let request = DhcpRepr {
+            client_hardware_address: OTHER_MAC,
+            server_identifier: None,
+            requested_ip: Some(addr),
+            ..DHCP_REQUEST
+        };
+        let (ip_repr, _, nak) = send(&mut s, &mut cx, Instant::from_secs(2), &request).unwrap();
+        assert_eq!(ip_repr.dst_addr, Ipv4Address::BROADCAST);
+        assert_eq!(nak.message_type, DhcpMessageType::Nak);
+        assert_eq!(nak.your_ip, Ipv4Address::UNSPECIFIED);
+        assert_eq!(nak.lease_duration, None);
+
+        // INIT-REBOOT for an address out of the pool.
+        let request = DhcpRepr {
+            server_identifier: None,
+            requested_ip: Some(Ipv4Address([10, 0, 0, 1])),
+            ..DHCP_REQUEST
+        };
+        let (_, _, nak) = send(&mut s, &mut cx, Instant::from_secs(2), &request).unwrap();
+        assert_eq!(nak.message_type, DhcpMessageType::Nak);
+    }
+
+    #[test]
+    fn test_request_other_server() {
+        let (mut s, mut cx) = server();
+        send(&mut s, &mut cx, Instant::from_secs(0), &DHCP_DISCOVER).unwrap();
+
+        let request = DhcpRepr {
+            server_identifier: Some(OTHER_SERVER_IP),
+            ..DHCP_REQUEST
+        };
+        assert!(send(&mut s, &mut cx, Instant::from_secs(1), &request).is_none());
+        assert_eq!(s.lease(POOL_START), None);
+    }
+
+    #[test]
+    fn test_reservation() {
+        let (mut s, mut cx) = server();
+        let mut reservations = BTreeMap::new();
+        reservations.insert(OTHER_MAC, POOL_START);
+        s.set_reservations(reservations);
+
+        // The reserved address isn't handed out to other clients.
+        assert_eq!(bind(&mut s, &mut cx, CLIENT_MAC), POOL_END);
+        assert_eq!(bind(&mut s, &mut cx, OTHER_MAC), POOL_START);
+
+        // Even when they ask for it.
+        let request = DhcpRepr {
+            server_identifier: None,
+            requested_ip: Some(POOL_START),
+            ..DHCP_REQUEST
+        };
+        let (_, _, nak) = send(&mut s, &mut cx, Instant::from_secs(2), &request).unwrap();
+        assert_eq!(nak.message_type, DhcpMessageType::Nak);
+    }
+
+    #[test]
+    fn test_release() {
+        let (mut s, mut cx) = server();
+        let addr = bind(&mut s, &mut cx, CLIENT_MAC);
+
+        let release = DhcpRepr {
+            message_type: DhcpMessageType::Release,
+            client_ip: addr,
+            server_identifier: Some(SERVER_IP),
+            ..DHCP_DEFAULT
+        };
+        assert!(send(&mut s, &mut cx, Instant::from_secs(2), &release).is_none());
+        assert_eq!(s.lease(addr), None);
+    }
+
+    #[test]
+    fn test_decline() {
+        let (mut s, mut cx) = server();
+        let addr = bind(&mut s, &mut cx, CLIENT_MAC);
+
+        let decline = DhcpRepr {
+            message_type: DhcpMessageType::Decline,
+            requested_ip: Some(addr),
+            server_identifier: Some(SERVER_IP),
+            ..DHCP_DEFAULT
+        };
+        assert!(send(&mut s, &mut cx, Instant::from_secs(2), &decline).is_none());
+        assert_eq!(s.lease(addr).unwrap().state, LeaseState::Declined);
+
+        // The declined address isn't offered again until the lease expires.
+        let (_, _, offer) = send(&mut s, &mut cx, Instant::from_secs(3), &DHCP_DISCOVER).unwrap();
+        assert_eq!(offer.your_ip, POOL_END);
+    }
+
+    #[test]
+    fn test_pool_exhausted() {
+        let (mut s, mut cx) = server();
+        bind(&mut s, &mut cx, CLIENT_MAC);
+        bind(&mut s, &mut cx, OTHER_MAC);
+
+        let discover = DhcpRepr {
+            client_hardware_address: EthernetAddress([0x02, 0, 0, 0, 0, 0x04]),
+            ..DHCP_DISCOVER
+        };
+        assert!(send(&mut s, &mut cx, Instant::from_secs(2), &discover).is_none());
+
+        // Expired leases are handed out again.
+        let (_, _, offer) = send(&mut s, &mut cx, Instant::from_secs(1001), &discover).unwrap();
+        assert_eq!(offer.your_ip, POOL_START);
+    }
+
+    #[test]
+    fn test_lease_storage_full() {
+        let (mut s, mut cx) = server();
+        let mut storage = [None];
+        s.leases = ManagedMap::Borrowed(&mut storage[..]);
+        bind(&mut s, &mut cx, CLIENT_MAC);
+
+        let discover = DhcpRepr {
+            client_hardware_address: OTHER_MAC,
+            ..DHCP_DISCOVER
+        };
+        assert!(send(&mut s, &mut cx, Instant::from_secs(2), &discover).is_none());
+
+        // Expired leases are forgotten to make room.
+        let (_, _, offer) = send(&mut s, &mut cx, Instant::from_secs(1001), &discover).unwrap();
+        assert_eq!(offer.your_ip, POOL_START);
+        assert_eq!(s.leases().count(), 1);
+    }
+
+    #[test]
+    fn test_relay() {
+        let (mut s, mut cx) = server();
+        let relay_ip = Ipv4Address([10, 0, 0, 1]);
+        let discover = DhcpRepr {
+            relay_agent_ip: relay_ip,
+            ..DHCP_DISCOVER
+        };
+        let (ip_repr, udp_repr, offer) =
+            send(&mut s, &mut cx, Instant::from_secs(0), &discover).unwrap();
+        assert_eq!(ip_repr.dst_addr, relay_ip);
+        assert_eq!(udp_repr.dst_port, DHCP_SERVER_PORT);
+        assert_eq!(offer.relay_agent_ip, relay_ip);
+    }
+}
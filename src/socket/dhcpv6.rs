@@ -0,0 +1,1414 @@
+// DHCPv6 client, see RFC 8415. The client either asks for an address and/or a delegated
+// prefix (§ 18.2.1 to § 18.2.5), or only for other configuration such as DNS servers with
+// an Information-Request (§ 18.2.6).
+
+#[cfg(feature = "async")]
+use core::task::Waker;
+
+use crate::iface::Context;
+use crate::time::{Duration, Instant};
+use crate::wire::dhcpv6::field as dhcpv6_field;
+use crate::wire::{
+    Dhcpv6IaAddress, Dhcpv6IaNa, Dhcpv6IaPd, Dhcpv6IaPrefix, Dhcpv6MessageType, Dhcpv6Option,
+    Dhcpv6Packet, Dhcpv6Repr, Dhcpv6StatusCode, HardwareAddress, IpProtocol, Ipv6Address, Ipv6Cidr,
+    Ipv6Repr, UdpRepr, DHCPV6_CLIENT_PORT, DHCPV6_MAX_DNS_SERVER_COUNT, DHCPV6_MAX_DUID_LEN,
+    DHCPV6_SERVER_PORT,
+};
+use heapless::Vec;
+
+#[cfg(feature = "async")]
+use super::WakerRegistration;
+
+use super::PollAt;
+
+// Transmission and retransmission parameters, see RFC 8415 § 7.6.
+const SOL_TIMEOUT: Duration = Duration::from_secs(1);
+const SOL_MAX_RT: Duration = Duration::from_secs(3600);
+const REQ_TIMEOUT: Duration = Duration::from_secs(1);
+const REQ_MAX_RT: Duration = Duration::from_secs(30);
+const REQ_MAX_RC: u16 = 10;
+const REN_TIMEOUT: Duration = Duration::from_secs(10);
+const REN_MAX_RT: Duration = Duration::from_secs(600);
+const REB_TIMEOUT: Duration = Duration::from_secs(10);
+const REB_MAX_RT: Duration = Duration::from_secs(600);
+const INF_TIMEOUT: Duration = Duration::from_secs(1);
+const INF_MAX_RT: Duration = Duration::from_secs(3600);
+const IRT_DEFAULT: Duration = Duration::from_secs(86400);
+const IRT_MINIMUM: Duration = Duration::from_secs(600);
+
+/// The identifier of the identity associations of the client.
+const IAID: u32 = 1;
+
+/// Options asked for in Solicit, Request, Renew and Rebind messages.
+const OPTION_REQUEST: &[u8] = &[0, dhcpv6_field::OPT_DNS_SERVERS as u8];
+/// Options asked for in Information-Request messages, see RFC 8415 § 21.23.
+const INFORMATION_OPTION_REQUEST: &[u8] = &[
+    0,
+    dhcpv6_field::OPT_DNS_SERVERS as u8,
+    0,
+    dhcpv6_field::OPT_INFORMATION_REFRESH_TIME as u8,
+];
+
+/// IPv6 configuration data provided by the DHCPv6 server.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Information on how to reach the DHCPv6 server that responded with DHCPv6
+    /// configuration.
+    pub server: ServerInfo,
+    /// IP address, if one was requested. DHCPv6 doesn't tell the length of the on-link
+    /// prefix, which comes from router advertisements.
+    pub address: Option<Ipv6Address>,
+    /// Delegated prefix, if one was requested.
+    pub prefix: Option<Ipv6Cidr>,
+    /// DNS servers
+    pub dns_servers: Vec<Ipv6Address, DHCPV6_MAX_DNS_SERVER_COUNT>,
+}
+
+/// Information on how to reach a DHCPv6 server.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ServerInfo {
+    /// Link-local IP address of the server, or of the relay agent it replied through.
+    pub address: Ipv6Address,
+    /// DHCP Unique Identifier of the server.
+    pub duid: Vec<u8, DHCPV6_MAX_DUID_LEN>,
+}
+
+/// Timing of the retransmissions of a message, see RFC 8415 § 15.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Retransmit {
+    /// When to send the next message.
+    retry_at: Instant,
+    /// How long to wait for a reply to the next message.
+    timeout: Duration,
+    /// How many messages have been sent.
+    count: u16,
+}
+
+impl Retransmit {
+    const fn new(retry_at: Instant, timeout: Duration) -> Self {
+        Retransmit {
+            retry_at,
+            timeout,
+            count: 0,
+        }
+    }
+
+    /// Record that a message has been sent at `now`, doubling the timeout up to `max_rt`.
+    fn sent(&mut self, now: Instant, max_rt: Duration) {
+        self.retry_at = now + self.timeout;
+        self.timeout = (self.timeout * 2).min(max_rt);
+        self.count += 1;
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct RequestState {
+    retransmit: Retransmit,
+    /// Server we're requesting from.
+    server: ServerInfo,
+    /// Identity associations advertised by the server.
+    ia_na: Option<Dhcpv6IaNa>,
+    ia_pd: Option<Dhcpv6IaPd>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct BoundState {
+    /// Active network config
+    config: Config,
+    /// Identity associations assigned by the server.
+    ia_na: Option<Dhcpv6IaNa>,
+    ia_pd: Option<Dhcpv6IaPd>,
+    /// Retransmissions of Renew and Rebind messages. Starts at T1.
+    retransmit: Retransmit,
+    /// T2, when we start asking any server to extend the lifetimes.
+    rebind_at: Instant,
+    /// Whether T2 has passed and we're sending Rebind messages.
+    rebinding: bool,
+    /// When the lifetimes expire, after which the config must be thrown away.
+    expires_at: Instant,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct InformedState {
+    /// Active network config
+    config: Config,
+    /// Retransmissions of Information-Request messages. Starts at the refresh time.
+    retransmit: Retransmit,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum ClientState {
+    /// Looking for a server that can assign addresses or prefixes.
+    Soliciting(Retransmit),
+    /// Requesting addresses or prefixes from a server.
+    Requesting(RequestState),
+    /// Having addresses or prefixes, extend their lifetimes periodically.
+    Bound(BoundState),
+    /// Asking for configuration without addresses nor prefixes.
+    InformationRequesting(Retransmit),
+    /// Having configuration, refresh it periodically.
+    Informed(InformedState),
+}
+
+/// Return value for the `dhcpv6::Socket::poll` function
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(clippy::large_enum_variant)]
+pub enum Event {
+    /// Configuration has been lost (for example, the lifetimes have expired)
+    Deconfigured,
+    /// Configuration has been newly acquired, or modified.
+    Configured(Config),
+}
+
+#[derive(Debug)]
+pub struct Socket<'a> {
+    /// State of the DHCPv6 client.
+    state: ClientState,
+    /// Set to true on config/state change, cleared back to false by the `poll` function.
+    config_changed: bool,
+    /// Transaction ID of the current exchange.
+    transaction_id: u32,
+    /// When the current exchange started.
+    transaction_started_at: Instant,
+
+    /// Ask for an address (IA_NA).
+    request_address: bool,
+    /// Ask for a delegated prefix (IA_PD).
+    request_prefix: bool,
+
+    /// A buffer contains options additional to be added to outgoing DHCPv6
+    /// packets.
+    outgoing_options: &'a [Dhcpv6Option<'a>],
+
+    /// Waker registration
+    #[cfg(feature = "async")]
+    waker: WakerRegistration,
+}
+
+/// DHCPv6 client socket.
+///
+/// The socket acquires an IPv6 configuration through DHCPv6 autonomously.
+/// You must query the configuration with `.poll()` after every call to `Interface::poll()`,
+/// and apply the configuration to the `Interface`.
+impl<'a> Socket<'a> {
+    /// Create a DHCPv6 socket, asking for an address.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Socket {
+            state: ClientState::Soliciting(Retransmit::new(Instant::from_millis(0), SOL_TIMEOUT)),
+            config_changed: true,
+            transaction_id: 1,
+            transaction_started_at: Instant::from_millis(0),
+            request_address: true,
+            request_prefix: false,
+            outgoing_options: &[],
+            #[cfg(feature = "async")]
+            waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Set the outgoing options.
+    pub fn set_outgoing_options(&mut self, options: &'a [Dhcpv6Option<'a>]) {
+        self.outgoing_options = options;
+    }
+
+    /// Get whether to ask for an address.
+    pub fn request_address(&self) -> bool {
+        self.request_address
+    }
+
+    /// Set whether to ask for an address, and restart the configuration.
+    ///
+    /// When asking for neither an address nor a prefix, the socket only asks for other
+    /// configuration such as DNS servers, i.e. it does stateless DHCPv6.
+    pub fn set_request_address(&mut self, request_address: bool) {
+        self.request_address = request_address;
+        self.reset();
+    }
+
+    /// Get whether to ask for a delegated prefix.
+    pub fn request_prefix(&self) -> bool {
+        self.request_prefix
+    }
+
+    /// Set whether to ask for a delegated prefix, and restart the configuration.
+    ///
+    /// When asking for neither an address nor a prefix, the socket only asks for other
+    /// configuration such as DNS servers, i.e. it does stateless DHCPv6.
+    pub fn set_request_prefix(&mut self, request_prefix: bool) {
+        self.request_prefix = request_prefix;
+        self.reset();
+    }
+
+    fn is_stateful(&self) -> bool {
+        self.request_address || self.request_prefix
+    }
+
+    pub(crate) fn poll_at(&self, _cx: &mut Context) -> PollAt {
+        let t = match &self.state {
+            ClientState::Soliciting(retransmit) => retransmit.retry_at,
+            ClientState::Requesting(state) => state.retransmit.retry_at,
+            ClientState::Bound(state) => state.retransmit.retry_at.min(state.expires_at),
+            ClientState::InformationRequesting(retransmit) => retransmit.retry_at,
+            ClientState::Informed(state) => state.retransmit.retry_at,
+        };
+        PollAt::Time(t)
+    }
+
+    /// Return our DHCP Unique Identifier, based on the link-layer address (DUID-LL, see
+    /// RFC 8415 § 11.4).
+    fn duid(cx: &mut Context) -> Option<[u8; 10]> {
+        match cx.hardware_addr() {
+            Some(HardwareAddress::Ethernet(addr)) => {
+                let mut duid = [0, 3, 0, 1, 0, 0, 0, 0, 0, 0];
+                duid[4..].copy_from_slice(addr.as_bytes());
+                Some(duid)
+            }
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    pub(crate) fn process(
+        &mut self,
+        cx: &mut Context,
+        ip_repr: &Ipv6Repr,
+        repr: &UdpRepr,
+        payload: &[u8],
+    ) {
+        let src_ip = ip_repr.src_addr;
+
+        // This is enforced in interface.rs.
+        assert!(repr.src_port == DHCPV6_SERVER_PORT && repr.dst_port == DHCPV6_CLIENT_PORT);
+
+        let dhcp_packet = match Dhcpv6Packet::new_checked(payload) {
+            Ok(dhcp_packet) => dhcp_packet,
+            Err(e) => {
+                net_debug!("DHCPv6 invalid pkt from {}: {:?}", src_ip, e);
+                return;
+            }
+        };
+        let dhcp_repr = match Dhcpv6Repr::parse(&dhcp_packet) {
+            Ok(dhcp_repr) => dhcp_repr,
+            Err(e) => {
+                net_debug!("DHCPv6 error parsing pkt from {}: {:?}", src_ip, e);
+                return;
+            }
+        };
+        let duid = match Self::duid(cx) {
+            Some(duid) => duid,
+            None => return,
+        };
+
+        if dhcp_repr.client_id != Some(&duid[..]) {
+            return;
+        }
+        if dhcp_repr.transaction_id != self.transaction_id {
+            return;
+        }
+        let server = match dhcp_repr.server_id {
+            Some(server_id) => ServerInfo {
+                address: src_ip,
+                duid: Vec::from_slice(server_id).unwrap(),
+            },
+            None => {
+                net_debug!(
+                    "DHCPv6 ignoring {:?} because missing server_id",
+                    dhcp_repr.message_type
+                );
+                return;
+            }
+        };
+
+        net_debug!(
+            "DHCPv6 recv {:?} from {}: {:?}",
+            dhcp_repr.message_type,
+            src_ip,
+            dhcp_repr
+        );
+
+        let now = cx.now();
+        let (request_address, request_prefix) = (self.request_address, self.request_prefix);
+        match (&mut self.state, dhcp_repr.message_type) {
+            (ClientState::Soliciting(_), Dhcpv6MessageType::Advertise) => {
+                // We go with the first server to advertise something, instead of waiting
+                // for advertisements with a higher preference.
+                if let Some((ia_na, ia_pd)) =
+                    Self::granted(&dhcp_repr, request_address, request_prefix)
+                {
+                    self.state = ClientState::Requesting(RequestState {
+                        retransmit: Retransmit::new(now, REQ_TIMEOUT),
+                        server,
+                        ia_na,
+                        ia_pd,
+                    });
+                } else {
+                    net_debug!("DHCPv6 ignoring ADVERTISE without anything we asked for");
+                }
+            }
+            (ClientState::Requesting(state), Dhcpv6MessageType::Reply) => {
+                if server.duid != state.server.duid {
+                    return;
+                }
+                match Self::parse_reply(now, &dhcp_repr, server, request_address, request_prefix) {
+                    Some(bound) => {
+                        self.state = ClientState::Bound(bound);
+                        self.config_changed();
+                    }
+                    None => {
+                        net_debug!("DHCPv6 REPLY doesn't assign anything, restarting");
+                        self.reset();
+                    }
+                }
+            }
+            (ClientState::Bound(state), Dhcpv6MessageType::Reply) => {
+                match Self::parse_reply(now, &dhcp_repr, server, request_address, request_prefix) {
+                    Some(bound) => {
+                        let config_changed = state.config != bound.config;
+                        *state = bound;
+                        if config_changed {
+                            self.config_changed();
+                        }
+                    }
+                    None if Self::has_status(&dhcp_repr, Dhcpv6StatusCode::NoBinding) => {
+                        // The server lost track of our bindings, ask for them again,
+                        // see RFC 8415 § 18.2.10.1.
+                        let server = state.config.server.clone();
+                        let (ia_na, ia_pd) = (state.ia_na.clone(), state.ia_pd.clone());
+                        self.state = ClientState::Requesting(RequestState {
+                            retransmit: Retransmit::new(now, REQ_TIMEOUT),
+                            server,
+                            ia_na,
+                            ia_pd,
+                        });
+                    }
+                    None => {
+                        net_debug!("DHCPv6 ignoring REPLY without anything we asked for");
+                    }
+                }
+            }
+            (
+                ClientState::InformationRequesting(_) | ClientState::Informed(_),
+                Dhcpv6MessageType::Reply,
+            ) => {
+                // Wait at least IRT_MINIMUM before refreshing, see RFC 8415 § 21.23.
+                let refresh_time = match dhcp_repr.information_refresh_time {
+                    Some(secs) => IRT_MINIMUM.max(Duration::from_secs(secs as u64)),
+                    None => IRT_DEFAULT,
+                };
+                let config = Config {
+                    server,
+                    address: None,
+                    prefix: None,
+                    dns_servers: dhcp_repr.dns_servers.clone().unwrap_or_default(),
+                };
+                let config_changed = match &self.state {
+                    ClientState::Informed(state) => state.config != config,
+                    _ => true,
+                };
+                self.state = ClientState::Informed(InformedState {
+                    config,
+                    retransmit: Retransmit::new(now + refresh_time, INF_TIMEOUT),
+                });
+                if config_changed {
+                    self.config_changed();
+                }
+            }
+            _ => {
+                net_debug!(
+                    "DHCPv6 ignoring {:?}: unexpected in current state",
+                    dhcp_repr.message_type
+                );
+            }
+        }
+    }
+
+    fn has_status(dhcp_repr: &Dhcpv6Repr, status: Dhcpv6StatusCode) -> bool {
+        dhcp_repr.status == Some(status)
+            || dhcp_repr.ia_na.as_ref().and_then(|ia| ia.status) == Some(status)
+            || dhcp_repr.ia_pd.as_ref().and_then(|ia| ia.status) == Some(status)
+    }
+
+    /// Return the identity associations with addresses or prefixes we asked for, if any.
+    #[allow(clippy::type_complexity)]
+    fn granted(
+        dhcp_repr: &Dhcpv6Repr,
+        request_address: bool,
+        request_prefix: bool,
+    ) -> Option<(Option<Dhcpv6IaNa>, Option<Dhcpv6IaPd>)> {
+        let is_success = |status: Option<Dhcpv6StatusCode>| {
+            status.is_none() || status == Some(Dhcpv6StatusCode::Success)
+        };
+        if !is_success(dhcp_repr.status) {
+            return None;
+        }
+
+        let ia_na = dhcp_repr.ia_na.clone().filter(|ia| {
+            request_address
+                && ia.iaid == IAID
+                && is_success(ia.status)
+                && ia.addresses.iter().any(|addr| addr.valid_lifetime > 0)
+        });
+        let ia_pd = dhcp_repr.ia_pd.clone().filter(|ia| {
+            request_prefix
+                && ia.iaid == IAID
+                && is_success(ia.status)
+                && ia.prefixes.iter().any(|prefix| prefix.valid_lifetime > 0)
+        });
+        if ia_na.is_none() && ia_pd.is_none() {
+            None
+        } else {
+            Some((ia_na, ia_pd))
+        }
+    }
+
+    fn parse_reply(
+        now: Instant,
+        dhcp_repr: &Dhcpv6Repr,
+        server: ServerInfo,
+        request_address: bool,
+        request_prefix: bool,
+    ) -> Option<BoundState> {
+        let (ia_na, ia_pd) = Self::granted(dhcp_repr, request_address, request_prefix)?;
+
+        let address = ia_na.as_ref().and_then(|ia| {
+            ia.addresses
+                .iter()
+                .find(|addr| addr.valid_lifetime > 0)
+                .copied()
+        });
+        let prefix = ia_pd.as_ref().and_then(|ia| {
+            ia.prefixes
+                .iter()
+                .find(|prefix| prefix.valid_lifetime > 0)
+                .copied()
+        });
+
+        // The shortest lifetimes, and T1 and T2 of the identity associations.
+        let mut preferred = u32::MAX;
+        let mut valid = u32::MAX;
+        let mut t1 = u32::MAX;
+        let mut t2 = u32::MAX;
+        if let (Some(ia), Some(addr)) = (&ia_na, address) {
+            preferred = preferred.min(addr.preferred_lifetime);
+            valid = valid.min(addr.valid_lifetime);
+            t1 = t1.min(ia.t1);
+            t2 = t2.min(ia.t2);
+        }
+        if let (Some(ia), Some(prefix)) = (&ia_pd, prefix) {
+            preferred = preferred.min(prefix.preferred_lifetime);
+            valid = valid.min(prefix.valid_lifetime);
+            t1 = t1.min(ia.t1);
+            t2 = t2.min(ia.t2);
+        }
+
+        // T1 and T2 are left to the client when zero, see RFC 8415 § 14.2.
+        if t1 == 0 {
+            t1 = preferred / 2;
+        }
+        if t2 == 0 {
+            t2 = (preferred / 5).saturating_mul(4);
+        }
+        let t2 = t2.max(t1).min(valid);
+        let t1 = t1.min(t2);
+
+        let config = Config {
+            server,
+            address: address.map(|addr| addr.address),
+            prefix: prefix.map(|prefix| Ipv6Cidr::new(prefix.prefix, prefix.prefix_len)),
+            dns_servers: dhcp_repr.dns_servers.clone().unwrap_or_default(),
+        };
+
+        Some(BoundState {
+            config,
+            ia_na,
+            ia_pd,
+            retransmit: Retransmit::new(now + Duration::from_secs(t1 as u64), REN_TIMEOUT),
+            rebind_at: now + Duration::from_secs(t2 as u64),
+            rebinding: false,
+            expires_at: now + Duration::from_secs(valid as u64),
+        })
+    }
+
+    #[cfg(not(test))]
+    fn random_transaction_id(cx: &mut Context) -> u32 {
+        cx.rand().rand_u32() & 0xff_ffff
+    }
+
+    #[cfg(test)]
+    fn random_transaction_id(_cx: &mut Context) -> u32 {
+        0x123456
+    }
+
+    pub(crate) fn dispatch<F, E>(&mut self, cx: &mut Context, emit: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut Context, (Ipv6Repr, UdpRepr, Dhcpv6Repr)) -> Result<(), E>,
+    {
+        // note: Dhcpv6Socket is only usable in ethernet mediums, so the
+        // unwrap can never fail.
+        let duid = match Self::duid(cx) {
+            Some(duid) => duid,
+            None => panic!("using DHCPv6 socket with a non-ethernet hardware address."),
+        };
+
+        let now = cx.now();
+        if let ClientState::Bound(state) = &self.state {
+            if state.expires_at <= now {
+                net_debug!("DHCPv6 lifetimes expired");
+                self.reset();
+                // return Ok so we get polled again
+                return Ok(());
+            }
+        }
+
+        let (retransmit, max_rt) = match &mut self.state {
+            ClientState::Soliciting(retransmit) => (retransmit, SOL_MAX_RT),
+            ClientState::Requesting(state) => (&mut state.retransmit, REQ_MAX_RT),
+            ClientState::Bound(state) => {
+                if !state.rebinding && now >= state.rebind_at {
+                    // Rebinding is a new exchange, see RFC 8415 § 18.2.5.
+                    state.rebinding = true;
+                    state.retransmit = Retransmit::new(now, REB_TIMEOUT);
+                }
+                if state.rebinding {
+                    (&mut state.retransmit, REB_MAX_RT)
+                } else {
+                    (&mut state.retransmit, REN_MAX_RT)
+                }
+            }
+            ClientState::InformationRequesting(retransmit) => (retransmit, INF_MAX_RT),
+            ClientState::Informed(state) => (&mut state.retransmit, INF_MAX_RT),
+        };
+        if now < retransmit.retry_at {
+            return Ok(());
+        }
+        let retransmit = *retransmit;
+
+        if let ClientState::Requesting(_) = self.state {
+            if retransmit.count >= REQ_MAX_RC {
+                net_debug!("DHCPv6 request retries exceeded, restarting");
+                self.reset();
+                return Ok(());
+            }
+        }
+
+        // We don't directly modify self.transaction_id because sending the packet
+        // may fail. We only want to update state after succesfully sending.
+        let (transaction_id, started_at) = if retransmit.count == 0 {
+            (Self::random_transaction_id(cx), now)
+        } else {
+            (self.transaction_id, self.transaction_started_at)
+        };
+        let elapsed_time = ((now - started_at).total_millis() / 10).min(0xffff) as u16;
+
+        let src_addr = match cx
+            .get_source_address_ipv6(Ipv6Address::LINK_LOCAL_ALL_DHCP_RELAY_AGENTS_AND_SERVERS)
+        {
+            Some(addr) if addr.is_link_local() => addr,
+            _ => {
+                net_debug!("DHCPv6 no usable link-local address");
+                return Ok(());
+            }
+        };
+
+        let mut dhcp_repr = Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Solicit,
+            transaction_id,
+            client_id: Some(&duid),
+            server_id: None,
+            ia_na: None,
+            ia_pd: None,
+            option_request: Some(OPTION_REQUEST),
+            elapsed_time: Some(elapsed_time),
+            preference: None,
+            status: None,
+            rapid_commit: false,
+            dns_servers: None,
+            information_refresh_time: None,
+            additional_options: self.outgoing_options,
+        };
+
+        let udp_repr = UdpRepr {
+            src_port: DHCPV6_CLIENT_PORT,
+            dst_port: DHCPV6_SERVER_PORT,
+        };
+
+        let mut ipv6_repr = Ipv6Repr {
+            src_addr,
+            dst_addr: Ipv6Address::LINK_LOCAL_ALL_DHCP_RELAY_AGENTS_AND_SERVERS,
+            next_header: IpProtocol::Udp,
+            payload_len: 0, // filled right before emit
+            hop_limit: 1,
+        };
+
+        let empty_ia_na = || Dhcpv6IaNa {
+            iaid: IAID,
+            t1: 0,
+            t2: 0,
+            addresses: Vec::new(),
+            status: None,
+        };
+        let empty_ia_pd = || Dhcpv6IaPd {
+            iaid: IAID,
+            t1: 0,
+            t2: 0,
+            prefixes: Vec::new(),
+            status: None,
+        };
+        // Lifetimes in messages from the client are ignored, see RFC 8415 § 21.6.
+        let from_server_ia_na = |ia: &Dhcpv6IaNa| Dhcpv6IaNa {
+            iaid: ia.iaid,
+            t1: 0,
+            t2: 0,
+            addresses: ia
+                .addresses
+                .iter()
+                .map(|addr| Dhcpv6IaAddress {
+                    address: addr.address,
+                    preferred_lifetime: 0,
+                    valid_lifetime: 0,
+                })
+                .collect(),
+            status: None,
+        };
+        let from_server_ia_pd = |ia: &Dhcpv6IaPd| Dhcpv6IaPd {
+            iaid: ia.iaid,
+            t1: 0,
+            t2: 0,
+            prefixes: ia
+                .prefixes
+                .iter()
+                .map(|prefix| Dhcpv6IaPrefix {
+                    prefix: prefix.prefix,
+                    prefix_len: prefix.prefix_len,
+                    preferred_lifetime: 0,
+                    valid_lifetime: 0,
+                })
+                .collect(),
+            status: None,
+        };
+
+        let server_duid;
+        match &self.state {
+            ClientState::Soliciting(_) => {
+                if self.request_address {
+                    dhcp_repr.ia_na = Some(empty_ia_na());
+                }
+                if self.request_prefix {
+                    dhcp_repr.ia_pd = Some(empty_ia_pd());
+                }
+            }
+            ClientState::Requesting(state) => {
+                server_duid = state.server.duid.clone();
+                dhcp_repr.message_type = Dhcpv6MessageType::Request;
+                dhcp_repr.server_id = Some(&server_duid);
+                dhcp_repr.ia_na = state.ia_na.as_ref().map(from_server_ia_na);
+                dhcp_repr.ia_pd = state.ia_pd.as_ref().map(from_server_ia_pd);
+            }
+            ClientState::Bound(state) => {
+                if !state.rebinding {
+                    server_duid = state.config.server.duid.clone();
+                    dhcp_repr.message_type = Dhcpv6MessageType::Renew;
+                    dhcp_repr.server_id = Some(&server_duid);
+                } else {
+                    dhcp_repr.message_type = Dhcpv6MessageType::Rebind;
+                }
+                dhcp_repr.ia_na = state.ia_na.as_ref().map(from_server_ia_na);
+                dhcp_repr.ia_pd = state.ia_pd.as_ref().map(from_server_ia_pd);
+            }
+            ClientState::InformationRequesting(_) | ClientState::Informed(_) => {
+                dhcp_repr.message_type = Dhcpv6MessageType::InformationRequest;
+                dhcp_repr.option_request = Some(INFORMATION_OPTION_REQUEST);
+            }
+        }
+
+        net_debug!(
+            "DHCPv6 send {:?} to {}: {:?}",
+            dhcp_repr.message_type,
+            ipv6_repr.dst_addr,
+            dhcp_repr
+        );
+        ipv6_repr.payload_len = udp_repr.header_len() + dhcp_repr.buffer_len();
+        emit(cx, (ipv6_repr, udp_repr, dhcp_repr))?;
+
+        // Update state AFTER the packet has been successfully sent.
+        self.transaction_id = transaction_id;
+        self.transaction_started_at = started_at;
+        match &mut self.state {
+            ClientState::Bound(state) => {
+                state.retransmit.sent(now, max_rt);
+                // Don't wait past T2 or the end of the lifetimes to send the next message.
+                let deadline = if state.rebinding {
+                    state.expires_at
+                } else {
+                    state.rebind_at
+                };
+                state.retransmit.retry_at = state.retransmit.retry_at.min(deadline);
+            }
+            ClientState::Soliciting(retransmit)
+            | ClientState::InformationRequesting(retransmit) => retransmit.sent(now, max_rt),
+            ClientState::Requesting(state) => state.retransmit.sent(now, max_rt),
+            ClientState::Informed(state) => state.retransmit.sent(now, max_rt),
+        }
+        Ok(())
+    }
+
+    /// Reset state and restart solicitation, or the Information-Request exchange.
+    ///
+    /// Use this to speed up acquisition of a configuration in a new
+    /// network if a link was down and it is now back up.
+    pub fn reset(&mut self) {
+        net_trace!("DHCPv6 reset");
+        if let ClientState::Bound(_) | ClientState::Informed(_) = &self.state {
+            self.config_changed();
+        }
+        let retry_at = Instant::from_millis(0);
+        self.state = if self.is_stateful() {
+            ClientState::Soliciting(Retransmit::new(retry_at, SOL_TIMEOUT))
+        } else {
+            ClientState::InformationRequesting(Retransmit::new(retry_at, INF_TIMEOUT))
+        };
+    }
+
+    /// Query the socket for configuration changes.
+    ///
+    /// The socket has an internal "configuration changed" flag. If
+    /// set, this function returns the configuration and resets the flag.
+    pub fn poll(&mut self) -> Option<Event> {
+        if !self.config_changed {
+            return None;
+        }
+        self.config_changed = false;
+        match &self.state {
+            ClientState::Bound(state) => Some(Event::Configured(state.config.clone())),
+            ClientState::Informed(state) => Some(Event::Configured(state.config.clone())),
+            _ => Some(Event::Deconfigured),
+        }
+    }
+
+    /// This function _must_ be called when the configuration provided to the
+    /// interface, by this DHCPv6 socket, changes. It will update the `config_changed` field
+    /// so that a subsequent call to `poll` will yield an event, and wake a possible waker.
+    pub(crate) fn config_changed(&mut self) {
+        self.config_changed = true;
+        #[cfg(feature = "async")]
+        self.waker.wake();
+    }
+
+    /// Register a waker.
+    ///
+    /// The waker is woken on state changes that might affect the return value
+    /// of `poll` method calls, which indicates a new state in the DHCPv6 configuration
+    /// provided by this DHCPv6 socket.
+    ///
+    /// Notes:
+    ///
+    /// - Only one waker can be registered at a time. If another waker was previously registered,
+    ///   it is overwritten and will no longer be woken.
+    /// - The Waker is woken only once. Once woken, you must register it again to receive more wakes.
+    #[cfg(feature = "async")]
+    pub fn register_waker(&mut self, waker: &Waker) {
+        self.waker.register(waker)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::ops::{Deref, DerefMut};
+
+    use super::*;
+    use crate::Error;
+
+    // =========================================================================================//
+    // Helper functions
+
+    struct TestSocket {
+        socket: Socket<'static>,
+        cx: Context<'static>,
+    }
+
+    impl Deref for TestSocket {
+        type Target = Socket<'static>;
+        fn deref(&self) -> &Self::Target {
+            &self.socket
+        }
+    }
+
+    impl DerefMut for TestSocket {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.socket
+        }
+    }
+
+    fn send(
+        s: &mut TestSocket,
+        timestamp: Instant,
+        (ip_repr, udp_repr, dhcp_repr): (Ipv6Repr, UdpRepr, Dhcpv6Repr),
+    ) {
+        s.cx.set_now(timestamp);
+
+        net_trace!("send: {:?}", ip_repr);
+        net_trace!("      {:?}", udp_repr);
+        net_trace!("      {:?}", dhcp_repr);
+
+        let mut payload = vec![0; dhcp_repr.buffer_len()];
+        dhcp_repr
+            .emit(&mut Dhcpv6Packet::new_unchecked(&mut payload))
+            .unwrap();
+
+        s.socket.process(&mut s.cx, &ip_repr, &udp_repr, &payload)
+    }
+
+    fn recv(s: &mut TestSocket, timestamp: Instant, reprs: &[(Ipv6Repr, UdpRepr, Dhcpv6Repr)]) {
+        s.cx.set_now(timestamp);
+
+        let mut i = 0;
+
+        while s.socket.poll_at(&mut s.cx) <= PollAt::Time(timestamp) {
+            let _ = s
+                .socket
+                .dispatch(&mut s.cx, |_, (mut ip_repr, udp_repr, dhcp_repr)| {
+                    assert_eq!(ip_repr.next_header, IpProtocol::Udp);
+                    assert_eq!(
+                        ip_repr.payload_len,
+                        udp_repr.header_len() + dhcp_repr.buffer_len()
+                    );
+
+                    // We validated the payload len, change it to 0 to make equality testing easier
+                    ip_repr.payload_len = 0;
+
+                    net_trace!("recv: {:?}", ip_repr);
+                    net_trace!("      {:?}", udp_repr);
+                    net_trace!("      {:?}", dhcp_repr);
+
+                    let got_repr = (ip_repr, udp_repr, dhcp_repr);
+                    match reprs.get(i) {
+                        Some(want_repr) => assert_eq!(want_repr, &got_repr),
+                        None => panic!("Too many reprs emitted"),
+                    }
+                    i += 1;
+                    Ok::<_, Error>(())
+                });
+        }
+
+        assert_eq!(i, reprs.len());
+    }
+
+    macro_rules! send {
+        ($socket:ident, $repr:expr) =>
+            (send!($socket, time 0, $repr));
+        ($socket:ident, time $time:expr, $repr:expr) =>
+            (send(&mut $socket, Instant::from_millis($time), $repr));
+    }
+
+    macro_rules! recv {
+        ($socket:ident, $reprs:expr) => ({
+            recv!($socket, time 0, $reprs);
+        });
+        ($socket:ident, time $time:expr, $reprs:expr) => ({
+            recv(&mut $socket, Instant::from_millis($time), &$reprs);
+        });
+    }
+
+    // =========================================================================================//
+    // Constants
+
+    const TXID: u32 = 0x123456;
+
+    const MY_LINK_LOCAL: Ipv6Address =
+        Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    const SERVER_LINK_LOCAL: Ipv6Address =
+        Ipv6Address([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+    const MY_IP: Ipv6Address = Ipv6Address([
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x42,
+    ]);
+    const PREFIX: Ipv6Address =
+        Ipv6Address([0x20, 0x01, 0x0d, 0xb8, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    const DNS_IP_1: Ipv6Address = Ipv6Address([
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x53,
+    ]);
+    const DNS_IPS: &[Ipv6Address] = &[DNS_IP_1];
+
+    const MY_DUID: &[u8] = &[0, 3, 0, 1, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02];
+    const SERVER_DUID: &[u8] = &[0, 3, 0, 1, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04];
+
+    const IP_SEND: Ipv6Repr = Ipv6Repr {
+        src_addr: MY_LINK_LOCAL,
+        dst_addr: Ipv6Address::LINK_LOCAL_ALL_DHCP_RELAY_AGENTS_AND_SERVERS,
+        next_header: IpProtocol::Udp,
+        payload_len: 0,
+        hop_limit: 1,
+    };
+
+    const IP_RECV: Ipv6Repr = Ipv6Repr {
+        src_addr: SERVER_LINK_LOCAL,
+        dst_addr: MY_LINK_LOCAL,
+        next_header: IpProtocol::Udp,
+        payload_len: 0,
+        hop_limit: 64,
+    };
+
+    const UDP_SEND: UdpRepr = UdpRepr {
+        src_port: 546,
+        dst_port: 547,
+    };
+    const UDP_RECV: UdpRepr = UdpRepr {
+        src_port: 547,
+        dst_port: 546,
+    };
+
+    const DHCP_DEFAULT: Dhcpv6Repr = Dhcpv6Repr {
+        message_type: Dhcpv6MessageType::Unknown(99),
+        transaction_id: TXID,
+        client_id: Some(MY_DUID),
+        server_id: None,
+        ia_na: None,
+        ia_pd: None,
+        option_request: None,
+        elapsed_time: None,
+        preference: None,
+        status: None,
+        rapid_commit: false,
+        dns_servers: None,
+        information_refresh_time: None,
+        additional_options: &[],
+    };
+
+    fn ia_na(preferred_lifetime: u32, valid_lifetime: u32, t1: u32, t2: u32) -> Dhcpv6IaNa {
+        Dhcpv6IaNa {
+            iaid: IAID,
+            t1,
+            t2,
+            addresses: Vec::from_slice(&[Dhcpv6IaAddress {
+                address: MY_IP,
+                preferred_lifetime,
+                valid_lifetime,
+            }])
+            .unwrap(),
+            status: None,
+        }
+    }
+
+    fn ia_pd(preferred_lifetime: u32, valid_lifetime: u32, t1: u32, t2: u32) -> Dhcpv6IaPd {
+        Dhcpv6IaPd {
+            iaid: IAID,
+            t1,
+            t2,
+            prefixes: Vec::from_slice(&[Dhcpv6IaPrefix {
+                prefix: PREFIX,
+                prefix_len: 56,
+                preferred_lifetime,
+                valid_lifetime,
+            }])
+            .unwrap(),
+            status: None,
+        }
+    }
+
+    fn dhcp_solicit(elapsed_time: u16) -> Dhcpv6Repr<'static> {
+        Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Solicit,
+            ia_na: Some(Dhcpv6IaNa {
+                iaid: IAID,
+                t1: 0,
+                t2: 0,
+                addresses: Vec::new(),
+                status: None,
+            }),
+            option_request: Some(OPTION_REQUEST),
+            elapsed_time: Some(elapsed_time),
+            ..DHCP_DEFAULT
+        }
+    }
+
+    fn dhcp_advertise() -> Dhcpv6Repr<'static> {
+        Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Advertise,
+            server_id: Some(SERVER_DUID),
+            ia_na: Some(ia_na(3000, 4000, 1000, 2000)),
+            dns_servers: Some(Vec::from_slice(DNS_IPS).unwrap()),
+            ..DHCP_DEFAULT
+        }
+    }
+
+    fn dhcp_request(elapsed_time: u16) -> Dhcpv6Repr<'static> {
+        Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Request,
+            server_id: Some(SERVER_DUID),
+            ia_na: Some(ia_na(0, 0, 0, 0)),
+            option_request: Some(OPTION_REQUEST),
+            elapsed_time: Some(elapsed_time),
+            ..DHCP_DEFAULT
+        }
+    }
+
+    fn dhcp_reply() -> Dhcpv6Repr<'static> {
+        Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Reply,
+            ..dhcp_advertise()
+        }
+    }
+
+    fn dhcp_renew(elapsed_time: u16) -> Dhcpv6Repr<'static> {
+        Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Renew,
+            ..dhcp_request(elapsed_time)
+        }
+    }
+
+    fn dhcp_rebind(elapsed_time: u16) -> Dhcpv6Repr<'static> {
+        Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Rebind,
+            server_id: None,
+            ..dhcp_request(elapsed_time)
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            server: ServerInfo {
+                address: SERVER_LINK_LOCAL,
+                duid: Vec::from_slice(SERVER_DUID).unwrap(),
+            },
+            address: Some(MY_IP),
+            prefix: None,
+            dns_servers: Vec::from_slice(DNS_IPS).unwrap(),
+        }
+    }
+
+    // =========================================================================================//
+    // Tests
+
+    fn socket() -> TestSocket {
+        let mut s = Socket::new();
+        assert_eq!(s.poll(), Some(Event::Deconfigured));
+        TestSocket {
+            socket: s,
+            cx: Context::mock(),
+        }
+    }
+
+    fn socket_bound() -> TestSocket {
+        let mut s = socket();
+        s.state = ClientState::Bound(BoundState {
+            config: config(),
+            ia_na: Some(ia_na(3000, 4000, 1000, 2000)),
+            ia_pd: None,
+            retransmit: Retransmit::new(Instant::from_secs(1000), REN_TIMEOUT),
+            rebind_at: Instant::from_secs(2000),
+            rebinding: false,
+            expires_at: Instant::from_secs(4000),
+        });
+
+        s
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut s = socket();
+
+        recv!(s, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        assert_eq!(s.poll(), None);
+        send!(s, (IP_RECV, UDP_RECV, dhcp_advertise()));
+        assert_eq!(s.poll(), None);
+        recv!(s, [(IP_SEND, UDP_SEND, dhcp_request(0))]);
+        assert_eq!(s.poll(), None);
+        send!(s, (IP_RECV, UDP_RECV, dhcp_reply()));
+
+        assert_eq!(s.poll(), Some(Event::Configured(config())));
+
+        match &s.state {
+            ClientState::Bound(state) => {
+                assert_eq!(state.retransmit.retry_at, Instant::from_secs(1000));
+                assert_eq!(state.rebind_at, Instant::from_secs(2000));
+                assert_eq!(state.expires_at, Instant::from_secs(4000));
+            }
+            _ => panic!("Invalid state"),
+        }
+    }
+
+    #[test]
+    fn test_solicit_retransmit() {
+        let mut s = socket();
+
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        recv!(s, time 999, []);
+        recv!(s, time 1_000, [(IP_SEND, UDP_SEND, dhcp_solicit(100))]);
+        recv!(s, time 2_999, []);
+        recv!(s, time 3_000, [(IP_SEND, UDP_SEND, dhcp_solicit(300))]);
+        recv!(s, time 7_000, [(IP_SEND, UDP_SEND, dhcp_solicit(700))]);
+        send!(s, time 7_500, (IP_RECV, UDP_RECV, dhcp_advertise()));
+        recv!(s, time 7_500, [(IP_SEND, UDP_SEND, dhcp_request(0))]);
+    }
+
+    #[test]
+    fn test_advertise_no_addresses() {
+        let mut s = socket();
+
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        send!(s, time 0, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            ia_na: None,
+            status: Some(Dhcpv6StatusCode::NoAddrsAvail),
+            ..dhcp_advertise()
+        }));
+        // Still soliciting.
+        recv!(s, time 1_000, [(IP_SEND, UDP_SEND, dhcp_solicit(100))]);
+    }
+
+    #[test]
+    fn test_ignore_other_transactions() {
+        let mut s = socket();
+
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        send!(s, time 0, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            transaction_id: TXID + 1,
+            ..dhcp_advertise()
+        }));
+        send!(s, time 0, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            client_id: Some(SERVER_DUID),
+            ..dhcp_advertise()
+        }));
+        recv!(s, time 1_000, [(IP_SEND, UDP_SEND, dhcp_solicit(100))]);
+    }
+
+    #[test]
+    fn test_request_retransmit() {
+        let mut s = socket();
+
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        send!(s, time 0, (IP_RECV, UDP_RECV, dhcp_advertise()));
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, dhcp_request(0))]);
+        recv!(s, time 1_000, [(IP_SEND, UDP_SEND, dhcp_request(100))]);
+        recv!(s, time 3_000, [(IP_SEND, UDP_SEND, dhcp_request(300))]);
+        send!(s, time 3_500, (IP_RECV, UDP_RECV, dhcp_reply()));
+
+        assert_eq!(s.poll(), Some(Event::Configured(config())));
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let mut s = socket();
+
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        send!(s, time 0, (IP_RECV, UDP_RECV, dhcp_advertise()));
+
+        // REQ_MAX_RC requests, with the timeout doubling up to REQ_MAX_RT.
+        for (secs, elapsed) in [
+            (0, 0),
+            (1, 100),
+            (3, 300),
+            (7, 700),
+            (15, 1500),
+            (31, 3100),
+            (61, 6100),
+            (91, 9100),
+            (121, 12100),
+            (151, 15100),
+        ] {
+            recv!(s, time secs * 1_000, [(IP_SEND, UDP_SEND, dhcp_request(elapsed))]);
+        }
+
+        // Give up and start over.
+        recv!(s, time 181_000, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        assert_eq!(s.poll(), None);
+    }
+
+    #[test]
+    fn test_renew() {
+        let mut s = socket_bound();
+
+        recv!(s, time 999_000, []);
+        recv!(s, time 1_000_000, [(IP_SEND, UDP_SEND, dhcp_renew(0))]);
+        recv!(s, time 1_005_000, []);
+        send!(s, time 1_005_000, (IP_RECV, UDP_RECV, dhcp_reply()));
+        // Same config, nothing to report.
+        assert_eq!(s.poll(), None);
+
+        match &s.state {
+            ClientState::Bound(state) => {
+                assert_eq!(state.retransmit.retry_at, Instant::from_secs(2005));
+                assert_eq!(state.rebind_at, Instant::from_secs(3005));
+                assert_eq!(state.expires_at, Instant::from_secs(5005));
+            }
+            _ => panic!("Invalid state"),
+        }
+    }
+
+    #[test]
+    fn test_renew_retransmit() {
+        let mut s = socket_bound();
+
+        recv!(s, time 1_000_000, [(IP_SEND, UDP_SEND, dhcp_renew(0))]);
+        recv!(s, time 1_009_999, []);
+        recv!(s, time 1_010_000, [(IP_SEND, UDP_SEND, dhcp_renew(1000))]);
+        recv!(s, time 1_030_000, [(IP_SEND, UDP_SEND, dhcp_renew(3000))]);
+        send!(s, time 1_030_000, (IP_RECV, UDP_RECV, dhcp_reply()));
+        assert_eq!(s.poll(), None);
+    }
+
+    #[test]
+    fn test_renew_no_binding() {
+        let mut s = socket_bound();
+
+        recv!(s, time 1_000_000, [(IP_SEND, UDP_SEND, dhcp_renew(0))]);
+        let mut ia = ia_na(0, 0, 0, 0);
+        ia.addresses.clear();
+        ia.status = Some(Dhcpv6StatusCode::NoBinding);
+        send!(s, time 1_000_000, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            ia_na: Some(ia),
+            ..dhcp_reply()
+        }));
+        // Ask again for the addresses we had.
+        recv!(s, time 1_000_000, [(IP_SEND, UDP_SEND, Dhcpv6Repr {
+            ia_na: Some(ia_na(0, 0, 0, 0)),
+            ..dhcp_request(0)
+        })]);
+        assert_eq!(s.poll(), None);
+    }
+
+    #[test]
+    fn test_rebind() {
+        let mut s = socket_bound();
+
+        recv!(s, time 1_000_000, [(IP_SEND, UDP_SEND, dhcp_renew(0))]);
+        // No answer from the server until T2, ask any server.
+        recv!(s, time 2_000_000, [(IP_SEND, UDP_SEND, dhcp_rebind(0))]);
+        recv!(s, time 2_010_000, [(IP_SEND, UDP_SEND, dhcp_rebind(1000))]);
+
+        let other_server_duid = &[0, 3, 0, 1, 0x06, 0x06, 0x06, 0x06, 0x06, 0x06];
+        send!(s, time 2_010_000, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            server_id: Some(other_server_duid),
+            ..dhcp_reply()
+        }));
+        assert_eq!(
+            s.poll(),
+            Some(Event::Configured(Config {
+                server: ServerInfo {
+                    address: SERVER_LINK_LOCAL,
+                    duid: Vec::from_slice(other_server_duid).unwrap(),
+                },
+                ..config()
+            }))
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut s = socket_bound();
+
+        recv!(s, time 1_000_000, [(IP_SEND, UDP_SEND, dhcp_renew(0))]);
+        recv!(s, time 2_000_000, [(IP_SEND, UDP_SEND, dhcp_rebind(0))]);
+        s.socket.state = match s.socket.state {
+            ClientState::Bound(state) => ClientState::Bound(BoundState {
+                retransmit: Retransmit::new(Instant::from_secs(5000), REB_TIMEOUT),
+                ..state
+            }),
+            _ => panic!("Invalid state"),
+        };
+
+        // The lifetimes expire before the next retransmission.
+        recv!(s, time 3_999_000, []);
+        recv!(s, time 4_000_000, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+        assert_eq!(s.poll(), Some(Event::Deconfigured));
+    }
+
+    #[test]
+    fn test_prefix_delegation() {
+        let mut s = socket();
+        s.set_request_prefix(true);
+
+        let solicit = Dhcpv6Repr {
+            ia_pd: Some(Dhcpv6IaPd {
+                iaid: IAID,
+                t1: 0,
+                t2: 0,
+                prefixes: Vec::new(),
+                status: None,
+            }),
+            ..dhcp_solicit(0)
+        };
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, solicit)]);
+        send!(s, time 0, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            ia_pd: Some(ia_pd(3000, 4000, 0, 0)),
+            ..dhcp_advertise()
+        }));
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, Dhcpv6Repr {
+            ia_pd: Some(ia_pd(0, 0, 0, 0)),
+            ..dhcp_request(0)
+        })]);
+        send!(s, time 0, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            ia_pd: Some(ia_pd(3000, 4000, 0, 0)),
+            ..dhcp_reply()
+        }));
+
+        assert_eq!(
+            s.poll(),
+            Some(Event::Configured(Config {
+                prefix: Some(Ipv6Cidr::new(PREFIX, 56)),
+                ..config()
+            }))
+        );
+
+        // T1 and T2 default to 0.5 and 0.8 times the preferred lifetime.
+        match &s.state {
+            ClientState::Bound(state) => {
+                assert_eq!(state.retransmit.retry_at, Instant::from_secs(1500));
+                assert_eq!(state.rebind_at, Instant::from_secs(2400));
+            }
+            _ => panic!("Invalid state"),
+        }
+    }
+
+    #[test]
+    fn test_information_request() {
+        let mut s = socket();
+        s.set_request_address(false);
+        assert_eq!(s.poll(), None);
+
+        let information_request = |elapsed_time| Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::InformationRequest,
+            option_request: Some(INFORMATION_OPTION_REQUEST),
+            elapsed_time: Some(elapsed_time),
+            ..DHCP_DEFAULT
+        };
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, information_request(0))]);
+        recv!(s, time 1_000, [(IP_SEND, UDP_SEND, information_request(100))]);
+        send!(s, time 1_000, (IP_RECV, UDP_RECV, Dhcpv6Repr {
+            message_type: Dhcpv6MessageType::Reply,
+            server_id: Some(SERVER_DUID),
+            dns_servers: Some(Vec::from_slice(DNS_IPS).unwrap()),
+            information_refresh_time: Some(7200),
+            ..DHCP_DEFAULT
+        }));
+
+        assert_eq!(
+            s.poll(),
+            Some(Event::Configured(Config {
+                address: None,
+                ..config()
+            }))
+        );
+
+        // Refresh after the information refresh time.
+        recv!(s, time 7_200_999, []);
+        recv!(s, time 7_201_000, [(IP_SEND, UDP_SEND, information_request(0))]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut s = socket_bound();
+
+        s.reset();
+        assert_eq!(s.poll(), Some(Event::Deconfigured));
+        recv!(s, time 0, [(IP_SEND, UDP_SEND, dhcp_solicit(0))]);
+    }
+}
@@ -1,19 +1,41 @@
+use core::fmt;
 #[cfg(feature = "async")]
 use core::task::Waker;
 
 use heapless::Vec;
 use managed::ManagedSlice;
 
+#[cfg(feature = "socket-tcp")]
+use crate::iface::SocketHandle;
+#[cfg(feature = "socket-tcp")]
+use crate::socket::tcp;
 use crate::socket::{Context, PollAt};
 use crate::time::{Duration, Instant};
-use crate::wire::dns::{Flags, Opcode, Packet, Question, Rcode, Record, RecordData, Repr, Type};
+use crate::wire::dns::{
+    Flags, Opcode, Packet, Question, Rcode, Record, RecordData, Repr, Section, Type,
+};
+#[cfg(feature = "proto-ipv4")]
+use crate::wire::Ipv4Address;
+#[cfg(feature = "proto-ipv6")]
+use crate::wire::Ipv6Address;
 use crate::wire::{self, IpAddress, IpProtocol, IpRepr, UdpRepr};
 
 #[cfg(feature = "async")]
 use super::WakerRegistration;
 
+#[cfg(feature = "socket-mdns")]
+mod responder;
+
+#[cfg(feature = "socket-mdns")]
+pub use self::responder::{Responder, ResponderError, ResponderState, Service, MAX_SERVICE_COUNT};
+
 pub const MAX_ADDRESS_COUNT: usize = 4;
 pub const MAX_SERVER_COUNT: usize = 4;
+pub const MAX_RECORD_COUNT: usize = 4;
+/// Maximum number of entries an owned answer cache grows to.
+pub const MAX_CACHE_ENTRY_COUNT: usize = 32;
+/// The maximum length of the data of a TXT record in a query result.
+pub const MAX_TXT_LEN: usize = 255;
 
 const DNS_PORT: u16 = 53;
 const MDNS_DNS_PORT: u16 = 5353;
@@ -22,13 +44,21 @@ const RETRANSMIT_DELAY: Duration = Duration::from_millis(1_000);
 const MAX_RETRANSMIT_DELAY: Duration = Duration::from_millis(10_000);
 const RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(10_000); // Should generally be 2-10 secs
 
+/// The IPv6 multicast group of mDNS, which an interface must join with
+/// [`Interface::join_multicast_group`] to receive mDNS queries and responses.
+///
+/// [`Interface::join_multicast_group`]: crate::iface::Interface::join_multicast_group
 #[cfg(feature = "proto-ipv6")]
-const MDNS_IPV6_ADDR: IpAddress = IpAddress::Ipv6(crate::wire::Ipv6Address([
+pub const MDNS_IPV6_ADDR: IpAddress = IpAddress::Ipv6(crate::wire::Ipv6Address([
     0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xfb,
 ]));
 
+/// The IPv4 multicast group of mDNS, which an interface must join with
+/// [`Interface::join_multicast_group`] to receive mDNS queries and responses.
+///
+/// [`Interface::join_multicast_group`]: crate::iface::Interface::join_multicast_group
 #[cfg(feature = "proto-ipv4")]
-const MDNS_IPV4_ADDR: IpAddress = IpAddress::Ipv4(crate::wire::Ipv4Address([224, 0, 0, 251]));
+pub const MDNS_IPV4_ADDR: IpAddress = IpAddress::Ipv4(crate::wire::Ipv4Address([224, 0, 0, 251]));
 
 /// Error returned by [`Socket::start_query`]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -49,6 +79,186 @@ pub enum GetQueryResultError {
     Failed,
 }
 
+/// A domain name in wire format, as a sequence of length-prefixed labels ending
+/// with the empty root label.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DnsName(Vec<u8, MAX_NAME_LEN>);
+
+impl DnsName {
+    /// Return the name in wire format, such as `b"\x09rust-lang\x03org\x00"`.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Return an iterator over the labels of the name, without the root label.
+    pub fn labels(&self) -> impl Iterator<Item = &[u8]> {
+        let mut rest = &self.0[..];
+        core::iter::from_fn(move || {
+            let (&len, labels) = rest.split_first()?;
+            if len == 0 {
+                return None;
+            }
+            let (label, labels) = labels.split_at(len as usize);
+            rest = labels;
+            Some(label)
+        })
+    }
+}
+
+impl fmt::Display for DnsName {
+    /// Format the name in human-friendly format, such as `rust-lang.org`. Characters
+    /// that aren't printable or would be ambiguous are escaped as in master files.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, label) in self.labels().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            for &c in label {
+                match c {
+                    b'.' | b'\\' => write!(f, "\\{}", c as char)?,
+                    0x21..=0x7e => write!(f, "{}", c as char)?,
+                    _ => write!(f, "\\{:03}", c)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DnsName({})", self)
+    }
+}
+
+/// A record in the answer to a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DnsRecord {
+    /// How many seconds the record may still be cached for.
+    pub ttl: u32,
+    pub data: DnsRecordData,
+}
+
+/// The data of a record in the answer to a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DnsRecordData {
+    #[cfg(feature = "proto-ipv4")]
+    A(Ipv4Address),
+    #[cfg(feature = "proto-ipv6")]
+    Aaaa(Ipv6Address),
+    Cname(DnsName),
+    /// A domain name, such as the one of an address in a reverse lookup.
+    Ptr(DnsName),
+    /// A mail exchange for the domain, see RFC 1035 § 3.3.9.
+    Mx {
+        preference: u16,
+        exchange: DnsName,
+    },
+    /// The location of a service, see RFC 2782.
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: DnsName,
+    },
+    /// The strings of a TXT record, each prefixed by its length. Use
+    /// [txt_strings](#method.txt_strings) to iterate over them.
+    Txt(Vec<u8, MAX_TXT_LEN>),
+}
+
+impl DnsRecordData {
+    fn parse(p: &Packet<&[u8]>, data: &RecordData) -> wire::Result<Option<Self>> {
+        let name = |name| {
+            let mut dest = Vec::new();
+            copy_name(&mut dest, p.parse_name(name))?;
+            Ok::<_, wire::Error>(DnsName(dest))
+        };
+
+        Ok(Some(match *data {
+            #[cfg(feature = "proto-ipv4")]
+            RecordData::A(addr) => DnsRecordData::A(addr),
+            #[cfg(feature = "proto-ipv6")]
+            RecordData::Aaaa(addr) => DnsRecordData::Aaaa(addr),
+            RecordData::Cname(cname) => DnsRecordData::Cname(name(cname)?),
+            RecordData::Ptr(ptr) => DnsRecordData::Ptr(name(ptr)?),
+            RecordData::Mx {
+                preference,
+                exchange,
+            } => DnsRecordData::Mx {
+                preference,
+                exchange: name(exchange)?,
+            },
+            RecordData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => DnsRecordData::Srv {
+                priority,
+                weight,
+                port,
+                target: name(target)?,
+            },
+            RecordData::Txt(txt) => match Vec::from_slice(txt) {
+                Ok(txt) => DnsRecordData::Txt(txt),
+                Err(_) => {
+                    net_trace!("TXT record too long, ignoring");
+                    return Ok(None);
+                }
+            },
+            RecordData::Other(type_, data) => {
+                net_trace!("unknown: {:?} {:?}", type_, data);
+                return Ok(None);
+            }
+        }))
+    }
+
+    /// Return the type of the record.
+    pub fn type_(&self) -> Type {
+        match self {
+            #[cfg(feature = "proto-ipv4")]
+            DnsRecordData::A(_) => Type::A,
+            #[cfg(feature = "proto-ipv6")]
+            DnsRecordData::Aaaa(_) => Type::Aaaa,
+            DnsRecordData::Cname(_) => Type::Cname,
+            DnsRecordData::Ptr(_) => Type::Ptr,
+            DnsRecordData::Mx { .. } => Type::Mx,
+            DnsRecordData::Srv { .. } => Type::Srv,
+            DnsRecordData::Txt(_) => Type::Txt,
+        }
+    }
+
+    /// Return the address of an A or AAAA record.
+    pub fn address(&self) -> Option<IpAddress> {
+        match *self {
+            #[cfg(feature = "proto-ipv4")]
+            DnsRecordData::A(addr) => Some(addr.into()),
+            #[cfg(feature = "proto-ipv6")]
+            DnsRecordData::Aaaa(addr) => Some(addr.into()),
+            _ => None,
+        }
+    }
+
+    /// Return an iterator over the strings of a TXT record, or no strings for
+    /// other records.
+    pub fn txt_strings(&self) -> impl Iterator<Item = &[u8]> {
+        let mut rest = match self {
+            DnsRecordData::Txt(txt) => &txt[..],
+            _ => &[],
+        };
+        core::iter::from_fn(move || {
+            let (&len, strings) = rest.split_first()?;
+            // A length running past the end of the record ends the strings.
+            let string = strings.get(..len as usize)?;
+            rest = &strings[len as usize..];
+            Some(string)
+        })
+    }
+}
+
 /// State for an in-progress DNS query.
 ///
 /// The only reason this struct is public is to allow the socket state
@@ -91,6 +301,48 @@ struct PendingQuery {
 
     server_idx: usize,
     mdns: MulticastDns,
+    transport: Transport,
+}
+
+impl PendingQuery {
+    /// Emit the query into `buffer`, returning its length.
+    fn emit(&self, buffer: &mut [u8; 512]) -> usize {
+        let question = [Question {
+            name: &self.name,
+            type_: self.type_,
+            unicast_response: false,
+        }];
+        let repr = Repr {
+            transaction_id: self.txid,
+            flags: Flags::RECURSION_DESIRED,
+            opcode: Opcode::Query,
+            rcode: Rcode::NoError,
+            questions: Section::new(&question),
+            answers: Section::default(),
+            authorities: Section::default(),
+            additionals: Section::default(),
+            opt: None,
+        };
+
+        // A query with a single name always fits.
+        let len = repr.buffer_len();
+        repr.emit(&mut Packet::new_unchecked(&mut buffer[..len]))
+            .unwrap();
+        len
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Transport {
+    Udp,
+    /// The query is retried over TCP after a truncated response, see RFC 7766.
+    #[cfg(feature = "socket-tcp")]
+    Tcp {
+        /// Whether the connection to the server has been opened.
+        opened: bool,
+        /// Whether the query has been written to the connection.
+        sent: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -102,7 +354,20 @@ pub enum MulticastDns {
 
 #[derive(Debug)]
 struct CompletedQuery {
-    addresses: Vec<IpAddress, MAX_ADDRESS_COUNT>,
+    records: Vec<DnsRecord, MAX_RECORD_COUNT>,
+}
+
+/// An answer to a DNS query, cached until its TTL runs out.
+///
+/// The only reason this struct is public is to allow the socket state
+/// to be allocated externally.
+#[derive(Debug)]
+pub struct DnsCacheEntry {
+    name: Vec<u8, MAX_NAME_LEN>,
+    type_: Type,
+    /// The records of the name, or none if it doesn't exist or has no such records.
+    records: Vec<DnsRecord, MAX_RECORD_COUNT>,
+    expires_at: Instant,
 }
 
 /// A handle to an in-progress DNS query.
@@ -117,9 +382,14 @@ pub struct QueryHandle(usize);
 pub struct Socket<'a> {
     servers: Vec<IpAddress, MAX_SERVER_COUNT>,
     queries: ManagedSlice<'a, Option<DnsQuery>>,
+    cache: ManagedSlice<'a, Option<DnsCacheEntry>>,
 
     /// The time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
     hop_limit: Option<u8>,
+
+    /// The TCP socket retrying queries whose response was truncated.
+    #[cfg(feature = "socket-tcp")]
+    tcp: Option<SocketHandle>,
 }
 
 impl<'a> Socket<'a> {
@@ -135,17 +405,48 @@ impl<'a> Socket<'a> {
         Socket {
             servers: Vec::from_slice(servers).unwrap(),
             queries: queries.into(),
+            cache: ManagedSlice::Borrowed(&mut []),
             hop_limit: None,
+            #[cfg(feature = "socket-tcp")]
+            tcp: None,
         }
     }
 
     /// Update the list of DNS servers, will replace all existing servers
     ///
+    /// The answers cached from the previous servers are flushed.
+    ///
     /// # Panics
     ///
     /// Panics if `servers.len() > MAX_SERVER_COUNT`
     pub fn update_servers(&mut self, servers: &[IpAddress]) {
         self.servers = Vec::from_slice(servers).unwrap();
+        self.flush_cache();
+    }
+
+    /// Set the storage of the answer cache. The cache is disabled until it's given some.
+    ///
+    /// Answers are cached for the lowest TTL of their records, and names that don't exist
+    /// or have no records of the type queried for the negative TTL of their zone, see
+    /// [RFC 2308]. While an answer is cached, [start_query](#method.start_query) completes
+    /// without sending a query. When the cache is full, the entry closest to expiring is
+    /// replaced. Owned storage grows as needed, up to `MAX_CACHE_ENTRY_COUNT` entries.
+    ///
+    /// Truncated responses, and errors other than nonexistent names, aren't cached.
+    ///
+    /// [RFC 2308]: https://tools.ietf.org/html/rfc2308
+    pub fn set_cache<C>(&mut self, cache: C)
+    where
+        C: Into<ManagedSlice<'a, Option<DnsCacheEntry>>>,
+    {
+        self.cache = cache.into();
+    }
+
+    /// Remove all the answers from the cache.
+    pub fn flush_cache(&mut self) {
+        for entry in self.cache.iter_mut() {
+            *entry = None;
+        }
     }
 
     /// Return the time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
@@ -172,7 +473,31 @@ impl<'a> Socket<'a> {
             panic!("the time-to-live value of a packet must not be zero")
         }
 
-        self.hop_limit = hop_limit
+        self.hop_limit = hop_limit;
+    }
+
+    /// Return the TCP socket used when a response is truncated, if any.
+    ///
+    /// See also the [set_tcp_socket](#method.set_tcp_socket) method
+    #[cfg(feature = "socket-tcp")]
+    pub fn tcp_socket(&self) -> Option<SocketHandle> {
+        self.tcp
+    }
+
+    /// Set the TCP socket used when a response is truncated.
+    ///
+    /// When a server sets the TC bit of a response, because the answer doesn't fit in
+    /// a UDP datagram, the query is sent again over TCP, see [RFC 7766]. The socket must
+    /// be in the same [`SocketSet`] as this one, and is opened and closed by it, so it
+    /// mustn't be used for anything else. Its receive buffer must hold the whole response
+    /// and its 2-octet length, or the query fails. Without a TCP socket the records of
+    /// the truncated response are used.
+    ///
+    /// [RFC 7766]: https://tools.ietf.org/html/rfc7766
+    /// [`SocketSet`]: crate::iface::SocketSet
+    #[cfg(feature = "socket-tcp")]
+    pub fn set_tcp_socket(&mut self, handle: Option<SocketHandle>) {
+        self.tcp = handle;
     }
 
     fn find_free_query(&mut self) -> Option<QueryHandle> {
@@ -252,6 +577,54 @@ impl<'a> Socket<'a> {
         self.start_query_raw(cx, &raw_name, query_type, mdns)
     }
 
+    /// Start a reverse query, for the PTR records of the name of `addr` in the
+    /// `in-addr.arpa` or `ip6.arpa` domain, see RFC 1035 § 3.5 and RFC 3596 § 2.5.
+    ///
+    /// The names of the address are returned by
+    /// [get_query_records](#method.get_query_records).
+    pub fn start_reverse_query(
+        &mut self,
+        cx: &mut Context,
+        addr: IpAddress,
+    ) -> Result<QueryHandle, StartQueryError> {
+        let mut raw_name: Vec<u8, MAX_NAME_LEN> = Vec::new();
+
+        // The name is at most 74 octets long, so it always fits.
+        match addr {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddress::Ipv4(addr) => {
+                for &octet in addr.as_bytes().iter().rev() {
+                    let mut label: Vec<u8, 3> = Vec::new();
+                    if octet >= 100 {
+                        label.push(b'0' + octet / 100).unwrap();
+                    }
+                    if octet >= 10 {
+                        label.push(b'0' + octet / 10 % 10).unwrap();
+                    }
+                    label.push(b'0' + octet % 10).unwrap();
+                    raw_name.push(label.len() as u8).unwrap();
+                    raw_name.extend_from_slice(&label).unwrap();
+                }
+                raw_name
+                    .extend_from_slice(b"\x07in-addr\x04arpa\x00")
+                    .unwrap();
+            }
+            #[cfg(feature = "proto-ipv6")]
+            IpAddress::Ipv6(addr) => {
+                const HEX: &[u8; 16] = b"0123456789abcdef";
+                for &octet in addr.as_bytes().iter().rev() {
+                    raw_name.push(1).unwrap();
+                    raw_name.push(HEX[(octet & 0xf) as usize]).unwrap();
+                    raw_name.push(1).unwrap();
+                    raw_name.push(HEX[(octet >> 4) as usize]).unwrap();
+                }
+                raw_name.extend_from_slice(b"\x03ip6\x04arpa\x00").unwrap();
+            }
+        }
+
+        self.start_query_raw(cx, &raw_name, Type::Ptr, MulticastDns::Disabled)
+    }
+
     /// Start a query with a raw (wire-format) DNS name.
     /// `b"\x09rust-lang\x03org\x00"`
     ///
@@ -265,6 +638,33 @@ impl<'a> Socket<'a> {
     ) -> Result<QueryHandle, StartQueryError> {
         let handle = self.find_free_query().ok_or(StartQueryError::NoFreeSlot)?;
 
+        let now = cx.now();
+        let cached = self.cache.iter().flatten().find(|entry| {
+            entry.type_ == query_type
+                && now < entry.expires_at
+                && entry.name.eq_ignore_ascii_case(raw_name)
+        });
+        if let Some(entry) = cached {
+            net_trace!("answering query from the cache");
+            let state = if entry.records.is_empty() {
+                State::Failure
+            } else {
+                // The records may only be cached for the time left.
+                let left = (entry.expires_at - now).secs() as u32;
+                let mut records = entry.records.clone();
+                for record in records.iter_mut() {
+                    record.ttl = record.ttl.min(left);
+                }
+                State::Completed(CompletedQuery { records })
+            };
+            self.queries[handle.0] = Some(DnsQuery {
+                state,
+                #[cfg(feature = "async")]
+                waker: WakerRegistration::new(),
+            });
+            return Ok(handle);
+        }
+
         self.queries[handle.0] = Some(DnsQuery {
             state: State::Pending(PendingQuery {
                 name: Vec::from_slice(raw_name).map_err(|_| StartQueryError::NameTooLong)?,
@@ -276,6 +676,7 @@ impl<'a> Socket<'a> {
                 retransmit_at: Instant::ZERO,
                 server_idx: 0,
                 mdns,
+                transport: Transport::Udp,
             }),
             #[cfg(feature = "async")]
             waker: WakerRegistration::new(),
@@ -286,6 +687,8 @@ impl<'a> Socket<'a> {
     /// Get the result of a query.
     ///
     /// If the query is completed, the query slot is automatically freed.
+    /// Only the addresses of A and AAAA records are returned, see
+    /// [get_query_records](#method.get_query_records) for the other types.
     ///
     /// # Panics
     /// Panics if the QueryHandle corresponds to a free slot.
@@ -293,6 +696,26 @@ impl<'a> Socket<'a> {
         &mut self,
         handle: QueryHandle,
     ) -> Result<Vec<IpAddress, MAX_ADDRESS_COUNT>, GetQueryResultError> {
+        let records = self.get_query_records(handle)?;
+        Ok(records
+            .iter()
+            .filter_map(|record| record.data.address())
+            .take(MAX_ADDRESS_COUNT)
+            .collect())
+    }
+
+    /// Get the records answering a query, with their TTL.
+    ///
+    /// The records are of the type queried for. CNAME records are followed, unless
+    /// they're what was queried for. If the query is completed, the query slot is
+    /// automatically freed.
+    ///
+    /// # Panics
+    /// Panics if the QueryHandle corresponds to a free slot.
+    pub fn get_query_records(
+        &mut self,
+        handle: QueryHandle,
+    ) -> Result<Vec<DnsRecord, MAX_RECORD_COUNT>, GetQueryResultError> {
         let slot = &mut self.queries[handle.0];
         let q = slot.as_mut().unwrap();
         match &mut q.state {
@@ -300,7 +723,7 @@ impl<'a> Socket<'a> {
             State::Pending(_) => Err(GetQueryResultError::Pending),
             // Query is done
             State::Completed(q) => {
-                let res = q.addresses.clone();
+                let res = core::mem::take(&mut q.records);
                 *slot = None; // Free up the slot for recycling.
                 Ok(res)
             }
@@ -351,7 +774,7 @@ impl<'a> Socket<'a> {
 
     pub(crate) fn process(
         &mut self,
-        _cx: &mut Context,
+        cx: &mut Context,
         ip_repr: &IpRepr,
         udp_repr: &UdpRepr,
         payload: &[u8],
@@ -367,6 +790,11 @@ impl<'a> Socket<'a> {
             udp_repr.dst_port
         );
 
+        self.process_response(cx, payload, Some(udp_repr.dst_port));
+    }
+
+    /// Process a response received over UDP on `port`, or over TCP if it's `None`.
+    fn process_response(&mut self, cx: &mut Context, payload: &[u8], port: Option<u16>) {
         let p = match Packet::new_checked(payload) {
             Ok(x) => x,
             Err(_) => {
@@ -389,16 +817,20 @@ impl<'a> Socket<'a> {
             return;
         }
 
+        #[cfg(feature = "socket-tcp")]
+        let tcp_busy = self.queries.iter().flatten().any(|q| match &q.state {
+            State::Pending(pq) => pq.transport != Transport::Udp,
+            _ => false,
+        });
+
         // Find pending query
         for q in self.queries.iter_mut().flatten() {
             if let State::Pending(pq) = &mut q.state {
-                if udp_repr.dst_port != pq.port || p.transaction_id() != pq.txid {
-                    continue;
-                }
-
-                if p.rcode() == Rcode::NXDomain {
-                    net_trace!("rcode NXDomain");
-                    q.set_state(State::Failure);
+                let transport_ok = match port {
+                    Some(port) => pq.transport == Transport::Udp && port == pq.port,
+                    None => pq.transport != Transport::Udp,
+                };
+                if !transport_ok || p.transaction_id() != pq.txid {
                     continue;
                 }
 
@@ -428,7 +860,44 @@ impl<'a> Socket<'a> {
                     }
                 }
 
-                let mut addresses = Vec::new();
+                // Retry over TCP if the answer didn't fit, or else make do with the
+                // records that did.
+                #[cfg(feature = "socket-tcp")]
+                if p.flags().contains(Flags::TRUNCATED)
+                    && pq.transport == Transport::Udp
+                    && self.tcp.is_some()
+                    && matches!(pq.mdns, MulticastDns::Disabled)
+                    && !tcp_busy
+                {
+                    net_trace!("response truncated, retrying over TCP");
+                    pq.transport = Transport::Tcp {
+                        opened: false,
+                        sent: false,
+                    };
+                    pq.timeout_at = Some(cx.now() + RETRANSMIT_TIMEOUT);
+                    return;
+                }
+
+                // The answer is cached for the name queried, not the one of a CNAME.
+                let name = pq.name.clone();
+                let answers = payload;
+                // The records of a truncated response may be missing some.
+                let cacheable = !p.flags().contains(Flags::TRUNCATED);
+
+                if p.rcode() == Rcode::NXDomain {
+                    net_trace!("rcode NXDomain");
+                    match negative_ttl(&p, answers) {
+                        Some(ttl) if cacheable => {
+                            cache_insert(&mut self.cache, name, pq.type_, Vec::new(), ttl, cx.now())
+                        }
+                        _ => (),
+                    }
+                    q.set_state(State::Failure);
+                    return;
+                }
+
+                let mut records: Vec<DnsRecord, MAX_RECORD_COUNT> = Vec::new();
+                let mut ttl = u32::MAX;
 
                 for _ in 0..p.answer_record_count() {
                     let (payload2, r) = match Record::parse(payload) {
@@ -453,27 +922,15 @@ impl<'a> Socket<'a> {
                     }
 
                     match r.data {
-                        #[cfg(feature = "proto-ipv4")]
-                        RecordData::A(addr) => {
-                            net_trace!("A: {:?}", addr);
-                            if addresses.push(addr.into()).is_err() {
-                                net_trace!("too many addresses in response, ignoring {:?}", addr);
-                            }
-                        }
-                        #[cfg(feature = "proto-ipv6")]
-                        RecordData::Aaaa(addr) => {
-                            net_trace!("AAAA: {:?}", addr);
-                            if addresses.push(addr.into()).is_err() {
-                                net_trace!("too many addresses in response, ignoring {:?}", addr);
-                            }
-                        }
-                        RecordData::Cname(name) => {
+                        RecordData::Cname(name)
+                            if pq.type_ != Type::Cname && pq.type_ != Type::Any =>
+                        {
                             net_trace!("CNAME: {:?}", name);
 
                             // When faced with a CNAME, recursive resolvers are supposed to
                             // resolve the CNAME and append the results for it.
                             //
-                            // We update the query with the new name, so that we pick up the A/AAAA
+                            // We update the query with the new name, so that we pick up the
                             // records for the CNAME when we parse them later.
                             // I believe it's mandatory the CNAME results MUST come *after* in the
                             // packet, so it's enough to do one linear pass over it.
@@ -481,17 +938,49 @@ impl<'a> Socket<'a> {
                                 net_trace!("dns answer cname malformed");
                                 return;
                             }
+                            ttl = ttl.min(r.ttl);
+                            continue;
                         }
-                        RecordData::Other(type_, data) => {
-                            net_trace!("unknown: {:?} {:?}", type_, data)
+                        ref data if data.type_() != pq.type_ && pq.type_ != Type::Any => {
+                            net_trace!("answer type mismatch: {:?}", r);
+                            continue;
                         }
+                        _ => (),
+                    }
+
+                    let data = match DnsRecordData::parse(&p, &r.data) {
+                        Ok(Some(data)) => data,
+                        Ok(None) => continue,
+                        Err(_) => {
+                            net_trace!("dns answer record data malformed");
+                            return;
+                        }
+                    };
+                    net_trace!("{:?}", data);
+                    ttl = ttl.min(r.ttl);
+                    let record = DnsRecord { ttl: r.ttl, data };
+                    if records.push(record).is_err() {
+                        net_trace!("too many records in response, ignoring {:?}", r);
                     }
                 }
 
-                q.set_state(if addresses.is_empty() {
+                // Other errors are about the server, not the name.
+                let ttl = if !cacheable || p.rcode() != Rcode::NoError {
+                    None
+                } else if records.is_empty() {
+                    negative_ttl(&p, answers)
+                } else {
+                    Some(ttl)
+                };
+                if let Some(ttl) = ttl {
+                    let records = records.clone();
+                    cache_insert(&mut self.cache, name, pq.type_, records, ttl, cx.now());
+                }
+
+                q.set_state(if records.is_empty() {
                     State::Failure
                 } else {
-                    State::Completed(CompletedQuery { addresses })
+                    State::Completed(CompletedQuery { records })
                 });
 
                 // If we get here, packet matched the current query, stop processing.
@@ -511,6 +1000,11 @@ impl<'a> Socket<'a> {
 
         for q in self.queries.iter_mut().flatten() {
             if let State::Pending(pq) = &mut q.state {
+                // Queries retried over TCP are sent by poll_tcp.
+                if pq.transport != Transport::Udp {
+                    continue;
+                }
+
                 // As per RFC 6762 any DNS query ending in .local. MUST be sent as mdns
                 // so we internally overwrite the servers for any of those queries
                 // in this function.
@@ -562,19 +1056,9 @@ impl<'a> Socket<'a> {
                     continue;
                 }
 
-                let repr = Repr {
-                    transaction_id: pq.txid,
-                    flags: Flags::RECURSION_DESIRED,
-                    opcode: Opcode::Query,
-                    question: Question {
-                        name: &pq.name,
-                        type_: pq.type_,
-                    },
-                };
-
                 let mut payload = [0u8; 512];
-                let payload = &mut payload[..repr.buffer_len()];
-                repr.emit(&mut Packet::new_unchecked(payload));
+                let len = pq.emit(&mut payload);
+                let payload = &payload[..len];
 
                 let dst_port = match pq.mdns {
                     #[cfg(feature = "socket-mdns")]
@@ -617,12 +1101,106 @@ impl<'a> Socket<'a> {
         Ok(())
     }
 
+    /// Retry the queries whose response was truncated over `tcp`, the socket set with
+    /// [set_tcp_socket](#method.set_tcp_socket): open the connection, write the query to
+    /// it, and read the response.
+    #[cfg(feature = "socket-tcp")]
+    pub(crate) fn poll_tcp(&mut self, cx: &mut Context, tcp: &mut tcp::Socket) {
+        let awaiting_response = self.queries.iter().flatten().any(|q| match &q.state {
+            State::Pending(pq) => matches!(pq.transport, Transport::Tcp { sent: true, .. }),
+            _ => false,
+        });
+
+        // The response is prefixed with its length. It's only read once it has been
+        // received whole, so that it's contiguous at the start of the buffer of the
+        // connection, which was opened for this query.
+        if awaiting_response {
+            if let Ok(&[len0, len1]) = tcp.peek(2) {
+                let len = 2 + u16::from_be_bytes([len0, len1]) as usize;
+                if len > tcp.recv_capacity() {
+                    net_trace!("response over TCP too long");
+                    tcp.abort();
+                } else if tcp.recv_queue() >= len {
+                    match tcp.peek(len) {
+                        Ok(message) if message.len() == len => {
+                            net_trace!("receiving {} octets over TCP", len - 2);
+                            self.process_response(cx, &message[2..], None);
+                            tcp.close();
+                        }
+                        _ => {
+                            net_trace!("response over TCP not contiguous");
+                            tcp.abort();
+                        }
+                    }
+                }
+            }
+        }
+
+        for q in self.queries.iter_mut().flatten() {
+            if let State::Pending(pq) = &mut q.state {
+                let (opened, sent) = match pq.transport {
+                    Transport::Tcp { opened, sent } => (opened, sent),
+                    Transport::Udp => continue,
+                };
+
+                if !opened {
+                    // The connection of a previous query may still be closing.
+                    if tcp.is_open() {
+                        tcp.abort();
+                    }
+                    let result = match self.servers.get(pq.server_idx) {
+                        Some(&server) => {
+                            let local_port = cx.rand().rand_source_port();
+                            tcp.connect(cx, (server, DNS_PORT), local_port)
+                        }
+                        None => Err(tcp::ConnectError::Unaddressable),
+                    };
+                    if let Err(e) = result {
+                        net_trace!("cannot retry over TCP: {:?}", e);
+                        q.set_state(State::Failure);
+                        continue;
+                    }
+                    pq.transport = Transport::Tcp { opened: true, sent };
+                } else if !tcp.is_active() || pq.timeout_at.map_or(false, |t| t < cx.now()) {
+                    net_trace!("query over TCP failed");
+                    tcp.abort();
+                    q.set_state(State::Failure);
+                    continue;
+                }
+
+                if !sent && tcp.may_send() {
+                    let mut message = [0u8; 512];
+                    let len = pq.emit(&mut message);
+                    let prefix = (len as u16).to_be_bytes();
+                    if tcp.send_slice(&prefix) != Ok(2)
+                        || tcp.send_slice(&message[..len]) != Ok(len)
+                    {
+                        net_trace!("query too long for the TCP transmit buffer");
+                        tcp.abort();
+                        q.set_state(State::Failure);
+                        continue;
+                    }
+                    pq.transport = Transport::Tcp {
+                        opened: true,
+                        sent: true,
+                    };
+                }
+            }
+        }
+    }
+
     pub(crate) fn poll_at(&self, _cx: &Context) -> PollAt {
         self.queries
             .iter()
             .flatten()
             .filter_map(|q| match &q.state {
-                State::Pending(pq) => Some(PollAt::Time(pq.retransmit_at)),
+                State::Pending(pq) => match pq.transport {
+                    Transport::Udp => Some(PollAt::Time(pq.retransmit_at)),
+                    #[cfg(feature = "socket-tcp")]
+                    Transport::Tcp { opened: false, .. } => Some(PollAt::Now),
+                    #[cfg(feature = "socket-tcp")]
+                    Transport::Tcp { .. } => pq.timeout_at.map(PollAt::Time),
+                },
                 State::Completed(_) => None,
                 State::Failure => None,
             })
@@ -631,9 +1209,9 @@ impl<'a> Socket<'a> {
     }
 }
 
-fn eq_names<'a>(
+fn eq_names<'a, 'b>(
     mut a: impl Iterator<Item = wire::Result<&'a [u8]>>,
-    mut b: impl Iterator<Item = wire::Result<&'a [u8]>>,
+    mut b: impl Iterator<Item = wire::Result<&'b [u8]>>,
 ) -> wire::Result<bool> {
     loop {
         match (a.next(), b.next()) {
@@ -648,9 +1226,9 @@ fn eq_names<'a>(
             (None, _) => return Ok(false),
             (_, None) => return Ok(false),
 
-            // Got two labels, check if they're equal
+            // Got two labels, check if they're equal. Names are case-insensitive.
             (Some(Ok(la)), Some(Ok(lb))) => {
-                if la != lb {
+                if !la.eq_ignore_ascii_case(lb) {
                     return Ok(false);
                 }
             }
@@ -658,6 +1236,84 @@ fn eq_names<'a>(
     }
 }
 
+/// Return how long a negative answer may be cached: the lower of the TTL and the
+/// minimum field of the SOA record in its authority section, see RFC 2308 § 5.
+/// Negative answers without one aren't cached.
+fn negative_ttl(p: &Packet<&[u8]>, mut payload: &[u8]) -> Option<u32> {
+    for i in 0..p.answer_record_count() + p.authority_record_count() {
+        let (rest, r) = Record::parse(payload).ok()?;
+        payload = rest;
+
+        match r.data {
+            RecordData::Other(Type::Soa, data)
+                if i >= p.answer_record_count() && data.len() >= 20 =>
+            {
+                let minimum = &data[data.len() - 4..];
+                let minimum = u32::from_be_bytes([minimum[0], minimum[1], minimum[2], minimum[3]]);
+                return Some(r.ttl.min(minimum));
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Cache an answer, replacing the previous one to the same query, or an entry that
+/// expired, or else the one closest to expiring once owned storage can't grow anymore.
+fn cache_insert(
+    cache: &mut ManagedSlice<'_, Option<DnsCacheEntry>>,
+    name: Vec<u8, MAX_NAME_LEN>,
+    type_: Type,
+    records: Vec<DnsRecord, MAX_RECORD_COUNT>,
+    ttl: u32,
+    now: Instant,
+) {
+    if ttl == 0 {
+        return;
+    }
+
+    let index = cache
+        .iter()
+        .position(|entry| match entry {
+            Some(entry) => entry.type_ == type_ && entry.name.eq_ignore_ascii_case(&name),
+            None => false,
+        })
+        .or_else(|| {
+            cache.iter().position(|entry| match entry {
+                Some(entry) => entry.expires_at <= now,
+                None => true,
+            })
+        });
+    let index = match index {
+        Some(index) => index,
+        None => match cache {
+            #[cfg(any(feature = "std", feature = "alloc"))]
+            ManagedSlice::Owned(cache) if cache.len() < MAX_CACHE_ENTRY_COUNT => {
+                cache.push(None);
+                cache.len() - 1
+            }
+            _ => {
+                let oldest = (0..cache.len()).min_by_key(|&index| match &cache[index] {
+                    Some(entry) => entry.expires_at,
+                    None => Instant::ZERO,
+                });
+                match oldest {
+                    Some(index) => index,
+                    None => return,
+                }
+            }
+        },
+    };
+
+    net_trace!("caching answer for {} seconds", ttl);
+    cache[index] = Some(DnsCacheEntry {
+        name,
+        type_,
+        records,
+        expires_at: now + Duration::from_secs(ttl as u64),
+    });
+}
+
 fn copy_name<'a, const N: usize>(
     dest: &mut Vec<u8, N>,
     name: impl Iterator<Item = wire::Result<&'a [u8]>>,
@@ -675,3 +1331,606 @@ fn copy_name<'a, const N: usize>(
 
     Ok(())
 }
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use super::*;
+    use crate::wire::Ipv4Address;
+    #[cfg(feature = "socket-tcp")]
+    use crate::wire::TcpRepr;
+    use std::vec::Vec as StdVec;
+
+    const SERVER_ADDR: Ipv4Address = Ipv4Address([192, 168, 1, 53]);
+    const NAME: &[u8] = b"\x07example\x03com\x00";
+
+    fn socket() -> Socket<'static> {
+        let mut socket = Socket::new(&[SERVER_ADDR.into()], vec![]);
+        socket.set_cache(vec![]);
+        socket
+    }
+
+    /// Send the pending query, and return its transaction ID and source port.
+    fn send_query(socket: &mut Socket, cx: &mut Context) -> Option<(u16, u16)> {
+        let mut sent = None;
+        socket
+            .dispatch(cx, |_, (_, udp_repr, payload)| {
+                let packet = Packet::new_checked(payload).unwrap();
+                sent = Some((packet.transaction_id(), udp_repr.src_port));
+                Ok::<(), ()>(())
+            })
+            .unwrap();
+        sent
+    }
+
+    fn recv_response(socket: &mut Socket, cx: &mut Context, query: (u16, u16), response: &[u8]) {
+        let (txid, port) = query;
+        let mut payload = StdVec::new();
+        payload.extend_from_slice(&txid.to_be_bytes());
+        payload.extend_from_slice(response);
+
+        let ip_repr = IpRepr::new(
+            SERVER_ADDR.into(),
+            Ipv4Address([192, 168, 1, 1]).into(),
+            IpProtocol::Udp,
+            8 + payload.len(),
+            64,
+        );
+        let udp_repr = UdpRepr {
+            src_port: DNS_PORT,
+            dst_port: port,
+        };
+        socket.process(cx, &ip_repr, &udp_repr, &payload);
+    }
+
+    fn answer(ttl: u32) -> StdVec<u8> {
+        let mut response = vec![0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00];
+        response.extend_from_slice(NAME);
+        response.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+        response.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01]);
+        response.extend_from_slice(&ttl.to_be_bytes());
+        response.extend_from_slice(&[0x00, 0x04, 0x5d, 0xb8, 0xd8, 0x22]);
+        response
+    }
+
+    fn nxdomain(ttl: u32, minimum: u32) -> StdVec<u8> {
+        let mut response = vec![0x81, 0x83, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00];
+        response.extend_from_slice(NAME);
+        response.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+        response.extend_from_slice(&[0xc0, 0x14, 0x00, 0x06, 0x00, 0x01]);
+        response.extend_from_slice(&ttl.to_be_bytes());
+        response.extend_from_slice(&[0x00, 0x16, 0x00, 0x00]);
+        response.extend_from_slice(&[0; 16]);
+        response.extend_from_slice(&minimum.to_be_bytes());
+        response
+    }
+
+    /// Build a response to the query for `name` of type `type_`, with `answers` of
+    /// the (type, TTL, data) of records all named `name`.
+    fn response(name: &[u8], type_: Type, answers: &[(Type, u32, &[u8])]) -> StdVec<u8> {
+        let mut response = vec![0x81, 0x80, 0x00, 0x01, 0x00, answers.len() as u8];
+        response.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        response.extend_from_slice(name);
+        response.extend_from_slice(&u16::from(type_).to_be_bytes());
+        response.extend_from_slice(&[0x00, 0x01]);
+        for (type_, ttl, data) in answers {
+            response.extend_from_slice(&[0xc0, 0x0c]);
+            response.extend_from_slice(&u16::from(*type_).to_be_bytes());
+            response.extend_from_slice(&[0x00, 0x01]);
+            response.extend_from_slice(&ttl.to_be_bytes());
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(data);
+        }
+        response
+    }
+
+    fn dns_name(name: &[u8]) -> DnsName {
+        DnsName(Vec::from_slice(name).unwrap())
+    }
+
+    #[test]
+    fn test_srv_records() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        const SRV_NAME: &[u8] = b"\x05_http\x04_tcp\x07example\x03com\x00";
+        let handle = socket
+            .start_query(&mut cx, "_http._tcp.example.com", Type::Srv)
+            .unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        let response = response(
+            SRV_NAME,
+            Type::Srv,
+            &[
+                // The target is compressed, pointing to "example.com" in the question.
+                (Type::Srv, 300, b"\x00\x0a\x00\x05\x1f\x90\x03www\xc0\x17"),
+                (Type::Srv, 60, b"\x00\x14\x00\x00\x1f\x90\x03web\xc0\x17"),
+                (Type::A, 60, b"\x5d\xb8\xd8\x22"),
+            ],
+        );
+        recv_response(&mut socket, &mut cx, query, &response);
+
+        let records = socket.get_query_records(handle).unwrap();
+        assert_eq!(
+            records,
+            [
+                DnsRecord {
+                    ttl: 300,
+                    data: DnsRecordData::Srv {
+                        priority: 10,
+                        weight: 5,
+                        port: 8080,
+                        target: dns_name(b"\x03www\x07example\x03com\x00"),
+                    },
+                },
+                DnsRecord {
+                    ttl: 60,
+                    data: DnsRecordData::Srv {
+                        priority: 20,
+                        weight: 0,
+                        port: 8080,
+                        target: dns_name(b"\x03web\x07example\x03com\x00"),
+                    },
+                },
+            ]
+        );
+
+        // From the cache, the records are only valid for the time left.
+        cx.set_now(Instant::from_secs(20));
+        let handle = socket
+            .start_query(&mut cx, "_http._tcp.example.com", Type::Srv)
+            .unwrap();
+        assert_eq!(send_query(&mut socket, &mut cx), None);
+        let records = socket.get_query_records(handle).unwrap();
+        assert_eq!(records[0].ttl, 40);
+        assert_eq!(records[1].ttl, 40);
+
+        // The records have no addresses.
+        let handle = socket
+            .start_query(&mut cx, "_http._tcp.example.com", Type::Srv)
+            .unwrap();
+        assert_eq!(socket.get_query_result(handle), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_txt_record() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        let handle = socket
+            .start_query(&mut cx, "example.com", Type::Txt)
+            .unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        let response = response(NAME, Type::Txt, &[(Type::Txt, 60, b"\x05a=b c\x00\x03d=e")]);
+        recv_response(&mut socket, &mut cx, query, &response);
+
+        let records = socket.get_query_records(handle).unwrap();
+        assert_eq!(records[0].data.type_(), Type::Txt);
+        let strings: StdVec<&[u8]> = records[0].data.txt_strings().collect();
+        assert_eq!(strings, [&b"a=b c"[..], &b""[..], &b"d=e"[..]]);
+    }
+
+    #[test]
+    fn test_txt_strings_malformed() {
+        let data = DnsRecordData::Txt(Vec::from_slice(b"\x03a=b\x05c=d").unwrap());
+        let strings: StdVec<&[u8]> = data.txt_strings().collect();
+        assert_eq!(strings, [&b"a=b"[..]]);
+    }
+
+    #[test]
+    fn test_reverse_query() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        const PTR_NAME: &[u8] = b"\x0234\x03216\x03184\x0293\x07in-addr\x04arpa\x00";
+        let handle = socket
+            .start_reverse_query(&mut cx, Ipv4Address([93, 184, 216, 34]).into())
+            .unwrap();
+        let mut sent = None;
+        socket
+            .dispatch(&mut cx, |_, (_, udp_repr, payload)| {
+                let packet = Packet::new_checked(payload).unwrap();
+                let (_, question) = Question::parse(packet.payload()).unwrap();
+                assert_eq!(question.name, PTR_NAME);
+                assert_eq!(question.type_, Type::Ptr);
+                sent = Some((packet.transaction_id(), udp_repr.src_port));
+                Ok::<(), ()>(())
+            })
+            .unwrap();
+        let response = response(PTR_NAME, Type::Ptr, &[(Type::Ptr, 60, NAME)]);
+        recv_response(&mut socket, &mut cx, sent.unwrap(), &response);
+
+        let records = socket.get_query_records(handle).unwrap();
+        assert_eq!(records[0].data, DnsRecordData::Ptr(dns_name(NAME)));
+        match &records[0].data {
+            DnsRecordData::Ptr(name) => assert_eq!(name.to_string(), "example.com"),
+            data => panic!("unexpected {:?}", data),
+        }
+    }
+
+    #[test]
+    fn test_cname_records() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        // A CNAME record is followed, unless it's what's queried for.
+        let answers: &[(Type, u32, &[u8])] = &[
+            (Type::Cname, 60, b"\x03www\xc0\x0c"),
+            (Type::Mx, 60, b"\x00\x0a\x04mail\xc0\x0c"),
+        ];
+        let handle = socket
+            .start_query(&mut cx, "example.com", Type::Mx)
+            .unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        recv_response(
+            &mut socket,
+            &mut cx,
+            query,
+            &response(NAME, Type::Mx, answers),
+        );
+        assert_eq!(
+            socket.get_query_result(handle),
+            Err(GetQueryResultError::Failed)
+        );
+
+        let handle = socket
+            .start_query(&mut cx, "example.com", Type::Cname)
+            .unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        recv_response(
+            &mut socket,
+            &mut cx,
+            query,
+            &response(NAME, Type::Cname, answers),
+        );
+        let records = socket.get_query_records(handle).unwrap();
+        assert_eq!(
+            records,
+            [DnsRecord {
+                ttl: 60,
+                data: DnsRecordData::Cname(dns_name(b"\x03www\x07example\x03com\x00")),
+            }]
+        );
+    }
+
+    #[cfg(feature = "socket-tcp")]
+    fn tcp_socket() -> tcp::Socket<'static> {
+        tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0; 1024]),
+            tcp::SocketBuffer::new(vec![0; 1024]),
+        )
+    }
+
+    /// Send the pending TCP segment, and return it with its payload.
+    #[cfg(feature = "socket-tcp")]
+    fn send_tcp(
+        socket: &mut Socket,
+        tcp: &mut tcp::Socket,
+        cx: &mut Context,
+    ) -> Option<(TcpRepr<'static>, StdVec<u8>)> {
+        socket.poll_tcp(cx, tcp);
+        let mut sent = None;
+        tcp.dispatch(cx, |_, (ip_repr, tcp_repr)| {
+            assert_eq!(ip_repr.dst_addr(), SERVER_ADDR.into());
+            let payload = tcp_repr.payload.to_vec();
+            let tcp_repr = TcpRepr {
+                payload: &[],
+                fast_open_cookie: None,
+                ..tcp_repr
+            };
+            sent = Some((tcp_repr, payload));
+            Ok::<(), ()>(())
+        })
+        .unwrap();
+        sent
+    }
+
+    #[cfg(feature = "socket-tcp")]
+    fn recv_tcp(socket: &mut Socket, tcp: &mut tcp::Socket, cx: &mut Context, tcp_repr: &TcpRepr) {
+        let ip_repr = IpRepr::new(
+            SERVER_ADDR.into(),
+            Ipv4Address([192, 168, 1, 1]).into(),
+            IpProtocol::Tcp,
+            tcp_repr.buffer_len(),
+            64,
+        );
+        assert!(tcp.accepts(cx, &ip_repr, tcp_repr));
+        tcp.process(cx, &ip_repr, tcp_repr);
+        socket.poll_tcp(cx, tcp);
+    }
+
+    #[test]
+    #[cfg(feature = "socket-tcp")]
+    fn test_truncated_retry_over_tcp() {
+        use crate::wire::{TcpControl, TcpSeqNumber};
+
+        let mut socket = socket();
+        let mut tcp = tcp_socket();
+        socket.set_tcp_socket(Some(SocketHandle::default()));
+        let mut cx = Context::mock();
+
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        let (txid, port) = send_query(&mut socket, &mut cx).unwrap();
+        let mut truncated = vec![0x83, 0x80, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        truncated.extend_from_slice(NAME);
+        truncated.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+        recv_response(&mut socket, &mut cx, (txid, port), &truncated);
+        assert_eq!(
+            socket.get_query_result(handle),
+            Err(GetQueryResultError::Pending)
+        );
+
+        // The query isn't sent over UDP again, but over TCP.
+        assert_eq!(send_query(&mut socket, &mut cx), None);
+        let (syn, _) = send_tcp(&mut socket, &mut tcp, &mut cx).unwrap();
+        assert_eq!(syn.control, TcpControl::Syn);
+        assert_eq!(syn.dst_port, DNS_PORT);
+
+        let syn_ack = TcpRepr {
+            src_port: DNS_PORT,
+            dst_port: syn.src_port,
+            control: TcpControl::Syn,
+            seq_number: TcpSeqNumber(5000),
+            ack_number: Some(syn.seq_number + 1),
+            window_len: 1024,
+            window_scale: None,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges: [None, None, None],
+            timestamp: None,
+            fast_open_cookie: None,
+            payload: &[],
+        };
+        recv_tcp(&mut socket, &mut tcp, &mut cx, &syn_ack);
+
+        // The query is prefixed with its length.
+        let (segment, query) = send_tcp(&mut socket, &mut tcp, &mut cx).unwrap();
+        assert_eq!(query.len(), 2 + 12 + NAME.len() + 4);
+        assert_eq!(&query[..2], &((query.len() - 2) as u16).to_be_bytes());
+        let packet = Packet::new_checked(&query[2..]).unwrap();
+        assert_eq!(packet.transaction_id(), txid);
+
+        let mut response = StdVec::new();
+        let answer = answer(60);
+        response.extend_from_slice(&((2 + answer.len()) as u16).to_be_bytes());
+        response.extend_from_slice(&txid.to_be_bytes());
+        response.extend_from_slice(&answer);
+        // Only a whole response is processed.
+        for (offset, chunk) in [(0, &response[..10]), (10, &response[10..])] {
+            let data = TcpRepr {
+                control: TcpControl::None,
+                seq_number: TcpSeqNumber(5001 + offset),
+                ack_number: Some(segment.seq_number + query.len()),
+                payload: chunk,
+                ..syn_ack
+            };
+            recv_tcp(&mut socket, &mut tcp, &mut cx, &data);
+            if offset == 0 {
+                assert_eq!(
+                    socket.get_query_result(handle),
+                    Err(GetQueryResultError::Pending)
+                );
+            }
+        }
+        assert_eq!(
+            socket.get_query_result(handle),
+            Ok(Vec::from_slice(&[Ipv4Address([93, 184, 216, 34]).into()]).unwrap())
+        );
+
+        // The connection is closed.
+        let (fin, _) = send_tcp(&mut socket, &mut tcp, &mut cx).unwrap();
+        assert_eq!(fin.control, TcpControl::Fin);
+    }
+
+    #[test]
+    #[cfg(feature = "socket-tcp")]
+    fn test_truncated_retry_over_tcp_refused() {
+        use crate::wire::{TcpControl, TcpSeqNumber};
+
+        let mut socket = socket();
+        let mut tcp = tcp_socket();
+        socket.set_tcp_socket(Some(SocketHandle::default()));
+        let mut cx = Context::mock();
+
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        let mut truncated = answer(60);
+        truncated[0] |= 0x02;
+        recv_response(&mut socket, &mut cx, query, &truncated);
+
+        let (syn, _) = send_tcp(&mut socket, &mut tcp, &mut cx).unwrap();
+        let rst = TcpRepr {
+            src_port: DNS_PORT,
+            dst_port: syn.src_port,
+            control: TcpControl::Rst,
+            seq_number: TcpSeqNumber(0),
+            ack_number: Some(syn.seq_number + 1),
+            window_len: 0,
+            window_scale: None,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges: [None, None, None],
+            timestamp: None,
+            fast_open_cookie: None,
+            payload: &[],
+        };
+        recv_tcp(&mut socket, &mut tcp, &mut cx, &rst);
+        assert!(send_tcp(&mut socket, &mut tcp, &mut cx).is_none());
+        assert_eq!(
+            socket.get_query_result(handle),
+            Err(GetQueryResultError::Failed)
+        );
+    }
+
+    #[test]
+    fn test_truncated_without_tcp() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        // The records of the truncated response are used.
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        let mut truncated = answer(60);
+        truncated[0] |= 0x02;
+        recv_response(&mut socket, &mut cx, query, &truncated);
+        assert_eq!(
+            socket.get_query_result(handle),
+            Ok(Vec::from_slice(&[Ipv4Address([93, 184, 216, 34]).into()]).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_cache_answer() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        recv_response(&mut socket, &mut cx, query, &answer(60));
+        let addresses = socket.get_query_result(handle).unwrap();
+        assert_eq!(addresses, [Ipv4Address([93, 184, 216, 34]).into()]);
+
+        // The answer is cached for its TTL, whatever the case of the name.
+        cx.set_now(Instant::from_secs(59));
+        let handle = socket
+            .start_query(&mut cx, "Example.COM.", Type::A)
+            .unwrap();
+        assert_eq!(send_query(&mut socket, &mut cx), None);
+        assert_eq!(socket.get_query_result(handle), Ok(addresses));
+
+        // Not for other types.
+        let handle = socket
+            .start_query(&mut cx, "example.com", Type::Aaaa)
+            .unwrap();
+        assert_eq!(
+            socket.get_query_result(handle),
+            Err(GetQueryResultError::Pending)
+        );
+        socket.cancel_query(handle);
+
+        cx.set_now(Instant::from_secs(60));
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        assert!(send_query(&mut socket, &mut cx).is_some());
+        socket.cancel_query(handle);
+    }
+
+    #[test]
+    fn test_cache_nxdomain() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        recv_response(&mut socket, &mut cx, query, &nxdomain(300, 30));
+        assert_eq!(
+            socket.get_query_result(handle),
+            Err(GetQueryResultError::Failed)
+        );
+
+        // The negative answer is cached for the SOA minimum.
+        cx.set_now(Instant::from_secs(29));
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        assert_eq!(send_query(&mut socket, &mut cx), None);
+        assert_eq!(
+            socket.get_query_result(handle),
+            Err(GetQueryResultError::Failed)
+        );
+
+        cx.set_now(Instant::from_secs(30));
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        assert!(send_query(&mut socket, &mut cx).is_some());
+        socket.cancel_query(handle);
+    }
+
+    #[test]
+    fn test_cache_flush() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        let query = send_query(&mut socket, &mut cx).unwrap();
+        recv_response(&mut socket, &mut cx, query, &answer(60));
+        socket.get_query_result(handle).unwrap();
+
+        socket.update_servers(&[SERVER_ADDR.into()]);
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        assert!(send_query(&mut socket, &mut cx).is_some());
+        socket.cancel_query(handle);
+    }
+
+    #[test]
+    fn test_cache_borrowed() {
+        let mut cache: [Option<DnsCacheEntry>; 1] = Default::default();
+        let mut socket = Socket::new(&[SERVER_ADDR.into()], vec![]);
+        socket.set_cache(&mut cache[..]);
+        let mut cx = Context::mock();
+
+        for ttl in [60, 30] {
+            let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+            let query = send_query(&mut socket, &mut cx).unwrap();
+            recv_response(&mut socket, &mut cx, query, &answer(ttl));
+            socket.get_query_result(handle).unwrap();
+            cx.set_now(Instant::from_secs(61));
+        }
+
+        // The second answer replaced the expired one in the only slot.
+        cx.set_now(Instant::from_secs(90));
+        let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+        assert_eq!(send_query(&mut socket, &mut cx), None);
+        socket.get_query_result(handle).unwrap();
+    }
+
+    #[test]
+    fn test_cache_owned_bounded() {
+        let mut cache = ManagedSlice::Owned(vec![]);
+        for i in 0..MAX_CACHE_ENTRY_COUNT + 1 {
+            let name = Vec::from_slice(i.to_string().as_bytes()).unwrap();
+            cache_insert(
+                &mut cache,
+                name,
+                Type::A,
+                Vec::new(),
+                60 + i as u32,
+                Instant::ZERO,
+            );
+        }
+
+        // The last answer replaced the one closest to expiring.
+        assert_eq!(cache.len(), MAX_CACHE_ENTRY_COUNT);
+        assert!(cache.iter().flatten().all(|entry| &entry.name[..] != b"0"));
+    }
+
+    #[test]
+    fn test_cache_truncated() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        // The records that fit are used, but not cached.
+        let mut response = answer(60);
+        response[0] |= 0x02;
+        for _ in 0..2 {
+            let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+            let query = send_query(&mut socket, &mut cx).unwrap();
+            recv_response(&mut socket, &mut cx, query, &response);
+            socket.get_query_result(handle).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cache_server_failure() {
+        let mut socket = socket();
+        let mut cx = Context::mock();
+
+        // Unlike NXDOMAIN, server failures aren't cached.
+        let mut response = nxdomain(300, 30);
+        response[1] = 0x82;
+        for _ in 0..2 {
+            let handle = socket.start_query(&mut cx, "example.com", Type::A).unwrap();
+            let query = send_query(&mut socket, &mut cx).unwrap();
+            recv_response(&mut socket, &mut cx, query, &response);
+            assert_eq!(
+                socket.get_query_result(handle),
+                Err(GetQueryResultError::Failed)
+            );
+        }
+    }
+}
@@ -0,0 +1,1453 @@
+// An mDNS responder, answering queries for a host name and for the DNS-SD services
+// advertised on it, as described in RFC 6762 and RFC 6763. The responder probes for
+// its names and announces its records when it starts; afterwards it answers queries
+// by multicast on the address family they came on, or by unicast to resolvers that
+// aren't mDNS-aware (RFC 6762 § 6.7). When another host probes for the same names at
+// the same time, the tie is broken as described in RFC 6762 § 8.2. The responder doesn't
+// pick new names on a conflict, that's left to the application, and known-answer
+// suppression isn't implemented.
+
+use core::cmp::Ordering;
+use core::{iter, mem, slice};
+
+use heapless::Vec;
+
+#[cfg(feature = "proto-ipv4")]
+use super::MDNS_IPV4_ADDR;
+#[cfg(feature = "proto-ipv6")]
+use super::MDNS_IPV6_ADDR;
+use super::{copy_name, eq_names, MAX_NAME_LEN, MDNS_DNS_PORT};
+use crate::socket::{Context, PollAt};
+use crate::time::{Duration, Instant};
+use crate::wire::dns::{
+    parse_name, Flags, Opcode, Packet, Question, Rcode, Record, RecordData, Repr, Section, Type,
+};
+use crate::wire::{self, IpAddress, IpEndpoint, IpProtocol, IpRepr, UdpRepr};
+
+pub const MAX_SERVICE_COUNT: usize = 4;
+
+const MAX_LABEL_LEN: usize = 63;
+const MAX_TXT_STRING_LEN: usize = 255;
+const MAX_PACKET_LEN: usize = 1024;
+
+const PROBE_COUNT: u8 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+const ANNOUNCE_COUNT: u8 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(1_000);
+// How long to wait before probing again after losing a tie-break, see RFC 6762 § 8.2.
+const TIEBREAK_DELAY: Duration = Duration::from_millis(1_000);
+
+// The most address records sent in a message, and the most records of any kind.
+const MAX_ADDRESS_COUNT: usize = 8;
+const MAX_RECORD_COUNT: usize = MAX_ADDRESS_COUNT + 4 * MAX_SERVICE_COUNT;
+
+// TTL of the records with a host name in their name or data, and of the others,
+// see RFC 6762 § 10.
+const HOST_RECORD_TTL: u32 = 120;
+const OTHER_RECORD_TTL: u32 = 4500;
+// Maximum TTL of the records in answers to legacy unicast queries.
+const LEGACY_RECORD_TTL: u32 = 10;
+// All mDNS packets are sent with the maximum hop limit, see RFC 6762 § 11.
+const HOP_LIMIT: u8 = 255;
+
+const LOCAL: &[u8] = b"local";
+// The name listing the service types advertised on the link, see RFC 6763 § 9.
+const SERVICES_NAME: [&[u8]; 3] = [b"_services", b"_dns-sd", b"_udp"];
+
+// The mDNS groups, by address family.
+const GROUPS: &[IpAddress] = &[
+    #[cfg(feature = "proto-ipv4")]
+    MDNS_IPV4_ADDR,
+    #[cfg(feature = "proto-ipv6")]
+    MDNS_IPV6_ADDR,
+];
+const ALL_GROUPS: u8 = (1 << GROUPS.len()) - 1;
+
+/// Error returned by [`Responder::new`], [`Responder::set_hostname`] and
+/// [`Responder::add_service`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResponderError {
+    InvalidName,
+    NameTooLong,
+    InvalidTxt,
+    TooManyServices,
+}
+
+/// The state of a [`Responder`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResponderState {
+    /// Checking that no other host uses the host name or the service instance names.
+    Probing,
+    /// The names are ours, and the records are being announced.
+    Announcing,
+    /// Answering queries.
+    Running,
+    /// Another host uses one of the names. The responder doesn't pick other names by
+    /// itself: it stays silent until it's given some, with [`Responder::set_hostname`]
+    /// or by adding the services again.
+    Conflict,
+}
+
+/// A DNS-SD service advertised by a [`Responder`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Service<'a> {
+    /// Name of the service instance, a single label such as `"Living room printer"`.
+    pub instance: &'a str,
+    /// Type of the service and its transport protocol, such as `"_http._tcp"`.
+    pub service: &'a str,
+    /// Port the service listens on.
+    pub port: u16,
+    /// Strings of the TXT record, usually key/value pairs such as `"path=/"`.
+    pub txt: &'a [&'a str],
+}
+
+/// A set of the records of a responder.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+struct Records(u32);
+
+impl Records {
+    const A: Records = Records(1 << 0);
+    const AAAA: Records = Records(1 << 1);
+
+    /// The PTR record listing the type of the service at `index`.
+    const fn service_type(index: usize) -> Records {
+        Records(1 << (2 + 4 * index))
+    }
+
+    /// The PTR record pointing from the type of the service at `index` to its instance.
+    const fn ptr(index: usize) -> Records {
+        Records(1 << (3 + 4 * index))
+    }
+
+    const fn srv(index: usize) -> Records {
+        Records(1 << (4 + 4 * index))
+    }
+
+    const fn txt(index: usize) -> Records {
+        Records(1 << (5 + 4 * index))
+    }
+
+    const fn union(self, other: Records) -> Records {
+        Records(self.0 | other.0)
+    }
+
+    const fn difference(self, other: Records) -> Records {
+        Records(self.0 & !other.0)
+    }
+
+    const fn contains(self, other: Records) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// The records to send in a response.
+#[derive(Debug, Default, Clone, Copy)]
+struct Response {
+    answers: Records,
+    additionals: Records,
+}
+
+impl Response {
+    fn merge(&mut self, other: Response) {
+        self.answers = self.answers.union(other.answers);
+        self.additionals = self.additionals.union(other.additionals);
+    }
+}
+
+/// A response to a legacy unicast query, which repeats the question like a unicast
+/// DNS server would.
+#[derive(Debug)]
+struct LegacyResponse {
+    endpoint: IpEndpoint,
+    txid: u16,
+    name: Vec<u8, MAX_NAME_LEN>,
+    type_: Type,
+    response: Response,
+}
+
+/// An mDNS responder socket.
+///
+/// The responder answers A and AAAA queries for `<hostname>.local` with the addresses
+/// of the interface, and advertises the [`Service`]s added to it with PTR, SRV and TXT
+/// records. It only receives queries if the interface is a member of the mDNS groups,
+/// [`MDNS_IPV4_ADDR`] and [`MDNS_IPV6_ADDR`], see [`Interface::join_multicast_group`].
+///
+/// [`MDNS_IPV4_ADDR`]: super::MDNS_IPV4_ADDR
+/// [`MDNS_IPV6_ADDR`]: super::MDNS_IPV6_ADDR
+/// [`Interface::join_multicast_group`]: crate::iface::Interface::join_multicast_group
+#[derive(Debug)]
+pub struct Responder<'a> {
+    hostname: Vec<u8, MAX_LABEL_LEN>,
+    // Not a `heapless::Vec`: its destructor would need the strings to outlive the socket set.
+    services: [Option<Service<'a>>; MAX_SERVICE_COUNT],
+    state: ResponderState,
+
+    /// Number of probes or announcements sent so far.
+    sent: u8,
+    /// When to send the next probe or announcement, once the initial delay is picked.
+    next_at: Option<Instant>,
+    /// The groups the next probe or announcement hasn't been sent to yet.
+    pending_groups: u8,
+
+    /// Pending responses, by group.
+    responses: [Response; GROUPS.len()],
+    legacy_response: Option<LegacyResponse>,
+}
+
+impl<'a> Responder<'a> {
+    /// Create an mDNS responder for `<hostname>.local`, with `hostname` a single label
+    /// such as `"smoltcp"`.
+    pub fn new(hostname: &str) -> Result<Responder<'a>, ResponderError> {
+        let mut responder = Responder {
+            hostname: Vec::new(),
+            services: [None; MAX_SERVICE_COUNT],
+            state: ResponderState::Probing,
+            sent: 0,
+            next_at: None,
+            pending_groups: ALL_GROUPS,
+            responses: Default::default(),
+            legacy_response: None,
+        };
+        responder.set_hostname(hostname)?;
+        Ok(responder)
+    }
+
+    /// Set the host name, and probe for it again.
+    pub fn set_hostname(&mut self, hostname: &str) -> Result<(), ResponderError> {
+        let hostname = hostname.as_bytes();
+        if hostname.contains(&b'.') {
+            return Err(ResponderError::InvalidName);
+        }
+        check_label(hostname)?;
+
+        self.hostname = Vec::from_slice(hostname).map_err(|_| ResponderError::InvalidName)?;
+        self.restart();
+        Ok(())
+    }
+
+    /// Advertise a service, and probe for its instance name.
+    pub fn add_service(&mut self, service: Service<'a>) -> Result<(), ResponderError> {
+        let index = self
+            .services
+            .iter()
+            .position(Option::is_none)
+            .ok_or(ResponderError::TooManyServices)?;
+
+        let mut len = 0;
+        for label in instance_name(&service) {
+            check_label(label)?;
+            len += 1 + label.len();
+        }
+        if len + 1 > MAX_NAME_LEN {
+            return Err(ResponderError::NameTooLong);
+        }
+        if service.txt.iter().any(|s| s.len() > MAX_TXT_STRING_LEN) {
+            return Err(ResponderError::InvalidTxt);
+        }
+
+        self.services[index] = Some(service);
+        self.restart();
+        Ok(())
+    }
+
+    /// Stop advertising the services.
+    pub fn clear_services(&mut self) {
+        self.services = [None; MAX_SERVICE_COUNT];
+        self.clear_responses();
+    }
+
+    /// Return the state of the responder.
+    pub fn state(&self) -> ResponderState {
+        self.state
+    }
+
+    fn restart(&mut self) {
+        self.state = ResponderState::Probing;
+        self.sent = 0;
+        self.next_at = None;
+        self.pending_groups = ALL_GROUPS;
+        self.clear_responses();
+    }
+
+    /// Count a probe or announcement sent to every group, and schedule the next one.
+    fn advance(&mut self, now: Instant) {
+        self.sent += 1;
+        self.pending_groups = ALL_GROUPS;
+
+        let delay = match self.state {
+            ResponderState::Probing if self.sent == PROBE_COUNT => {
+                net_debug!("mdns: probing done, announcing records");
+                self.state = ResponderState::Announcing;
+                self.sent = 0;
+                PROBE_INTERVAL
+            }
+            ResponderState::Probing => PROBE_INTERVAL,
+            ResponderState::Announcing if self.sent == ANNOUNCE_COUNT => {
+                self.state = ResponderState::Running;
+                ANNOUNCE_INTERVAL
+            }
+            _ => ANNOUNCE_INTERVAL,
+        };
+        self.next_at = Some(now + delay);
+    }
+
+    fn host_name(&self) -> impl Iterator<Item = &[u8]> + Clone {
+        iter::once(&self.hostname[..]).chain(iter::once(LOCAL))
+    }
+
+    /// Return the records whose names must be unique on the link.
+    fn unique_records(&self) -> Records {
+        (0..self.services().count()).fold(Records::A.union(Records::AAAA), |records, i| {
+            records.union(Records::srv(i)).union(Records::txt(i))
+        })
+    }
+
+    /// Return all the records, listing every service type once.
+    fn all_records(&self) -> Records {
+        (0..self.services().count()).fold(self.unique_records(), |records, i| {
+            let records = records.union(Records::ptr(i));
+            if self.is_first_of_type(i) {
+                records.union(Records::service_type(i))
+            } else {
+                records
+            }
+        })
+    }
+
+    /// Return the services, in the order they were added.
+    fn services(&self) -> impl Iterator<Item = &Service<'a>> {
+        self.services.iter().flatten()
+    }
+
+    fn is_first_of_type(&self, index: usize) -> bool {
+        match &self.services[index] {
+            Some(service) => !self.services[..index]
+                .iter()
+                .flatten()
+                .any(|s| s.service.eq_ignore_ascii_case(service.service)),
+            None => false,
+        }
+    }
+
+    /// Return the records answering a question for `name` and `type_`.
+    fn answer(&self, p: &Packet<&[u8]>, name: &[u8], type_: Type) -> wire::Result<Response> {
+        let mut response = Response::default();
+        let is = |t: Type| type_ == t || type_ == Type::Any;
+
+        if eq_names(p.parse_name(name), self.host_name().map(Ok))? {
+            if is(Type::A) {
+                response.merge(Response {
+                    answers: Records::A,
+                    additionals: Records::AAAA,
+                });
+            }
+            if is(Type::Aaaa) {
+                response.merge(Response {
+                    answers: Records::AAAA,
+                    additionals: Records::A,
+                });
+            }
+        }
+
+        let addresses = Records::A.union(Records::AAAA);
+        let is_services = is(Type::Ptr) && eq_names(p.parse_name(name), services_name().map(Ok))?;
+        for (i, service) in self.services().enumerate() {
+            if is_services && self.is_first_of_type(i) {
+                response.answers = response.answers.union(Records::service_type(i));
+            }
+            if is(Type::Ptr) && eq_names(p.parse_name(name), service_type_name(service).map(Ok))? {
+                // Save the querier from asking how to reach the instance, RFC 6763 § 12.1.
+                response.merge(Response {
+                    answers: Records::ptr(i),
+                    additionals: Records::srv(i).union(Records::txt(i)).union(addresses),
+                });
+            }
+            if eq_names(p.parse_name(name), instance_name(service).map(Ok))? {
+                if is(Type::Srv) {
+                    response.merge(Response {
+                        answers: Records::srv(i),
+                        additionals: addresses,
+                    });
+                }
+                if is(Type::Txt) {
+                    response.answers = response.answers.union(Records::txt(i));
+                }
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Return whether a record of another host conflicts with ours: while probing,
+    /// any record for our names does; afterwards, addresses that aren't ours do.
+    fn conflicts(&self, cx: &Context, p: &Packet<&[u8]>, record: &Record) -> wire::Result<bool> {
+        let probing = self.state == ResponderState::Probing;
+        let is_host = || eq_names(p.parse_name(record.name), self.host_name().map(Ok));
+
+        match record.data {
+            #[cfg(feature = "proto-ipv4")]
+            RecordData::A(addr) => Ok((probing || !cx.has_ip_addr(addr)) && is_host()?),
+            #[cfg(feature = "proto-ipv6")]
+            RecordData::Aaaa(addr) => Ok((probing || !cx.has_ip_addr(addr)) && is_host()?),
+            RecordData::Srv { .. } | RecordData::Txt(_) if probing => {
+                for service in self.services() {
+                    if eq_names(p.parse_name(record.name), instance_name(service).map(Ok))? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    pub(crate) fn accepts(&self, _ip_repr: &IpRepr, udp_repr: &UdpRepr) -> bool {
+        udp_repr.dst_port == MDNS_DNS_PORT
+    }
+
+    pub(crate) fn process(
+        &mut self,
+        cx: &mut Context,
+        ip_repr: &IpRepr,
+        udp_repr: &UdpRepr,
+        payload: &[u8],
+    ) {
+        debug_assert!(self.accepts(ip_repr, udp_repr));
+
+        // Our own multicast packets may be looped back to us.
+        if cx.has_ip_addr(ip_repr.src_addr()) {
+            return;
+        }
+
+        let p = match Packet::new_checked(payload) {
+            Ok(x) => x,
+            Err(_) => {
+                net_trace!("mdns packet malformed");
+                return;
+            }
+        };
+        if p.opcode() != Opcode::Query {
+            net_trace!("unwanted opcode {:?}", p.opcode());
+            return;
+        }
+        let repr = match Repr::parse(&p) {
+            Ok(x) => x,
+            Err(_) => {
+                net_trace!("mdns packet malformed");
+                return;
+            }
+        };
+
+        let result = if repr.flags.contains(Flags::RESPONSE) {
+            self.process_response(cx, &p, &repr)
+        } else if self.state == ResponderState::Probing {
+            self.process_probe(cx, payload, &repr)
+        } else {
+            self.process_query(ip_repr, udp_repr, &p, &repr)
+        };
+        if result.is_err() {
+            net_trace!("mdns packet malformed");
+        }
+    }
+
+    fn process_response(
+        &mut self,
+        cx: &Context,
+        p: &Packet<&[u8]>,
+        repr: &Repr,
+    ) -> wire::Result<()> {
+        if self.state == ResponderState::Conflict {
+            return Ok(());
+        }
+
+        let records = repr
+            .answers
+            .iter()
+            .chain(repr.authorities.iter())
+            .chain(repr.additionals.iter());
+        for record in records {
+            if self.conflicts(cx, p, &record?)? {
+                net_debug!("mdns: another host uses our names");
+                self.state = ResponderState::Conflict;
+                self.clear_responses();
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare the records another host proposes in a probe for our names with ours,
+    /// and defer to it if they're later, see RFC 6762 § 8.2.
+    fn process_probe(&mut self, cx: &Context, payload: &[u8], repr: &Repr) -> wire::Result<()> {
+        let mut arena = [0u8; MAX_PACKET_LEN];
+        let names = self.names(&mut arena);
+        let records = self.records(cx, &names, self.unique_records(), false, u32::MAX);
+
+        let instances = names.instances.iter().filter(|name| !name.is_empty());
+        for &name in iter::once(&names.host).chain(instances) {
+            let mut theirs = Vec::<Record, MAX_RECORD_COUNT>::new();
+            for record in repr.authorities.iter() {
+                let record = record?;
+                if eq_names(parse_name(payload, record.name), parse_name(&[], name))?
+                    && theirs.push(record).is_err()
+                {
+                    break;
+                }
+            }
+            if theirs.is_empty() {
+                continue;
+            }
+
+            let mut ours: Vec<Record, MAX_RECORD_COUNT> =
+                records.iter().filter(|r| r.name == name).copied().collect();
+            ours.sort_unstable_by(|a, b| cmp_records((&[], a), (&[], b)));
+            theirs.sort_unstable_by(|a, b| cmp_records((payload, a), (payload, b)));
+
+            if cmp_record_lists((&[], &ours), (payload, &theirs)) == Ordering::Less {
+                net_debug!("mdns: another host probes for our names, deferring to it");
+                self.sent = 0;
+                self.next_at = Some(cx.now() + TIEBREAK_DELAY);
+                self.pending_groups = ALL_GROUPS;
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_query(
+        &mut self,
+        ip_repr: &IpRepr,
+        udp_repr: &UdpRepr,
+        p: &Packet<&[u8]>,
+        repr: &Repr,
+    ) -> wire::Result<()> {
+        // The names are only ours to answer for once we've probed for them.
+        if !matches!(
+            self.state,
+            ResponderState::Announcing | ResponderState::Running
+        ) {
+            return Ok(());
+        }
+
+        let mut response = Response::default();
+        let mut first_question = None;
+        for question in repr.questions.iter() {
+            let question = question?;
+            response.merge(self.answer(p, question.name, question.type_)?);
+            first_question = first_question.or(Some(question));
+        }
+
+        let question = match first_question {
+            Some(question) if !response.answers.is_empty() => question,
+            _ => return Ok(()),
+        };
+
+        if udp_repr.src_port == MDNS_DNS_PORT {
+            let src_addr = ip_repr.src_addr();
+            if let Some(i) = GROUPS
+                .iter()
+                .position(|g| g.version() == src_addr.version())
+            {
+                self.responses[i].merge(response);
+            }
+        } else {
+            let mut name = Vec::new();
+            copy_name(&mut name, p.parse_name(question.name))?;
+            self.legacy_response = Some(LegacyResponse {
+                endpoint: IpEndpoint::new(ip_repr.src_addr(), udp_repr.src_port),
+                txid: repr.transaction_id,
+                name,
+                type_: question.type_,
+                response,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn clear_responses(&mut self) {
+        for response in self.responses.iter_mut() {
+            *response = Response::default();
+        }
+        self.legacy_response = None;
+    }
+
+    pub(crate) fn dispatch<F, E>(&mut self, cx: &mut Context, emit: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut Context, (IpRepr, UdpRepr, &[u8])) -> Result<(), E>,
+    {
+        let mut buffer = [0u8; MAX_PACKET_LEN];
+
+        if let ResponderState::Probing | ResponderState::Announcing = self.state {
+            let next_at = match self.next_at {
+                Some(next_at) => next_at,
+                None => {
+                    // Wait up to 250 ms before the first probe, RFC 6762 § 8.1.
+                    let delay = cx.rand().rand_u16() as u64 % PROBE_INTERVAL.total_millis();
+                    let next_at = cx.now() + Duration::from_millis(delay);
+                    self.next_at = Some(next_at);
+                    next_at
+                }
+            };
+
+            if next_at <= cx.now() {
+                while let Some(i) = (0..GROUPS.len()).find(|i| self.pending_groups & 1 << i != 0) {
+                    let src_addr = match cx.get_source_address(GROUPS[i]) {
+                        Some(src_addr) => src_addr,
+                        None => {
+                            self.pending_groups &= !(1 << i);
+                            continue;
+                        }
+                    };
+
+                    let len = if self.state == ResponderState::Probing {
+                        self.emit_probe(cx, &mut buffer)
+                    } else {
+                        let announcement = Response {
+                            answers: self.all_records(),
+                            additionals: Records::default(),
+                        };
+                        self.emit_response(cx, &mut buffer, announcement, None)
+                    };
+                    if let Some(len) = len {
+                        let dst = IpEndpoint::new(GROUPS[i], MDNS_DNS_PORT);
+                        emit(cx, udp_packet(src_addr, dst, &buffer[..len]))?;
+                    }
+
+                    self.pending_groups &= !(1 << i);
+                    if self.pending_groups == 0 {
+                        self.advance(cx.now());
+                    }
+                    return Ok(());
+                }
+                self.advance(cx.now());
+            }
+        }
+
+        if let Some(legacy) = &self.legacy_response {
+            if let Some(src_addr) = cx.get_source_address(legacy.endpoint.addr) {
+                let len = self.emit_response(cx, &mut buffer, legacy.response, Some(legacy));
+                if let Some(len) = len {
+                    emit(cx, udp_packet(src_addr, legacy.endpoint, &buffer[..len]))?;
+                }
+                self.legacy_response = None;
+                return Ok(());
+            }
+            self.legacy_response = None;
+        }
+
+        for (i, &group) in GROUPS.iter().enumerate() {
+            let response = self.responses[i];
+            if response.answers.is_empty() {
+                continue;
+            }
+            if let Some(src_addr) = cx.get_source_address(group) {
+                if let Some(len) = self.emit_response(cx, &mut buffer, response, None) {
+                    let dst = IpEndpoint::new(group, MDNS_DNS_PORT);
+                    emit(cx, udp_packet(src_addr, dst, &buffer[..len]))?;
+                }
+                self.responses[i] = Response::default();
+                return Ok(());
+            }
+            self.responses[i] = Response::default();
+        }
+
+        // Nothing to dispatch
+        Ok(())
+    }
+
+    /// Emit a probe: a query for our names, with the records we'd like to use for them
+    /// in the authority section.
+    fn emit_probe(&self, cx: &Context, buffer: &mut [u8]) -> Option<usize> {
+        let mut arena = [0u8; MAX_PACKET_LEN];
+        let names = self.names(&mut arena);
+
+        // Ask for unicast responses, the other hosts don't need to see them.
+        let question = |name| Question {
+            name,
+            type_: Type::Any,
+            unicast_response: true,
+        };
+        let instances = names.instances.iter().filter(|name| !name.is_empty());
+        let questions: Vec<Question, { MAX_SERVICE_COUNT + 1 }> = iter::once(&names.host)
+            .chain(instances)
+            .map(|&name| question(name))
+            .collect();
+        let authorities = self.records(cx, &names, self.unique_records(), false, u32::MAX);
+
+        emit_message(
+            buffer,
+            0,
+            Flags::empty(),
+            &questions,
+            [&[], &authorities, &[]],
+        )
+    }
+
+    /// Emit a response, to a legacy unicast query if `legacy` is set.
+    fn emit_response(
+        &self,
+        cx: &Context,
+        buffer: &mut [u8],
+        response: Response,
+        legacy: Option<&LegacyResponse>,
+    ) -> Option<usize> {
+        let mut arena = [0u8; MAX_PACKET_LEN];
+        let names = self.names(&mut arena);
+
+        let (txid, question, cache_flush, max_ttl) = match legacy {
+            Some(legacy) => {
+                let question = Question {
+                    name: &legacy.name,
+                    type_: legacy.type_,
+                    unicast_response: false,
+                };
+                (legacy.txid, Some(question), false, LEGACY_RECORD_TTL)
+            }
+            None => (0, None, true, u32::MAX),
+        };
+        let questions = match &question {
+            Some(question) => slice::from_ref(question),
+            None => &[],
+        };
+
+        let answers = response.answers;
+        let additionals = response.additionals.difference(answers);
+        let answers = self.records(cx, &names, answers, cache_flush, max_ttl);
+        let additionals = self.records(cx, &names, additionals, cache_flush, max_ttl);
+
+        emit_message(
+            buffer,
+            txid,
+            Flags::RESPONSE | Flags::AUTHORITATIVE,
+            questions,
+            [&answers, &[], &additionals],
+        )
+    }
+
+    /// Write the names and TXT data of our records into `arena`.
+    fn names<'s>(&self, mut arena: &'s mut [u8]) -> Names<'s> {
+        let mut names = Names {
+            host: encode_name(&mut arena, self.host_name()).unwrap_or_default(),
+            services: encode_name(&mut arena, services_name()).unwrap_or_default(),
+            instances: [&[]; MAX_SERVICE_COUNT],
+            txts: [&[]; MAX_SERVICE_COUNT],
+        };
+        for (i, service) in self.services().enumerate() {
+            names.instances[i] =
+                encode_name(&mut arena, instance_name(service)).unwrap_or_default();
+            names.txts[i] = encode_txt(&mut arena, service.txt).unwrap_or_default();
+        }
+        names
+    }
+
+    /// Return the `records`, leaving out those whose names didn't fit in the arena.
+    fn records<'s>(
+        &self,
+        cx: &Context,
+        names: &Names<'s>,
+        records: Records,
+        cache_flush: bool,
+        max_ttl: u32,
+    ) -> Vec<Record<'s>, MAX_RECORD_COUNT> {
+        let mut result = Vec::new();
+        let host_ttl = HOST_RECORD_TTL.min(max_ttl);
+        let other_ttl = OTHER_RECORD_TTL.min(max_ttl);
+        let unique = |name, ttl, data| Record {
+            name,
+            ttl,
+            cache_flush,
+            data,
+        };
+        let shared = |name, data| Record {
+            name,
+            ttl: other_ttl,
+            cache_flush: false,
+            data,
+        };
+
+        for cidr in cx.ip_addrs() {
+            let data = match cidr.address() {
+                addr if !cx.is_usable_ip_addr(addr) => continue,
+                #[cfg(feature = "proto-ipv4")]
+                IpAddress::Ipv4(addr) if records.contains(Records::A) => RecordData::A(addr),
+                #[cfg(feature = "proto-ipv6")]
+                IpAddress::Ipv6(addr) if records.contains(Records::AAAA) => RecordData::Aaaa(addr),
+                _ => continue,
+            };
+            if result.len() < MAX_ADDRESS_COUNT {
+                let _ = result.push(unique(names.host, host_ttl, data));
+            }
+        }
+
+        // The capacity is enough for the addresses and all the service records.
+        for (i, service) in self.services().enumerate() {
+            let instance = names.instances[i];
+            if instance.is_empty() {
+                continue;
+            }
+            let type_name = &instance[1 + service.instance.len()..];
+
+            if records.contains(Records::service_type(i)) {
+                let _ = result.push(shared(names.services, RecordData::Ptr(type_name)));
+            }
+            if records.contains(Records::ptr(i)) {
+                let _ = result.push(shared(type_name, RecordData::Ptr(instance)));
+            }
+            if records.contains(Records::srv(i)) {
+                let data = RecordData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: service.port,
+                    target: names.host,
+                };
+                let _ = result.push(unique(instance, host_ttl, data));
+            }
+            if records.contains(Records::txt(i)) && !names.txts[i].is_empty() {
+                let data = RecordData::Txt(names.txts[i]);
+                let _ = result.push(unique(instance, other_ttl, data));
+            }
+        }
+
+        result
+    }
+
+    pub(crate) fn poll_at(&self, _cx: &Context) -> PollAt {
+        if self.legacy_response.is_some() || self.responses.iter().any(|r| !r.answers.is_empty()) {
+            return PollAt::Now;
+        }
+
+        match self.state {
+            ResponderState::Probing | ResponderState::Announcing => match self.next_at {
+                Some(next_at) => PollAt::Time(next_at),
+                None => PollAt::Now,
+            },
+            ResponderState::Running | ResponderState::Conflict => PollAt::Ingress,
+        }
+    }
+}
+
+/// The names and TXT data of the records of a responder, in wire format. Those that
+/// didn't fit in the arena they're written to are empty.
+struct Names<'s> {
+    host: &'s [u8],
+    services: &'s [u8],
+    /// The instance name of each service, of which its type name is a suffix.
+    instances: [&'s [u8]; MAX_SERVICE_COUNT],
+    txts: [&'s [u8]; MAX_SERVICE_COUNT],
+}
+
+fn check_label(label: &[u8]) -> Result<(), ResponderError> {
+    if label.is_empty() || label.len() > MAX_LABEL_LEN {
+        return Err(ResponderError::InvalidName);
+    }
+    Ok(())
+}
+
+fn services_name() -> impl Iterator<Item = &'static [u8]> + Clone {
+    SERVICES_NAME.iter().copied().chain(iter::once(LOCAL))
+}
+
+fn service_type_name<'a>(service: &Service<'a>) -> impl Iterator<Item = &'a [u8]> + Clone {
+    service
+        .service
+        .as_bytes()
+        .split(|&c| c == b'.')
+        .chain(iter::once(LOCAL))
+}
+
+fn instance_name<'a>(service: &Service<'a>) -> impl Iterator<Item = &'a [u8]> + Clone {
+    iter::once(service.instance.as_bytes()).chain(service_type_name(service))
+}
+
+/// Take the first `len` octets of `arena`.
+fn alloc<'s>(arena: &mut &'s mut [u8], len: usize) -> Option<&'s mut [u8]> {
+    if len > arena.len() {
+        return None;
+    }
+    let (head, tail) = mem::take(arena).split_at_mut(len);
+    *arena = tail;
+    Some(head)
+}
+
+/// Write an uncompressed name with the `labels` into `arena`.
+fn encode_name<'s, 'n>(
+    arena: &mut &'s mut [u8],
+    labels: impl Iterator<Item = &'n [u8]> + Clone,
+) -> Option<&'s [u8]> {
+    let len = labels.clone().map(|label| 1 + label.len()).sum::<usize>() + 1;
+    let dest = alloc(arena, len)?;
+    let mut at = 0;
+    for label in labels {
+        dest[at] = label.len() as u8;
+        dest[at + 1..at + 1 + label.len()].copy_from_slice(label);
+        at += 1 + label.len();
+    }
+    dest[at] = 0;
+    Some(dest)
+}
+
+/// Write the data of a TXT record with the `strings` into `arena`.
+fn encode_txt<'s>(arena: &mut &'s mut [u8], strings: &[&str]) -> Option<&'s [u8]> {
+    // A TXT record has at least one string, RFC 6763 § 6.1.
+    let len = strings.iter().map(|s| 1 + s.len()).sum::<usize>().max(1);
+    let dest = alloc(arena, len)?;
+    dest[0] = 0;
+    let mut at = 0;
+    for s in strings {
+        dest[at] = s.len() as u8;
+        dest[at + 1..at + 1 + s.len()].copy_from_slice(s.as_bytes());
+        at += 1 + s.len();
+    }
+    Some(dest)
+}
+
+/// Emit a message with the `questions`, and the records of `sections` in the answer,
+/// authority and additional sections, into `buffer`. The last records are left out
+/// until it fits. Returns the length of the message, if even the questions fit.
+fn emit_message(
+    buffer: &mut [u8],
+    transaction_id: u16,
+    flags: Flags,
+    questions: &[Question],
+    mut sections: [&[Record]; 3],
+) -> Option<usize> {
+    loop {
+        let repr = Repr {
+            transaction_id,
+            opcode: Opcode::Query,
+            flags,
+            rcode: Rcode::NoError,
+            questions: Section::new(questions),
+            answers: Section::new(sections[0]),
+            authorities: Section::new(sections[1]),
+            additionals: Section::new(sections[2]),
+            opt: None,
+        };
+        let len = repr.buffer_len();
+        if len <= buffer.len() {
+            repr.emit(&mut Packet::new_unchecked(&mut buffer[..len]))
+                .ok()?;
+            return Some(len);
+        }
+
+        let section = sections.iter_mut().rev().find(|s| !s.is_empty())?;
+        let records = *section;
+        *section = &records[..records.len() - 1];
+    }
+}
+
+/// Compare two records, each with the packet it's from, in the order of RFC 6762 § 8.2:
+/// by class, type, and then data with the names in it uncompressed.
+fn cmp_records(a: (&[u8], &Record), b: (&[u8], &Record)) -> Ordering {
+    // Both classes are IN, as other ones aren't parsed.
+    u16::from(a.1.data.type_())
+        .cmp(&u16::from(b.1.data.type_()))
+        .then_with(|| record_data(a.0, &a.1.data).cmp(record_data(b.0, &b.1.data)))
+}
+
+/// Compare two sorted lists of records pairwise. If one runs out of records before a
+/// difference is found, it's the earlier one.
+fn cmp_record_lists(a: (&[u8], &[Record]), b: (&[u8], &[Record])) -> Ordering {
+    a.1.iter()
+        .zip(b.1)
+        .map(|(ra, rb)| cmp_records((a.0, ra), (b.0, rb)))
+        .find(|order| order.is_ne())
+        .unwrap_or_else(|| a.1.len().cmp(&b.1.len()))
+}
+
+/// Return the octets of the data of a record from `packet`, with the names in it
+/// uncompressed.
+fn record_data<'p>(packet: &'p [u8], data: &RecordData<'p>) -> impl Iterator<Item = u8> + 'p {
+    let mut fixed = Vec::<u8, 16>::new();
+    let (name, rest): (Option<&[u8]>, &[u8]) = match *data {
+        #[cfg(feature = "proto-ipv4")]
+        RecordData::A(addr) => {
+            let _ = fixed.extend_from_slice(addr.as_bytes());
+            (None, &[])
+        }
+        #[cfg(feature = "proto-ipv6")]
+        RecordData::Aaaa(addr) => {
+            let _ = fixed.extend_from_slice(addr.as_bytes());
+            (None, &[])
+        }
+        RecordData::Cname(name) | RecordData::Ptr(name) => (Some(name), &[]),
+        RecordData::Mx {
+            preference,
+            exchange,
+        } => {
+            let _ = fixed.extend_from_slice(&preference.to_be_bytes());
+            (Some(exchange), &[])
+        }
+        RecordData::Srv {
+            priority,
+            weight,
+            port,
+            target,
+        } => {
+            for value in [priority, weight, port] {
+                let _ = fixed.extend_from_slice(&value.to_be_bytes());
+            }
+            (Some(target), &[])
+        }
+        RecordData::Txt(data) | RecordData::Other(_, data) => (None, data),
+    };
+
+    let name = name.into_iter().flat_map(move |name| {
+        parse_name(packet, name)
+            .map_while(Result::ok)
+            .flat_map(|label| iter::once(label.len() as u8).chain(label.iter().copied()))
+            .chain(iter::once(0))
+    });
+    fixed.into_iter().chain(name).chain(rest.iter().copied())
+}
+
+fn udp_packet(src_addr: IpAddress, dst: IpEndpoint, payload: &[u8]) -> (IpRepr, UdpRepr, &[u8]) {
+    let udp_repr = UdpRepr {
+        src_port: MDNS_DNS_PORT,
+        dst_port: dst.port,
+    };
+    let ip_repr = IpRepr::new(
+        src_addr,
+        dst.addr,
+        IpProtocol::Udp,
+        udp_repr.header_len() + payload.len(),
+        HOP_LIMIT,
+    );
+
+    net_trace!(
+        "sending {} octets to {}:{}",
+        payload.len(),
+        ip_repr.dst_addr(),
+        udp_repr.dst_port
+    );
+
+    (ip_repr, udp_repr, payload)
+}
+
+#[cfg(all(test, feature = "proto-ipv4"))]
+mod test {
+    use super::*;
+    use crate::wire::Ipv4Address;
+    use std::vec::Vec as StdVec;
+
+    const LOCAL_ADDR: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const PEER_ADDR: Ipv4Address = Ipv4Address([192, 168, 1, 2]);
+    const HOST_NAME: &[u8] = b"\x07smoltcp\x05local\x00";
+    const TYPE_NAME: &[u8] = b"\x05_http\x04_tcp\x05local\x00";
+    const INSTANCE_NAME: &[u8] = b"\x03Web\x05_http\x04_tcp\x05local\x00";
+
+    fn responder() -> Responder<'static> {
+        let mut responder = Responder::new("smoltcp").unwrap();
+        responder
+            .add_service(Service {
+                instance: "Web",
+                service: "_http._tcp",
+                port: 80,
+                txt: &["path=/"],
+            })
+            .unwrap();
+        responder
+    }
+
+    /// Dispatch the responder, and return the packet it sent, if any.
+    fn send(responder: &mut Responder, cx: &mut Context) -> Option<(IpRepr, UdpRepr, StdVec<u8>)> {
+        let mut sent = None;
+        responder
+            .dispatch(cx, |_, (ip_repr, udp_repr, payload)| {
+                sent = Some((ip_repr, udp_repr, payload.to_vec()));
+                Ok::<(), ()>(())
+            })
+            .unwrap();
+        sent
+    }
+
+    fn recv(responder: &mut Responder, cx: &mut Context, src_port: u16, payload: &[u8]) {
+        let ip_repr = IpRepr::new(
+            PEER_ADDR.into(),
+            MDNS_IPV4_ADDR,
+            IpProtocol::Udp,
+            8 + payload.len(),
+            HOP_LIMIT,
+        );
+        let udp_repr = UdpRepr {
+            src_port,
+            dst_port: MDNS_DNS_PORT,
+        };
+        assert!(responder.accepts(&ip_repr, &udp_repr));
+        responder.process(cx, &ip_repr, &udp_repr, payload);
+    }
+
+    /// Run the responder until it has probed for its names and announced its records.
+    fn start(responder: &mut Responder, cx: &mut Context) {
+        while responder.state() != ResponderState::Running {
+            if let PollAt::Time(at) = responder.poll_at(cx) {
+                cx.set_now(at);
+            }
+            send(responder, cx);
+        }
+    }
+
+    fn query(txid: u16, name: &[u8], type_: Type) -> StdVec<u8> {
+        let mut packet = txid.to_be_bytes().to_vec();
+        packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        packet.extend_from_slice(name);
+        packet.extend_from_slice(&u16::from(type_).to_be_bytes());
+        packet.extend_from_slice(&[0x00, 0x01]);
+        packet
+    }
+
+    fn address_response(addr: Ipv4Address) -> StdVec<u8> {
+        let mut packet = vec![
+            0x00, 0x00, 0x84, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        ];
+        packet.extend_from_slice(HOST_NAME);
+        packet.extend_from_slice(&[0x00, 0x01, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04]);
+        packet.extend_from_slice(addr.as_bytes());
+        packet
+    }
+
+    fn probe(addr: Ipv4Address) -> StdVec<u8> {
+        let mut packet = vec![
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+        ];
+        packet.extend_from_slice(HOST_NAME);
+        packet.extend_from_slice(&[0x00, 0xff, 0x80, 0x01]);
+        packet.extend_from_slice(&[0xc0, 0x0c]);
+        packet.extend_from_slice(&[0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04]);
+        packet.extend_from_slice(addr.as_bytes());
+        packet
+    }
+
+    /// Return the records of a message, in order.
+    fn records(payload: &[u8]) -> StdVec<Record<'_>> {
+        let p = Packet::new_checked(payload).unwrap();
+        let repr = Repr::parse(&p).unwrap();
+        repr.answers
+            .iter()
+            .chain(repr.authorities.iter())
+            .chain(repr.additionals.iter())
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    /// Return a name of a message, uncompressed.
+    fn uncompress(payload: &[u8], name: &[u8]) -> StdVec<u8> {
+        let mut result = StdVec::new();
+        for label in parse_name(payload, name) {
+            let label = label.unwrap();
+            result.push(label.len() as u8);
+            result.extend_from_slice(label);
+        }
+        result.push(0);
+        result
+    }
+
+    #[test]
+    fn test_probe_and_announce() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+
+        // The first probe is delayed by up to 250 ms.
+        assert_eq!(responder.poll_at(&cx), PollAt::Now);
+        assert!(send(&mut responder, &mut cx).is_none());
+        let start = match responder.poll_at(&cx) {
+            PollAt::Time(at) => at,
+            poll_at => panic!("unexpected {:?}", poll_at),
+        };
+        assert!(start < Instant::from_millis(250));
+
+        let address_count = if cfg!(feature = "proto-ipv6") { 2 } else { 1 };
+        for i in 0..PROBE_COUNT as u64 + ANNOUNCE_COUNT as u64 {
+            let at = match i {
+                0..=2 => start + PROBE_INTERVAL * i as u32,
+                _ => start + PROBE_INTERVAL * 3 + ANNOUNCE_INTERVAL * (i as u32 - 3),
+            };
+            assert_eq!(responder.poll_at(&cx), PollAt::Time(at));
+            cx.set_now(at);
+
+            for group in GROUPS {
+                let (ip_repr, udp_repr, payload) = send(&mut responder, &mut cx).unwrap();
+                assert_eq!(ip_repr.dst_addr(), *group);
+                assert_eq!(ip_repr.hop_limit(), HOP_LIMIT);
+                assert_eq!(udp_repr.src_port, MDNS_DNS_PORT);
+                assert_eq!(udp_repr.dst_port, MDNS_DNS_PORT);
+
+                let p = Packet::new_checked(&payload[..]).unwrap();
+                if i < PROBE_COUNT as u64 {
+                    // A query for the host and instance names, proposing our records.
+                    assert!(!p.flags().contains(Flags::RESPONSE));
+                    assert_eq!(p.question_count(), 2);
+                    assert_eq!(p.answer_record_count(), 0);
+                    assert_eq!(p.authority_record_count(), address_count + 2);
+                } else {
+                    // All the records: the addresses, two PTRs, SRV and TXT.
+                    assert!(p.flags().contains(Flags::RESPONSE));
+                    assert_eq!(p.question_count(), 0);
+                    assert_eq!(p.answer_record_count(), address_count + 4);
+                }
+            }
+        }
+
+        assert_eq!(responder.state(), ResponderState::Running);
+        assert_eq!(responder.poll_at(&cx), PollAt::Ingress);
+    }
+
+    #[test]
+    fn test_answer_ptr_query() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+        start(&mut responder, &mut cx);
+
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &query(0, TYPE_NAME, Type::Ptr),
+        );
+        let (ip_repr, udp_repr, payload) = send(&mut responder, &mut cx).unwrap();
+        assert_eq!(ip_repr.dst_addr(), MDNS_IPV4_ADDR);
+        assert_eq!(udp_repr.dst_port, MDNS_DNS_PORT);
+
+        // The instance is in the answer, and how to reach it in the additional section.
+        let p = Packet::new_checked(&payload[..]).unwrap();
+        assert_eq!(p.answer_record_count(), 1);
+        let records = records(&payload);
+        assert_eq!(uncompress(&payload, records[0].name), TYPE_NAME);
+        assert_eq!(records[0].ttl, OTHER_RECORD_TTL);
+        match records[0].data {
+            RecordData::Ptr(instance) => assert_eq!(uncompress(&payload, instance), INSTANCE_NAME),
+            data => panic!("unexpected {:?}", data),
+        }
+        assert_eq!(uncompress(&payload, records[1].name), HOST_NAME);
+        assert!(records[1].cache_flush);
+        assert_eq!(records[1].data, RecordData::A(LOCAL_ADDR));
+        let srv = &records[records.len() - 2];
+        assert_eq!(uncompress(&payload, srv.name), INSTANCE_NAME);
+        assert_eq!(
+            srv.data,
+            RecordData::Srv {
+                priority: 0,
+                weight: 0,
+                port: 80,
+                target: HOST_NAME
+            }
+        );
+        let txt = &records[records.len() - 1];
+        assert_eq!(txt.data, RecordData::Txt(b"\x06path=/"));
+
+        assert!(send(&mut responder, &mut cx).is_none());
+    }
+
+    #[test]
+    fn test_answer_address_query() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+        start(&mut responder, &mut cx);
+
+        // Names are case-insensitive.
+        let name = b"\x07SmolTCP\x05LOCAL\x00";
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &query(0, name, Type::A),
+        );
+        let (_, _, payload) = send(&mut responder, &mut cx).unwrap();
+        let records = records(&payload);
+        assert_eq!(uncompress(&payload, records[0].name), HOST_NAME);
+        assert_eq!(records[0].ttl, HOST_RECORD_TTL);
+        assert_eq!(records[0].data, RecordData::A(LOCAL_ADDR));
+
+        // Other names aren't ours to answer for.
+        let name = b"\x05other\x05local\x00";
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &query(0, name, Type::A),
+        );
+        assert!(send(&mut responder, &mut cx).is_none());
+    }
+
+    #[test]
+    fn test_answer_legacy_unicast_query() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+        start(&mut responder, &mut cx);
+
+        recv(
+            &mut responder,
+            &mut cx,
+            40000,
+            &query(0x1234, HOST_NAME, Type::A),
+        );
+        let (ip_repr, udp_repr, payload) = send(&mut responder, &mut cx).unwrap();
+        assert_eq!(ip_repr.src_addr(), IpAddress::Ipv4(LOCAL_ADDR));
+        assert_eq!(ip_repr.dst_addr(), IpAddress::Ipv4(PEER_ADDR));
+        assert_eq!(udp_repr.dst_port, 40000);
+
+        // Like a unicast DNS server would answer, with a short TTL.
+        let p = Packet::new_checked(&payload[..]).unwrap();
+        assert_eq!(p.transaction_id(), 0x1234);
+        assert_eq!(p.question_count(), 1);
+        assert_eq!(Question::parse(p.payload()).unwrap().1.name, HOST_NAME);
+        let records = records(&payload);
+        assert_eq!(records[0].ttl, LEGACY_RECORD_TTL);
+        assert!(!records[0].cache_flush);
+        assert_eq!(records[0].data, RecordData::A(LOCAL_ADDR));
+    }
+
+    #[test]
+    fn test_ignore_queries_while_probing() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &query(0, HOST_NAME, Type::A),
+        );
+        send(&mut responder, &mut cx);
+        cx.set_now(Instant::from_millis(250));
+        let (_, _, payload) = send(&mut responder, &mut cx).unwrap();
+        let p = Packet::new_checked(&payload[..]).unwrap();
+        assert!(!p.flags().contains(Flags::RESPONSE));
+    }
+
+    #[test]
+    fn test_probe_conflict() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+        send(&mut responder, &mut cx);
+        cx.set_now(Instant::from_millis(250));
+        assert!(send(&mut responder, &mut cx).is_some());
+
+        // Any answer for our names means another host uses them.
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &address_response(PEER_ADDR),
+        );
+        assert_eq!(responder.state(), ResponderState::Conflict);
+        cx.set_now(Instant::from_secs(10));
+        assert_eq!(responder.poll_at(&cx), PollAt::Ingress);
+        assert!(send(&mut responder, &mut cx).is_none());
+
+        responder.set_hostname("smoltcp-2").unwrap();
+        assert_eq!(responder.state(), ResponderState::Probing);
+    }
+
+    #[test]
+    fn test_probe_tiebreak_lose() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+        send(&mut responder, &mut cx);
+        cx.set_now(Instant::from_millis(250));
+        assert!(send(&mut responder, &mut cx).is_some());
+
+        // Another host probes for our host name with a later address: wait, and probe
+        // again from the start.
+        recv(&mut responder, &mut cx, MDNS_DNS_PORT, &probe(PEER_ADDR));
+        assert_eq!(responder.state(), ResponderState::Probing);
+        let at = Instant::from_millis(250) + TIEBREAK_DELAY;
+        assert_eq!(responder.poll_at(&cx), PollAt::Time(at));
+
+        cx.set_now(at);
+        let mut probes = 0;
+        while responder.state() == ResponderState::Probing {
+            if let PollAt::Time(at) = responder.poll_at(&cx) {
+                cx.set_now(at);
+            }
+            if send(&mut responder, &mut cx).is_some() {
+                probes += 1;
+            }
+        }
+        assert_eq!(probes, PROBE_COUNT as usize * GROUPS.len());
+        start(&mut responder, &mut cx);
+    }
+
+    #[test]
+    fn test_probe_tiebreak_win() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+        send(&mut responder, &mut cx);
+        let poll_at = responder.poll_at(&cx);
+
+        // Another host probes for our host name with an earlier address: carry on.
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &probe(Ipv4Address([192, 168, 1, 0])),
+        );
+        assert_eq!(responder.state(), ResponderState::Probing);
+        assert_eq!(responder.poll_at(&cx), poll_at);
+    }
+
+    #[test]
+    fn test_running_conflict() {
+        let mut responder = responder();
+        let mut cx = Context::mock();
+        start(&mut responder, &mut cx);
+
+        // Another host may repeat our own records.
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &address_response(LOCAL_ADDR),
+        );
+        assert_eq!(responder.state(), ResponderState::Running);
+
+        recv(
+            &mut responder,
+            &mut cx,
+            MDNS_DNS_PORT,
+            &address_response(PEER_ADDR),
+        );
+        assert_eq!(responder.state(), ResponderState::Conflict);
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        assert_eq!(Responder::new("").err(), Some(ResponderError::InvalidName));
+        assert_eq!(
+            Responder::new("smoltcp.local").err(),
+            Some(ResponderError::InvalidName)
+        );
+
+        let mut responder = Responder::new("smoltcp").unwrap();
+        let service = Service {
+            instance: "Web",
+            service: "_http._tcp",
+            port: 80,
+            txt: &[],
+        };
+        assert_eq!(
+            responder.add_service(Service {
+                service: "_http..tcp",
+                ..service
+            }),
+            Err(ResponderError::InvalidName)
+        );
+        for _ in 0..MAX_SERVICE_COUNT {
+            responder.add_service(service).unwrap();
+        }
+        assert_eq!(
+            responder.add_service(service),
+            Err(ResponderError::TooManyServices)
+        );
+    }
+}
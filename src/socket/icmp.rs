@@ -37,6 +37,8 @@ pub enum SendError {
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum RecvError {
     Exhausted,
+    /// A packet sent to the address couldn't be delivered.
+    Unreachable(IpAddress),
 }
 
 /// Type of endpoint to bind the ICMP socket to. See [IcmpSocket::bind] for
@@ -90,6 +92,8 @@ pub struct Socket<'a> {
     endpoint: Endpoint,
     /// The time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
     hop_limit: Option<u8>,
+    /// The address of a packet that couldn't be delivered, reported by `recv`.
+    pending_error: Option<IpAddress>,
     #[cfg(feature = "async")]
     rx_waker: WakerRegistration,
     #[cfg(feature = "async")]
@@ -104,6 +108,7 @@ impl<'a> Socket<'a> {
             tx_buffer: tx_buffer,
             endpoint: Default::default(),
             hop_limit: None,
+            pending_error: None,
             #[cfg(feature = "async")]
             rx_waker: WakerRegistration::new(),
             #[cfg(feature = "async")]
@@ -254,10 +259,10 @@ impl<'a> Socket<'a> {
         !self.tx_buffer.is_full()
     }
 
-    /// Check whether the receive buffer is not empty.
+    /// Check whether the receive buffer is not empty, or an error is pending.
     #[inline]
     pub fn can_recv(&self) -> bool {
-        !self.rx_buffer.is_empty()
+        !self.rx_buffer.is_empty() || self.pending_error.is_some()
     }
 
     /// Return the maximum number packets the socket can receive.
@@ -349,8 +354,13 @@ impl<'a> Socket<'a> {
     /// Dequeue a packet received from a remote endpoint, and return the `IpAddress` as well
     /// as a pointer to the payload.
     ///
-    /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty.
+    /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty, and
+    /// `Err(RecvError::Unreachable)` once if a packet sent couldn't be delivered.
     pub fn recv(&mut self) -> Result<(&[u8], IpAddress), RecvError> {
+        if let Some(endpoint) = self.pending_error.take() {
+            return Err(RecvError::Unreachable(endpoint));
+        }
+
         let (endpoint, packet_buf) = self.rx_buffer.dequeue().map_err(|_| RecvError::Exhausted)?;
 
         net_trace!(
@@ -485,6 +495,22 @@ impl<'a> Socket<'a> {
         self.rx_waker.wake();
     }
 
+    /// Report that an Echo Request with the identifier `ident` sent to `addr` couldn't
+    /// be delivered, if it was sent by this socket.
+    pub(crate) fn process_unreachable(&mut self, ident: u16, addr: IpAddress) -> bool {
+        if self.endpoint != Endpoint::Ident(ident) {
+            return false;
+        }
+
+        net_trace!("icmp:{}: unreachable", addr);
+        self.pending_error = Some(addr);
+
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
+
+        true
+    }
+
     pub(crate) fn dispatch<F, E>(&mut self, cx: &mut Context, emit: F) -> Result<(), E>
     where
         F: FnOnce(&mut Context, (IpRepr, IcmpRepr)) -> Result<(), E>,
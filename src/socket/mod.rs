@@ -16,6 +16,8 @@ use crate::time::Instant;
 
 #[cfg(feature = "socket-dhcpv4")]
 pub mod dhcpv4;
+#[cfg(feature = "socket-dhcpv6")]
+pub mod dhcpv6;
 #[cfg(feature = "socket-dns")]
 pub mod dns;
 #[cfg(feature = "socket-icmp")]
@@ -56,6 +58,8 @@ pub(crate) enum PollAt {
 /// [AnySocket]: trait.AnySocket.html
 /// [SocketSet::get]: struct.SocketSet.html#method.get
 #[derive(Debug)]
+// Sockets are stored in place in a `SocketSet`, there is no heap to box the large ones in.
+#[allow(clippy::large_enum_variant)]
 pub enum Socket<'a> {
     #[cfg(feature = "socket-raw")]
     Raw(raw::Socket<'a>),
@@ -67,8 +71,14 @@ pub enum Socket<'a> {
     Tcp(tcp::Socket<'a>),
     #[cfg(feature = "socket-dhcpv4")]
     Dhcpv4(dhcpv4::Socket<'a>),
+    #[cfg(feature = "socket-dhcpv4")]
+    Dhcpv4Server(dhcpv4::Server<'a>),
+    #[cfg(feature = "socket-dhcpv6")]
+    Dhcpv6(dhcpv6::Socket<'a>),
     #[cfg(feature = "socket-dns")]
     Dns(dns::Socket<'a>),
+    #[cfg(feature = "socket-mdns")]
+    MdnsResponder(dns::Responder<'a>),
 }
 
 impl<'a> Socket<'a> {
@@ -84,8 +94,14 @@ impl<'a> Socket<'a> {
             Socket::Tcp(s) => s.poll_at(cx),
             #[cfg(feature = "socket-dhcpv4")]
             Socket::Dhcpv4(s) => s.poll_at(cx),
+            #[cfg(feature = "socket-dhcpv4")]
+            Socket::Dhcpv4Server(s) => s.poll_at(cx),
+            #[cfg(feature = "socket-dhcpv6")]
+            Socket::Dhcpv6(s) => s.poll_at(cx),
             #[cfg(feature = "socket-dns")]
             Socket::Dns(s) => s.poll_at(cx),
+            #[cfg(feature = "socket-mdns")]
+            Socket::MdnsResponder(s) => s.poll_at(cx),
         }
     }
 }
@@ -133,5 +149,11 @@ from_socket!(udp::Socket<'a>, Udp);
 from_socket!(tcp::Socket<'a>, Tcp);
 #[cfg(feature = "socket-dhcpv4")]
 from_socket!(dhcpv4::Socket<'a>, Dhcpv4);
+#[cfg(feature = "socket-dhcpv4")]
+from_socket!(dhcpv4::Server<'a>, Dhcpv4Server);
+#[cfg(feature = "socket-dhcpv6")]
+from_socket!(dhcpv6::Socket<'a>, Dhcpv6);
 #[cfg(feature = "socket-dns")]
 from_socket!(dns::Socket<'a>, Dns);
+#[cfg(feature = "socket-mdns")]
+from_socket!(dns::Responder<'a>, MdnsResponder);
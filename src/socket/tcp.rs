@@ -14,13 +14,20 @@ use crate::storage::{Assembler, RingBuffer};
 use crate::time::{Duration, Instant};
 use crate::wire::{
     IpAddress, IpEndpoint, IpListenEndpoint, IpProtocol, IpRepr, TcpControl, TcpRepr, TcpSeqNumber,
-    TCP_HEADER_LEN,
+    TcpTimestampRepr, TCP_HEADER_LEN,
 };
 
 macro_rules! tcp_trace {
     ($($arg:expr),*) => (net_log!(trace, $($arg),*));
 }
 
+mod congestion;
+mod scoreboard;
+
+use self::congestion::AnyController;
+pub use self::congestion::{CongestionControl, CongestionController};
+use self::scoreboard::Scoreboard;
+
 /// Error returned by [`Socket::listen`]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -52,6 +59,17 @@ pub enum RecvError {
     Finished,
 }
 
+/// Error that aborted a connection attempt, returned by [`Socket::error`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AbortError {
+    /// The remote endpoint refused the connection, with a reset or an ICMP error saying
+    /// that nothing listens on its port.
+    Refused,
+    /// The remote endpoint couldn't be reached, as reported by an ICMP error.
+    Unreachable,
+}
+
 /// A TCP socket ring buffer.
 pub type SocketBuffer<'a> = RingBuffer<'a, u8>;
 
@@ -103,6 +121,10 @@ const RTTE_MIN_MARGIN: u32 = 5;
 const RTTE_MIN_RTO: u32 = 10;
 const RTTE_MAX_RTO: u32 = 10000;
 
+// Maximum number of samples expected per RTT. With more, the gain of a sample is too
+// small to move an estimate kept in milliseconds.
+const RTTE_MAX_EXPECTED_SAMPLES: u32 = 16;
+
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 struct RttEstimator {
@@ -127,6 +149,10 @@ impl Default for RttEstimator {
 }
 
 impl RttEstimator {
+    fn rtt(&self) -> Duration {
+        Duration::from_millis(self.rtt as u64)
+    }
+
     fn retransmission_timeout(&self) -> Duration {
         let margin = RTTE_MIN_MARGIN.max(self.deviation * 4);
         let ms = (self.rtt + margin).clamp(RTTE_MIN_RTO, RTTE_MAX_RTO);
@@ -134,10 +160,28 @@ impl RttEstimator {
     }
 
     fn sample(&mut self, new_rtt: u32) {
+        self.sample_weighted(new_rtt, 1)
+    }
+
+    /// Take an RTT sample, when `expected_samples` samples are expected per RTT.
+    ///
+    /// The gains are divided by the number of expected samples, so that taking a sample
+    /// from every ACK doesn't make the estimate forget its history faster than taking
+    /// one per RTT would, see [RFC 7323 Appendix G].
+    ///
+    /// [RFC 7323 Appendix G]: https://tools.ietf.org/html/rfc7323#appendix-G
+    fn sample_weighted(&mut self, new_rtt: u32, expected_samples: u32) {
+        // Average in u64, where an old value of up to u32::MAX times the weight fits,
+        // rounding to the nearest millisecond so as not to bias the estimate.
+        fn average(old: u32, new: u32, weight: u64) -> u32 {
+            ((old as u64 * (weight - 1) + new as u64 + weight / 2) / weight) as u32
+        }
+
+        let expected_samples = expected_samples.clamp(1, RTTE_MAX_EXPECTED_SAMPLES) as u64;
         // "Congestion Avoidance and Control", Van Jacobson, Michael J. Karels, 1988
-        self.rtt = (self.rtt * 7 + new_rtt + 7) / 8;
+        self.rtt = average(self.rtt, new_rtt, 8 * expected_samples);
         let diff = (self.rtt as i32 - new_rtt as i32).unsigned_abs();
-        self.deviation = (self.deviation * 3 + diff + 3) / 4;
+        self.deviation = average(self.deviation, diff, 4 * expected_samples);
 
         self.rto_count = 0;
 
@@ -174,6 +218,12 @@ impl RttEstimator {
         }
     }
 
+    fn on_ack_timestamp(&mut self, new_rtt: u32, expected_samples: u32) {
+        // Every ACK carries a sample, so there's no need to time a segment separately.
+        self.timestamp = None;
+        self.sample_weighted(new_rtt.min(RTTE_MAX_RTO), expected_samples);
+    }
+
     fn on_retransmit(&mut self) {
         if self.timestamp.is_some() {
             tcp_trace!("rtte: abort sampling due to retransmit");
@@ -218,6 +268,12 @@ enum Timer {
 
 const ACK_DELAY_DEFAULT: Duration = Duration::from_millis(10);
 const CLOSE_DELAY: Duration = Duration::from_millis(10_000);
+// RFC 7323 § 5.5: TS.Recent is no longer valid after 24 days of idle time.
+const PAWS_IDLE_TIMEOUT: Duration = Duration::from_secs(24 * 24 * 60 * 60);
+// Space taken by the timestamps option in every segment, including padding.
+const TIMESTAMP_OPTION_LEN: usize = 12;
+// Most space the options of a SYN can take.
+const MAX_SYN_OPTIONS_LEN: usize = 40;
 
 impl Timer {
     fn new() -> Timer {
@@ -349,6 +405,101 @@ impl Display for Tuple {
     }
 }
 
+/// A TCP Fast Open cookie, as described in [RFC 7413].
+///
+/// A client obtains a cookie from a server on a first connection and presents it on later
+/// connections to the same server, which lets the server accept data carried in the SYN.
+///
+/// [RFC 7413]: https://tools.ietf.org/html/rfc7413
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FastOpenCookie {
+    len: u8,
+    bytes: [u8; 16],
+}
+
+impl FastOpenCookie {
+    /// Create a cookie from its octets.
+    ///
+    /// Returns `None` if the cookie isn't between 4 and 16 octets long.
+    pub fn new(data: &[u8]) -> Option<FastOpenCookie> {
+        if !(4..=16).contains(&data.len()) {
+            return None;
+        }
+        let mut bytes = [0; 16];
+        bytes[..data.len()].copy_from_slice(data);
+        Some(FastOpenCookie {
+            len: data.len() as u8,
+            bytes,
+        })
+    }
+
+    /// Return the octets of the cookie.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// Compute the cookie a server with the given key hands out to a client address,
+    /// which is the SipHash-2-4 of the address.
+    fn generate(key: &[u8; 16], addr: IpAddress) -> FastOpenCookie {
+        let hash = siphash24(key, addr.as_bytes());
+        // NOTE(unwrap): the hash is 8 octets long.
+        FastOpenCookie::new(&hash.to_le_bytes()).unwrap()
+    }
+}
+
+/// SipHash-2-4, a keyed hash that is cheap to compute over short inputs.
+fn siphash24(key: &[u8; 16], data: &[u8]) -> u64 {
+    fn round(v: &mut [u64; 4]) {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+
+    fn compress(m: u64, v: &mut [u64; 4]) {
+        v[3] ^= m;
+        round(v);
+        round(v);
+        v[0] ^= m;
+    }
+
+    let mut k0 = [0; 8];
+    let mut k1 = [0; 8];
+    k0.copy_from_slice(&key[..8]);
+    k1.copy_from_slice(&key[8..]);
+    let (k0, k1) = (u64::from_le_bytes(k0), u64::from_le_bytes(k1));
+    let mut v = [
+        k0 ^ 0x736f6d6570736575,
+        k1 ^ 0x646f72616e646f6d,
+        k0 ^ 0x6c7967656e657261,
+        k1 ^ 0x7465646279746573,
+    ];
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut m = [0; 8];
+        m.copy_from_slice(chunk);
+        compress(u64::from_le_bytes(m), &mut v);
+    }
+    let mut last = [0; 8];
+    last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    last[7] = data.len() as u8;
+    compress(u64::from_le_bytes(last), &mut v);
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
 /// A Transmission Control Protocol socket.
 ///
 /// A TCP socket may passively listen for connections or actively connect to another endpoint.
@@ -384,6 +535,9 @@ pub struct Socket<'a> {
     /// The last sequence number sent.
     /// I.e. in an idle socket, local_seq_no+tx_buffer.len().
     remote_last_seq: TcpSeqNumber,
+    /// The highest sequence number sent.
+    /// I.e. remote_last_seq, unless it was rewound to retransmit data.
+    remote_max_seq: TcpSeqNumber,
     /// The last acknowledgement number sent.
     /// I.e. in an idle socket, remote_seq_no+rx_buffer.len().
     remote_last_ack: Option<TcpSeqNumber>,
@@ -399,6 +553,16 @@ pub struct Socket<'a> {
     remote_win_scale: Option<u8>,
     /// Whether or not the remote supports selective ACK as described in RFC 2018.
     remote_has_sack: bool,
+    /// The ranges of sent data the remote has selectively acknowledged.
+    scoreboard: Scoreboard,
+    /// Whether to use the timestamps option described in RFC 7323 on new connections.
+    timestamps: bool,
+    /// Offset added to the local clock to produce timestamp values, chosen per connection.
+    tsval_offset: u32,
+    /// The last timestamp value received from the remote which is eligible to be echoed
+    /// (TS.Recent in RFC 7323), and when it was received.
+    /// None if the timestamps option isn't used on this connection.
+    remote_ts_recent: Option<(u32, Instant)>,
     /// The maximum number of data octets that the remote side may receive.
     remote_mss: usize,
     /// The timestamp of the last packet received.
@@ -410,6 +574,11 @@ pub struct Socket<'a> {
     /// The number of packets received directly after
     /// each other which have the same ACK number.
     local_rx_dup_acks: u8,
+    /// The congestion controller limiting the amount of data in flight.
+    congestion_controller: AnyController<'a>,
+    /// The highest sequence number sent when fast recovery was entered, if in fast recovery.
+    /// An ACK covering it ends fast recovery, see RFC 6582.
+    recovery_point: Option<TcpSeqNumber>,
 
     /// Duration for Delayed ACK. If None no ACKs will be delayed.
     ack_delay: Option<Duration>,
@@ -423,6 +592,19 @@ pub struct Socket<'a> {
     /// Nagle's Algorithm enabled.
     nagle: bool,
 
+    /// Whether to use TCP Fast Open when connecting.
+    fast_open: bool,
+    /// The cookie to present to the remote when connecting, if one is known.
+    fast_open_cookie: Option<FastOpenCookie>,
+    /// The key used to generate and validate cookies when listening, if TCP Fast Open
+    /// is enabled for incoming connections.
+    fast_open_key: Option<[u8; 16]>,
+    /// Whether the remote asked for a cookie in its SYN, and should get one in our SYN|ACK.
+    fast_open_cookie_requested: bool,
+
+    /// The error that aborted the last connection attempt, if any.
+    error: Option<AbortError>,
+
     #[cfg(feature = "async")]
     rx_waker: WakerRegistration,
     #[cfg(feature = "async")]
@@ -466,21 +648,33 @@ impl<'a> Socket<'a> {
             local_seq_no: TcpSeqNumber::default(),
             remote_seq_no: TcpSeqNumber::default(),
             remote_last_seq: TcpSeqNumber::default(),
+            remote_max_seq: TcpSeqNumber::default(),
             remote_last_ack: None,
             remote_last_win: 0,
             remote_win_len: 0,
             remote_win_shift: rx_cap_log2.saturating_sub(16) as u8,
             remote_win_scale: None,
             remote_has_sack: false,
+            scoreboard: Scoreboard::default(),
+            timestamps: false,
+            tsval_offset: 0,
+            remote_ts_recent: None,
             remote_mss: DEFAULT_MSS,
             remote_last_ts: None,
             local_rx_last_ack: None,
             local_rx_last_seq: None,
             local_rx_dup_acks: 0,
+            congestion_controller: AnyController::new(CongestionControl::None),
+            recovery_point: None,
             ack_delay: Some(ACK_DELAY_DEFAULT),
             ack_delay_timer: AckDelayTimer::Idle,
             challenge_ack_timer: Instant::from_secs(0),
             nagle: true,
+            fast_open: false,
+            fast_open_cookie: None,
+            fast_open_key: None,
+            fast_open_cookie_requested: false,
+            error: None,
 
             #[cfg(feature = "async")]
             rx_waker: WakerRegistration::new(),
@@ -545,6 +739,20 @@ impl<'a> Socket<'a> {
         self.nagle
     }
 
+    /// Return whether the timestamps option is enabled.
+    ///
+    /// See also the [set_timestamps_enabled](#method.set_timestamps_enabled) method.
+    pub fn timestamps_enabled(&self) -> bool {
+        self.timestamps
+    }
+
+    /// Return the congestion control algorithm.
+    ///
+    /// See also the [set_congestion_control](#method.set_congestion_control) method.
+    pub fn congestion_control(&self) -> CongestionControl {
+        self.congestion_controller.algorithm()
+    }
+
     /// Return the current window field value, including scaling according to RFC 1323.
     ///
     /// Used in internal calculations as well as packet generation.
@@ -579,6 +787,101 @@ impl<'a> Socket<'a> {
         self.ack_delay = duration
     }
 
+    /// Set the congestion control algorithm.
+    ///
+    /// By default, no congestion control is performed, and the socket sends as much data
+    /// as the remote window allows. With congestion control enabled, the amount of data in flight
+    /// is additionally limited by a congestion window that starts small, grows while data
+    /// is acknowledged, and shrinks when packet loss is detected.
+    ///
+    /// Changing the algorithm restarts congestion control from the initial window.
+    pub fn set_congestion_control(&mut self, congestion_control: CongestionControl) {
+        let mut controller = AnyController::new(congestion_control);
+        controller.inner_mut().set_mss(self.remote_mss);
+        controller
+            .inner_mut()
+            .set_remote_window(self.remote_win_len);
+        self.congestion_controller = controller;
+        self.recovery_point = None;
+    }
+
+    /// Use a congestion controller supplied by the application.
+    ///
+    /// This is like [set_congestion_control](#method.set_congestion_control), but with an
+    /// algorithm implemented outside of smoltcp. The controller is borrowed for as long as
+    /// the socket exists; `congestion_control` returns `CongestionControl::Custom` until
+    /// another algorithm is selected.
+    pub fn set_congestion_controller(&mut self, controller: &'a mut dyn CongestionController) {
+        controller.set_mss(self.remote_mss);
+        controller.set_remote_window(self.remote_win_len);
+        self.congestion_controller = AnyController::Custom(controller);
+        self.recovery_point = None;
+    }
+
+    /// Enable or disable the timestamps option, as described in [RFC 7323].
+    ///
+    /// By default, it is disabled. When enabled, the socket offers the option when connecting
+    /// and accepts it when the remote offers it. If both sides agree, every segment carries
+    /// a timestamp, which is used to take an RTT sample from every ACK, and to discard old
+    /// duplicate segments that would otherwise be mistaken for new ones once the sequence
+    /// numbers wrap around (PAWS).
+    ///
+    /// The setting takes effect on the next connection.
+    ///
+    /// [RFC 7323]: https://tools.ietf.org/html/rfc7323
+    pub fn set_timestamps_enabled(&mut self, enabled: bool) {
+        self.timestamps = enabled
+    }
+
+    /// Return whether TCP Fast Open is enabled when connecting.
+    ///
+    /// See also the [set_fast_open_enabled](#method.set_fast_open_enabled) method.
+    pub fn fast_open_enabled(&self) -> bool {
+        self.fast_open
+    }
+
+    /// Enable or disable TCP Fast Open when connecting, as described in [RFC 7413].
+    ///
+    /// By default, it is disabled. When enabled and a cookie for the remote is known
+    /// (see [set_fast_open_cookie](#method.set_fast_open_cookie)), data may be sent right
+    /// after [connect](#method.connect), and as much of it as fits is carried in the SYN,
+    /// saving a round trip. Data the remote doesn't accept is sent again once the connection
+    /// is established. When no cookie is known, the SYN requests one instead, which becomes
+    /// available through [fast_open_cookie](#method.fast_open_cookie) once the connection
+    /// is established.
+    ///
+    /// [RFC 7413]: https://tools.ietf.org/html/rfc7413
+    pub fn set_fast_open_enabled(&mut self, enabled: bool) {
+        self.fast_open = enabled
+    }
+
+    /// Return the TCP Fast Open cookie presented when connecting.
+    ///
+    /// This is the cookie set with [set_fast_open_cookie](#method.set_fast_open_cookie),
+    /// or the one most recently received from a remote.
+    pub fn fast_open_cookie(&self) -> Option<FastOpenCookie> {
+        self.fast_open_cookie
+    }
+
+    /// Set the TCP Fast Open cookie presented when connecting.
+    ///
+    /// Cookies are specific to a server, so a cookie cached from an earlier connection
+    /// should only be set before connecting to the same server again.
+    pub fn set_fast_open_cookie(&mut self, cookie: Option<FastOpenCookie>) {
+        self.fast_open_cookie = cookie
+    }
+
+    /// Set the key used to generate and validate TCP Fast Open cookies when listening.
+    ///
+    /// By default, there is no key and TCP Fast Open is disabled for incoming connections.
+    /// With a key, the socket hands out cookies to remotes that request one, and accepts
+    /// data carried in the SYN of remotes that present a valid cookie. Such data is readable
+    /// before the connection is established. The same key should be set on every socket
+    /// listening for the same service, and may be changed to invalidate the cookies given out.
+    pub fn set_fast_open_key(&mut self, key: Option<[u8; 16]>) {
+        self.fast_open_key = key
+    }
+
     /// Enable or disable Nagle's Algorithm.
     ///
     /// Also known as "tinygram prevention". By default, it is enabled.
@@ -668,6 +971,16 @@ impl<'a> Socket<'a> {
         self.state
     }
 
+    /// Return the error that aborted the last connection attempt, if any.
+    ///
+    /// A socket in the `SYN-SENT` state is moved to the `CLOSED` state when the remote
+    /// endpoint answers with a reset, or an ICMP error reports that it can't be reached.
+    /// The error is cleared when the socket is opened again.
+    #[inline]
+    pub fn error(&self) -> Option<AbortError> {
+        self.error
+    }
+
     fn reset(&mut self) {
         let rx_cap_log2 =
             mem::size_of::<usize>() * 8 - self.rx_buffer.capacity().leading_zeros() as usize;
@@ -684,15 +997,24 @@ impl<'a> Socket<'a> {
         self.local_seq_no = TcpSeqNumber::default();
         self.remote_seq_no = TcpSeqNumber::default();
         self.remote_last_seq = TcpSeqNumber::default();
+        self.remote_max_seq = TcpSeqNumber::default();
         self.remote_last_ack = None;
         self.remote_last_win = 0;
         self.remote_win_len = 0;
         self.remote_win_scale = None;
         self.remote_win_shift = rx_cap_log2.saturating_sub(16) as u8;
+        self.remote_has_sack = false;
+        self.scoreboard.clear();
+        self.tsval_offset = 0;
+        self.remote_ts_recent = None;
         self.remote_mss = DEFAULT_MSS;
         self.remote_last_ts = None;
         self.ack_delay_timer = AckDelayTimer::Idle;
         self.challenge_ack_timer = Instant::from_secs(0);
+        self.congestion_controller.reset();
+        self.recovery_point = None;
+        self.fast_open_cookie_requested = false;
+        self.error = None;
 
         #[cfg(feature = "async")]
         {
@@ -793,6 +1115,8 @@ impl<'a> Socket<'a> {
         let seq = Self::random_seq_no(cx);
         self.local_seq_no = seq;
         self.remote_last_seq = seq;
+        self.remote_max_seq = seq;
+        self.tsval_offset = Self::random_tsval_offset(cx);
         Ok(())
     }
 
@@ -806,6 +1130,37 @@ impl<'a> Socket<'a> {
         TcpSeqNumber(cx.rand().rand_u32() as i32)
     }
 
+    #[cfg(test)]
+    fn random_tsval_offset(_cx: &mut Context) -> u32 {
+        0
+    }
+
+    #[cfg(not(test))]
+    fn random_tsval_offset(cx: &mut Context) -> u32 {
+        cx.rand().rand_u32()
+    }
+
+    /// Return the value of the timestamp clock at `timestamp`.
+    fn tsval(&self, timestamp: Instant) -> u32 {
+        (timestamp.total_millis() as u32).wrapping_add(self.tsval_offset)
+    }
+
+    /// Return the timestamps option to include in outgoing segments, if any.
+    fn timestamp_to_send(&self, timestamp: Instant) -> Option<TcpTimestampRepr> {
+        match self.remote_ts_recent {
+            Some((ts_recent, _)) => Some(TcpTimestampRepr {
+                tsval: self.tsval(timestamp),
+                tsecr: ts_recent,
+            }),
+            // RFC 7323 § 3.2: the TSecr field of the initial SYN is zero.
+            None if self.state == State::SynSent && self.timestamps => Some(TcpTimestampRepr {
+                tsval: self.tsval(timestamp),
+                tsecr: 0,
+            }),
+            None => None,
+        }
+    }
+
     /// Close the transmit half of the full-duplex connection.
     ///
     /// Note that there is no corresponding function for the receive half of the full-duplex
@@ -903,11 +1258,14 @@ impl<'a> Socket<'a> {
     /// not be able to enqueue any octets.
     ///
     /// In terms of the TCP state machine, the socket must be in the `ESTABLISHED` or
-    /// `CLOSE-WAIT` state.
+    /// `CLOSE-WAIT` state, or in the `SYN-SENT` state if TCP Fast Open is enabled and
+    /// a cookie is known.
     #[inline]
     pub fn may_send(&self) -> bool {
         match self.state {
             State::Established => true,
+            // With TCP Fast Open, data may be carried in the SYN.
+            State::SynSent => self.fast_open && self.fast_open_cookie.is_some(),
             // In CLOSE-WAIT, the remote endpoint has closed our receive half of the connection
             // but we still can transmit indefinitely.
             State::CloseWait => true,
@@ -1026,7 +1384,8 @@ impl<'a> Socket<'a> {
     fn recv_error_check(&mut self) -> Result<(), RecvError> {
         // We may have received some data inside the initial SYN, but until the connection
         // is fully open we must not dequeue any data, as it may be overwritten by e.g.
-        // another (stale) SYN. (We do not support TCP Fast Open.)
+        // another (stale) SYN. (With TCP Fast Open, data is only queued from a SYN
+        // carrying a valid cookie, and that data may be dequeued right away.)
         if !self.may_recv() {
             if self.rx_fin_received {
                 return Err(RecvError::Finished);
@@ -1158,6 +1517,8 @@ impl<'a> Socket<'a> {
             max_seg_size: None,
             sack_permitted: false,
             sack_ranges: [None, None, None],
+            timestamp: None,
+            fast_open_cookie: None,
             payload: &[],
         };
         let ip_reply_repr = IpRepr::new(
@@ -1186,7 +1547,12 @@ impl<'a> Socket<'a> {
         (ip_reply_repr, reply_repr)
     }
 
-    fn ack_reply(&mut self, ip_repr: &IpRepr, repr: &TcpRepr) -> (IpRepr, TcpRepr<'static>) {
+    fn ack_reply(
+        &mut self,
+        cx: &mut Context,
+        ip_repr: &IpRepr,
+        repr: &TcpRepr,
+    ) -> (IpRepr, TcpRepr<'static>) {
         let (mut ip_reply_repr, mut reply_repr) = Self::reply(ip_repr, repr);
 
         // From RFC 793:
@@ -1203,6 +1569,8 @@ impl<'a> Socket<'a> {
         reply_repr.window_len = self.scaled_window();
         self.remote_last_win = reply_repr.window_len;
 
+        reply_repr.timestamp = self.timestamp_to_send(cx.now());
+
         // If the remote supports selective acknowledgement, add the option to the outgoing
         // segment.
         if self.remote_has_sack {
@@ -1238,7 +1606,7 @@ impl<'a> Socket<'a> {
             }
         }
 
-        // Since the sACK option may have changed the length of the payload, update that.
+        // Since the options may have changed the length of the payload, update that.
         ip_reply_repr.set_payload_len(reply_repr.buffer_len());
         (ip_reply_repr, reply_repr)
     }
@@ -1256,7 +1624,7 @@ impl<'a> Socket<'a> {
         // Rate-limit to 1 per second max.
         self.challenge_ack_timer = cx.now() + Duration::from_secs(1);
 
-        return Some(self.ack_reply(ip_repr, repr));
+        return Some(self.ack_reply(cx, ip_repr, repr));
     }
 
     pub(crate) fn accepts(&self, _cx: &mut Context, ip_repr: &IpRepr, repr: &TcpRepr) -> bool {
@@ -1287,6 +1655,38 @@ impl<'a> Socket<'a> {
         }
     }
 
+    /// Report an ICMP error about a segment with the sequence number `seq_number` sent
+    /// from `local_endpoint` to `remote_endpoint`, if it was sent by this socket.
+    ///
+    /// Only a connection attempt is aborted. Errors about established connections are
+    /// soft, see RFC 1122 § 4.2.3.9, and are left to the retransmission timeout.
+    pub(crate) fn process_error(
+        &mut self,
+        local_endpoint: IpEndpoint,
+        remote_endpoint: IpEndpoint,
+        seq_number: TcpSeqNumber,
+        error: AbortError,
+    ) -> bool {
+        let tuple = match self.tuple {
+            Some(tuple) if tuple.local == local_endpoint && tuple.remote == remote_endpoint => {
+                tuple
+            }
+            _ => return false,
+        };
+
+        // Check that the error quotes our SYN, so that it can't be forged blindly.
+        if self.state != State::SynSent || seq_number != self.local_seq_no {
+            net_debug!("ignoring ICMP error about {}", tuple);
+            return true;
+        }
+
+        net_debug!("connection {} aborted by ICMP error: {:?}", tuple, error);
+        self.error = Some(error);
+        self.set_state(State::Closed);
+        self.tuple = None;
+        true
+    }
+
     pub(crate) fn process(
         &mut self,
         cx: &mut Context,
@@ -1307,6 +1707,29 @@ impl<'a> Socket<'a> {
         };
         let control_len = (sent_syn as usize) + (sent_fin as usize);
 
+        // Reject old duplicate segments, see RFC 7323 § 5.3.
+        if let Some((ts_recent, ts_recent_age)) = self.remote_ts_recent {
+            match repr.timestamp {
+                _ if repr.control == TcpControl::Rst => (),
+                None => {
+                    net_debug!("segment without timestamp, ignoring");
+                    return None;
+                }
+                Some(timestamp)
+                    if (timestamp.tsval.wrapping_sub(ts_recent) as i32) < 0
+                        && cx.now() < ts_recent_age + PAWS_IDLE_TIMEOUT =>
+                {
+                    net_debug!(
+                        "segment with old timestamp ({} < {}), will send challenge ACK",
+                        timestamp.tsval,
+                        ts_recent
+                    );
+                    return self.challenge_ack_reply(cx, ip_repr, repr);
+                }
+                Some(_) => (),
+            }
+        }
+
         // Reject unacceptable acknowledgements.
         match (self.state, repr.control, repr.ack_number) {
             // An RST received in response to initial SYN is acceptable if it acknowledges
@@ -1316,7 +1739,7 @@ impl<'a> Socket<'a> {
                 return None;
             }
             (State::SynSent, TcpControl::Rst, Some(ack_number)) => {
-                if ack_number != self.local_seq_no + 1 {
+                if !self.syn_acceptably_acked(ack_number) {
                     net_debug!("unacceptable RST|ACK in response to initial SYN");
                     return None;
                 }
@@ -1332,9 +1755,10 @@ impl<'a> Socket<'a> {
                 net_debug!("expecting an ACK");
                 return None;
             }
-            // SYN|ACK in the SYN-SENT state must have the exact ACK number.
+            // SYN|ACK in the SYN-SENT state must acknowledge the SYN, and may acknowledge
+            // data sent along with it.
             (State::SynSent, TcpControl::Syn, Some(ack_number)) => {
-                if ack_number != self.local_seq_no + 1 {
+                if !self.syn_acceptably_acked(ack_number) {
                     net_debug!("unacceptable SYN|ACK in response to initial SYN");
                     return Some(Self::rst_reply(ip_repr, repr));
                 }
@@ -1456,6 +1880,22 @@ impl<'a> Socket<'a> {
             }
         }
 
+        // Remember the timestamp to echo, see RFC 7323 § 4.3. The segment is in the window,
+        // so this only needs to check that it doesn't start past what we've acknowledged.
+        // An expired TS.Recent is replaced by whatever arrives next, see RFC 7323 § 5.5.
+        if let (Some((ts_recent, ts_recent_age)), Some(timestamp)) =
+            (self.remote_ts_recent, repr.timestamp)
+        {
+            if ((timestamp.tsval.wrapping_sub(ts_recent) as i32) >= 0
+                || cx.now() >= ts_recent_age + PAWS_IDLE_TIMEOUT)
+                && self
+                    .remote_last_ack
+                    .map_or(true, |last_ack| repr.seq_number <= last_ack)
+            {
+                self.remote_ts_recent = Some((timestamp.tsval, cx.now()));
+            }
+        }
+
         // Compute the amount of acknowledged octets, removing the SYN and FIN bits
         // from the sequence space.
         let mut ack_len = 0;
@@ -1482,10 +1922,33 @@ impl<'a> Socket<'a> {
                     ack_all = self.remote_last_seq == ack_number
                 }
 
-                self.rtte.on_ack(cx.now(), ack_number);
+                match (self.remote_ts_recent, repr.timestamp) {
+                    // RFC 7323 § 4.2: take an RTT sample from every ACK of new data.
+                    // A TSecr of zero isn't a valid echo.
+                    (Some(_), Some(timestamp))
+                        if ack_number > self.local_seq_no && timestamp.tsecr != 0 =>
+                    {
+                        let rtt = self.tsval(cx.now()).wrapping_sub(timestamp.tsecr);
+                        // A timestamp from the future means the remote echoed garbage.
+                        if rtt as i32 >= 0 {
+                            // RFC 7323 Appendix G: expect a sample for every other segment
+                            // in flight, since the remote may delay ACKs.
+                            let flight_size = self.remote_last_seq - self.local_seq_no;
+                            let bytes_per_sample = 2 * self.effective_mss(cx);
+                            let expected_samples =
+                                (flight_size + bytes_per_sample - 1) / bytes_per_sample;
+                            self.rtte
+                                .on_ack_timestamp(rtt, expected_samples.max(1) as u32);
+                        }
+                    }
+                    _ => self.rtte.on_ack(cx.now(), ack_number),
+                }
             }
         }
 
+        // Data in a SYN is only accepted along with a valid TCP Fast Open cookie, when enabled.
+        let mut payload = repr.payload;
+
         // Disregard control flags we don't care about or shouldn't act on yet.
         let mut control = repr.control;
         control = control.quash_psh();
@@ -1512,6 +1975,9 @@ impl<'a> Socket<'a> {
             // RSTs in any other state close the socket.
             (_, TcpControl::Rst) => {
                 tcp_trace!("received RST");
+                if self.state == State::SynSent {
+                    self.error = Some(AbortError::Refused);
+                }
                 self.set_state(State::Closed);
                 self.tuple = None;
                 return None;
@@ -1532,15 +1998,37 @@ impl<'a> Socket<'a> {
                     local: IpEndpoint::new(ip_repr.dst_addr(), repr.dst_port),
                     remote: IpEndpoint::new(ip_repr.src_addr(), repr.src_port),
                 });
+                if let (true, Some(timestamp)) = (self.timestamps, repr.timestamp) {
+                    self.tsval_offset = Self::random_tsval_offset(cx);
+                    self.remote_ts_recent = Some((timestamp.tsval, cx.now()));
+                }
+                let mss = self.effective_mss(cx);
+                self.congestion_controller.inner_mut().set_mss(mss);
                 self.local_seq_no = Self::random_seq_no(cx);
                 self.remote_seq_no = repr.seq_number + 1;
                 self.remote_last_seq = self.local_seq_no;
+                self.remote_max_seq = self.local_seq_no;
                 self.remote_has_sack = repr.sack_permitted;
                 self.remote_win_scale = repr.window_scale;
                 // Remote doesn't support window scaling, don't do it.
                 if self.remote_win_scale.is_none() {
                     self.remote_win_shift = 0;
                 }
+                if let Some(key) = self.fast_open_key {
+                    let cookie = FastOpenCookie::generate(&key, ip_repr.src_addr());
+                    match repr.fast_open_cookie {
+                        Some(received) if received == cookie.as_bytes() => {
+                            tcp_trace!("received valid TFO cookie");
+                        }
+                        Some(_) => {
+                            // An empty cookie is a request, an invalid one gets replaced.
+                            tcp_trace!("received TFO cookie request");
+                            self.fast_open_cookie_requested = true;
+                            payload = &[];
+                        }
+                        None => payload = &[],
+                    }
+                }
                 self.set_state(State::SynReceived);
                 self.timer.set_for_idle(cx.now(), self.keep_alive);
             }
@@ -1571,10 +2059,27 @@ impl<'a> Socket<'a> {
                     }
                     self.remote_mss = max_seg_size as usize;
                 }
+                // We've offered the timestamps option if enabled, so it's in use if the remote
+                // agreed.
+                if let (true, Some(timestamp)) = (self.timestamps, repr.timestamp) {
+                    self.remote_ts_recent = Some((timestamp.tsval, cx.now()));
+                }
+                let mss = self.effective_mss(cx);
+                self.congestion_controller.inner_mut().set_mss(mss);
 
                 self.remote_seq_no = repr.seq_number + 1;
                 self.remote_last_seq = self.local_seq_no + 1;
                 self.remote_last_ack = Some(repr.seq_number);
+                // We've offered selective ACK, so it's in use if the remote agreed.
+                self.remote_has_sack = repr.sack_permitted;
+                // We've requested or presented a TCP Fast Open cookie if enabled, so remember
+                // the one the remote gave us for the next connection.
+                if let (true, Some(cookie)) = (self.fast_open, repr.fast_open_cookie) {
+                    if let Some(cookie) = FastOpenCookie::new(cookie) {
+                        tcp_trace!("received TFO cookie");
+                        self.fast_open_cookie = Some(cookie);
+                    }
+                }
                 self.remote_win_scale = repr.window_scale;
                 // Remote doesn't support window scaling, don't do it.
                 if self.remote_win_scale.is_none() {
@@ -1681,6 +2186,9 @@ impl<'a> Socket<'a> {
             _ => self.remote_win_scale.unwrap_or(0),
         };
         self.remote_win_len = (repr.window_len as usize) << (scale as usize);
+        self.congestion_controller
+            .inner_mut()
+            .set_remote_window(self.remote_win_len);
 
         if ack_len > 0 {
             // Dequeue acknowledged octets.
@@ -1692,6 +2200,10 @@ impl<'a> Socket<'a> {
             );
             self.tx_buffer.dequeue_allocated(ack_len);
 
+            // Forward progress confirms that the neighbor the remote endpoint is reached
+            // through is reachable (RFC 4861 § 7.3.1).
+            cx.confirm_reachable(&ip_repr.src_addr());
+
             // There's new room available in tx_buffer, wake the waiting task if any.
             #[cfg(feature = "async")]
             self.tx_waker.wake();
@@ -1731,6 +2243,19 @@ impl<'a> Socket<'a> {
                     if self.local_rx_dup_acks == 3 {
                         self.timer.set_for_fast_retransmit();
                         net_debug!("started fast retransmit");
+
+                        // Enter fast recovery, unless we're already recovering from
+                        // a loss in the same window of data.
+                        if self.recovery_point.is_none() {
+                            self.congestion_controller
+                                .inner_mut()
+                                .on_fast_retransmit(cx.now(), self.remote_last_seq - ack_number);
+                            self.recovery_point = Some(self.remote_last_seq);
+                        }
+                    } else if self.local_rx_dup_acks > 3 && self.recovery_point.is_some() {
+                        self.congestion_controller
+                            .inner_mut()
+                            .on_duplicate_ack(cx.now());
                     }
                 }
                 // No duplicate ACK -> Reset state and update last received ACK
@@ -1742,6 +2267,24 @@ impl<'a> Socket<'a> {
                     self.local_rx_last_ack = Some(ack_number);
                 }
             };
+
+            if ack_len > 0 {
+                match self.recovery_point {
+                    Some(recovery_point) if ack_number >= recovery_point => {
+                        net_debug!("fast recovery complete");
+                        self.recovery_point = None;
+                        self.congestion_controller
+                            .inner_mut()
+                            .on_recovery_exit(cx.now());
+                    }
+                    _ => self.congestion_controller.inner_mut().on_ack(
+                        cx.now(),
+                        ack_len,
+                        self.rtte.rtt(),
+                    ),
+                }
+            }
+
             // We've processed everything in the incoming segment, so advance the local
             // sequence number past it.
             self.local_seq_no = ack_number;
@@ -1753,9 +2296,27 @@ impl<'a> Socket<'a> {
             if self.remote_last_seq < self.local_seq_no {
                 self.remote_last_seq = self.local_seq_no
             }
+            if self.remote_max_seq < self.remote_last_seq {
+                self.remote_max_seq = self.remote_last_seq
+            }
+
+            // Record the data the remote has selectively acknowledged, see RFC 6675 § 5.
+            // Ranges that are already cumulatively acknowledged or that we haven't sent yet
+            // are bogus.
+            if self.remote_has_sack {
+                self.scoreboard.ack(self.local_seq_no);
+                for &(left, right) in repr.sack_ranges.iter().flatten() {
+                    let left = TcpSeqNumber(left as i32);
+                    let right = TcpSeqNumber(right as i32);
+                    if self.local_seq_no <= left && right <= self.remote_max_seq {
+                        self.scoreboard.add(left, right);
+                    }
+                }
+                self.skip_sacked();
+            }
         }
 
-        let payload_len = repr.payload.len();
+        let payload_len = payload.len();
         if payload_len == 0 {
             return None;
         }
@@ -1772,9 +2333,7 @@ impl<'a> Socket<'a> {
                     payload_len,
                     payload_offset
                 );
-                let len_written = self
-                    .rx_buffer
-                    .write_unallocated(payload_offset, repr.payload);
+                let len_written = self.rx_buffer.write_unallocated(payload_offset, payload);
                 debug_assert!(len_written == payload_len);
             }
             Err(_) => {
@@ -1839,7 +2398,7 @@ impl<'a> Socket<'a> {
             // This is fine because smoltcp assumes that it can always transmit zero or one
             // packets for every packet it receives.
             tcp_trace!("ACKing incoming segment");
-            Some(self.ack_reply(ip_repr, repr))
+            Some(self.ack_reply(cx, ip_repr, repr))
         } else {
             None
         }
@@ -1852,19 +2411,68 @@ impl<'a> Socket<'a> {
         }
     }
 
-    fn seq_to_transmit(&self, cx: &mut Context) -> bool {
-        let ip_header_len = match self.tuple.unwrap().local.addr {
+    /// Return the effective max segment size, taking into account our and remote's limits.
+    /// Return whether an ACK received in the SYN-SENT state acknowledges our SYN, and
+    /// no more than the data we've sent along with it.
+    fn syn_acceptably_acked(&self, ack_number: TcpSeqNumber) -> bool {
+        let ack_min = self.local_seq_no + 1;
+        let ack_max = if self.remote_max_seq > ack_min {
+            self.remote_max_seq
+        } else {
+            ack_min
+        };
+        ack_min <= ack_number && ack_number <= ack_max
+    }
+
+    fn effective_mss(&self, cx: &mut Context) -> usize {
+        let tuple = self.tuple.unwrap();
+        let ip_header_len = match tuple.local.addr {
             #[cfg(feature = "proto-ipv4")]
             IpAddress::Ipv4(_) => crate::wire::IPV4_HEADER_LEN,
             #[cfg(feature = "proto-ipv6")]
             IpAddress::Ipv6(_) => crate::wire::IPV6_HEADER_LEN,
         };
 
-        // Max segment size we're able to send due to MTU limitations.
-        let local_mss = cx.ip_mtu() - ip_header_len - TCP_HEADER_LEN;
+        // Max segment size we're able to send due to the MTU of the path to the remote.
+        let local_mss = cx.path_mtu(&tuple.remote.addr) - ip_header_len - TCP_HEADER_LEN;
+
+        // The MSS doesn't account for TCP options, so leave room for the ones we send
+        // in every segment. A remote announcing a tiny MSS still gets one octet per segment.
+        let options_len = if self.remote_ts_recent.is_some() {
+            TIMESTAMP_OPTION_LEN
+        } else {
+            0
+        };
+
+        local_mss
+            .min(self.remote_mss)
+            .saturating_sub(options_len)
+            .max(1)
+    }
+
+    /// Return the amount of octets we're allowed to have in flight, which is limited by
+    /// both the remote window and the congestion window.
+    fn send_window(&self) -> usize {
+        self.remote_win_len
+            .min(self.congestion_controller.inner().window())
+    }
+
+    /// While retransmitting, move past the data the remote has selectively acknowledged,
+    /// so that only the holes in it are sent again.
+    fn skip_sacked(&mut self) {
+        if self.remote_last_seq < self.remote_max_seq {
+            let next_seq = self
+                .scoreboard
+                .next_hole(self.remote_last_seq, self.remote_max_seq);
+            if next_seq != self.remote_last_seq {
+                tcp_trace!("sACK: skipping to seq {}", next_seq);
+                self.remote_last_seq = next_seq;
+            }
+        }
+    }
 
-        // The effective max segment size, taking into account our and remote's limits.
-        let effective_mss = local_mss.min(self.remote_mss);
+    fn seq_to_transmit(&self, cx: &mut Context) -> bool {
+        let effective_mss = self.effective_mss(cx);
 
         // Have we sent data that hasn't been ACKed yet?
         let data_in_flight = self.remote_last_seq != self.local_seq_no;
@@ -1876,7 +2484,7 @@ impl<'a> Socket<'a> {
 
         // max sequence number we can send.
         let max_send_seq =
-            self.local_seq_no + core::cmp::min(self.remote_win_len, self.tx_buffer.len());
+            self.local_seq_no + core::cmp::min(self.send_window(), self.tx_buffer.len());
 
         // Max amount of octets we can send.
         let max_send = if max_send_seq >= self.remote_last_seq {
@@ -1972,10 +2580,27 @@ impl<'a> Socket<'a> {
                 // If a retransmit timer expired, we should resend data starting at the last ACK.
                 net_debug!("retransmitting at t+{}", retransmit_delta);
 
+                // A retransmission timeout (as opposed to a fast retransmit, which was
+                // already reported when the duplicate ACKs arrived) is a strong
+                // congestion signal.
+                if self.timer != Timer::FastRetransmit {
+                    let flight_size = self.remote_last_seq - self.local_seq_no;
+                    self.congestion_controller
+                        .inner_mut()
+                        .on_retransmit_timeout(cx.now(), flight_size);
+                    self.recovery_point = None;
+
+                    // RFC 2018 § 8: the remote may have discarded data it selectively
+                    // acknowledged, so don't rely on that after a timeout.
+                    self.scoreboard.clear();
+                }
+
                 // Rewind "last sequence number sent", as if we never
                 // had sent them. This will cause all data in the queue
-                // to be sent again.
+                // to be sent again, except for what the remote has
+                // selectively acknowledged.
                 self.remote_last_seq = self.local_seq_no;
+                self.skip_sacked();
 
                 // Clear the `should_retransmit` state. If we can't retransmit right
                 // now for whatever reason (like zero window), this avoids an
@@ -2026,6 +2651,14 @@ impl<'a> Socket<'a> {
             self.hop_limit.unwrap_or(64),
         );
 
+        // The TCP Fast Open cookie given out in a SYN|ACK, if the remote asked for one.
+        let fast_open_cookie = match (self.state, self.fast_open_key) {
+            (State::SynReceived, Some(key)) if self.fast_open_cookie_requested => {
+                Some(FastOpenCookie::generate(&key, tuple.remote.addr))
+            }
+            _ => None,
+        };
+
         // Construct the basic TCP representation, an empty ACK packet.
         // We'll adjust this to be more specific as needed.
         let mut repr = TcpRepr {
@@ -2039,6 +2672,8 @@ impl<'a> Socket<'a> {
             max_seg_size: None,
             sack_permitted: false,
             sack_ranges: [None, None, None],
+            timestamp: None,
+            fast_open_cookie: None,
             payload: &[],
         };
 
@@ -2062,9 +2697,27 @@ impl<'a> Socket<'a> {
                     repr.ack_number = None;
                     repr.window_scale = Some(self.remote_win_shift);
                     repr.sack_permitted = true;
+                    // With TCP Fast Open, the first SYN presents our cookie along with as much
+                    // data as fits, or requests a cookie. Retransmitted SYNs carry neither,
+                    // in case the remote or a middlebox drops them.
+                    if self.fast_open && self.remote_max_seq == self.local_seq_no {
+                        match self.fast_open_cookie {
+                            Some(ref cookie) => {
+                                repr.fast_open_cookie = Some(cookie.as_bytes());
+                                // Leave room for the options of the SYN.
+                                let size = self
+                                    .effective_mss(cx)
+                                    .saturating_sub(MAX_SYN_OPTIONS_LEN)
+                                    .max(1);
+                                repr.payload = self.tx_buffer.get_allocated(0, size);
+                            }
+                            None => repr.fast_open_cookie = Some(&[]),
+                        }
+                    }
                 } else {
                     repr.sack_permitted = self.remote_has_sack;
                     repr.window_scale = self.remote_win_scale.map(|_| self.remote_win_shift);
+                    repr.fast_open_cookie = fast_open_cookie.as_ref().map(|c| c.as_bytes());
                 }
             }
 
@@ -2079,7 +2732,7 @@ impl<'a> Socket<'a> {
                 // from the transmit buffer.
 
                 // Right edge of window, ie the max sequence number we're allowed to send.
-                let win_right_edge = self.local_seq_no + self.remote_win_len;
+                let win_right_edge = self.local_seq_no + self.send_window();
 
                 // Max amount of octets we're allowed to send according to the remote window.
                 let win_limit = if win_right_edge >= self.remote_last_seq {
@@ -2097,9 +2750,12 @@ impl<'a> Socket<'a> {
                 // 1. remote window
                 // 2. MSS the remote is willing to accept, probably determined by their MTU
                 // 3. MSS we can send, determined by our MTU.
+                // Both MSS limits are reduced by the space taken by TCP options.
+                // When retransmitting, we also stop before data the remote has selectively
+                // acknowledged.
                 let size = win_limit
-                    .min(self.remote_mss)
-                    .min(cx.ip_mtu() - ip_repr.header_len() - TCP_HEADER_LEN);
+                    .min(self.effective_mss(cx))
+                    .min(self.scoreboard.hole_len(self.remote_last_seq));
 
                 let offset = self.remote_last_seq - self.local_seq_no;
                 repr.payload = self.tx_buffer.get_allocated(offset, size);
@@ -2159,6 +2815,10 @@ impl<'a> Socket<'a> {
             tcp_trace!("sending {}", flags);
         }
 
+        if repr.control != TcpControl::Rst {
+            repr.timestamp = self.timestamp_to_send(cx.now());
+        }
+
         if repr.control == TcpControl::Syn {
             // Fill the MSS option. See RFC 6691 for an explanation of this calculation.
             let max_segment_size = cx.ip_mtu() - ip_repr.header_len() - TCP_HEADER_LEN;
@@ -2198,16 +2858,21 @@ impl<'a> Socket<'a> {
         }
 
         // We've sent a packet successfully, so we can update the internal state now.
-        self.remote_last_seq = repr.seq_number + repr.segment_len();
+        let segment_len = repr.segment_len();
+        self.remote_last_seq = repr.seq_number + segment_len;
         self.remote_last_ack = repr.ack_number;
         self.remote_last_win = repr.window_len;
 
-        if repr.segment_len() > 0 {
-            self.rtte
-                .on_send(cx.now(), repr.seq_number + repr.segment_len());
+        if segment_len > 0 {
+            self.rtte.on_send(cx.now(), self.remote_last_seq);
+        }
+
+        if self.remote_max_seq < self.remote_last_seq {
+            self.remote_max_seq = self.remote_last_seq;
         }
+        self.skip_sacked();
 
-        if !self.seq_to_transmit(cx) && repr.segment_len() > 0 {
+        if !self.seq_to_transmit(cx) && segment_len > 0 {
             // If we've transmitted all data we could (and there was something at all,
             // data or flag, to transmit, not just an ACK), wind up the retransmit timer.
             self.timer
@@ -2280,6 +2945,7 @@ mod test {
     use super::*;
     use crate::wire::IpRepr;
     use crate::Error;
+    use core::cell::Cell;
     use core::i32;
     use std::ops::{Deref, DerefMut};
     use std::vec::Vec;
@@ -2357,6 +3023,8 @@ mod test {
         max_seg_size: None,
         sack_permitted: false,
         sack_ranges: [None, None, None],
+        timestamp: None,
+        fast_open_cookie: None,
         payload: &[],
     };
     const _RECV_IP_TEMPL: IpRepr = IpReprIpvX(IpvXRepr {
@@ -2377,6 +3045,8 @@ mod test {
         max_seg_size: None,
         sack_permitted: false,
         sack_ranges: [None, None, None],
+        timestamp: None,
+        fast_open_cookie: None,
         payload: &[],
     };
 
@@ -3284,6 +3954,41 @@ mod test {
             }
         );
         assert_eq!(s.state, State::Closed);
+        assert_eq!(s.error(), Some(AbortError::Refused));
+    }
+
+    #[test]
+    fn test_syn_sent_icmp_error() {
+        let mut s = socket_syn_sent();
+        let other_end = IpEndpoint::new(REMOTE_ADDR.into(), REMOTE_PORT + 1);
+        assert!(!s.process_error(LOCAL_END, other_end, LOCAL_SEQ, AbortError::Unreachable));
+
+        // An error that doesn't quote our SYN is ignored.
+        assert!(s.process_error(
+            LOCAL_END,
+            REMOTE_END,
+            LOCAL_SEQ + 1,
+            AbortError::Unreachable
+        ));
+        assert_eq!(s.state, State::SynSent);
+        assert_eq!(s.error(), None);
+
+        assert!(s.process_error(LOCAL_END, REMOTE_END, LOCAL_SEQ, AbortError::Unreachable));
+        assert_eq!(s.state, State::Closed);
+        assert_eq!(s.error(), Some(AbortError::Unreachable));
+        // The connection attempt is aborted without sending a reset.
+        recv_nothing!(s);
+
+        s.listen(LOCAL_END).unwrap();
+        assert_eq!(s.error(), None);
+    }
+
+    #[test]
+    fn test_established_icmp_error() {
+        let mut s = socket_established();
+        assert!(s.process_error(LOCAL_END, REMOTE_END, LOCAL_SEQ + 1, AbortError::Refused));
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.error(), None);
     }
 
     #[test]
@@ -5646,64 +6351,1270 @@ mod test {
     }
 
     // =========================================================================================//
-    // Tests for window management.
+    // Tests for selective acknowledgement on the sending side.
     // =========================================================================================//
 
-    #[test]
-    fn test_maximum_segment_size() {
-        let mut s = socket_listen();
-        s.tx_buffer = SocketBuffer::new(vec![0; 32767]);
-        send!(
-            s,
-            TcpRepr {
-                control: TcpControl::Syn,
-                seq_number: REMOTE_SEQ,
-                ack_number: None,
-                max_seg_size: Some(1000),
-                ..SEND_TEMPL
-            }
-        );
-        recv!(
-            s,
-            [TcpRepr {
-                control: TcpControl::Syn,
-                seq_number: LOCAL_SEQ,
-                ack_number: Some(REMOTE_SEQ + 1),
-                max_seg_size: Some(BASE_MSS),
-                ..RECV_TEMPL
-            }]
-        );
-        send!(
-            s,
-            TcpRepr {
-                seq_number: REMOTE_SEQ + 1,
-                ack_number: Some(LOCAL_SEQ + 1),
-                window_len: 32767,
-                ..SEND_TEMPL
-            }
-        );
-        s.send_slice(&[0; 1200][..]).unwrap();
-        recv!(
-            s,
-            Ok(TcpRepr {
-                seq_number: LOCAL_SEQ + 1,
+    fn sack_range(start: usize, end: usize) -> Option<(u32, u32)> {
+        Some((
+            (LOCAL_SEQ + 1 + start).0 as u32,
+            (LOCAL_SEQ + 1 + end).0 as u32,
+        ))
+    }
+
+    fn socket_established_with_sack() -> TestSocket {
+        let mut s = socket_established();
+        s.remote_has_sack = true;
+        s.remote_mss = 6;
+        s.send_slice(b"xxxxxxyyyyyywwwwwwzzzzzz").unwrap();
+        for (i, payload) in [b"xxxxxx", b"yyyyyy", b"wwwwww", b"zzzzzz"]
+            .iter()
+            .enumerate()
+        {
+            recv!(s, time 1000, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
                 ack_number: Some(REMOTE_SEQ + 1),
-                payload: &[0; 1000][..],
+                payload: &payload[..],
                 ..RECV_TEMPL
-            })
-        );
+            }));
+        }
+        s
     }
 
     #[test]
-    fn test_close_wait_no_window_update() {
-        let mut s = socket_established();
-        send!(
-            s,
-            TcpRepr {
-                control: TcpControl::Fin,
-                seq_number: REMOTE_SEQ + 1,
-                ack_number: Some(LOCAL_SEQ + 1),
-                payload: &[1, 2, 3, 4],
+    fn test_sack_negotiation_connect() {
+        for &sack_permitted in &[false, true] {
+            let mut s = socket_syn_sent();
+            s.remote_last_seq = LOCAL_SEQ + 1;
+            send!(
+                s,
+                TcpRepr {
+                    control: TcpControl::Syn,
+                    seq_number: REMOTE_SEQ,
+                    ack_number: Some(LOCAL_SEQ + 1),
+                    max_seg_size: Some(BASE_MSS),
+                    sack_permitted,
+                    ..SEND_TEMPL
+                }
+            );
+            assert_eq!(s.state, State::Established);
+            assert_eq!(s.remote_has_sack, sack_permitted);
+        }
+    }
+
+    #[test]
+    fn test_sack_fast_retransmit_holes_only() {
+        let mut s = socket_established_with_sack();
+
+        // The first and third segments are lost.
+        send!(s, time 1045, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            sack_ranges: [sack_range(6, 12), None, None],
+            ..SEND_TEMPL
+        });
+        for &time in &[1050, 1055, 1060] {
+            send!(s, time time, TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                sack_ranges: [sack_range(18, 24), sack_range(6, 12), None],
+                ..SEND_TEMPL
+            });
+        }
+
+        // Only the holes are retransmitted.
+        recv!(s, time 1100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"xxxxxx"[..],
+            ..RECV_TEMPL
+        }));
+        recv!(s, time 1105, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 12,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"wwwwww"[..],
+            ..RECV_TEMPL
+        }));
+        recv_nothing!(s, time 1110);
+        assert!(s.timer.is_retransmit());
+
+        send!(s, time 1120, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 24),
+            ..SEND_TEMPL
+        });
+        assert!(s.scoreboard.is_empty());
+        assert_eq!(s.tx_buffer.len(), 0);
+    }
+
+    #[test]
+    fn test_sack_partial_ack() {
+        let mut s = socket_established_with_sack();
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            sack_ranges: [sack_range(18, 24), None, None],
+            ..SEND_TEMPL
+        });
+        assert_eq!(
+            s.scoreboard
+                .next_hole(LOCAL_SEQ + 1 + 6, LOCAL_SEQ + 1 + 24),
+            LOCAL_SEQ + 1 + 6
+        );
+        assert_eq!(s.scoreboard.hole_len(LOCAL_SEQ + 1 + 6), 12);
+
+        // The cumulative ACK covers the scoreboard.
+        send!(s, time 1060, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 24),
+            ..SEND_TEMPL
+        });
+        assert!(s.scoreboard.is_empty());
+    }
+
+    #[test]
+    fn test_sack_bogus_ranges() {
+        let mut s = socket_established_with_sack();
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            // Already acknowledged, not sent yet, and reversed.
+            sack_ranges: [sack_range(0, 6), sack_range(18, 30), sack_range(18, 12)],
+            ..SEND_TEMPL
+        });
+        assert!(s.scoreboard.is_empty());
+    }
+
+    #[test]
+    fn test_sack_ignored_without_negotiation() {
+        let mut s = socket_established_with_sack();
+        s.remote_has_sack = false;
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            sack_ranges: [sack_range(6, 12), None, None],
+            ..SEND_TEMPL
+        });
+        assert!(s.scoreboard.is_empty());
+    }
+
+    #[test]
+    fn test_sack_retransmit_timeout_retransmits_all() {
+        let mut s = socket_established_with_sack();
+        send!(s, time 1050, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            sack_ranges: [sack_range(6, 24), None, None],
+            ..SEND_TEMPL
+        });
+
+        // After a timeout, previously selectively acknowledged data is sent again,
+        // since the remote may have discarded it.
+        for (i, payload) in [b"xxxxxx", b"yyyyyy", b"wwwwww", b"zzzzzz"]
+            .iter()
+            .enumerate()
+        {
+            recv!(s, time 3000, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &payload[..],
+                ..RECV_TEMPL
+            }));
+        }
+        assert!(s.scoreboard.is_empty());
+    }
+
+    // =========================================================================================//
+    // Tests for congestion control.
+    // =========================================================================================//
+
+    fn socket_established_with_congestion_control(cc: CongestionControl) -> TestSocket {
+        let mut s = socket_established_with_buffer_sizes(256, 64);
+        s.remote_mss = 6;
+        s.set_congestion_control(cc);
+        s
+    }
+
+    fn congestion_window(s: &TestSocket) -> usize {
+        s.congestion_controller.inner().window()
+    }
+
+    #[test]
+    fn test_congestion_control_default() {
+        let s = socket();
+        assert_eq!(s.congestion_control(), CongestionControl::None);
+    }
+
+    #[test]
+    fn test_congestion_control_survives_reset() {
+        let mut s = socket();
+        s.set_congestion_control(CongestionControl::Cubic);
+        s.listen(LOCAL_END).unwrap();
+        s.abort();
+        s.listen(LOCAL_END).unwrap();
+        assert_eq!(s.congestion_control(), CongestionControl::Cubic);
+    }
+
+    #[derive(Debug)]
+    struct FixedWindow {
+        acked: &'static Cell<usize>,
+        resets: &'static Cell<usize>,
+    }
+
+    impl CongestionController for FixedWindow {
+        fn window(&self) -> usize {
+            12
+        }
+
+        fn on_ack(&mut self, _now: Instant, len: usize, _rtt: Duration) {
+            self.acked.set(self.acked.get() + len);
+        }
+
+        fn reset(&mut self) {
+            self.resets.set(self.resets.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_congestion_control_custom() {
+        let mut s = socket_established_with_buffer_sizes(256, 64);
+        s.remote_mss = 6;
+        let acked: &'static Cell<usize> = Box::leak(Box::default());
+        let resets: &'static Cell<usize> = Box::leak(Box::default());
+        s.set_congestion_controller(Box::leak(Box::new(FixedWindow { acked, resets })));
+        assert_eq!(s.congestion_control(), CongestionControl::Custom);
+
+        // Only as much data as the controller allows is in flight.
+        let data = b"abcdefghijklmnopqrstuvwx";
+        s.send_slice(&data[..]).unwrap();
+        for i in 0..2 {
+            recv!(s, time 0, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i * 6..i * 6 + 6],
+                ..RECV_TEMPL
+            }));
+        }
+        recv_nothing!(s, time 0);
+
+        send!(s, time 10, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!(acked.get(), 6);
+        recv!(s, time 10, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1 + 12,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &data[12..18],
+            ..RECV_TEMPL
+        }));
+        recv_nothing!(s, time 10);
+
+        // The controller is kept, and told to start over, when the socket is reused.
+        s.abort();
+        s.listen(LOCAL_END).unwrap();
+        assert_eq!(s.congestion_control(), CongestionControl::Custom);
+        assert!(resets.get() > 0);
+    }
+
+    #[test]
+    fn test_congestion_control_initial_window_from_mss() {
+        let mut s = socket_syn_sent();
+        s.set_congestion_control(CongestionControl::Reno);
+        recv!(s, time 0, Ok(TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            window_scale: Some(0),
+            sack_permitted: true,
+            ..RECV_TEMPL
+        }));
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1),
+                max_seg_size: Some(BASE_MSS - 80),
+                window_scale: Some(0),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+        // RFC 5681: the initial window is 3 segments for an MSS between 1095 and 2190 octets.
+        assert_eq!(congestion_window(&s), 3 * (BASE_MSS as usize - 80));
+    }
+
+    #[test]
+    fn test_congestion_control_slow_start() {
+        let mut s = socket_established_with_congestion_control(CongestionControl::Reno);
+        let data = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        s.send_slice(&data[..]).unwrap();
+
+        // The initial window is 4 segments, even though the remote window is much larger.
+        assert_eq!(congestion_window(&s), 24);
+        for i in 0..4 {
+            recv!(s, time 0, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i * 6..i * 6 + 6],
+                ..RECV_TEMPL
+            }));
+        }
+        recv_nothing!(s, time 0);
+
+        // Every ACK grows the window by one segment, so one segment acknowledged
+        // allows two more to be sent.
+        send!(s, time 10, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 30);
+        for i in 4..6 {
+            recv!(s, time 10, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i * 6..i * 6 + 6],
+                ..RECV_TEMPL
+            }));
+        }
+        recv_nothing!(s, time 10);
+
+        // A stretch ACK still only grows the window by one segment.
+        send!(s, time 20, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6 * 4),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 36);
+    }
+
+    #[test]
+    fn test_congestion_control_retransmit_timeout() {
+        let mut s = socket_established_with_congestion_control(CongestionControl::Reno);
+        let data = b"abcdefghijklmnopqrstuvwx";
+        s.send_slice(&data[..]).unwrap();
+        for i in 0..4 {
+            recv!(s, time 0, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i * 6..i * 6 + 6],
+                ..RECV_TEMPL
+            }));
+        }
+
+        // After a timeout, the window collapses to a single segment.
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &data[0..6],
+            ..RECV_TEMPL
+        }));
+        recv_nothing!(s, time 1000);
+        assert_eq!(congestion_window(&s), 6);
+
+        // Slow start up to half of the data that was in flight...
+        send!(s, time 1010, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 12);
+        for i in 1..3 {
+            recv!(s, time 1010, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i * 6..i * 6 + 6],
+                ..RECV_TEMPL
+            }));
+        }
+        recv_nothing!(s, time 1010);
+    }
+
+    #[test]
+    fn test_congestion_control_congestion_avoidance() {
+        let mut s = socket_established_with_congestion_control(CongestionControl::Reno);
+        let data = b"abcdefghijklmnopqrstuvwx";
+        s.send_slice(&data[..]).unwrap();
+        for i in 0..4 {
+            recv!(s, time 0, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i * 6..i * 6 + 6],
+                ..RECV_TEMPL
+            }));
+        }
+
+        // Time out, which sets the slow start threshold to 12 octets.
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &data[0..6],
+            ..RECV_TEMPL
+        }));
+        send!(s, time 1010, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 6),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 12);
+
+        // Past the threshold, the window only grows by one segment per window's worth
+        // of acknowledged data.
+        send!(s, time 1020, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 12),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 12);
+        send!(s, time 1030, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 18),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 18);
+    }
+
+    fn socket_fast_recovery(cc: CongestionControl) -> TestSocket {
+        let mut s = socket_established_with_congestion_control(cc);
+        send!(s, time 0, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+
+        let data = b"abcdefghijklmnopqrstuvwx";
+        s.send_slice(&data[..]).unwrap();
+        for i in 0..4 {
+            recv!(s, time 0, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i * 6..i * 6 + 6],
+                ..RECV_TEMPL
+            }));
+        }
+
+        // The first segment is lost, and the others trigger duplicate ACKs.
+        for t in 0..3 {
+            assert_eq!(s.recovery_point, None);
+            send!(s, time 10 + t, TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                ..SEND_TEMPL
+            });
+        }
+        assert_eq!(s.recovery_point, Some(LOCAL_SEQ + 1 + 24));
+        s
+    }
+
+    #[test]
+    fn test_congestion_control_fast_recovery() {
+        let mut s = socket_fast_recovery(CongestionControl::Reno);
+        // ssthresh is half the flight size (12), plus the 3 segments that left the network.
+        assert_eq!(congestion_window(&s), 30);
+        recv!(s, time 20, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+
+        // Further duplicate ACKs inflate the window.
+        send!(s, time 30, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 36);
+
+        // A partial ACK deflates it, without leaving fast recovery.
+        send!(s, time 40, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 12),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 30);
+        assert_eq!(s.recovery_point, Some(LOCAL_SEQ + 1 + 24));
+
+        // An ACK of everything that was in flight ends fast recovery.
+        send!(s, time 50, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 24),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 12);
+        assert_eq!(s.recovery_point, None);
+    }
+
+    #[test]
+    fn test_congestion_control_cubic_fast_recovery() {
+        let mut s = socket_fast_recovery(CongestionControl::Cubic);
+        // CUBIC reduces the window by 30% instead of halving it.
+        assert_eq!(congestion_window(&s), 16);
+
+        send!(s, time 50, TcpRepr {
+            seq_number: REMOTE_SEQ + 1,
+            ack_number: Some(LOCAL_SEQ + 1 + 24),
+            ..SEND_TEMPL
+        });
+        assert_eq!(congestion_window(&s), 16);
+        assert_eq!(s.recovery_point, None);
+    }
+
+    // =========================================================================================//
+    // Tests for timestamps.
+    // =========================================================================================//
+
+    fn socket_established_with_timestamps() -> TestSocket {
+        let mut s = socket_established();
+        s.set_timestamps_enabled(true);
+        s.remote_ts_recent = Some((500, Instant::from_millis(0)));
+        s
+    }
+
+    #[test]
+    fn test_timestamps_connect() {
+        let mut s = socket_syn_sent();
+        s.set_timestamps_enabled(true);
+        recv!(s, time 100, Ok(TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            window_scale: Some(0),
+            sack_permitted: true,
+            timestamp: Some(TcpTimestampRepr {
+                tsval: 100,
+                tsecr: 0
+            }),
+            ..RECV_TEMPL
+        }));
+        send!(
+            s,
+            time 150,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 5000,
+                    tsecr: 100
+                }),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+        recv!(s, time 150, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            timestamp: Some(TcpTimestampRepr {
+                tsval: 150,
+                tsecr: 5000
+            }),
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_timestamps_connect_not_offered() {
+        let mut s = socket_syn_sent();
+        s.set_timestamps_enabled(true);
+        s.remote_last_seq = LOCAL_SEQ + 1;
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.remote_ts_recent, None);
+        recv!(
+            s,
+            [TcpRepr {
+                seq_number: LOCAL_SEQ + 1,
+                ack_number: Some(REMOTE_SEQ + 1),
+                ..RECV_TEMPL
+            }]
+        );
+    }
+
+    #[test]
+    fn test_timestamps_listen() {
+        let syn = TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            timestamp: Some(TcpTimestampRepr {
+                tsval: 5000,
+                tsecr: 0,
+            }),
+            ..SEND_TEMPL
+        };
+        let syn_ack = TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: Some(REMOTE_SEQ + 1),
+            max_seg_size: Some(BASE_MSS),
+            ..RECV_TEMPL
+        };
+
+        let mut s = socket_listen();
+        s.set_timestamps_enabled(true);
+        send!(s, syn);
+        assert_eq!(s.remote_ts_recent, Some((5000, Instant::from_millis(0))));
+        recv!(s, time 50, Ok(TcpRepr {
+            timestamp: Some(TcpTimestampRepr {
+                tsval: 50,
+                tsecr: 5000
+            }),
+            ..syn_ack
+        }));
+
+        // The option isn't used unless enabled on our side...
+        let mut s = socket_listen();
+        send!(s, syn);
+        assert_eq!(s.remote_ts_recent, None);
+        recv!(s, time 50, Ok(syn_ack));
+
+        // ...and on the remote side.
+        let mut s = socket_listen();
+        s.set_timestamps_enabled(true);
+        send!(
+            s,
+            TcpRepr {
+                timestamp: None,
+                ..syn
+            }
+        );
+        assert_eq!(s.remote_ts_recent, None);
+        recv!(s, time 50, Ok(syn_ack));
+    }
+
+    #[test]
+    fn test_timestamps_paws() {
+        let mut s = socket_established_with_timestamps();
+        send!(
+            s,
+            time 1000,
+            TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                payload: &b"abcdef"[..],
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 499,
+                    tsecr: 0
+                }),
+                ..SEND_TEMPL
+            },
+            Some(TcpRepr {
+                seq_number: LOCAL_SEQ + 1,
+                ack_number: Some(REMOTE_SEQ + 1),
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 1000,
+                    tsecr: 500
+                }),
+                ..RECV_TEMPL
+            })
+        );
+        assert_eq!(s.rx_buffer.len(), 0);
+
+        send!(
+            s,
+            time 1000,
+            TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                payload: &b"abcdef"[..],
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 501,
+                    tsecr: 0
+                }),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.rx_buffer.len(), 6);
+        assert_eq!(s.remote_ts_recent, Some((501, Instant::from_millis(1000))));
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1 + 6),
+            window_len: 58,
+            timestamp: Some(TcpTimestampRepr {
+                tsval: 1000,
+                tsecr: 501
+            }),
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_timestamps_paws_idle() {
+        let mut s = socket_established_with_timestamps();
+        let now = Instant::from_millis(0) + PAWS_IDLE_TIMEOUT;
+        send!(
+            s,
+            time now.total_millis(),
+            TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                payload: &b"abcdef"[..],
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 499,
+                    tsecr: 0
+                }),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.rx_buffer.len(), 6);
+        assert_eq!(s.remote_ts_recent, Some((499, now)));
+    }
+
+    #[test]
+    fn test_timestamps_missing() {
+        let mut s = socket_established_with_timestamps();
+        send!(
+            s,
+            TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                payload: &b"abcdef"[..],
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.rx_buffer.len(), 0);
+        recv_nothing!(s);
+
+        // RSTs are accepted without the option.
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Rst,
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: None,
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Closed);
+    }
+
+    #[test]
+    fn test_timestamps_rtt_sample_every_ack() {
+        let mut s = socket_established_with_timestamps();
+        s.remote_mss = 3 + TIMESTAMP_OPTION_LEN;
+        s.send_slice(b"abcdef").unwrap();
+        for (i, payload) in [&b"abc"[..], &b"def"[..]].iter().enumerate() {
+            recv!(s, time 1000, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i * 3,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload,
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 1000,
+                    tsecr: 500
+                }),
+                ..RECV_TEMPL
+            }));
+        }
+
+        // Both ACKs echo the timestamp of the first segment, and both yield a sample.
+        for &(time, ack, rtt) in &[(1100, 3, 275), (1150, 6, 259)] {
+            send!(
+                s,
+                time time,
+                TcpRepr {
+                    seq_number: REMOTE_SEQ + 1,
+                    ack_number: Some(LOCAL_SEQ + 1 + ack),
+                    timestamp: Some(TcpTimestampRepr {
+                        tsval: 600,
+                        tsecr: 1000
+                    }),
+                    ..SEND_TEMPL
+                }
+            );
+            assert_eq!(s.rtte.rtt, rtt);
+        }
+    }
+
+    #[test]
+    fn test_timestamps_rtt_sample_mss_1() {
+        let mut s = socket_established_with_timestamps();
+        s.remote_mss = 1 + TIMESTAMP_OPTION_LEN;
+        let data = [0x55; 40];
+        s.send_slice(&data).unwrap();
+        for i in 0..data.len() {
+            recv!(s, time 1000, Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + i,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &data[i..i + 1],
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 1000,
+                    tsecr: 500
+                }),
+                ..RECV_TEMPL
+            }));
+        }
+
+        // With 40 one-byte segments in flight, 20 samples are expected per RTT, more than
+        // the sample weight is divided by.
+        send!(
+            s,
+            time 1100,
+            TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 2),
+                timestamp: Some(TcpTimestampRepr {
+                    tsval: 600,
+                    tsecr: 1000
+                }),
+                ..SEND_TEMPL
+            }
+        );
+        let mut r = RttEstimator::default();
+        r.sample_weighted(100, RTTE_MAX_EXPECTED_SAMPLES);
+        assert_eq!(s.rtte.rtt, r.rtt);
+    }
+
+    #[test]
+    fn test_timestamps_mss() {
+        let mut s = socket_established_with_timestamps();
+        s.remote_mss = 20;
+        s.send_slice(b"abcdefghijklmnop").unwrap();
+        recv!(s, time 1000, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"abcdefgh"[..],
+            timestamp: Some(TcpTimestampRepr {
+                tsval: 1000,
+                tsecr: 500
+            }),
+            ..RECV_TEMPL
+        }));
+    }
+
+    // =========================================================================================//
+    // Tests for TCP Fast Open.
+    // =========================================================================================//
+
+    const TFO_KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const TFO_COOKIE: [u8; 8] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+
+    fn socket_syn_sent_with_fast_open(cookie: Option<&[u8]>) -> TestSocket {
+        let mut s = socket_syn_sent();
+        s.remote_max_seq = LOCAL_SEQ;
+        s.set_fast_open_enabled(true);
+        s.set_fast_open_cookie(cookie.map(|cookie| FastOpenCookie::new(cookie).unwrap()));
+        s
+    }
+
+    fn fast_open_syn<'a>(cookie: Option<&'a [u8]>, payload: &'a [u8]) -> TcpRepr<'a> {
+        TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: REMOTE_SEQ,
+            ack_number: None,
+            fast_open_cookie: cookie,
+            payload,
+            ..SEND_TEMPL
+        }
+    }
+
+    #[test]
+    fn test_fast_open_siphash() {
+        // Test vector from the SipHash paper.
+        let data: [u8; 15] = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e,
+        ];
+        assert_eq!(siphash24(&TFO_KEY, &data), 0xa129ca6149be45e5);
+    }
+
+    #[test]
+    fn test_fast_open_cookie_new() {
+        assert_eq!(FastOpenCookie::new(&[1, 2, 3]), None);
+        assert_eq!(FastOpenCookie::new(&[0; 17]), None);
+        let cookie = FastOpenCookie::new(&TFO_COOKIE).unwrap();
+        assert_eq!(cookie.as_bytes(), &TFO_COOKIE[..]);
+    }
+
+    #[test]
+    fn test_fast_open_connect_request_cookie() {
+        let mut s = socket_syn_sent_with_fast_open(None);
+        assert!(!s.may_send());
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: None,
+                max_seg_size: Some(BASE_MSS),
+                window_scale: Some(0),
+                sack_permitted: true,
+                fast_open_cookie: Some(&[]),
+                ..RECV_TEMPL
+            }]
+        );
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                fast_open_cookie: Some(&TFO_COOKIE),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.fast_open_cookie(), FastOpenCookie::new(&TFO_COOKIE));
+    }
+
+    #[test]
+    fn test_fast_open_connect_disabled() {
+        let mut s = socket_syn_sent();
+        s.set_fast_open_cookie(FastOpenCookie::new(&TFO_COOKIE));
+        assert!(!s.may_send());
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: None,
+                max_seg_size: Some(BASE_MSS),
+                window_scale: Some(0),
+                sack_permitted: true,
+                ..RECV_TEMPL
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fast_open_connect_data_acked() {
+        let mut s = socket_syn_sent_with_fast_open(Some(&TFO_COOKIE));
+        assert!(s.may_send());
+        s.send_slice(b"abcdef").unwrap();
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: None,
+                max_seg_size: Some(BASE_MSS),
+                window_scale: Some(0),
+                sack_permitted: true,
+                fast_open_cookie: Some(&TFO_COOKIE),
+                payload: &b"abcdef"[..],
+                ..RECV_TEMPL
+            }]
+        );
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1 + 6),
+                max_seg_size: Some(BASE_MSS),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+        assert!(s.tx_buffer.is_empty());
+        recv!(
+            s,
+            [TcpRepr {
+                seq_number: LOCAL_SEQ + 1 + 6,
+                ack_number: Some(REMOTE_SEQ + 1),
+                ..RECV_TEMPL
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fast_open_connect_data_not_acked() {
+        let mut s = socket_syn_sent_with_fast_open(Some(&TFO_COOKIE));
+        s.send_slice(b"abcdef").unwrap();
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: None,
+                max_seg_size: Some(BASE_MSS),
+                window_scale: Some(0),
+                sack_permitted: true,
+                fast_open_cookie: Some(&TFO_COOKIE),
+                payload: &b"abcdef"[..],
+                ..RECV_TEMPL
+            }]
+        );
+        // The remote didn't accept our cookie, and gives us a new one.
+        let new_cookie = [0x99; 8];
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                fast_open_cookie: Some(&new_cookie),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+        assert_eq!(s.fast_open_cookie(), FastOpenCookie::new(&new_cookie));
+        recv!(
+            s,
+            [TcpRepr {
+                seq_number: LOCAL_SEQ + 1,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &b"abcdef"[..],
+                ..RECV_TEMPL
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fast_open_connect_ack_too_far() {
+        let mut s = socket_syn_sent_with_fast_open(Some(&TFO_COOKIE));
+        s.send_slice(b"abcdef").unwrap();
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: None,
+                max_seg_size: Some(BASE_MSS),
+                window_scale: Some(0),
+                sack_permitted: true,
+                fast_open_cookie: Some(&TFO_COOKIE),
+                payload: &b"abcdef"[..],
+                ..RECV_TEMPL
+            }]
+        );
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1 + 7),
+                max_seg_size: Some(BASE_MSS),
+                ..SEND_TEMPL
+            },
+            Some(TcpRepr {
+                control: TcpControl::Rst,
+                seq_number: LOCAL_SEQ + 1 + 7,
+                ack_number: None,
+                window_len: 0,
+                ..RECV_TEMPL
+            })
+        );
+        assert_eq!(s.state, State::SynSent);
+    }
+
+    #[test]
+    fn test_fast_open_connect_syn_retransmit() {
+        let mut s = socket_syn_sent_with_fast_open(Some(&TFO_COOKIE));
+        s.send_slice(b"abcdef").unwrap();
+        recv!(s, time 0, Ok(TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            window_scale: Some(0),
+            sack_permitted: true,
+            fast_open_cookie: Some(&TFO_COOKIE),
+            payload: &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+        // Retransmitted SYNs carry neither the cookie nor data.
+        recv!(s, time 1000, Ok(TcpRepr {
+            control: TcpControl::Syn,
+            seq_number: LOCAL_SEQ,
+            ack_number: None,
+            max_seg_size: Some(BASE_MSS),
+            window_scale: Some(0),
+            sack_permitted: true,
+            ..RECV_TEMPL
+        }));
+        send!(
+            s,
+            time 1100,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: Some(LOCAL_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+        recv!(s, time 1100, Ok(TcpRepr {
+            seq_number: LOCAL_SEQ + 1,
+            ack_number: Some(REMOTE_SEQ + 1),
+            payload: &b"abcdef"[..],
+            ..RECV_TEMPL
+        }));
+    }
+
+    #[test]
+    fn test_fast_open_listen_cookie_request() {
+        let cookie = FastOpenCookie::generate(&TFO_KEY, REMOTE_ADDR.into());
+        let mut s = socket_listen();
+        s.set_fast_open_key(Some(TFO_KEY));
+        send!(s, fast_open_syn(Some(&[]), &b"abcdef"[..]));
+        assert_eq!(s.state, State::SynReceived);
+        // Data isn't accepted without a valid cookie.
+        assert!(!s.may_recv());
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: Some(REMOTE_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                fast_open_cookie: Some(cookie.as_bytes()),
+                ..RECV_TEMPL
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fast_open_listen_valid_cookie() {
+        let cookie = FastOpenCookie::generate(&TFO_KEY, REMOTE_ADDR.into());
+        let mut s = socket_listen();
+        s.set_fast_open_key(Some(TFO_KEY));
+        send!(s, fast_open_syn(Some(cookie.as_bytes()), &b"abcdef"[..]));
+        assert_eq!(s.state, State::SynReceived);
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: Some(REMOTE_SEQ + 1 + 6),
+                max_seg_size: Some(BASE_MSS),
+                window_len: 58,
+                ..RECV_TEMPL
+            }]
+        );
+        // The data is readable before the handshake completes.
+        let mut buffer = [0; 6];
+        assert_eq!(s.recv_slice(&mut buffer), Ok(6));
+        assert_eq!(&buffer, b"abcdef");
+        send!(
+            s,
+            TcpRepr {
+                seq_number: REMOTE_SEQ + 1 + 6,
+                ack_number: Some(LOCAL_SEQ + 1),
+                ..SEND_TEMPL
+            }
+        );
+        assert_eq!(s.state, State::Established);
+    }
+
+    #[test]
+    fn test_fast_open_listen_invalid_cookie() {
+        let cookie = FastOpenCookie::generate(&TFO_KEY, REMOTE_ADDR.into());
+        let mut s = socket_listen();
+        s.set_fast_open_key(Some(TFO_KEY));
+        send!(s, fast_open_syn(Some(&TFO_COOKIE), &b"abcdef"[..]));
+        assert!(!s.may_recv());
+        // The remote gets a valid cookie for its next connection.
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: Some(REMOTE_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                fast_open_cookie: Some(cookie.as_bytes()),
+                ..RECV_TEMPL
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fast_open_listen_no_cookie() {
+        let mut s = socket_listen();
+        s.set_fast_open_key(Some(TFO_KEY));
+        send!(s, fast_open_syn(None, &b"abcdef"[..]));
+        assert!(!s.may_recv());
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: Some(REMOTE_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                ..RECV_TEMPL
+            }]
+        );
+    }
+
+    // =========================================================================================//
+    // Tests for window management.
+    // =========================================================================================//
+
+    #[test]
+    fn test_maximum_segment_size() {
+        let mut s = socket_listen();
+        s.tx_buffer = SocketBuffer::new(vec![0; 32767]);
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: REMOTE_SEQ,
+                ack_number: None,
+                max_seg_size: Some(1000),
+                ..SEND_TEMPL
+            }
+        );
+        recv!(
+            s,
+            [TcpRepr {
+                control: TcpControl::Syn,
+                seq_number: LOCAL_SEQ,
+                ack_number: Some(REMOTE_SEQ + 1),
+                max_seg_size: Some(BASE_MSS),
+                ..RECV_TEMPL
+            }]
+        );
+        send!(
+            s,
+            TcpRepr {
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                window_len: 32767,
+                ..SEND_TEMPL
+            }
+        );
+        s.send_slice(&[0; 1200][..]).unwrap();
+        recv!(
+            s,
+            Ok(TcpRepr {
+                seq_number: LOCAL_SEQ + 1,
+                ack_number: Some(REMOTE_SEQ + 1),
+                payload: &[0; 1000][..],
+                ..RECV_TEMPL
+            })
+        );
+    }
+
+    #[test]
+    fn test_close_wait_no_window_update() {
+        let mut s = socket_established();
+        send!(
+            s,
+            TcpRepr {
+                control: TcpControl::Fin,
+                seq_number: REMOTE_SEQ + 1,
+                ack_number: Some(LOCAL_SEQ + 1),
+                payload: &[1, 2, 3, 4],
                 ..SEND_TEMPL
             }
         );
@@ -6974,8 +8885,8 @@ mod test {
         let mut r = RttEstimator::default();
 
         let rtos = &[
-            751, 766, 755, 731, 697, 656, 613, 567, 523, 484, 445, 411, 378, 350, 322, 299, 280,
-            261, 243, 229, 215, 206, 197, 188,
+            751, 765, 754, 725, 686, 641, 594, 548, 504, 465, 426, 392, 359, 331, 303, 280, 261,
+            242, 224, 210, 196, 187, 178, 169,
         ];
 
         for &rto in rtos {
@@ -6983,4 +8894,50 @@ mod test {
             assert_eq!(r.retransmission_timeout(), Duration::from_millis(rto));
         }
     }
+
+    #[test]
+    fn test_rtt_estimator_weighted_sample() {
+        let mut r = RttEstimator::default();
+        r.sample_weighted(100, 1);
+        assert_eq!(r.rtt, 275);
+
+        // With 4 samples expected per RTT, each sample has a quarter of the weight.
+        let mut r = RttEstimator::default();
+        r.sample_weighted(100, 4);
+        assert_eq!(r.rtt, 294);
+    }
+
+    #[test]
+    fn test_rtt_estimator_decreasing_samples() {
+        // The estimate converges to within half a gain step of the samples, from above as
+        // from below.
+        for &expected_samples in &[1, 4, RTTE_MAX_EXPECTED_SAMPLES] {
+            let mut r = RttEstimator::default();
+            for _ in 0..1000 {
+                r.sample_weighted(100, expected_samples);
+            }
+            assert!(r.rtt <= 100 + 4 * expected_samples, "{}", r.rtt);
+
+            r.rtt = 20;
+            for _ in 0..1000 {
+                r.sample_weighted(100, expected_samples);
+            }
+            assert!(r.rtt >= 100 - 4 * expected_samples, "{}", r.rtt);
+        }
+    }
+
+    #[test]
+    fn test_rtt_estimator_expected_samples_clamped() {
+        let mut r = RttEstimator::default();
+        r.sample_weighted(100, RTTE_MAX_EXPECTED_SAMPLES);
+        let mut clamped = RttEstimator::default();
+        clamped.sample_weighted(100, u32::MAX);
+        assert_eq!(clamped.rtt, r.rtt);
+        assert_eq!(clamped.deviation, r.deviation);
+
+        let mut r = RttEstimator::default();
+        r.rtt = RTTE_MAX_RTO;
+        r.sample_weighted(RTTE_MAX_RTO, u32::MAX);
+        assert_eq!(r.rtt, RTTE_MAX_RTO);
+    }
 }
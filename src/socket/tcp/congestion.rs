@@ -0,0 +1,203 @@
+// Congestion control for the TCP socket. See RFC 5681 for the general framework,
+// RFC 6582 for NewReno loss recovery and RFC 8312 for CUBIC.
+
+use core::fmt;
+
+use crate::time::{Duration, Instant};
+
+mod cubic;
+mod reno;
+
+pub(super) use self::cubic::Cubic;
+pub(super) use self::reno::Reno;
+
+/// The congestion control algorithm used by a TCP socket.
+///
+/// See also the [set_congestion_control](struct.Socket.html#method.set_congestion_control)
+/// method.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CongestionControl {
+    /// No congestion control; the socket sends as much as the remote window allows.
+    #[default]
+    None,
+    /// NewReno, as described in [RFC 5681] and [RFC 6582].
+    ///
+    /// [RFC 5681]: https://tools.ietf.org/html/rfc5681
+    /// [RFC 6582]: https://tools.ietf.org/html/rfc6582
+    Reno,
+    /// CUBIC, as described in [RFC 8312].
+    ///
+    /// [RFC 8312]: https://tools.ietf.org/html/rfc8312
+    Cubic,
+    /// A controller supplied by the application with
+    /// [set_congestion_controller](struct.Socket.html#method.set_congestion_controller).
+    ///
+    /// Passing this to `set_congestion_control` disables congestion control, as there is
+    /// no controller to select.
+    Custom,
+}
+
+/// A congestion controller.
+///
+/// The socket reports acknowledgements and loss events to the controller, and the controller
+/// answers with the congestion window, i.e. the amount of unacknowledged octets the socket
+/// may have in flight.
+///
+/// Implement this trait to use an algorithm other than the built-in ones, see the
+/// [set_congestion_controller](struct.Socket.html#method.set_congestion_controller) method.
+pub trait CongestionController {
+    /// Return the congestion window, in octets.
+    fn window(&self) -> usize;
+
+    /// Set the sender maximum segment size. Called once the MSS is known, before any data
+    /// has been sent, which also resets the congestion window to the initial window.
+    fn set_mss(&mut self, _mss: usize) {}
+
+    /// Set the remote receive window. The congestion window isn't grown past the largest
+    /// window the remote has advertised, since it couldn't be used anyway.
+    fn set_remote_window(&mut self, _remote_window: usize) {}
+
+    /// Called when an ACK acknowledges `len` octets of new data.
+    /// `rtt` is the current smoothed round-trip time estimate.
+    fn on_ack(&mut self, _now: Instant, _len: usize, _rtt: Duration) {}
+
+    /// Called on every duplicate ACK received after entering fast recovery.
+    fn on_duplicate_ack(&mut self, _now: Instant) {}
+
+    /// Called when the third duplicate ACK triggers a fast retransmit, entering fast recovery.
+    /// `flight_size` is the amount of outstanding data at that point.
+    fn on_fast_retransmit(&mut self, _now: Instant, _flight_size: usize) {}
+
+    /// Called when an ACK covers all data that was outstanding when fast recovery was
+    /// entered, leaving it.
+    fn on_recovery_exit(&mut self, _now: Instant) {}
+
+    /// Called when the retransmission timer expires.
+    /// `flight_size` is the amount of outstanding data at that point.
+    fn on_retransmit_timeout(&mut self, _now: Instant, _flight_size: usize) {}
+
+    /// Called when the socket is reset, before it is reused for a new connection.
+    /// The controller should forget everything it learned about the previous path.
+    fn reset(&mut self) {}
+}
+
+/// A controller that never limits the socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) struct NoControl;
+
+impl CongestionController for NoControl {
+    fn window(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// Enum dispatch over the built-in controllers, so that sockets don't need to be
+/// generic over the algorithm or allocate it on the heap. A user-supplied controller is
+/// borrowed for the lifetime of the socket, like its buffers.
+pub(super) enum AnyController<'a> {
+    None(NoControl),
+    Reno(Reno),
+    Cubic(Cubic),
+    Custom(&'a mut dyn CongestionController),
+}
+
+impl<'a> AnyController<'a> {
+    pub(super) fn new(algorithm: CongestionControl) -> Self {
+        match algorithm {
+            CongestionControl::None | CongestionControl::Custom => AnyController::None(NoControl),
+            CongestionControl::Reno => AnyController::Reno(Reno::new()),
+            CongestionControl::Cubic => AnyController::Cubic(Cubic::new()),
+        }
+    }
+
+    pub(super) fn algorithm(&self) -> CongestionControl {
+        match self {
+            AnyController::None(_) => CongestionControl::None,
+            AnyController::Reno(_) => CongestionControl::Reno,
+            AnyController::Cubic(_) => CongestionControl::Cubic,
+            AnyController::Custom(_) => CongestionControl::Custom,
+        }
+    }
+
+    /// Restart congestion control from scratch, keeping the algorithm.
+    pub(super) fn reset(&mut self) {
+        match self {
+            AnyController::Custom(c) => c.reset(),
+            _ => *self = AnyController::new(self.algorithm()),
+        }
+    }
+
+    pub(super) fn inner(&self) -> &dyn CongestionController {
+        match self {
+            AnyController::None(c) => c,
+            AnyController::Reno(c) => c,
+            AnyController::Cubic(c) => c,
+            AnyController::Custom(c) => *c,
+        }
+    }
+
+    pub(super) fn inner_mut(&mut self) -> &mut dyn CongestionController {
+        match self {
+            AnyController::None(c) => c,
+            AnyController::Reno(c) => c,
+            AnyController::Cubic(c) => c,
+            AnyController::Custom(c) => *c,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for AnyController<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnyController::None(c) => f.debug_tuple("None").field(c).finish(),
+            AnyController::Reno(c) => f.debug_tuple("Reno").field(c).finish(),
+            AnyController::Cubic(c) => f.debug_tuple("Cubic").field(c).finish(),
+            AnyController::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// Return the initial congestion window for the given sender MSS, per [RFC 5681 § 3.1].
+///
+/// [RFC 5681 § 3.1]: https://tools.ietf.org/html/rfc5681#section-3.1
+fn initial_window(mss: usize) -> usize {
+    if mss > 2190 {
+        2 * mss
+    } else if mss > 1095 {
+        3 * mss
+    } else {
+        4 * mss
+    }
+}
+
+/// Return the slow start threshold after a loss event, per [RFC 5681 § 3.1].
+///
+/// [RFC 5681 § 3.1]: https://tools.ietf.org/html/rfc5681#section-3.1
+fn loss_threshold(flight_size: usize, mss: usize) -> usize {
+    (flight_size / 2).max(2 * mss)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_initial_window() {
+        assert_eq!(initial_window(536), 4 * 536);
+        assert_eq!(initial_window(1460), 3 * 1460);
+        assert_eq!(initial_window(4000), 2 * 4000);
+    }
+
+    #[test]
+    fn test_no_control() {
+        let mut c = AnyController::new(CongestionControl::None);
+        c.inner_mut()
+            .on_fast_retransmit(Instant::from_millis(0), 10000);
+        c.inner_mut()
+            .on_retransmit_timeout(Instant::from_millis(0), 10000);
+        assert_eq!(c.inner().window(), usize::MAX);
+        assert_eq!(c.algorithm(), CongestionControl::None);
+    }
+}
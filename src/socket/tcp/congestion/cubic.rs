@@ -0,0 +1,300 @@
+use crate::socket::tcp::DEFAULT_MSS;
+use crate::time::{Duration, Instant};
+
+use super::{initial_window, CongestionController};
+
+// Constants from RFC 8312 § 5.
+const BETA_CUBIC: f64 = 0.7;
+const C_CUBIC: f64 = 0.4;
+
+/// CUBIC congestion control, as described in [RFC 8312].
+///
+/// Slow start is the same as in NewReno. During congestion avoidance, the window follows
+/// a cubic function of the time elapsed since the last congestion event, which quickly
+/// returns to the window size where that event happened, then probes beyond it slowly at first
+/// and more aggressively as time goes on. Where standard TCP would grow faster (short RTTs,
+/// small windows) the window follows the standard TCP estimate instead.
+///
+/// [RFC 8312]: https://tools.ietf.org/html/rfc8312
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Cubic {
+    /// Congestion window, in octets.
+    cwnd: usize,
+    /// Slow start threshold, in octets.
+    ssthresh: usize,
+    /// Sender maximum segment size.
+    mss: usize,
+    /// Largest receive window advertised by the remote.
+    rwnd: usize,
+    /// Window just before the last reduction, in octets.
+    w_max: usize,
+    /// Value of `w_max` before the last reduction, used for fast convergence.
+    w_last_max: usize,
+    /// Start of the current congestion avoidance epoch, if one has started.
+    epoch_start: Option<Instant>,
+    /// Window the cubic function is centered on in the current epoch, in octets.
+    origin_point: usize,
+    /// Time the cubic function takes to reach `origin_point`, in seconds.
+    k: f64,
+    /// Estimate of the window standard TCP would have in the current epoch, in octets.
+    w_est: usize,
+    /// Whether we're in fast recovery.
+    in_recovery: bool,
+}
+
+impl Cubic {
+    pub fn new() -> Cubic {
+        Cubic {
+            cwnd: initial_window(DEFAULT_MSS),
+            ssthresh: usize::MAX,
+            mss: DEFAULT_MSS,
+            rwnd: 0,
+            w_max: 0,
+            w_last_max: 0,
+            epoch_start: None,
+            origin_point: 0,
+            k: 0.0,
+            w_est: 0,
+            in_recovery: false,
+        }
+    }
+
+    /// Multiplicative decrease, common to all congestion events.
+    fn reduce(&mut self) {
+        // RFC 8312 § 4.6: fast convergence. If the window at this congestion event is below
+        // the one at the previous event, another flow is likely taking bandwidth, so give
+        // up some more to let it converge faster.
+        if self.cwnd < self.w_last_max {
+            self.w_last_max = self.cwnd;
+            self.w_max = (self.cwnd as f64 * (1.0 + BETA_CUBIC) / 2.0) as usize;
+        } else {
+            self.w_last_max = self.cwnd;
+            self.w_max = self.cwnd;
+        }
+
+        self.ssthresh = ((self.cwnd as f64 * BETA_CUBIC) as usize).max(2 * self.mss);
+        self.epoch_start = None;
+    }
+
+    /// Window the cubic function predicts at `t` seconds into the current epoch, in octets.
+    fn w_cubic(&self, t: f64) -> f64 {
+        let d = t - self.k;
+        C_CUBIC * d * d * d * self.mss as f64 + self.origin_point as f64
+    }
+}
+
+impl CongestionController for Cubic {
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+        self.cwnd = initial_window(mss);
+    }
+
+    fn set_remote_window(&mut self, remote_window: usize) {
+        self.rwnd = self.rwnd.max(remote_window);
+    }
+
+    fn on_ack(&mut self, now: Instant, len: usize, rtt: Duration) {
+        if self.in_recovery {
+            return;
+        }
+
+        // There's no point in opening the window past what the remote accepts.
+        if self.cwnd >= self.rwnd {
+            return;
+        }
+
+        if self.cwnd < self.ssthresh {
+            // Slow start: grow by at most one MSS per ACK.
+            self.cwnd += len.min(self.mss);
+            net_trace!("cubic: slow start, cwnd={}", self.cwnd);
+            return;
+        }
+
+        let epoch_start = match self.epoch_start {
+            Some(epoch_start) => epoch_start,
+            None => {
+                // RFC 8312 § 4.1: a new epoch starts with the first ACK in congestion avoidance.
+                if self.cwnd < self.w_max {
+                    let segments = (self.w_max - self.cwnd) as f64 / self.mss as f64;
+                    self.k = cube_root(segments / C_CUBIC);
+                    self.origin_point = self.w_max;
+                } else {
+                    self.k = 0.0;
+                    self.origin_point = self.cwnd;
+                }
+                self.w_est = self.cwnd;
+                self.epoch_start = Some(now);
+                now
+            }
+        };
+
+        // RFC 8312 § 4.2: standard TCP grows by `alpha` segments per RTT, chosen so that
+        // both achieve the same average window given CUBIC's smaller decrease.
+        let alpha = 3.0 * (1.0 - BETA_CUBIC) / (1.0 + BETA_CUBIC);
+        self.w_est += (alpha * self.mss as f64 * len as f64 / self.cwnd as f64) as usize;
+
+        // RFC 8312 § 4.3 and 4.4: aim for the window the cubic function predicts one RTT
+        // from now, but never more than 1.5 times the current window.
+        let rtt = rtt.total_millis() as f64 / 1000.0;
+        let t = (now - epoch_start).total_millis() as f64 / 1000.0;
+        let target = self
+            .w_cubic(t + rtt)
+            .min(self.cwnd as f64 * 1.5)
+            .max(self.cwnd as f64) as usize;
+        let increment = (target - self.cwnd) * len / self.cwnd;
+
+        self.cwnd = (self.cwnd + increment).max(self.w_est);
+        net_trace!("cubic: congestion avoidance, cwnd={}", self.cwnd);
+    }
+
+    fn on_fast_retransmit(&mut self, _now: Instant, _flight_size: usize) {
+        self.reduce();
+        self.cwnd = self.ssthresh;
+        self.in_recovery = true;
+        net_trace!(
+            "cubic: fast recovery, ssthresh={} cwnd={}",
+            self.ssthresh,
+            self.cwnd
+        );
+    }
+
+    fn on_recovery_exit(&mut self, _now: Instant) {
+        self.in_recovery = false;
+        net_trace!("cubic: recovered, cwnd={}", self.cwnd);
+    }
+
+    fn on_retransmit_timeout(&mut self, _now: Instant, _flight_size: usize) {
+        self.reduce();
+        self.cwnd = self.mss;
+        self.in_recovery = false;
+        net_trace!(
+            "cubic: retransmit timeout, ssthresh={} cwnd={}",
+            self.ssthresh,
+            self.cwnd
+        );
+    }
+}
+
+/// Compute the cube root of a non-negative number with Newton's method,
+/// since `f64::cbrt` isn't available without `std`.
+fn cube_root(a: f64) -> f64 {
+    if a <= 0.0 {
+        return 0.0;
+    }
+
+    // Newton's method converges from above, so start from a guess that's no smaller
+    // than the root.
+    let mut x = if a > 1.0 { a / 3.0 + 1.0 } else { 1.0 };
+    for _ in 0..128 {
+        let next = (2.0 * x + a / (x * x)) / 3.0;
+        if x - next <= 1e-9 * next {
+            return next;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cubic() -> Cubic {
+        let mut cubic = Cubic::new();
+        cubic.set_mss(1000);
+        cubic.set_remote_window(usize::MAX);
+        cubic
+    }
+
+    #[test]
+    fn test_cube_root() {
+        for &(a, r) in &[
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (8.0, 2.0),
+            (0.125, 0.5),
+            (1e9, 1000.0),
+        ] {
+            assert!((cube_root(a) - r).abs() < 1e-6, "cube_root({}) != {}", a, r);
+        }
+    }
+
+    #[test]
+    fn test_slow_start() {
+        let mut cubic = cubic();
+        let rtt = Duration::from_millis(300);
+        assert_eq!(cubic.window(), 4000);
+        cubic.on_ack(Instant::from_millis(0), 1000, rtt);
+        cubic.on_ack(Instant::from_millis(0), 1000, rtt);
+        assert_eq!(cubic.window(), 6000);
+    }
+
+    #[test]
+    fn test_fast_recovery() {
+        let mut cubic = cubic();
+        let rtt = Duration::from_millis(300);
+        cubic.cwnd = 100_000;
+
+        cubic.on_fast_retransmit(Instant::from_millis(0), 100_000);
+        assert_eq!(cubic.w_max, 100_000);
+        assert_eq!(cubic.window(), 70_000);
+
+        // The window doesn't grow during recovery.
+        cubic.on_ack(Instant::from_millis(0), 1000, rtt);
+        assert_eq!(cubic.window(), 70_000);
+        cubic.on_recovery_exit(Instant::from_millis(0));
+        assert_eq!(cubic.window(), 70_000);
+
+        // Another loss below the previous maximum triggers fast convergence.
+        cubic.on_fast_retransmit(Instant::from_millis(0), 70_000);
+        assert_eq!(cubic.w_max, 59_500);
+        assert_eq!(cubic.window(), 49_000);
+    }
+
+    #[test]
+    fn test_congestion_avoidance() {
+        let mut cubic = cubic();
+        let rtt = Duration::from_millis(300);
+        cubic.cwnd = 100_000;
+        cubic.on_fast_retransmit(Instant::from_millis(0), 100_000);
+        cubic.on_recovery_exit(Instant::from_millis(0));
+
+        // K = cbrt(30 segments / 0.4) ~= 4.2 s. Keep ACKing a window's worth of data every RTT,
+        // and the window should be back to its previous maximum around K.
+        let mut now = Instant::from_millis(0);
+        let mut last = cubic.window();
+        while now < Instant::from_millis(4000) {
+            for _ in 0..cubic.window() / 1000 {
+                cubic.on_ack(now, 1000, rtt);
+            }
+            assert!(cubic.window() >= last);
+            last = cubic.window();
+            now += Duration::from_millis(300);
+        }
+        assert!(cubic.window() < 100_000);
+
+        while now < Instant::from_millis(5000) {
+            for _ in 0..cubic.window() / 1000 {
+                cubic.on_ack(now, 1000, rtt);
+            }
+            now += Duration::from_millis(300);
+        }
+        assert!(cubic.window() >= 100_000);
+        // ...but it then probes beyond it slowly.
+        assert!(cubic.window() < 110_000);
+    }
+
+    #[test]
+    fn test_retransmit_timeout() {
+        let mut cubic = cubic();
+        cubic.cwnd = 10_000;
+        cubic.on_retransmit_timeout(Instant::from_millis(0), 10_000);
+        assert_eq!(cubic.ssthresh, 7000);
+        assert_eq!(cubic.window(), 1000);
+    }
+}
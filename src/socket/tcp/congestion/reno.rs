@@ -0,0 +1,211 @@
+use crate::socket::tcp::DEFAULT_MSS;
+use crate::time::{Duration, Instant};
+
+use super::{initial_window, loss_threshold, CongestionController};
+
+/// NewReno congestion control.
+///
+/// Slow start and congestion avoidance follow [RFC 5681], with appropriate byte counting
+/// ([RFC 3465]) during congestion avoidance. Fast recovery follows [RFC 6582]: the window is
+/// inflated by every duplicate ACK, and deflated by the amount of data covered by every
+/// partial ACK.
+///
+/// [RFC 5681]: https://tools.ietf.org/html/rfc5681
+/// [RFC 3465]: https://tools.ietf.org/html/rfc3465
+/// [RFC 6582]: https://tools.ietf.org/html/rfc6582
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Reno {
+    /// Congestion window, in octets.
+    cwnd: usize,
+    /// Slow start threshold, in octets.
+    ssthresh: usize,
+    /// Sender maximum segment size.
+    mss: usize,
+    /// Largest receive window advertised by the remote.
+    rwnd: usize,
+    /// Octets acknowledged since the window was last grown in congestion avoidance.
+    bytes_acked: usize,
+    /// Whether we're in fast recovery.
+    in_recovery: bool,
+}
+
+impl Reno {
+    pub fn new() -> Reno {
+        Reno {
+            cwnd: initial_window(DEFAULT_MSS),
+            ssthresh: usize::MAX,
+            mss: DEFAULT_MSS,
+            rwnd: 0,
+            bytes_acked: 0,
+            in_recovery: false,
+        }
+    }
+}
+
+impl CongestionController for Reno {
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+        self.cwnd = initial_window(mss);
+    }
+
+    fn set_remote_window(&mut self, remote_window: usize) {
+        self.rwnd = self.rwnd.max(remote_window);
+    }
+
+    fn on_ack(&mut self, _now: Instant, len: usize, _rtt: Duration) {
+        if self.in_recovery {
+            // RFC 6582 § 3.2, step 5: deflate the window by the amount of new data
+            // acknowledged, then add back one MSS if that amount was at least one MSS.
+            self.cwnd = self.cwnd.saturating_sub(len);
+            if len >= self.mss {
+                self.cwnd += self.mss;
+            }
+            return;
+        }
+
+        // There's no point in opening the window past what the remote accepts.
+        if self.cwnd >= self.rwnd {
+            return;
+        }
+
+        if self.cwnd < self.ssthresh {
+            // Slow start: grow by at most one MSS per ACK.
+            self.cwnd += len.min(self.mss);
+            net_trace!("reno: slow start, cwnd={}", self.cwnd);
+        } else {
+            // Congestion avoidance: grow by one MSS per window's worth of acknowledged data.
+            self.bytes_acked += len;
+            if self.bytes_acked >= self.cwnd {
+                self.bytes_acked -= self.cwnd;
+                self.cwnd += self.mss;
+                net_trace!("reno: congestion avoidance, cwnd={}", self.cwnd);
+            }
+        }
+    }
+
+    fn on_duplicate_ack(&mut self, _now: Instant) {
+        if self.in_recovery {
+            // Each duplicate ACK means a segment has left the network.
+            self.cwnd += self.mss;
+        }
+    }
+
+    fn on_fast_retransmit(&mut self, _now: Instant, flight_size: usize) {
+        self.ssthresh = loss_threshold(flight_size, self.mss);
+        self.cwnd = self.ssthresh + 3 * self.mss;
+        self.bytes_acked = 0;
+        self.in_recovery = true;
+        net_trace!(
+            "reno: fast recovery, ssthresh={} cwnd={}",
+            self.ssthresh,
+            self.cwnd
+        );
+    }
+
+    fn on_recovery_exit(&mut self, _now: Instant) {
+        self.cwnd = self.ssthresh;
+        self.in_recovery = false;
+        net_trace!("reno: recovered, cwnd={}", self.cwnd);
+    }
+
+    fn on_retransmit_timeout(&mut self, _now: Instant, flight_size: usize) {
+        self.ssthresh = loss_threshold(flight_size, self.mss);
+        self.cwnd = self.mss;
+        self.bytes_acked = 0;
+        self.in_recovery = false;
+        net_trace!(
+            "reno: retransmit timeout, ssthresh={} cwnd={}",
+            self.ssthresh,
+            self.cwnd
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reno() -> Reno {
+        let mut reno = Reno::new();
+        reno.set_mss(100);
+        reno.set_remote_window(usize::MAX);
+        reno
+    }
+
+    #[test]
+    fn test_slow_start() {
+        let mut reno = reno();
+        let rtt = Duration::from_millis(300);
+        assert_eq!(reno.window(), 400);
+
+        for _ in 0..4 {
+            reno.on_ack(Instant::from_millis(0), 100, rtt);
+        }
+        assert_eq!(reno.window(), 800);
+
+        // Stretch ACKs still only grow the window by one MSS.
+        reno.on_ack(Instant::from_millis(0), 800, rtt);
+        assert_eq!(reno.window(), 900);
+    }
+
+    #[test]
+    fn test_congestion_avoidance() {
+        let mut reno = reno();
+        let rtt = Duration::from_millis(300);
+        reno.ssthresh = 400;
+
+        // A full window of acknowledged data grows the window by one MSS.
+        for _ in 0..3 {
+            reno.on_ack(Instant::from_millis(0), 100, rtt);
+        }
+        assert_eq!(reno.window(), 400);
+        reno.on_ack(Instant::from_millis(0), 100, rtt);
+        assert_eq!(reno.window(), 500);
+    }
+
+    #[test]
+    fn test_window_limited_by_remote() {
+        let mut reno = Reno::new();
+        let rtt = Duration::from_millis(300);
+        reno.set_mss(100);
+        reno.set_remote_window(500);
+
+        for _ in 0..10 {
+            reno.on_ack(Instant::from_millis(0), 100, rtt);
+        }
+        assert_eq!(reno.window(), 500);
+    }
+
+    #[test]
+    fn test_fast_recovery() {
+        let mut reno = reno();
+        let rtt = Duration::from_millis(300);
+
+        reno.on_fast_retransmit(Instant::from_millis(0), 1000);
+        assert_eq!(reno.ssthresh, 500);
+        assert_eq!(reno.window(), 800);
+
+        reno.on_duplicate_ack(Instant::from_millis(0));
+        assert_eq!(reno.window(), 900);
+
+        // Partial ACK.
+        reno.on_ack(Instant::from_millis(0), 300, rtt);
+        assert_eq!(reno.window(), 700);
+
+        reno.on_recovery_exit(Instant::from_millis(0));
+        assert_eq!(reno.window(), 500);
+    }
+
+    #[test]
+    fn test_retransmit_timeout() {
+        let mut reno = reno();
+        reno.on_retransmit_timeout(Instant::from_millis(0), 300);
+        assert_eq!(reno.ssthresh, 200);
+        assert_eq!(reno.window(), 100);
+    }
+}
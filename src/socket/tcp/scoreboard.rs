@@ -0,0 +1,232 @@
+// The sender-side SACK scoreboard, which records the data the remote has selectively
+// acknowledged so that only the holes between it are retransmitted. See RFC 2018 for
+// the option itself and RFC 6675 for the loss recovery algorithm.
+
+use crate::wire::TcpSeqNumber;
+
+/// Maximum number of discontiguous ranges tracked. Once it's exceeded, the highest ranges
+/// are forgotten, which only makes the sender more conservative.
+const SCOREBOARD_SIZE: usize = 4;
+
+/// A scoreboard of the selectively acknowledged ranges in the transmit buffer.
+///
+/// Ranges are kept in ascending order, without overlaps, as `(left, right)` pairs
+/// where `left` is the first sequence number in the range and `right` is one past the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(super) struct Scoreboard {
+    ranges: [(TcpSeqNumber, TcpSeqNumber); SCOREBOARD_SIZE],
+    len: usize,
+}
+
+impl Default for Scoreboard {
+    fn default() -> Self {
+        Self {
+            ranges: [(TcpSeqNumber::default(), TcpSeqNumber::default()); SCOREBOARD_SIZE],
+            len: 0,
+        }
+    }
+}
+
+impl Scoreboard {
+    fn ranges(&self) -> &[(TcpSeqNumber, TcpSeqNumber)] {
+        &self.ranges[..self.len]
+    }
+
+    /// Return whether the remote hasn't selectively acknowledged anything.
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Forget everything the remote has selectively acknowledged.
+    pub(super) fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Record a range reported in a SACK block, merging it with the ranges it touches.
+    pub(super) fn add(&mut self, mut left: TcpSeqNumber, mut right: TcpSeqNumber) {
+        if left >= right {
+            return;
+        }
+
+        // Find the ranges that overlap or touch the new one, and absorb them.
+        let first = self.ranges().iter().take_while(|r| r.1 < left).count();
+        let last = first
+            + self.ranges()[first..]
+                .iter()
+                .take_while(|r| r.0 <= right)
+                .count();
+        if first < last {
+            if self.ranges[first].0 < left {
+                left = self.ranges[first].0;
+            }
+            if self.ranges[last - 1].1 > right {
+                right = self.ranges[last - 1].1;
+            }
+        }
+
+        // Replace the absorbed ranges (if any) with the new one.
+        let removed = last - first;
+        if removed == 0 && self.len == SCOREBOARD_SIZE {
+            if first == SCOREBOARD_SIZE {
+                return;
+            }
+            self.len -= 1;
+        }
+        if removed != 1 {
+            let tail = self.len - last;
+            self.ranges.copy_within(last..self.len, first + 1);
+            self.len = first + 1 + tail;
+        }
+        self.ranges[first] = (left, right);
+    }
+
+    /// Forget the ranges that the cumulative acknowledgement number `ack_number` covers.
+    pub(super) fn ack(&mut self, ack_number: TcpSeqNumber) {
+        let acked = self
+            .ranges()
+            .iter()
+            .take_while(|r| r.1 <= ack_number)
+            .count();
+        self.ranges.copy_within(acked..self.len, 0);
+        self.len -= acked;
+        if self.len > 0 && self.ranges[0].0 < ack_number {
+            self.ranges[0].0 = ack_number;
+        }
+    }
+
+    /// Return the first sequence number at or after `seq` that needs to be retransmitted.
+    ///
+    /// That is the next one that hasn't been selectively acknowledged, as long as some data
+    /// after it has. Data past the highest selectively acknowledged range might still be
+    /// in flight, so `end` is returned instead once `seq` reaches it. If nothing has been
+    /// selectively acknowledged, `seq` is returned, so that everything is retransmitted.
+    pub(super) fn next_hole(&self, mut seq: TcpSeqNumber, end: TcpSeqNumber) -> TcpSeqNumber {
+        if self.is_empty() {
+            return seq;
+        }
+
+        for &(left, right) in self.ranges() {
+            if seq < left {
+                return seq;
+            }
+            if seq < right {
+                seq = right;
+            }
+        }
+        end
+    }
+
+    /// Return the amount of octets starting at `seq` that haven't been selectively
+    /// acknowledged, up to the next range that has.
+    pub(super) fn hole_len(&self, seq: TcpSeqNumber) -> usize {
+        self.ranges()
+            .iter()
+            .find(|r| r.0 > seq)
+            .map(|&(left, _)| left - seq)
+            .unwrap_or(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn seq(n: i32) -> TcpSeqNumber {
+        TcpSeqNumber(n)
+    }
+
+    fn scoreboard(ranges: &[(i32, i32)]) -> Scoreboard {
+        let mut scoreboard = Scoreboard::default();
+        for &(left, right) in ranges {
+            scoreboard.add(seq(left), seq(right));
+        }
+        scoreboard
+    }
+
+    fn ranges(scoreboard: &Scoreboard) -> std::vec::Vec<(i32, i32)> {
+        scoreboard
+            .ranges()
+            .iter()
+            .map(|&(left, right)| (left.0, right.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_add_disjoint() {
+        let s = scoreboard(&[(30, 40), (10, 20), (50, 60)]);
+        assert_eq!(ranges(&s), [(10, 20), (30, 40), (50, 60)]);
+    }
+
+    #[test]
+    fn test_add_merge() {
+        let s = scoreboard(&[(10, 20), (30, 40), (50, 60), (15, 30)]);
+        assert_eq!(ranges(&s), [(10, 40), (50, 60)]);
+
+        let s = scoreboard(&[(10, 20), (30, 40), (50, 60), (5, 70)]);
+        assert_eq!(ranges(&s), [(5, 70)]);
+
+        let s = scoreboard(&[(10, 20), (12, 18)]);
+        assert_eq!(ranges(&s), [(10, 20)]);
+
+        let s = scoreboard(&[(10, 20), (20, 30)]);
+        assert_eq!(ranges(&s), [(10, 30)]);
+    }
+
+    #[test]
+    fn test_add_full() {
+        let mut s = scoreboard(&[(10, 20), (30, 40), (50, 60), (70, 80)]);
+        // The highest range is forgotten to make room for a lower one...
+        s.add(seq(0), seq(5));
+        assert_eq!(ranges(&s), [(0, 5), (10, 20), (30, 40), (50, 60)]);
+        // ...and higher ranges are dropped.
+        s.add(seq(90), seq(100));
+        assert_eq!(ranges(&s), [(0, 5), (10, 20), (30, 40), (50, 60)]);
+        // Merging still works.
+        s.add(seq(55), seq(65));
+        assert_eq!(ranges(&s), [(0, 5), (10, 20), (30, 40), (50, 65)]);
+    }
+
+    #[test]
+    fn test_add_wraparound() {
+        let s = scoreboard(&[(i32::MAX - 5, i32::MIN + 5), (i32::MIN + 10, i32::MIN + 20)]);
+        assert_eq!(
+            ranges(&s),
+            [(i32::MAX - 5, i32::MIN + 5), (i32::MIN + 10, i32::MIN + 20)]
+        );
+    }
+
+    #[test]
+    fn test_ack() {
+        let mut s = scoreboard(&[(10, 20), (30, 40)]);
+        s.ack(seq(5));
+        assert_eq!(ranges(&s), [(10, 20), (30, 40)]);
+        s.ack(seq(20));
+        assert_eq!(ranges(&s), [(30, 40)]);
+        s.ack(seq(35));
+        assert_eq!(ranges(&s), [(35, 40)]);
+        s.ack(seq(50));
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn test_next_hole() {
+        let s = scoreboard(&[(10, 20), (30, 40)]);
+        assert_eq!(s.next_hole(seq(0), seq(50)), seq(0));
+        assert_eq!(s.next_hole(seq(10), seq(50)), seq(20));
+        assert_eq!(s.next_hole(seq(25), seq(50)), seq(25));
+        assert_eq!(s.next_hole(seq(30), seq(50)), seq(50));
+        assert_eq!(s.next_hole(seq(45), seq(50)), seq(50));
+
+        let s = Scoreboard::default();
+        assert_eq!(s.next_hole(seq(0), seq(50)), seq(0));
+    }
+
+    #[test]
+    fn test_hole_len() {
+        let s = scoreboard(&[(10, 20), (30, 40)]);
+        assert_eq!(s.hole_len(seq(0)), 10);
+        assert_eq!(s.hole_len(seq(25)), 5);
+        assert_eq!(s.hole_len(seq(45)), usize::MAX);
+    }
+}
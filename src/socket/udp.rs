@@ -2,13 +2,21 @@ use core::cmp::min;
 #[cfg(feature = "async")]
 use core::task::Waker;
 
+use heapless::Deque;
+
 use crate::iface::Context;
 use crate::socket::PollAt;
 #[cfg(feature = "async")]
 use crate::socket::WakerRegistration;
 use crate::storage::Empty;
+#[cfg(feature = "proto-ipv6")]
+use crate::wire::{IpAddress, UDP_HEADER_LEN};
 use crate::wire::{IpEndpoint, IpListenEndpoint, IpProtocol, IpRepr, UdpRepr};
 
+/// Maximum number of errors a socket holds until they're returned by [`Socket::recv`].
+/// When it's full, further errors are dropped.
+pub const MAX_PENDING_ERROR_COUNT: usize = 4;
+
 /// A UDP packet metadata.
 pub type PacketMetadata = crate::storage::PacketMetadata<IpEndpoint>;
 
@@ -36,6 +44,13 @@ pub enum SendError {
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum RecvError {
     Exhausted,
+    /// A datagram sent to the endpoint couldn't be delivered.
+    Unreachable(IpEndpoint),
+    /// A datagram sent to the endpoint was refused, since nothing listens on its port.
+    Refused(IpEndpoint),
+    /// A datagram sent to the endpoint was dropped, since it was larger than the path MTU
+    /// and IPv6 packets aren't fragmented.
+    TooLong(IpEndpoint),
 }
 
 /// A User Datagram Protocol socket.
@@ -49,6 +64,8 @@ pub struct Socket<'a> {
     tx_buffer: PacketBuffer<'a>,
     /// The time-to-live (IPv4) or hop limit (IPv6) value used in outgoing packets.
     hop_limit: Option<u8>,
+    /// The errors of datagrams that couldn't be delivered, reported by `recv`.
+    pending_errors: Deque<RecvError, MAX_PENDING_ERROR_COUNT>,
     #[cfg(feature = "async")]
     rx_waker: WakerRegistration,
     #[cfg(feature = "async")]
@@ -63,6 +80,7 @@ impl<'a> Socket<'a> {
             rx_buffer,
             tx_buffer,
             hop_limit: None,
+            pending_errors: Deque::new(),
             #[cfg(feature = "async")]
             rx_waker: WakerRegistration::new(),
             #[cfg(feature = "async")]
@@ -172,6 +190,7 @@ impl<'a> Socket<'a> {
         // Reset the RX and TX buffers of the socket.
         self.tx_buffer.reset();
         self.rx_buffer.reset();
+        self.pending_errors.clear();
 
         #[cfg(feature = "async")]
         {
@@ -192,10 +211,10 @@ impl<'a> Socket<'a> {
         !self.tx_buffer.is_full()
     }
 
-    /// Check whether the receive buffer is not empty.
+    /// Check whether the receive buffer is not empty, or an error is pending.
     #[inline]
     pub fn can_recv(&self) -> bool {
-        !self.rx_buffer.is_empty()
+        !self.rx_buffer.is_empty() || !self.pending_errors.is_empty()
     }
 
     /// Return the maximum number packets the socket can receive.
@@ -313,7 +332,14 @@ impl<'a> Socket<'a> {
     /// as a pointer to the payload.
     ///
     /// This function returns `Err(Error::Exhausted)` if the receive buffer is empty.
+    /// Errors of datagrams sent that couldn't be delivered, `Err(RecvError::Unreachable)`,
+    /// `Err(RecvError::Refused)` or `Err(RecvError::TooLong)`, are returned once each, in the
+    /// order they occurred.
     pub fn recv(&mut self) -> Result<(&[u8], IpEndpoint), RecvError> {
+        if let Some(error) = self.pending_errors.pop_front() {
+            return Err(error);
+        }
+
         let (remote_endpoint, payload_buf) =
             self.rx_buffer.dequeue().map_err(|_| RecvError::Exhausted)?;
 
@@ -331,7 +357,7 @@ impl<'a> Socket<'a> {
     ///
     /// See also [recv](#method.recv).
     pub fn recv_slice(&mut self, data: &mut [u8]) -> Result<(usize, IpEndpoint), RecvError> {
-        let (buffer, endpoint) = self.recv().map_err(|_| RecvError::Exhausted)?;
+        let (buffer, endpoint) = self.recv()?;
         let length = min(data.len(), buffer.len());
         data[..length].copy_from_slice(&buffer[..length]);
         Ok((length, endpoint))
@@ -421,6 +447,32 @@ impl<'a> Socket<'a> {
         self.rx_waker.wake();
     }
 
+    /// Report that a datagram sent from `local_endpoint` couldn't be delivered, if it was
+    /// sent by this socket.
+    pub(crate) fn process_error(&mut self, local_endpoint: IpEndpoint, error: RecvError) -> bool {
+        if self.endpoint.port != local_endpoint.port
+            || self
+                .endpoint
+                .addr
+                .map_or(false, |addr| addr != local_endpoint.addr)
+        {
+            return false;
+        }
+
+        self.push_error(error);
+        true
+    }
+
+    fn push_error(&mut self, error: RecvError) {
+        net_trace!("udp:{}: {:?}", self.endpoint, error);
+        if self.pending_errors.push_back(error).is_err() {
+            net_trace!("udp:{}: too many pending errors, dropped", self.endpoint);
+        }
+
+        #[cfg(feature = "async")]
+        self.rx_waker.wake();
+    }
+
     pub(crate) fn dispatch<F, E>(&mut self, cx: &mut Context, emit: F) -> Result<(), E>
     where
         F: FnOnce(&mut Context, (IpRepr, UdpRepr, &[u8])) -> Result<(), E>,
@@ -428,6 +480,27 @@ impl<'a> Socket<'a> {
         let endpoint = self.endpoint;
         let hop_limit = self.hop_limit.unwrap_or(64);
 
+        // IPv6 packets aren't fragmented, and routers drop the ones that don't fit.
+        #[cfg(feature = "proto-ipv6")]
+        if let Ok((&remote_endpoint, payload_buf)) = self.tx_buffer.peek() {
+            let ip_len = crate::wire::IPV6_HEADER_LEN + UDP_HEADER_LEN + payload_buf.len();
+            if matches!(remote_endpoint.addr, IpAddress::Ipv6(_))
+                && ip_len > cx.path_mtu(&remote_endpoint.addr)
+            {
+                net_debug!(
+                    "udp:{}:{}: {} octets exceed the path MTU, dropping.",
+                    endpoint,
+                    remote_endpoint,
+                    payload_buf.len()
+                );
+                let _ = self.tx_buffer.dequeue();
+                self.push_error(RecvError::TooLong(remote_endpoint));
+                #[cfg(feature = "async")]
+                self.tx_waker.wake();
+                return Ok(());
+            }
+        }
+
         let res = self.tx_buffer.dequeue_with(|remote_endpoint, payload_buf| {
             let src_addr = match endpoint.addr {
                 Some(addr) => addr,
@@ -462,6 +535,7 @@ impl<'a> Socket<'a> {
                 repr.header_len() + payload_buf.len(),
                 hop_limit,
             );
+
             emit(cx, (ip_repr, repr, payload_buf))
         });
         match res {
@@ -691,6 +765,27 @@ mod test {
         assert!(!socket.can_recv());
     }
 
+    #[test]
+    fn test_recv_errors() {
+        let mut socket = socket(buffer(1), buffer(0));
+        let mut cx = Context::mock();
+
+        assert_eq!(socket.bind(LOCAL_END), Ok(()));
+
+        let other_end = IpEndpoint::new(OTHER_ADDR.into(), LOCAL_PORT);
+        let error = RecvError::Unreachable(REMOTE_END);
+        assert!(!socket.process_error(other_end, error));
+        assert!(socket.process_error(LOCAL_END, error));
+        assert!(socket.process_error(LOCAL_END, RecvError::Refused(REMOTE_END)));
+        assert!(socket.can_recv());
+
+        socket.process(&mut cx, &REMOTE_IP_REPR, &REMOTE_UDP_REPR, PAYLOAD);
+        assert_eq!(socket.recv(), Err(RecvError::Unreachable(REMOTE_END)));
+        assert_eq!(socket.recv(), Err(RecvError::Refused(REMOTE_END)));
+        assert_eq!(socket.recv(), Ok((&b"abcdef"[..], REMOTE_END)));
+        assert!(!socket.can_recv());
+    }
+
     #[test]
     fn test_peek_process() {
         let mut socket = socket(buffer(1), buffer(0));
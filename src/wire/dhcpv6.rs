@@ -0,0 +1,870 @@
+// See https://tools.ietf.org/html/rfc8415 for the DHCPv6 specification.
+
+use byteorder::{ByteOrder, NetworkEndian};
+use core::iter;
+use heapless::Vec;
+
+use super::{Error, Result};
+use crate::wire::Ipv6Address;
+
+pub const SERVER_PORT: u16 = 547;
+pub const CLIENT_PORT: u16 = 546;
+pub const MAX_DNS_SERVER_COUNT: usize = 3;
+/// The maximum length of a DHCP Unique Identifier, see RFC 8415 § 11.1.
+pub const MAX_DUID_LEN: usize = 130;
+/// The maximum number of addresses, or prefixes, parsed from an identity association.
+pub const MAX_IA_ADDRESS_COUNT: usize = 2;
+
+enum_with_unknown! {
+    /// The possible message types of a DHCPv6 packet.
+    pub enum MessageType(u8) {
+        Solicit = 1,
+        Advertise = 2,
+        Request = 3,
+        Confirm = 4,
+        Renew = 5,
+        Rebind = 6,
+        Reply = 7,
+        Release = 8,
+        Decline = 9,
+        Reconfigure = 10,
+        InformationRequest = 11,
+    }
+}
+
+enum_with_unknown! {
+    /// The status codes of a DHCPv6 status code option, see RFC 8415 § 21.13.
+    pub enum StatusCode(u16) {
+        Success = 0,
+        UnspecFail = 1,
+        NoAddrsAvail = 2,
+        NoBinding = 3,
+        NotOnLink = 4,
+        UseMulticast = 5,
+        NoPrefixAvail = 6,
+    }
+}
+
+/// A representation of a single DHCPv6 option.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DhcpOption<'a> {
+    pub kind: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> DhcpOption<'a> {
+    /// Return the length of the option, including its header.
+    pub const fn buffer_len(&self) -> usize {
+        4 + self.data.len()
+    }
+}
+
+/// Return an iterator over the options in `buf`, stopping at the first truncated one.
+fn options(mut buf: &[u8]) -> impl Iterator<Item = DhcpOption<'_>> {
+    iter::from_fn(move || {
+        if buf.len() < 4 {
+            return None;
+        }
+
+        let kind = NetworkEndian::read_u16(&buf[0..2]);
+        let len = NetworkEndian::read_u16(&buf[2..4]) as usize;
+        if buf.len() < 4 + len {
+            return None;
+        }
+
+        let opt = DhcpOption {
+            kind,
+            data: &buf[4..4 + len],
+        };
+        buf = &buf[4 + len..];
+        Some(opt)
+    })
+}
+
+/// A buffer for DHCPv6 options.
+#[derive(Debug)]
+struct DhcpOptionWriter<'a> {
+    buffer: &'a mut [u8],
+}
+
+impl<'a> DhcpOptionWriter<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer }
+    }
+
+    /// Emit an option of `len` bytes, whose data is written by `f`.
+    fn emit_with<F>(&mut self, kind: u16, len: usize, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut [u8]) -> Result<()>,
+    {
+        if len > u16::MAX as usize || self.buffer.len() < 4 + len {
+            return Err(Error);
+        }
+
+        let (buf, rest) = core::mem::take(&mut self.buffer).split_at_mut(4 + len);
+        self.buffer = rest;
+
+        NetworkEndian::write_u16(&mut buf[0..2], kind);
+        NetworkEndian::write_u16(&mut buf[2..4], len as u16);
+        f(&mut buf[4..])
+    }
+
+    fn emit(&mut self, option: DhcpOption<'_>) -> Result<()> {
+        self.emit_with(option.kind, option.data.len(), |buf| {
+            buf.copy_from_slice(option.data);
+            Ok(())
+        })
+    }
+}
+
+/// A read/write wrapper around a DHCPv6 packet buffer.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+pub(crate) mod field {
+    #![allow(non_snake_case)]
+    #![allow(unused)]
+
+    use crate::wire::field::*;
+
+    pub const MSG_TYPE: usize = 0;
+    pub const TRANSACTION_ID: Field = 1..4;
+    pub const OPTIONS: Rest = 4..;
+
+    pub const OPT_CLIENTID: u16 = 1;
+    pub const OPT_SERVERID: u16 = 2;
+    pub const OPT_IA_NA: u16 = 3;
+    pub const OPT_IA_TA: u16 = 4;
+    pub const OPT_IAADDR: u16 = 5;
+    pub const OPT_ORO: u16 = 6;
+    pub const OPT_PREFERENCE: u16 = 7;
+    pub const OPT_ELAPSED_TIME: u16 = 8;
+    pub const OPT_RELAY_MSG: u16 = 9;
+    pub const OPT_AUTH: u16 = 11;
+    pub const OPT_UNICAST: u16 = 12;
+    pub const OPT_STATUS_CODE: u16 = 13;
+    pub const OPT_RAPID_COMMIT: u16 = 14;
+    pub const OPT_USER_CLASS: u16 = 15;
+    pub const OPT_VENDOR_CLASS: u16 = 16;
+    pub const OPT_VENDOR_OPTS: u16 = 17;
+    pub const OPT_INTERFACE_ID: u16 = 18;
+    pub const OPT_RECONF_MSG: u16 = 19;
+    pub const OPT_RECONF_ACCEPT: u16 = 20;
+    pub const OPT_DNS_SERVERS: u16 = 23;
+    pub const OPT_DOMAIN_LIST: u16 = 24;
+    pub const OPT_IA_PD: u16 = 25;
+    pub const OPT_IAPREFIX: u16 = 26;
+    pub const OPT_INFORMATION_REFRESH_TIME: u16 = 32;
+    pub const OPT_SOL_MAX_RT: u16 = 82;
+    pub const OPT_INF_MAX_RT: u16 = 83;
+
+    // Identity associations.
+    pub const IA_IAID: Field = 0..4;
+    pub const IA_T1: Field = 4..8;
+    pub const IA_T2: Field = 8..12;
+    pub const IA_OPTIONS: Rest = 12..;
+
+    // IA Address option.
+    pub const IAADDR_ADDR: Field = 0..16;
+    pub const IAADDR_PREFERRED: Field = 16..20;
+    pub const IAADDR_VALID: Field = 20..24;
+    pub const IAADDR_OPTIONS: Rest = 24..;
+
+    // IA Prefix option.
+    pub const IAPREFIX_PREFERRED: Field = 0..4;
+    pub const IAPREFIX_VALID: Field = 4..8;
+    pub const IAPREFIX_PREFIX_LEN: usize = 8;
+    pub const IAPREFIX_PREFIX: Field = 9..25;
+    pub const IAPREFIX_OPTIONS: Rest = 25..;
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with DHCPv6 packet structure.
+    pub const fn new_unchecked(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new_unchecked] and [check_len].
+    ///
+    /// [new_unchecked]: #method.new_unchecked
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new_unchecked(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::OPTIONS.start {
+            Err(Error)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the message type field.
+    pub fn msg_type(&self) -> MessageType {
+        MessageType::from(self.buffer.as_ref()[field::MSG_TYPE])
+    }
+
+    /// Return the 24-bit transaction ID, which is chosen by the client.
+    pub fn transaction_id(&self) -> u32 {
+        let data = &self.buffer.as_ref()[field::TRANSACTION_ID];
+        u32::from_be_bytes([0, data[0], data[1], data[2]])
+    }
+
+    /// Return an iterator over the options.
+    #[inline]
+    pub fn options(&self) -> impl Iterator<Item = DhcpOption<'_>> + '_ {
+        options(&self.buffer.as_ref()[field::OPTIONS])
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the message type field.
+    pub fn set_msg_type(&mut self, value: MessageType) {
+        self.buffer.as_mut()[field::MSG_TYPE] = value.into();
+    }
+
+    /// Set the transaction ID. Only its 24 low bits are used.
+    pub fn set_transaction_id(&mut self, value: u32) {
+        let bytes = value.to_be_bytes();
+        self.buffer.as_mut()[field::TRANSACTION_ID].copy_from_slice(&bytes[1..]);
+    }
+
+    /// Return a mutable pointer to the options.
+    #[inline]
+    pub fn options_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[field::OPTIONS]
+    }
+}
+
+fn parse_status_code(data: &[u8]) -> Result<StatusCode> {
+    if data.len() < 2 {
+        return Err(Error);
+    }
+    Ok(StatusCode::from(NetworkEndian::read_u16(&data[0..2])))
+}
+
+fn emit_status_code(options: &mut DhcpOptionWriter, status: Option<StatusCode>) -> Result<()> {
+    match status {
+        Some(status) => options.emit(DhcpOption {
+            kind: field::OPT_STATUS_CODE,
+            data: &u16::from(status).to_be_bytes(),
+        }),
+        None => Ok(()),
+    }
+}
+
+const fn status_code_len(status: Option<StatusCode>) -> usize {
+    match status {
+        Some(_) => 4 + 2,
+        None => 0,
+    }
+}
+
+/// An address of a non-temporary identity association, see RFC 8415 § 21.6.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IaAddress {
+    pub address: Ipv6Address,
+    /// The preferred lifetime, in seconds.
+    pub preferred_lifetime: u32,
+    /// The valid lifetime, in seconds.
+    pub valid_lifetime: u32,
+}
+
+/// A prefix of an identity association for prefix delegation, see RFC 8415 § 21.22.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IaPrefix {
+    pub prefix: Ipv6Address,
+    pub prefix_len: u8,
+    /// The preferred lifetime, in seconds.
+    pub preferred_lifetime: u32,
+    /// The valid lifetime, in seconds.
+    pub valid_lifetime: u32,
+}
+
+/// An identity association for non-temporary addresses, see RFC 8415 § 21.4.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IaNa {
+    /// The identity association identifier, chosen by the client.
+    pub iaid: u32,
+    /// When the client should contact the server that assigned the addresses, in seconds.
+    pub t1: u32,
+    /// When the client should contact any server, in seconds.
+    pub t2: u32,
+    pub addresses: Vec<IaAddress, MAX_IA_ADDRESS_COUNT>,
+    pub status: Option<StatusCode>,
+}
+
+/// An identity association for prefix delegation, see RFC 8415 § 21.21.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IaPd {
+    /// The identity association identifier, chosen by the client.
+    pub iaid: u32,
+    /// When the client should contact the server that delegated the prefixes, in seconds.
+    pub t1: u32,
+    /// When the client should contact any server, in seconds.
+    pub t2: u32,
+    pub prefixes: Vec<IaPrefix, MAX_IA_ADDRESS_COUNT>,
+    pub status: Option<StatusCode>,
+}
+
+/// Parse the header of an identity association, returning its IAID, T1, T2 and options.
+fn parse_ia(data: &[u8]) -> Result<(u32, u32, u32, &[u8])> {
+    if data.len() < field::IA_OPTIONS.start {
+        return Err(Error);
+    }
+    Ok((
+        NetworkEndian::read_u32(&data[field::IA_IAID]),
+        NetworkEndian::read_u32(&data[field::IA_T1]),
+        NetworkEndian::read_u32(&data[field::IA_T2]),
+        &data[field::IA_OPTIONS],
+    ))
+}
+
+fn emit_ia(data: &mut [u8], iaid: u32, t1: u32, t2: u32) {
+    NetworkEndian::write_u32(&mut data[field::IA_IAID], iaid);
+    NetworkEndian::write_u32(&mut data[field::IA_T1], t1);
+    NetworkEndian::write_u32(&mut data[field::IA_T2], t2);
+}
+
+impl IaNa {
+    /// Parse the data of an IA_NA option.
+    pub fn parse(data: &[u8]) -> Result<IaNa> {
+        let (iaid, t1, t2, ia_options) = parse_ia(data)?;
+
+        let mut addresses = Vec::new();
+        let mut status = None;
+        for option in options(ia_options) {
+            let data = option.data;
+            match option.kind {
+                field::OPT_IAADDR => {
+                    if data.len() < field::IAADDR_OPTIONS.start {
+                        return Err(Error);
+                    }
+                    // We ignore push failures, only the first addresses are kept.
+                    let _ = addresses.push(IaAddress {
+                        address: Ipv6Address::from_bytes(&data[field::IAADDR_ADDR]),
+                        preferred_lifetime: NetworkEndian::read_u32(&data[field::IAADDR_PREFERRED]),
+                        valid_lifetime: NetworkEndian::read_u32(&data[field::IAADDR_VALID]),
+                    });
+                }
+                field::OPT_STATUS_CODE => status = Some(parse_status_code(data)?),
+                _ => {}
+            }
+        }
+
+        Ok(IaNa {
+            iaid,
+            t1,
+            t2,
+            addresses,
+            status,
+        })
+    }
+
+    /// Return the length of the data of an IA_NA option emitted from this representation.
+    pub fn buffer_len(&self) -> usize {
+        field::IA_OPTIONS.start
+            + self.addresses.len() * (4 + field::IAADDR_OPTIONS.start)
+            + status_code_len(self.status)
+    }
+
+    /// Emit this representation into the data of an IA_NA option.
+    pub fn emit(&self, data: &mut [u8]) -> Result<()> {
+        emit_ia(data, self.iaid, self.t1, self.t2);
+        let mut options = DhcpOptionWriter::new(&mut data[field::IA_OPTIONS]);
+        for address in &self.addresses {
+            options.emit_with(field::OPT_IAADDR, field::IAADDR_OPTIONS.start, |buf| {
+                buf[field::IAADDR_ADDR].copy_from_slice(address.address.as_bytes());
+                NetworkEndian::write_u32(
+                    &mut buf[field::IAADDR_PREFERRED],
+                    address.preferred_lifetime,
+                );
+                NetworkEndian::write_u32(&mut buf[field::IAADDR_VALID], address.valid_lifetime);
+                Ok(())
+            })?;
+        }
+        emit_status_code(&mut options, self.status)
+    }
+}
+
+impl IaPd {
+    /// Parse the data of an IA_PD option.
+    pub fn parse(data: &[u8]) -> Result<IaPd> {
+        let (iaid, t1, t2, ia_options) = parse_ia(data)?;
+
+        let mut prefixes = Vec::new();
+        let mut status = None;
+        for option in options(ia_options) {
+            let data = option.data;
+            match option.kind {
+                field::OPT_IAPREFIX => {
+                    if data.len() < field::IAPREFIX_OPTIONS.start {
+                        return Err(Error);
+                    }
+                    let prefix_len = data[field::IAPREFIX_PREFIX_LEN];
+                    if prefix_len > 128 {
+                        return Err(Error);
+                    }
+                    // We ignore push failures, only the first prefixes are kept.
+                    let _ = prefixes.push(IaPrefix {
+                        prefix: Ipv6Address::from_bytes(&data[field::IAPREFIX_PREFIX]),
+                        prefix_len,
+                        preferred_lifetime: NetworkEndian::read_u32(
+                            &data[field::IAPREFIX_PREFERRED],
+                        ),
+                        valid_lifetime: NetworkEndian::read_u32(&data[field::IAPREFIX_VALID]),
+                    });
+                }
+                field::OPT_STATUS_CODE => status = Some(parse_status_code(data)?),
+                _ => {}
+            }
+        }
+
+        Ok(IaPd {
+            iaid,
+            t1,
+            t2,
+            prefixes,
+            status,
+        })
+    }
+
+    /// Return the length of the data of an IA_PD option emitted from this representation.
+    pub fn buffer_len(&self) -> usize {
+        field::IA_OPTIONS.start
+            + self.prefixes.len() * (4 + field::IAPREFIX_OPTIONS.start)
+            + status_code_len(self.status)
+    }
+
+    /// Emit this representation into the data of an IA_PD option.
+    pub fn emit(&self, data: &mut [u8]) -> Result<()> {
+        emit_ia(data, self.iaid, self.t1, self.t2);
+        let mut options = DhcpOptionWriter::new(&mut data[field::IA_OPTIONS]);
+        for prefix in &self.prefixes {
+            options.emit_with(field::OPT_IAPREFIX, field::IAPREFIX_OPTIONS.start, |buf| {
+                NetworkEndian::write_u32(
+                    &mut buf[field::IAPREFIX_PREFERRED],
+                    prefix.preferred_lifetime,
+                );
+                NetworkEndian::write_u32(&mut buf[field::IAPREFIX_VALID], prefix.valid_lifetime);
+                buf[field::IAPREFIX_PREFIX_LEN] = prefix.prefix_len;
+                buf[field::IAPREFIX_PREFIX].copy_from_slice(prefix.prefix.as_bytes());
+                Ok(())
+            })?;
+        }
+        emit_status_code(&mut options, self.status)
+    }
+}
+
+/// A high-level representation of a DHCPv6 client/server message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Repr<'a> {
+    pub message_type: MessageType,
+    /// The transaction ID, chosen by the client. Only its 24 low bits are used.
+    pub transaction_id: u32,
+    /// The DHCP Unique Identifier of the client.
+    pub client_id: Option<&'a [u8]>,
+    /// The DHCP Unique Identifier of the server.
+    pub server_id: Option<&'a [u8]>,
+    /// The identity association for non-temporary addresses. Only the first one of a
+    /// message is parsed.
+    pub ia_na: Option<IaNa>,
+    /// The identity association for prefix delegation. Only the first one of a message
+    /// is parsed.
+    pub ia_pd: Option<IaPd>,
+    /// The "option request" option, a list of big-endian option codes the client is
+    /// interested in.
+    pub option_request: Option<&'a [u8]>,
+    /// How long the client has been trying to complete the exchange, in hundredths of
+    /// a second.
+    pub elapsed_time: Option<u16>,
+    /// The preference of the server, used by the client to choose among advertisements.
+    pub preference: Option<u8>,
+    /// The status of the exchange. `None` means success.
+    pub status: Option<StatusCode>,
+    /// The "rapid commit" option.
+    pub rapid_commit: bool,
+    /// DNS servers
+    pub dns_servers: Option<Vec<Ipv6Address, MAX_DNS_SERVER_COUNT>>,
+    /// How long the client should wait before refreshing information received with an
+    /// Information-Request, in seconds.
+    pub information_refresh_time: Option<u32>,
+    /// When returned from [`Repr::parse`], this field will be empty.
+    /// However, when calling [`Repr::emit`], this field should contain only
+    /// additional DHCPv6 options not known to smoltcp.
+    pub additional_options: &'a [DhcpOption<'a>],
+}
+
+impl<'a> Repr<'a> {
+    /// Return the length of a packet that will be emitted from this high-level representation.
+    pub fn buffer_len(&self) -> usize {
+        let mut len = field::OPTIONS.start;
+        if let Some(client_id) = self.client_id {
+            len += 4 + client_id.len();
+        }
+        if let Some(server_id) = self.server_id {
+            len += 4 + server_id.len();
+        }
+        if let Some(ia_na) = &self.ia_na {
+            len += 4 + ia_na.buffer_len();
+        }
+        if let Some(ia_pd) = &self.ia_pd {
+            len += 4 + ia_pd.buffer_len();
+        }
+        if let Some(option_request) = self.option_request {
+            len += 4 + option_request.len();
+        }
+        if self.elapsed_time.is_some() {
+            len += 4 + 2;
+        }
+        if self.preference.is_some() {
+            len += 4 + 1;
+        }
+        len += status_code_len(self.status);
+        if self.rapid_commit {
+            len += 4;
+        }
+        if let Some(dns_servers) = &self.dns_servers {
+            len += 4 + dns_servers.len() * 16;
+        }
+        if self.information_refresh_time.is_some() {
+            len += 4 + 4;
+        }
+        for opt in self.additional_options {
+            len += opt.buffer_len();
+        }
+        len
+    }
+
+    /// Parse a DHCPv6 packet and return a high-level representation.
+    pub fn parse<T>(packet: &'a Packet<&'a T>) -> Result<Self>
+    where
+        T: AsRef<[u8]> + ?Sized,
+    {
+        packet.check_len()?;
+
+        let message_type = packet.msg_type();
+        if let MessageType::Unknown(_) = message_type {
+            return Err(Error);
+        }
+
+        let mut client_id = None;
+        let mut server_id = None;
+        let mut ia_na = None;
+        let mut ia_pd = None;
+        let mut option_request = None;
+        let mut elapsed_time = None;
+        let mut preference = None;
+        let mut status = None;
+        let mut rapid_commit = false;
+        let mut dns_servers = None;
+        let mut information_refresh_time = None;
+
+        for option in packet.options() {
+            let data = option.data;
+            match (option.kind, data.len()) {
+                (field::OPT_CLIENTID, 1..=MAX_DUID_LEN) => client_id = Some(data),
+                (field::OPT_SERVERID, 1..=MAX_DUID_LEN) => server_id = Some(data),
+                (field::OPT_IA_NA, _) if ia_na.is_none() => ia_na = Some(IaNa::parse(data)?),
+                (field::OPT_IA_PD, _) if ia_pd.is_none() => ia_pd = Some(IaPd::parse(data)?),
+                (field::OPT_ORO, len) if len % 2 == 0 => option_request = Some(data),
+                (field::OPT_ELAPSED_TIME, 2) => {
+                    elapsed_time = Some(NetworkEndian::read_u16(data));
+                }
+                (field::OPT_PREFERENCE, 1) => preference = Some(data[0]),
+                (field::OPT_STATUS_CODE, _) => status = Some(parse_status_code(data)?),
+                (field::OPT_RAPID_COMMIT, 0) => rapid_commit = true,
+                (field::OPT_DNS_SERVERS, len) if len % 16 == 0 => {
+                    let mut servers = Vec::new();
+                    for chunk in data.chunks(16) {
+                        // We ignore push failures because that will only happen
+                        // if we attempt to push more than 3 addresses.
+                        servers.push(Ipv6Address::from_bytes(chunk)).ok();
+                    }
+                    dns_servers = Some(servers);
+                }
+                (field::OPT_INFORMATION_REFRESH_TIME, 4) => {
+                    information_refresh_time = Some(NetworkEndian::read_u32(data));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Repr {
+            message_type,
+            transaction_id: packet.transaction_id(),
+            client_id,
+            server_id,
+            ia_na,
+            ia_pd,
+            option_request,
+            elapsed_time,
+            preference,
+            status,
+            rapid_commit,
+            dns_servers,
+            information_refresh_time,
+            additional_options: &[],
+        })
+    }
+
+    /// Emit a high-level representation into a DHCPv6 packet.
+    pub fn emit<T>(&self, packet: &mut Packet<&mut T>) -> Result<()>
+    where
+        T: AsRef<[u8]> + AsMut<[u8]> + ?Sized,
+    {
+        packet.set_msg_type(self.message_type);
+        packet.set_transaction_id(self.transaction_id);
+
+        let mut options = DhcpOptionWriter::new(packet.options_mut());
+        if let Some(client_id) = self.client_id {
+            options.emit(DhcpOption {
+                kind: field::OPT_CLIENTID,
+                data: client_id,
+            })?;
+        }
+        if let Some(server_id) = self.server_id {
+            options.emit(DhcpOption {
+                kind: field::OPT_SERVERID,
+                data: server_id,
+            })?;
+        }
+        if let Some(ia_na) = &self.ia_na {
+            options.emit_with(field::OPT_IA_NA, ia_na.buffer_len(), |buf| ia_na.emit(buf))?;
+        }
+        if let Some(ia_pd) = &self.ia_pd {
+            options.emit_with(field::OPT_IA_PD, ia_pd.buffer_len(), |buf| ia_pd.emit(buf))?;
+        }
+        if let Some(option_request) = self.option_request {
+            options.emit(DhcpOption {
+                kind: field::OPT_ORO,
+                data: option_request,
+            })?;
+        }
+        if let Some(elapsed_time) = self.elapsed_time {
+            options.emit(DhcpOption {
+                kind: field::OPT_ELAPSED_TIME,
+                data: &elapsed_time.to_be_bytes(),
+            })?;
+        }
+        if let Some(preference) = self.preference {
+            options.emit(DhcpOption {
+                kind: field::OPT_PREFERENCE,
+                data: &[preference],
+            })?;
+        }
+        emit_status_code(&mut options, self.status)?;
+        if self.rapid_commit {
+            options.emit(DhcpOption {
+                kind: field::OPT_RAPID_COMMIT,
+                data: &[],
+            })?;
+        }
+        if let Some(dns_servers) = &self.dns_servers {
+            options.emit_with(field::OPT_DNS_SERVERS, dns_servers.len() * 16, |buf| {
+                for (chunk, addr) in buf.chunks_mut(16).zip(dns_servers) {
+                    chunk.copy_from_slice(addr.as_bytes());
+                }
+                Ok(())
+            })?;
+        }
+        if let Some(information_refresh_time) = self.information_refresh_time {
+            options.emit(DhcpOption {
+                kind: field::OPT_INFORMATION_REFRESH_TIME,
+                data: &information_refresh_time.to_be_bytes(),
+            })?;
+        }
+        for option in self.additional_options {
+            options.emit(*option)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static CLIENT_DUID: [u8; 10] = [0x00, 0x03, 0x00, 0x01, 0x02, 0x02, 0x02, 0x02, 0x02, 0x02];
+    static SERVER_DUID: [u8; 10] = [0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+    static SOLICIT_BYTES: [u8; 46] = [
+        0x01, 0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x0a, 0x00, 0x03, 0x00, 0x01, 0x02, 0x02, 0x02,
+        0x02, 0x02, 0x02, 0x00, 0x03, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x02, 0x00, 0x17, 0x00, 0x08, 0x00, 0x02, 0x00,
+        0x00,
+    ];
+
+    static REPLY_BYTES: [u8; 118] = [
+        0x07, 0x12, 0x34, 0x56, 0x00, 0x01, 0x00, 0x0a, 0x00, 0x03, 0x00, 0x01, 0x02, 0x02, 0x02,
+        0x02, 0x02, 0x02, 0x00, 0x02, 0x00, 0x0a, 0x00, 0x03, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x03, 0x00, 0x28, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0xf4, 0x00,
+        0x00, 0x03, 0x20, 0x00, 0x05, 0x00, 0x18, 0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00, 0x07,
+        0xd0, 0x00, 0x19, 0x00, 0x12, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x0d, 0x00, 0x02, 0x00, 0x06, 0x00, 0x17, 0x00, 0x10, 0xfd, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    ];
+
+    const ADDR: Ipv6Address = Ipv6Address([
+        0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x64,
+    ]);
+    const DNS_SERVER: Ipv6Address = Ipv6Address([
+        0xfd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01,
+    ]);
+
+    fn solicit_repr() -> Repr<'static> {
+        Repr {
+            message_type: MessageType::Solicit,
+            transaction_id: 0x123456,
+            client_id: Some(&CLIENT_DUID),
+            server_id: None,
+            ia_na: Some(IaNa {
+                iaid: 1,
+                t1: 0,
+                t2: 0,
+                addresses: Vec::new(),
+                status: None,
+            }),
+            ia_pd: None,
+            option_request: Some(&[0x00, 0x17]),
+            elapsed_time: Some(0),
+            preference: None,
+            status: None,
+            rapid_commit: false,
+            dns_servers: None,
+            information_refresh_time: None,
+            additional_options: &[],
+        }
+    }
+
+    fn reply_repr() -> Repr<'static> {
+        Repr {
+            message_type: MessageType::Reply,
+            transaction_id: 0x123456,
+            client_id: Some(&CLIENT_DUID),
+            server_id: Some(&SERVER_DUID),
+            ia_na: Some(IaNa {
+                iaid: 1,
+                t1: 500,
+                t2: 800,
+                addresses: Vec::from_slice(&[IaAddress {
+                    address: ADDR,
+                    preferred_lifetime: 1000,
+                    valid_lifetime: 2000,
+                }])
+                .unwrap(),
+                status: None,
+            }),
+            ia_pd: Some(IaPd {
+                iaid: 2,
+                t1: 0,
+                t2: 0,
+                prefixes: Vec::new(),
+                status: Some(StatusCode::NoPrefixAvail),
+            }),
+            option_request: None,
+            elapsed_time: None,
+            preference: None,
+            status: None,
+            rapid_commit: false,
+            dns_servers: Some(Vec::from_slice(&[DNS_SERVER]).unwrap()),
+            information_refresh_time: None,
+            additional_options: &[],
+        }
+    }
+
+    #[test]
+    fn test_deconstruct() {
+        let packet = Packet::new_unchecked(&REPLY_BYTES[..]);
+        assert_eq!(packet.msg_type(), MessageType::Reply);
+        assert_eq!(packet.transaction_id(), 0x123456);
+
+        let mut options = packet.options();
+        assert_eq!(
+            options.next(),
+            Some(DhcpOption {
+                kind: field::OPT_CLIENTID,
+                data: &CLIENT_DUID,
+            })
+        );
+        assert_eq!(
+            options.next(),
+            Some(DhcpOption {
+                kind: field::OPT_SERVERID,
+                data: &SERVER_DUID,
+            })
+        );
+        assert_eq!(options.next().map(|opt| opt.kind), Some(field::OPT_IA_NA));
+        assert_eq!(options.next().map(|opt| opt.kind), Some(field::OPT_IA_PD));
+        assert_eq!(
+            options.next().map(|opt| opt.kind),
+            Some(field::OPT_DNS_SERVERS)
+        );
+        assert_eq!(options.next(), None);
+    }
+
+    #[test]
+    fn test_parse_solicit() {
+        let packet = Packet::new_unchecked(&SOLICIT_BYTES[..]);
+        assert_eq!(Repr::parse(&packet), Ok(solicit_repr()));
+    }
+
+    #[test]
+    fn test_emit_solicit() {
+        let repr = solicit_repr();
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        let mut packet = Packet::new_unchecked(&mut bytes);
+        repr.emit(&mut packet).unwrap();
+        assert_eq!(&*packet.into_inner(), &SOLICIT_BYTES[..]);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let packet = Packet::new_unchecked(&REPLY_BYTES[..]);
+        assert_eq!(Repr::parse(&packet), Ok(reply_repr()));
+    }
+
+    #[test]
+    fn test_emit_reply() {
+        let repr = reply_repr();
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        let mut packet = Packet::new_unchecked(&mut bytes);
+        repr.emit(&mut packet).unwrap();
+        assert_eq!(&*packet.into_inner(), &REPLY_BYTES[..]);
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        assert_eq!(Packet::new_checked(&SOLICIT_BYTES[..3]), Err(Error));
+
+        // A truncated IA_NA.
+        let mut bytes = SOLICIT_BYTES;
+        bytes[21] = 0x08;
+        let packet = Packet::new_unchecked(&bytes[..30]);
+        assert_eq!(Repr::parse(&packet), Err(Error));
+    }
+}
@@ -2,6 +2,7 @@
 
 use bitflags::bitflags;
 use byteorder::{ByteOrder, NetworkEndian};
+use core::convert::TryFrom;
 use core::iter;
 use core::iter::Iterator;
 
@@ -42,7 +43,13 @@ enum_with_unknown! {
         Ns    = 0x0002,
         Cname = 0x0005,
         Soa   = 0x0006,
+        Ptr   = 0x000c,
+        Mx    = 0x000f,
+        Txt   = 0x0010,
         Aaaa  = 0x001c,
+        Srv   = 0x0021,
+        Opt   = 0x0029,
+        Any   = 0x00ff,
     }
 }
 
@@ -72,8 +79,14 @@ mod field {
     pub const HEADER_END: usize = 12;
 }
 
+/// Length of the DNS header, which the questions and records follow.
+pub(crate) const HEADER_LEN: usize = field::HEADER_END;
+
 // DNS class IN (Internet)
-const CLASS_IN: u16 = 1;
+pub(crate) const CLASS_IN: u16 = 1;
+// Top bit of the class, which mDNS uses to ask for a unicast response in questions and
+// to flush caches in records, see RFC 6762 § 5.4 and § 10.2.
+pub(crate) const CLASS_MDNS_FLAG: u16 = 0x8000;
 
 /// A read/write wrapper around a DNS packet buffer.
 #[derive(Debug, PartialEq, Eq)]
@@ -161,52 +174,8 @@ impl<T: AsRef<[u8]>> Packet<T> {
     }
 
     /// Parse part of a name from `bytes`, following pointers if any.
-    pub fn parse_name<'a>(&'a self, mut bytes: &'a [u8]) -> impl Iterator<Item = Result<&'a [u8]>> {
-        let mut packet = self.buffer.as_ref();
-
-        iter::from_fn(move || loop {
-            if bytes.is_empty() {
-                return Some(Err(Error));
-            }
-            match bytes[0] {
-                0x00 => return None,
-                x if x & 0xC0 == 0x00 => {
-                    let len = (x & 0x3F) as usize;
-                    if bytes.len() < 1 + len {
-                        return Some(Err(Error));
-                    }
-                    let label = &bytes[1..1 + len];
-                    bytes = &bytes[1 + len..];
-                    return Some(Ok(label));
-                }
-                x if x & 0xC0 == 0xC0 => {
-                    if bytes.len() < 2 {
-                        return Some(Err(Error));
-                    }
-                    let y = bytes[1];
-                    let ptr = ((x & 0x3F) as usize) << 8 | (y as usize);
-                    if packet.len() <= ptr {
-                        return Some(Err(Error));
-                    }
-
-                    // RFC1035 says: "In this scheme, an entire domain name or a list of labels at
-                    //      the end of a domain name is replaced with a pointer to a ***prior*** occurance
-                    //      of the same name.
-                    //
-                    // Is it unclear if this means the pointer MUST point backwards in the packet or not. Either way,
-                    // pointers that don't point backwards are never seen in the fields, so use this to check that
-                    // there are no pointer loops.
-
-                    // Split packet into parts before and after `ptr`.
-                    // parse the part after, keep only the part before in `packet`. This ensure we never
-                    // parse the same byte twice, therefore eliminating pointer loops.
-
-                    bytes = &packet[ptr..];
-                    packet = &packet[..ptr];
-                }
-                _ => return Some(Err(Error)),
-            }
-        })
+    pub fn parse_name<'a>(&'a self, bytes: &'a [u8]) -> impl Iterator<Item = Result<&'a [u8]>> {
+        parse_name(self.buffer.as_ref(), bytes)
     }
 }
 
@@ -228,6 +197,14 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
         NetworkEndian::write_u16(field, (old & !mask) | val.bits());
     }
 
+    pub fn set_rcode(&mut self, val: Rcode) {
+        let field = &mut self.buffer.as_mut()[field::FLAGS];
+        let mask = 0x000f;
+        let val: u8 = val.into();
+        let old = NetworkEndian::read_u16(field);
+        NetworkEndian::write_u16(field, (old & !mask) | (val as u16 & mask));
+    }
+
     pub fn set_opcode(&mut self, val: Opcode) {
         let field = &mut self.buffer.as_mut()[field::FLAGS];
         let mask = 0x3800;
@@ -255,6 +232,56 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
     }
 }
 
+/// Parse part of a name from `bytes`, following pointers into `packet` if any.
+pub(crate) fn parse_name<'a>(
+    mut packet: &'a [u8],
+    mut bytes: &'a [u8],
+) -> impl Iterator<Item = Result<&'a [u8]>> {
+    iter::from_fn(move || loop {
+        if bytes.is_empty() {
+            return Some(Err(Error));
+        }
+        match bytes[0] {
+            0x00 => return None,
+            x if x & 0xC0 == 0x00 => {
+                let len = (x & 0x3F) as usize;
+                if bytes.len() < 1 + len {
+                    return Some(Err(Error));
+                }
+                let label = &bytes[1..1 + len];
+                bytes = &bytes[1 + len..];
+                return Some(Ok(label));
+            }
+            x if x & 0xC0 == 0xC0 => {
+                if bytes.len() < 2 {
+                    return Some(Err(Error));
+                }
+                let y = bytes[1];
+                let ptr = ((x & 0x3F) as usize) << 8 | (y as usize);
+                if packet.len() <= ptr {
+                    return Some(Err(Error));
+                }
+
+                // RFC1035 says: "In this scheme, an entire domain name or a list of labels at
+                //      the end of a domain name is replaced with a pointer to a ***prior*** occurance
+                //      of the same name.
+                //
+                // Is it unclear if this means the pointer MUST point backwards in the packet or not. Either way,
+                // pointers that don't point backwards are never seen in the fields, so use this to check that
+                // there are no pointer loops.
+
+                // Split packet into parts before and after `ptr`.
+                // parse the part after, keep only the part before in `packet`. This ensure we never
+                // parse the same byte twice, therefore eliminating pointer loops.
+
+                bytes = &packet[ptr..];
+                packet = &packet[..ptr];
+            }
+            _ => return Some(Err(Error)),
+        }
+    })
+}
+
 /// Parse part of a name from `bytes`, not following pointers.
 /// Returns the unused part of `bytes`, and the pointer offset if the sequence ends with a pointer.
 fn parse_name_part<'a>(
@@ -284,11 +311,13 @@ fn parse_name_part<'a>(
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Question<'a> {
     pub name: &'a [u8],
     pub type_: Type,
+    /// Whether a unicast response is wanted, in an mDNS question, see RFC 6762 § 5.4.
+    pub unicast_response: bool,
 }
 
 impl<'a> Question<'a> {
@@ -303,11 +332,18 @@ impl<'a> Question<'a> {
         let class = NetworkEndian::read_u16(&rest[2..4]);
         let rest = &rest[4..];
 
-        if class != CLASS_IN {
+        if class & !CLASS_MDNS_FLAG != CLASS_IN {
             return Err(Error);
         }
 
-        Ok((rest, Question { name, type_ }))
+        Ok((
+            rest,
+            Question {
+                name,
+                type_,
+                unicast_response: class & CLASS_MDNS_FLAG != 0,
+            },
+        ))
     }
 
     /// Return the length of a packet that will be emitted from this high-level representation.
@@ -320,15 +356,26 @@ impl<'a> Question<'a> {
         packet[..self.name.len()].copy_from_slice(self.name);
         let rest = &mut packet[self.name.len()..];
         NetworkEndian::write_u16(&mut rest[0..2], self.type_.into());
-        NetworkEndian::write_u16(&mut rest[2..4], CLASS_IN);
+        NetworkEndian::write_u16(&mut rest[2..4], self.class());
+    }
+
+    fn class(&self) -> u16 {
+        if self.unicast_response {
+            CLASS_IN | CLASS_MDNS_FLAG
+        } else {
+            CLASS_IN
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Record<'a> {
     pub name: &'a [u8],
     pub ttl: u32,
+    /// Whether the record replaces the cached ones of the same name and type, in an mDNS
+    /// response, see RFC 6762 § 10.2.
+    pub cache_flush: bool,
     pub data: RecordData<'a>,
 }
 
@@ -350,12 +397,62 @@ impl<'a> RecordData<'a> {
                 Ok(RecordData::Aaaa(Ipv6Address::from_bytes(data)))
             }
             Type::Cname => Ok(RecordData::Cname(data)),
+            Type::Ptr => Ok(RecordData::Ptr(data)),
+            Type::Mx => {
+                if data.len() < 3 {
+                    return Err(Error);
+                }
+                Ok(RecordData::Mx {
+                    preference: NetworkEndian::read_u16(&data[0..2]),
+                    exchange: &data[2..],
+                })
+            }
+            Type::Txt => {
+                // One or more strings, each prefixed by its length.
+                if data.is_empty() {
+                    return Err(Error);
+                }
+                let mut rest = data;
+                while let Some((&len, strings)) = rest.split_first() {
+                    rest = strings.get(len as usize..).ok_or(Error)?;
+                }
+                Ok(RecordData::Txt(data))
+            }
+            Type::Srv => {
+                if data.len() < 7 {
+                    return Err(Error);
+                }
+                Ok(RecordData::Srv {
+                    priority: NetworkEndian::read_u16(&data[0..2]),
+                    weight: NetworkEndian::read_u16(&data[2..4]),
+                    port: NetworkEndian::read_u16(&data[4..6]),
+                    target: &data[6..],
+                })
+            }
             x => Ok(RecordData::Other(x, data)),
         }
     }
+
+    /// Return the type of the record.
+    pub fn type_(&self) -> Type {
+        match self {
+            #[cfg(feature = "proto-ipv4")]
+            RecordData::A(_) => Type::A,
+            #[cfg(feature = "proto-ipv6")]
+            RecordData::Aaaa(_) => Type::Aaaa,
+            RecordData::Cname(_) => Type::Cname,
+            RecordData::Ptr(_) => Type::Ptr,
+            RecordData::Mx { .. } => Type::Mx,
+            RecordData::Txt(_) => Type::Txt,
+            RecordData::Srv { .. } => Type::Srv,
+            RecordData::Other(type_, _) => *type_,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// The data of a record. Domain names in it may be compressed, and can be read with
+/// [`Packet::parse_name`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum RecordData<'a> {
     #[cfg(feature = "proto-ipv4")]
@@ -363,6 +460,22 @@ pub enum RecordData<'a> {
     #[cfg(feature = "proto-ipv6")]
     Aaaa(Ipv6Address),
     Cname(&'a [u8]),
+    /// A domain name, such as the one of an address in a reverse lookup.
+    Ptr(&'a [u8]),
+    /// A mail exchange for the domain, see RFC 1035 § 3.3.9.
+    Mx {
+        preference: u16,
+        exchange: &'a [u8],
+    },
+    /// The strings of a TXT record, each prefixed by its length.
+    Txt(&'a [u8]),
+    /// The location of a service, see RFC 2782.
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: &'a [u8],
+    },
     Other(Type, &'a [u8]),
 }
 
@@ -380,7 +493,7 @@ impl<'a> Record<'a> {
         let len = NetworkEndian::read_u16(&rest[8..10]) as usize;
         let rest = &rest[10..];
 
-        if class != CLASS_IN {
+        if class & !CLASS_MDNS_FLAG != CLASS_IN {
             return Err(Error);
         }
 
@@ -392,43 +505,518 @@ impl<'a> Record<'a> {
             Record {
                 name,
                 ttl,
+                cache_flush: class & CLASS_MDNS_FLAG != 0,
                 data: RecordData::parse(type_, data)?,
             },
         ))
     }
 }
 
+/// Parse an entry of the additional section, or skip it if it's the OPT pseudo-record,
+/// which is parsed with [`Opt::parse`].
+fn parse_additional(buffer: &[u8]) -> Result<(&[u8], Option<Record<'_>>)> {
+    let (rest, _) = parse_name_part(buffer, |_| ())?;
+    if rest.len() < 10 {
+        return Err(Error);
+    }
+    if Type::from(NetworkEndian::read_u16(&rest[0..2])) != Type::Opt {
+        return Record::parse(buffer).map(|(rest, record)| (rest, Some(record)));
+    }
+    let len = NetworkEndian::read_u16(&rest[8..10]) as usize;
+    let rest = rest.get(10 + len..).ok_or(Error)?;
+    Ok((rest, None))
+}
+
+fn parse_question(buffer: &[u8]) -> Result<(&[u8], Option<Question<'_>>)> {
+    Question::parse(buffer).map(|(rest, question)| (rest, Some(question)))
+}
+
+fn parse_record(buffer: &[u8]) -> Result<(&[u8], Option<Record<'_>>)> {
+    Record::parse(buffer).map(|(rest, record)| (rest, Some(record)))
+}
+
+/// An EDNS(0) OPT pseudo-record, see RFC 6891.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Opt<'a> {
+    /// The largest UDP payload the sender can reassemble.
+    pub udp_payload_size: u16,
+    /// The upper 8 bits of the 12-bit response code, whose lower 4 bits are in the header.
+    pub extended_rcode: u8,
+    pub version: u8,
+    /// Whether DNSSEC records are wanted, see RFC 3225.
+    pub dnssec_ok: bool,
+    /// The options, each a 2-octet code and length followed by the data.
+    pub options: &'a [u8],
+}
+
+impl<'a> Opt<'a> {
+    /// Parse an OPT pseudo-record, which has the root name and the OPT type.
+    pub fn parse(buffer: &'a [u8]) -> Result<(&'a [u8], Opt<'a>)> {
+        if buffer.len() < 11 || buffer[0] != 0 {
+            return Err(Error);
+        }
+        let rest = &buffer[1..];
+        if Type::from(NetworkEndian::read_u16(&rest[0..2])) != Type::Opt {
+            return Err(Error);
+        }
+        let udp_payload_size = NetworkEndian::read_u16(&rest[2..4]);
+        let flags = NetworkEndian::read_u32(&rest[4..8]);
+        let len = NetworkEndian::read_u16(&rest[8..10]) as usize;
+        let rest = &rest[10..];
+
+        let options = rest.get(..len).ok_or(Error)?;
+        let opt = Opt {
+            udp_payload_size,
+            extended_rcode: (flags >> 24) as u8,
+            version: (flags >> 16) as u8,
+            dnssec_ok: flags & 0x8000 != 0,
+            options,
+        };
+        // Check that the options lie within the record.
+        for option in opt.options() {
+            option?;
+        }
+
+        Ok((&rest[len..], opt))
+    }
+
+    /// Return an iterator over the code and data of the options.
+    pub fn options(&self) -> impl Iterator<Item = Result<(u16, &'a [u8])>> {
+        let mut rest = self.options;
+        iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            if rest.len() < 4 {
+                rest = &[];
+                return Some(Err(Error));
+            }
+            let code = NetworkEndian::read_u16(&rest[0..2]);
+            let len = NetworkEndian::read_u16(&rest[2..4]) as usize;
+            match rest.get(4..4 + len) {
+                Some(data) => {
+                    rest = &rest[4 + len..];
+                    Some(Ok((code, data)))
+                }
+                None => {
+                    rest = &[];
+                    Some(Err(Error))
+                }
+            }
+        })
+    }
+}
+
+/// Parses an entry of a section, returning the rest of the buffer, and the entry unless
+/// it's to be skipped.
+type EntryParser<'a, T> = fn(&'a [u8]) -> Result<(&'a [u8], Option<T>)>;
+
+/// The questions or records of a section of a DNS packet.
+///
+/// To emit a packet, the section is given as a slice, whose names must not be compressed.
+/// The sections of a parsed packet are read from it instead: names in them may be
+/// compressed, and can be read with [`Packet::parse_name`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Section<'a, T> {
+    entries: &'a [T],
+    /// The packet the section was parsed from, if any.
+    packet: &'a [u8],
+    /// The offset of the section in the packet, and its number of entries.
+    parsed: Option<(usize, u16)>,
+}
+
+impl<'a, T: Copy> Section<'a, T> {
+    /// Create a section of the `entries`.
+    pub const fn new(entries: &'a [T]) -> Section<'a, T> {
+        Section {
+            entries,
+            packet: &[],
+            parsed: None,
+        }
+    }
+
+    /// Parse a section of `count` entries at `offset` in `packet`, returning it and the
+    /// offset of the next one.
+    fn parse(
+        packet: &'a [u8],
+        offset: usize,
+        count: u16,
+        parse: EntryParser<'a, T>,
+    ) -> Result<(Section<'a, T>, usize)> {
+        let mut rest = packet.get(offset..).ok_or(Error)?;
+        for _ in 0..count {
+            rest = parse(rest)?.0;
+        }
+        let section = Section {
+            entries: &[],
+            packet,
+            parsed: Some((offset, count)),
+        };
+        Ok((section, packet.len() - rest.len()))
+    }
+
+    fn iter_with(&self, parse: EntryParser<'a, T>) -> impl Iterator<Item = Result<T>> + 'a {
+        let mut entries = self.entries.iter();
+        let (mut rest, mut count) = match self.parsed {
+            Some((offset, count)) => (&self.packet[offset..], count),
+            None => (&[][..], 0),
+        };
+        iter::from_fn(move || {
+            if let Some(entry) = entries.next() {
+                return Some(Ok(*entry));
+            }
+            while count > 0 {
+                count -= 1;
+                match parse(rest) {
+                    Ok((next, entry)) => {
+                        rest = next;
+                        if let Some(entry) = entry {
+                            return Some(Ok(entry));
+                        }
+                    }
+                    Err(e) => {
+                        count = 0;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            None
+        })
+    }
+}
+
+impl<'a, T: Copy> Default for Section<'a, T> {
+    fn default() -> Self {
+        Section::new(&[])
+    }
+}
+
+impl<'a, T: Copy> From<&'a [T]> for Section<'a, T> {
+    fn from(entries: &'a [T]) -> Self {
+        Section::new(entries)
+    }
+}
+
+impl<'a> Section<'a, Question<'a>> {
+    /// Return an iterator over the questions.
+    pub fn iter(&self) -> impl Iterator<Item = Result<Question<'a>>> + 'a {
+        self.iter_with(parse_question)
+    }
+}
+
+impl<'a> Section<'a, Record<'a>> {
+    /// Return an iterator over the records, without the OPT pseudo-record of a
+    /// parsed additional section.
+    pub fn iter(&self) -> impl Iterator<Item = Result<Record<'a>>> + 'a {
+        self.iter_with(parse_additional)
+    }
+}
+
 /// High-level DNS packet representation.
 ///
-/// Currently only supports query packets.
+/// The OPT pseudo-record of the additional section, if any, is in `opt` rather than in
+/// `additionals`. Names are compressed when emitted.
 #[derive(Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Repr<'a> {
     pub transaction_id: u16,
     pub opcode: Opcode,
     pub flags: Flags,
-    pub question: Question<'a>,
+    pub rcode: Rcode,
+    pub questions: Section<'a, Question<'a>>,
+    pub answers: Section<'a, Record<'a>>,
+    pub authorities: Section<'a, Record<'a>>,
+    pub additionals: Section<'a, Record<'a>>,
+    pub opt: Option<Opt<'a>>,
 }
 
 impl<'a> Repr<'a> {
+    /// Parse a DNS packet and return a high-level representation.
+    pub fn parse<T>(packet: &Packet<&'a T>) -> Result<Repr<'a>>
+    where
+        T: AsRef<[u8]> + ?Sized,
+    {
+        packet.check_len()?;
+        let buffer: &'a [u8] = packet.buffer.as_ref();
+
+        let offset = field::HEADER_END;
+        let (questions, offset) =
+            Section::parse(buffer, offset, packet.question_count(), parse_question)?;
+        let (answers, offset) =
+            Section::parse(buffer, offset, packet.answer_record_count(), parse_record)?;
+        let (authorities, offset) = Section::parse(
+            buffer,
+            offset,
+            packet.authority_record_count(),
+            parse_record,
+        )?;
+        let (additionals, _) = Section::parse(
+            buffer,
+            offset,
+            packet.additional_record_count(),
+            parse_additional,
+        )?;
+
+        // There may be only one OPT pseudo-record, see RFC 6891 § 6.1.1.
+        let mut opt = None;
+        let mut rest = &buffer[offset..];
+        for _ in 0..packet.additional_record_count() {
+            rest = match parse_additional(rest)? {
+                (next, Some(_)) => next,
+                (_, None) if opt.is_some() => return Err(Error),
+                (_, None) => {
+                    let (next, record) = Opt::parse(rest)?;
+                    opt = Some(record);
+                    next
+                }
+            };
+        }
+
+        Ok(Repr {
+            transaction_id: packet.transaction_id(),
+            opcode: packet.opcode(),
+            flags: packet.flags(),
+            rcode: packet.rcode(),
+            questions,
+            answers,
+            authorities,
+            additionals,
+            opt,
+        })
+    }
+
     /// Return the length of a packet that will be emitted from this high-level representation.
-    pub const fn buffer_len(&self) -> usize {
-        field::HEADER_END + self.question.buffer_len()
+    pub fn buffer_len(&self) -> usize {
+        let mut emitter = Emitter::new(None);
+        let _ = self.emit_sections(&mut emitter);
+        emitter.len
     }
 
     /// Emit a high-level representation into a DNS packet.
-    pub fn emit<T: ?Sized>(&self, packet: &mut Packet<&mut T>)
+    ///
+    /// Returns an error if the packet is too short, or a name can't be read.
+    pub fn emit<T>(&self, packet: &mut Packet<&mut T>) -> Result<()>
     where
-        T: AsRef<[u8]> + AsMut<[u8]>,
+        T: AsRef<[u8]> + AsMut<[u8]> + ?Sized,
     {
+        packet.check_len()?;
+        let counts = self.emit_sections(&mut Emitter::new(Some(packet.buffer.as_mut())))?;
+
         packet.set_transaction_id(self.transaction_id);
         packet.set_flags(self.flags);
         packet.set_opcode(self.opcode);
-        packet.set_question_count(1);
-        packet.set_answer_record_count(0);
-        packet.set_authority_record_count(0);
-        packet.set_additional_record_count(0);
-        self.question.emit(packet.payload_mut())
+        packet.set_rcode(self.rcode);
+        packet.set_question_count(counts[0]);
+        packet.set_answer_record_count(counts[1]);
+        packet.set_authority_record_count(counts[2]);
+        packet.set_additional_record_count(counts[3]);
+        Ok(())
+    }
+
+    /// Emit the sections, returning the number of entries of each.
+    fn emit_sections(&self, emitter: &mut Emitter<'_, 'a>) -> Result<[u16; 4]> {
+        let mut counts = [0u16; 4];
+
+        for question in self.questions.iter() {
+            let question = question?;
+            emitter.name(Name::new(question.name, self.questions.packet), true)?;
+            emitter.u16(question.type_.into())?;
+            emitter.u16(question.class())?;
+            counts[0] = counts[0].checked_add(1).ok_or(Error)?;
+        }
+
+        let sections = [&self.answers, &self.authorities, &self.additionals];
+        for (count, section) in counts[1..].iter_mut().zip(sections) {
+            for record in section.iter() {
+                emitter.record(&record?, section.packet)?;
+                *count = count.checked_add(1).ok_or(Error)?;
+            }
+        }
+
+        if let Some(opt) = &self.opt {
+            emitter.opt(opt)?;
+            counts[3] = counts[3].checked_add(1).ok_or(Error)?;
+        }
+
+        Ok(counts)
+    }
+}
+
+/// The most name suffixes that later names can be compressed against.
+const MAX_COMPRESSION_ENTRIES: usize = 32;
+
+/// A name to emit, and the packet it was parsed from, if its pointers are to follow.
+#[derive(Debug, Clone, Copy)]
+struct Name<'a> {
+    name: &'a [u8],
+    packet: &'a [u8],
+}
+
+impl<'a> Name<'a> {
+    fn new(name: &'a [u8], packet: &'a [u8]) -> Name<'a> {
+        Name { name, packet }
+    }
+
+    fn labels(&self) -> impl Iterator<Item = Result<&'a [u8]>> {
+        parse_name(self.packet, self.name)
+    }
+}
+
+/// Write the sections of a packet after its header, compressing names, or only count
+/// the octets they need if there's no buffer.
+struct Emitter<'b, 'a> {
+    buffer: Option<&'b mut [u8]>,
+    len: usize,
+    /// The offsets of the name suffixes emitted so far: the labels of the name from the
+    /// given index are at the offset.
+    suffixes: [Option<(u16, Name<'a>, u8)>; MAX_COMPRESSION_ENTRIES],
+}
+
+impl<'b, 'a> Emitter<'b, 'a> {
+    fn new(buffer: Option<&'b mut [u8]>) -> Emitter<'b, 'a> {
+        Emitter {
+            buffer,
+            len: field::HEADER_END,
+            suffixes: [None; MAX_COMPRESSION_ENTRIES],
+        }
+    }
+
+    fn bytes(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(buffer) = &mut self.buffer {
+            let dest = buffer.get_mut(self.len..self.len + data.len());
+            dest.ok_or(Error)?.copy_from_slice(data);
+        }
+        self.len += data.len();
+        Ok(())
+    }
+
+    fn u16(&mut self, value: u16) -> Result<()> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> Result<()> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// Write a name, ending it with a pointer to the longest suffix of it that was
+    /// already written, if `compress` is set.
+    fn name(&mut self, name: Name<'a>, compress: bool) -> Result<()> {
+        for (index, label) in name.labels().enumerate() {
+            let label = label?;
+            if compress {
+                if let Some(offset) = self.find_suffix(name, index)? {
+                    return self.u16(0xc000 | offset);
+                }
+            }
+
+            // Pointers are 14 bits long, so can't point further.
+            if self.len <= 0x3fff && index <= u8::MAX as usize {
+                let entry = (self.len as u16, name, index as u8);
+                if let Some(slot) = self.suffixes.iter_mut().find(|slot| slot.is_none()) {
+                    *slot = Some(entry);
+                }
+            }
+            self.bytes(&[label.len() as u8])?;
+            self.bytes(label)?;
+        }
+        self.bytes(&[0])
+    }
+
+    /// Return the offset of the labels of `name` from `index`, if they were written.
+    fn find_suffix(&self, name: Name<'a>, index: usize) -> Result<Option<u16>> {
+        for &(offset, other, other_index) in self.suffixes.iter().flatten() {
+            let mut a = name.labels().skip(index);
+            let mut b = other.labels().skip(other_index as usize);
+            let equal = loop {
+                match (a.next(), b.next()) {
+                    (None, None) => break true,
+                    (Some(la), Some(lb)) if la?.eq_ignore_ascii_case(lb?) => (),
+                    _ => break false,
+                }
+            };
+            if equal {
+                return Ok(Some(offset));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Write the data of a record with a length, patched in once it's known.
+    fn data(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.u16(0)?;
+        let start = self.len;
+        f(self)?;
+        let len = u16::try_from(self.len - start).map_err(|_| Error)?;
+        if let Some(buffer) = &mut self.buffer {
+            NetworkEndian::write_u16(&mut buffer[start - 2..start], len);
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, record: &Record<'a>, packet: &'a [u8]) -> Result<()> {
+        self.name(Name::new(record.name, packet), true)?;
+        self.u16(record.data.type_().into())?;
+        self.u16(if record.cache_flush {
+            CLASS_IN | CLASS_MDNS_FLAG
+        } else {
+            CLASS_IN
+        })?;
+        self.u32(record.ttl)?;
+        self.data(|w| match record.data {
+            #[cfg(feature = "proto-ipv4")]
+            RecordData::A(addr) => w.bytes(addr.as_bytes()),
+            #[cfg(feature = "proto-ipv6")]
+            RecordData::Aaaa(addr) => w.bytes(addr.as_bytes()),
+            RecordData::Cname(name) | RecordData::Ptr(name) | RecordData::Other(Type::Ns, name) => {
+                w.name(Name::new(name, packet), true)
+            }
+            RecordData::Mx {
+                preference,
+                exchange,
+            } => {
+                w.u16(preference)?;
+                w.name(Name::new(exchange, packet), true)
+            }
+            // The target of SRV records must not be compressed, see RFC 2782.
+            RecordData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                w.u16(priority)?;
+                w.u16(weight)?;
+                w.u16(port)?;
+                w.name(Name::new(target, packet), false)
+            }
+            RecordData::Txt(data) => w.bytes(data),
+            RecordData::Other(Type::Soa, data) => {
+                let (rest, _) = parse_name_part(data, |_| ())?;
+                let mname = &data[..data.len() - rest.len()];
+                let (serial, _) = parse_name_part(rest, |_| ())?;
+                let rname = &rest[..rest.len() - serial.len()];
+                if serial.len() != 20 {
+                    return Err(Error);
+                }
+                w.name(Name::new(mname, packet), true)?;
+                w.name(Name::new(rname, packet), true)?;
+                w.bytes(serial)
+            }
+            RecordData::Other(_, data) => w.bytes(data),
+        })
+    }
+
+    fn opt(&mut self, opt: &Opt) -> Result<()> {
+        self.bytes(&[0])?;
+        self.u16(Type::Opt.into())?;
+        self.u16(opt.udp_payload_size)?;
+        let flags = (opt.extended_rcode as u32) << 24
+            | (opt.version as u32) << 16
+            | if opt.dnssec_ok { 0x8000 } else { 0 };
+        self.u32(flags)?;
+        self.data(|w| w.bytes(opt.options))
     }
 }
 
@@ -762,6 +1350,60 @@ mod test {
         ));
     }
 
+    #[test]
+    fn test_parse_response_srv() {
+        // _http._tcp.example.com SRV 10 5 8080 www.example.com
+        let mut bytes = vec![
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        ];
+        bytes.extend_from_slice(b"\x05_http\x04_tcp\x07example\x03com\x00\x00\x21\x00\x01");
+        bytes.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x21, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10]);
+        bytes.extend_from_slice(&[0x00, 0x0c, 0x00, 0x0a, 0x00, 0x05, 0x1f, 0x90]);
+        bytes.extend_from_slice(&[0x03, 0x77, 0x77, 0x77, 0xc0, 0x17]);
+        let p = Parsed::parse(&bytes).unwrap();
+
+        assert_eq!(p.questions[0].type_, Type::Srv);
+        assert_eq!(p.answers[0].ttl, 3600);
+        assert_eq!(p.answers[0].data.type_(), Type::Srv);
+        let target = match p.answers[0].data {
+            RecordData::Srv {
+                priority: 10,
+                weight: 5,
+                port: 8080,
+                target,
+            } => target,
+            ref data => panic!("unexpected {:?}", data),
+        };
+        let target: Vec<&[u8]> = p.packet.parse_name(target).map(|l| l.unwrap()).collect();
+        assert_eq!(target, [&b"www"[..], &b"example"[..], &b"com"[..]]);
+    }
+
+    #[test]
+    fn test_parse_record_data() {
+        assert_eq!(
+            RecordData::parse(Type::Ptr, b"\x03www\x07example\x03com\x00"),
+            Ok(RecordData::Ptr(b"\x03www\x07example\x03com\x00"))
+        );
+        assert_eq!(
+            RecordData::parse(Type::Mx, b"\x00\x0a\x04mail\xc0\x0c"),
+            Ok(RecordData::Mx {
+                preference: 10,
+                exchange: b"\x04mail\xc0\x0c"
+            })
+        );
+        assert_eq!(RecordData::parse(Type::Mx, b"\x00\x0a"), Err(Error));
+        assert_eq!(
+            RecordData::parse(Type::Txt, b"\x06path=/\x00"),
+            Ok(RecordData::Txt(b"\x06path=/\x00"))
+        );
+        assert_eq!(RecordData::parse(Type::Txt, b""), Err(Error));
+        assert_eq!(RecordData::parse(Type::Txt, b"\x07path=/"), Err(Error));
+        assert_eq!(
+            RecordData::parse(Type::Srv, b"\x00\x00\x00\x00\x00\x50"),
+            Err(Error)
+        );
+    }
+
     #[test]
     fn test_emit() {
         let name = &[
@@ -769,19 +1411,26 @@ mod test {
             0x00,
         ];
 
+        let question = [Question {
+            name,
+            type_: Type::A,
+            unicast_response: false,
+        }];
         let repr = Repr {
             transaction_id: 0x1234,
             flags: Flags::RECURSION_DESIRED,
             opcode: Opcode::Query,
-            question: Question {
-                name,
-                type_: Type::A,
-            },
+            rcode: Rcode::NoError,
+            questions: Section::new(&question),
+            answers: Section::default(),
+            authorities: Section::default(),
+            additionals: Section::default(),
+            opt: None,
         };
 
         let mut buf = Vec::new();
         buf.resize(repr.buffer_len(), 0);
-        repr.emit(&mut Packet::new_unchecked(&mut buf));
+        repr.emit(&mut Packet::new_unchecked(&mut buf)).unwrap();
 
         let want = &[
             0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x72,
@@ -790,4 +1439,204 @@ mod test {
         ];
         assert_eq!(&buf, want);
     }
+
+    #[test]
+    fn test_emit_compressed() {
+        const NAME: &[u8] = b"\x07example\x03com\x00";
+        let questions = [Question {
+            name: NAME,
+            type_: Type::Mx,
+            unicast_response: false,
+        }];
+        let answers = [
+            Record {
+                name: NAME,
+                ttl: 300,
+                cache_flush: false,
+                data: RecordData::Mx {
+                    preference: 10,
+                    exchange: b"\x04mail\x07EXAMPLE\x03com\x00",
+                },
+            },
+            Record {
+                name: NAME,
+                ttl: 300,
+                cache_flush: false,
+                data: RecordData::Mx {
+                    preference: 20,
+                    exchange: b"\x04mail\x07example\x03net\x00",
+                },
+            },
+        ];
+        let additionals = [Record {
+            name: b"\x04mail\x07example\x03com\x00",
+            ttl: 60,
+            cache_flush: false,
+            data: RecordData::A(Ipv4Address::new(192, 0, 2, 1)),
+        }];
+        let repr = Repr {
+            transaction_id: 0x1234,
+            flags: Flags::RESPONSE | Flags::AUTHORITATIVE,
+            opcode: Opcode::Query,
+            rcode: Rcode::NoError,
+            questions: Section::new(&questions),
+            answers: Section::new(&answers),
+            authorities: Section::default(),
+            additionals: Section::new(&additionals),
+            opt: None,
+        };
+
+        let mut buf = vec![0; repr.buffer_len()];
+        repr.emit(&mut Packet::new_unchecked(&mut buf)).unwrap();
+
+        let mut want = vec![
+            0x12, 0x34, 0x84, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01,
+        ];
+        want.extend_from_slice(b"\x07example\x03com\x00\x00\x0f\x00\x01");
+        // Names are compressed whatever their case.
+        want.extend_from_slice(b"\xc0\x0c\x00\x0f\x00\x01\x00\x00\x01\x2c\x00\x09");
+        want.extend_from_slice(b"\x00\x0a\x04mail\xc0\x0c");
+        want.extend_from_slice(b"\xc0\x0c\x00\x0f\x00\x01\x00\x00\x01\x2c\x00\x14");
+        want.extend_from_slice(b"\x00\x14\x04mail\x07example\x03net\x00");
+        want.extend_from_slice(b"\xc0\x2b\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\xc0\x00\x02\x01");
+        assert_eq!(buf, want);
+
+        // The buffer must be long enough.
+        let mut buf = vec![0; repr.buffer_len() - 1];
+        assert_eq!(repr.emit(&mut Packet::new_unchecked(&mut buf)), Err(Error));
+    }
+
+    #[test]
+    fn test_mdns_class_bits() {
+        const NAME: &[u8] = b"\x07smoltcp\x05local\x00";
+        let questions = [Question {
+            name: NAME,
+            type_: Type::Any,
+            unicast_response: true,
+        }];
+        let answers = [Record {
+            name: NAME,
+            ttl: 120,
+            cache_flush: true,
+            data: RecordData::Txt(b"\x00"),
+        }];
+        let repr = Repr {
+            transaction_id: 0,
+            flags: Flags::empty(),
+            opcode: Opcode::Query,
+            rcode: Rcode::NoError,
+            questions: Section::new(&questions),
+            answers: Section::new(&answers),
+            authorities: Section::default(),
+            additionals: Section::default(),
+            opt: None,
+        };
+
+        let mut buf = vec![0; repr.buffer_len()];
+        repr.emit(&mut Packet::new_unchecked(&mut buf)).unwrap();
+        assert_eq!(&buf[27..31], b"\x00\xff\x80\x01");
+        assert_eq!(&buf[33..37], b"\x00\x10\x80\x01");
+
+        let packet = Packet::new_checked(&buf[..]).unwrap();
+        let parsed = Repr::parse(&packet).unwrap();
+        assert_eq!(parsed.questions.iter().next(), Some(Ok(questions[0])));
+        let record = parsed.answers.iter().next().unwrap().unwrap();
+        assert!(record.cache_flush);
+        assert_eq!(record.data, answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_repr() {
+        let bytes = [
+            0x78, 0x6c, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x03, 0x77,
+            0x77, 0x77, 0x08, 0x66, 0x61, 0x63, 0x65, 0x62, 0x6f, 0x6f, 0x6b, 0x03, 0x63, 0x6f,
+            0x6d, 0x00, 0x00, 0x01, 0x00, 0x01, 0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00,
+            0x05, 0xf3, 0x00, 0x11, 0x09, 0x73, 0x74, 0x61, 0x72, 0x2d, 0x6d, 0x69, 0x6e, 0x69,
+            0x04, 0x63, 0x31, 0x30, 0x72, 0xc0, 0x10, 0xc0, 0x2e, 0x00, 0x01, 0x00, 0x01, 0x00,
+            0x00, 0x00, 0x05, 0x00, 0x04, 0x1f, 0x0d, 0x53, 0x24,
+        ];
+        let packet = Packet::new_checked(&bytes[..]).unwrap();
+        let repr = Repr::parse(&packet).unwrap();
+
+        assert_eq!(repr.transaction_id, 0x786c);
+        assert_eq!(repr.rcode, Rcode::NoError);
+        let questions: Vec<Question> = repr.questions.iter().map(|q| q.unwrap()).collect();
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0].name, b"\x03www\x08facebook\x03com\x00");
+        let answers: Vec<Record> = repr.answers.iter().map(|r| r.unwrap()).collect();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[0].ttl, 1523);
+        assert_eq!(
+            answers[1].data,
+            RecordData::A(Ipv4Address::new(0x1f, 0x0d, 0x53, 0x24))
+        );
+        assert_eq!(repr.authorities.iter().count(), 0);
+        assert_eq!(repr.opt, None);
+
+        // Emitting it again follows the pointers of the parsed packet, and compresses
+        // the names the same way.
+        let mut buf = vec![0; repr.buffer_len()];
+        repr.emit(&mut Packet::new_unchecked(&mut buf)).unwrap();
+        assert_eq!(&buf[..], &bytes[..]);
+
+        // Sections must be whole.
+        let packet = Packet::new_checked(&bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(Repr::parse(&packet), Err(Error));
+    }
+
+    #[test]
+    fn test_opt() {
+        let question = [Question {
+            name: b"\x07example\x03com\x00",
+            type_: Type::A,
+            unicast_response: false,
+        }];
+        let repr = Repr {
+            transaction_id: 0x1234,
+            flags: Flags::RECURSION_DESIRED,
+            opcode: Opcode::Query,
+            rcode: Rcode::NoError,
+            questions: Section::new(&question),
+            answers: Section::default(),
+            authorities: Section::default(),
+            additionals: Section::default(),
+            opt: Some(Opt {
+                udp_payload_size: 1232,
+                extended_rcode: 0,
+                version: 0,
+                dnssec_ok: true,
+                // A client cookie, see RFC 7873.
+                options: b"\x00\x0a\x00\x08\x01\x02\x03\x04\x05\x06\x07\x08",
+            }),
+        };
+
+        let mut buf = vec![0; repr.buffer_len()];
+        repr.emit(&mut Packet::new_unchecked(&mut buf)).unwrap();
+        assert_eq!(&buf[10..12], &[0x00, 0x01]);
+        assert_eq!(
+            &buf[29..],
+            b"\x00\x00\x29\x04\xd0\x00\x00\x80\x00\x00\x0c\x00\x0a\x00\x08\x01\x02\x03\x04\x05\x06\x07\x08"
+        );
+
+        let packet = Packet::new_checked(&buf[..]).unwrap();
+        let parsed = Repr::parse(&packet).unwrap();
+        assert_eq!(parsed.opt, repr.opt);
+        assert_eq!(parsed.additionals.iter().count(), 0);
+        let options: Vec<(u16, &[u8])> =
+            parsed.opt.unwrap().options().map(|o| o.unwrap()).collect();
+        assert_eq!(options, [(10, &b"\x01\x02\x03\x04\x05\x06\x07\x08"[..])]);
+
+        // There may only be one OPT record.
+        let mut twice = buf.clone();
+        twice[11] = 2;
+        twice.extend_from_slice(&buf[29..]);
+        let packet = Packet::new_checked(&twice[..]).unwrap();
+        assert_eq!(Repr::parse(&packet), Err(Error));
+
+        // Its options must lie within it.
+        assert_eq!(
+            Opt::parse(b"\x00\x00\x29\x04\xd0\x00\x00\x00\x00\x00\x03\x00\x0a\x00"),
+            Err(Error)
+        );
+    }
 }
@@ -8,6 +8,7 @@ enum_with_unknown! {
     pub enum EtherType(u16) {
         Ipv4 = 0x0800,
         Arp  = 0x0806,
+        Vlan = 0x8100,
         Ipv6 = 0x86DD
     }
 }
@@ -18,6 +19,7 @@ impl fmt::Display for EtherType {
             EtherType::Ipv4 => write!(f, "IPv4"),
             EtherType::Ipv6 => write!(f, "IPv6"),
             EtherType::Arp => write!(f, "ARP"),
+            EtherType::Vlan => write!(f, "802.1Q"),
             EtherType::Unknown(id) => write!(f, "0x{:04x}", id),
         }
     }
@@ -251,6 +253,10 @@ impl<T: AsRef<[u8]>> PrettyPrint for Frame<T> {
                 indent.increase(f)?;
                 super::Ipv6Packet::<&[u8]>::pretty_print(&frame.payload(), f, indent)
             }
+            EtherType::Vlan => {
+                indent.increase(f)?;
+                super::VlanPacket::<&[u8]>::pretty_print(&frame.payload(), f, indent)
+            }
             _ => Ok(()),
         }
     }
@@ -4,7 +4,7 @@ use core::{cmp, fmt};
 use super::{Error, Result};
 use crate::phy::ChecksumCapabilities;
 use crate::wire::ip::checksum;
-use crate::wire::{Ipv4Packet, Ipv4Repr};
+use crate::wire::{Ipv4Packet, Ipv4Repr, IPV4_HEADER_LEN};
 
 enum_with_unknown! {
     /// Internet protocol control message type.
@@ -179,6 +179,8 @@ mod field {
     pub const ECHO_IDENT: Field = 4..6;
     pub const ECHO_SEQNO: Field = 6..8;
 
+    pub const NEXT_HOP_MTU: Field = 6..8;
+
     pub const HEADER_END: usize = 8;
 }
 
@@ -259,6 +261,17 @@ impl<T: AsRef<[u8]>> Packet<T> {
         NetworkEndian::read_u16(&data[field::ECHO_SEQNO])
     }
 
+    /// Return the next-hop MTU field (for fragmentation required packets), or 0 if the
+    /// router doesn't report it.
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a destination unreachable packet.
+    #[inline]
+    pub fn next_hop_mtu(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::NEXT_HOP_MTU])
+    }
+
     /// Return the header length.
     /// The result depends on the value of the message type field.
     pub fn header_len(&self) -> usize {
@@ -335,6 +348,16 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
         NetworkEndian::write_u16(&mut data[field::ECHO_SEQNO], value)
     }
 
+    /// Set the next-hop MTU field (for fragmentation required packets).
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not a destination unreachable packet.
+    #[inline]
+    pub fn set_next_hop_mtu(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::NEXT_HOP_MTU], value)
+    }
+
     /// Compute and fill in the header checksum.
     pub fn fill_checksum(&mut self) {
         self.set_checksum(0);
@@ -399,6 +422,18 @@ impl<'a> Repr<'a> {
     where
         T: AsRef<[u8]> + ?Sized,
     {
+        // Routers truncate the packets they quote, so the total length of the quoted
+        // header can't be checked against its buffer.
+        fn quoted_packet(data: &[u8]) -> Result<Ipv4Packet<&[u8]>> {
+            let ip_packet = Ipv4Packet::new_unchecked(data);
+            if data.len() < IPV4_HEADER_LEN
+                || (ip_packet.header_len() as usize) < IPV4_HEADER_LEN
+                || data.len() < ip_packet.header_len() as usize
+            {
+                return Err(Error);
+            }
+            Ok(ip_packet)
+        }
         // Valid checksum is expected.
         if checksum_caps.icmpv4.rx() && !packet.verify_checksum() {
             return Err(Error);
@@ -418,7 +453,7 @@ impl<'a> Repr<'a> {
             }),
 
             (Message::DstUnreachable, code) => {
-                let ip_packet = Ipv4Packet::new_checked(packet.data())?;
+                let ip_packet = quoted_packet(packet.data())?;
 
                 let payload = &packet.data()[ip_packet.header_len() as usize..];
                 // RFC 792 requires exactly eight bytes to be returned.
@@ -441,7 +476,7 @@ impl<'a> Repr<'a> {
             }
 
             (Message::TimeExceeded, code) => {
-                let ip_packet = Ipv4Packet::new_checked(packet.data())?;
+                let ip_packet = quoted_packet(packet.data())?;
 
                 let payload = &packet.data()[ip_packet.header_len() as usize..];
                 // RFC 792 requires exactly eight bytes to be returned.
@@ -692,6 +727,16 @@ mod test {
         assert_eq!(&packet.into_inner()[..], &ECHO_PACKET_BYTES[..]);
     }
 
+    #[test]
+    fn test_next_hop_mtu() {
+        let mut bytes = [0x03, 0x04, 0x00, 0x00, 0x00, 0x00, 0x05, 0x78];
+        let mut packet = Packet::new_unchecked(&mut bytes[..]);
+        assert_eq!(packet.msg_type(), Message::DstUnreachable);
+        assert_eq!(packet.next_hop_mtu(), 1400);
+        packet.set_next_hop_mtu(1280);
+        assert_eq!(&packet.into_inner()[6..], &[0x05, 0x00]);
+    }
+
     #[test]
     fn test_check_len() {
         let bytes = [0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
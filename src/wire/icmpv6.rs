@@ -7,7 +7,7 @@ use crate::wire::ip::checksum;
 use crate::wire::MldRepr;
 #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
 use crate::wire::NdiscRepr;
-use crate::wire::{IpAddress, IpProtocol, Ipv6Packet, Ipv6Repr};
+use crate::wire::{IpAddress, IpProtocol, Ipv6Packet, Ipv6Repr, IPV6_HEADER_LEN};
 
 enum_with_unknown! {
     /// Internet protocol control message type.
@@ -26,6 +26,10 @@ enum_with_unknown! {
         EchoReply       = 0x81,
         /// Multicast Listener Query
         MldQuery        = 0x82,
+        /// Multicast Listener Report (MLDv1)
+        MldV1Report     = 0x83,
+        /// Multicast Listener Done (MLDv1)
+        MldDone         = 0x84,
         /// Router Solicitation
         RouterSolicit   = 0x85,
         /// Router Advertisement
@@ -72,7 +76,9 @@ impl Message {
     /// [MLD]: https://tools.ietf.org/html/rfc3810
     pub const fn is_mld(&self) -> bool {
         match *self {
-            Message::MldQuery | Message::MldReport => true,
+            Message::MldQuery | Message::MldV1Report | Message::MldDone | Message::MldReport => {
+                true
+            }
             _ => false,
         }
     }
@@ -93,6 +99,8 @@ impl fmt::Display for Message {
             Message::NeighborAdvert => write!(f, "neighbor advert"),
             Message::Redirect => write!(f, "redirect"),
             Message::MldQuery => write!(f, "multicast listener query"),
+            Message::MldV1Report => write!(f, "multicast listener report (MLDv1)"),
+            Message::MldDone => write!(f, "multicast listener done"),
             Message::MldReport => write!(f, "multicast listener report"),
             Message::Unknown(id) => write!(f, "{}", id),
         }
@@ -341,7 +349,12 @@ impl<T: AsRef<[u8]>> Packet<T> {
             Message::NeighborSolicit => field::TARGET_ADDR.end,
             Message::NeighborAdvert => field::TARGET_ADDR.end,
             Message::Redirect => field::DEST_ADDR.end,
+            // MLDv1 queries are shorter, see RFC 3810 § 8.1.
+            Message::MldQuery if self.buffer.as_ref().len() < field::QUERY_NUM_SRCS.end => {
+                field::QUERY_MCAST_ADDR.end
+            }
             Message::MldQuery => field::QUERY_NUM_SRCS.end,
+            Message::MldV1Report | Message::MldDone => field::QUERY_MCAST_ADDR.end,
             Message::MldReport => field::NR_MCAST_RCRDS.end,
             // For packets that are not included in RFC 4443, do not
             // include the last 32 bits of the ICMPv6 header in
@@ -412,7 +425,13 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
             Message::MldQuery => {
                 let data = self.buffer.as_mut();
                 NetworkEndian::write_u16(&mut data[field::QUERY_RESV], 0);
-                data[field::SQRV] &= 0xf;
+                if data.len() > field::SQRV {
+                    data[field::SQRV] &= 0xf;
+                }
+            }
+            Message::MldV1Report | Message::MldDone => {
+                let data = self.buffer.as_mut();
+                NetworkEndian::write_u16(&mut data[field::QUERY_RESV], 0);
             }
             Message::MldReport => {
                 let data = self.buffer.as_mut();
@@ -553,7 +572,12 @@ impl<'a> Repr<'a> {
         where
             T: AsRef<[u8]> + ?Sized,
         {
-            let ip_packet = Ipv6Packet::new_checked(packet.payload())?;
+            // Routers truncate the packets they quote, so the payload length of the
+            // quoted header can't be checked against its buffer.
+            if packet.payload().len() < IPV6_HEADER_LEN {
+                return Err(Error);
+            }
+            let ip_packet = Ipv6Packet::new_unchecked(packet.payload());
 
             let payload = &packet.payload()[ip_packet.header_len()..];
             if payload.len() < 8 {
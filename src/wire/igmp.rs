@@ -8,7 +8,7 @@ use crate::wire::ip::checksum;
 use crate::wire::Ipv4Address;
 
 enum_with_unknown! {
-    /// Internet Group Management Protocol v1/v2 message version/type.
+    /// Internet Group Management Protocol v1/v2/v3 message version/type.
     pub enum Message(u8) {
         /// Membership Query
         MembershipQuery = 0x11,
@@ -17,11 +17,33 @@ enum_with_unknown! {
         /// Leave Group
         LeaveGroup = 0x17,
         /// Version 1 Membership Report
-        MembershipReportV1 = 0x12
+        MembershipReportV1 = 0x12,
+        /// Version 3 Membership Report
+        MembershipReportV3 = 0x22
     }
 }
 
-/// A read/write wrapper around an Internet Group Management Protocol v1/v2 packet buffer.
+enum_with_unknown! {
+    /// IGMPv3 Group Record Type. See [RFC 3376 § 4.2.12] for more details.
+    ///
+    /// [RFC 3376 § 4.2.12]: https://tools.ietf.org/html/rfc3376#section-4.2.12
+    pub enum GroupRecordType(u8) {
+        /// Interface has a filter mode of INCLUDE for the specified group.
+        ModeIsInclude   = 0x01,
+        /// Interface has a filter mode of EXCLUDE for the specified group.
+        ModeIsExclude   = 0x02,
+        /// Interface has changed to a filter mode of INCLUDE for the specified group.
+        ChangeToInclude = 0x03,
+        /// Interface has changed to a filter mode of EXCLUDE for the specified group.
+        ChangeToExclude = 0x04,
+        /// Interface wishes to receive from the sources in the specified list.
+        AllowNewSources = 0x05,
+        /// Interface no longer wishes to receive from the sources in the specified list.
+        BlockOldSources = 0x06
+    }
+}
+
+/// A read/write wrapper around an Internet Group Management Protocol v1/v2/v3 packet buffer.
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Packet<T: AsRef<[u8]>> {
@@ -35,6 +57,20 @@ mod field {
     pub const MAX_RESP_CODE: usize = 1;
     pub const CHECKSUM: Field = 2..4;
     pub const GROUP_ADDRESS: Field = 4..8;
+
+    // IGMPv3 Membership Query, see RFC 3376 § 4.1.
+    pub const QUERY_S_QRV: usize = 8;
+    pub const QUERY_QQIC: usize = 9;
+    pub const QUERY_NUM_SRCS: Field = 10..12;
+
+    // IGMPv3 Membership Report, see RFC 3376 § 4.2.
+    pub const REPORT_NUM_RECORDS: Field = 6..8;
+
+    // IGMPv3 Group Record, see RFC 3376 § 4.2.4.
+    pub const RECORD_TYPE: usize = 0;
+    pub const RECORD_AUX_DATA_LEN: usize = 1;
+    pub const RECORD_NUM_SRCS: Field = 2..4;
+    pub const RECORD_GROUP_ADDRESS: Field = 4..8;
 }
 
 impl fmt::Display for Message {
@@ -44,6 +80,7 @@ impl fmt::Display for Message {
             Message::MembershipReportV2 => write!(f, "version 2 membership report"),
             Message::LeaveGroup => write!(f, "leave group"),
             Message::MembershipReportV1 => write!(f, "version 1 membership report"),
+            Message::MembershipReportV3 => write!(f, "version 3 membership report"),
             Message::Unknown(id) => write!(f, "{}", id),
         }
     }
@@ -115,6 +152,23 @@ impl<T: AsRef<[u8]>> Packet<T> {
         Ipv4Address::from_bytes(&data[field::GROUP_ADDRESS])
     }
 
+    /// Return the number of sources field of an IGMPv3 query.
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not an IGMPv3 query.
+    #[inline]
+    pub fn num_srcs(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::QUERY_NUM_SRCS])
+    }
+
+    /// Return the number of group records field of an IGMPv3 report.
+    #[inline]
+    pub fn num_group_records(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::REPORT_NUM_RECORDS])
+    }
+
     /// Validate the header checksum.
     ///
     /// # Fuzzing
@@ -159,6 +213,23 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
         data[field::GROUP_ADDRESS].copy_from_slice(addr.as_bytes());
     }
 
+    /// Set the number of sources field of an IGMPv3 query.
+    ///
+    /// # Panics
+    /// This function may panic if this packet is not an IGMPv3 query.
+    #[inline]
+    pub fn set_num_srcs(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::QUERY_NUM_SRCS], value)
+    }
+
+    /// Set the number of group records field of an IGMPv3 report.
+    #[inline]
+    pub fn set_num_group_records(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::REPORT_NUM_RECORDS], value)
+    }
+
     /// Compute and fill in the header checksum.
     pub fn fill_checksum(&mut self) {
         self.set_checksum(0);
@@ -170,15 +241,182 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
     }
 }
 
-/// A high-level representation of an Internet Group Management Protocol v1/v2 header.
+/// A read/write wrapper around an IGMPv3 Group Record buffer.
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum Repr {
+pub struct GroupRecord<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+impl<T: AsRef<[u8]>> GroupRecord<T> {
+    /// Imbue a raw octet buffer with an IGMPv3 Group Record structure.
+    pub const fn new_unchecked(buffer: T) -> Self {
+        Self { buffer }
+    }
+
+    /// Shorthand for a combination of [new_unchecked] and [check_len].
+    ///
+    /// [new_unchecked]: #method.new_unchecked
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Self> {
+        let record = Self::new_unchecked(buffer);
+        record.check_len()?;
+        Ok(record)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < field::RECORD_GROUP_ADDRESS.end || len < self.buffer_len() {
+            Err(Error)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consume the group record, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the length of the group record, including its sources and auxiliary data.
+    ///
+    /// # Panics
+    /// This function may panic if the buffer is shorter than the fixed part of the record.
+    pub fn buffer_len(&self) -> usize {
+        field::RECORD_GROUP_ADDRESS.end
+            + self.num_srcs() as usize * 4
+            + self.aux_data_len() as usize * 4
+    }
+
+    /// Return the record type field.
+    #[inline]
+    pub fn record_type(&self) -> GroupRecordType {
+        let data = self.buffer.as_ref();
+        GroupRecordType::from(data[field::RECORD_TYPE])
+    }
+
+    /// Return the auxiliary data length field, in units of 32-bit words.
+    #[inline]
+    pub fn aux_data_len(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::RECORD_AUX_DATA_LEN]
+    }
+
+    /// Return the number of sources field.
+    #[inline]
+    pub fn num_srcs(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::RECORD_NUM_SRCS])
+    }
+
+    /// Return the multicast address field.
+    #[inline]
+    pub fn group_addr(&self) -> Ipv4Address {
+        let data = self.buffer.as_ref();
+        Ipv4Address::from_bytes(&data[field::RECORD_GROUP_ADDRESS])
+    }
+
+    /// Return the source address at `index`.
+    ///
+    /// # Panics
+    /// This function panics if `index` isn't below the number of sources.
+    #[inline]
+    pub fn source_addr(&self, index: usize) -> Ipv4Address {
+        assert!(index < self.num_srcs() as usize);
+        let data = self.buffer.as_ref();
+        let start = field::RECORD_GROUP_ADDRESS.end + index * 4;
+        Ipv4Address::from_bytes(&data[start..start + 4])
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> GroupRecord<T> {
+    /// Set the record type field.
+    #[inline]
+    pub fn set_record_type(&mut self, value: GroupRecordType) {
+        let data = self.buffer.as_mut();
+        data[field::RECORD_TYPE] = value.into()
+    }
+
+    /// Set the auxiliary data length field, in units of 32-bit words.
+    #[inline]
+    pub fn set_aux_data_len(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::RECORD_AUX_DATA_LEN] = value
+    }
+
+    /// Set the number of sources field.
+    #[inline]
+    pub fn set_num_srcs(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::RECORD_NUM_SRCS], value)
+    }
+
+    /// Set the multicast address field.
+    #[inline]
+    pub fn set_group_addr(&mut self, addr: Ipv4Address) {
+        let data = self.buffer.as_mut();
+        data[field::RECORD_GROUP_ADDRESS].copy_from_slice(addr.as_bytes());
+    }
+
+    /// Set the source address at `index`.
+    ///
+    /// # Panics
+    /// This function panics if `index` isn't below the number of sources.
+    #[inline]
+    pub fn set_source_addr(&mut self, index: usize, addr: Ipv4Address) {
+        assert!(index < self.num_srcs() as usize);
+        let data = self.buffer.as_mut();
+        let start = field::RECORD_GROUP_ADDRESS.end + index * 4;
+        data[start..start + 4].copy_from_slice(addr.as_bytes());
+    }
+}
+
+/// A high-level representation of an IGMPv3 Group Record, without auxiliary data.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GroupRecordRepr<'a> {
+    pub record_type: GroupRecordType,
+    pub group_addr: Ipv4Address,
+    pub sources: &'a [Ipv4Address],
+}
+
+impl<'a> GroupRecordRepr<'a> {
+    /// Return the length of a group record that will be emitted from this high-level
+    /// representation.
+    pub const fn buffer_len(&self) -> usize {
+        field::RECORD_GROUP_ADDRESS.end + self.sources.len() * 4
+    }
+
+    /// Emit a high-level representation into an IGMPv3 Group Record.
+    pub fn emit<T>(&self, record: &mut GroupRecord<&mut T>)
+    where
+        T: AsRef<[u8]> + AsMut<[u8]> + ?Sized,
+    {
+        record.set_record_type(self.record_type);
+        record.set_aux_data_len(0);
+        record.set_num_srcs(self.sources.len() as u16);
+        record.set_group_addr(self.group_addr);
+        for (index, addr) in self.sources.iter().enumerate() {
+            record.set_source_addr(index, *addr);
+        }
+    }
+}
+
+/// A high-level representation of an Internet Group Management Protocol v1/v2/v3 header.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Repr<'a> {
+    /// A Membership Query. The source list of IGMPv3 group-and-source-specific
+    /// queries isn't represented.
     MembershipQuery {
         max_resp_time: Duration,
         group_addr: Ipv4Address,
         version: IgmpVersion,
     },
+    /// A Membership Report. With [`IgmpVersion::Version3`], it's emitted as an IGMPv3
+    /// report with a single record of the group, without any source filter.
     MembershipReport {
         group_addr: Ipv4Address,
         version: IgmpVersion,
@@ -186,6 +424,13 @@ pub enum Repr {
     LeaveGroup {
         group_addr: Ipv4Address,
     },
+    /// A parsed IGMPv3 Membership Report; the group records are in `data`.
+    MembershipReportV3 {
+        num_group_records: u16,
+        data: &'a [u8],
+    },
+    /// An IGMPv3 Membership Report emitted from the given group records.
+    MembershipReportV3Records(&'a [GroupRecordRepr<'a>]),
 }
 
 /// Type of IGMP membership report version
@@ -196,15 +441,34 @@ pub enum IgmpVersion {
     Version1,
     /// IGMPv2
     Version2,
+    /// IGMPv3
+    Version3,
 }
 
-impl Repr {
-    /// Parse an Internet Group Management Protocol v1/v2 packet and return
+impl<'a> Repr<'a> {
+    /// Parse an Internet Group Management Protocol v1/v2/v3 packet and return
     /// a high-level representation.
-    pub fn parse<T>(packet: &Packet<&T>) -> Result<Repr>
+    pub fn parse<T>(packet: &Packet<&'a T>) -> Result<Repr<'a>>
     where
         T: AsRef<[u8]> + ?Sized,
     {
+        let len = packet.buffer.as_ref().len();
+
+        if packet.msg_type() == Message::MembershipReportV3 {
+            // The group address field is reserved in IGMPv3 reports.
+            let num_group_records = packet.num_group_records();
+            let data = &packet.buffer.as_ref()[field::REPORT_NUM_RECORDS.end..];
+            let mut records = data;
+            for _ in 0..num_group_records {
+                let record = GroupRecord::new_checked(records)?;
+                records = &records[record.buffer_len()..];
+            }
+            return Ok(Repr::MembershipReportV3 {
+                num_group_records,
+                data,
+            });
+        }
+
         // Check if the address is 0.0.0.0 or multicast
         let addr = packet.group_addr();
         if !addr.is_unspecified() && !addr.is_multicast() {
@@ -216,7 +480,12 @@ impl Repr {
             Message::MembershipQuery => {
                 let max_resp_time = max_resp_code_to_duration(packet.max_resp_code());
                 // See RFC 3376: 7.1. Query Version Distinctions
-                let version = if packet.max_resp_code() == 0 {
+                let version = if len >= field::QUERY_NUM_SRCS.end {
+                    if len < field::QUERY_NUM_SRCS.end + packet.num_srcs() as usize * 4 {
+                        return Err(Error);
+                    }
+                    IgmpVersion::Version3
+                } else if packet.max_resp_code() == 0 {
                     IgmpVersion::Version1
                 } else {
                     IgmpVersion::Version2
@@ -246,12 +515,31 @@ impl Repr {
     }
 
     /// Return the length of a packet that will be emitted from this high-level representation.
-    pub const fn buffer_len(&self) -> usize {
-        // always 8 bytes
-        field::GROUP_ADDRESS.end
+    pub fn buffer_len(&self) -> usize {
+        match self {
+            Repr::MembershipQuery {
+                version: IgmpVersion::Version3,
+                ..
+            } => field::QUERY_NUM_SRCS.end,
+            Repr::MembershipReport {
+                version: IgmpVersion::Version3,
+                ..
+            } => field::REPORT_NUM_RECORDS.end + field::RECORD_GROUP_ADDRESS.end,
+            Repr::MembershipReportV3 { data, .. } => field::REPORT_NUM_RECORDS.end + data.len(),
+            Repr::MembershipReportV3Records(records) => {
+                field::REPORT_NUM_RECORDS.end
+                    + records
+                        .iter()
+                        .map(|record| record.buffer_len())
+                        .sum::<usize>()
+            }
+            // always 8 bytes
+            _ => field::GROUP_ADDRESS.end,
+        }
     }
 
-    /// Emit a high-level representation into an Internet Group Management Protocol v2 packet.
+    /// Emit a high-level representation into an Internet Group Management Protocol v1/v2/v3
+    /// packet.
     pub fn emit<T>(&self, packet: &mut Packet<&mut T>)
     where
         T: AsRef<[u8]> + AsMut<[u8]> + ?Sized,
@@ -265,11 +553,29 @@ impl Repr {
                 packet.set_msg_type(Message::MembershipQuery);
                 match version {
                     IgmpVersion::Version1 => packet.set_max_resp_code(0),
-                    IgmpVersion::Version2 => {
+                    IgmpVersion::Version2 | IgmpVersion::Version3 => {
                         packet.set_max_resp_code(duration_to_max_resp_code(max_resp_time))
                     }
                 }
                 packet.set_group_address(group_addr);
+                if version == IgmpVersion::Version3 {
+                    let data = packet.buffer.as_mut();
+                    data[field::QUERY_S_QRV] = 0;
+                    data[field::QUERY_QQIC] = 0;
+                    packet.set_num_srcs(0);
+                }
+            }
+            Repr::MembershipReport {
+                group_addr,
+                version: IgmpVersion::Version3,
+            } => {
+                let record = GroupRecordRepr {
+                    record_type: GroupRecordType::ModeIsExclude,
+                    group_addr,
+                    sources: &[],
+                };
+                Repr::MembershipReportV3Records(&[record]).emit(packet);
+                return;
             }
             Repr::MembershipReport {
                 group_addr,
@@ -278,6 +584,7 @@ impl Repr {
                 match version {
                     IgmpVersion::Version1 => packet.set_msg_type(Message::MembershipReportV1),
                     IgmpVersion::Version2 => packet.set_msg_type(Message::MembershipReportV2),
+                    IgmpVersion::Version3 => unreachable!(),
                 };
                 packet.set_max_resp_code(0);
                 packet.set_group_address(group_addr);
@@ -286,6 +593,28 @@ impl Repr {
                 packet.set_msg_type(Message::LeaveGroup);
                 packet.set_group_address(group_addr);
             }
+            Repr::MembershipReportV3 {
+                num_group_records,
+                data,
+            } => {
+                packet.set_msg_type(Message::MembershipReportV3);
+                packet.set_max_resp_code(0);
+                packet.set_group_address(Ipv4Address::UNSPECIFIED);
+                packet.set_num_group_records(num_group_records);
+                packet.buffer.as_mut()[field::REPORT_NUM_RECORDS.end..].copy_from_slice(data);
+            }
+            Repr::MembershipReportV3Records(records) => {
+                packet.set_msg_type(Message::MembershipReportV3);
+                packet.set_max_resp_code(0);
+                packet.set_group_address(Ipv4Address::UNSPECIFIED);
+                packet.set_num_group_records(records.len() as u16);
+                let mut payload = &mut packet.buffer.as_mut()[field::REPORT_NUM_RECORDS.end..];
+                for record in records {
+                    let (record_buffer, rest) = payload.split_at_mut(record.buffer_len());
+                    record.emit(&mut GroupRecord::new_unchecked(record_buffer));
+                    payload = rest;
+                }
+            }
         }
 
         packet.fill_checksum()
@@ -330,7 +659,7 @@ impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
     }
 }
 
-impl fmt::Display for Repr {
+impl<'a> fmt::Display for Repr<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Repr::MembershipQuery {
@@ -353,6 +682,18 @@ impl fmt::Display for Repr {
             Repr::LeaveGroup { group_addr } => {
                 write!(f, "IGMP leave group group_addr={})", group_addr)
             }
+            Repr::MembershipReportV3 {
+                num_group_records, ..
+            } => write!(
+                f,
+                "IGMPv3 membership report num_group_records={}",
+                num_group_records
+            ),
+            Repr::MembershipReportV3Records(records) => write!(
+                f,
+                "IGMPv3 membership report num_group_records={}",
+                records.len()
+            ),
         }
     }
 }
@@ -427,6 +768,83 @@ mod test {
         assert_eq!(&*packet.into_inner(), &REPORT_PACKET_BYTES[..]);
     }
 
+    static V3_QUERY_PACKET_BYTES: [u8; 16] = [
+        0x11, 0x64, 0x41, 0x71, 0xe8, 0x01, 0x01, 0x01, 0x02, 0x7d, 0x00, 0x01, 0xc0, 0xa8, 0x01,
+        0x01,
+    ];
+    static V3_REPORT_PACKET_BYTES: [u8; 28] = [
+        0x22, 0x00, 0x3f, 0x4e, 0x00, 0x00, 0x00, 0x02, 0x01, 0x00, 0x00, 0x01, 0xe8, 0x01, 0x01,
+        0x01, 0xc0, 0xa8, 0x01, 0x01, 0x04, 0x00, 0x00, 0x00, 0xef, 0x00, 0x00, 0x01,
+    ];
+
+    #[test]
+    fn test_v3_query_repr_parse() {
+        let packet = Packet::new_checked(&V3_QUERY_PACKET_BYTES[..]).unwrap();
+        assert!(packet.verify_checksum());
+        assert_eq!(packet.num_srcs(), 1);
+        assert_eq!(
+            Repr::parse(&packet),
+            Ok(Repr::MembershipQuery {
+                max_resp_time: Duration::from_secs(10),
+                group_addr: Ipv4Address::new(232, 1, 1, 1),
+                version: IgmpVersion::Version3,
+            })
+        );
+
+        // The source list must fit in the packet.
+        let packet = Packet::new_checked(&V3_QUERY_PACKET_BYTES[..12]).unwrap();
+        assert_eq!(Repr::parse(&packet), Err(Error));
+    }
+
+    #[test]
+    fn test_v3_report_repr_emit() {
+        let sources = [Ipv4Address::new(192, 168, 1, 1)];
+        let records = [
+            GroupRecordRepr {
+                record_type: GroupRecordType::ModeIsInclude,
+                group_addr: Ipv4Address::new(232, 1, 1, 1),
+                sources: &sources,
+            },
+            GroupRecordRepr {
+                record_type: GroupRecordType::ChangeToExclude,
+                group_addr: Ipv4Address::new(239, 0, 0, 1),
+                sources: &[],
+            },
+        ];
+        let repr = Repr::MembershipReportV3Records(&records);
+        assert_eq!(repr.buffer_len(), V3_REPORT_PACKET_BYTES.len());
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        repr.emit(&mut Packet::new_unchecked(&mut bytes[..]));
+        assert_eq!(&bytes[..], &V3_REPORT_PACKET_BYTES[..]);
+    }
+
+    #[test]
+    fn test_v3_report_repr_parse() {
+        let packet = Packet::new_checked(&V3_REPORT_PACKET_BYTES[..]).unwrap();
+        assert!(packet.verify_checksum());
+        let data = match Repr::parse(&packet).unwrap() {
+            Repr::MembershipReportV3 {
+                num_group_records: 2,
+                data,
+            } => data,
+            repr => panic!("unexpected {:?}", repr),
+        };
+
+        let record = GroupRecord::new_checked(data).unwrap();
+        assert_eq!(record.record_type(), GroupRecordType::ModeIsInclude);
+        assert_eq!(record.group_addr(), Ipv4Address::new(232, 1, 1, 1));
+        assert_eq!(record.num_srcs(), 1);
+        assert_eq!(record.source_addr(0), Ipv4Address::new(192, 168, 1, 1));
+        let record = GroupRecord::new_checked(&data[record.buffer_len()..]).unwrap();
+        assert_eq!(record.record_type(), GroupRecordType::ChangeToExclude);
+        assert_eq!(record.group_addr(), Ipv4Address::new(239, 0, 0, 1));
+        assert_eq!(record.num_srcs(), 0);
+
+        // All group records must fit in the packet.
+        let packet = Packet::new_checked(&V3_REPORT_PACKET_BYTES[..24]).unwrap();
+        assert_eq!(Repr::parse(&packet), Err(Error));
+    }
+
     #[test]
     fn max_resp_time_to_duration_and_back() {
         for i in 0..256usize {
@@ -749,6 +749,14 @@ pub mod checksum {
         propagate_carries(accum)
     }
 
+    /// Incrementally update the `checksum` field of a packet in which `old` data was
+    /// replaced with `new` data of the same length, as described in RFC 1624.
+    #[cfg(feature = "iface-nat")]
+    pub fn update(checksum: u16, old: &[u8], new: &[u8]) -> u16 {
+        debug_assert_eq!(old.len(), new.len());
+        !combine(&[!checksum, !data(old), data(new)])
+    }
+
     /// Compute an IP pseudo header checksum.
     pub fn pseudo_header(
         src_addr: &Address,
@@ -994,4 +1002,16 @@ pub(crate) mod test {
             .prefix_len()
         );
     }
+
+    #[test]
+    #[cfg(feature = "iface-nat")]
+    fn test_checksum_update() {
+        let mut packet = [0x45, 0x00, 0x00, 0x1c, 0x12, 0x34, 0xc0, 0xa8, 0x01, 0x02];
+        let checksum = !checksum::data(&packet);
+
+        let new = [0x0a, 0x00, 0x00, 0x07];
+        let updated = checksum::update(checksum, &packet[6..], &new);
+        packet[6..].copy_from_slice(&new);
+        assert_eq!(updated, !checksum::data(&packet));
+    }
 }
@@ -51,6 +51,11 @@ impl Address {
     /// All multicast-capable routers
     pub const MULTICAST_ALL_ROUTERS: Address = Address([224, 0, 0, 2]);
 
+    /// All IGMPv3-capable routers, see [RFC 3376 § 4.2.14].
+    ///
+    /// [RFC 3376 § 4.2.14]: https://tools.ietf.org/html/rfc3376#section-4.2.14
+    pub const MULTICAST_ALL_IGMPV3_ROUTERS: Address = Address([224, 0, 0, 22]);
+
     /// Construct an IPv4 address from parts.
     pub const fn new(a0: u8, a1: u8, a2: u8, a3: u8) -> Address {
         Address([a0, a1, a2, a3])
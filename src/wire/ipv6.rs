@@ -52,6 +52,22 @@ impl Address {
         0x02,
     ]);
 
+    /// The link-local [all MLDv2-capable routers multicast address].
+    ///
+    /// [all MLDv2-capable routers multicast address]: https://tools.ietf.org/html/rfc3810#section-11
+    pub const LINK_LOCAL_ALL_MLDV2_ROUTERS: Address = Address([
+        0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x16,
+    ]);
+
+    /// The link-local [all DHCP relay agents and servers multicast address].
+    ///
+    /// [all DHCP relay agents and servers multicast address]: https://tools.ietf.org/html/rfc8415#section-7.1
+    pub const LINK_LOCAL_ALL_DHCP_RELAY_AGENTS_AND_SERVERS: Address = Address([
+        0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x02,
+    ]);
+
     /// The [loopback address].
     ///
     /// [loopback address]: https://tools.ietf.org/html/rfc4291#section-2.5.3
@@ -162,6 +178,16 @@ impl Address {
         self.0[..IPV4_MAPPED_PREFIX_SIZE] == Self::IPV4_MAPPED_PREFIX
     }
 
+    /// Query whether the IPv6 address is a [solicited-node multicast address].
+    ///
+    /// [solicited-node multicast address]: https://tools.ietf.org/html/rfc4291#section-2.7.1
+    pub fn is_solicited_node_multicast(&self) -> bool {
+        self.0[..13]
+            == [
+                0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xff,
+            ]
+    }
+
     #[cfg(feature = "proto-ipv4")]
     /// Convert an IPv4 mapped IPv6 address to an IPv4 address.
     pub fn as_ipv4(&self) -> Option<ipv4::Address> {
@@ -876,6 +902,14 @@ mod test {
         assert!(Address::from(Ipv4Address::new(192, 168, 1, 1)).is_ipv4_mapped());
     }
 
+    #[test]
+    fn test_is_solicited_node_multicast() {
+        let addr = Address::new(0xfdbe, 0, 0, 0, 0, 0x1234, 0x5678, 0x9abc);
+        assert!(addr.solicited_node().is_solicited_node_multicast());
+        assert!(!addr.is_solicited_node_multicast());
+        assert!(!Address::LINK_LOCAL_ALL_NODES.is_solicited_node_multicast());
+    }
+
     #[cfg(feature = "proto-ipv4")]
     #[test]
     fn test_as_ipv4() {
@@ -294,6 +294,69 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> AddressRecord<T> {
     }
 }
 
+/// A high-level representation of an MLDv2 Listener Report Message Address Record.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AddressRecordRepr<'a> {
+    pub record_type: RecordType,
+    pub aux_data_len: u8,
+    pub num_srcs: u16,
+    pub mcast_addr: Ipv6Address,
+    /// The source addresses, followed by the auxiliary data.
+    pub payload: &'a [u8],
+}
+
+impl<'a> AddressRecordRepr<'a> {
+    /// Create a record for `mcast_addr` without any sources or auxiliary data.
+    pub const fn new(record_type: RecordType, mcast_addr: Ipv6Address) -> Self {
+        Self {
+            record_type,
+            aux_data_len: 0,
+            num_srcs: 0,
+            mcast_addr,
+            payload: &[],
+        }
+    }
+
+    /// Parse an MLDv2 Address Record and return a high-level representation.
+    pub fn parse<T>(record: &AddressRecord<&'a T>) -> Result<Self>
+    where
+        T: AsRef<[u8]> + ?Sized,
+    {
+        // The auxiliary data length is in units of 32-bit words.
+        let payload_len = record.num_srcs() as usize * 16 + record.aux_data_len() as usize * 4;
+        let payload = record.payload();
+        if payload.len() < payload_len {
+            return Err(Error);
+        }
+
+        Ok(Self {
+            record_type: record.record_type(),
+            aux_data_len: record.aux_data_len(),
+            num_srcs: record.num_srcs(),
+            mcast_addr: record.mcast_addr(),
+            payload: &payload[..payload_len],
+        })
+    }
+
+    /// Return the length of a record that will be emitted from this high-level representation.
+    pub const fn buffer_len(&self) -> usize {
+        field::RECORD_MCAST_ADDR.end + self.payload.len()
+    }
+
+    /// Emit a high-level representation into an MLDv2 Address Record.
+    pub fn emit<T>(&self, record: &mut AddressRecord<&mut T>)
+    where
+        T: AsRef<[u8]> + AsMut<[u8]> + ?Sized,
+    {
+        record.set_record_type(self.record_type);
+        record.set_aux_data_len(self.aux_data_len);
+        record.set_num_srcs(self.num_srcs);
+        record.set_mcast_addr(self.mcast_addr);
+        record.payload_mut().copy_from_slice(self.payload);
+    }
+}
+
 /// A high-level representation of an MLDv2 packet header.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -311,6 +374,19 @@ pub enum Repr<'a> {
         nr_mcast_addr_rcrds: u16,
         data: &'a [u8],
     },
+    /// An MLDv2 Report emitted from the given address records.
+    ReportRecordReprs(&'a [AddressRecordRepr<'a>]),
+    /// An MLDv1 Query, see [RFC 2710 § 3.6].
+    ///
+    /// [RFC 2710 § 3.6]: https://tools.ietf.org/html/rfc2710#section-3.6
+    QueryV1 {
+        max_resp_delay: u16,
+        mcast_addr: Ipv6Address,
+    },
+    /// An MLDv1 Report.
+    ReportV1 { mcast_addr: Ipv6Address },
+    /// An MLDv1 Done message, sent when leaving a multicast group.
+    Done { mcast_addr: Ipv6Address },
 }
 
 impl<'a> Repr<'a> {
@@ -319,7 +395,15 @@ impl<'a> Repr<'a> {
     where
         T: AsRef<[u8]> + ?Sized,
     {
+        let len = packet.buffer.as_ref().len();
         match packet.msg_type() {
+            // RFC 3810 § 8.1: the length tells MLDv1 and MLDv2 queries apart, and queries of
+            // any other length are ignored.
+            Message::MldQuery if len == field::QUERY_MCAST_ADDR.end => Ok(Repr::QueryV1 {
+                max_resp_delay: packet.max_resp_code(),
+                mcast_addr: packet.mcast_addr(),
+            }),
+            Message::MldQuery if len < field::QUERY_NUM_SRCS.end => Err(Error),
             Message::MldQuery => Ok(Repr::Query {
                 max_resp_code: packet.max_resp_code(),
                 mcast_addr: packet.mcast_addr(),
@@ -333,6 +417,12 @@ impl<'a> Repr<'a> {
                 nr_mcast_addr_rcrds: packet.nr_mcast_addr_rcrds(),
                 data: packet.payload(),
             }),
+            Message::MldV1Report => Ok(Repr::ReportV1 {
+                mcast_addr: packet.mcast_addr(),
+            }),
+            Message::MldDone => Ok(Repr::Done {
+                mcast_addr: packet.mcast_addr(),
+            }),
             _ => Err(Error),
         }
     }
@@ -342,6 +432,18 @@ impl<'a> Repr<'a> {
         match self {
             Repr::Query { data, .. } => field::QUERY_NUM_SRCS.end + data.len(),
             Repr::Report { data, .. } => field::NR_MCAST_RCRDS.end + data.len(),
+            Repr::ReportRecordReprs(records) => {
+                let mut len = field::NR_MCAST_RCRDS.end;
+                let mut i = 0;
+                while i < records.len() {
+                    len += records[i].buffer_len();
+                    i += 1;
+                }
+                len
+            }
+            Repr::QueryV1 { .. } | Repr::ReportV1 { .. } | Repr::Done { .. } => {
+                field::QUERY_MCAST_ADDR.end
+            }
         }
     }
 
@@ -385,6 +487,40 @@ impl<'a> Repr<'a> {
                 packet.set_nr_mcast_addr_rcrds(*nr_mcast_addr_rcrds);
                 packet.payload_mut().copy_from_slice(&data[..]);
             }
+            Repr::ReportRecordReprs(records) => {
+                packet.set_msg_type(Message::MldReport);
+                packet.set_msg_code(0);
+                packet.clear_reserved();
+                packet.set_nr_mcast_addr_rcrds(records.len() as u16);
+                let mut payload = packet.payload_mut();
+                for record in records.iter() {
+                    let (buf, rest) =
+                        core::mem::take(&mut payload).split_at_mut(record.buffer_len());
+                    record.emit(&mut AddressRecord::new_unchecked(buf));
+                    payload = rest;
+                }
+            }
+            Repr::QueryV1 {
+                max_resp_delay,
+                mcast_addr,
+            } => {
+                packet.set_msg_type(Message::MldQuery);
+                packet.set_msg_code(0);
+                packet.clear_reserved();
+                packet.set_max_resp_code(*max_resp_delay);
+                packet.set_mcast_addr(*mcast_addr);
+            }
+            Repr::ReportV1 { mcast_addr } | Repr::Done { mcast_addr } => {
+                let msg_type = match self {
+                    Repr::ReportV1 { .. } => Message::MldV1Report,
+                    _ => Message::MldDone,
+                };
+                packet.set_msg_type(msg_type);
+                packet.set_msg_code(0);
+                packet.clear_reserved();
+                packet.set_max_resp_code(0);
+                packet.set_mcast_addr(*mcast_addr);
+            }
         }
     }
 }
@@ -562,6 +698,93 @@ mod test {
         assert_eq!(&*packet.into_inner(), &QUERY_PACKET_BYTES[..]);
     }
 
+    static V1_QUERY_PACKET_BYTES: [u8; 24] = [
+        0x82, 0x00, 0xfe, 0x13, 0x27, 0x10, 0x00, 0x00, 0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    ];
+
+    #[test]
+    fn test_v1_query_repr_parse() {
+        let packet = Packet::new_unchecked(&V1_QUERY_PACKET_BYTES[..]);
+        assert_eq!(packet.header_len(), 24);
+        let repr = Icmpv6Repr::parse(
+            &Ipv6Address::LINK_LOCAL_ALL_NODES.into(),
+            &Ipv6Address::LINK_LOCAL_ALL_ROUTERS.into(),
+            &packet,
+            &ChecksumCapabilities::ignored(),
+        );
+        assert_eq!(
+            repr,
+            Ok(Icmpv6Repr::Mld(Repr::QueryV1 {
+                max_resp_delay: 10000,
+                mcast_addr: Ipv6Address::LINK_LOCAL_ALL_ROUTERS,
+            }))
+        );
+
+        // Queries longer than MLDv1 ones but shorter than MLDv2 ones are invalid.
+        let bytes = [&V1_QUERY_PACKET_BYTES[..], &[0, 0][..]].concat();
+        let packet = Packet::new_unchecked(&bytes[..]);
+        assert_eq!(Repr::parse(&packet), Err(Error));
+    }
+
+    #[test]
+    fn test_v1_repr_emit() {
+        for repr in [
+            Repr::ReportV1 {
+                mcast_addr: Ipv6Address::LINK_LOCAL_ALL_ROUTERS,
+            },
+            Repr::Done {
+                mcast_addr: Ipv6Address::LINK_LOCAL_ALL_ROUTERS,
+            },
+        ] {
+            let mut bytes = [0x2a; 24];
+            let mut packet = Packet::new_unchecked(&mut bytes[..]);
+            Icmpv6Repr::Mld(repr).emit(
+                &Ipv6Address::LINK_LOCAL_ALL_NODES.into(),
+                &Ipv6Address::LINK_LOCAL_ALL_ROUTERS.into(),
+                &mut packet,
+                &ChecksumCapabilities::default(),
+            );
+            let packet = Packet::new_unchecked(&*packet.into_inner());
+            assert_eq!(packet.max_resp_code(), 0);
+            assert_eq!(
+                Icmpv6Repr::parse(
+                    &Ipv6Address::LINK_LOCAL_ALL_NODES.into(),
+                    &Ipv6Address::LINK_LOCAL_ALL_ROUTERS.into(),
+                    &packet,
+                    &ChecksumCapabilities::default(),
+                ),
+                Ok(Icmpv6Repr::Mld(repr))
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_record_reprs_emit() {
+        let records = [AddressRecordRepr {
+            record_type: RecordType::ModeIsInclude,
+            aux_data_len: 0,
+            num_srcs: 1,
+            mcast_addr: Ipv6Address::LINK_LOCAL_ALL_NODES,
+            payload: Ipv6Address::LINK_LOCAL_ALL_ROUTERS.as_bytes(),
+        }];
+        let repr = Icmpv6Repr::Mld(Repr::ReportRecordReprs(&records));
+        assert_eq!(repr.buffer_len(), REPORT_PACKET_BYTES.len());
+
+        let mut bytes = [0x2a; 44];
+        let mut packet = Packet::new_unchecked(&mut bytes[..]);
+        repr.emit(
+            &Ipv6Address::LINK_LOCAL_ALL_NODES.into(),
+            &Ipv6Address::LINK_LOCAL_ALL_ROUTERS.into(),
+            &mut packet,
+            &ChecksumCapabilities::default(),
+        );
+        assert_eq!(&*packet.into_inner(), &REPORT_PACKET_BYTES[..]);
+
+        let record = AddressRecord::new_unchecked(&REPORT_PACKET_PAYLOAD[..]);
+        assert_eq!(AddressRecordRepr::parse(&record), Ok(records[0]));
+    }
+
     #[test]
     fn test_report_repr_emit() {
         let mut bytes = [0x2a; 44];
@@ -81,6 +81,8 @@ pub mod pretty_print;
 mod arp;
 #[cfg(feature = "proto-dhcpv4")]
 pub(crate) mod dhcpv4;
+#[cfg(feature = "proto-dhcpv6")]
+pub(crate) mod dhcpv6;
 #[cfg(feature = "proto-dns")]
 pub(crate) mod dns;
 #[cfg(feature = "medium-ethernet")]
@@ -124,6 +126,8 @@ mod ndiscoption;
 mod sixlowpan;
 mod tcp;
 mod udp;
+#[cfg(feature = "medium-ethernet")]
+mod vlan;
 
 use core::fmt;
 
@@ -137,6 +141,12 @@ pub use self::ethernet::{
     Repr as EthernetRepr, HEADER_LEN as ETHERNET_HEADER_LEN,
 };
 
+#[cfg(feature = "medium-ethernet")]
+pub use self::vlan::{
+    Packet as VlanPacket, Repr as VlanRepr, HEADER_LEN as VLAN_HEADER_LEN,
+    MAX_VLAN_ID as VLAN_MAX_ID,
+};
+
 #[cfg(all(feature = "proto-ipv4", feature = "medium-ethernet"))]
 pub use self::arp::{
     Hardware as ArpHardware, Operation as ArpOperation, Packet as ArpPacket, Repr as ArpRepr,
@@ -204,7 +214,10 @@ pub use self::icmpv4::{
 };
 
 #[cfg(feature = "proto-igmp")]
-pub use self::igmp::{IgmpVersion, Packet as IgmpPacket, Repr as IgmpRepr};
+pub use self::igmp::{
+    GroupRecord as IgmpGroupRecord, GroupRecordRepr as IgmpGroupRecordRepr,
+    GroupRecordType as IgmpGroupRecordType, IgmpVersion, Packet as IgmpPacket, Repr as IgmpRepr,
+};
 
 #[cfg(feature = "proto-ipv6")]
 pub use self::icmpv6::{
@@ -234,13 +247,16 @@ pub use self::ndiscoption::{
 };
 
 #[cfg(feature = "proto-ipv6")]
-pub use self::mld::{AddressRecord as MldAddressRecord, Repr as MldRepr};
+pub use self::mld::{
+    AddressRecord as MldAddressRecord, AddressRecordRepr as MldAddressRecordRepr,
+    RecordType as MldRecordType, Repr as MldRepr,
+};
 
 pub use self::udp::{Packet as UdpPacket, Repr as UdpRepr, HEADER_LEN as UDP_HEADER_LEN};
 
 pub use self::tcp::{
     Control as TcpControl, Packet as TcpPacket, Repr as TcpRepr, SeqNumber as TcpSeqNumber,
-    TcpOption, HEADER_LEN as TCP_HEADER_LEN,
+    TcpOption, TimestampRepr as TcpTimestampRepr, HEADER_LEN as TCP_HEADER_LEN,
 };
 
 #[cfg(feature = "proto-dhcpv4")]
@@ -250,8 +266,22 @@ pub use self::dhcpv4::{
     MAX_DNS_SERVER_COUNT as DHCP_MAX_DNS_SERVER_COUNT, SERVER_PORT as DHCP_SERVER_PORT,
 };
 
+#[cfg(feature = "proto-dhcpv6")]
+pub use self::dhcpv6::{
+    DhcpOption as Dhcpv6Option, IaAddress as Dhcpv6IaAddress, IaNa as Dhcpv6IaNa,
+    IaPd as Dhcpv6IaPd, IaPrefix as Dhcpv6IaPrefix, MessageType as Dhcpv6MessageType,
+    Packet as Dhcpv6Packet, Repr as Dhcpv6Repr, StatusCode as Dhcpv6StatusCode,
+    CLIENT_PORT as DHCPV6_CLIENT_PORT, MAX_DNS_SERVER_COUNT as DHCPV6_MAX_DNS_SERVER_COUNT,
+    MAX_DUID_LEN as DHCPV6_MAX_DUID_LEN, MAX_IA_ADDRESS_COUNT as DHCPV6_MAX_IA_ADDRESS_COUNT,
+    SERVER_PORT as DHCPV6_SERVER_PORT,
+};
+
 #[cfg(feature = "proto-dns")]
-pub use self::dns::{Packet as DnsPacket, Repr as DnsRepr, Type as DnsQueryType};
+pub use self::dns::{
+    Flags as DnsFlags, Opcode as DnsOpcode, Opt as DnsOpt, Packet as DnsPacket,
+    Question as DnsQuestion, Rcode as DnsRcode, Record as DnsResourceRecord,
+    RecordData as DnsResourceData, Repr as DnsRepr, Section as DnsSection, Type as DnsQueryType,
+};
 
 /// Parsing a packet failed.
 ///
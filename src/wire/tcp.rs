@@ -107,6 +107,8 @@ mod field {
     pub const OPT_WS: u8 = 0x03;
     pub const OPT_SACKPERM: u8 = 0x04;
     pub const OPT_SACKRNG: u8 = 0x05;
+    pub const OPT_TSTAMP: u8 = 0x08;
+    pub const OPT_TFO: u8 = 0x22;
 }
 
 pub const HEADER_LEN: usize = field::URGENT.end;
@@ -590,6 +592,19 @@ impl<T: AsRef<[u8]>> AsRef<[u8]> for Packet<T> {
     }
 }
 
+/// A representation of the TCP timestamps option, as described in [RFC 7323 § 3].
+///
+/// [RFC 7323 § 3]: https://tools.ietf.org/html/rfc7323#section-3
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimestampRepr {
+    /// The current value of the sender's timestamp clock.
+    pub tsval: u32,
+    /// The most recent timestamp value received from the remote, or zero if the ACK bit
+    /// is not set.
+    pub tsecr: u32,
+}
+
 /// A representation of a single TCP option.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -600,7 +615,16 @@ pub enum TcpOption<'a> {
     WindowScale(u8),
     SackPermitted,
     SackRange([Option<(u32, u32)>; 3]),
-    Unknown { kind: u8, data: &'a [u8] },
+    Timestamp(TimestampRepr),
+    /// The TCP Fast Open cookie option of [RFC 7413 § 4.1.1]. An empty cookie is
+    /// a request for one.
+    ///
+    /// [RFC 7413 § 4.1.1]: https://tools.ietf.org/html/rfc7413#section-4.1.1
+    FastOpenCookie(&'a [u8]),
+    Unknown {
+        kind: u8,
+        data: &'a [u8],
+    },
 }
 
 impl<'a> TcpOption<'a> {
@@ -663,6 +687,18 @@ impl<'a> TcpOption<'a> {
                         });
                         option = TcpOption::SackRange(sack_ranges);
                     }
+                    (field::OPT_TSTAMP, 10) => {
+                        option = TcpOption::Timestamp(TimestampRepr {
+                            tsval: NetworkEndian::read_u32(&data[0..4]),
+                            tsecr: NetworkEndian::read_u32(&data[4..8]),
+                        })
+                    }
+                    (field::OPT_TSTAMP, _) => return Err(Error),
+                    // The cookie is either absent, or 4 to 16 octets long.
+                    (field::OPT_TFO, 2) | (field::OPT_TFO, 6..=18) => {
+                        option = TcpOption::FastOpenCookie(data)
+                    }
+                    (field::OPT_TFO, _) => return Err(Error),
                     (_, _) => option = TcpOption::Unknown { kind, data },
                 }
             }
@@ -678,6 +714,8 @@ impl<'a> TcpOption<'a> {
             TcpOption::WindowScale(_) => 3,
             TcpOption::SackPermitted => 2,
             TcpOption::SackRange(s) => s.iter().filter(|s| s.is_some()).count() * 8 + 2,
+            TcpOption::Timestamp(_) => 10,
+            TcpOption::FastOpenCookie(cookie) => 2 + cookie.len(),
             TcpOption::Unknown { data, .. } => 2 + data.len(),
         }
     }
@@ -725,6 +763,15 @@ impl<'a> TcpOption<'a> {
                                 NetworkEndian::write_u32(&mut buffer[pos + 4..], second);
                             });
                     }
+                    &TcpOption::Timestamp(timestamp) => {
+                        buffer[0] = field::OPT_TSTAMP;
+                        NetworkEndian::write_u32(&mut buffer[2..], timestamp.tsval);
+                        NetworkEndian::write_u32(&mut buffer[6..], timestamp.tsecr);
+                    }
+                    &TcpOption::FastOpenCookie(cookie) => {
+                        buffer[0] = field::OPT_TFO;
+                        buffer[2..length].copy_from_slice(cookie);
+                    }
                     &TcpOption::Unknown {
                         kind,
                         data: provided,
@@ -783,6 +830,8 @@ pub struct Repr<'a> {
     pub max_seg_size: Option<u16>,
     pub sack_permitted: bool,
     pub sack_ranges: [Option<(u32, u32)>; 3],
+    pub timestamp: Option<TimestampRepr>,
+    pub fast_open_cookie: Option<&'a [u8]>,
     pub payload: &'a [u8],
 }
 
@@ -831,6 +880,8 @@ impl<'a> Repr<'a> {
         let mut options = packet.options();
         let mut sack_permitted = false;
         let mut sack_ranges = [None, None, None];
+        let mut timestamp = None;
+        let mut fast_open_cookie = None;
         while !options.is_empty() {
             let (next_options, option) = TcpOption::parse(options)?;
             match option {
@@ -857,6 +908,8 @@ impl<'a> Repr<'a> {
                 }
                 TcpOption::SackPermitted => sack_permitted = true,
                 TcpOption::SackRange(slice) => sack_ranges = slice,
+                TcpOption::Timestamp(value) => timestamp = Some(value),
+                TcpOption::FastOpenCookie(cookie) => fast_open_cookie = Some(cookie),
                 _ => (),
             }
             options = next_options;
@@ -873,6 +926,8 @@ impl<'a> Repr<'a> {
             max_seg_size: max_seg_size,
             sack_permitted: sack_permitted,
             sack_ranges: sack_ranges,
+            timestamp: timestamp,
+            fast_open_cookie: fast_open_cookie,
             payload: packet.payload(),
         })
     }
@@ -900,6 +955,12 @@ impl<'a> Repr<'a> {
         if sack_range_len > 0 {
             length += sack_range_len + 2;
         }
+        if self.timestamp.is_some() {
+            length += 10;
+        }
+        if let Some(cookie) = self.fast_open_cookie {
+            length += 2 + cookie.len();
+        }
         if length % 4 != 0 {
             length += 4 - length % 4;
         }
@@ -953,6 +1014,14 @@ impl<'a> Repr<'a> {
                 let tmp = options;
                 options = TcpOption::SackRange(self.sack_ranges).emit(tmp);
             }
+            if let Some(timestamp) = self.timestamp {
+                let tmp = options;
+                options = TcpOption::Timestamp(timestamp).emit(tmp);
+            }
+            if let Some(cookie) = self.fast_open_cookie {
+                let tmp = options;
+                options = TcpOption::FastOpenCookie(cookie).emit(tmp);
+            }
 
             if !options.is_empty() {
                 TcpOption::EndOfList.emit(options);
@@ -1033,6 +1102,10 @@ impl<'a, T: AsRef<[u8]> + ?Sized> fmt::Display for Packet<&'a T> {
                 TcpOption::WindowScale(value) => write!(f, " ws={}", value)?,
                 TcpOption::SackPermitted => write!(f, " sACK")?,
                 TcpOption::SackRange(slice) => write!(f, " sACKr{:?}", slice)?, // debug print conveniently includes the []s
+                TcpOption::Timestamp(timestamp) => {
+                    write!(f, " tsval={} tsecr={}", timestamp.tsval, timestamp.tsecr)?
+                }
+                TcpOption::FastOpenCookie(cookie) => write!(f, " tfo={}", cookie.len())?,
                 TcpOption::Unknown { kind, .. } => write!(f, " opt({})", kind)?,
             }
             options = next_options;
@@ -1185,6 +1258,8 @@ mod test {
             max_seg_size: None,
             sack_permitted: false,
             sack_ranges: [None, None, None],
+            timestamp: None,
+            fast_open_cookie: None,
             payload: &PAYLOAD_BYTES,
         }
     }
@@ -1226,6 +1301,62 @@ mod test {
         assert_eq!(repr.header_len() % 4, 0); // Should e.g. be 28 instead of 27.
     }
 
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_timestamp_roundtrip() {
+        let mut repr = packet_repr();
+        repr.timestamp = Some(TimestampRepr {
+            tsval: 0x01020304,
+            tsecr: 0xa0b0c0d0,
+        });
+        assert_eq!(repr.header_len(), 32);
+
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        let mut packet = Packet::new_unchecked(&mut bytes);
+        repr.emit(
+            &mut packet,
+            &SRC_ADDR.into(),
+            &DST_ADDR.into(),
+            &ChecksumCapabilities::default(),
+        );
+        let packet = Packet::new_checked(&bytes[..]).unwrap();
+        let parsed = Repr::parse(
+            &packet,
+            &SRC_ADDR.into(),
+            &DST_ADDR.into(),
+            &ChecksumCapabilities::default(),
+        )
+        .unwrap();
+        assert_eq!(parsed, repr);
+    }
+
+    #[test]
+    #[cfg(feature = "proto-ipv4")]
+    fn test_fast_open_cookie_roundtrip() {
+        let mut repr = packet_repr();
+        repr.max_seg_size = Some(1460);
+        repr.fast_open_cookie = Some(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        assert_eq!(repr.header_len(), 36);
+
+        let mut bytes = vec![0xa5; repr.buffer_len()];
+        let mut packet = Packet::new_unchecked(&mut bytes);
+        repr.emit(
+            &mut packet,
+            &SRC_ADDR.into(),
+            &DST_ADDR.into(),
+            &ChecksumCapabilities::default(),
+        );
+        let packet = Packet::new_checked(&bytes[..]).unwrap();
+        let parsed = Repr::parse(
+            &packet,
+            &SRC_ADDR.into(),
+            &DST_ADDR.into(),
+            &ChecksumCapabilities::default(),
+        )
+        .unwrap();
+        assert_eq!(parsed, repr);
+    }
+
     macro_rules! assert_option_parses {
         ($opt:expr, $data:expr) => {{
             assert_eq!(TcpOption::parse($data), Ok((&[][..], $opt)));
@@ -1264,6 +1395,18 @@ mod test {
                 0x00, 0x26, 0x25, 0xa0, 0x34, 0x3e, 0xfc, 0xea, 0x34, 0x40, 0xae, 0xf0
             ]
         );
+        assert_option_parses!(
+            TcpOption::Timestamp(TimestampRepr {
+                tsval: 0x01020304,
+                tsecr: 0x05060708
+            }),
+            &[0x08, 0x0a, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]
+        );
+        assert_option_parses!(TcpOption::FastOpenCookie(&[]), &[0x22, 0x02]);
+        assert_option_parses!(
+            TcpOption::FastOpenCookie(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]),
+            &[0x22, 0x0a, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]
+        );
         assert_option_parses!(
             TcpOption::Unknown {
                 kind: 12,
@@ -1281,5 +1424,7 @@ mod test {
         assert_eq!(TcpOption::parse(&[0xc, 0x01]), Err(Error));
         assert_eq!(TcpOption::parse(&[0x2, 0x02]), Err(Error));
         assert_eq!(TcpOption::parse(&[0x3, 0x02]), Err(Error));
+        assert_eq!(TcpOption::parse(&[0x8, 0x02]), Err(Error));
+        assert_eq!(TcpOption::parse(&[0x22, 0x04, 0x01, 0x02]), Err(Error));
     }
 }
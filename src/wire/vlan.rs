@@ -0,0 +1,294 @@
+use byteorder::{ByteOrder, NetworkEndian};
+use core::fmt;
+
+use super::{Error, EthernetProtocol, Result};
+
+/// A read/write wrapper around an IEEE 802.1Q VLAN tag buffer.
+///
+/// The tag follows the EtherType field of an Ethernet II frame when it's set
+/// to [`EthernetProtocol::Vlan`], and is followed by the actual EtherType.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T,
+}
+
+// Format of the tag, after the tag protocol identifier (0x8100)
+//
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// | PCP |D|        VLAN ID        |           EtherType           |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// See IEEE 802.1Q-2018 section 9.6 for details.
+mod field {
+    use crate::wire::field::*;
+
+    // 3-bit priority code point, 1-bit drop eligible indicator and 12-bit VLAN ID.
+    pub const TCI: Field = 0..2;
+    // 16-bit EtherType of the payload.
+    pub const ETHERTYPE: Field = 2..4;
+    pub const PAYLOAD: Rest = 4..;
+}
+
+/// The VLAN tag length, not counting the tag protocol identifier.
+pub const HEADER_LEN: usize = field::PAYLOAD.start;
+
+/// The largest VLAN ID that can be assigned, 0xfff being reserved.
+pub const MAX_VLAN_ID: u16 = 0xffe;
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    /// Imbue a raw octet buffer with VLAN tag structure.
+    pub const fn new_unchecked(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    /// Shorthand for a combination of [new_unchecked] and [check_len].
+    ///
+    /// [new_unchecked]: #method.new_unchecked
+    /// [check_len]: #method.check_len
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new_unchecked(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Ensure that no accessor method will panic if called.
+    /// Returns `Err(Error)` if the buffer is too short.
+    pub fn check_len(&self) -> Result<()> {
+        let len = self.buffer.as_ref().len();
+        if len < HEADER_LEN {
+            Err(Error)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Consumes the packet, returning the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Return the priority code point field.
+    #[inline]
+    pub fn priority(&self) -> u8 {
+        let data = self.buffer.as_ref();
+        data[field::TCI.start] >> 5
+    }
+
+    /// Return the drop eligible indicator field.
+    #[inline]
+    pub fn drop_eligible(&self) -> bool {
+        let data = self.buffer.as_ref();
+        data[field::TCI.start] & 0x10 != 0
+    }
+
+    /// Return the VLAN identifier field.
+    #[inline]
+    pub fn vlan_id(&self) -> u16 {
+        let data = self.buffer.as_ref();
+        NetworkEndian::read_u16(&data[field::TCI]) & 0xfff
+    }
+
+    /// Return the EtherType field of the payload.
+    #[inline]
+    pub fn ethertype(&self) -> EthernetProtocol {
+        let data = self.buffer.as_ref();
+        let raw = NetworkEndian::read_u16(&data[field::ETHERTYPE]);
+        EthernetProtocol::from(raw)
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
+    /// Return a pointer to the payload.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        let data = self.buffer.as_ref();
+        &data[field::PAYLOAD]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    /// Set the priority code point field.
+    #[inline]
+    pub fn set_priority(&mut self, value: u8) {
+        let data = self.buffer.as_mut();
+        data[field::TCI.start] = (data[field::TCI.start] & !0xe0) | (value << 5);
+    }
+
+    /// Set the drop eligible indicator field.
+    #[inline]
+    pub fn set_drop_eligible(&mut self, value: bool) {
+        let data = self.buffer.as_mut();
+        let raw = data[field::TCI.start];
+        data[field::TCI.start] = if value { raw | 0x10 } else { raw & !0x10 };
+    }
+
+    /// Set the VLAN identifier field.
+    #[inline]
+    pub fn set_vlan_id(&mut self, value: u16) {
+        let data = self.buffer.as_mut();
+        let raw = NetworkEndian::read_u16(&data[field::TCI]);
+        NetworkEndian::write_u16(&mut data[field::TCI], (raw & !0xfff) | (value & 0xfff))
+    }
+
+    /// Set the EtherType field of the payload.
+    #[inline]
+    pub fn set_ethertype(&mut self, value: EthernetProtocol) {
+        let data = self.buffer.as_mut();
+        NetworkEndian::write_u16(&mut data[field::ETHERTYPE], value.into())
+    }
+
+    /// Return a mutable pointer to the payload.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let data = self.buffer.as_mut();
+        &mut data[field::PAYLOAD]
+    }
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for Packet<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+}
+
+impl<T: AsRef<[u8]>> fmt::Display for Packet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "802.1Q vid={} pcp={} type={}",
+            self.vlan_id(),
+            self.priority(),
+            self.ethertype()
+        )
+    }
+}
+
+use crate::wire::pretty_print::{PrettyIndent, PrettyPrint};
+
+impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
+    fn pretty_print(
+        buffer: &dyn AsRef<[u8]>,
+        f: &mut fmt::Formatter,
+        indent: &mut PrettyIndent,
+    ) -> fmt::Result {
+        let packet = match Packet::new_checked(buffer) {
+            Err(err) => return write!(f, "{}({})", indent, err),
+            Ok(packet) => packet,
+        };
+        write!(f, "{}{}", indent, packet)?;
+
+        match packet.ethertype() {
+            #[cfg(feature = "proto-ipv4")]
+            EthernetProtocol::Arp => {
+                indent.increase(f)?;
+                super::ArpPacket::<&[u8]>::pretty_print(&packet.payload(), f, indent)
+            }
+            #[cfg(feature = "proto-ipv4")]
+            EthernetProtocol::Ipv4 => {
+                indent.increase(f)?;
+                super::Ipv4Packet::<&[u8]>::pretty_print(&packet.payload(), f, indent)
+            }
+            #[cfg(feature = "proto-ipv6")]
+            EthernetProtocol::Ipv6 => {
+                indent.increase(f)?;
+                super::Ipv6Packet::<&[u8]>::pretty_print(&packet.payload(), f, indent)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A high-level representation of an IEEE 802.1Q VLAN tag.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Repr {
+    pub priority: u8,
+    pub drop_eligible: bool,
+    pub vlan_id: u16,
+    pub ethertype: EthernetProtocol,
+}
+
+impl Repr {
+    /// Parse a VLAN tag and return a high-level representation.
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(packet: &Packet<&T>) -> Result<Repr> {
+        packet.check_len()?;
+        Ok(Repr {
+            priority: packet.priority(),
+            drop_eligible: packet.drop_eligible(),
+            vlan_id: packet.vlan_id(),
+            ethertype: packet.ethertype(),
+        })
+    }
+
+    /// Return the length of a header that will be emitted from this high-level representation.
+    pub const fn buffer_len(&self) -> usize {
+        HEADER_LEN
+    }
+
+    /// Emit a high-level representation into a VLAN tag.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut Packet<T>) {
+        packet.set_priority(self.priority);
+        packet.set_drop_eligible(self.drop_eligible);
+        packet.set_vlan_id(self.vlan_id);
+        packet.set_ethertype(self.ethertype);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static PACKET_BYTES: [u8; 8] = [0xb0, 0x2a, 0x08, 0x00, 0xaa, 0x00, 0x00, 0xff];
+
+    static PAYLOAD_BYTES: [u8; 4] = [0xaa, 0x00, 0x00, 0xff];
+
+    #[test]
+    fn test_deconstruct() {
+        let packet = Packet::new_unchecked(&PACKET_BYTES[..]);
+        assert_eq!(packet.priority(), 5);
+        assert!(packet.drop_eligible());
+        assert_eq!(packet.vlan_id(), 42);
+        assert_eq!(packet.ethertype(), EthernetProtocol::Ipv4);
+        assert_eq!(packet.payload(), &PAYLOAD_BYTES[..]);
+    }
+
+    #[test]
+    fn test_construct() {
+        let mut bytes = vec![0xa5; 8];
+        let mut packet = Packet::new_unchecked(&mut bytes);
+        packet.set_priority(5);
+        packet.set_drop_eligible(true);
+        packet.set_vlan_id(42);
+        packet.set_ethertype(EthernetProtocol::Ipv4);
+        packet.payload_mut().copy_from_slice(&PAYLOAD_BYTES[..]);
+        assert_eq!(&packet.into_inner()[..], &PACKET_BYTES[..]);
+    }
+
+    #[test]
+    fn test_repr() {
+        let packet = Packet::new_unchecked(&PACKET_BYTES[..]);
+        let repr = Repr::parse(&packet).unwrap();
+        assert_eq!(
+            repr,
+            Repr {
+                priority: 5,
+                drop_eligible: true,
+                vlan_id: 42,
+                ethertype: EthernetProtocol::Ipv4,
+            }
+        );
+
+        let mut bytes = vec![0xa5; 8];
+        repr.emit(&mut Packet::new_unchecked(&mut bytes));
+        assert_eq!(&bytes[..HEADER_LEN], &PACKET_BYTES[..HEADER_LEN]);
+    }
+
+    #[test]
+    fn test_check_len() {
+        assert!(Packet::new_checked(&PACKET_BYTES[..3]).is_err());
+        assert!(Packet::new_checked(&PACKET_BYTES[..4]).is_ok());
+    }
+}